@@ -3,9 +3,11 @@ use clap::Args;
 use colored::Colorize;
 use form_types::state::{Response, Success};
 use reqwest::Client;
+use trust_dns_proto::rr::RecordType;
 use url::Host;
 
 use crate::{default_context, default_formfile};
+use super::{is_record_data_type, parse_record_type, set_alias, RecordDataArgs};
 
 /// Create a new instance
 #[derive(Debug, Clone, Args)]
@@ -44,11 +46,23 @@ pub struct AddCommand {
     pub vanity: bool,
     #[clap(long="public", default_value_t=false)]
     pub public: bool,
-    /// The build id for the instances you want this domain to point to 
+    /// The build id for the instances you want this domain to point to,
+    /// required unless the record type is TXT, MX, SRV or CAA
     #[clap(long="build-id", short='b')]
-    pub build_id: String,
+    pub build_id: Option<String>,
     #[clap(long="tls-enabled", short='t', default_value_t=false)]
     pub ssl_cert: bool,
+    /// Record type (A, ALIAS, TXT, MX, SRV or CAA). A records point the
+    /// domain to your instances, ALIAS records to the instances another
+    /// domain points to, the others are added to the domain's record
+    #[clap(long="record-type", short='R', default_value="A")]
+    pub record_type: String,
+    /// The domain an ALIAS record takes its addresses from, e.g. to point
+    /// an apex domain at www.example.com
+    #[clap(long="target")]
+    pub target: Option<String>,
+    #[clap(flatten)]
+    pub records: RecordDataArgs,
 }
 
 pub fn print_add_response(
@@ -84,6 +98,20 @@ if !cnames.is_empty() { cnames.join(", ").to_string().bold().blue() } else { "".
 );
 }
 
+pub fn print_add_records_response(domain_name: String, record_type: String) {
+println!(r#"
+Your {} records for {} were added successfully!
+
+They are served by the formation network as soon as they have propagated through
+it, which usually takes a few minutes. If your domain is delegated elsewhere, add
+the same records with your DNS provider.
+
+"#,
+record_type.blue(),
+domain_name.blue(),
+);
+}
+
 pub fn print_add_invalid_response<T: Debug>(r: Success<T>, endpoint: &str) {
 println!(r#"
 Something went {} wrong. Received {} which is not a
@@ -125,7 +153,18 @@ impl AddCommand {
         provider: String, 
     ) -> Result<(), Box<dyn std::error::Error>> {
         let domain = self.domain_name.clone();
-        let build_id = self.build_id.clone();
+        let record_type = parse_record_type(&self.record_type)?;
+        if record_type == RecordType::ANAME {
+            return self.handle_add_alias(&provider).await;
+        }
+        if is_record_data_type(record_type) {
+            return self.handle_add_records(&provider, record_type).await;
+        }
+
+        let Some(build_id) = self.build_id.clone() else {
+            print_add_failure(Some(format!("--build-id is required to add an {record_type} record")));
+            return Ok(());
+        };
         let endpoint = if !self.public {
             format!("http://{provider}:3004/dns/{domain}/{build_id}/request_vanity")
         } else {
@@ -155,6 +194,12 @@ impl AddCommand {
                         }
                     }
                 }
+                if !self.records.is_empty() {
+                    if let Response::Failure { reason } = self.records.submit(&provider, &domain, record_type, false, false).await? {
+                        print_add_failure(reason);
+                        return Ok(());
+                    }
+                }
                 print_add_response(ips, cname, domain, build_id);
             }
             Response::Success(r) => {
@@ -171,4 +216,52 @@ impl AddCommand {
 
         Ok(())
     }
+
+    async fn handle_add_alias(&self, provider: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let domain = self.domain_name.clone();
+        let Some(target) = self.target.clone() else {
+            print_add_failure(Some("--target is required to add an ALIAS record".to_string()));
+            return Ok(());
+        };
+
+        match set_alias(provider, &domain, &target).await? {
+            Response::Success(Success::Some(_)) => {
+                print_add_records_response(domain, "ALIAS".to_string());
+            }
+            Response::Success(r) => {
+                print_add_invalid_response(r, "/dns/create");
+            }
+            Response::Failure { reason } => {
+                print_add_failure(reason);
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_add_records(
+        &self,
+        provider: &str,
+        record_type: RecordType,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let domain = self.domain_name.clone();
+        if self.records.is_empty() {
+            print_add_failure(Some(format!("No {record_type} values were provided, use --txt, --mx, --srv or --caa")));
+            return Ok(());
+        }
+
+        match self.records.submit(provider, &domain, record_type, false, true).await? {
+            Response::Success(Success::Some(_)) => {
+                print_add_records_response(domain, record_type.to_string());
+            }
+            Response::Success(r) => {
+                print_add_invalid_response(r, "/dns/create");
+            }
+            Response::Failure { reason } => {
+                print_add_failure(reason);
+            }
+        }
+
+        Ok(())
+    }
 }
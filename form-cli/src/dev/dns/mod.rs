@@ -1,11 +1,20 @@
-use clap::Subcommand;
+use std::str::FromStr;
+use clap::{Args, Subcommand};
 use add::AddCommand;
+use form_dns::routing::RoutingPolicy;
+use form_dns::store::{CaaRecord, FormDnsRecord, MxRecord, SrvRecord};
+use form_types::state::{Response, Success};
 use remove::RemoveCommand;
+use route::RouteCommand;
+use reqwest::Client;
+use serde_json::json;
+use trust_dns_proto::rr::RecordType;
 use update::UpdateCommand;
 use verify::VerifyCommand;
 
 pub mod add;
 pub mod remove;
+pub mod route;
 pub mod update;
 pub mod verify;
 
@@ -14,5 +23,155 @@ pub enum DnsCommand {
     Add(AddCommand),
     Remove(RemoveCommand),
     Update(UpdateCommand),
+    Route(RouteCommand),
     Verify(VerifyCommand),
 }
+
+/// TXT, MX, SRV and CAA values of a domain. They are served alongside the
+/// addresses of the domain, so they can be added to a domain that already
+/// points to your instances.
+#[derive(Debug, Clone, Default, Args)]
+pub struct RecordDataArgs {
+    /// A TXT value, e.g. an SPF policy or a DKIM key. Can be repeated
+    #[clap(long="txt")]
+    pub txt: Vec<String>,
+    /// A mail exchange, as "<preference> <exchange>". Can be repeated
+    #[clap(long="mx")]
+    pub mx: Vec<MxRecord>,
+    /// A service location, as "<priority> <weight> <port> <target>".
+    /// Can be repeated
+    #[clap(long="srv")]
+    pub srv: Vec<SrvRecord>,
+    /// A CA authorization, as "<flags> <tag> <value>", e.g.
+    /// "0 issue letsencrypt.org". Can be repeated
+    #[clap(long="caa")]
+    pub caa: Vec<CaaRecord>,
+}
+
+impl RecordDataArgs {
+    pub fn is_empty(&self) -> bool {
+        self.txt.is_empty() && self.mx.is_empty() && self.srv.is_empty() && self.caa.is_empty()
+    }
+
+    /// Add the values to a record, or replace its values with them
+    pub fn apply(&self, record: &mut FormDnsRecord, replace: bool) {
+        if replace {
+            record.txt = self.txt.clone();
+            record.mx = self.mx.clone();
+            record.srv = self.srv.clone();
+            record.caa = self.caa.clone();
+        } else {
+            extend_unique(&mut record.txt, &self.txt);
+            extend_unique(&mut record.mx, &self.mx);
+            extend_unique(&mut record.srv, &self.srv);
+            extend_unique(&mut record.caa, &self.caa);
+        }
+    }
+
+    /// Set the values on the record of `domain` in form-state. If the domain
+    /// has no record yet, one of `record_type` is created when `create` is
+    /// set.
+    pub async fn submit(
+        &self,
+        provider: &str,
+        domain: &str,
+        record_type: RecordType,
+        replace: bool,
+        create: bool,
+    ) -> Result<Response<FormDnsRecord>, Box<dyn std::error::Error>> {
+        change_record(provider, domain, record_type, create, |record| self.apply(record, replace)).await
+    }
+}
+
+/// Point the record of `domain` in form-state at the addresses of `target`,
+/// creating the record if the domain has none
+pub async fn set_alias(
+    provider: &str,
+    domain: &str,
+    target: &str,
+) -> Result<Response<FormDnsRecord>, Box<dyn std::error::Error>> {
+    change_record(provider, domain, RecordType::ANAME, true, |record| {
+        record.record_type = RecordType::ANAME;
+        record.cname_target = Some(target.to_string());
+        // The addresses of an ANAME record are its target's
+        record.public_ip.clear();
+        record.formnet_ip.clear();
+    }).await
+}
+
+/// Apply `change` to the record of `domain` in form-state. If the domain has
+/// no record yet, `change` is applied to a new record of `record_type` when
+/// `create` is set.
+pub async fn change_record(
+    provider: &str,
+    domain: &str,
+    record_type: RecordType,
+    create: bool,
+    change: impl FnOnce(&mut FormDnsRecord),
+) -> Result<Response<FormDnsRecord>, Box<dyn std::error::Error>> {
+    let existing = Client::new()
+        .get(format!("http://{provider}:3004/dns/{domain}/get"))
+        .send().await?
+        .json::<Response<FormDnsRecord>>().await?;
+
+    let resp = match existing {
+        Response::Success(Success::Some(mut record)) => {
+            change(&mut record);
+            Client::new()
+                .post(format!("http://{provider}:3004/dns/update"))
+                .json(&json!({ "Update": record }))
+                .send().await?
+                .json::<Response<FormDnsRecord>>().await?
+        }
+        _ if create => {
+            let mut record = FormDnsRecord {
+                domain: domain.to_string(),
+                record_type,
+                public_ip: vec![],
+                formnet_ip: vec![],
+                cname_target: None,
+                ssl_cert: false,
+                ttl: 3600,
+                verification_status: None,
+                verification_timestamp: None,
+                txt: vec![],
+                mx: vec![],
+                srv: vec![],
+                caa: vec![],
+                routing: RoutingPolicy::default(),
+            };
+            change(&mut record);
+            Client::new()
+                .post(format!("http://{provider}:3004/dns/create"))
+                .json(&json!({ "Create": record }))
+                .send().await?
+                .json::<Response<FormDnsRecord>>().await?
+        }
+        _ => Response::Failure {
+            reason: Some(format!("No record exists for {domain}, add one with `form dns add` first"))
+        },
+    };
+
+    Ok(resp)
+}
+
+/// Parse a record type, taking ALIAS as the ANAME record type
+pub fn parse_record_type(record_type: &str) -> Result<RecordType, Box<dyn std::error::Error>> {
+    match record_type.to_uppercase().as_str() {
+        "ALIAS" => Ok(RecordType::ANAME),
+        other => Ok(RecordType::from_str(other)?),
+    }
+}
+
+/// Whether a record type is one set with `--txt`, `--mx`, `--srv` or `--caa`
+pub fn is_record_data_type(record_type: RecordType) -> bool {
+    matches!(record_type, RecordType::TXT | RecordType::MX | RecordType::SRV | RecordType::CAA)
+}
+
+fn extend_unique<T: Clone + PartialEq>(values: &mut Vec<T>, new: &[T]) {
+    for value in new {
+        if !values.contains(value) {
+            values.push(value.clone());
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::{fmt::Debug, net::IpAddr, path::PathBuf};
+use clap::Args;
+use colored::Colorize;
+use form_dns::routing::{RegionPin, RoutingPolicy, WeightedTarget};
+use form_types::state::{Response, Success};
+use trust_dns_proto::rr::RecordType;
+
+use crate::{default_context, default_formfile};
+use super::change_record;
+
+/// Set how the addresses of a domain are picked for each answer
+#[derive(Debug, Clone, Args)]
+pub struct RouteCommand {
+    /// Path to the context directory (e.g., . for current directory)
+    /// This should be the directory containing the Formfile and other artifacts
+    /// however, you can provide a path to the Formfile.
+    #[clap(default_value_os_t = default_context())]
+    pub context_dir: PathBuf,
+    /// The directory where the form pack artifacts can be found
+    #[clap(long, short, default_value_os_t = default_formfile(default_context()))]
+    pub formfile: PathBuf,
+    /// A hexadecimal or base64 representation of a valid private key for
+    /// signing the request. Given this is the create command, this will
+    /// be how the network derives ownership of the instance. Authorization
+    /// to other public key/wallet addresses can be granted by the owner
+    /// after creation, however, this key will be the initial owner until
+    /// revoked or changed by a request made with the same signing key
+    #[clap(long, short)]
+    pub private_key: Option<String>,
+    /// An altenrative to private key or mnemonic. If you have a keyfile
+    /// stored locally, you can use the keyfile to read in your private key
+    //TODO: Add support for HSM and other Enclave based key storage
+    #[clap(long, short)]
+    pub keyfile: Option<String>,
+    /// An alternative to private key or keyfile. If you have a 12 or 24 word
+    /// BIP39 compliant mnemonic phrase, you can use it to derive the signing
+    /// key for this request
+    //TODO: Add support for HSM and other Enclave based key storage
+    #[clap(long, short)]
+    pub mnemonic: Option<String>,
+    /// The domain whose routing you want to set
+    #[clap(long="domain", short='d')]
+    pub domain_name: String,
+    /// The routing policy: proximity, weighted, failover, latency or region
+    #[clap(long="policy", short='P', default_value="proximity")]
+    pub policy: String,
+    /// An address and its weight, as "<ip>=<weight>", for the weighted
+    /// policy. Can be repeated
+    #[clap(long="weight")]
+    pub weights: Vec<WeightedTarget>,
+    /// An address served while it is healthy, for the failover policy.
+    /// Can be repeated
+    #[clap(long="primary")]
+    pub primary: Vec<IpAddr>,
+    /// An address served when no primary address is healthy, for the
+    /// failover policy. Can be repeated
+    #[clap(long="secondary")]
+    pub secondary: Vec<IpAddr>,
+    /// Addresses served to clients of a region, as "<region>=<ip>[,<ip>...]"
+    /// where the region is a country code such as DE or a country and
+    /// subdivision such as US-CA, for the region policy. Can be repeated
+    #[clap(long="pin")]
+    pub pins: Vec<RegionPin>,
+}
+
+pub fn print_route_response(domain_name: String, policy: &RoutingPolicy) {
+    println!(r#"
+The routing of {} is now {}.
+
+The changes may take a few minutes to fully propagate through the system.
+"#,
+    domain_name.blue(),
+    policy.to_string().blue()
+    );
+}
+
+pub fn print_route_invalid_response<T: Debug>(r: Success<T>) {
+    println!(r#"
+Something went {} wrong. Received {} which is not a
+valid response format for a routing update.
+"#,
+    "terribly".bold().bright_red(),
+    format!("{:?}", r).blue()
+    );
+}
+
+pub fn print_route_failure(reason: Option<String>) {
+    println!(r#"
+
+Sadly, the request to set the routing of the domain failed.
+
+Reason: {}
+
+If you're not sure what to do from here, please consider doing one of the following:
+
+    1. Join our discord at {} and go to the {} channel and paste this response
+    2. Submitting an {} on our project github at {}
+    3. Sending us a direct message on X at {}
+
+Someone from our core team will gladly help you out.
+"#,
+    if let Some(r) = reason { r.bold().bright_red() } else { "none".bold().bright_red() },
+    "discord.gg/formation".blue(),
+    "chewing-glass".blue(),
+    "issue".bright_yellow(),
+    "http://github.com/formthefog/formation.git".blue(),
+    "@formthefog".blue(),
+    )
+}
+
+impl RouteCommand {
+    /// The policy described by the flags
+    pub fn routing_policy(&self) -> Result<RoutingPolicy, String> {
+        let policy = match self.policy.to_lowercase().as_str() {
+            "proximity" => RoutingPolicy::Proximity,
+            "weighted" => RoutingPolicy::Weighted(self.weights.clone()),
+            "failover" => RoutingPolicy::Failover {
+                primary: self.primary.clone(),
+                secondary: self.secondary.clone(),
+            },
+            "latency" => RoutingPolicy::Latency,
+            "region" => RoutingPolicy::Region(self.pins.clone()),
+            other => return Err(format!("Unknown routing policy {other}, expected proximity, weighted, failover, latency or region")),
+        };
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    pub async fn handle_route_command(&self, provider: String) -> Result<(), Box<dyn std::error::Error>> {
+        let domain = self.domain_name.clone();
+        let policy = match self.routing_policy() {
+            Ok(policy) => policy,
+            Err(e) => {
+                print_route_failure(Some(e));
+                return Ok(());
+            }
+        };
+
+        let routing = policy.clone();
+        match change_record(&provider, &domain, RecordType::A, false, |record| record.routing = routing).await? {
+            Response::Success(Success::Some(_)) => print_route_response(domain, &policy),
+            Response::Success(other) => print_route_invalid_response(other),
+            Response::Failure { reason } => print_route_failure(reason),
+        }
+
+        Ok(())
+    }
+}
@@ -4,8 +4,10 @@ use colored::Colorize;
 use form_types::state::{Response, Success};
 use reqwest::Client;
 use serde_json::json;
+use trust_dns_proto::rr::RecordType;
 
 use crate::{default_context, default_formfile};
+use super::{is_record_data_type, parse_record_type, set_alias, RecordDataArgs};
 
 /// Update an existing domain record
 #[derive(Debug, Clone, Args)]
@@ -40,18 +42,26 @@ pub struct UpdateCommand {
     /// The domain name you want to update
     #[clap(long="domain", short='d')]
     pub domain_name: String,
-    /// The build id for the instances you want this domain to point to 
+    /// The build id for the instances you want this domain to point to,
+    /// required unless the record type is TXT, MX, SRV or CAA
     #[clap(long="build-id", short='b')]
-    pub build_id: String,
+    pub build_id: Option<String>,
     /// Whether to enable TLS (HTTPS) for this domain
     #[clap(long="tls-enabled", short='t', default_value_t=false)]
     pub ssl_cert: bool,
     /// Whether to completely replace the existing record
     #[clap(long="replace", short='r', default_value_t=false)]
     pub replace: bool,
-    /// Record type (A, AAAA, CNAME, etc.)
+    /// Record type (A, AAAA, CNAME, ALIAS, TXT, MX, SRV or CAA)
     #[clap(long="record-type", short='R', default_value="A")]
     pub record_type: String,
+    /// The domain an ALIAS record takes its addresses from
+    #[clap(long="target")]
+    pub target: Option<String>,
+    /// TXT, MX, SRV and CAA values, added to the record's values or
+    /// replacing them all with --replace
+    #[clap(flatten)]
+    pub records: RecordDataArgs,
 }
 
 pub fn print_update_response(domain_name: String) {
@@ -107,6 +117,36 @@ Someone from our core team will gladly help you out.
 impl UpdateCommand {
     pub async fn handle_update_command(&self, provider: String) -> Result<(), Box<dyn std::error::Error>> {
         let domain = self.domain_name.clone();
+        let record_type = parse_record_type(&self.record_type)?;
+        if record_type == RecordType::ANAME {
+            let Some(target) = self.target.clone() else {
+                print_update_failure(Some("--target is required to update an ALIAS record".to_string()));
+                return Ok(());
+            };
+            match set_alias(&provider, &domain, &target).await? {
+                Response::Success(Success::Some(_)) => print_update_response(domain),
+                Response::Success(other) => print_update_invalid_response(other),
+                Response::Failure { reason } => print_update_failure(reason),
+            }
+            return Ok(());
+        }
+        if is_record_data_type(record_type) {
+            if self.records.is_empty() && !self.replace {
+                print_update_failure(Some(format!("No {record_type} values were provided, use --txt, --mx, --srv or --caa")));
+                return Ok(());
+            }
+            match self.records.submit(&provider, &domain, record_type, self.replace, false).await? {
+                Response::Success(Success::Some(_)) => print_update_response(domain),
+                Response::Success(other) => print_update_invalid_response(other),
+                Response::Failure { reason } => print_update_failure(reason),
+            }
+            return Ok(());
+        }
+
+        let Some(build_id) = self.build_id.clone() else {
+            print_update_failure(Some(format!("--build-id is required to update an {record_type} record")));
+            return Ok(());
+        };
         
         // Construct the request to the /record/{domain}/update endpoint
         let endpoint = format!("http://{provider}:3004/record/{domain}/update");
@@ -118,7 +158,7 @@ impl UpdateCommand {
             "ip_addr": [],  // This will be populated by the server based on the build_id
             "cname_target": null,
             "ssl_cert": self.ssl_cert,
-            "build_id": self.build_id  // Including build_id to let the server find the right IPs
+            "build_id": build_id  // Including build_id to let the server find the right IPs
         });
 
         // Send the request
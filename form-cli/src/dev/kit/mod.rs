@@ -4,7 +4,9 @@ use clap::Subcommand;
 pub mod init;
 pub mod util;
 pub mod operator;
+pub mod snapshot;
 pub use operator::*;
+pub use snapshot::*;
 pub use init::*;
 pub use util::*;
 
@@ -4,10 +4,14 @@ use anyhow::Result;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input};
 use serde::{Serialize, Deserialize};
 use form_config::*;
+use crate::SnapshotCommand;
 
 #[derive(Clone, Debug, Subcommand, Serialize, Deserialize)]
 pub enum Operator {
-    Config
+    Config,
+    /// Export or restore snapshots of the node's form-state datastore
+    #[clap(subcommand)]
+    Snapshot(SnapshotCommand),
 }
 
 pub fn operator_config() -> Result<()> {
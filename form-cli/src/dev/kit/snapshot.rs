@@ -0,0 +1,227 @@
+use std::path::PathBuf;
+use clap::{Args, Subcommand};
+use colored::*;
+use reqwest::{Client, RequestBuilder};
+use serde::{Serialize, Deserialize};
+use form_types::state::{Response, Success};
+use form_state::snapshot::{Snapshot, SnapshotMetadata};
+
+/// Commands for exporting and restoring point-in-time snapshots of the
+/// form-state datastore on an operator node
+#[derive(Clone, Debug, Subcommand, Serialize, Deserialize)]
+pub enum SnapshotCommand {
+    /// Write a new snapshot on the node, optionally downloading it
+    Create(SnapshotCreateCommand),
+    /// List the snapshots stored on the node
+    List(SnapshotTarget),
+    /// Download a snapshot stored on the node
+    Download(SnapshotDownloadCommand),
+    /// Restore the node from a local snapshot file or one stored on the node
+    Restore(SnapshotRestoreCommand),
+}
+
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+pub struct SnapshotTarget {
+    /// The ip or domain name of the node running form-state
+    #[clap(long, default_value="127.0.0.1")]
+    pub host: String,
+    /// The port form-state listens on
+    #[clap(long, default_value="3004")]
+    pub port: u16,
+    /// An operator key trusted by the node, required unless
+    /// the request comes from the node itself
+    #[clap(long)]
+    pub api_key: Option<String>,
+    /// The node id that `api_key` belongs to
+    #[clap(long)]
+    pub node_id: Option<String>,
+}
+
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+pub struct SnapshotCreateCommand {
+    #[clap(flatten)]
+    pub target: SnapshotTarget,
+    /// Also download the snapshot to this path
+    #[clap(long, short)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+pub struct SnapshotDownloadCommand {
+    #[clap(flatten)]
+    pub target: SnapshotTarget,
+    /// The name of the snapshot, as shown by `list`
+    #[clap(long, short)]
+    pub name: String,
+    /// Where to write the snapshot, defaults to its name
+    #[clap(long, short)]
+    pub out: Option<PathBuf>,
+}
+
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+pub struct SnapshotRestoreCommand {
+    #[clap(flatten)]
+    pub target: SnapshotTarget,
+    /// A local snapshot file to upload and restore
+    #[clap(long, short, conflicts_with="name")]
+    pub file: Option<PathBuf>,
+    /// The name of a snapshot already stored on the node
+    #[clap(long, short)]
+    pub name: Option<String>,
+}
+
+impl SnapshotTarget {
+    fn url(&self, path: &str) -> String {
+        format!("http://{}:{}/admin/snapshot/{}", self.host, self.port, path)
+    }
+
+    fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+        let mut request = request;
+        if let Some(key) = &self.api_key {
+            request = request.header("X-Formation-API-Key", key);
+        }
+        if let Some(id) = &self.node_id {
+            request = request.header("X-Formation-Node-ID", id);
+        }
+        request
+    }
+}
+
+impl SnapshotCommand {
+    pub async fn handle(&self) -> Result<(), Box<dyn std::error::Error>> {
+        match self {
+            SnapshotCommand::Create(cmd) => cmd.handle().await,
+            SnapshotCommand::List(target) => handle_list(target).await,
+            SnapshotCommand::Download(cmd) => cmd.handle().await,
+            SnapshotCommand::Restore(cmd) => cmd.handle().await,
+        }
+    }
+}
+
+impl SnapshotCreateCommand {
+    pub async fn handle(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let resp = self.target.authorize(Client::new().post(self.target.url("create")))
+            .send().await?
+            .json::<Response<SnapshotMetadata>>().await?;
+
+        let metadata = expect_one(resp, "create snapshot")?;
+        print_metadata(&metadata);
+
+        if let Some(out) = &self.out {
+            download(&self.target, &metadata.name, out).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl SnapshotDownloadCommand {
+    pub async fn handle(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let out = self.out.clone().unwrap_or_else(|| PathBuf::from(&self.name));
+        download(&self.target, &self.name, &out).await
+    }
+}
+
+impl SnapshotRestoreCommand {
+    pub async fn handle(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let request = match (&self.file, &self.name) {
+            (Some(file), _) => {
+                // Verify locally first so a corrupt file never leaves this machine
+                let bytes = std::fs::read(file)?;
+                Snapshot::from_bytes(&bytes)?;
+                Client::new()
+                    .post(self.target.url("restore"))
+                    .header("Content-Type", "application/octet-stream")
+                    .body(bytes)
+            }
+            (None, Some(name)) => Client::new().post(self.target.url(&format!("{name}/restore"))),
+            (None, None) => return Err("Either --file or --name is required to restore a snapshot".into()),
+        };
+
+        let resp = self.target.authorize(request)
+            .send().await?
+            .json::<Response<SnapshotMetadata>>().await?;
+
+        let metadata = expect_one(resp, "restore snapshot")?;
+        println!("✅ {}", "Successfully restored snapshot".green());
+        print_metadata(&metadata);
+
+        Ok(())
+    }
+}
+
+async fn handle_list(target: &SnapshotTarget) -> Result<(), Box<dyn std::error::Error>> {
+    let resp = target.authorize(Client::new().get(target.url("list")))
+        .send().await?
+        .json::<Response<SnapshotMetadata>>().await?;
+
+    match resp {
+        Response::Success(Success::List(list)) => {
+            if list.is_empty() {
+                println!("No snapshots found on {}", target.host.yellow());
+            }
+            for metadata in list {
+                print_metadata(&metadata);
+            }
+            Ok(())
+        }
+        Response::Failure { reason } => Err(format!("Failed to list snapshots: {}", reason.unwrap_or_default()).into()),
+        _ => Err("Invalid response variant for snapshot list".into()),
+    }
+}
+
+async fn download(target: &SnapshotTarget, name: &str, out: &PathBuf) -> Result<(), Box<dyn std::error::Error>> {
+    let resp = target.authorize(Client::new().get(target.url(&format!("{name}/download"))))
+        .send().await?;
+
+    if !resp.status().is_success() {
+        return Err(format!("Failed to download snapshot {name}: {}", resp.text().await?).into());
+    }
+
+    let bytes = resp.bytes().await?;
+    let snapshot = Snapshot::from_bytes(&bytes)?;
+    std::fs::write(out, &bytes)?;
+    println!(
+        "✅ Downloaded snapshot {} to {} (checksum {})",
+        name.yellow(),
+        out.display().to_string().green(),
+        snapshot.header.checksum
+    );
+
+    Ok(())
+}
+
+fn expect_one(resp: Response<SnapshotMetadata>, action: &str) -> Result<SnapshotMetadata, Box<dyn std::error::Error>> {
+    match resp {
+        Response::Success(Success::Some(metadata)) => Ok(metadata),
+        Response::Failure { reason } => Err(format!("Failed to {action}: {}", reason.unwrap_or_default()).into()),
+        _ => Err(format!("Invalid response variant for {action}").into()),
+    }
+}
+
+fn print_metadata(metadata: &SnapshotMetadata) {
+    let header = &metadata.header;
+    println!(
+        r#"
+Snapshot: {}
+  Taken by: {} at {}
+  Checksum: {}
+  Size: {} bytes
+  Peers: {}, CIDRs: {}, Associations: {}, DNS records: {}
+  Instances: {}, Nodes: {}, Accounts: {}, Agents: {}, Models: {}"#,
+        metadata.name.yellow(),
+        header.node_id,
+        header.created_at,
+        header.checksum,
+        header.size,
+        header.counts.peers,
+        header.counts.cidrs,
+        header.counts.assocs,
+        header.counts.dns,
+        header.counts.instances,
+        header.counts.nodes,
+        header.counts.accounts,
+        header.counts.agents,
+        header.counts.models,
+    );
+}
@@ -1,5 +1,8 @@
 use clap::Args;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use form_types::{AddDeviceRequest, VmmResponse};
+use crate::Keystore;
+use crate::dev::manage::hotplug::{send, sign_operation, signing_key, target};
 
 #[derive(Clone, Debug, Args)]
 pub struct AddDeviceCommand {
@@ -27,51 +30,38 @@ pub struct AddDeviceCommand {
     #[clap(long)]
     pub path: Option<String>,
     
-    /// ID of the device to add
+    /// Enable IOMMU for this device
     #[clap(long)]
-    pub device_id: Option<String>,
+    pub iommu: bool,
     
-    /// Send request via queue instead of direct API call
+    /// ID of the device to add
     #[clap(long)]
-    pub queue: bool,
+    pub device_id: Option<String>,
 }
 
 impl AddDeviceCommand {
-    /// Handle the add device command using direct API communication
+    /// Passes a host device through to the running instance
     pub async fn handle(
         &self,
-        _provider: &dyn std::fmt::Debug,
-        _vmm_port: u16,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The add device command is not yet implemented");
-        println!("This command will allow adding a device to a running VM in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists and is running
-        // 2. Prepare the device configuration
-        // 3. Send a request to the VMM API to add the device
-        // 4. Handle the response and display results
-        
-        Ok(())
-    }
+        provider: &str,
+        vmm_port: u16,
+        keystore: Option<Keystore>,
+    ) -> Result<VmmResponse> {
+        let (id, name) = target(&self.id, &self.name)?;
+        let path = self.path.clone().ok_or(anyhow!("A device path is required"))?;
+        let signing_key = signing_key(&self.private_key, &self.mnemonic, keystore)?;
+        let (signature, recovery_id) = sign_operation(&signing_key, "AddDeviceRequest", &id)?;
 
-    /// Handle the add device command using queue-based communication
-    pub async fn handle_queue(
-        &self,
-        _provider: &dyn std::fmt::Debug,
-        _keystore: Option<String>,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The add device via queue command is not yet implemented");
-        println!("This command will allow adding a device to a running VM via the message queue in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists
-        // 2. Create and sign a queue message
-        // 3. Send the message to the queue
-        // 4. Display confirmation to the user
-        
-        Ok(())
+        let request = AddDeviceRequest {
+            id: id.clone(),
+            name,
+            path,
+            iommu: self.iommu,
+            device_id: self.device_id.clone(),
+            signature: Some(signature),
+            recovery_id,
+        };
+
+        send(provider, vmm_port, &id, "add_device", &request).await
     }
 }
@@ -1,5 +1,8 @@
 use clap::Args;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use form_types::{AddDiskRequest, VmmResponse};
+use crate::Keystore;
+use crate::dev::manage::hotplug::{send, sign_operation, signing_key, target};
 
 #[derive(Clone, Debug, Args)]
 pub struct AddDiskCommand {
@@ -23,10 +26,14 @@ pub struct AddDiskCommand {
     #[clap(long)]
     pub mnemonic: Option<String>,
     
-    /// Path to the disk image file to add
+    /// Name of the disk image in the instance's volume directory on the node
     #[clap(long)]
     pub path: Option<String>,
     
+    /// Size in GB of the disk to create if the image doesn't exist yet
+    #[clap(long)]
+    pub size_gb: Option<u64>,
+    
     /// Set disk as read-only
     #[clap(long)]
     pub readonly: bool,
@@ -42,48 +49,34 @@ pub struct AddDiskCommand {
     /// Optional disk identifier
     #[clap(long)]
     pub disk_id: Option<String>,
-    
-    /// Send request via queue instead of direct API call
-    #[clap(long)]
-    pub queue: bool,
 }
 
 impl AddDiskCommand {
-    /// Handle the add disk command using direct API communication
+    /// Hot-plugs a disk into the running instance
     pub async fn handle(
         &self,
-        _provider: &dyn std::fmt::Debug,
-        _vmm_port: u16,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The add disk command is not yet implemented");
-        println!("This command will allow adding a disk to a running VM in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists and is running
-        // 2. Prepare the disk configuration
-        // 3. Send a request to the VMM API to add the disk
-        // 4. Handle the response and display results
-        
-        Ok(())
-    }
+        provider: &str,
+        vmm_port: u16,
+        keystore: Option<Keystore>,
+    ) -> Result<VmmResponse> {
+        let (id, name) = target(&self.id, &self.name)?;
+        let path = self.path.clone().ok_or(anyhow!("A disk path is required"))?;
+        let signing_key = signing_key(&self.private_key, &self.mnemonic, keystore)?;
+        let (signature, recovery_id) = sign_operation(&signing_key, "AddDiskRequest", &id)?;
 
-    /// Handle the add disk command using queue-based communication
-    pub async fn handle_queue(
-        &self,
-        _provider: &dyn std::fmt::Debug,
-        _keystore: Option<String>,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The add disk via queue command is not yet implemented");
-        println!("This command will allow adding a disk to a running VM via the message queue in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists
-        // 2. Create and sign a queue message
-        // 3. Send the message to the queue
-        // 4. Display confirmation to the user
-        
-        Ok(())
+        let request = AddDiskRequest {
+            id: id.clone(),
+            name,
+            path,
+            size_gb: self.size_gb,
+            readonly: self.readonly,
+            direct: self.direct,
+            iommu: self.iommu,
+            disk_id: self.disk_id.clone(),
+            signature: Some(signature),
+            recovery_id,
+        };
+
+        send(provider, vmm_port, &id, "add_disk", &request).await
     }
 }
@@ -1,5 +1,8 @@
 use clap::Args;
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use form_types::{AddFsRequest, VmmResponse};
+use crate::Keystore;
+use crate::dev::manage::hotplug::{send, sign_operation, signing_key, target};
 
 #[derive(Clone, Debug, Args)]
 pub struct AddFilesystemCommand {
@@ -23,59 +26,43 @@ pub struct AddFilesystemCommand {
     #[clap(long)]
     pub mnemonic: Option<String>,
     
-    /// Path to the directory to share with the VM
-    #[clap(long)]
-    pub source: Option<String>,
-    
-    /// Mount tag to identify this filesystem in the guest
+    /// Tag the guest mounts the filesystem by
     #[clap(long)]
     pub tag: Option<String>,
     
-    /// Socket path for the virtiofsd daemon
+    /// Name of the virtiofsd socket in the instance's volume directory on the node
     #[clap(long)]
     pub socket: Option<String>,
     
-    /// Send request via queue instead of direct API call
+    /// Optional filesystem identifier
     #[clap(long)]
-    pub queue: bool,
+    pub fs_id: Option<String>,
 }
 
 impl AddFilesystemCommand {
-    /// Handle the add filesystem command using direct API communication
+    /// Hot-plugs a virtio-fs share into the running instance
     pub async fn handle(
         &self,
-        _provider: &dyn std::fmt::Debug,
-        _vmm_port: u16,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The add filesystem command is not yet implemented");
-        println!("This command will allow adding a filesystem to a running VM in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists and is running
-        // 2. Prepare the filesystem configuration
-        // 3. Send a request to the VMM API to add the filesystem
-        // 4. Handle the response and display results
-        
-        Ok(())
-    }
+        provider: &str,
+        vmm_port: u16,
+        keystore: Option<Keystore>,
+    ) -> Result<VmmResponse> {
+        let (id, name) = target(&self.id, &self.name)?;
+        let tag = self.tag.clone().ok_or(anyhow!("A filesystem tag is required"))?;
+        let socket = self.socket.clone().ok_or(anyhow!("A virtiofsd socket is required"))?;
+        let signing_key = signing_key(&self.private_key, &self.mnemonic, keystore)?;
+        let (signature, recovery_id) = sign_operation(&signing_key, "AddFsRequest", &id)?;
 
-    /// Handle the add filesystem command using queue-based communication
-    pub async fn handle_queue(
-        &self,
-        _provider: &dyn std::fmt::Debug,
-        _keystore: Option<String>,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The add filesystem via queue command is not yet implemented");
-        println!("This command will allow adding a filesystem to a running VM via the message queue in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists
-        // 2. Create and sign a queue message
-        // 3. Send the message to the queue
-        // 4. Display confirmation to the user
-        
-        Ok(())
+        let request = AddFsRequest {
+            id: id.clone(),
+            name,
+            tag,
+            socket,
+            fs_id: self.fs_id.clone(),
+            signature: Some(signature),
+            recovery_id,
+        };
+
+        send(provider, vmm_port, &id, "add_fs", &request).await
     }
 }
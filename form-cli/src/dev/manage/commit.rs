@@ -1,53 +1,121 @@
+use std::time::Duration;
 use clap::Args;
 use colored::*;
+use anyhow::Result;
+use form_types::{CommitResponse, CommitStatus, CommitVmRequest, MemberState};
 use crate::Keystore;
+use crate::dev::manage::hotplug::{send, sign_operation, signing_key, target};
 
+/// How often `--wait` checks on the rollout
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Propagates the disk of a modified instance to every other instance in
+/// its cluster. The commit has to be sent to the node running the modified
+/// instance, which updates the other members one at a time, restarting each.
 #[derive(Clone, Debug, Args)]
 pub struct CommitCommand {
     /// The ID of the instance that has been modified
     #[clap(long, short)]
     pub id: Option<String>,
-    
+
     /// The name of the instance that has been modified, an alternative to ID
     #[clap(long, short)]
     pub name: Option<String>,
-    
-    /// A hexadecimal or base64 representation of a valid private key for 
+
+    /// A hexadecimal or base64 representation of a valid private key for
     /// signing the request
     #[clap(long, short)]
     pub private_key: Option<String>,
-    
+
     /// An alternative to private key or mnemonic
     #[clap(long, short)]
     pub keyfile: Option<String>,
-    
+
     /// An alternative to private key or keyfile - BIP39 mnemonic phrase
     #[clap(long, short)]
     pub mnemonic: Option<String>,
-    
+
     /// Description for the commit (optional)
     #[clap(long)]
     pub description: Option<String>,
+
+    /// Follow the rollout, printing each member's progress, until it ends
+    #[clap(long)]
+    pub wait: bool,
+
+    /// Show the progress of an earlier commit instead of starting one
+    #[clap(long)]
+    pub status: Option<String>,
 }
 
 impl CommitCommand {
-    pub async fn handle(&self, _provider: &str, _vmm_port: u16) -> Result<(), Box<dyn std::error::Error>> {
-        println!("{}", "Commit command functionality is planned for future implementation.".yellow());
-        println!("This command will enable you to commit changes from one VM instance to all instances in its cluster.");
-        println!();
-        println!("Implementation plan details can be found in:");
-        println!("form-cli/src/dev/manage/commit_implementation_plan.md");
-        
-        Ok(())
+    pub async fn handle(&self, provider: &str, vmm_port: u16, keystore: Option<Keystore>) -> Result<CommitResponse> {
+        let (id, name) = target(&self.id, &self.name)?;
+        if let Some(commit_id) = &self.status {
+            return status(provider, vmm_port, &id, commit_id).await;
+        }
+
+        let signing_key = signing_key(&self.private_key, &self.mnemonic, keystore)?;
+        let (signature, recovery_id) = sign_operation(&signing_key, "CommitVmRequest", &id)?;
+        let request = CommitVmRequest {
+            id: id.clone(),
+            name,
+            description: self.description.clone(),
+            signature: Some(signature),
+            recovery_id,
+        };
+
+        let resp: CommitResponse = send(provider, vmm_port, &id, "commit", &request).await?;
+        match resp {
+            CommitResponse::Accepted { commit_id, .. } if self.wait => {
+                println!("Commit {} started", commit_id.yellow());
+                wait(provider, vmm_port, &id, &commit_id).await
+            }
+            resp => Ok(resp),
+        }
     }
-    
-    pub async fn handle_queue(&self, _provider: &str, _keystore: Option<Keystore>) -> Result<(), Box<dyn std::error::Error>> {
-        println!("{}", "Commit command functionality is planned for future implementation.".yellow());
-        println!("This command will enable you to commit changes from one VM instance to all instances in its cluster.");
-        println!();
-        println!("Implementation plan details can be found in:");
-        println!("form-cli/src/dev/manage/commit_implementation_plan.md");
-        
-        Ok(())
+}
+
+async fn status(provider: &str, vmm_port: u16, id: &str, commit_id: &str) -> Result<CommitResponse> {
+    Ok(reqwest::Client::new()
+        .get(format!("http://{provider}:{vmm_port}/vm/{id}/commit/{commit_id}"))
+        .send()
+        .await?
+        .json::<CommitResponse>()
+        .await?)
+}
+
+/// Polls a commit until it completes or fails, printing each change
+async fn wait(provider: &str, vmm_port: u16, id: &str, commit_id: &str) -> Result<CommitResponse> {
+    let mut last = None;
+    loop {
+        let current = match status(provider, vmm_port, id, commit_id).await? {
+            CommitResponse::Status(current) => current,
+            resp => return Ok(resp),
+        };
+        let progress = progress(&current);
+        if last.as_ref() != Some(&progress) {
+            println!("{progress}");
+            last = Some(progress);
+        }
+        if current.phase.is_terminal() {
+            return Ok(CommitResponse::Status(current));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn progress(status: &CommitStatus) -> String {
+    let mut lines = vec![format!("Commit {}: {:?}", status.commit_id, status.phase)];
+    for member in &status.members {
+        let state = match &member.state {
+            MemberState::Committed => format!(
+                "committed, {} bytes changed", member.changed_bytes.unwrap_or_default()
+            ).green().to_string(),
+            MemberState::Failed(reason) => format!("failed: {reason}").red().to_string(),
+            state => format!("{state:?}").to_lowercase(),
+        };
+        lines.push(format!("  {} on {}: {state}", member.instance_id, member.node_id));
     }
+    lines.join("\n")
 }
@@ -0,0 +1,69 @@
+//! Shared plumbing for the `form manage add`, `rm` and `commit` commands,
+//! which change a running VM through the vmm-service of the node hosting it.
+
+use alloy_core::primitives::Address;
+use alloy_signer_local::{coins_bip39::English, MnemonicBuilder};
+use anyhow::{anyhow, Result};
+use k256::ecdsa::SigningKey;
+use serde::{de::DeserializeOwned, Serialize};
+use tiny_keccak::{Hasher, Sha3};
+use crate::Keystore;
+
+/// Resolves the instance a command targets, the name defaulting to the id
+pub fn target(id: &Option<String>, name: &Option<String>) -> Result<(String, String)> {
+    match (id, name) {
+        (Some(id), name) => Ok((id.clone(), name.clone().unwrap_or_else(|| id.clone()))),
+        (None, Some(name)) => Ok((name.clone(), name.clone())),
+        _ => Err(anyhow!("Either instance ID or name must be provided")),
+    }
+}
+
+pub fn signing_key(private_key: &Option<String>, mnemonic: &Option<String>, keystore: Option<Keystore>) -> Result<SigningKey> {
+    if let Some(pk) = private_key {
+        Ok(SigningKey::from_slice(&hex::decode(pk)?)?)
+    } else if let Some(ks) = keystore {
+        Ok(SigningKey::from_slice(&hex::decode(ks.secret_key)?)?)
+    } else if let Some(mnemonic) = mnemonic {
+        Ok(SigningKey::from_slice(&MnemonicBuilder::<English>::default()
+            .phrase(mnemonic)
+            .derivation_path("m/44'/60'/0'/0/0")?
+            .build()?
+            .to_field_bytes()
+            .to_vec()
+        )?)
+    } else {
+        Err(anyhow!("A signing key is required, use either private_key, mnemonic or keyfile CLI arg to provide a valid signing key"))
+    }
+}
+
+/// Signs `<operation>:<id>`, the message vmm-service checks the signature
+/// of a VM operation against. Returns the hex signature and recovery id.
+pub fn sign_operation(signing_key: &SigningKey, operation: &str, id: &str) -> Result<(String, u32)> {
+    sign_message(signing_key, &format!("{operation}:{id}"))
+}
+
+/// Signs the Sha3-256 hash of `message`. Returns the hex signature and
+/// recovery id.
+pub fn sign_message(signing_key: &SigningKey, message: &str) -> Result<(String, u32)> {
+    let mut hasher = Sha3::v256();
+    let mut message_hash = [0u8; 32];
+    hasher.update(message.as_bytes());
+    hasher.finalize(&mut message_hash);
+
+    let (sig, rec) = signing_key.sign_recoverable(&message_hash)?;
+    let address = Address::from_private_key(signing_key);
+    println!("Request will be signed by address: {address:x}");
+
+    Ok((hex::encode(sig.to_vec()), rec.to_byte() as u32))
+}
+
+/// Posts a signed request to `/vm/<id>/<endpoint>` on the provider
+pub async fn send<T: Serialize, R: DeserializeOwned>(provider: &str, vmm_port: u16, id: &str, endpoint: &str, request: &T) -> Result<R> {
+    Ok(reqwest::Client::new()
+        .post(format!("http://{provider}:{vmm_port}/vm/{id}/{endpoint}"))
+        .json(request)
+        .send()
+        .await?
+        .json::<R>()
+        .await?)
+}
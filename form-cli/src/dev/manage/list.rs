@@ -0,0 +1,139 @@
+use clap::{Args, ValueEnum};
+use colored::*;
+use reqwest::Client;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use form_types::state::{Response, Success};
+use form_state::pagination::{ListQuery, Page, SortOrder};
+
+/// Records are fetched in pages of this size when no limit is given
+const FETCH_PAGE_SIZE: usize = 500;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ListResource {
+    Instances,
+    Nodes,
+    Accounts,
+    Dns,
+    Users,
+}
+
+impl ListResource {
+    fn path(&self) -> &'static str {
+        match self {
+            ListResource::Instances => "instance/list",
+            ListResource::Nodes => "node/list",
+            ListResource::Accounts => "account/list",
+            ListResource::Dns => "dns/list",
+            ListResource::Users => "user/list",
+        }
+    }
+}
+
+/// List records stored in form-state, with optional filters and sorting.
+#[derive(Clone, Debug, Args)]
+pub struct ListCommand {
+    /// The kind of record to list
+    #[clap(value_enum)]
+    pub resource: ListResource,
+    /// Only return records owned by this address
+    #[clap(long)]
+    pub owner: Option<String>,
+    /// Only return records with this status
+    #[clap(long)]
+    pub status: Option<String>,
+    /// Only return records in this region
+    #[clap(long)]
+    pub region: Option<String>,
+    /// Only return records with this tag, may be repeated
+    #[clap(long = "tag")]
+    pub tags: Vec<String>,
+    /// Only return instances of this build
+    #[clap(long)]
+    pub build_id: Option<String>,
+    /// The field to sort by
+    #[clap(long)]
+    pub sort: Option<String>,
+    /// Sort in descending order
+    #[clap(long)]
+    pub desc: bool,
+    /// Return at most this many records; by default every matching
+    /// record is fetched, one page at a time
+    #[clap(long)]
+    pub limit: Option<usize>,
+    /// Continue from the cursor printed by a previous limited list
+    #[clap(long)]
+    pub cursor: Option<String>,
+    /// The port form-state listens on
+    #[clap(long, default_value="3004")]
+    pub port: u16,
+}
+
+impl ListCommand {
+    fn query(&self) -> ListQuery {
+        ListQuery {
+            limit: self.limit,
+            cursor: self.cursor.clone(),
+            sort: self.sort.clone(),
+            order: self.desc.then_some(SortOrder::Desc),
+            owner: self.owner.clone(),
+            status: self.status.clone(),
+            region: self.region.clone(),
+            tags: (!self.tags.is_empty()).then(|| self.tags.join(",")),
+            build_id: self.build_id.clone(),
+        }
+    }
+
+    pub async fn handle(&self, provider: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let url = format!("http://{provider}:{}/{}", self.port, self.resource.path());
+        let query = self.query();
+
+        let (records, next_cursor) = if self.limit.is_some() {
+            let page = fetch_page::<Value>(&url, &query).await?;
+            (page.items, page.next_cursor)
+        } else {
+            (fetch_all::<Value>(&url, query).await?, None)
+        };
+
+        for record in &records {
+            println!("{}", serde_json::to_string_pretty(record)?);
+        }
+
+        println!("\n{} {:?} returned", records.len().to_string().yellow(), self.resource);
+        if let Some(cursor) = next_cursor {
+            println!("More records available, continue with {} {}", "--cursor".bold(), cursor.green());
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetches a single page from a form-state list endpoint
+pub async fn fetch_page<T: DeserializeOwned>(url: &str, query: &ListQuery) -> Result<Page<T>, Box<dyn std::error::Error>> {
+    let resp = Client::new()
+        .get(url)
+        .query(query)
+        .send().await?
+        .json::<Response<Page<T>>>().await?;
+
+    match resp {
+        Response::Success(Success::Some(page)) => Ok(page),
+        Response::Failure { reason } => Err(format!("Failed to list {url}: {}", reason.unwrap_or_default()).into()),
+        _ => Err(format!("Invalid response variant from {url}").into()),
+    }
+}
+
+/// Walks every page of a form-state list endpoint and returns all records
+/// matching the query
+pub async fn fetch_all<T: DeserializeOwned>(url: &str, mut query: ListQuery) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+    query.limit = Some(FETCH_PAGE_SIZE);
+    let mut records = Vec::new();
+    loop {
+        let page = fetch_page::<T>(url, &query).await?;
+        records.extend(page.items);
+        match page.next_cursor {
+            Some(cursor) => query.cursor = Some(cursor),
+            None => return Ok(records),
+        }
+    }
+}
@@ -9,6 +9,8 @@ pub mod commit;
 pub mod config;
 pub mod join;
 pub mod account;
+pub mod list;
+pub mod hotplug;
 
 pub use start::StartCommand;
 pub use stop::StopCommand;
@@ -19,6 +21,7 @@ pub use commit::CommitCommand;
 pub use config::ConfigCommand;
 pub use join::{JoinCommand, FormnetUp};
 pub use account::TransferOwnershipCommand;
+pub use list::ListCommand;
 
 #[derive(Debug, Subcommand)]
 pub enum ManageCommand {
@@ -37,6 +40,8 @@ pub enum ManageCommand {
     Leave(LeaveCommand),
     /// Transfer ownership of an instance from one account to another
     TransferOwnership(TransferOwnershipCommand),
+    /// List instances, nodes, accounts, DNS records or formnet users
+    List(ListCommand),
 }
 
 
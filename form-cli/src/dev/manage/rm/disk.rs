@@ -1,5 +1,8 @@
 use clap::Args;
 use anyhow::Result;
+use form_types::{RemoveDeviceRequest, VmmResponse};
+use crate::Keystore;
+use crate::dev::manage::hotplug::{send, sign_operation, signing_key, target};
 
 #[derive(Clone, Debug, Args)]
 pub struct RemoveDiskCommand {
@@ -26,48 +29,28 @@ pub struct RemoveDiskCommand {
     /// ID of the disk to remove (as returned when the disk was added)
     #[clap(long, required = true)]
     pub disk_id: String,
-    
-    /// Send request via queue instead of direct API call
-    #[clap(long)]
-    pub queue: bool,
 }
 
 impl RemoveDiskCommand {
-    /// Handle the remove disk command using direct API communication
+    /// Unplugs a disk from the running instance
     pub async fn handle(
         &self,
-        _provider: &dyn std::fmt::Debug,
-        _vmm_port: u16,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The remove disk command is not yet implemented");
-        println!("This command will allow removing a disk from a running VM in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists and is running
-        // 2. Verify the disk exists
-        // 3. Send a request to the VMM API to remove the disk
-        // 4. Handle the response and display results
-        
-        Ok(())
-    }
+        provider: &str,
+        vmm_port: u16,
+        keystore: Option<Keystore>,
+    ) -> Result<VmmResponse> {
+        let (id, name) = target(&self.id, &self.name)?;
+        let signing_key = signing_key(&self.private_key, &self.mnemonic, keystore)?;
+        let (signature, recovery_id) = sign_operation(&signing_key, "RemoveDeviceRequest", &id)?;
 
-    /// Handle the remove disk command using queue-based communication
-    pub async fn handle_queue(
-        &self,
-        _provider: &dyn std::fmt::Debug,
-        _keystore: Option<String>,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The remove disk via queue command is not yet implemented");
-        println!("This command will allow removing a disk from a running VM via the message queue in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists
-        // 2. Create and sign a queue message
-        // 3. Send the message to the queue
-        // 4. Display confirmation to the user
-        
-        Ok(())
+        let request = RemoveDeviceRequest {
+            id: id.clone(),
+            name,
+            device_id: self.disk_id.clone(),
+            signature: Some(signature),
+            recovery_id,
+        };
+
+        send(provider, vmm_port, &id, "remove_device", &request).await
     }
 }
@@ -1,5 +1,8 @@
 use clap::Args;
 use anyhow::Result;
+use form_types::{RemoveDeviceRequest, VmmResponse};
+use crate::Keystore;
+use crate::dev::manage::hotplug::{send, sign_operation, signing_key, target};
 
 #[derive(Clone, Debug, Args)]
 pub struct RemoveFilesystemCommand {
@@ -26,48 +29,28 @@ pub struct RemoveFilesystemCommand {
     /// ID of the filesystem to remove (as returned when the filesystem was added)
     #[clap(long, required = true)]
     pub fs_id: String,
-    
-    /// Send request via queue instead of direct API call
-    #[clap(long)]
-    pub queue: bool,
 }
 
 impl RemoveFilesystemCommand {
-    /// Handle the remove filesystem command using direct API communication
+    /// Unplugs a filesystem from the running instance
     pub async fn handle(
         &self,
-        _provider: &dyn std::fmt::Debug,
-        _vmm_port: u16,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The remove filesystem command is not yet implemented");
-        println!("This command will allow removing a filesystem from a running VM in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists and is running
-        // 2. Verify the filesystem exists
-        // 3. Send a request to the VMM API to remove the filesystem
-        // 4. Handle the response and display results
-        
-        Ok(())
-    }
+        provider: &str,
+        vmm_port: u16,
+        keystore: Option<Keystore>,
+    ) -> Result<VmmResponse> {
+        let (id, name) = target(&self.id, &self.name)?;
+        let signing_key = signing_key(&self.private_key, &self.mnemonic, keystore)?;
+        let (signature, recovery_id) = sign_operation(&signing_key, "RemoveDeviceRequest", &id)?;
 
-    /// Handle the remove filesystem command using queue-based communication
-    pub async fn handle_queue(
-        &self,
-        _provider: &dyn std::fmt::Debug,
-        _keystore: Option<String>,
-    ) -> Result<()> {
-        // This feature is not yet implemented
-        println!("The remove filesystem via queue command is not yet implemented");
-        println!("This command will allow removing a filesystem from a running VM via the message queue in the future");
-        
-        // Future implementation will:
-        // 1. Validate the VM exists
-        // 2. Create and sign a queue message
-        // 3. Send the message to the queue
-        // 4. Display confirmation to the user
-        
-        Ok(())
+        let request = RemoveDeviceRequest {
+            id: id.clone(),
+            name,
+            device_id: self.fs_id.clone(),
+            signature: Some(signature),
+            recovery_id,
+        };
+
+        send(provider, vmm_port, &id, "remove_device", &request).await
     }
 }
@@ -0,0 +1,200 @@
+use std::path::PathBuf;
+use std::time::Duration;
+use anyhow::{anyhow, Result};
+use clap::{Args, ValueEnum};
+use colored::*;
+use form_types::{
+    BatchState, CreateVmRequest, DeployRequest, DeployResponse, DeployStatus,
+    DeployStrategy, HealthCheck,
+};
+use crate::{default_context, default_formfile, Keystore, ShipCommand};
+use crate::dev::manage::hotplug::{sign_message, signing_key};
+
+/// How often `--wait` checks on the deploy
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RolloutStrategy {
+    Rolling,
+    BlueGreen,
+}
+
+/// Deploys a new version of a Formpack over a running build, moving its
+/// domain over to the new build's instances once they pass their health
+/// checks. Build the new version with `form pack build` first, under a new
+/// name in the Formfile; the deploy creates its instances, so it replaces
+/// `form pack ship`. If the new instances fail their checks, the domain is
+/// pointed back at the old build and the new instances are deleted.
+#[derive(Debug, Clone, Args)]
+pub struct DeployCommand {
+    /// Path to the context directory of the new version
+    #[clap(default_value_os_t = default_context())]
+    pub context_dir: PathBuf,
+    /// Path to the Formfile of the new version
+    #[clap(long, short, default_value_os_t = default_formfile(default_context()))]
+    pub formfile: PathBuf,
+    /// The build id of the running version to deploy over
+    #[clap(long, required_unless_present = "status")]
+    pub from: Option<String>,
+    /// The domain to move over to the new version, which has to point at
+    /// the running version
+    #[clap(long, required_unless_present = "status")]
+    pub domain: Option<String>,
+    /// How traffic is moved over to the new version
+    #[clap(long, value_enum, default_value_t = RolloutStrategy::Rolling)]
+    pub strategy: RolloutStrategy,
+    /// Number of new instances brought up and given traffic at a time in a
+    /// rolling deploy
+    #[clap(long, default_value_t = 1)]
+    pub batch_size: u32,
+    /// Number of instances of the new version, defaults to the number the
+    /// running version has
+    #[clap(long)]
+    pub instances: Option<u32>,
+    /// Path new instances have to answer with a 2xx status before they are
+    /// given traffic, and for as long as they are observed
+    #[clap(long)]
+    pub health_path: Option<String>,
+    /// Port the health check is sent to
+    #[clap(long, default_value_t = 80)]
+    pub health_port: u16,
+    /// Seconds a batch has to pass its health checks in
+    #[clap(long, default_value_t = 600)]
+    pub gate_timeout: u64,
+    /// Seconds a batch has to stay healthy while serving traffic before the
+    /// deploy moves on
+    #[clap(long, default_value_t = 60)]
+    pub observe: u64,
+    /// A hexadecimal or base64 representation of a valid private key for
+    /// signing the request
+    #[clap(long, short)]
+    pub private_key: Option<String>,
+    /// An alternative to private key or mnemonic
+    #[clap(long, short)]
+    pub keyfile: Option<String>,
+    /// An alternative to private key or keyfile - BIP39 mnemonic phrase
+    #[clap(long, short)]
+    pub mnemonic: Option<String>,
+    /// Follow the deploy, printing each batch's progress, until it ends
+    #[clap(long)]
+    pub wait: bool,
+    /// Show the progress of an earlier deploy instead of starting one
+    #[clap(long)]
+    pub status: Option<String>,
+}
+
+impl DeployCommand {
+    pub async fn handle(&self, provider: &str, vmm_port: u16, keystore: Option<Keystore>) -> Result<DeployResponse> {
+        if let Some(deploy_id) = &self.status {
+            return status(provider, vmm_port, deploy_id).await;
+        }
+        let from = self.from.clone().ok_or_else(|| anyhow!("The build id to deploy over is required"))?;
+        let domain = self.domain.clone().ok_or_else(|| anyhow!("The domain to deploy is required"))?;
+
+        let create_request = self.create_request(keystore.clone())?;
+        let to = create_request.name.clone();
+        let signing_key = signing_key(&self.private_key, &self.mnemonic, keystore)?;
+
+        let mut request = DeployRequest {
+            from_build_id: from.clone(),
+            to_build_id: to.clone(),
+            domain,
+            strategy: match self.strategy {
+                RolloutStrategy::Rolling => DeployStrategy::Rolling { batch_size: self.batch_size },
+                RolloutStrategy::BlueGreen => DeployStrategy::BlueGreen,
+            },
+            instances: self.instances,
+            health_check: self.health_path.clone().map(|path| HealthCheck { port: self.health_port, path }),
+            gate_timeout_secs: self.gate_timeout,
+            observe_secs: self.observe,
+            create_request,
+            signature: None,
+            recovery_id: 0,
+        };
+        let (signature, recovery_id) = sign_message(&signing_key, &request.signing_message())?;
+        request.signature = Some(signature);
+        request.recovery_id = recovery_id;
+
+        let resp = reqwest::Client::new()
+            .post(format!("http://{provider}:{vmm_port}/cluster/{from}/deploy"))
+            .json(&request)
+            .send()
+            .await?
+            .json::<DeployResponse>()
+            .await?;
+        match resp {
+            DeployResponse::Accepted { deploy_id } if self.wait => {
+                println!("Deploy {} of {} started", deploy_id.yellow(), to.yellow());
+                wait(provider, vmm_port, &deploy_id).await
+            }
+            resp => Ok(resp),
+        }
+    }
+
+    /// Signs a create request for the new version the way `form pack ship`
+    /// does, which also derives its build id
+    fn create_request(&self, keystore: Option<Keystore>) -> Result<CreateVmRequest> {
+        let mut ship = ShipCommand {
+            context_dir: self.context_dir.clone(),
+            formfile: self.formfile.clone(),
+            private_key: self.private_key.clone(),
+            keyfile: self.keyfile.clone(),
+            mnemonic: self.mnemonic.clone(),
+        };
+        let key = ship.get_signing_key(keystore.clone()).map_err(|e| anyhow!(e))?;
+        let name = hex::encode(ship.derive_name(&key).map_err(|e| anyhow!(e))?);
+        let (signature, recovery_id, _) = ship.sign_payload(keystore).map_err(|e| anyhow!(e))?;
+        Ok(CreateVmRequest {
+            name,
+            formfile: serde_json::to_string(&ship.parse_formfile().map_err(|e| anyhow!(e))?)?,
+            signature: Some(signature),
+            recovery_id: recovery_id.to_byte() as u32,
+        })
+    }
+}
+
+async fn status(provider: &str, vmm_port: u16, deploy_id: &str) -> Result<DeployResponse> {
+    Ok(reqwest::Client::new()
+        .get(format!("http://{provider}:{vmm_port}/deploy/{deploy_id}"))
+        .send()
+        .await?
+        .json::<DeployResponse>()
+        .await?)
+}
+
+/// Polls a deploy until it completes or is rolled back, printing each change
+async fn wait(provider: &str, vmm_port: u16, deploy_id: &str) -> Result<DeployResponse> {
+    let mut last = None;
+    loop {
+        let current = match status(provider, vmm_port, deploy_id).await? {
+            DeployResponse::Status(current) => current,
+            resp => return Ok(resp),
+        };
+        let progress = progress(&current);
+        if last.as_ref() != Some(&progress) {
+            println!("{progress}");
+            last = Some(progress);
+        }
+        if current.phase.is_terminal() {
+            return Ok(DeployResponse::Status(current));
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+fn progress(status: &DeployStatus) -> String {
+    let mut lines = vec![format!(
+        "Deploy {} of {} over {}: {:?}",
+        status.deploy_id, status.to_build_id, status.from_build_id, status.phase
+    )];
+    for (index, batch) in status.batches.iter().enumerate() {
+        let state = match &batch.state {
+            BatchState::Healthy => "healthy".green().to_string(),
+            BatchState::Failed(reason) => format!("failed: {reason}").red().to_string(),
+            state => format!("{state:?}").to_lowercase(),
+        };
+        lines.push(format!("  batch {} ({} instances): {state}", index + 1, batch.size));
+    }
+    lines.push(format!("  {} serves {} instances", status.domain, status.serving.len()));
+    lines.join("\n")
+}
@@ -6,12 +6,14 @@ pub mod validate;
 pub mod ship;
 pub mod dry_run;
 pub mod status;
+pub mod deploy;
 
 pub use build::*;
 pub use validate::*;
 pub use ship::*;
 pub use dry_run::*;
 pub use status::*;
+pub use deploy::*;
 
 pub fn default_formfile(context: PathBuf) -> PathBuf {
     context.join("Formfile")
@@ -28,5 +30,6 @@ pub enum PackCommand {
     Ship(ShipCommand),
     DryRun(DryRunCommand),
     Status(StatusCommand),
+    Deploy(DeployCommand),
 }
 
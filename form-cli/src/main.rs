@@ -1,12 +1,13 @@
 use std::path::PathBuf;
-use form_types::state::{Response, Success};
 use clap::{Parser, Subcommand};
 use dialoguer::{theme::ColorfulTheme, Confirm};
 use colored::*;
 use form_cli::{
-    decrypt_file, default_config_dir, default_data_dir, default_keystore_dir, join_formnet, operator_config, Config, DnsCommand, Init, Keystore, KitCommand, manage::ManageCommand, Operator, PackCommand, WalletCommand
+    decrypt_file, default_config_dir, default_data_dir, default_keystore_dir, join_formnet, operator_config, Config, DnsCommand, Init, Keystore, KitCommand, manage::{AddCommand, ManageCommand, RemoveCommand}, Operator, PackCommand, WalletCommand
 };
 use form_p2p::queue::QUEUE_PORT;
+use form_cli::manage::list::fetch_all;
+use form_state::pagination::ListQuery;
 use formnet::{leave, uninstall};
 use reqwest::Client;
 use serde_json::Value;
@@ -137,6 +138,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     let provider = config.hosts[0].clone();
                     status_command.handle_status(provider, 3004).await?;
                 }
+                PackCommand::Deploy(deploy_command) => {
+                    let (config, keystore) = load_config_and_keystore(&parser).await?;
+                    let provider = config.hosts[0].clone();
+                    let resp = deploy_command.handle(&provider, config.vmm_port, Some(keystore)).await?;
+                    println!("Response: {:?}", resp);
+                }
             }
         }
         FormCommand::Kit(ref mut kit_command) => {
@@ -156,6 +163,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                         Operator::Config => {
                             operator_config()?;
                         }
+                        Operator::Snapshot(snapshot_command) => {
+                            snapshot_command.handle().await?;
+                        }
                     }
                 }
             }
@@ -204,31 +214,17 @@ The {} interface has officially been removed from your machine
                     let (config, _) = load_config_and_keystore(&parser).await?;
                     let build_id = get_ip_command.build_id.clone();
                     let host = config.hosts[0].clone();
-                    let resp = Client::new()
-                        .get(format!("http://{host}:3004/instance/list"))
-                        .send()
-                        .await?.json::<Response<Value>>().await?;
-
-                    let ips = match resp {
-                        Response::Success(Success::List(values)) => {
-                            values.iter().filter_map(|inst| {
-                                if let Some(bid) = inst.get("build_id").and_then(|bid| bid.as_str()) {
-                                    if bid == build_id {
-                                        if let Some(ip) = inst.get("formnet_ip").and_then(|ip| ip.as_str()) {
-                                            Some(ip.to_string())
-                                        } else {
-                                            None 
-                                        }
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            }).collect::<Vec<String>>()
-                        }
-                        _ => vec![],
+                    let query = ListQuery {
+                        build_id: Some(build_id),
+                        ..Default::default()
                     };
+                    let instances = fetch_all::<Value>(&format!("http://{host}:3004/instance/list"), query)
+                        .await
+                        .unwrap_or_default();
+
+                    let ips = instances.iter().filter_map(|inst| {
+                        inst.get("formnet_ip").and_then(|ip| ip.as_str()).map(|ip| ip.to_string())
+                    }).collect::<Vec<String>>();
                     let ips_string = ips.join(", ");
                     println!(r#"
 Your build has {} instances, below are their formnet ip addresses:
@@ -239,6 +235,11 @@ format!("{}", ips.len()).yellow(),
 ips_string.yellow(),
 );
                 }
+                ManageCommand::List(list_command) => {
+                    let (config, _) = load_config_and_keystore(&parser).await?;
+                    let provider = config.hosts[0].clone();
+                    list_command.handle(&provider).await?;
+                }
                 ManageCommand::FormnetUp(formnet_up_command) => {
                 if parser.debug {
                     simple_logger::SimpleLogger::new().init().unwrap();
@@ -278,11 +279,28 @@ ips_string.yellow(),
                 ManageCommand::Commit(commit_command) => {
                     let (config, keystore) = load_config_and_keystore(&parser).await?;
                     let provider = config.hosts[0].clone();
-                    if parser.queue {
-                        commit_command.handle_queue(&provider, Some(keystore)).await?;
-                    } else {
-                        commit_command.handle(&provider, config.vmm_port).await?;
-                    }
+                    let resp = commit_command.handle(&provider, config.vmm_port, Some(keystore)).await?;
+                    println!("Response: {:?}", resp);
+                }
+                ManageCommand::Add(add_command) => {
+                    let (config, keystore) = load_config_and_keystore(&parser).await?;
+                    let provider = config.hosts[0].clone();
+                    let resp = match add_command {
+                        AddCommand::Disk(disk_command) => disk_command.handle(&provider, config.vmm_port, Some(keystore)).await?,
+                        AddCommand::Fs(fs_command) => fs_command.handle(&provider, config.vmm_port, Some(keystore)).await?,
+                        AddCommand::Device(device_command) => device_command.handle(&provider, config.vmm_port, Some(keystore)).await?,
+                    };
+                    println!("Response: {:?}", resp);
+                }
+                ManageCommand::Rm(rm_command) => {
+                    let (config, keystore) = load_config_and_keystore(&parser).await?;
+                    let provider = config.hosts[0].clone();
+                    let resp = match rm_command {
+                        RemoveCommand::Disk(disk_command) => disk_command.handle(&provider, config.vmm_port, Some(keystore)).await?,
+                        RemoveCommand::Fs(fs_command) => fs_command.handle(&provider, config.vmm_port, Some(keystore)).await?,
+                        RemoveCommand::Device(device_command) => device_command.handle(&provider, config.vmm_port, Some(keystore)).await?,
+                    };
+                    println!("Response: {:?}", resp);
                 }
                 _ => {}
             }
@@ -301,6 +319,9 @@ ips_string.yellow(),
                 DnsCommand::Remove(remove_command) => {
                     remove_command.handle_remove_command(provider).await?;
                 },
+                DnsCommand::Route(route_command) => {
+                    route_command.handle_route_command(provider).await?;
+                },
                 DnsCommand::Verify(verify_command) => {
                     verify_command.handle_verify_command(provider).await?;
                 }
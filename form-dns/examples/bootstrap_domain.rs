@@ -3,6 +3,7 @@ use std::sync::Arc;
 use std::time::Duration;
 use form_dns::authority::FormAuthority;
 use form_dns::health;
+use form_dns::routing::RoutingPolicy;
 use form_dns::store::{DnsStore, SharedStore, FormDnsRecord, VerificationStatus};
 use tokio::sync::RwLock;
 use trust_dns_client::client::{AsyncClient, ClientHandle};
@@ -61,6 +62,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             verification_timestamp: Some(std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .map_or(0, |d| d.as_secs())),
+            txt: vec![],
+            mx: vec![],
+            srv: vec![],
+            caa: vec![],
+            routing: RoutingPolicy::default(),
         };
         
         // Add the bootstrap domain to the DNS store
@@ -3,6 +3,7 @@ use std::sync::Arc;
 use std::time::Duration;
 use form_dns::authority::FormAuthority;
 use form_dns::health;
+use form_dns::routing::RoutingPolicy;
 use form_dns::store::{DnsStore, SharedStore, FormDnsRecord, VerificationStatus};
 use tokio::sync::RwLock;
 use trust_dns_client::client::AsyncClient;
@@ -44,6 +45,11 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ttl: 300,
             verification_status: Some(VerificationStatus::NotVerified),
             verification_timestamp: Some(0),
+            txt: vec![],
+            mx: vec![],
+            srv: vec![],
+            caa: vec![],
+            routing: RoutingPolicy::default(),
         };
         
         store_guard.insert(test_domain, record).await;
@@ -1,6 +1,10 @@
-use std::{collections::hash_map::Entry, net::{IpAddr, Ipv4Addr, SocketAddr}};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
-use crate::store::{FormDnsRecord, SharedStore, VerificationResult, VerificationStatus};
+use crate::routing::RoutingPolicy;
+use crate::store::{
+    txt_rdata, CaaRecord, FormDnsRecord, MxRecord, SharedStore, SrvRecord, VerificationResult,
+    VerificationStatus
+};
 use serde::{Serialize, Deserialize};
 use axum::{extract::{Path, State}, routing::{delete, get, post}, Json, Router};
 use tokio::net::TcpListener;
@@ -30,6 +34,17 @@ pub enum DomainRequest {
         ip_addr: Vec<SocketAddr>,
         cname_target: Option<String>,
         ssl_cert: bool,
+        #[serde(default)]
+        txt: Vec<String>,
+        #[serde(default)]
+        mx: Vec<MxRecord>,
+        #[serde(default)]
+        srv: Vec<SrvRecord>,
+        #[serde(default)]
+        caa: Vec<CaaRecord>,
+        /// Routing policy of the record, proximity when not given
+        #[serde(default)]
+        routing: Option<RoutingPolicy>,
     },
     Update {
         replace: bool,
@@ -37,6 +52,17 @@ pub enum DomainRequest {
         ip_addr: Vec<SocketAddr>,
         cname_target: Option<String>,
         ssl_cert: bool,
+        #[serde(default)]
+        txt: Vec<String>,
+        #[serde(default)]
+        mx: Vec<MxRecord>,
+        #[serde(default)]
+        srv: Vec<SrvRecord>,
+        #[serde(default)]
+        caa: Vec<CaaRecord>,
+        /// Replaces the routing policy of the record when given
+        #[serde(default)]
+        routing: Option<RoutingPolicy>,
     },
 }
 
@@ -80,16 +106,70 @@ pub struct BootstrapNodeInfo {
     pub health_status: String,  // "healthy", "unhealthy", etc.
 }
 
+/// Check that every TXT, MX, SRV and CAA value of a request can be served
+fn validate_rdata(txt: &[String], mx: &[MxRecord], srv: &[SrvRecord], caa: &[CaaRecord]) -> Result<(), String> {
+    for value in txt {
+        txt_rdata(value)?;
+    }
+    for record in mx {
+        record.rdata()?;
+    }
+    for record in srv {
+        record.rdata()?;
+    }
+    for record in caa {
+        record.rdata()?;
+    }
+    Ok(())
+}
+
+/// Whether a request carries values of a TXT, MX, SRV or CAA record type
+fn has_rdata(record_type: RecordType, txt: &[String], mx: &[MxRecord], srv: &[SrvRecord], caa: &[CaaRecord]) -> bool {
+    match record_type {
+        RecordType::TXT => !txt.is_empty(),
+        RecordType::MX => !mx.is_empty(),
+        RecordType::SRV => !srv.is_empty(),
+        RecordType::CAA => !caa.is_empty(),
+        _ => false,
+    }
+}
+
+/// Target of an ANAME record, which has to be another domain
+fn alias_target(domain: &str, target: Option<String>) -> Result<String, String> {
+    let Some(target) = target else {
+        return Err("ANAME Record requires a target domain be provided as its cname_target".to_string());
+    };
+    if target.trim_end_matches('.').eq_ignore_ascii_case(domain.trim_end_matches('.')) {
+        return Err(format!("The ANAME record of {domain} cannot point to itself"));
+    }
+    Ok(target)
+}
+
+fn extend_unique<T: PartialEq>(values: &mut Vec<T>, new: Vec<T>) {
+    for value in new {
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+}
+
 async fn create_record(
     State(state): State<SharedStore>,
     Json(request): Json<DomainRequest>,
 ) -> Json<DomainResponse> {
     log::info!("Received Create request..."); 
     match request {
-        DomainRequest::Create { domain, record_type, ip_addr, cname_target, ssl_cert } => {
+        DomainRequest::Create { domain, record_type, ip_addr, cname_target, ssl_cert, txt, mx, srv, caa, routing } => {
             log::info!("Create request for {domain}: {record_type}..."); 
             log::info!("Create ips?: {ip_addr:?}...");
             log::info!("Create CNAME target?: {cname_target:?}...");
+            if let Err(e) = validate_rdata(&txt, &mx, &srv, &caa) {
+                return Json(DomainResponse::Failure(Some(e)));
+            }
+            let routing = routing.unwrap_or_default();
+            if let Err(e) = routing.validate() {
+                return Json(DomainResponse::Failure(Some(e)));
+            }
             let record = match record_type {
                 RecordType::A => {
                     let (formnet_ip, public_ip) = if !ip_addr.is_empty() {
@@ -122,6 +202,11 @@ async fn create_record(
                         ttl: 3600,
                         verification_status: Some(VerificationStatus::NotVerified),
                         verification_timestamp: None,
+                        txt: vec![],
+                        mx: vec![],
+                        srv: vec![],
+                        caa: vec![],
+                        routing: RoutingPolicy::default(),
                     }
                 }
                 RecordType::AAAA => {
@@ -152,6 +237,11 @@ async fn create_record(
                         ttl: 3600,
                         verification_status: Some(VerificationStatus::NotVerified),
                         verification_timestamp: None,
+                        txt: vec![],
+                        mx: vec![],
+                        srv: vec![],
+                        caa: vec![],
+                        routing: RoutingPolicy::default(),
                     }
                 }
                 RecordType::CNAME => {
@@ -172,10 +262,61 @@ async fn create_record(
                         ttl: 3600,
                         verification_status: Some(VerificationStatus::NotVerified),
                         verification_timestamp: None,
+                        txt: vec![],
+                        mx: vec![],
+                        srv: vec![],
+                        caa: vec![],
+                        routing: RoutingPolicy::default(),
+                    }
+                }
+                RecordType::ANAME => {
+                    let cname_target = match alias_target(&domain, cname_target) {
+                        Ok(target) => Some(target),
+                        Err(e) => return Json(DomainResponse::Failure(Some(e))),
+                    };
+
+                    FormDnsRecord {
+                        domain: domain.clone(),
+                        record_type,
+                        formnet_ip: vec![],
+                        public_ip: vec![],
+                        cname_target,
+                        ssl_cert,
+                        ttl: 3600,
+                        verification_status: Some(VerificationStatus::NotVerified),
+                        verification_timestamp: None,
+                        txt: vec![],
+                        mx: vec![],
+                        srv: vec![],
+                        caa: vec![],
+                        routing: RoutingPolicy::default(),
+                    }
+                }
+                RecordType::TXT | RecordType::MX | RecordType::SRV | RecordType::CAA => {
+                    if !has_rdata(record_type, &txt, &mx, &srv, &caa) {
+                        return Json(DomainResponse::Failure(Some(format!("{record_type} Record requires at least one {record_type} value be provided"))));
+                    }
+
+                    FormDnsRecord {
+                        domain: domain.clone(),
+                        record_type,
+                        formnet_ip: vec![],
+                        public_ip: vec![],
+                        cname_target: None,
+                        ssl_cert,
+                        ttl: 3600,
+                        verification_status: Some(VerificationStatus::NotVerified),
+                        verification_timestamp: None,
+                        txt: vec![],
+                        mx: vec![],
+                        srv: vec![],
+                        caa: vec![],
+                        routing: RoutingPolicy::default(),
                     }
                 }
                 _ => return Json(DomainResponse::Failure(Some(format!("Sorry, the record type {record_type} is not currently supported"))))
             };
+            let record = FormDnsRecord { txt, mx, srv, caa, routing, ..record };
 
             log::info!("Build record: {record:?}...");
             let mut guard = state.write().await;
@@ -197,11 +338,16 @@ async fn update_record(
     log::info!("Received Update request for {domain}...");
     let mut guard = state.write().await;
     match request {
-        DomainRequest::Update { replace, record_type, ip_addr, cname_target, ssl_cert} => {
+        DomainRequest::Update { replace, record_type, ip_addr, cname_target, ssl_cert, txt, mx, srv, caa, routing } => {
+            if let Err(e) = validate_rdata(&txt, &mx, &srv, &caa) {
+                return Json(DomainResponse::Failure(Some(e)));
+            }
+            if let Some(Err(e)) = routing.as_ref().map(RoutingPolicy::validate) {
+                return Json(DomainResponse::Failure(Some(e)));
+            }
             let record = match record_type {
                 RecordType::A => {
-                    let record = if let Entry::Occupied(ref mut entry) = guard.entry(&domain) {
-                        let record = entry.get_mut();
+                    let record = if let Some(record) = guard.get_mut(&domain) {
                         record.record_type = record_type;
                         let (formnet_ips, public_ips) = if !ip_addr.is_empty() {
                             let mut formnet_ips = vec![]; 
@@ -240,8 +386,7 @@ async fn update_record(
                     record
                 }
                 RecordType::AAAA => {
-                    let record = if let Entry::Occupied(ref mut entry) = guard.entry(&domain) {
-                        let record = entry.get_mut();
+                    let record = if let Some(record) = guard.get_mut(&domain) {
                         record.record_type = record_type;
                         if !ip_addr.is_empty() {
                             record.public_ip.extend(ip_addr);
@@ -256,8 +401,7 @@ async fn update_record(
                     record
                 }
                 RecordType::CNAME => {
-                    let record = if let Entry::Occupied(ref mut entry) = guard.entry(&domain) {
-                        let record = entry.get_mut();
+                    let record = if let Some(record) = guard.get_mut(&domain) {
                         record.record_type = record_type;
                         if let Some(ref _target) = cname_target {
                             record.cname_target = cname_target.clone();
@@ -271,9 +415,56 @@ async fn update_record(
                     };
                     record
                 }
+                RecordType::ANAME => {
+                    let target = match alias_target(&domain, cname_target) {
+                        Ok(target) => target,
+                        Err(e) => return Json(DomainResponse::Failure(Some(e))),
+                    };
+                    let record = if let Some(record) = guard.get_mut(&domain) {
+                        record.record_type = record_type;
+                        record.cname_target = Some(target);
+                        // The addresses of an ANAME record are its target's
+                        record.formnet_ip.clear();
+                        record.public_ip.clear();
+                        record.ssl_cert = ssl_cert;
+                        record.clone()
+                    } else {
+                        return Json(DomainResponse::Failure(Some("ANAME record updates can only occur if the record exists, use /record/create endpoint instead".to_string())))
+                    };
+                    record
+                }
+                // TXT, MX, SRV and CAA values are served alongside the
+                // addresses or CNAME target of the domain, so the record
+                // keeps its type
+                RecordType::TXT | RecordType::MX | RecordType::SRV | RecordType::CAA => {
+                    if !replace && !has_rdata(record_type, &txt, &mx, &srv, &caa) {
+                        return Json(DomainResponse::Failure(Some(format!("{record_type} Record update must include a {record_type} value"))))
+                    }
+                    if let Some(mut record) = guard.get(&domain) {
+                        record.ssl_cert = ssl_cert;
+                        record
+                    } else {
+                        return Json(DomainResponse::Failure(Some(format!("{record_type} record updates can only occur if the record exists, use /record/create endpoint instead"))))
+                    }
+                }
                 _ => return Json(DomainResponse::Failure(Some(format!("Sorry, the record type {record_type} is not currently supported"))))
 
             };
+            let mut record = record;
+            if replace {
+                record.txt = txt;
+                record.mx = mx;
+                record.srv = srv;
+                record.caa = caa;
+            } else {
+                extend_unique(&mut record.txt, txt);
+                extend_unique(&mut record.mx, mx);
+                extend_unique(&mut record.srv, srv);
+                extend_unique(&mut record.caa, caa);
+            }
+            if let Some(routing) = routing {
+                record.routing = routing;
+            }
             log::info!("Successfully built record {record:?}");
             guard.insert(&domain, record).await;
             drop(guard);
@@ -432,6 +623,11 @@ async fn add_bootstrap_node(
             ttl: request.ttl.unwrap_or(60), // Low TTL for bootstrap domain
             verification_status: Some(VerificationStatus::Verified),
             verification_timestamp: None,
+            txt: vec![],
+            mx: vec![],
+            srv: vec![],
+            caa: vec![],
+            routing: RoutingPolicy::default(),
         };
         
         guard.insert(domain, record).await;
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::net::{IpAddr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use trust_dns_client::client::AsyncClient;
 use trust_dns_proto::rr::rdata::CNAME;
@@ -9,25 +10,43 @@ use trust_dns_proto::op::ResponseCode;
 use trust_dns_proto::rr::{
     RecordType, RData, Record, RecordSet, LowerName, Name
 };
+use trust_dns_proto::rr::dnssec::SupportedAlgorithms;
 use trust_dns_server::authority::LookupObject;
+use crate::dnssec::{SharedDnssecStore, ZoneSigner};
 use crate::store::{FormDnsRecord, SharedStore, VerificationStatus};
 use anyhow::Result;
 use trust_dns_client::client::ClientHandle;
 use crate::health::SharedIpHealthRepository;
+use crate::latency::SharedLatencyRepository;
+use crate::routing::{RoutingContext, RoutingPolicy};
 
 #[derive(Clone)]
 pub struct SimpleLookup {
     records: RecordSet,
     additionals: Option<RecordSet>,
+    /// Record sets served after `records`, such as the SOA that goes with a
+    /// DNSSEC denial
+    extra: Vec<RecordSet>,
+    /// Whether the RRSIGs of the record sets are served
+    dnssec: bool,
 }
 
 impl SimpleLookup {
     pub fn from_record_set(rrset: RecordSet) -> Self {
-        Self { records: rrset, additionals: None }
+        Self { records: rrset, additionals: None, extra: vec![], dnssec: false }
     }
 
     pub fn with_additionals(rrset: RecordSet, additionals: RecordSet) -> Self {
-        Self { records: rrset, additionals: Some(additionals) }
+        Self { records: rrset, additionals: Some(additionals), extra: vec![], dnssec: false }
+    }
+
+    /// Record sets of a signed zone, served with their RRSIGs if `dnssec`
+    pub fn signed(rrset: RecordSet, extra: Vec<RecordSet>, dnssec: bool) -> Self {
+        Self { records: rrset, additionals: None, extra, dnssec }
+    }
+
+    pub fn empty() -> Self {
+        Self::from_record_set(RecordSet::new(&Name::root(), RecordType::SOA, 0))
     }
 }
 
@@ -37,6 +56,10 @@ pub struct FormAuthority {
     store: SharedStore,
     fallback_client: AsyncClient,
     health_repository: Option<SharedIpHealthRepository>,
+    dnssec: Option<SharedDnssecStore>,
+    latency_repository: Option<SharedLatencyRepository>,
+    /// Answers given per name, for the turns of weighted records
+    turns: std::sync::Mutex<HashMap<String, u64>>,
 }
 
 impl FormAuthority {
@@ -48,6 +71,9 @@ impl FormAuthority {
             store,
             fallback_client,
             health_repository: None,
+            dnssec: None,
+            latency_repository: None,
+            turns: std::sync::Mutex::new(HashMap::new()),
         }
     }
 
@@ -57,6 +83,98 @@ impl FormAuthority {
         self
     }
 
+    /// Configure the authority with measured round-trip times for records
+    /// routed by latency
+    pub fn with_latency_repository(mut self, repository: SharedLatencyRepository) -> Self {
+        self.latency_repository = Some(repository);
+        self
+    }
+
+    /// Configure the authority to sign the zones of a DNSSEC store
+    pub fn with_dnssec(mut self, store: SharedDnssecStore) -> Self {
+        self.dnssec = Some(store);
+        self
+    }
+
+    /// Answer a query for a name of a signed zone, or `None` if the name is
+    /// in no signed zone. Formation is authoritative for signed zones, so
+    /// names that are not found are denied rather than looked up upstream.
+    async fn lookup_signed(
+        &self,
+        name: &LowerName,
+        rtype: RecordType,
+        src: Option<IpAddr>,
+        lookup_options: LookupOptions,
+    ) -> Option<Result<SimpleLookup, LookupError>> {
+        let dnssec = self.dnssec.as_ref()?;
+        let guard = dnssec.read().await;
+        let zone = guard.zone_for(&name.to_string())?;
+        let qname = Name::from(name.clone());
+
+        let mut rrset = if zone.is_apex(&qname) && zone.apex_types().contains(&rtype) {
+            zone.apex(rtype)
+        } else {
+            self.lookup_local(&name.to_string(), rtype, src).await
+        };
+        let types = self.types_at(zone, &qname).await;
+        // A CNAME stands in for every other type of its name
+        if rrset.is_none() && rtype != RecordType::CNAME && types.contains(&RecordType::CNAME) {
+            rrset = self.lookup_local(&name.to_string(), RecordType::CNAME, src).await;
+        }
+
+        let Some(mut rrset) = rrset else {
+            // Without DNSSEC a missing name is NXDOMAIN. With it, every name
+            // is denied as a name without the type, the NSEC or NSEC3 record
+            // at the name proving which types it has.
+            return Some(if types.is_empty() && !lookup_options.is_dnssec() {
+                Err(LookupError::ResponseCode(ResponseCode::NXDomain))
+            } else {
+                Err(LookupError::NameExists)
+            });
+        };
+
+        if lookup_options.is_dnssec() {
+            if let Err(e) = zone.sign(&mut rrset, crate::dnssec::now()) {
+                log::error!("Failed to sign {rtype} records of {name}: {e}");
+                return Some(Err(LookupError::ResponseCode(ResponseCode::ServFail)));
+            }
+        }
+        Some(Ok(SimpleLookup::signed(rrset, vec![], lookup_options.is_dnssec())))
+    }
+
+    /// Types of the records served for a name of a signed zone
+    async fn types_at(&self, zone: &ZoneSigner, name: &Name) -> Vec<RecordType> {
+        let key = name.to_string().trim_end_matches('.').to_lowercase();
+        let mut types = self.store.read().await
+            .resolve(&key)
+            .map(|record| record.record_types())
+            .unwrap_or_default();
+        if zone.is_apex(name) {
+            types.extend(zone.apex_types());
+        }
+        types
+    }
+
+    /// The signed denial of existence for a name of a signed zone, with the
+    /// zone's signed SOA
+    async fn signed_denial(&self, name: &LowerName) -> Result<SimpleLookup, LookupError> {
+        let dnssec = self.dnssec.as_ref()
+            .ok_or(LookupError::ResponseCode(ResponseCode::NXDomain))?;
+        let guard = dnssec.read().await;
+        let zone = guard.zone_for(&name.to_string())
+            .ok_or(LookupError::ResponseCode(ResponseCode::NXDomain))?;
+        let qname = Name::from(name.clone());
+        let types = self.types_at(zone, &qname).await;
+
+        match zone.deny(&qname, &types, crate::dnssec::now()) {
+            Ok((denial, soa)) => Ok(SimpleLookup::signed(denial, vec![soa], true)),
+            Err(e) => {
+                log::error!("Failed to deny {name}: {e}");
+                Err(LookupError::ResponseCode(ResponseCode::ServFail))
+            }
+        }
+    }
+
     async fn lookup_local(
         &self,
         name: &str,
@@ -69,11 +187,20 @@ impl FormAuthority {
 
         let record_opt = {
             let guard = self.store.read().await;
-            guard.get(&key)
+            guard.resolve(&key)
         };
         log::info!("retrieved record {record_opt:?}");
 
         if let Some(record) = record_opt {
+            if matches!(rtype, RecordType::TXT | RecordType::MX | RecordType::SRV | RecordType::CAA) {
+                let rr_name = Name::from_utf8(&key).ok()?;
+                let mut rrset = RecordSet::new(&rr_name, rtype, record.ttl);
+                for rdata in record.rdata(rtype) {
+                    rrset.add_rdata(rdata);
+                }
+                return if rrset.is_empty() { None } else { Some(rrset) };
+            }
+
             let is_formnet = {
                 match src {
                     Some(IpAddr::V4(addr)) => addr.octets()[0] == 10,
@@ -188,6 +315,12 @@ impl FormAuthority {
                 }
             }
 
+            if !ips.is_empty() && !record.routing.is_default() {
+                let ctx = self.routing_context(&key, &record.routing, &ips, src).await;
+                ips = record.routing.select(ips, &ctx);
+                log::info!("IPs picked by {} routing: {ips:?}", record.routing);
+            }
+
             log::info!("Final IPS: {ips:?}");
 
             // Calculate TTL based on health status
@@ -223,7 +356,9 @@ impl FormAuthority {
                             }
                         }
                     }
-                    RecordType::CNAME => {
+                    // An ANAME target is flattened into the addresses above,
+                    // it is never served as a CNAME
+                    RecordType::CNAME if record.record_type != RecordType::ANAME => {
                         log::info!("Request is for CNAME record");
                         if let Ok(name) = Name::from_utf8(record.cname_target?) {
                             let rdata = RData::CNAME(CNAME(name));
@@ -243,6 +378,42 @@ impl FormAuthority {
         None
     }
 
+    /// What a routing policy needs to know about a query, looked up only
+    /// for the policy that uses it
+    async fn routing_context(
+        &self,
+        key: &str,
+        policy: &RoutingPolicy,
+        ips: &[SocketAddr],
+        src: Option<IpAddr>,
+    ) -> RoutingContext {
+        let mut ctx = RoutingContext::default();
+        match policy {
+            RoutingPolicy::Weighted(_) => {
+                if let Ok(mut turns) = self.turns.lock() {
+                    let turn = turns.entry(key.to_string()).or_insert(0);
+                    ctx.turn = *turn;
+                    *turn = turn.wrapping_add(1);
+                }
+            }
+            RoutingPolicy::Latency => {
+                if let Some(latency_repo) = &self.latency_repository {
+                    ctx.rtts = latency_repo.read().await.rtts(ips.iter().map(|addr| addr.ip()));
+                }
+            }
+            RoutingPolicy::Region(_) => {
+                if let Some(location) = src.and_then(crate::geo_util::get_client_location) {
+                    ctx.client_regions = RoutingContext::regions(
+                        location.country_code.as_deref(),
+                        location.region_code.as_deref(),
+                    );
+                }
+            }
+            RoutingPolicy::Proximity | RoutingPolicy::Failover { .. } => {}
+        }
+        ctx
+    }
+
     async fn lookup_upstream(
         &self,
         name: &LowerName,
@@ -336,6 +507,11 @@ impl FormAuthority {
                                 ttl: 3600,
                                 verification_status: Some(VerificationStatus::NotVerified),
                                 verification_timestamp: None,
+                                txt: vec![],
+                                mx: vec![],
+                                srv: vec![],
+                                caa: vec![],
+                                routing: RoutingPolicy::default(),
                             };
                             store_guard.insert(&domain, record).await;
                             changed = true;
@@ -380,6 +556,11 @@ impl FormAuthority {
                                 ttl: 3600,
                                 verification_status: Some(VerificationStatus::NotVerified),
                                 verification_timestamp: None,
+                                txt: vec![],
+                                mx: vec![],
+                                srv: vec![],
+                                caa: vec![],
+                                routing: RoutingPolicy::default(),
                             };
                             store_guard.insert(&domain, record).await;
                             changed = true;
@@ -401,6 +582,11 @@ impl FormAuthority {
                                 ttl: 3600,
                                 verification_status: Some(VerificationStatus::NotVerified),
                                 verification_timestamp: None,
+                                txt: vec![],
+                                mx: vec![],
+                                srv: vec![],
+                                caa: vec![],
+                                routing: RoutingPolicy::default(),
                             };
                             store_guard.insert(&domain, record).await;
                             changed = true;
@@ -430,6 +616,11 @@ impl FormAuthority {
                                 ttl: 3600,
                                 verification_status: Some(VerificationStatus::NotVerified),
                                 verification_timestamp: None,
+                                txt: vec![],
+                                mx: vec![],
+                                srv: vec![],
+                                caa: vec![],
+                                routing: RoutingPolicy::default(),
                             };
                             store_guard.insert(&domain, record).await;
                             changed = true;
@@ -451,6 +642,11 @@ impl FormAuthority {
                                 ttl: 3600,
                                 verification_status: Some(VerificationStatus::NotVerified),
                                 verification_timestamp: None,
+                                txt: vec![],
+                                mx: vec![],
+                                srv: vec![],
+                                caa: vec![],
+                                routing: RoutingPolicy::default(),
                             };
                             store_guard.insert(&domain, record).await;
                         }
@@ -475,6 +671,11 @@ impl FormAuthority {
                                 ttl: 3600,
                                 verification_status: Some(VerificationStatus::NotVerified),
                                 verification_timestamp: None,
+                                txt: vec![],
+                                mx: vec![],
+                                srv: vec![],
+                                caa: vec![],
+                                routing: RoutingPolicy::default(),
                             };
                             store_guard.insert(&domain, record).await;
                             changed = true;
@@ -492,12 +693,15 @@ impl FormAuthority {
 
 impl LookupObject for SimpleLookup {
     fn is_empty(&self) -> bool {
-        self.records.is_empty()
+        self.records.is_empty() && self.extra.iter().all(RecordSet::is_empty)
     }
 
     fn iter(&self) -> Box<dyn Iterator<Item = &'_ Record> + Send + '_> {
+        let and_rrsigs = self.dnssec;
         Box::new(
-            self.records.records_without_rrsigs()
+            std::iter::once(&self.records)
+                .chain(self.extra.iter())
+                .flat_map(move |rrset| rrset.records(and_rrsigs, SupportedAlgorithms::all()))
         )
     }
 
@@ -506,6 +710,8 @@ impl LookupObject for SimpleLookup {
             return Some(Box::new(SimpleLookup {
                 records: adds,
                 additionals: None,
+                extra: vec![],
+                dnssec: self.dnssec,
             }))
         }
         None
@@ -537,8 +743,12 @@ impl Authority for FormAuthority {
         &self.origin
     }
 
-    fn lookup<'life0,'life1,'async_trait>(&'life0 self,name: &'life1 LowerName,rtype:RecordType,_lookup_options:LookupOptions,) ->  ::core::pin::Pin<Box<dyn ::core::future::Future<Output = std::result::Result<Self::Lookup,LookupError> > + ::core::marker::Send+'async_trait> >where 'life0:'async_trait,'life1:'async_trait,Self:'async_trait {
+    fn lookup<'life0,'life1,'async_trait>(&'life0 self,name: &'life1 LowerName,rtype:RecordType,lookup_options:LookupOptions,) ->  ::core::pin::Pin<Box<dyn ::core::future::Future<Output = std::result::Result<Self::Lookup,LookupError> > + ::core::marker::Send+'async_trait> >where 'life0:'async_trait,'life1:'async_trait,Self:'async_trait {
         Box::pin(async move {
+            if let Some(result) = self.lookup_signed(name, rtype, None, lookup_options).await {
+                return result;
+            }
+
             let name_str = name.to_string();
             if let Some(rrset) = self.lookup_local(&name_str, rtype, None).await {
                 return Ok(SimpleLookup::from_record_set(rrset));
@@ -551,11 +761,14 @@ impl Authority for FormAuthority {
         })
     }
 
-    fn search<'life0,'life1,'async_trait>(&'life0 self,request:trust_dns_server::server::RequestInfo<'life1> ,_lookup_options:LookupOptions,) ->  ::core::pin::Pin<Box<dyn ::core::future::Future<Output = std::result::Result<Self::Lookup,LookupError> > + ::core::marker::Send+'async_trait> >where 'life0:'async_trait,'life1:'async_trait,Self:'async_trait {
+    fn search<'life0,'life1,'async_trait>(&'life0 self,request:trust_dns_server::server::RequestInfo<'life1> ,lookup_options:LookupOptions,) ->  ::core::pin::Pin<Box<dyn ::core::future::Future<Output = std::result::Result<Self::Lookup,LookupError> > + ::core::marker::Send+'async_trait> >where 'life0:'async_trait,'life1:'async_trait,Self:'async_trait {
         Box::pin(async move {
             let src = request.src;
             let rtype = request.query.query_type();
             let name = request.query.name();
+            if let Some(result) = self.lookup_signed(name, rtype, Some(src.ip()), lookup_options).await {
+                return result;
+            }
             if let Some(rrset) = self.lookup_local(&name.to_string(), rtype, Some(src.ip())).await {
                 log::info!("Found record in local, returning...");
                 return Ok(SimpleLookup::from_record_set(rrset));
@@ -569,9 +782,21 @@ impl Authority for FormAuthority {
         })
     }
 
-    fn get_nsec_records<'life0,'life1,'async_trait>(&'life0 self,_name: &'life1 LowerName,_lookup_options:LookupOptions,) ->  ::core::pin::Pin<Box<dyn ::core::future::Future<Output = std::result::Result<Self::Lookup,LookupError> > + ::core::marker::Send+'async_trait> >where 'life0:'async_trait,'life1:'async_trait,Self:'async_trait {
+    fn get_nsec_records<'life0,'life1,'async_trait>(&'life0 self,name: &'life1 LowerName,_lookup_options:LookupOptions,) ->  ::core::pin::Pin<Box<dyn ::core::future::Future<Output = std::result::Result<Self::Lookup,LookupError> > + ::core::marker::Send+'async_trait> >where 'life0:'async_trait,'life1:'async_trait,Self:'async_trait {
         Box::pin(async move {
-            Err(LookupError::ResponseCode(ResponseCode::NXDomain))
+            self.signed_denial(name).await
+        })
+    }
+
+    fn soa_secure<'life0,'async_trait>(&'life0 self,lookup_options:LookupOptions,) ->  ::core::pin::Pin<Box<dyn ::core::future::Future<Output = std::result::Result<Self::Lookup,LookupError> > + ::core::marker::Send+'async_trait> >where 'life0:'async_trait,Self:'async_trait {
+        Box::pin(async move {
+            // The origin is the root, whose SOA says nothing about the zone
+            // of the query. Signed zones serve their own SOA together with
+            // the denial of existence.
+            if lookup_options.is_dnssec() {
+                return Ok(SimpleLookup::empty());
+            }
+            self.lookup(self.origin(), RecordType::SOA, lookup_options).await
         })
     }
 }
@@ -0,0 +1,966 @@
+//! DNSSEC signing of Formation-authoritative zones.
+//!
+//! A signed zone holds a key signing key (KSK) and a zone signing key (ZSK),
+//! stored with the zone in form-state so every node signs with the same keys.
+//! Answers are signed online as they are served, and names or types that do
+//! not exist are denied with a single NSEC or NSEC3 record at the query name
+//! ("compact denial of existence"), so no zone walk is needed to build them.
+//!
+//! Keys roll over on their own. A new ZSK is published in the DNSKEY set
+//! before it signs anything, and the old one stays published until the
+//! signatures it made have expired from caches. A new KSK is published and
+//! signs the DNSKEY set next to the old one; it only takes over once the
+//! parent zone serves a DS record for it, which the registrar has to be given
+//! by hand from the DS export.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use base64::Engine;
+use log::{error, info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use trust_dns_client::client::{AsyncClient, ClientHandle};
+use trust_dns_proto::rr::dnssec::rdata::{DNSSECRData, DNSKEY, DS, NSEC, NSEC3, NSEC3PARAM, SIG};
+use trust_dns_proto::rr::dnssec::{tbs, Algorithm, DigestType, KeyFormat, KeyPair, Nsec3HashAlgorithm, Private};
+use trust_dns_proto::rr::rdata::{NS, SOA};
+use trust_dns_proto::rr::{DNSClass, Name, RData, Record, RecordSet, RecordType};
+use form_types::state::{Response, Success};
+
+/// Algorithm of every zone key
+pub const ALGORITHM: Algorithm = Algorithm::ECDSAP256SHA256;
+/// TTL of the DNSKEY, SOA, NS and NSEC3PARAM sets of a signed zone
+pub const DNSKEY_TTL: u32 = 3600;
+/// TTL of denial of existence records, also the SOA minimum
+pub const NEGATIVE_TTL: u32 = 300;
+/// How long a signature stays valid after it is made
+pub const SIGNATURE_VALIDITY: u64 = 7 * 24 * 3600;
+/// How far signatures are backdated to allow for clock skew of resolvers
+pub const CLOCK_SKEW: u64 = 3600;
+/// How long a ZSK signs before it is replaced
+pub const ZSK_LIFETIME: u64 = 30 * 24 * 3600;
+/// How long a KSK signs before it is replaced
+pub const KSK_LIFETIME: u64 = 365 * 24 * 3600;
+/// How long a change to the DNSKEY set takes to reach every resolver: longer
+/// than any TTL a signed zone serves, plus the time form-state takes to
+/// replicate the keys to every node
+pub const PROPAGATION: u64 = 24 * 3600;
+/// Default interval of the key manager
+pub const DEFAULT_ROLLOVER_INTERVAL: Duration = Duration::from_secs(600);
+/// How long since its last heartbeat a node can still be elected to roll
+/// keys
+pub const ROLLER_HEARTBEAT_TIMEOUT: u64 = 300;
+
+/// What a zone key signs
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum KeyRole {
+    /// Signs the DNSKEY set; its DS record is published by the parent zone
+    Ksk,
+    /// Signs every other record set of the zone
+    Zsk,
+}
+
+/// Where a zone key is in its rollover
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum KeyState {
+    /// In the DNSKEY set, waiting to take over (a ZSK) or for the parent's
+    /// DS record (a KSK)
+    Published,
+    /// In the DNSKEY set and signing
+    Active,
+    /// Replaced, but kept in the DNSKEY set until cached signatures expire
+    Retired,
+}
+
+/// A key of a signed zone
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ZoneKey {
+    pub role: KeyRole,
+    pub state: KeyState,
+    /// Hex encoded PKCS#8 document of the private key
+    pub pkcs8: String,
+    /// Unix time the key was generated
+    pub created: u64,
+    /// Unix time the key entered its current state
+    pub changed: u64,
+}
+
+impl ZoneKey {
+    /// Generate a new key
+    pub fn generate(role: KeyRole, state: KeyState, now: u64) -> Result<Self, String> {
+        let pkcs8 = KeyPair::<Private>::generate_pkcs8(ALGORITHM)
+            .map_err(|e| format!("Failed to generate {role:?}: {e}"))?;
+        Ok(Self {
+            role,
+            state,
+            pkcs8: hex::encode(pkcs8),
+            created: now,
+            changed: now,
+        })
+    }
+
+    /// The private key
+    pub fn key_pair(&self) -> Result<KeyPair<Private>, String> {
+        let pkcs8 = hex::decode(&self.pkcs8).map_err(|e| format!("Invalid key encoding: {e}"))?;
+        KeyFormat::Pkcs8.decode_key(&pkcs8, None, ALGORITHM)
+            .map_err(|e| format!("Invalid key: {e}"))
+    }
+
+    /// The key as published in the DNSKEY set; KSKs carry the secure entry
+    /// point flag
+    pub fn dnskey(&self) -> Result<DNSKEY, String> {
+        let public_key = self.key_pair()?.to_public_bytes()
+            .map_err(|e| format!("Invalid key: {e}"))?;
+        Ok(DNSKEY::new(true, self.role == KeyRole::Ksk, false, ALGORITHM, public_key))
+    }
+
+    fn enter(&mut self, state: KeyState, now: u64) {
+        self.state = state;
+        self.changed = now;
+    }
+
+    fn since(&self, now: u64) -> u64 {
+        now.saturating_sub(self.changed)
+    }
+}
+
+/// How a signed zone denies names and types that do not exist
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum Denial {
+    #[default]
+    Nsec,
+    /// Hashed denial. Iterations and salt default to none, as RFC 9276
+    /// recommends.
+    Nsec3 {
+        #[serde(default)]
+        iterations: u16,
+        /// Hex encoded salt
+        #[serde(default)]
+        salt: String,
+    },
+}
+
+/// DNSSEC configuration and keys of a zone Formation is authoritative for
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DnssecZone {
+    /// Apex of the zone, without the trailing dot
+    pub zone: String,
+    pub keys: Vec<ZoneKey>,
+    #[serde(default)]
+    pub denial: Denial,
+    /// Name servers published in the zone's NS set
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+    /// Unix time of the last change, used as the SOA serial
+    pub updated: u64,
+}
+
+impl DnssecZone {
+    /// Sign a zone with a new KSK and ZSK
+    pub fn new(zone: &str, denial: Denial, nameservers: Vec<String>, now: u64) -> Result<Self, String> {
+        let zone = zone.trim_end_matches('.').to_lowercase();
+        Name::from_utf8(&zone).map_err(|e| format!("Invalid zone {zone}: {e}"))?;
+        if let Denial::Nsec3 { salt, .. } = &denial {
+            hex::decode(salt).map_err(|e| format!("Invalid NSEC3 salt: {e}"))?;
+        }
+        Ok(Self {
+            zone,
+            keys: vec![
+                ZoneKey::generate(KeyRole::Ksk, KeyState::Active, now)?,
+                ZoneKey::generate(KeyRole::Zsk, KeyState::Active, now)?,
+            ],
+            denial,
+            nameservers,
+            updated: now,
+        })
+    }
+
+    /// Apex of the zone as a fully qualified name
+    pub fn name(&self) -> Result<Name, String> {
+        let mut name = Name::from_utf8(&self.zone).map_err(|e| format!("Invalid zone {}: {e}", self.zone))?;
+        name.set_fqdn(true);
+        Ok(name)
+    }
+
+    /// Whether a domain is the apex of the zone or a name below it
+    pub fn contains(&self, domain: &str) -> bool {
+        let domain = domain.trim_end_matches('.').to_lowercase();
+        domain == self.zone || domain.ends_with(&format!(".{}", self.zone))
+    }
+
+    /// DS records of the zone's KSKs, for the registrar to publish in the
+    /// parent zone. Published KSKs are included so the parent can be updated
+    /// ahead of a KSK rollover.
+    pub fn ds_records(&self) -> Result<Vec<DS>, String> {
+        let name = self.name()?;
+        self.keys.iter()
+            .filter(|key| key.role == KeyRole::Ksk && key.state != KeyState::Retired)
+            .map(|key| {
+                let dnskey = key.dnskey()?;
+                let key_tag = dnskey.calculate_key_tag().map_err(|e| e.to_string())?;
+                let digest = dnskey.to_digest(&name, DigestType::SHA256).map_err(|e| e.to_string())?;
+                Ok(DS::new(key_tag, ALGORITHM, DigestType::SHA256, digest.as_ref().to_vec()))
+            })
+            .collect()
+    }
+
+    /// DS and DNSKEY records to hand to the registrar
+    pub fn export(&self) -> Result<DsExport, String> {
+        let ds = self.ds_records()?.iter()
+            .map(|ds| ds_presentation(&self.zone, ds))
+            .collect();
+        let dnskey = self.keys.iter()
+            .filter(|key| key.role == KeyRole::Ksk && key.state != KeyState::Retired)
+            .map(|key| dnskey_presentation(&self.zone, key))
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(DsExport {
+            zone: self.zone.clone(),
+            ds,
+            dnskey,
+            awaiting_ds: self.awaiting_ds(),
+        })
+    }
+
+    /// Whether the zone is waiting on its parent's DS records to finish a
+    /// KSK rollover
+    pub fn awaiting_ds(&self) -> bool {
+        self.keys.iter().any(|key| key.role == KeyRole::Ksk && key.state == KeyState::Published)
+    }
+
+    /// Move the zone's keys through their rollover. `parent_ds` are the DS
+    /// records the parent zone serves for the zone, or `None` if they could
+    /// not be looked up. Returns whether anything changed.
+    pub fn roll(&mut self, now: u64, parent_ds: Option<&[DS]>) -> Result<bool, String> {
+        let before = self.keys.clone();
+        self.roll_zsk(now)?;
+        self.roll_ksk(now, parent_ds)?;
+
+        let changed = self.keys != before;
+        if changed {
+            self.updated = now;
+        }
+        Ok(changed)
+    }
+
+    fn roll_zsk(&mut self, now: u64) -> Result<(), String> {
+        self.keys.retain(|key| !(key.role == KeyRole::Zsk && key.state == KeyState::Retired && key.since(now) >= PROPAGATION));
+
+        let published = self.keys.iter().position(|key| key.role == KeyRole::Zsk && key.state == KeyState::Published);
+        let active = self.keys.iter().position(|key| key.role == KeyRole::Zsk && key.state == KeyState::Active);
+        match (active, published) {
+            (None, None) => self.keys.push(ZoneKey::generate(KeyRole::Zsk, KeyState::Active, now)?),
+            (Some(active), None) if self.keys[active].since(now) >= ZSK_LIFETIME => {
+                self.keys.push(ZoneKey::generate(KeyRole::Zsk, KeyState::Published, now)?);
+            }
+            // Resolvers have the new key cached by now, it can take over
+            (active, Some(published)) if self.keys[published].since(now) >= PROPAGATION => {
+                if let Some(active) = active {
+                    self.keys[active].enter(KeyState::Retired, now);
+                }
+                self.keys[published].enter(KeyState::Active, now);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn roll_ksk(&mut self, now: u64, parent_ds: Option<&[DS]>) -> Result<(), String> {
+        self.keys.retain(|key| !(key.role == KeyRole::Ksk && key.state == KeyState::Retired && key.since(now) >= PROPAGATION));
+
+        let published = self.keys.iter().position(|key| key.role == KeyRole::Ksk && key.state == KeyState::Published);
+        let active = self.keys.iter().position(|key| key.role == KeyRole::Ksk && key.state == KeyState::Active);
+        match (active, published) {
+            (None, None) => self.keys.push(ZoneKey::generate(KeyRole::Ksk, KeyState::Active, now)?),
+            (Some(active), None) if self.keys[active].since(now) >= KSK_LIFETIME => {
+                self.keys.push(ZoneKey::generate(KeyRole::Ksk, KeyState::Published, now)?);
+            }
+            (active, Some(published)) if self.keys[published].since(now) >= PROPAGATION => {
+                // The new KSK can only take over once the parent vouches for
+                // it, unless the parent vouches for no key of the zone at all
+                let name = self.name()?;
+                let dnskey = self.keys[published].dnskey()?;
+                let ready = match parent_ds {
+                    Some([]) => true,
+                    Some(parent_ds) => parent_ds.iter().any(|ds| ds.covers(&name, &dnskey).unwrap_or(false)),
+                    None => false,
+                };
+                if ready {
+                    if let Some(active) = active {
+                        self.keys[active].enter(KeyState::Retired, now);
+                    }
+                    self.keys[published].enter(KeyState::Active, now);
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// What a registrar needs to secure the delegation of a zone
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct DsExport {
+    pub zone: String,
+    /// DS records in zone file format
+    pub ds: Vec<String>,
+    /// DNSKEY records of the KSKs, for registrars that take keys instead
+    pub dnskey: Vec<String>,
+    /// Whether a KSK rollover is waiting for the parent zone to serve the
+    /// new DS record
+    pub awaiting_ds: bool,
+}
+
+/// DS record in zone file format
+pub fn ds_presentation(zone: &str, ds: &DS) -> String {
+    format!(
+        "{}. {} IN DS {} {} {} {}",
+        zone.trim_end_matches('.'),
+        DNSKEY_TTL,
+        ds.key_tag(),
+        u8::from(ds.algorithm()),
+        u8::from(ds.digest_type()),
+        hex::encode_upper(ds.digest()),
+    )
+}
+
+/// DNSKEY record in zone file format
+pub fn dnskey_presentation(zone: &str, key: &ZoneKey) -> Result<String, String> {
+    let dnskey = key.dnskey()?;
+    let flags = if dnskey.secure_entry_point() { 257 } else { 256 };
+    Ok(format!(
+        "{}. {} IN DNSKEY {} 3 {} {}",
+        zone.trim_end_matches('.'),
+        DNSKEY_TTL,
+        flags,
+        u8::from(ALGORITHM),
+        base64::engine::general_purpose::STANDARD.encode(dnskey.public_key()),
+    ))
+}
+
+/// A zone key ready to sign
+struct Signer {
+    role: KeyRole,
+    state: KeyState,
+    dnskey: DNSKEY,
+    key_tag: u16,
+    key_pair: KeyPair<Private>,
+}
+
+/// A signed zone with its keys decoded
+pub struct ZoneSigner {
+    zone: DnssecZone,
+    name: Name,
+    signers: Vec<Signer>,
+}
+
+impl ZoneSigner {
+    pub fn new(zone: DnssecZone) -> Result<Self, String> {
+        let name = zone.name()?;
+        let signers = zone.keys.iter()
+            .map(|key| {
+                let dnskey = key.dnskey()?;
+                Ok(Signer {
+                    role: key.role,
+                    state: key.state,
+                    key_tag: dnskey.calculate_key_tag().map_err(|e| e.to_string())?,
+                    dnskey,
+                    key_pair: key.key_pair()?,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self { zone, name, signers })
+    }
+
+    pub fn zone(&self) -> &DnssecZone {
+        &self.zone
+    }
+
+    /// Apex of the zone
+    pub fn name(&self) -> &Name {
+        &self.name
+    }
+
+    /// Whether a name is the apex of the zone
+    pub fn is_apex(&self, name: &Name) -> bool {
+        &self.name == name
+    }
+
+    /// Types served at the apex on top of the records stored for it
+    pub fn apex_types(&self) -> Vec<RecordType> {
+        let mut types = vec![RecordType::SOA, RecordType::DNSKEY];
+        if !self.zone.nameservers.is_empty() {
+            types.push(RecordType::NS);
+        }
+        if matches!(self.zone.denial, Denial::Nsec3 { .. }) {
+            types.push(RecordType::NSEC3PARAM);
+        }
+        types
+    }
+
+    /// Record set of a type served at the apex, unsigned
+    pub fn apex(&self, rtype: RecordType) -> Option<RecordSet> {
+        let mut rrset = RecordSet::with_ttl(self.name.clone(), rtype, DNSKEY_TTL);
+        match rtype {
+            RecordType::SOA => {
+                let mname = self.zone.nameservers.first()
+                    .and_then(|ns| fqdn(ns).ok())
+                    .or_else(|| Name::from_str("ns1").ok()?.append_domain(&self.name).ok())?;
+                let rname = Name::from_str("hostmaster").ok()?.append_domain(&self.name).ok()?;
+                let soa = SOA::new(mname, rname, self.zone.updated as u32, 3600, 600, 1_209_600, NEGATIVE_TTL);
+                rrset.add_rdata(RData::SOA(soa));
+            }
+            RecordType::NS => {
+                for ns in &self.zone.nameservers {
+                    match fqdn(ns) {
+                        Ok(name) => { rrset.add_rdata(RData::NS(NS(name))); }
+                        Err(e) => warn!("Skipping invalid name server {ns} of {}: {e}", self.zone.zone),
+                    }
+                }
+            }
+            RecordType::DNSKEY => {
+                for signer in &self.signers {
+                    rrset.add_rdata(RData::DNSSEC(DNSSECRData::DNSKEY(signer.dnskey.clone())));
+                }
+            }
+            RecordType::NSEC3PARAM => {
+                let Denial::Nsec3 { iterations, salt } = &self.zone.denial else {
+                    return None;
+                };
+                let param = NSEC3PARAM::new(Nsec3HashAlgorithm::SHA1, false, *iterations, hex::decode(salt).ok()?);
+                rrset.add_rdata(RData::DNSSEC(DNSSECRData::NSEC3PARAM(param)));
+            }
+            _ => return None,
+        }
+
+        if rrset.is_empty() {
+            None
+        } else {
+            Some(rrset)
+        }
+    }
+
+    /// Record that proves `name` has none but `types`. For a name that does
+    /// not exist at all, `types` is empty.
+    pub fn denial(&self, name: &Name, types: &[RecordType]) -> Result<RecordSet, String> {
+        let name = name.to_lowercase();
+        match &self.zone.denial {
+            Denial::Nsec => {
+                // `\000.name` is the next name after `name` in canonical
+                // order, so the record covers nothing but the name itself
+                let next = name.prepend_label(&[0u8][..]).map_err(|e| e.to_string())?;
+                let mut bitmap = types.to_vec();
+                bitmap.extend([RecordType::RRSIG, RecordType::NSEC]);
+
+                let mut rrset = RecordSet::with_ttl(name.clone(), RecordType::NSEC, NEGATIVE_TTL);
+                rrset.add_rdata(RData::DNSSEC(DNSSECRData::NSEC(NSEC::new(next, bitmap))));
+                Ok(rrset)
+            }
+            Denial::Nsec3 { iterations, salt } => {
+                let salt = hex::decode(salt).map_err(|e| format!("Invalid NSEC3 salt: {e}"))?;
+                let hash = Nsec3HashAlgorithm::SHA1.hash(&salt, &name, *iterations)
+                    .map_err(|e| e.to_string())?;
+                let owner = Name::from_str(&base32hex(hash.as_ref()))
+                    .and_then(|label| label.append_domain(&self.name))
+                    .map_err(|e| e.to_string())?;
+                let mut bitmap = types.to_vec();
+                bitmap.push(RecordType::RRSIG);
+
+                let nsec3 = NSEC3::new(
+                    Nsec3HashAlgorithm::SHA1,
+                    false,
+                    *iterations,
+                    salt,
+                    successor(hash.as_ref()),
+                    bitmap,
+                );
+                let mut rrset = RecordSet::with_ttl(owner, RecordType::NSEC3, NEGATIVE_TTL);
+                rrset.add_rdata(RData::DNSSEC(DNSSECRData::NSEC3(nsec3)));
+                Ok(rrset)
+            }
+        }
+    }
+
+    /// Signed denial of existence for `name`, with the signed SOA that goes
+    /// with it
+    pub fn deny(&self, name: &Name, types: &[RecordType], now: u64) -> Result<(RecordSet, RecordSet), String> {
+        let mut denial = self.denial(name, types)?;
+        self.sign(&mut denial, now)?;
+        let mut soa = self.apex(RecordType::SOA)
+            .ok_or_else(|| format!("{} has no SOA", self.zone.zone))?;
+        self.sign(&mut soa, now)?;
+        Ok((denial, soa))
+    }
+
+    /// Sign a record set of the zone. The DNSKEY set is signed by every KSK,
+    /// so it validates against the parent's DS records before and after a
+    /// KSK rollover; everything else by the active ZSK.
+    pub fn sign(&self, rrset: &mut RecordSet, now: u64) -> Result<(), String> {
+        let inception = now.saturating_sub(CLOCK_SKEW) as u32;
+        let expiration = (now + SIGNATURE_VALIDITY) as u32;
+        let records: Vec<Record> = rrset.records_without_rrsigs().cloned().collect();
+        let num_labels = rrset.name().num_labels();
+
+        let signers = self.signers.iter().filter(|signer| match rrset.record_type() {
+            RecordType::DNSKEY => signer.role == KeyRole::Ksk,
+            _ => signer.role == KeyRole::Zsk && signer.state == KeyState::Active,
+        });
+        for signer in signers {
+            let tbs = tbs::rrset_tbs(
+                rrset.name(),
+                rrset.dns_class(),
+                num_labels,
+                rrset.record_type(),
+                ALGORITHM,
+                rrset.ttl(),
+                expiration,
+                inception,
+                signer.key_tag,
+                &self.name,
+                &records,
+            ).map_err(|e| e.to_string())?;
+            let signature = signer.key_pair.sign(ALGORITHM, &tbs).map_err(|e| e.to_string())?;
+
+            let mut rrsig = Record::with(rrset.name().clone(), RecordType::RRSIG, rrset.ttl());
+            rrsig.set_dns_class(DNSClass::IN);
+            rrsig.set_data(Some(RData::DNSSEC(DNSSECRData::SIG(SIG::new(
+                rrset.record_type(),
+                ALGORITHM,
+                num_labels,
+                rrset.ttl(),
+                expiration,
+                inception,
+                signer.key_tag,
+                self.name.clone(),
+                signature,
+            )))));
+            rrset.insert_rrsig(rrsig);
+        }
+        Ok(())
+    }
+}
+
+fn fqdn(name: &str) -> Result<Name, trust_dns_proto::error::ProtoError> {
+    Name::from_str(&format!("{}.", name.trim_end_matches('.')))
+}
+
+/// Unpadded, lower case base32 with the extended hex alphabet, as NSEC3
+/// owner names use
+pub fn base32hex(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"0123456789abcdefghijklmnopqrstuv";
+    let mut encoded = String::with_capacity((bytes.len() * 8 + 4) / 5);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | *byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        encoded.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    encoded
+}
+
+/// The hash right after `hash`, the next hashed owner name of a compact
+/// NSEC3 record
+fn successor(hash: &[u8]) -> Vec<u8> {
+    let mut next = hash.to_vec();
+    for byte in next.iter_mut().rev() {
+        let (value, overflow) = byte.overflowing_add(1);
+        *byte = value;
+        if !overflow {
+            break;
+        }
+    }
+    next
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Signed zones served by this node
+#[derive(Default)]
+pub struct DnssecStore {
+    zones: HashMap<String, ZoneSigner>,
+}
+
+pub type SharedDnssecStore = Arc<RwLock<DnssecStore>>;
+
+impl DnssecStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replace the signed zones, skipping any whose keys cannot be decoded
+    pub fn replace(&mut self, zones: Vec<DnssecZone>) {
+        self.zones = zones.into_iter()
+            .filter_map(|zone| {
+                let key = zone.zone.clone();
+                match ZoneSigner::new(zone) {
+                    Ok(signer) => Some((key, signer)),
+                    Err(e) => {
+                        error!("Not signing {key}: {e}");
+                        None
+                    }
+                }
+            })
+            .collect();
+    }
+
+    /// The most specific signed zone a domain belongs to
+    pub fn zone_for(&self, domain: &str) -> Option<&ZoneSigner> {
+        self.zones.values()
+            .filter(|signer| signer.zone.contains(domain))
+            .max_by_key(|signer| signer.zone.zone.len())
+    }
+
+    pub fn get(&self, zone: &str) -> Option<&ZoneSigner> {
+        self.zones.get(&zone.trim_end_matches('.').to_lowercase())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+}
+
+/// A node as listed by form-state, as far as electing the key roller goes
+#[derive(Clone, Debug, Deserialize)]
+struct RollerCandidate {
+    node_id: String,
+    last_heartbeat: i64,
+}
+
+/// Whether `node_id` is the node that rolls keys over: the node with the
+/// lowest id among itself and the nodes with a recent heartbeat. Rolling on
+/// a single node keeps nodes from generating conflicting keys for a zone.
+pub fn elects_roller<'a>(node_id: &str, nodes: impl IntoIterator<Item = (&'a str, i64)>, now: u64) -> bool {
+    let cutoff = now.saturating_sub(ROLLER_HEARTBEAT_TIMEOUT) as i64;
+    nodes.into_iter()
+        .filter(|(_, last_heartbeat)| *last_heartbeat >= cutoff)
+        .all(|(candidate, _)| candidate >= node_id)
+}
+
+/// Loads the signed zones from form-state and rolls their keys over,
+/// writing any change back to form-state so every node picks it up. Only
+/// the elected node rolls keys; the others serve the zones as stored.
+pub struct KeyManager {
+    form_state_api: String,
+    store: SharedDnssecStore,
+    client: AsyncClient,
+    http_client: Client,
+    interval: Duration,
+}
+
+impl KeyManager {
+    pub fn new(form_state_api: String, store: SharedDnssecStore, client: AsyncClient, interval: Duration) -> Self {
+        Self {
+            form_state_api,
+            store,
+            client,
+            http_client: Client::new(),
+            interval,
+        }
+    }
+
+    /// Run the key manager loop
+    pub async fn start(&self) {
+        info!("Starting DNSSEC key manager");
+        let mut interval = tokio::time::interval(self.interval);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.refresh().await {
+                error!("Error refreshing DNSSEC zones: {}", e);
+            }
+        }
+    }
+
+    /// Load the zones, roll their keys and serve the result
+    pub async fn refresh(&self) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let mut zones = self.fetch_zones().await?;
+        let now = now();
+        let roller = match self.is_roller(now).await {
+            Ok(roller) => roller,
+            Err(e) => {
+                warn!("Failed to elect the DNSSEC key roller, not rolling keys: {}", e);
+                false
+            }
+        };
+        if roller {
+            self.roll(&mut zones, now).await;
+        }
+
+        self.store.write().await.replace(zones);
+        Ok(())
+    }
+
+    /// Roll the keys of the zones, storing the zones that changed
+    async fn roll(&self, zones: &mut [DnssecZone], now: u64) {
+        for zone in zones.iter_mut() {
+            let parent_ds = if zone.awaiting_ds() {
+                self.parent_ds(zone).await
+            } else {
+                None
+            };
+            match zone.roll(now, parent_ds.as_deref()) {
+                Ok(true) => {
+                    info!("Rolled DNSSEC keys of {}", zone.zone);
+                    if let Err(e) = self.store_zone(zone).await {
+                        error!("Failed to store DNSSEC keys of {}: {}", zone.zone, e);
+                    }
+                }
+                Ok(false) => {}
+                Err(e) => error!("Failed to roll DNSSEC keys of {}: {}", zone.zone, e),
+            }
+        }
+    }
+
+    /// Whether this node is the one that rolls keys over
+    async fn is_roller(&self, now: u64) -> Result<bool, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/node/local", self.form_state_api);
+        let node_id = match self.http_client.get(&url).send().await?.json::<Response<String>>().await? {
+            Response::Success(Success::Some(node_id)) => node_id,
+            _ => return Err("Failed to get the id of this node".into()),
+        };
+
+        let url = format!("{}/node/list", self.form_state_api);
+        let nodes = match self.http_client.get(&url).send().await?.json::<Response<RollerCandidate>>().await? {
+            Response::Success(Success::List(nodes)) => nodes,
+            Response::Success(_) => vec![],
+            Response::Failure { reason } => return Err(reason.unwrap_or_else(|| "Failed to list nodes".to_string()).into()),
+        };
+        Ok(elects_roller(
+            &node_id,
+            nodes.iter().map(|node| (node.node_id.as_str(), node.last_heartbeat)),
+            now,
+        ))
+    }
+
+    async fn fetch_zones(&self) -> Result<Vec<DnssecZone>, Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/dnssec/list", self.form_state_api);
+        match self.http_client.get(&url).send().await?.json::<Response<DnssecZone>>().await? {
+            Response::Success(Success::List(zones)) => Ok(zones),
+            Response::Success(_) => Ok(vec![]),
+            Response::Failure { reason } => Err(reason.unwrap_or_else(|| "Failed to list DNSSEC zones".to_string()).into()),
+        }
+    }
+
+    async fn store_zone(&self, zone: &DnssecZone) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+        let url = format!("{}/dnssec/update", self.form_state_api);
+        let body = serde_json::json!({ "Update": zone });
+        match self.http_client.post(&url).json(&body).send().await?.json::<Response<DnssecZone>>().await? {
+            Response::Success(_) => Ok(()),
+            Response::Failure { reason } => Err(reason.unwrap_or_else(|| "update was rejected".to_string()).into()),
+        }
+    }
+
+    /// DS records the parent zone serves for a zone, `None` if the lookup
+    /// failed
+    async fn parent_ds(&self, zone: &DnssecZone) -> Option<Vec<DS>> {
+        let name = zone.name().ok()?;
+        let mut client = self.client.clone();
+        match client.query(name, DNSClass::IN, RecordType::DS).await {
+            Ok(response) => Some(
+                response.answers().iter()
+                    .filter_map(|record| match record.data() {
+                        Some(RData::DNSSEC(DNSSECRData::DS(ds))) => Some(ds.clone()),
+                        _ => None,
+                    })
+                    .collect()
+            ),
+            Err(e) => {
+                warn!("Failed to look up the DS records of {}: {}", zone.zone, e);
+                None
+            }
+        }
+    }
+}
+
+/// Start the DNSSEC key manager, returning the signed zones it keeps up to
+/// date
+pub async fn start_key_manager(
+    form_state_api: String,
+    client: AsyncClient,
+    interval: Option<Duration>,
+) -> SharedDnssecStore {
+    info!("Starting DNSSEC key manager with form-state API: {}", form_state_api);
+    let store: SharedDnssecStore = Arc::new(RwLock::new(DnssecStore::new()));
+    let manager = KeyManager::new(
+        form_state_api,
+        store.clone(),
+        client,
+        interval.unwrap_or(DEFAULT_ROLLOVER_INTERVAL),
+    );
+
+    tokio::spawn(async move {
+        manager.start().await;
+    });
+
+    store
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn zone(denial: Denial) -> DnssecZone {
+        DnssecZone::new("Example.com.", denial, vec!["ns1.formation.cloud".to_string()], 1_000).unwrap()
+    }
+
+    fn key(zone: &DnssecZone, role: KeyRole, state: KeyState) -> Option<&ZoneKey> {
+        zone.keys.iter().find(|key| key.role == role && key.state == state)
+    }
+
+    #[test]
+    fn test_elects_roller() {
+        let now = 10_000;
+        let nodes = [("b", now as i64), ("c", now as i64 - 10), ("a", 0)];
+        // `a` has the lowest id, but has stopped sending heartbeats
+        assert!(elects_roller("b", nodes, now));
+        assert!(!elects_roller("c", nodes, now));
+        assert!(elects_roller("a", nodes, now));
+        // A node that isn't listed yet still takes part
+        assert!(elects_roller("0", nodes, now));
+        assert!(elects_roller("b", [], now));
+    }
+
+    #[test]
+    fn test_base32hex() {
+        assert_eq!(base32hex(b""), "");
+        assert_eq!(base32hex(b"f"), "co");
+        assert_eq!(base32hex(b"foobar"), "cpnmuoj1e8");
+        assert_eq!(successor(&[0x01, 0xff]), vec![0x02, 0x00]);
+    }
+
+    #[test]
+    fn test_new_zone() {
+        let zone = zone(Denial::Nsec);
+        assert_eq!(zone.zone, "example.com");
+        assert!(zone.contains("www.example.com."));
+        assert!(zone.contains("EXAMPLE.com"));
+        assert!(!zone.contains("badexample.com"));
+
+        assert!(key(&zone, KeyRole::Ksk, KeyState::Active).unwrap().dnskey().unwrap().secure_entry_point());
+        assert!(!key(&zone, KeyRole::Zsk, KeyState::Active).unwrap().dnskey().unwrap().secure_entry_point());
+
+        let ds = zone.ds_records().unwrap();
+        assert_eq!(ds.len(), 1);
+        let ksk = key(&zone, KeyRole::Ksk, KeyState::Active).unwrap().dnskey().unwrap();
+        assert!(ds[0].covers(&zone.name().unwrap(), &ksk).unwrap());
+        let export = zone.export().unwrap();
+        assert_eq!(export.ds, vec![ds_presentation(&zone.zone, &ds[0])]);
+        assert!(export.ds[0].starts_with("example.com. 3600 IN DS "));
+        assert!(export.dnskey[0].starts_with("example.com. 3600 IN DNSKEY 257 3 13 "));
+        assert!(!export.awaiting_ds);
+    }
+
+    #[test]
+    fn test_zsk_rollover() {
+        let mut zone = zone(Denial::Nsec);
+        let old = key(&zone, KeyRole::Zsk, KeyState::Active).unwrap().clone();
+
+        assert!(!zone.roll(2_000, None).unwrap());
+
+        // The new ZSK is published first
+        let expired = 1_000 + ZSK_LIFETIME;
+        assert!(zone.roll(expired, None).unwrap());
+        assert_eq!(zone.updated, expired);
+        assert!(key(&zone, KeyRole::Zsk, KeyState::Published).is_some());
+        assert_eq!(key(&zone, KeyRole::Zsk, KeyState::Active), Some(&old));
+
+        // and takes over once resolvers have it
+        assert!(zone.roll(expired + PROPAGATION, None).unwrap());
+        assert_eq!(key(&zone, KeyRole::Zsk, KeyState::Retired).unwrap().pkcs8, old.pkcs8);
+        assert_ne!(key(&zone, KeyRole::Zsk, KeyState::Active).unwrap().pkcs8, old.pkcs8);
+
+        // The old ZSK is dropped once its signatures expired from caches
+        assert!(zone.roll(expired + 2 * PROPAGATION, None).unwrap());
+        assert!(key(&zone, KeyRole::Zsk, KeyState::Retired).is_none());
+        assert_eq!(zone.keys.len(), 2);
+    }
+
+    #[test]
+    fn test_ksk_rollover_waits_for_ds() {
+        let mut zone = zone(Denial::Nsec);
+        let old = zone.ds_records().unwrap();
+        let expired = 1_000 + KSK_LIFETIME;
+        zone.roll(expired, None).unwrap();
+        assert!(zone.awaiting_ds());
+        assert_eq!(zone.ds_records().unwrap().len(), 2);
+
+        // The parent still vouches for the old KSK only
+        let after = expired + PROPAGATION;
+        zone.roll(after, Some(&old)).unwrap();
+        assert!(zone.awaiting_ds());
+        zone.roll(after, None).unwrap();
+        assert!(zone.awaiting_ds());
+
+        let new = zone.ds_records().unwrap();
+        zone.roll(after, Some(&new)).unwrap();
+        assert!(!zone.awaiting_ds());
+        assert!(key(&zone, KeyRole::Ksk, KeyState::Retired).is_some());
+        assert_eq!(zone.ds_records().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_sign_and_deny() {
+        let signer = ZoneSigner::new(zone(Denial::Nsec)).unwrap();
+        let apex = signer.name().clone();
+
+        let mut dnskeys = signer.apex(RecordType::DNSKEY).unwrap();
+        signer.sign(&mut dnskeys, 2_000).unwrap();
+        assert_eq!(dnskeys.records_without_rrsigs().count(), 2);
+        // Only the KSK signs the DNSKEY set
+        assert_eq!(dnskeys.rrsigs().len(), 1);
+
+        let mut soa = signer.apex(RecordType::SOA).unwrap();
+        signer.sign(&mut soa, 2_000).unwrap();
+        match soa.rrsigs()[0].data() {
+            Some(RData::DNSSEC(DNSSECRData::SIG(sig))) => {
+                assert_eq!(sig.type_covered(), RecordType::SOA);
+                assert_eq!(sig.signer_name(), &apex);
+                assert_eq!(sig.sig_expiration(), (2_000 + SIGNATURE_VALIDITY) as u32);
+            }
+            other => panic!("unexpected RRSIG {other:?}"),
+        }
+
+        let name = Name::from_str("missing.example.com.").unwrap();
+        let nsec = signer.denial(&name, &[]).unwrap();
+        assert_eq!(nsec.name(), &name);
+        match nsec.records_without_rrsigs().next().and_then(|record| record.data()) {
+            Some(RData::DNSSEC(DNSSECRData::NSEC(nsec))) => {
+                assert_eq!(nsec.next_domain_name(), &name.prepend_label(&[0u8][..]).unwrap());
+                assert_eq!(nsec.type_bit_maps(), &[RecordType::RRSIG, RecordType::NSEC]);
+            }
+            other => panic!("unexpected NSEC {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_nsec3_denial() {
+        let signer = ZoneSigner::new(zone(Denial::Nsec3 { iterations: 0, salt: String::new() })).unwrap();
+        assert!(signer.apex_types().contains(&RecordType::NSEC3PARAM));
+
+        let name = Name::from_str("www.example.com.").unwrap();
+        let nsec3 = signer.denial(&name, &[RecordType::A]).unwrap();
+        // A SHA-1 hash is 32 base32hex characters
+        assert_eq!(nsec3.name().num_labels(), 3);
+        assert_eq!(nsec3.name().iter().next().unwrap().len(), 32);
+        match nsec3.records_without_rrsigs().next().and_then(|record| record.data()) {
+            Some(RData::DNSSEC(DNSSECRData::NSEC3(nsec3))) => {
+                assert_eq!(nsec3.type_bit_maps(), &[RecordType::A, RecordType::RRSIG]);
+                assert_eq!(nsec3.iterations(), 0);
+            }
+            other => panic!("unexpected NSEC3 {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use log::{debug, info};
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+use tokio::task::JoinSet;
+
+use crate::routing::RoutingPolicy;
+use crate::store::SharedStore;
+
+pub const DEFAULT_PROBE_INTERVAL: Duration = Duration::from_secs(30);
+pub const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Weight of a new sample in the smoothed round-trip time, in tenths
+const SAMPLE_WEIGHT: u32 = 3;
+
+/// Round-trip times from this node to the addresses of records routed by
+/// latency. Clients query the form-dns node nearest to them, so the node's
+/// view stands in for the client's.
+#[derive(Debug, Default)]
+pub struct LatencyRepository {
+    rtts: HashMap<IpAddr, Duration>,
+}
+
+impl LatencyRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Fold a measurement into the smoothed round-trip time of an address
+    pub fn record(&mut self, ip: IpAddr, sample: Duration) {
+        let rtt = match self.rtts.get(&ip) {
+            Some(rtt) => (*rtt * (10 - SAMPLE_WEIGHT) + sample * SAMPLE_WEIGHT) / 10,
+            None => sample,
+        };
+        self.rtts.insert(ip, rtt);
+    }
+
+    /// Drop the round-trip time of an address that could not be reached,
+    /// so it is ordered after the addresses that could
+    pub fn forget(&mut self, ip: &IpAddr) {
+        self.rtts.remove(ip);
+    }
+
+    pub fn rtt(&self, ip: &IpAddr) -> Option<Duration> {
+        self.rtts.get(ip).copied()
+    }
+
+    /// Round-trip times of the given addresses that have been measured
+    pub fn rtts(&self, ips: impl IntoIterator<Item = IpAddr>) -> HashMap<IpAddr, Duration> {
+        ips.into_iter().filter_map(|ip| self.rtt(&ip).map(|rtt| (ip, rtt))).collect()
+    }
+}
+
+pub type SharedLatencyRepository = Arc<RwLock<LatencyRepository>>;
+
+/// Measures the time a TCP handshake with each address of a record routed
+/// by latency takes
+pub struct LatencyTracker {
+    store: SharedStore,
+    latency_repo: SharedLatencyRepository,
+    probe_interval: Duration,
+}
+
+impl LatencyTracker {
+    pub fn new(
+        store: SharedStore,
+        latency_repo: SharedLatencyRepository,
+        probe_interval: Option<Duration>,
+    ) -> Self {
+        Self {
+            store,
+            latency_repo,
+            probe_interval: probe_interval.unwrap_or(DEFAULT_PROBE_INTERVAL),
+        }
+    }
+
+    pub async fn start_probing(&self) {
+        info!("Starting latency tracker probing loop");
+        let mut interval = tokio::time::interval(self.probe_interval);
+        loop {
+            interval.tick().await;
+            self.probe().await;
+        }
+    }
+
+    /// Addresses of every record routed by latency
+    async fn targets(&self) -> HashSet<SocketAddr> {
+        let guard = self.store.read().await;
+        let domains: Vec<String> = guard.iter()
+            .filter(|(_, record)| record.routing == RoutingPolicy::Latency)
+            .map(|(domain, _)| domain.clone())
+            .collect();
+
+        // Resolved, so an ANAME record is measured on its target's addresses
+        domains.iter()
+            .filter_map(|domain| guard.resolve(domain))
+            .flat_map(|record| record.public_ip.into_iter().chain(record.formnet_ip))
+            .collect()
+    }
+
+    async fn probe(&self) {
+        let targets = self.targets().await;
+        if targets.is_empty() {
+            return;
+        }
+        debug!("Probing round-trip times of {} addresses", targets.len());
+
+        let mut probes = JoinSet::new();
+        for addr in targets {
+            probes.spawn(async move {
+                let start = Instant::now();
+                match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(addr)).await {
+                    Ok(Ok(_)) => (addr, Some(start.elapsed())),
+                    _ => (addr, None),
+                }
+            });
+        }
+
+        while let Some(result) = probes.join_next().await {
+            let Ok((addr, rtt)) = result else {
+                continue;
+            };
+            let mut repo = self.latency_repo.write().await;
+            match rtt {
+                Some(rtt) => repo.record(addr.ip(), rtt),
+                None => {
+                    debug!("Could not reach {addr} within {PROBE_TIMEOUT:?}");
+                    repo.forget(&addr.ip());
+                }
+            }
+        }
+    }
+}
+
+/// Start measuring round-trip times to the addresses of records routed by
+/// latency
+pub async fn start_latency_tracker(
+    store: SharedStore,
+    probe_interval: Option<Duration>,
+) -> SharedLatencyRepository {
+    let latency_repo = Arc::new(RwLock::new(LatencyRepository::new()));
+    let tracker = LatencyTracker::new(store, latency_repo.clone(), probe_interval);
+    tokio::spawn(async move {
+        tracker.start_probing().await;
+    });
+
+    latency_repo
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_smoothed_rtt() {
+        let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let mut repo = LatencyRepository::new();
+        assert_eq!(repo.rtt(&ip), None);
+
+        repo.record(ip, Duration::from_millis(100));
+        assert_eq!(repo.rtt(&ip), Some(Duration::from_millis(100)));
+
+        // A single slow probe moves the estimate, it doesn't replace it
+        repo.record(ip, Duration::from_millis(200));
+        assert_eq!(repo.rtt(&ip), Some(Duration::from_millis(130)));
+
+        let other = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        assert_eq!(repo.rtts([ip, other]).len(), 1);
+
+        repo.forget(&ip);
+        assert_eq!(repo.rtt(&ip), None);
+    }
+}
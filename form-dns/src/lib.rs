@@ -3,12 +3,16 @@ use std::net::Ipv4Addr;
 pub mod store;
 pub mod proxy;
 pub mod authority;
+pub mod dnssec;
 pub mod api;
 pub mod geolocation;
 pub mod geo_resolver;
 pub mod geo_util;
 pub mod health;
 pub mod health_tracker;
+pub mod latency;
+pub mod routing;
+pub mod tls;
 
 pub fn resolvectl_domain() -> Result<(), Box<dyn std::error::Error>> {
     let output = std::process::Command::new("resolvectl")
@@ -6,7 +6,9 @@ use form_dns::api::serve_api;
 use form_dns::proxy::IntegratedProxy;
 use form_dns::store::{DnsStore, SharedStore};
 use form_dns::authority::FormAuthority;
-use form_dns::health_tracker;
+use form_dns::{dnssec, health_tracker, latency};
+use form_dns::routing::RoutingPolicy;
+use form_dns::tls::{serve_encrypted, EncryptedDnsConfig};
 use form_rplb::config::ProxyConfig;
 use form_rplb::resolver::TlsManager;
 use tokio::net::UdpSocket;
@@ -63,6 +65,11 @@ async fn main() -> anyhow::Result<()> {
             verification_timestamp: Some(std::time::SystemTime::now()
                 .duration_since(std::time::UNIX_EPOCH)
                 .map_or(0, |d| d.as_secs())),
+            txt: vec![],
+            mx: vec![],
+            srv: vec![],
+            caa: vec![],
+            routing: RoutingPolicy::default(),
         };
         
         // Add the bootstrap domain to the DNS store
@@ -81,6 +88,8 @@ async fn main() -> anyhow::Result<()> {
     let proxy_config = ProxyConfig::default();
     log::info!("Building IntegratedProxy...");
     let mut reverse_proxy = IntegratedProxy::new(store.clone(), tls_manager, proxy_config).await.map_err(|e| anyhow::anyhow!(e.to_string()))?;
+    // DoT and DoH take their certificates from the reverse proxy's manager
+    let encrypted_tls_manager = reverse_proxy.tls_manager.clone();
     log::info!("Launching IntegratedProxy...");
     let reverse_proxy_handle = tokio::spawn(async move {
         if let Err(e) = reverse_proxy.bind().await {
@@ -109,15 +118,29 @@ async fn main() -> anyhow::Result<()> {
     let (fallback_client, bg) = AsyncClient::connect(stream).await?;
     tokio::spawn(bg);
     log::warn!("Spawned AsyncClient in background...");
+
+    // Load the DNSSEC keys of signed zones and keep them rolling over
+    log::info!("Starting DNSSEC key manager");
+    let dnssec_store = dnssec::start_key_manager(
+        "http://localhost:3004".to_string(),  // Form-state API endpoint
+        fallback_client.clone(),              // Looks up the DS records of parent zones
+        None,
+    ).await;
     
+    // Measure round-trip times to the addresses of records routed by latency
+    log::info!("Starting latency tracker");
+    let latency_repo = latency::start_latency_tracker(store.clone(), None).await;
+
     log::warn!("Setting authority origin to root...");
     let origin = Name::root();
     
     // Create the authority with health repository integration
     let auth = FormAuthority::new(origin, store.clone(), fallback_client)
-        .with_health_repository(health_repo);
+        .with_health_repository(health_repo)
+        .with_latency_repository(latency_repo)
+        .with_dnssec(dnssec_store);
 
-    log::info!("Created FormAuthority with health repository integration");
+    log::info!("Created FormAuthority with health repository and DNSSEC integration");
     log::debug!("Wrapping authority in an Atomic Reference Counter...");
     let auth_arc = Arc::new(auth);
 
@@ -126,6 +149,17 @@ async fn main() -> anyhow::Result<()> {
     catalog.upsert(Name::root().into(), Box::new(auth_arc.clone()));
     log::info!("Built catalog for FormAuthority with origin root...");
 
+    // DoT and DoH are answered by a catalog around the same authority
+    let mut encrypted_catalog = Catalog::new();
+    encrypted_catalog.upsert(Name::root().into(), Box::new(auth_arc.clone()));
+    let encrypted_config = EncryptedDnsConfig::from_env();
+    log::info!("Launching DNS-over-TLS and DNS-over-HTTPS listeners for {:?}...", encrypted_config.hostnames);
+    let encrypted_handle = tokio::spawn(async move {
+        if let Err(e) = serve_encrypted(encrypted_catalog, encrypted_tls_manager, encrypted_config).await {
+            eprintln!("Error in DNS-over-TLS/HTTPS listeners: {e}");
+        }
+    });
+
     let mut server_future = ServerFuture::new(catalog);
     log::warn!("Built server future for catalog...");
     let udp_socket = UdpSocket::bind("0.0.0.0:5453").await?;
@@ -137,6 +171,7 @@ async fn main() -> anyhow::Result<()> {
     server_future.block_until_done().await?;
     reverse_proxy_handle.await?;
     dns_store_api_handle.await?;
+    encrypted_handle.await?;
 
     Ok(())
 }
@@ -171,9 +171,12 @@ impl IntegratedProxy {
     }
 
     pub async fn handle_https(&self, stream: TcpStream) -> Result<(), ProxyError> {
-        let tls_manager = self.tls_manager.lock().await;
-        let acceptor = tls_manager.acceptor.clone();
-        let server_config = tls_manager.config.clone();
+        // The manager is shared with the DoT and DoH listeners, so don't
+        // hold it for the lifetime of the connection
+        let (acceptor, server_config) = {
+            let tls_manager = self.tls_manager.lock().await;
+            (tls_manager.acceptor.clone(), tls_manager.config.clone())
+        };
 
         match acceptor.accept(stream).await? {
             Some(handshake) => {
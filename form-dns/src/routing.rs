@@ -0,0 +1,356 @@
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::str::FromStr;
+use std::time::Duration;
+use serde::{Deserialize, Serialize};
+
+/// How the addresses of a record are picked for an answer. Every policy
+/// works on the addresses left after health filtering, sorted by distance
+/// to the client, so unhealthy addresses are never preferred.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum RoutingPolicy {
+    /// All addresses, nearest to the client first
+    #[default]
+    Proximity,
+    /// A single address per answer, taking turns in proportion to the
+    /// weights. Addresses without a weight only answer when no weighted
+    /// address is healthy.
+    Weighted(Vec<WeightedTarget>),
+    /// The primary addresses while any of them is healthy, the secondary
+    /// addresses otherwise
+    Failover {
+        primary: Vec<IpAddr>,
+        secondary: Vec<IpAddr>,
+    },
+    /// All addresses, lowest measured round-trip time first. Addresses that
+    /// have not been measured yet follow, nearest first.
+    Latency,
+    /// Clients in a pinned region get that region's addresses, everyone
+    /// else all addresses, nearest first
+    Region(Vec<RegionPin>),
+}
+
+/// An address and its share of the answers of a weighted record
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct WeightedTarget {
+    pub ip: IpAddr,
+    pub weight: u32,
+}
+
+/// Addresses served to clients of a region, an ISO 3166 country code such
+/// as `DE` or a country and subdivision such as `US-CA`
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct RegionPin {
+    pub region: String,
+    pub ips: Vec<IpAddr>,
+}
+
+/// What is known about a query when a policy picks its addresses
+#[derive(Clone, Debug, Default)]
+pub struct RoutingContext {
+    /// Regions of the client, most specific first, e.g. `["US-CA", "US"]`
+    pub client_regions: Vec<String>,
+    /// Measured round-trip times to the record's addresses
+    pub rtts: HashMap<IpAddr, Duration>,
+    /// How many answers the record has had, for weighted turns
+    pub turn: u64,
+}
+
+impl RoutingContext {
+    /// Regions of a client from its geolocation
+    pub fn regions(country_code: Option<&str>, region_code: Option<&str>) -> Vec<String> {
+        let mut regions = vec![];
+        if let Some(country) = country_code {
+            let country = country.to_uppercase();
+            if let Some(region) = region_code {
+                regions.push(format!("{country}-{}", region.to_uppercase()));
+            }
+            regions.push(country);
+        }
+        regions
+    }
+}
+
+impl RoutingPolicy {
+    pub fn is_default(&self) -> bool {
+        *self == RoutingPolicy::Proximity
+    }
+
+    /// Check that the policy can pick an address
+    pub fn validate(&self) -> Result<(), String> {
+        match self {
+            RoutingPolicy::Proximity | RoutingPolicy::Latency => Ok(()),
+            RoutingPolicy::Weighted(targets) => {
+                if targets.iter().all(|target| target.weight == 0) {
+                    return Err("A weighted policy needs at least one address with a weight above 0".to_string());
+                }
+                Ok(())
+            }
+            RoutingPolicy::Failover { primary, .. } => {
+                if primary.is_empty() {
+                    return Err("A failover policy needs at least one primary address".to_string());
+                }
+                Ok(())
+            }
+            RoutingPolicy::Region(pins) => {
+                if pins.is_empty() {
+                    return Err("A region policy needs at least one pinned region".to_string());
+                }
+                if let Some(pin) = pins.iter().find(|pin| pin.ips.is_empty()) {
+                    return Err(format!("Region {} is pinned to no addresses", pin.region));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Pick the addresses to answer with from the healthy addresses of a
+    /// record, sorted nearest first
+    pub fn select(&self, addrs: Vec<SocketAddr>, ctx: &RoutingContext) -> Vec<SocketAddr> {
+        match self {
+            RoutingPolicy::Proximity => addrs,
+            RoutingPolicy::Weighted(targets) => {
+                // Turns follow the order of the policy, not the client's
+                let weighted: Vec<(SocketAddr, u32)> = targets.iter()
+                    .filter(|target| target.weight > 0)
+                    .filter_map(|target| {
+                        addrs.iter()
+                            .find(|addr| addr.ip() == target.ip)
+                            .map(|addr| (*addr, target.weight))
+                    }).collect();
+                let total: u64 = weighted.iter().map(|(_, weight)| *weight as u64).sum();
+                if total == 0 {
+                    return addrs;
+                }
+
+                let mut position = ctx.turn % total;
+                for (addr, weight) in weighted {
+                    if position < weight as u64 {
+                        return vec![addr];
+                    }
+                    position -= weight as u64;
+                }
+                addrs
+            }
+            RoutingPolicy::Failover { primary, secondary } => {
+                for tier in [primary, secondary] {
+                    let healthy = only(&addrs, tier);
+                    if !healthy.is_empty() {
+                        return healthy;
+                    }
+                }
+                addrs
+            }
+            RoutingPolicy::Latency => {
+                let mut addrs = addrs;
+                // A stable sort keeps unmeasured addresses nearest first
+                addrs.sort_by_key(|addr| ctx.rtts.get(&addr.ip()).copied().unwrap_or(Duration::MAX));
+                addrs
+            }
+            RoutingPolicy::Region(pins) => {
+                for region in &ctx.client_regions {
+                    let Some(pin) = pins.iter().find(|pin| pin.region.eq_ignore_ascii_case(region)) else {
+                        continue;
+                    };
+                    let pinned = only(&addrs, &pin.ips);
+                    if !pinned.is_empty() {
+                        return pinned;
+                    }
+                }
+                addrs
+            }
+        }
+    }
+}
+
+/// The addresses whose IP is one of `ips`, in their current order
+fn only(addrs: &[SocketAddr], ips: &[IpAddr]) -> Vec<SocketAddr> {
+    addrs.iter().filter(|addr| ips.contains(&addr.ip())).copied().collect()
+}
+
+impl FromStr for WeightedTarget {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((ip, weight)) = s.split_once('=') else {
+            return Err(format!("Invalid weighted address {s}, expected <ip>=<weight>"));
+        };
+        Ok(Self {
+            ip: ip.trim().parse().map_err(|_| format!("Invalid IP address {ip}"))?,
+            weight: weight.trim().parse().map_err(|_| format!("Invalid weight {weight}"))?,
+        })
+    }
+}
+
+impl std::fmt::Display for WeightedTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.ip, self.weight)
+    }
+}
+
+impl FromStr for RegionPin {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let Some((region, ips)) = s.split_once('=') else {
+            return Err(format!("Invalid region pin {s}, expected <region>=<ip>[,<ip>...]"));
+        };
+        let region = region.trim().to_uppercase();
+        if region.is_empty() {
+            return Err(format!("Invalid region pin {s}, the region is empty"));
+        }
+        let ips = ips.split(',').map(|ip| {
+            ip.trim().parse().map_err(|_| format!("Invalid IP address {ip}"))
+        }).collect::<Result<Vec<IpAddr>, String>>()?;
+        Ok(Self { region, ips })
+    }
+}
+
+impl std::fmt::Display for RegionPin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let ips = self.ips.iter().map(|ip| ip.to_string()).collect::<Vec<String>>();
+        write!(f, "{}={}", self.region, ips.join(","))
+    }
+}
+
+impl std::fmt::Display for RoutingPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let join = |values: Vec<String>| values.join(" ");
+        match self {
+            RoutingPolicy::Proximity => write!(f, "proximity"),
+            RoutingPolicy::Weighted(targets) => {
+                write!(f, "weighted {}", join(targets.iter().map(|t| t.to_string()).collect()))
+            }
+            RoutingPolicy::Failover { primary, secondary } => write!(
+                f,
+                "failover primary {} secondary {}",
+                join(primary.iter().map(|ip| ip.to_string()).collect()),
+                join(secondary.iter().map(|ip| ip.to_string()).collect()),
+            ),
+            RoutingPolicy::Latency => write!(f, "latency"),
+            RoutingPolicy::Region(pins) => {
+                write!(f, "region {}", join(pins.iter().map(|p| p.to_string()).collect()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addrs(ips: &[&str]) -> Vec<SocketAddr> {
+        ips.iter().map(|ip| SocketAddr::new(ip.parse().unwrap(), 80)).collect()
+    }
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    #[test]
+    fn test_weighted_turns() {
+        let policy = RoutingPolicy::Weighted(vec![
+            "192.0.2.1=3".parse().unwrap(),
+            "192.0.2.2=1".parse().unwrap(),
+        ]);
+        let candidates = addrs(&["192.0.2.1", "192.0.2.2", "192.0.2.3"]);
+
+        let mut counts: HashMap<IpAddr, u32> = HashMap::new();
+        for turn in 0..400 {
+            let ctx = RoutingContext { turn, ..Default::default() };
+            let answer = policy.select(candidates.clone(), &ctx);
+            assert_eq!(answer.len(), 1);
+            *counts.entry(answer[0].ip()).or_default() += 1;
+        }
+        assert_eq!(counts.get(&ip("192.0.2.1")), Some(&300));
+        assert_eq!(counts.get(&ip("192.0.2.2")), Some(&100));
+        assert_eq!(counts.get(&ip("192.0.2.3")), None);
+
+        // Unhealthy weighted addresses are gone from the candidates, the
+        // unweighted ones answer once no weighted address is left
+        let answer = policy.select(addrs(&["192.0.2.3"]), &RoutingContext::default());
+        assert_eq!(answer, addrs(&["192.0.2.3"]));
+    }
+
+    #[test]
+    fn test_failover() {
+        let policy = RoutingPolicy::Failover {
+            primary: vec![ip("192.0.2.1")],
+            secondary: vec![ip("192.0.2.2"), ip("192.0.2.3")],
+        };
+        let ctx = RoutingContext::default();
+
+        assert_eq!(
+            policy.select(addrs(&["192.0.2.3", "192.0.2.1", "192.0.2.2"]), &ctx),
+            addrs(&["192.0.2.1"])
+        );
+        assert_eq!(
+            policy.select(addrs(&["192.0.2.3", "192.0.2.2"]), &ctx),
+            addrs(&["192.0.2.3", "192.0.2.2"])
+        );
+        assert_eq!(policy.select(addrs(&["192.0.2.4"]), &ctx), addrs(&["192.0.2.4"]));
+    }
+
+    #[test]
+    fn test_latency() {
+        let ctx = RoutingContext {
+            rtts: HashMap::from([
+                (ip("192.0.2.1"), Duration::from_millis(80)),
+                (ip("192.0.2.2"), Duration::from_millis(12)),
+            ]),
+            ..Default::default()
+        };
+
+        assert_eq!(
+            RoutingPolicy::Latency.select(addrs(&["192.0.2.4", "192.0.2.1", "192.0.2.3", "192.0.2.2"]), &ctx),
+            addrs(&["192.0.2.2", "192.0.2.1", "192.0.2.4", "192.0.2.3"])
+        );
+    }
+
+    #[test]
+    fn test_region_pinning() {
+        let policy = RoutingPolicy::Region(vec![
+            "us-ca=192.0.2.1".parse().unwrap(),
+            "US=192.0.2.2,192.0.2.3".parse().unwrap(),
+            "DE=192.0.2.4".parse().unwrap(),
+        ]);
+        let candidates = addrs(&["192.0.2.1", "192.0.2.2", "192.0.2.3", "192.0.2.4"]);
+
+        let california = RoutingContext {
+            client_regions: RoutingContext::regions(Some("us"), Some("ca")),
+            ..Default::default()
+        };
+        assert_eq!(policy.select(candidates.clone(), &california), addrs(&["192.0.2.1"]));
+
+        // The subdivision's addresses are down, the country's take over
+        assert_eq!(
+            policy.select(addrs(&["192.0.2.2", "192.0.2.4"]), &california),
+            addrs(&["192.0.2.2"])
+        );
+
+        let texas = RoutingContext {
+            client_regions: RoutingContext::regions(Some("US"), Some("TX")),
+            ..Default::default()
+        };
+        assert_eq!(policy.select(candidates.clone(), &texas), addrs(&["192.0.2.2", "192.0.2.3"]));
+
+        let unknown = RoutingContext::default();
+        assert_eq!(policy.select(candidates.clone(), &unknown), candidates);
+    }
+
+    #[test]
+    fn test_validate_and_parse() {
+        assert!(RoutingPolicy::Weighted(vec!["192.0.2.1=0".parse().unwrap()]).validate().is_err());
+        assert!(RoutingPolicy::Failover { primary: vec![], secondary: vec![ip("192.0.2.1")] }.validate().is_err());
+        assert!(RoutingPolicy::Region(vec![]).validate().is_err());
+        assert!(RoutingPolicy::Latency.validate().is_ok());
+
+        assert!("192.0.2.1".parse::<WeightedTarget>().is_err());
+        assert!("192.0.2.1=heavy".parse::<WeightedTarget>().is_err());
+        assert!("=192.0.2.1".parse::<RegionPin>().is_err());
+        assert_eq!(
+            "de=192.0.2.1, 2001:db8::1".parse::<RegionPin>().unwrap().to_string(),
+            "DE=192.0.2.1,2001:db8::1"
+        );
+    }
+}
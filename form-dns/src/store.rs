@@ -1,4 +1,4 @@
-use std::collections::hash_map::{Entry, Iter};
+use std::collections::hash_map::Iter;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::collections::HashMap;
 use tokio::sync::{RwLock, mpsc::Sender};
@@ -6,6 +6,8 @@ use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use trust_dns_proto::rr::RecordType;
 use trust_dns_proto::rr::{Name, RData};
+use trust_dns_proto::rr::rdata::{CAA, MX, SRV, TXT};
+use trust_dns_proto::rr::rdata::caa::KeyValue;
 use trust_dns_client::client::{AsyncClient, ClientHandle};
 use trust_dns_client::udp::UdpClientStream;
 use trust_dns_client::rr::DNSClass;
@@ -14,6 +16,11 @@ use std::time::Duration;
 
 use crate::resolvectl_dns;
 use crate::health::SharedIpHealthRepository;
+use crate::routing::RoutingPolicy;
+
+/// How many ANAME records a lookup follows before giving up, so aliases
+/// pointing at each other cannot loop
+pub const MAX_ALIAS_DEPTH: usize = 8;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct FormDnsRecord {
@@ -21,11 +28,219 @@ pub struct FormDnsRecord {
     pub record_type: RecordType,
     pub public_ip: Vec<SocketAddr>,
     pub formnet_ip: Vec<SocketAddr>,
+    /// Target of a CNAME record, or the Formation record an ANAME record
+    /// takes its addresses from
     pub cname_target: Option<String>,
     pub ssl_cert: bool,
     pub ttl: u32,
     pub verification_status: Option<VerificationStatus>,
     pub verification_timestamp: Option<u64>,
+    /// TXT values served for the domain, whatever its record type
+    #[serde(default)]
+    pub txt: Vec<String>,
+    /// Mail exchanges served for the domain, whatever its record type
+    #[serde(default)]
+    pub mx: Vec<MxRecord>,
+    /// Service locations served for the domain, whatever its record type
+    #[serde(default)]
+    pub srv: Vec<SrvRecord>,
+    /// CA authorizations served for the domain, whatever its record type
+    #[serde(default)]
+    pub caa: Vec<CaaRecord>,
+    /// How the addresses of the domain are picked for an answer
+    #[serde(default)]
+    pub routing: RoutingPolicy,
+}
+
+impl FormDnsRecord {
+    /// Rdata of the TXT, MX, SRV or CAA records of the domain. Values that
+    /// cannot be served are logged and skipped.
+    pub fn rdata(&self, rtype: RecordType) -> Vec<RData> {
+        let rdata: Vec<Result<RData, String>> = match rtype {
+            RecordType::TXT => self.txt.iter().map(|value| txt_rdata(value)).collect(),
+            RecordType::MX => self.mx.iter().map(MxRecord::rdata).collect(),
+            RecordType::SRV => self.srv.iter().map(SrvRecord::rdata).collect(),
+            RecordType::CAA => self.caa.iter().map(CaaRecord::rdata).collect(),
+            _ => vec![],
+        };
+
+        rdata.into_iter().filter_map(|rdata| {
+            rdata.map_err(|e| log::warn!("Skipping {rtype} record of {}: {e}", self.domain)).ok()
+        }).collect()
+    }
+
+    /// Types of the records served for the domain, as listed in the type
+    /// bitmap of a DNSSEC denial
+    pub fn record_types(&self) -> Vec<RecordType> {
+        let addrs = || self.public_ip.iter().chain(self.formnet_ip.iter());
+        let mut types = vec![];
+        if addrs().any(|addr| addr.is_ipv4()) {
+            types.push(RecordType::A);
+        }
+        if addrs().any(|addr| addr.is_ipv6()) {
+            types.push(RecordType::AAAA);
+        }
+        if self.record_type == RecordType::CNAME && self.cname_target.is_some() {
+            types.push(RecordType::CNAME);
+        }
+        for rtype in [RecordType::TXT, RecordType::MX, RecordType::SRV, RecordType::CAA] {
+            if !self.rdata(rtype).is_empty() {
+                types.push(rtype);
+            }
+        }
+        types
+    }
+}
+
+/// Rdata of a TXT value, split into character-strings of at most 255 bytes
+/// so long values such as DKIM keys can be served
+pub fn txt_rdata(value: &str) -> Result<RData, String> {
+    if value.is_empty() {
+        return Err("TXT values cannot be empty".to_string());
+    }
+    Ok(RData::TXT(TXT::from_bytes(value.as_bytes().chunks(255).collect())))
+}
+
+/// A mail exchange, written `<preference> <exchange>` as in a zone file
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MxRecord {
+    pub preference: u16,
+    pub exchange: String,
+}
+
+impl MxRecord {
+    pub fn rdata(&self) -> Result<RData, String> {
+        let exchange = Name::from_utf8(&self.exchange)
+            .map_err(|e| format!("Invalid MX exchange {}: {e}", self.exchange))?;
+        Ok(RData::MX(MX::new(self.preference, exchange)))
+    }
+}
+
+impl FromStr for MxRecord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<&str>>()[..] {
+            [preference, exchange] => Ok(Self {
+                preference: preference.parse().map_err(|_| format!("Invalid MX preference {preference}"))?,
+                exchange: exchange.to_string(),
+            }),
+            _ => Err(format!("Invalid MX record {s}, expected <preference> <exchange>")),
+        }
+    }
+}
+
+impl std::fmt::Display for MxRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.preference, self.exchange)
+    }
+}
+
+/// A service location, written `<priority> <weight> <port> <target>` as in
+/// a zone file. It is served for a `_service._proto` domain.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+impl SrvRecord {
+    pub fn rdata(&self) -> Result<RData, String> {
+        let target = Name::from_utf8(&self.target)
+            .map_err(|e| format!("Invalid SRV target {}: {e}", self.target))?;
+        Ok(RData::SRV(SRV::new(self.priority, self.weight, self.port, target)))
+    }
+}
+
+impl FromStr for SrvRecord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<Vec<&str>>()[..] {
+            [priority, weight, port, target] => Ok(Self {
+                priority: priority.parse().map_err(|_| format!("Invalid SRV priority {priority}"))?,
+                weight: weight.parse().map_err(|_| format!("Invalid SRV weight {weight}"))?,
+                port: port.parse().map_err(|_| format!("Invalid SRV port {port}"))?,
+                target: target.to_string(),
+            }),
+            _ => Err(format!("Invalid SRV record {s}, expected <priority> <weight> <port> <target>")),
+        }
+    }
+}
+
+impl std::fmt::Display for SrvRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {} {}", self.priority, self.weight, self.port, self.target)
+    }
+}
+
+/// A certification authority authorization, written `<flags> <tag> <value>`
+/// as in a zone file. The issue, issuewild and iodef tags are supported.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CaaRecord {
+    pub critical: bool,
+    pub tag: String,
+    pub value: String,
+}
+
+impl CaaRecord {
+    pub fn rdata(&self) -> Result<RData, String> {
+        let caa = match self.tag.to_lowercase().as_str() {
+            tag @ ("issue" | "issuewild") => {
+                let mut parts = self.value.split(';');
+                let issuer = parts.next().unwrap_or_default().trim();
+                let name = if issuer.is_empty() {
+                    None
+                } else {
+                    Some(Name::from_utf8(issuer).map_err(|e| format!("Invalid CAA issuer {issuer}: {e}"))?)
+                };
+                let key_values = parts.map(str::trim).filter(|p| !p.is_empty()).map(|p| {
+                    p.split_once('=')
+                        .map(|(key, value)| KeyValue::new(key.trim(), value.trim()))
+                        .ok_or_else(|| format!("Invalid CAA parameter {p}, expected <key>=<value>"))
+                }).collect::<Result<Vec<KeyValue>, String>>()?;
+                if tag == "issue" {
+                    CAA::new_issue(self.critical, name, key_values)
+                } else {
+                    CAA::new_issuewild(self.critical, name, key_values)
+                }
+            }
+            "iodef" => {
+                let url = url::Url::parse(&self.value)
+                    .map_err(|e| format!("Invalid CAA iodef URL {}: {e}", self.value))?;
+                CAA::new_iodef(self.critical, url)
+            }
+            _ => return Err(format!("Unsupported CAA tag {}, expected issue, issuewild or iodef", self.tag)),
+        };
+        Ok(RData::CAA(caa))
+    }
+}
+
+impl FromStr for CaaRecord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.trim().splitn(3, char::is_whitespace);
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some(flags), Some(tag), Some(value)) => {
+                let flags: u8 = flags.parse().map_err(|_| format!("Invalid CAA flags {flags}"))?;
+                Ok(Self {
+                    critical: flags & 0x80 != 0,
+                    tag: tag.to_lowercase(),
+                    value: value.trim().trim_matches('"').to_string(),
+                })
+            }
+            _ => Err(format!("Invalid CAA record {s}, expected <flags> <tag> <value>")),
+        }
+    }
+}
+
+impl std::fmt::Display for CaaRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} \"{}\"", if self.critical { 128 } else { 0 }, self.tag, self.value)
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -41,24 +256,58 @@ pub enum FormTarget {
     A(Vec<SocketAddr>),
     AAAA(Vec<SocketAddr>),
     CNAME(String),
+    TXT(Vec<String>),
+    MX(Vec<MxRecord>),
+    SRV(Vec<SrvRecord>),
+    CAA(Vec<CaaRecord>),
     None
 }
 
 #[derive(Clone, Default, Debug, Serialize, Deserialize)]
+#[serde(from = "StoredDnsStore")]
 pub struct DnsStore {
     servers: Vec<Ipv4Addr>,
     records: HashMap<String, FormDnsRecord>,
+    /// Number of records at or below each name, so that whether a name
+    /// exists, possibly as an empty non-terminal, is a single lookup
+    #[serde(skip)]
+    names: HashMap<String, usize>,
     #[serde(skip)]
     sender: Option<Sender<FormDnsRecord>>,
     #[serde(skip)]
     health_repository: Option<SharedIpHealthRepository>,
 }
 
+/// The serialized fields of a `DnsStore`, from which the name index is
+/// rebuilt when it is deserialized
+#[derive(Deserialize)]
+struct StoredDnsStore {
+    servers: Vec<Ipv4Addr>,
+    records: HashMap<String, FormDnsRecord>,
+}
+
+impl From<StoredDnsStore> for DnsStore {
+    fn from(stored: StoredDnsStore) -> Self {
+        let mut store = DnsStore { servers: stored.servers, ..Default::default() };
+        for (key, record) in stored.records {
+            store.put(key, record);
+        }
+        store
+    }
+}
+
+/// The name and each of its ancestors, e.g. `a.example.com`, `example.com`
+/// and `com` for `a.example.com`
+fn self_and_ancestors(name: &str) -> impl Iterator<Item = &str> {
+    std::iter::successors(Some(name), |name| name.split_once('.').map(|(_, rest)| rest))
+}
+
 impl DnsStore {
     pub fn new(sender: Sender<FormDnsRecord>) -> Self {
         Self {
             servers: Vec::new(),
             records: HashMap::new(),
+            names: HashMap::new(),
             sender: Some(sender),
             health_repository: None,
         }
@@ -89,15 +338,82 @@ impl DnsStore {
         if let Some(ref mut sender) = &mut self.sender {
             let _ = sender.send(record.clone()).await;
         }
+        self.put(key, record);
+    }
+
+    /// Stores a record, indexing its name if it is new
+    fn put(&mut self, key: String, record: FormDnsRecord) {
+        if !self.records.contains_key(&key) {
+            for name in self_and_ancestors(&key) {
+                *self.names.entry(name.to_string()).or_default() += 1;
+            }
+        }
         self.records.insert(key, record);
     }
 
+    /// Record answering queries for `domain`: the record of the domain
+    /// itself, or else the wildcard record of its closest encloser. An
+    /// ANAME record takes on the addresses its target has at query time.
+    pub fn resolve(&self, domain: &str) -> Option<FormDnsRecord> {
+        let mut record = self.find(domain)?;
+        if record.record_type == RecordType::ANAME {
+            (record.public_ip, record.formnet_ip) = self.alias_addresses(&record);
+        }
+        Some(record)
+    }
+
+    fn find(&self, domain: &str) -> Option<FormDnsRecord> {
+        let key = domain.trim_end_matches('.').to_lowercase();
+        if let Some(record) = self.records.get(&key) {
+            return Some(record.clone());
+        }
+        if self.exists(&key) {
+            return None;
+        }
+
+        // Only the wildcard of the closest encloser, the nearest ancestor
+        // that exists, covers the domain (RFC 4592): `*.app.example.com`
+        // covers `a.b.app.example.com`, unless `b.app.example.com` exists
+        let mut parent = key.as_str();
+        while let Some((_, rest)) = parent.split_once('.') {
+            if self.exists(rest) {
+                return self.records.get(&format!("*.{rest}")).cloned();
+            }
+            parent = rest;
+        }
+        None
+    }
+
+    /// Whether a name exists in the store, either with a record of its own
+    /// or as an empty non-terminal with records below it
+    fn exists(&self, name: &str) -> bool {
+        self.names.contains_key(name)
+    }
+
+    /// Public and formnet addresses of the record an ANAME record points
+    /// to, following ANAME records up to `MAX_ALIAS_DEPTH` deep
+    fn alias_addresses(&self, record: &FormDnsRecord) -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+        let mut target = record.cname_target.clone();
+        for _ in 0..MAX_ALIAS_DEPTH {
+            let Some(name) = target else {
+                break;
+            };
+            match self.find(&name) {
+                Some(rec) if rec.record_type == RecordType::ANAME => target = rec.cname_target,
+                Some(rec) => return (rec.public_ip, rec.formnet_ip),
+                None => break,
+            }
+        }
+
+        log::warn!("ANAME record of {} does not resolve to a Formation record", record.domain);
+        (vec![], vec![])
+    }
+
     pub fn lookup(&self, domain: &str, src: IpAddr) -> FormTarget {
-        let key = domain.trim_end_matches('.').to_lowercase(); 
-        let record = self.records.get(&key);
-        if let Some(rec) = record {
+        let record = self.resolve(domain);
+        if let Some(rec) = record.as_ref() {
             match rec.record_type {
-                RecordType::A => {
+                RecordType::A | RecordType::ANAME => {
                     match src {
                         IpAddr::V4(addr) => {
                             if addr.octets()[0] == 10 {
@@ -146,6 +462,26 @@ impl DnsStore {
                         }
                     }
                 }
+                RecordType::TXT => {
+                    if !rec.txt.is_empty() {
+                        return FormTarget::TXT(rec.txt.clone())
+                    }
+                }
+                RecordType::MX => {
+                    if !rec.mx.is_empty() {
+                        return FormTarget::MX(rec.mx.clone())
+                    }
+                }
+                RecordType::SRV => {
+                    if !rec.srv.is_empty() {
+                        return FormTarget::SRV(rec.srv.clone())
+                    }
+                }
+                RecordType::CAA => {
+                    if !rec.caa.is_empty() {
+                        return FormTarget::CAA(rec.caa.clone())
+                    }
+                }
                 _ => return FormTarget::None
             }
         }
@@ -157,11 +493,20 @@ impl DnsStore {
     }
 
     pub fn remove(&mut self, domain: &str) -> Option<FormDnsRecord> {
-        self.records.remove(domain)
+        let record = self.records.remove(domain)?;
+        for name in self_and_ancestors(domain) {
+            if let Some(count) = self.names.get_mut(name) {
+                *count -= 1;
+                if *count == 0 {
+                    self.names.remove(name);
+                }
+            }
+        }
+        Some(record)
     }
 
-    pub fn entry(&mut self, domain: &str) -> Entry<'_, String, FormDnsRecord> {
-        self.records.entry(domain.to_string())
+    pub fn get_mut(&mut self, domain: &str) -> Option<&mut FormDnsRecord> {
+        self.records.get_mut(domain)
     }
 
     pub fn iter(&self) -> Iter<String, FormDnsRecord> {
@@ -382,3 +727,178 @@ pub struct DnsConfiguration {
 }
 
 pub type SharedStore = Arc<RwLock<DnsStore>>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(domain: &str, record_type: RecordType) -> FormDnsRecord {
+        FormDnsRecord {
+            domain: domain.to_string(),
+            record_type,
+            public_ip: vec![],
+            formnet_ip: vec![],
+            cname_target: None,
+            ssl_cert: false,
+            ttl: 3600,
+            verification_status: None,
+            verification_timestamp: None,
+            txt: vec![],
+            mx: vec![],
+            srv: vec![],
+            caa: vec![],
+            routing: RoutingPolicy::default(),
+        }
+    }
+
+    #[test]
+    fn test_parse_record_data() {
+        let mx: MxRecord = "10 mail.example.com".parse().unwrap();
+        assert_eq!(mx, MxRecord { preference: 10, exchange: "mail.example.com".to_string() });
+        assert_eq!(mx.to_string(), "10 mail.example.com");
+        assert!("mail.example.com".parse::<MxRecord>().is_err());
+
+        let srv: SrvRecord = "10 5 5060 sip.example.com".parse().unwrap();
+        assert_eq!(srv.port, 5060);
+        assert_eq!(srv.to_string().parse::<SrvRecord>().unwrap(), srv);
+        assert!("10 5 sip.example.com".parse::<SrvRecord>().is_err());
+
+        let caa: CaaRecord = "128 issue \"letsencrypt.org; accounturi=https://acme.example/1\"".parse().unwrap();
+        assert!(caa.critical);
+        assert_eq!(caa.tag, "issue");
+        assert_eq!(caa.value, "letsencrypt.org; accounturi=https://acme.example/1");
+        assert_eq!(caa.to_string().parse::<CaaRecord>().unwrap(), caa);
+        assert!("issue letsencrypt.org".parse::<CaaRecord>().is_err());
+    }
+
+    #[test]
+    fn test_rdata() {
+        let mut rec = record("example.com", RecordType::A);
+        rec.txt = vec!["v=spf1 -all".to_string(), "k".repeat(300)];
+        rec.caa = vec![
+            "0 issue letsencrypt.org".parse().unwrap(),
+            "0 iodef mailto:security@example.com".parse().unwrap(),
+            "0 contactemail security@example.com".parse().unwrap(),
+        ];
+
+        let txt = rec.rdata(RecordType::TXT);
+        assert_eq!(txt.len(), 2);
+        match &txt[1] {
+            RData::TXT(txt) => assert_eq!(txt.txt_data().len(), 2),
+            other => panic!("Expected TXT rdata, got {other:?}"),
+        }
+
+        // Tags that cannot be served are skipped
+        assert_eq!(rec.rdata(RecordType::CAA).len(), 2);
+        assert!(rec.rdata(RecordType::MX).is_empty());
+        assert!(txt_rdata("").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lookup_record_data() {
+        let mut store = DnsStore::default();
+        let mut rec = record("_sip._tcp.example.com", RecordType::SRV);
+        rec.srv = vec!["10 5 5060 sip.example.com".parse().unwrap()];
+        store.insert("_sip._tcp.example.com.", rec).await;
+
+        let src = IpAddr::V4(Ipv4Addr::new(1, 1, 1, 1));
+        assert!(matches!(
+            store.lookup("_SIP._tcp.example.com", src),
+            FormTarget::SRV(srv) if srv[0].target == "sip.example.com"
+        ));
+        assert!(matches!(store.lookup("example.com", src), FormTarget::None));
+    }
+
+    fn a_record(domain: &str, ip: [u8; 4]) -> FormDnsRecord {
+        let mut rec = record(domain, RecordType::A);
+        rec.public_ip = vec![SocketAddr::from((ip, 80))];
+        rec
+    }
+
+    fn alias(domain: &str, target: &str) -> FormDnsRecord {
+        let mut rec = record(domain, RecordType::ANAME);
+        rec.cname_target = Some(target.to_string());
+        rec
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_most_specific_wins() {
+        let mut store = DnsStore::default();
+        store.insert("*.example.com", a_record("*.example.com", [1, 1, 1, 1])).await;
+        store.insert("*.app.example.com", a_record("*.app.example.com", [2, 2, 2, 2])).await;
+        store.insert("api.app.example.com", a_record("api.app.example.com", [3, 3, 3, 3])).await;
+
+        let public_ip = |domain: &str| store.resolve(domain).map(|rec| rec.public_ip[0].ip().to_string());
+        assert_eq!(public_ip("api.app.example.com").as_deref(), Some("3.3.3.3"));
+        assert_eq!(public_ip("pr-42.app.example.com.").as_deref(), Some("2.2.2.2"));
+        assert_eq!(public_ip("a.b.app.example.com").as_deref(), Some("2.2.2.2"));
+        assert_eq!(public_ip("www.example.com").as_deref(), Some("1.1.1.1"));
+        // A wildcard does not cover names that exist, even without records
+        assert!(store.resolve("app.example.com").is_none());
+        assert!(store.resolve("example.org").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_wildcard_stops_at_closest_encloser() {
+        let mut store = DnsStore::default();
+        store.insert("*.example.com", a_record("*.example.com", [1, 1, 1, 1])).await;
+        store.insert("b.example.com", a_record("b.example.com", [2, 2, 2, 2])).await;
+        store.insert("x.c.example.com", a_record("x.c.example.com", [3, 3, 3, 3])).await;
+
+        // `b.example.com` is the closest encloser and has no wildcard
+        assert!(store.resolve("a.b.example.com").is_none());
+        // Neither has the empty non-terminal `c.example.com`
+        assert!(store.resolve("y.c.example.com").is_none());
+        assert!(store.resolve("c.example.com").is_none());
+        let rec = store.resolve("d.example.com").unwrap();
+        assert_eq!(rec.public_ip[0].ip().to_string(), "1.1.1.1");
+    }
+
+    #[tokio::test]
+    async fn test_empty_non_terminals_follow_removals() {
+        let mut store = DnsStore::default();
+        store.insert("*.example.com", a_record("*.example.com", [1, 1, 1, 1])).await;
+        store.insert("x.c.example.com", a_record("x.c.example.com", [2, 2, 2, 2])).await;
+        store.insert("y.c.example.com", a_record("y.c.example.com", [3, 3, 3, 3])).await;
+        // Replacing a record does not count its name twice
+        store.insert("y.c.example.com", a_record("y.c.example.com", [4, 4, 4, 4])).await;
+        assert!(store.resolve("c.example.com").is_none());
+
+        store.remove("x.c.example.com");
+        assert!(store.resolve("c.example.com").is_none());
+        store.remove("y.c.example.com");
+        // With nothing left below it, the wildcard covers `c.example.com` again
+        let rec = store.resolve("c.example.com").unwrap();
+        assert_eq!(rec.public_ip[0].ip().to_string(), "1.1.1.1");
+
+        // The index is rebuilt when a store is deserialized
+        store.insert("z.c.example.com", a_record("z.c.example.com", [5, 5, 5, 5])).await;
+        let store: DnsStore = serde_json::from_str(&serde_json::to_string(&store).unwrap()).unwrap();
+        assert!(store.resolve("c.example.com").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_alias_takes_target_addresses() {
+        let mut store = DnsStore::default();
+        store.insert("www.example.com", a_record("www.example.com", [1, 1, 1, 1])).await;
+        store.insert("example.com", alias("example.com", "www.example.com.")).await;
+        store.insert("example.net", alias("example.net", "example.com")).await;
+
+        let rec = store.resolve("example.net").unwrap();
+        assert_eq!(rec.domain, "example.net");
+        assert_eq!(rec.public_ip, vec![SocketAddr::from(([1, 1, 1, 1], 80))]);
+
+        // The alias follows its target when the target changes
+        store.insert("www.example.com", a_record("www.example.com", [4, 4, 4, 4])).await;
+        let src = IpAddr::V4(Ipv4Addr::new(8, 8, 8, 8));
+        assert!(matches!(
+            store.lookup("example.com", src),
+            FormTarget::A(ips) if ips == vec![SocketAddr::from(([4, 4, 4, 4], 80))]
+        ));
+
+        // Aliases pointing at each other resolve to no addresses
+        store.insert("loop.example.com", alias("loop.example.com", "loop.example.org")).await;
+        store.insert("loop.example.org", alias("loop.example.org", "loop.example.com")).await;
+        assert!(store.resolve("loop.example.com").unwrap().public_ip.is_empty());
+    }
+}
@@ -0,0 +1,440 @@
+//! DNS-over-TLS (RFC 7858) and DNS-over-HTTPS (RFC 8484) listeners.
+//!
+//! Queries arriving over either transport are handed to a `Catalog` holding
+//! the same `FormAuthority` as the UDP listener, so health filtering, geo
+//! sorting and DNSSEC apply unchanged. Certificates come from the
+//! `TlsManager` shared with the reverse proxy: `.fog` names get a local
+//! certificate, every other hostname one from Let's Encrypt.
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+use axum::{
+    body::Bytes,
+    extract::{Query, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use form_rplb::resolver::TlsManager;
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::conn::auto,
+    service::TowerToHyperService,
+};
+use serde::Deserialize;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::Mutex,
+};
+use trust_dns_proto::{
+    op::{Message, ResponseCode},
+    rr::Record,
+    serialize::binary::{BinDecodable, BinEncoder},
+};
+use trust_dns_server::{
+    authority::{Catalog, MessageRequest, MessageResponse},
+    server::{Protocol, Request, RequestHandler, ResponseHandler, ResponseInfo},
+};
+
+/// Media type of DNS messages carried over HTTPS
+pub const DNS_MESSAGE: &str = "application/dns-message";
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long a DoT connection may sit idle between queries
+const IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a client has to send the rest of a message once its length
+/// has arrived
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+/// Largest message the two byte length prefix of a DoT message can describe
+const MAX_DOT_MESSAGE: usize = u16::MAX as usize;
+/// How long to wait before accepting again when accepting a connection fails,
+/// such as when the process is out of file descriptors
+const ACCEPT_BACKOFF: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Debug)]
+pub struct EncryptedDnsConfig {
+    /// Hostnames clients connect to, each gets a certificate
+    pub hostnames: Vec<String>,
+    /// Use the production Let's Encrypt directory instead of staging
+    pub acme_production: bool,
+    pub dot_addr: SocketAddr,
+    pub doh_addr: SocketAddr,
+}
+
+impl Default for EncryptedDnsConfig {
+    fn default() -> Self {
+        Self {
+            hostnames: vec!["dns.fog".to_string()],
+            acme_production: false,
+            dot_addr: ([0, 0, 0, 0], 853).into(),
+            // 443 belongs to the reverse proxy
+            doh_addr: ([0, 0, 0, 0], 8443).into(),
+        }
+    }
+}
+
+impl EncryptedDnsConfig {
+    /// Reads `DNS_TLS_HOSTNAMES`, `DNS_ACME_PRODUCTION`, `DNS_DOT_PORT` and
+    /// `DNS_DOH_PORT`, keeping the defaults for anything unset or invalid.
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Ok(hostnames) = std::env::var("DNS_TLS_HOSTNAMES") {
+            config.hostnames = hostnames.split(',')
+                .map(|h| h.trim().trim_end_matches('.').to_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect();
+        }
+        if let Ok(production) = std::env::var("DNS_ACME_PRODUCTION") {
+            config.acme_production = production == "1" || production.eq_ignore_ascii_case("true");
+        }
+        if let Some(port) = std::env::var("DNS_DOT_PORT").ok().and_then(|p| p.parse().ok()) {
+            config.dot_addr.set_port(port);
+        }
+        if let Some(port) = std::env::var("DNS_DOH_PORT").ok().and_then(|p| p.parse().ok()) {
+            config.doh_addr.set_port(port);
+        }
+        config
+    }
+}
+
+/// Collects the encoded response the catalog sends for a single query
+#[derive(Clone, Default)]
+struct BufferedResponse(Arc<std::sync::Mutex<Option<Vec<u8>>>>);
+
+impl BufferedResponse {
+    fn take(&self) -> Option<Vec<u8>> {
+        self.0.lock().ok().and_then(|mut guard| guard.take())
+    }
+}
+
+#[async_trait::async_trait]
+impl ResponseHandler for BufferedResponse {
+    async fn send_response<'a>(
+        &mut self,
+        response: MessageResponse<
+            '_,
+            'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+            impl Iterator<Item = &'a Record> + Send + 'a,
+        >,
+    ) -> std::io::Result<ResponseInfo> {
+        let mut buffer = Vec::with_capacity(512);
+        let info = {
+            let mut encoder = BinEncoder::new(&mut buffer);
+            response.destructive_emit(&mut encoder)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?
+        };
+        if let Ok(mut guard) = self.0.lock() {
+            *guard = Some(buffer);
+        }
+        Ok(info)
+    }
+}
+
+/// Answers one wire-format query through the catalog. Returns `None` when
+/// the query cannot be parsed.
+pub async fn handle_query(catalog: &Catalog, query: &[u8], src: SocketAddr) -> Option<Vec<u8>> {
+    let message = match MessageRequest::from_bytes(query) {
+        Ok(message) => message,
+        Err(e) => {
+            log::warn!("Dropping malformed query from {src}: {e}");
+            return None;
+        }
+    };
+
+    // Both transports carry whole messages like TCP does, so answers are
+    // never truncated
+    let request = Request::new(message, src, Protocol::Tcp);
+    let response = BufferedResponse::default();
+    catalog.handle_request(&request, response.clone()).await;
+    response.take()
+}
+
+/// Starts the DoT and DoH listeners. Hostnames are added to the TLS manager
+/// first so their certificates are requested before clients connect.
+pub async fn serve_encrypted(
+    catalog: Catalog,
+    tls_manager: Arc<Mutex<TlsManager>>,
+    config: EncryptedDnsConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    {
+        let mut guard = tls_manager.lock().await;
+        for hostname in &config.hostnames {
+            if guard.domains.contains_key(hostname) {
+                continue;
+            }
+            if let Err(e) = guard.add_domain(hostname.clone(), config.acme_production).await {
+                log::error!("Unable to set up a certificate for {hostname}: {e}");
+            }
+        }
+    }
+
+    let catalog = Arc::new(catalog);
+    let dot_listener = TcpListener::bind(config.dot_addr).await?;
+    log::info!("DNS-over-TLS listening on {}", config.dot_addr);
+    let doh_listener = TcpListener::bind(config.doh_addr).await?;
+    log::info!("DNS-over-HTTPS listening on {}", config.doh_addr);
+
+    loop {
+        tokio::select! {
+            accepted = dot_listener.accept() => {
+                let Some((stream, peer)) = accepted_or_backoff("DoT", accepted).await else {
+                    continue;
+                };
+                let catalog = catalog.clone();
+                let tls_manager = tls_manager.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_dot(catalog, tls_manager, stream, peer).await {
+                        log::debug!("DoT connection from {peer} ended: {e}");
+                    }
+                });
+            }
+            accepted = doh_listener.accept() => {
+                let Some((stream, peer)) = accepted_or_backoff("DoH", accepted).await else {
+                    continue;
+                };
+                let catalog = catalog.clone();
+                let tls_manager = tls_manager.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = handle_doh(catalog, tls_manager, stream, peer).await {
+                        log::debug!("DoH connection from {peer} ended: {e}");
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// The accepted connection, or `None` after logging the error and backing
+/// off, so a failing listener doesn't spin
+async fn accepted_or_backoff(
+    protocol: &str,
+    accepted: std::io::Result<(TcpStream, SocketAddr)>,
+) -> Option<(TcpStream, SocketAddr)> {
+    match accepted {
+        Ok(accepted) => Some(accepted),
+        Err(e) => {
+            log::warn!("Failed to accept {protocol} connection: {e}");
+            tokio::time::sleep(ACCEPT_BACKOFF).await;
+            None
+        }
+    }
+}
+
+/// Completes the TLS handshake with the manager's current certificates.
+/// Returns `None` for ACME TLS-ALPN challenges, which the acceptor answers.
+async fn accept_tls(
+    tls_manager: &Mutex<TlsManager>,
+    stream: TcpStream,
+    alpn: &[&[u8]],
+) -> Result<Option<impl AsyncRead + AsyncWrite + Unpin + Send + 'static>, Box<dyn std::error::Error + Send + Sync>> {
+    // The manager swaps its acceptor and config when domains are added, so
+    // read them per connection and don't hold the lock over the handshake
+    let (acceptor, config) = {
+        let guard = tls_manager.lock().await;
+        let mut config = (*guard.config).clone();
+        config.alpn_protocols = alpn.iter().map(|p| p.to_vec()).collect();
+        (guard.acceptor.clone(), Arc::new(config))
+    };
+
+    let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, async move {
+        match acceptor.accept(stream).await {
+            Ok(Some(start)) => start.into_stream(config).await.map(Some),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }).await??;
+
+    Ok(handshake)
+}
+
+async fn handle_dot(
+    catalog: Arc<Catalog>,
+    tls_manager: Arc<Mutex<TlsManager>>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(mut stream) = accept_tls(&tls_manager, stream, &[b"dot"]).await? else {
+        return Ok(());
+    };
+
+    // Every message is prefixed with its length, as on TCP (RFC 7766)
+    loop {
+        let len = match tokio::time::timeout(IDLE_TIMEOUT, stream.read_u16()).await {
+            Ok(Ok(len)) => len as usize,
+            Ok(Err(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(()),
+            Ok(Err(e)) => return Err(e.into()),
+            Err(_) => return Ok(()),
+        };
+        let mut query = vec![0u8; len];
+        tokio::time::timeout(READ_TIMEOUT, stream.read_exact(&mut query)).await??;
+
+        let Some(mut answer) = handle_query(&catalog, &query, peer).await else {
+            return Ok(());
+        };
+        if answer.len() > MAX_DOT_MESSAGE {
+            log::warn!("Answer of {} bytes to {peer} is too large for DoT, answering SERVFAIL", answer.len());
+            let Some(servfail) = servfail(&query) else {
+                return Ok(());
+            };
+            answer = servfail;
+        }
+        stream.write_u16(answer.len() as u16).await?;
+        stream.write_all(&answer).await?;
+        stream.flush().await?;
+    }
+}
+
+/// SERVFAIL answer to a query, sent over DoT in place of an answer too large
+/// to frame. Setting TC instead would only send the client on to TCP, where
+/// the answer is just as large.
+fn servfail(query: &[u8]) -> Option<Vec<u8>> {
+    let query = Message::from_vec(query).ok()?;
+    let mut answer = Message::error_msg(query.id(), query.op_code(), ResponseCode::ServFail);
+    answer.set_recursion_desired(query.recursion_desired());
+    answer.add_queries(query.queries().to_vec());
+    answer.to_vec().ok()
+}
+
+#[derive(Clone)]
+struct DohState {
+    catalog: Arc<Catalog>,
+    peer: SocketAddr,
+}
+
+#[derive(Deserialize)]
+struct DohQuery {
+    dns: String,
+}
+
+fn doh_routes() -> Router<DohState> {
+    Router::new()
+        .route("/dns-query", get(doh_get).post(doh_post))
+}
+
+async fn handle_doh(
+    catalog: Arc<Catalog>,
+    tls_manager: Arc<Mutex<TlsManager>>,
+    stream: TcpStream,
+    peer: SocketAddr,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let Some(stream) = accept_tls(&tls_manager, stream, &[b"h2", b"http/1.1"]).await? else {
+        return Ok(());
+    };
+
+    let service = TowerToHyperService::new(doh_routes().with_state(DohState { catalog, peer }));
+    auto::Builder::new(TokioExecutor::new())
+        .serve_connection(TokioIo::new(stream), service)
+        .await
+}
+
+async fn doh_get(
+    State(state): State<DohState>,
+    Query(query): Query<DohQuery>,
+) -> Response {
+    match decode_get_param(&query.dns) {
+        Some(query) => doh_answer(&state, &query).await,
+        None => (StatusCode::BAD_REQUEST, "dns parameter is not base64url").into_response(),
+    }
+}
+
+async fn doh_post(
+    State(state): State<DohState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if content_type != Some(DNS_MESSAGE) {
+        return (StatusCode::UNSUPPORTED_MEDIA_TYPE, format!("Expected {DNS_MESSAGE}")).into_response();
+    }
+    doh_answer(&state, &body).await
+}
+
+async fn doh_answer(state: &DohState, query: &[u8]) -> Response {
+    let Some(answer) = handle_query(&state.catalog, query, state.peer).await else {
+        return (StatusCode::BAD_REQUEST, "Malformed DNS message").into_response();
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, DNS_MESSAGE.to_string()),
+            (header::CACHE_CONTROL, format!("max-age={}", cache_max_age(&answer))),
+        ],
+        answer,
+    ).into_response()
+}
+
+/// Decodes the `dns` parameter of a GET request, base64url without padding
+fn decode_get_param(param: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(param.trim_end_matches('=')).ok()
+}
+
+/// Freshness of an answer over HTTP, the smallest TTL it holds (RFC 8484
+/// section 5.1). Negative answers use the SOA's TTL from the authority
+/// section.
+fn cache_max_age(answer: &[u8]) -> u32 {
+    let Ok(message) = Message::from_vec(answer) else {
+        return 0;
+    };
+    message.answers().iter()
+        .chain(message.name_servers())
+        .map(|record| record.ttl())
+        .min()
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+    use trust_dns_proto::op::{MessageType, Query as DnsQuery};
+    use trust_dns_proto::rr::{rdata::A, Name, RData, RecordType};
+    use trust_dns_proto::serialize::binary::BinEncodable;
+
+    #[test]
+    fn test_decode_get_param() {
+        // The example query of RFC 8484 section 4.1.1, www.example.com A
+        let query = decode_get_param("AAABAAABAAAAAAAAA3d3dwdleGFtcGxlA2NvbQAAAQAB").unwrap();
+        let message = Message::from_vec(&query).unwrap();
+        assert_eq!(message.queries()[0].name(), &Name::from_str("www.example.com.").unwrap());
+        assert_eq!(message.queries()[0].query_type(), RecordType::A);
+
+        // Padding is tolerated, standard base64 is not
+        assert!(decode_get_param("AAABAAABAAAAAAAAA3d3dwdleGFtcGxlA2NvbQAAAQAB==").is_some());
+        assert!(decode_get_param("a+b/").is_none());
+    }
+
+    #[test]
+    fn test_cache_max_age() {
+        let name = Name::from_str("www.example.com.").unwrap();
+        let mut message = Message::new();
+        message.set_message_type(MessageType::Response);
+        message.add_query(DnsQuery::query(name.clone(), RecordType::A));
+        assert_eq!(cache_max_age(&message.to_bytes().unwrap()), 0);
+
+        message.add_answer(Record::from_rdata(name.clone(), 300, RData::A(A::new(192, 0, 2, 1))));
+        message.add_answer(Record::from_rdata(name, 60, RData::A(A::new(192, 0, 2, 2))));
+        assert_eq!(cache_max_age(&message.to_bytes().unwrap()), 60);
+
+        assert_eq!(cache_max_age(b"not dns"), 0);
+    }
+
+    #[test]
+    fn test_servfail() {
+        let mut query = Message::new();
+        query.set_id(42);
+        query.add_query(DnsQuery::query(Name::from_str("www.example.com.").unwrap(), RecordType::A));
+
+        let answer = Message::from_vec(&servfail(&query.to_bytes().unwrap()).unwrap()).unwrap();
+        assert_eq!(answer.id(), 42);
+        assert_eq!(answer.message_type(), MessageType::Response);
+        assert_eq!(answer.response_code(), ResponseCode::ServFail);
+        assert_eq!(answer.queries(), query.queries());
+        assert!(answer.answers().is_empty());
+
+        assert!(servfail(b"not dns").is_none());
+    }
+}
@@ -3,12 +3,15 @@
 // This module contains handlers for operation-related API endpoints,
 // such as checking the status of long-running operations.
 
-use actix_web::{web, HttpResponse, Responder};
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse, Responder};
+use futures_util::stream;
 use serde::Serialize;
 use std::sync::Arc;
+use tokio::sync::broadcast::error::RecvError;
 
 use crate::api::handlers::ApiResponse;
-use crate::models::operations::OperationsRepository;
+use crate::auth::AuthData;
+use crate::models::operations::{Operation, OperationsRepository};
 
 /// Data structure for operation status response
 #[derive(Serialize)]
@@ -18,6 +21,8 @@ pub struct OperationStatus {
     pub progress: Option<f32>,
     pub result: Option<serde_json::Value>,
     pub error: Option<String>,
+    pub message: Option<String>,
+    pub logs: Vec<crate::models::operations::OperationLog>,
 }
 
 /// Handler for checking the status of a long-running operation
@@ -79,4 +84,146 @@ pub async fn list_operations(
     HttpResponse::Ok().json(ApiResponse::success(OperationListResponse {
         operations: operation_statuses,
     }))
-} 
\ No newline at end of file
+}
+
+/// Whether the caller may act on an operation, which only its owner and
+/// admins can. Requests carry no authentication data while authentication
+/// is disabled, and are then treated as admin, as MCP clients are.
+fn may_access(req: &HttpRequest, operation: &Operation) -> bool {
+    match req.extensions().get::<AuthData>() {
+        Some(auth) => auth.user_id == operation.user_id
+            || auth.permissions.iter().any(|permission| permission == "admin"),
+        None => true,
+    }
+}
+
+fn forbidden(operation_id: &str) -> HttpResponse {
+    HttpResponse::Forbidden().json(ApiResponse::<()>::error(
+        format!("Not allowed to access operation with ID '{}'", operation_id)
+    ))
+}
+
+/// Handler for cancelling a long-running operation
+pub async fn cancel_operation(
+    req: HttpRequest,
+    repository: web::Data<Arc<OperationsRepository>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let operation_id = path.into_inner();
+    
+    match repository.get_operation(&operation_id).await {
+        Some(operation) if !may_access(&req, &operation) => return forbidden(&operation_id),
+        Some(_) => {},
+        None => return HttpResponse::NotFound().json(ApiResponse::<()>::error(
+            format!("Operation with ID '{}' not found", operation_id)
+        )),
+    }
+    
+    match repository.cancel(&operation_id).await {
+        Ok(operation) => HttpResponse::Ok().json(ApiResponse::success(operation.to_api_response())),
+        // The operation finished before it could be cancelled
+        Err(e) => HttpResponse::Conflict().json(ApiResponse::<()>::error(e)),
+    }
+}
+
+/// Handler streaming the progress and logs of an operation as server-sent
+/// events. The current state is sent first, then every change, and the
+/// stream ends once the operation has finished.
+pub async fn stream_operation_events(
+    req: HttpRequest,
+    repository: web::Data<Arc<OperationsRepository>>,
+    path: web::Path<String>,
+) -> impl Responder {
+    let operation_id = path.into_inner();
+    
+    // Subscribe before reading the current state, so no change is missed
+    let events = repository.subscribe();
+    let operation = match repository.get_operation(&operation_id).await {
+        Some(operation) => operation,
+        None => return HttpResponse::NotFound().json(ApiResponse::<()>::error(
+            format!("Operation with ID '{}' not found", operation_id)
+        )),
+    };
+    if !may_access(&req, &operation) {
+        return forbidden(&operation_id);
+    }
+    
+    let state = OperationEvents {
+        events,
+        repository: repository.get_ref().clone(),
+        operation_id,
+        pending: Some(operation),
+        done: false,
+    };
+    let body = stream::unfold(state, |mut state| async move {
+        if state.done {
+            return None;
+        }
+        let operation = match state.pending.take() {
+            Some(operation) => operation,
+            None => state.next_update().await?,
+        };
+        
+        // A finished operation ends the stream after its final event
+        state.done = operation.is_finished();
+        let event = web::Bytes::from(format!(
+            "event: operation\ndata: {}\n\n",
+            serde_json::to_string(&operation.to_api_response()).unwrap_or_default(),
+        ));
+        Some((Ok::<_, actix_web::Error>(event), state))
+    });
+    
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
+
+/// State of a stream of operation events
+struct OperationEvents {
+    events: tokio::sync::broadcast::Receiver<Operation>,
+    repository: Arc<OperationsRepository>,
+    operation_id: String,
+    pending: Option<Operation>,
+    done: bool,
+}
+
+impl OperationEvents {
+    /// Wait for the next change of the operation
+    async fn next_update(&mut self) -> Option<Operation> {
+        loop {
+            match self.events.recv().await {
+                Ok(operation) if operation.id == self.operation_id => return Some(operation),
+                Ok(_) => continue,
+                // Changes were missed, so catch up with the current state
+                Err(RecvError::Lagged(_)) => return self.repository.get_operation(&self.operation_id).await,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+    
+    fn request_from(user_id: &str, permissions: &[&str]) -> HttpRequest {
+        let req = TestRequest::default().to_http_request();
+        req.extensions_mut().insert(AuthData {
+            user_id: user_id.to_string(),
+            permissions: permissions.iter().map(|p| p.to_string()).collect(),
+        });
+        req
+    }
+    
+    #[test]
+    fn test_only_owner_or_admin_may_access() {
+        let operation = Operation::new("alice".to_string(), "deploy".to_string());
+        
+        assert!(may_access(&request_from("alice", &[]), &operation));
+        assert!(may_access(&request_from("root", &["admin"]), &operation));
+        assert!(!may_access(&request_from("mallory", &[]), &operation));
+        assert!(!may_access(&request_from("mallory", &["operations:cancel"]), &operation));
+    }
+}
@@ -7,13 +7,11 @@ use actix_web::{web, HttpResponse, Responder};
 use serde::Deserialize;
 use std::sync::Arc;
 use uuid::Uuid;
-use tokio::task;
-use serde_json::json;
 
 use crate::tools::{ToolRegistry, ToolRequest, ToolContext, ToolResponse};
 use crate::api::handlers::ApiResponse;
 use crate::errors::ToolError;
-use crate::models::operations::{OperationsRepository, Operation};
+use crate::models::operations::OperationsRepository;
 
 /// Query parameters for tool listing
 #[derive(Deserialize, Default)]
@@ -103,65 +101,24 @@ pub async fn execute_tool(
         request_id: Uuid::new_v4().to_string(),
         context: req.context.clone().unwrap_or_default(),
         is_admin: true, // Placeholder, would come from auth
+        operation: None,
     };
     
     // Check if the tool is marked as long running
     let is_long_running = tool.definition().is_long_running.unwrap_or(false);
     
     if is_long_running {
-        // Create an operation to track the tool execution
-        let operation = Operation::new(context.user_id.clone(), tool_name.clone());
-        let operation_id = operation.id.clone();
-        
-        // Store the operation
-        operations_repo.add_operation(operation).await;
-        
-        // Clone dependencies for async task
-        let registry_clone = registry.get_ref().clone();
-        let tool_request_clone = tool_request.clone();
-        let context_clone = context.clone();
-        let operations_repo_clone = operations_repo.get_ref().clone();
-        let operation_id_clone = operation_id.clone();
-        
-        // Spawn an async task to execute the tool
-        task::spawn(async move {
-            // Get the operation and mark it as running
-            if let Some(mut operation) = operations_repo_clone.get_operation(&operation_id_clone).await {
-                operation.mark_running();
-                if let Err(e) = operations_repo_clone.update_operation(operation).await {
-                    log::error!("Failed to update operation status to running: {}", e);
-                    return;
-                }
-                
-                // Execute the tool
-                let result = crate::tools::execute_tool(registry_clone, tool_request_clone, context_clone).await;
-                
-                // Update operation with result
-                if let Some(mut operation) = operations_repo_clone.get_operation(&operation_id_clone).await {
-                    match result {
-                        Ok(response) => {
-                            operation.mark_completed(json!(response));
-                            if let Err(e) = operations_repo_clone.update_operation(operation).await {
-                                log::error!("Failed to update operation status to completed: {}", e);
-                            }
-                        },
-                        Err(error) => {
-                            let error_msg = format!("Tool execution failed: {}", error);
-                            operation.mark_failed(error_msg);
-                            if let Err(e) = operations_repo_clone.update_operation(operation).await {
-                                log::error!("Failed to update operation status to failed: {}", e);
-                            }
-                        }
-                    }
-                }
-            }
-        });
+        // Execute the tool in the background as an operation
+        let operation = operations_repo
+            .spawn(registry.get_ref().clone(), tool_request, context)
+            .await;
+        let operation_id = operation.id;
         
         // Return immediate response with operation ID
         HttpResponse::Accepted().json(ApiResponse::success(AsyncToolResponse {
+            message: format!("Tool '{}' is executing as operation {}", tool_name, operation_id),
+            status: "running".to_string(),
             operation_id,
-            status: "queued".to_string(),
-            message: format!("Tool '{}' execution has been queued", tool_name),
         }))
     } else {
         // Execute the tool synchronously for non-long-running tools
@@ -12,6 +12,9 @@ use actix_web::middleware::Compress;
 use actix_cors::Cors;
 use log::info;
 use crate::config::Settings;
+use crate::events::EventService;
+use crate::mcp::McpServer;
+use crate::models::operations::OperationsRepository;
 use crate::tools::ToolRegistry;
 use crate::auth;
 
@@ -19,10 +22,21 @@ use crate::auth;
 pub async fn init_server(
     settings: Arc<Settings>,
     tool_registry: Arc<ToolRegistry>,
+    operations: Arc<OperationsRepository>,
+    events: Arc<EventService>,
 ) -> std::io::Result<()> {
+    // The MCP server shares the registry and repository, so every worker
+    // sees the same operations
+    let mcp_server_data = web::Data::new(
+        McpServer::new(tool_registry.clone(), operations.clone()).with_events(events)
+    );
+    
     // Create a tool registry data object
     let tool_registry_data = web::Data::new(tool_registry);
     
+    // Create an operations repository data object
+    let operations_data = web::Data::new(operations);
+    
     // Get server settings
     let host = settings.server.host.clone();
     let port = settings.server.port;
@@ -55,6 +69,9 @@ pub async fn init_server(
         App::new()
             // Register the tool registry
             .app_data(tool_registry_data.clone())
+            // Register the operations repository and the MCP server
+            .app_data(operations_data.clone())
+            .app_data(mcp_server_data.clone())
             // Set request timeout
             .app_data(web::PayloadConfig::new(settings.server.request_timeout as usize))
             // Enable compression
@@ -4,19 +4,19 @@
 
 use actix_web::{web, HttpResponse, Responder};
 use crate::api::health_check;
+use crate::mcp;
 use crate::api::handlers::{tools, operations, auth};
-use crate::models::operations::{OperationsRepository, create_repository};
 
 /// Configure API routes for the MCP server
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    // Create and register the operations repository
-    let operations_repository = create_repository();
-    cfg.app_data(web::Data::new(operations_repository));
-    
     cfg
         // Health check endpoint
         .route("/health", web::get().to(health_check))
         
+        // MCP JSON-RPC endpoint (streamable HTTP transport)
+        .route("/mcp", web::post().to(mcp::http::handle_post))
+        .route("/mcp", web::get().to(mcp::http::handle_get))
+        
         // MCP protocol endpoints
         .service(
             web::scope("/api")
@@ -33,6 +33,8 @@ pub fn configure(cfg: &mut web::ServiceConfig) {
                 
                 // Operation status endpoints
                 .route("/operations/{id}", web::get().to(operations::get_operation_status))
+                .route("/operations/{id}/cancel", web::post().to(operations::cancel_operation))
+                .route("/operations/{id}/events", web::get().to(operations::stream_operation_events))
                 .route("/operations", web::get().to(operations::list_operations))
         )
         
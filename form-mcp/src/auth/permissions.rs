@@ -57,9 +57,13 @@ impl Role {
         self.permissions.insert(permission);
     }
     
-    /// Check if the role has a specific permission
+    /// Check if the role has a specific permission, either directly or
+    /// through a `*` resource or action
     pub fn has_permission(&self, resource: &str, action: &str) -> bool {
-        self.permissions.contains(&Permission::new(resource, action))
+        self.permissions.iter().any(|permission| {
+            (permission.resource == "*" || permission.resource == resource)
+                && (permission.action == "*" || permission.action == action)
+        })
     }
 }
 
@@ -74,7 +78,14 @@ static ROLES: Lazy<RwLock<HashMap<String, Role>>> = Lazy::new(|| {
     
     let mut user = Role::new("user", "Standard user with limited access");
     user.add_permission(Permission::new("vm", "read"));
-    user.add_permission(Permission::new("network", "read"));
+    // No "network" permissions: formnet peers, CIDRs and associations
+    // describe every node and user on the network, so only admins read them
+    user.add_permission(Permission::new("metrics", "read"));
+    user.add_permission(Permission::new("dns", "read"));
+    user.add_permission(Permission::new("dns", "request"));
+    user.add_permission(Permission::new("events", "read"));
+    user.add_permission(Permission::new("events", "subscribe"));
+    user.add_permission(Permission::new("billing", "read"));
     roles.insert("user".to_string(), user);
     
     RwLock::new(roles)
@@ -0,0 +1,82 @@
+// Invoicing
+//
+// This module prices the usage of a user over a billing period into an
+// invoice, with a line per resource type.
+
+use serde::{Serialize, Deserialize};
+
+use crate::config::BillingRates;
+use super::{UsageTotal, GPU, INFERENCE_TOKENS, STORAGE, TOOL_EXECUTION, VM};
+
+/// Currency invoices are drawn up in
+pub const CURRENCY: &str = "credits";
+
+/// Usage of one resource type on an invoice
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct InvoiceLine {
+    /// Resource type
+    pub resource_type: String,
+    /// Usage unit
+    pub unit: String,
+    /// Usage across every resource of the type
+    pub usage: f64,
+    /// Price of one unit
+    pub unit_price: f64,
+    /// Price of the usage
+    pub amount: f64,
+}
+
+/// Priced usage of a user over a billing period
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Invoice {
+    /// User the invoice is for
+    pub user_id: String,
+    /// Billing period (`YYYY-MM`)
+    pub period: String,
+    /// Usage per resource type
+    pub lines: Vec<InvoiceLine>,
+    /// Price of all usage
+    pub total: f64,
+    /// Currency of the prices
+    pub currency: String,
+}
+
+/// Price of one unit of a resource type
+pub fn unit_price(rates: &BillingRates, resource_type: &str) -> f64 {
+    match resource_type {
+        VM => rates.vm_hour,
+        STORAGE => rates.storage_gb_hour,
+        GPU => rates.gpu_hour,
+        INFERENCE_TOKENS => rates.thousand_tokens / 1000.0,
+        TOOL_EXECUTION => rates.tool_execution,
+        _ => 0.0,
+    }
+}
+
+/// Draw up the invoice of a user's usage over a period
+pub fn invoice(user_id: &str, period: &str, usage: &[UsageTotal], rates: &BillingRates) -> Invoice {
+    let mut lines: Vec<InvoiceLine> = Vec::new();
+    for total in usage {
+        match lines.iter_mut().find(|line| line.resource_type == total.resource_type && line.unit == total.unit) {
+            Some(line) => line.usage += total.usage,
+            None => lines.push(InvoiceLine {
+                resource_type: total.resource_type.clone(),
+                unit: total.unit.clone(),
+                usage: total.usage,
+                unit_price: unit_price(rates, &total.resource_type),
+                amount: 0.0,
+            }),
+        }
+    }
+    for line in &mut lines {
+        line.amount = line.usage * line.unit_price;
+    }
+
+    Invoice {
+        user_id: user_id.to_string(),
+        period: period.to_string(),
+        total: lines.iter().map(|line| line.amount).sum(),
+        lines,
+        currency: CURRENCY.to_string(),
+    }
+}
@@ -0,0 +1,162 @@
+// Billing ledger
+//
+// This module keeps the billing records metered by form-mcp. Records are
+// appended to a JSON Lines file, one record per line, and summed in memory
+// per user, month and resource, so summaries and invoices never have to read
+// the file back.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::RwLock;
+
+use super::BillingRecord;
+
+/// Key usage is summed under
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct UsageKey {
+    user_id: String,
+    period: String,
+    resource_type: String,
+    resource_id: String,
+    unit: String,
+}
+
+impl UsageKey {
+    fn of(record: &BillingRecord) -> Self {
+        Self {
+            user_id: record.user_id.clone(),
+            period: record.period(),
+            resource_type: record.resource_type.clone(),
+            resource_id: record.resource_id.clone(),
+            unit: record.unit.clone(),
+        }
+    }
+}
+
+/// Usage of one resource by a user over a period
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct UsageTotal {
+    /// Resource type
+    pub resource_type: String,
+    /// Resource ID
+    pub resource_id: String,
+    /// Usage unit
+    pub unit: String,
+    /// Usage amount
+    pub usage: f64,
+}
+
+/// BillingLedger keeps billing records, optionally on disk
+#[derive(Debug, Default)]
+pub struct BillingLedger {
+    totals: RwLock<BTreeMap<UsageKey, f64>>,
+    path: Option<PathBuf>,
+}
+
+impl BillingLedger {
+    /// Create an in-memory ledger
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the ledger stored at `path`, which is created on the first
+    /// record if it does not exist. A line that cannot be read, such as one
+    /// a crash left half written, is skipped.
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let mut totals = BTreeMap::new();
+        match std::fs::read_to_string(&path) {
+            Ok(content) => {
+                for (number, line) in content.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+                    match serde_json::from_str::<BillingRecord>(line) {
+                        Ok(record) => *totals.entry(UsageKey::of(&record)).or_insert(0.0) += record.usage,
+                        Err(e) => log::warn!("Skipping line {} of billing ledger {}: {}", number + 1, path.display(), e),
+                    }
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+            }
+            Err(e) => return Err(e),
+        }
+
+        Ok(Self {
+            totals: RwLock::new(totals),
+            path: Some(path),
+        })
+    }
+
+    /// Record billing records. They are written to disk before they are
+    /// counted, so a record that could not be stored is never billed.
+    pub async fn record(&self, records: &[BillingRecord]) -> io::Result<()> {
+        if records.is_empty() {
+            return Ok(());
+        }
+
+        let mut totals = self.totals.write().await;
+        if let Some(path) = &self.path {
+            let mut lines = Vec::new();
+            for record in records {
+                serde_json::to_writer(&mut lines, record)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                lines.push(b'\n');
+            }
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(&lines).await?;
+            file.flush().await?;
+        }
+
+        for record in records {
+            *totals.entry(UsageKey::of(record)).or_insert(0.0) += record.usage;
+        }
+        Ok(())
+    }
+
+    /// Usage of every resource of a user over a period (`YYYY-MM`)
+    pub async fn usage(&self, user_id: &str, period: &str) -> Vec<UsageTotal> {
+        let totals = self.totals.read().await;
+        totals.iter()
+            .filter(|(key, _)| key.user_id == user_id && key.period == period)
+            .map(|(key, usage)| UsageTotal {
+                resource_type: key.resource_type.clone(),
+                resource_id: key.resource_id.clone(),
+                unit: key.unit.clone(),
+                usage: *usage,
+            })
+            .collect()
+    }
+
+    /// Usage of one resource of a user over a period, in any unit
+    pub async fn total(&self, user_id: &str, period: &str, resource_type: &str, resource_id: &str) -> f64 {
+        let totals = self.totals.read().await;
+        totals.iter()
+            .filter(|(key, _)| {
+                key.user_id == user_id
+                    && key.period == period
+                    && key.resource_type == resource_type
+                    && key.resource_id == resource_id
+            })
+            .map(|(_, usage)| usage)
+            .sum()
+    }
+
+    /// Users with usage over a period
+    pub async fn users(&self, period: &str) -> Vec<String> {
+        let totals = self.totals.read().await;
+        let mut users: Vec<String> = totals.keys()
+            .filter(|key| key.period == period)
+            .map(|key| key.user_id.clone())
+            .collect();
+        users.dedup();
+        users
+    }
+}
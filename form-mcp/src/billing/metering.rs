@@ -0,0 +1,84 @@
+// Usage metering
+//
+// This module turns what form-state knows about users' resources into
+// billing records: the VM-hours, storage and GPU-hours of running instances,
+// and the inference tokens the usage tracker of each account recorded.
+
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use super::{BillingRecord, GPU, STORAGE, VM};
+
+/// Usage an instance accrued over the last `hours`. Only running instances
+/// accrue usage; the owner is billed for it.
+pub fn instance_usage(instance: &Value, hours: f64, timestamp: DateTime<Utc>) -> Vec<BillingRecord> {
+    if hours <= 0.0 || instance["status"] != "Started" {
+        return Vec::new();
+    }
+    let (Some(owner), Some(instance_id)) = (
+        instance["instance_owner"].as_str(),
+        instance["instance_id"].as_str(),
+    ) else {
+        return Vec::new();
+    };
+
+    let mut records = vec![BillingRecord::new(owner, instance_id, VM, hours, "hour", timestamp)];
+    if let Some(disk_gb) = disk_gb(instance) {
+        records.push(BillingRecord::new(owner, instance_id, STORAGE, disk_gb as f64 * hours, "GB-hour", timestamp));
+    }
+    let gpus = instance["resources"]["gpu"]["count"].as_u64().unwrap_or_default();
+    if gpus > 0 {
+        records.push(BillingRecord::new(owner, instance_id, GPU, gpus as f64 * hours, "hour", timestamp));
+    }
+    records
+}
+
+/// Disk size in GB the Formfile of an instance asks for. form-state keeps
+/// the Formfile as a JSON string.
+pub fn disk_gb(instance: &Value) -> Option<u64> {
+    let formfile: Value = serde_json::from_str(instance["formfile"].as_str()?).ok()?;
+    formfile["system_config"]
+        .as_array()?
+        .iter()
+        .find_map(|option| option["Disk"].as_u64())
+}
+
+/// Tokens per model the usage tracker of an account recorded over a period
+/// (`YYYY-MM`)
+pub fn tracked_tokens(account: &Value, period: &str) -> Vec<(String, u64)> {
+    let Some(models) = account["usage"]["token_usage"][period]["model_breakdown"].as_object() else {
+        return Vec::new();
+    };
+    models.iter()
+        .map(|(model_id, usage)| {
+            let tokens = usage["input_tokens"].as_u64().unwrap_or_default()
+                + usage["output_tokens"].as_u64().unwrap_or_default();
+            (model_id.clone(), tokens)
+        })
+        .collect()
+}
+
+/// Inference tokens of one model over a period, as metered by form-mcp and
+/// as tracked by form-state
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Reconciliation {
+    /// User the tokens were used by
+    pub user_id: String,
+    /// Billing period (`YYYY-MM`)
+    pub period: String,
+    /// Model the tokens were used with
+    pub model_id: String,
+    /// Tokens in the ledger before reconciling
+    pub metered: f64,
+    /// Tokens the usage tracker recorded
+    pub tracked: u64,
+}
+
+impl Reconciliation {
+    /// Tokens to record so the ledger matches form-state, which is
+    /// authoritative for token usage
+    pub fn adjustment(&self) -> f64 {
+        self.tracked as f64 - self.metered
+    }
+}
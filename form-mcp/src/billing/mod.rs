@@ -1,6 +1,43 @@
 // Billing module for the MCP server
 //
 // This module handles billing and payment integration for the MCP server.
+// Usage is metered into billing records: tool executions as they happen,
+// and the VM-hours, storage, GPU-hours and inference tokens of every user's
+// resources by sampling form-state. Token usage is reconciled against the
+// usage tracker of form-state accounts, and the quota of the requester's
+// subscription is checked before a tool creates resources.
+
+pub mod invoice;
+pub mod ledger;
+pub mod metering;
+pub mod quota;
+#[cfg(test)]
+mod tests;
+
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use tokio::sync::Mutex;
+
+use crate::config::{BillingRates, BillingSettings};
+use crate::errors::ToolError;
+use crate::tools::state::StateClient;
+
+pub use invoice::{Invoice, InvoiceLine};
+pub use ledger::{BillingLedger, UsageTotal};
+pub use metering::Reconciliation;
+pub use quota::ResourceRequest;
+
+/// Resource type of tool executions, metered in calls
+pub const TOOL_EXECUTION: &str = "tool_execution";
+/// Resource type of running instances, metered in hours
+pub const VM: &str = "vm";
+/// Resource type of instance disks, metered in GB-hours
+pub const STORAGE: &str = "storage";
+/// Resource type of instance GPUs, metered in hours
+pub const GPU: &str = "gpu";
+/// Resource type of inference tokens, metered per model in tokens
+pub const INFERENCE_TOKENS: &str = "inference_tokens";
 
 /// Represents a billing record for resource usage
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
@@ -19,4 +56,188 @@ pub struct BillingRecord {
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
-// The full billing system will be implemented in future sub-tasks 
\ No newline at end of file
+impl BillingRecord {
+    /// Create a new billing record
+    pub fn new(
+        user_id: &str,
+        resource_id: &str,
+        resource_type: &str,
+        usage: f64,
+        unit: &str,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            resource_id: resource_id.to_string(),
+            resource_type: resource_type.to_string(),
+            usage,
+            unit: unit.to_string(),
+            timestamp,
+        }
+    }
+
+    /// Billing period (`YYYY-MM`) the record falls in
+    pub fn period(&self) -> String {
+        period_of(self.timestamp)
+    }
+}
+
+/// Billing period (`YYYY-MM`) a time falls in, the calendar month also used
+/// by form-state's usage tracker
+pub fn period_of(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m").to_string()
+}
+
+/// BillingService meters usage and enforces quotas
+pub struct BillingService {
+    ledger: BillingLedger,
+    state: StateClient,
+    rates: BillingRates,
+    /// Time of the last instance sample; held while metering so rounds
+    /// never overlap
+    last_sample: Mutex<Option<DateTime<Utc>>>,
+}
+
+impl BillingService {
+    /// Create a new billing service
+    pub fn new(ledger: BillingLedger, state: StateClient, rates: BillingRates) -> Self {
+        Self {
+            ledger,
+            state,
+            rates,
+            last_sample: Mutex::new(None),
+        }
+    }
+
+    /// Open the billing service described by the settings, falling back to
+    /// an in-memory ledger if the ledger cannot be read
+    pub fn open(settings: &BillingSettings) -> Arc<Self> {
+        let ledger = BillingLedger::open(&settings.ledger_path).unwrap_or_else(|e| {
+            log::error!(
+                "Failed to open billing ledger at {}, usage will not survive a restart: {}",
+                settings.ledger_path, e
+            );
+            BillingLedger::new()
+        });
+        Arc::new(Self::new(
+            ledger,
+            StateClient::with_base_url(settings.state_url.clone()),
+            settings.rates.clone(),
+        ))
+    }
+
+    /// Ledger of the service
+    pub fn ledger(&self) -> &BillingLedger {
+        &self.ledger
+    }
+
+    /// Check that the user's quota leaves room for `request`
+    pub async fn check_quota(&self, user_id: &str, request: &ResourceRequest) -> Result<(), ToolError> {
+        let account_quota = self.state.get(&format!("/account/{}/quota", user_id), &[]).await?;
+        quota::check_quota(&account_quota, request)
+            .map_err(|e| ToolError::Forbidden(format!("Quota exceeded: {}", e)))
+    }
+
+    /// Meter a successful tool execution
+    pub async fn record_tool_execution(&self, user_id: &str, tool_name: &str) {
+        let record = BillingRecord::new(user_id, tool_name, TOOL_EXECUTION, 1.0, "call", Utc::now());
+        if let Err(e) = self.ledger.record(&[record]).await {
+            log::error!("Failed to meter execution of {} by {}: {}", tool_name, user_id, e);
+        }
+    }
+
+    /// Meter the usage instances accrued since the last sample. The first
+    /// sample only sets the baseline, so time form-mcp was down is not
+    /// billed. Returns the number of records written.
+    pub async fn sample_instances(&self) -> Result<usize, ToolError> {
+        let mut last_sample = self.last_sample.lock().await;
+        let instances = self.state.get("/instance/list", &[]).await?;
+        let now = Utc::now();
+        let Some(previous) = last_sample.replace(now) else {
+            return Ok(0);
+        };
+
+        let hours = (now - previous).to_std().map(|elapsed| elapsed.as_secs_f64()).unwrap_or_default() / 3600.0;
+        let records: Vec<BillingRecord> = instances.as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|instance| metering::instance_usage(instance, hours, now))
+            .collect();
+        self.ledger.record(&records).await
+            .map_err(|e| ToolError::ExecutionFailed(format!("Failed to record instance usage: {}", e)))?;
+        Ok(records.len())
+    }
+
+    /// Bring the metered inference tokens of the current period in line with
+    /// the usage tracker of every form-state account, recording the
+    /// difference per model
+    pub async fn reconcile_tokens(&self) -> Result<Vec<Reconciliation>, ToolError> {
+        let _metering = self.last_sample.lock().await;
+        let accounts = self.state.get("/account/list", &[]).await?;
+        let now = Utc::now();
+        let period = period_of(now);
+
+        let mut reconciliations = Vec::new();
+        let mut records = Vec::new();
+        for account in accounts.as_array().into_iter().flatten() {
+            let Some(user_id) = account["address"].as_str() else {
+                continue;
+            };
+            for (model_id, tracked) in metering::tracked_tokens(account, &period) {
+                let metered = self.ledger.total(user_id, &period, INFERENCE_TOKENS, &model_id).await;
+                let reconciliation = Reconciliation {
+                    user_id: user_id.to_string(),
+                    period: period.clone(),
+                    model_id,
+                    metered,
+                    tracked,
+                };
+                let adjustment = reconciliation.adjustment();
+                if adjustment != 0.0 {
+                    if metered > tracked as f64 {
+                        log::warn!(
+                            "Metered {} tokens of {} for {}, but form-state tracked {}",
+                            metered, reconciliation.model_id, user_id, tracked
+                        );
+                    }
+                    records.push(BillingRecord::new(
+                        user_id, &reconciliation.model_id, INFERENCE_TOKENS, adjustment, "token", now,
+                    ));
+                }
+                reconciliations.push(reconciliation);
+            }
+        }
+
+        self.ledger.record(&records).await
+            .map_err(|e| ToolError::ExecutionFailed(format!("Failed to record token usage: {}", e)))?;
+        Ok(reconciliations)
+    }
+
+    /// Usage of every resource of a user over a period
+    pub async fn usage(&self, user_id: &str, period: &str) -> Vec<UsageTotal> {
+        self.ledger.usage(user_id, period).await
+    }
+
+    /// Invoice of a user's usage over a period
+    pub async fn invoice(&self, user_id: &str, period: &str) -> Invoice {
+        invoice::invoice(user_id, period, &self.ledger.usage(user_id, period).await, &self.rates)
+    }
+
+    /// Sample instances and reconcile tokens every `interval` in the
+    /// background
+    pub fn start(self: &Arc<Self>, interval: Duration) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = service.sample_instances().await {
+                    log::warn!("Failed to sample instance usage: {}", e);
+                }
+                if let Err(e) = service.reconcile_tokens().await {
+                    log::warn!("Failed to reconcile token usage: {}", e);
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,43 @@
+// Quota enforcement
+//
+// This module checks what a tool is about to create against the compute
+// quota of the requester's subscription tier. form-state reports the quota
+// together with what the requester's instances already hold.
+
+use serde_json::Value;
+
+/// Compute a tool creates
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ResourceRequest {
+    /// vCPUs the new resources hold
+    pub vcpus: u32,
+    /// Memory in MiB the new resources hold
+    pub memory_mb: u64,
+}
+
+/// Check that an account can take on `request`, given its quota and usage
+/// as reported by form-state's `/account/{address}/quota`
+pub fn check_quota(account_quota: &Value, request: &ResourceRequest) -> Result<(), String> {
+    let tier = account_quota["tier"].as_str().unwrap_or("Free");
+    let quota = &account_quota["quota"];
+
+    let vcpus = account_quota["vcpus"].as_u64().unwrap_or_default() + request.vcpus as u64;
+    let max_vcpus = quota["max_vcpus"].as_u64().unwrap_or_default();
+    if request.vcpus > 0 && vcpus > max_vcpus {
+        return Err(format!(
+            "This needs {} vCPUs across your instances, the {} tier allows {}",
+            vcpus, tier, max_vcpus
+        ));
+    }
+
+    let memory_mb = account_quota["memory_mb"].as_u64().unwrap_or_default() + request.memory_mb;
+    let max_memory_mb = quota["max_memory_mb"].as_u64().unwrap_or_default();
+    if request.memory_mb > 0 && memory_mb > max_memory_mb {
+        return Err(format!(
+            "This needs {} MiB of memory across your instances, the {} tier allows {}",
+            memory_mb, tier, max_memory_mb
+        ));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,207 @@
+use std::sync::Arc;
+use chrono::{TimeZone, Utc};
+use serde_json::{json, Value};
+
+use crate::billing::invoice::invoice;
+use crate::billing::metering::{instance_usage, tracked_tokens};
+use crate::billing::quota::check_quota;
+use crate::billing::{
+    period_of, BillingLedger, BillingRecord, BillingService, Reconciliation, ResourceRequest, UsageTotal, GPU,
+    INFERENCE_TOKENS, STORAGE, TOOL_EXECUTION, VM,
+};
+use crate::config::BillingRates;
+use crate::tools::state::StateClient;
+use crate::tools::{execute_tool, init_registry, ToolContext, ToolRequest};
+
+fn instance(id: &str, owner: &str, status: &str) -> Value {
+    json!({
+        "instance_id": id,
+        "instance_owner": owner,
+        "status": status,
+        "formfile": json!({ "system_config": [{ "Cpu": 2 }, { "Disk": 20 }] }).to_string(),
+        "resources": { "vcpus": 2, "memory_mb": 2048, "gpu": { "count": 2, "model": "H100" } },
+    })
+}
+
+fn account(address: &str, tokens: &[(&str, u64, u64)]) -> Value {
+    let period = period_of(Utc::now());
+    let models: serde_json::Map<String, Value> = tokens.iter()
+        .map(|(model, input, output)| (model.to_string(), json!({ "input_tokens": input, "output_tokens": output })))
+        .collect();
+    json!({
+        "address": address,
+        "usage": { "token_usage": { period: { "model_breakdown": models } } },
+    })
+}
+
+fn state_response(value: Value) -> String {
+    json!({ "Success": { "Some": value } }).to_string()
+}
+
+fn state_list(values: Vec<Value>) -> String {
+    json!({ "Success": { "List": values } }).to_string()
+}
+
+fn context(user_id: &str) -> ToolContext {
+    ToolContext {
+        user_id: user_id.to_string(),
+        request_id: "request-1".to_string(),
+        context: Default::default(),
+        is_admin: false,
+        operation: None,
+    }
+}
+
+#[test]
+fn test_instance_usage() {
+    let now = Utc.with_ymd_and_hms(2026, 3, 31, 12, 0, 0).unwrap();
+    let records = instance_usage(&instance("i-1", "alice", "Started"), 0.5, now);
+
+    let usage: Vec<(&str, f64, &str)> = records.iter()
+        .map(|record| (record.resource_type.as_str(), record.usage, record.unit.as_str()))
+        .collect();
+    assert_eq!(usage, vec![(VM, 0.5, "hour"), (STORAGE, 10.0, "GB-hour"), (GPU, 1.0, "hour")]);
+    assert!(records.iter().all(|record| record.user_id == "alice" && record.resource_id == "i-1"));
+    assert_eq!(records[0].period(), "2026-03");
+
+    // Instances that are not running accrue nothing
+    assert!(instance_usage(&instance("i-2", "alice", "Stopped"), 0.5, now).is_empty());
+}
+
+#[test]
+fn test_token_reconciliation() {
+    let account = account("alice", &[("llama", 1000, 500)]);
+    assert_eq!(tracked_tokens(&account, &period_of(Utc::now())), vec![("llama".to_string(), 1500)]);
+    assert!(tracked_tokens(&account, "1999-01").is_empty());
+
+    let reconciliation = Reconciliation {
+        user_id: "alice".to_string(),
+        period: "2026-03".to_string(),
+        model_id: "llama".to_string(),
+        metered: 1000.0,
+        tracked: 1500,
+    };
+    assert_eq!(reconciliation.adjustment(), 500.0);
+}
+
+#[test]
+fn test_check_quota() {
+    let account_quota = json!({
+        "tier": "Free",
+        "quota": { "max_vcpus": 2, "max_memory_mb": 4096 },
+        "vcpus": 1,
+        "memory_mb": 1024,
+    });
+
+    assert!(check_quota(&account_quota, &ResourceRequest { vcpus: 1, memory_mb: 3072 }).is_ok());
+    let err = check_quota(&account_quota, &ResourceRequest { vcpus: 2, memory_mb: 1024 }).unwrap_err();
+    assert!(err.contains("3 vCPUs"), "{}", err);
+    assert!(check_quota(&account_quota, &ResourceRequest { vcpus: 1, memory_mb: 4096 }).is_err());
+}
+
+#[test]
+fn test_invoice() {
+    let now = Utc::now();
+    let usage = vec![
+        UsageTotal { resource_type: VM.to_string(), resource_id: "i-1".to_string(), unit: "hour".to_string(), usage: 2.0 },
+        UsageTotal { resource_type: VM.to_string(), resource_id: "i-2".to_string(), unit: "hour".to_string(), usage: 1.0 },
+        UsageTotal { resource_type: INFERENCE_TOKENS.to_string(), resource_id: "llama".to_string(), unit: "token".to_string(), usage: 5000.0 },
+    ];
+    let invoice = invoice("alice", &period_of(now), &usage, &BillingRates::default());
+
+    assert_eq!(invoice.lines.len(), 2);
+    assert_eq!(invoice.lines[0].usage, 3.0);
+    assert_eq!(invoice.lines[0].amount, 3.0);
+    assert_eq!(invoice.lines[1].amount, 5.0);
+    assert_eq!(invoice.total, 8.0);
+}
+
+#[tokio::test]
+async fn test_ledger_is_persisted() {
+    let path = std::env::temp_dir()
+        .join(format!("form-mcp-billing-{}", uuid::Uuid::new_v4()))
+        .join("billing.jsonl");
+    let march = Utc.with_ymd_and_hms(2026, 3, 1, 0, 0, 0).unwrap();
+    let april = Utc.with_ymd_and_hms(2026, 4, 1, 0, 0, 0).unwrap();
+
+    let ledger = BillingLedger::open(&path).unwrap();
+    ledger.record(&[
+        BillingRecord::new("alice", "i-1", VM, 1.5, "hour", march),
+        BillingRecord::new("alice", "i-1", VM, 0.5, "hour", march),
+        BillingRecord::new("alice", "i-1", VM, 3.0, "hour", april),
+        BillingRecord::new("bob", "vm.create", TOOL_EXECUTION, 1.0, "call", march),
+    ]).await.unwrap();
+
+    // A line a crash left half written is skipped
+    std::fs::write(&path, std::fs::read_to_string(&path).unwrap() + "{\"user_id\": \"al").unwrap();
+
+    let ledger = BillingLedger::open(&path).unwrap();
+    assert_eq!(ledger.total("alice", "2026-03", VM, "i-1").await, 2.0);
+    assert_eq!(ledger.total("alice", "2026-04", VM, "i-1").await, 3.0);
+    assert_eq!(ledger.usage("bob", "2026-03").await.len(), 1);
+    assert_eq!(ledger.users("2026-03").await, vec!["alice".to_string(), "bob".to_string()]);
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[tokio::test]
+async fn test_service_meters_form_state() {
+    let mut server = mockito::Server::new_async().await;
+    let service = BillingService::new(
+        BillingLedger::new(),
+        StateClient::with_base_url(server.url()),
+        BillingRates::default(),
+    );
+    let period = period_of(Utc::now());
+
+    server.mock("GET", "/instance/list")
+        .with_body(state_list(vec![instance("i-1", "alice", "Started")]))
+        .create_async().await;
+    // The first sample sets the baseline
+    assert_eq!(service.sample_instances().await.unwrap(), 0);
+    assert_eq!(service.sample_instances().await.unwrap(), 3);
+    assert!(service.ledger().total("alice", &period, VM, "i-1").await > 0.0);
+
+    server.mock("GET", "/account/list")
+        .with_body(state_list(vec![account("alice", &[("llama", 1000, 500)])]))
+        .create_async().await;
+    let reconciliations = service.reconcile_tokens().await.unwrap();
+    assert_eq!(reconciliations[0].adjustment(), 1500.0);
+    assert_eq!(service.ledger().total("alice", &period, INFERENCE_TOKENS, "llama").await, 1500.0);
+
+    // Once reconciled, the ledger matches form-state
+    let reconciliations = service.reconcile_tokens().await.unwrap();
+    assert_eq!(reconciliations[0].adjustment(), 0.0);
+}
+
+#[tokio::test]
+async fn test_quota_is_enforced_before_execution() {
+    let mut server = mockito::Server::new_async().await;
+    server.mock("GET", "/account/alice/quota")
+        .with_body(state_response(json!({
+            "tier": "Free",
+            "quota": { "max_vcpus": 2, "max_memory_mb": 4096 },
+            "vcpus": 2,
+            "memory_mb": 2048,
+        })))
+        .create_async().await;
+    let billing = Arc::new(BillingService::new(
+        BillingLedger::new(),
+        StateClient::with_base_url(server.url()),
+        BillingRates::default(),
+    ));
+    let registry = init_registry();
+    registry.set_billing(billing.clone());
+
+    let request = ToolRequest {
+        name: "vm.create".to_string(),
+        parameters: json!({ "name": "web", "vcpus": 1 }),
+        context: None,
+    };
+    let response = execute_tool(registry, request, context("alice")).await.unwrap();
+
+    assert_eq!(response.status, "error");
+    assert!(response.error.unwrap().contains("Quota exceeded"));
+    // Refused executions are not metered
+    assert!(billing.usage("alice", &period_of(Utc::now())).await.is_empty());
+}
@@ -5,7 +5,7 @@
 
 mod settings;
 
-pub use settings::Settings;
+pub use settings::{BillingRates, BillingSettings, EventsSettings, OperationsSettings, Settings};
 
 use std::path::Path;
 use std::sync::Arc;
@@ -79,6 +79,119 @@ impl Default for DatabaseSettings {
     }
 }
 
+/// Operations store configuration settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OperationsSettings {
+    /// Directory operations are persisted to
+    pub store_path: String,
+    /// How long finished operations are kept, in seconds
+    pub ttl_secs: u64,
+    /// How often expired operations are cleaned up, in seconds
+    pub cleanup_interval_secs: u64,
+}
+
+impl Default for OperationsSettings {
+    fn default() -> Self {
+        Self {
+            store_path: "/var/lib/formation/mcp/operations".to_string(),
+            ttl_secs: 86400,
+            cleanup_interval_secs: 300,
+        }
+    }
+}
+
+/// Event subscription configuration settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EventsSettings {
+    /// Whether the form-state change feed is followed
+    pub enabled: bool,
+    /// URL of the form-state API
+    pub state_url: String,
+    /// File subscriptions are persisted to
+    pub store_path: String,
+    /// How many times a webhook delivery is attempted
+    pub webhook_max_attempts: u32,
+    /// Timeout of a webhook delivery attempt, in seconds
+    pub webhook_timeout_secs: u64,
+    /// How many webhook deliveries are in flight at once
+    pub webhook_max_concurrency: usize,
+    /// Whether webhooks may point at loopback, link-local and private
+    /// addresses. Off by default, as users choose webhook URLs.
+    pub webhook_allow_private: bool,
+}
+
+impl Default for EventsSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            state_url: "http://127.0.0.1:3004".to_string(),
+            store_path: "/var/lib/formation/mcp/subscriptions.json".to_string(),
+            webhook_max_attempts: 5,
+            webhook_timeout_secs: 10,
+            webhook_max_concurrency: 32,
+            webhook_allow_private: false,
+        }
+    }
+}
+
+/// Billing configuration settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BillingSettings {
+    /// Whether usage is metered and quotas are enforced
+    pub enabled: bool,
+    /// URL of the form-state API
+    pub state_url: String,
+    /// File billing records are appended to
+    pub ledger_path: String,
+    /// Interval between samples of the instances in form-state, in seconds
+    pub sample_interval_secs: u64,
+    /// Prices invoices are drawn up with
+    pub rates: BillingRates,
+}
+
+impl Default for BillingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            state_url: "http://127.0.0.1:3004".to_string(),
+            ledger_path: "/var/lib/formation/mcp/billing.jsonl".to_string(),
+            sample_interval_secs: 300,
+            rates: BillingRates::default(),
+        }
+    }
+}
+
+/// Prices of metered usage, in credits
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BillingRates {
+    /// Price of a VM-hour
+    pub vm_hour: f64,
+    /// Price of a GB of storage held for an hour
+    pub storage_gb_hour: f64,
+    /// Price of a GPU-hour
+    pub gpu_hour: f64,
+    /// Price of a thousand inference tokens
+    pub thousand_tokens: f64,
+    /// Price of a tool execution
+    pub tool_execution: f64,
+}
+
+impl Default for BillingRates {
+    fn default() -> Self {
+        Self {
+            vm_hour: 1.0,
+            storage_gb_hour: 0.01,
+            gpu_hour: 20.0,
+            thousand_tokens: 1.0,
+            tool_execution: 0.0,
+        }
+    }
+}
+
 /// Main settings structure
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Settings {
@@ -90,6 +203,15 @@ pub struct Settings {
     pub auth: AuthSettings,
     /// Database settings
     pub database: DatabaseSettings,
+    /// Operations store settings
+    #[serde(default)]
+    pub operations: OperationsSettings,
+    /// Event subscription settings
+    #[serde(default)]
+    pub events: EventsSettings,
+    /// Usage metering and quota settings
+    #[serde(default)]
+    pub billing: BillingSettings,
     /// Log level
     pub log_level: String,
 }
@@ -101,6 +223,9 @@ impl Default for Settings {
             server: ServerSettings::default(),
             auth: AuthSettings::default(),
             database: DatabaseSettings::default(),
+            operations: OperationsSettings::default(),
+            events: EventsSettings::default(),
+            billing: BillingSettings::default(),
             log_level: "info".to_string(),
         }
     }
@@ -1,19 +1,168 @@
 // Events module for the MCP server
 //
 // This module implements the event system for workload state changes
-// and notifications.
+// and notifications. Changes to instances, nodes and pack builds are read
+// from the form-state change feed, finished operations from the operations
+// repository, and every resulting event is delivered to the subscriptions
+// it matches, as MCP notifications or webhooks.
+
+pub mod subscriptions;
+pub mod watcher;
+pub mod webhook;
+#[cfg(test)]
+mod tests;
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
+use serde_json::json;
+use tokio::sync::broadcast;
+
+use crate::config::EventsSettings;
+use crate::models::operations::Operation;
+
+pub use subscriptions::{Delivery, EventFilter, Subscription, SubscriptionManager};
+pub use watcher::StateWatcher;
+pub use webhook::WebhookDispatcher;
+
+/// Number of events a listener can fall behind before it misses some
+const EVENT_CAPACITY: usize = 1024;
 
 /// Event represents a state change or notification
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Event {
-    /// Event type
+    /// Unique identifier of the event
+    pub id: String,
+    /// Event type, such as `instance.status_changed`
     pub event_type: String,
     /// Event source
     pub source: String,
+    /// User that owns the resource the event is about, if any
+    pub user_id: Option<String>,
+    /// ID of the resource the event is about
+    pub resource_id: String,
+    /// Build the resource belongs to, if any
+    pub build_id: Option<String>,
     /// Event data
     pub data: serde_json::Value,
     /// Event timestamp
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
-// The full event system will be implemented in future sub-tasks 
\ No newline at end of file
+/// EventService fans events out to listeners and subscriptions
+pub struct EventService {
+    sender: broadcast::Sender<Event>,
+    subscriptions: SubscriptionManager,
+    webhooks: WebhookDispatcher,
+}
+
+impl EventService {
+    /// Create a new event service
+    pub fn new(subscriptions: SubscriptionManager, webhooks: WebhookDispatcher) -> Self {
+        let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+        Self {
+            sender,
+            subscriptions,
+            webhooks,
+        }
+    }
+
+    /// Open the event service described by the settings, falling back to
+    /// in-memory subscriptions if the subscription store cannot be read
+    pub fn open(settings: &EventsSettings) -> Arc<Self> {
+        let subscriptions = SubscriptionManager::open(&settings.store_path).unwrap_or_else(|e| {
+            log::error!(
+                "Failed to open subscription store at {}, subscriptions will not survive a restart: {}",
+                settings.store_path, e
+            );
+            SubscriptionManager::new()
+        });
+        let webhooks = WebhookDispatcher::new(
+            settings.webhook_max_attempts,
+            Duration::from_secs(settings.webhook_timeout_secs),
+        )
+        .with_max_concurrency(settings.webhook_max_concurrency)
+        .allow_private_destinations(settings.webhook_allow_private);
+        Arc::new(Self::new(subscriptions, webhooks))
+    }
+
+    /// Subscriptions of the service
+    pub fn subscriptions(&self) -> &SubscriptionManager {
+        &self.subscriptions
+    }
+
+    /// Listen to every event published from now on
+    pub fn listen(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+
+    /// Publish an event to listeners and to the webhooks it matches
+    pub async fn publish(self: &Arc<Self>, event: Event) {
+        for subscription in self.subscriptions.matching(&event).await {
+            if let Delivery::Webhook { .. } = subscription.delivery {
+                let service = self.clone();
+                let event = event.clone();
+                tokio::spawn(async move {
+                    let result = service.webhooks.deliver(&subscription, &event).await;
+                    service.subscriptions.record_delivery(&subscription.id, result.err()).await;
+                });
+            }
+        }
+
+        // No listeners is not an error, nobody is connected
+        let _ = self.sender.send(event);
+    }
+
+    /// Follow the form-state change feed at `state_url` in the background
+    pub fn watch_state(self: &Arc<Self>, state_url: String) {
+        let watcher = StateWatcher::new(state_url, self.clone());
+        tokio::spawn(watcher.run());
+    }
+
+    /// Publish an event for every operation that finishes
+    pub fn watch_operations(self: &Arc<Self>, mut operations: broadcast::Receiver<Operation>) {
+        let service = self.clone();
+        tokio::spawn(async move {
+            // Finished operations can be broadcast more than once
+            let mut published = HashSet::new();
+            loop {
+                let operation = match operations.recv().await {
+                    Ok(operation) => operation,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("Event service fell behind, {} operation updates were dropped", skipped);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return,
+                };
+                if !operation.is_finished() || !published.insert(operation.id.clone()) {
+                    continue;
+                }
+                if published.len() > EVENT_CAPACITY * 16 {
+                    published.clear();
+                    published.insert(operation.id.clone());
+                }
+                service.publish(operation_event(&operation)).await;
+            }
+        });
+    }
+}
+
+/// Event for a finished operation
+pub fn operation_event(operation: &Operation) -> Event {
+    Event {
+        id: uuid::Uuid::new_v4().to_string(),
+        event_type: format!("operation.{}", operation.status),
+        source: "form-mcp".to_string(),
+        user_id: Some(operation.user_id.clone()),
+        resource_id: operation.id.clone(),
+        build_id: None,
+        data: json!({
+            "operation_id": operation.id,
+            "tool_name": operation.tool_name,
+            "status": operation.status.to_string(),
+            "error": operation.error,
+        }),
+        timestamp: chrono::Utc::now(),
+    }
+}
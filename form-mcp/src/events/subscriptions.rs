@@ -0,0 +1,231 @@
+// Event subscriptions
+//
+// This module keeps the event subscriptions of clients and decides which of
+// them an event is delivered to. Subscriptions are written to a JSON file so
+// webhooks keep firing after a restart.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Value};
+use tokio::sync::RwLock;
+
+use super::Event;
+
+/// How the events of a subscription are delivered
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Delivery {
+    /// As notifications on the MCP connections of the subscriber
+    Mcp,
+    /// As POST requests to a URL, signed with the secret if there is one
+    Webhook {
+        url: String,
+        #[serde(default)]
+        secret: Option<String>,
+    },
+}
+
+/// Which events a subscription receives
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct EventFilter {
+    /// Event types to receive, such as `instance.deleted`; `instance.*`
+    /// matches every instance event. Empty matches every type.
+    #[serde(default)]
+    pub event_types: Vec<String>,
+    /// Instances, nodes, builds or operations to receive events about.
+    /// Empty matches every resource.
+    #[serde(default)]
+    pub resource_ids: Vec<String>,
+}
+
+impl EventFilter {
+    /// Check whether an event passes the filter
+    pub fn matches(&self, event: &Event) -> bool {
+        let type_matches = self.event_types.is_empty() || self.event_types.iter().any(|pattern| {
+            match pattern.strip_suffix('*') {
+                Some(prefix) => event.event_type.starts_with(prefix),
+                None => *pattern == event.event_type,
+            }
+        });
+        let resource_matches = self.resource_ids.is_empty() || self.resource_ids.iter().any(|id| {
+            *id == event.resource_id || Some(id) == event.build_id.as_ref()
+        });
+        type_matches && resource_matches
+    }
+}
+
+/// A client's subscription to events
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Subscription {
+    /// Unique identifier of the subscription
+    pub id: String,
+    /// User that created the subscription
+    pub user_id: String,
+    /// Whether the subscriber is an admin, who receives every user's events
+    pub is_admin: bool,
+    /// Which events the subscription receives
+    pub filter: EventFilter,
+    /// How the events are delivered
+    pub delivery: Delivery,
+    /// When the subscription was created
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Error of the last failed delivery, cleared by a successful one
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+impl Subscription {
+    /// Create a new subscription
+    pub fn new(user_id: String, is_admin: bool, filter: EventFilter, delivery: Delivery) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            user_id,
+            is_admin,
+            filter,
+            delivery,
+            created_at: chrono::Utc::now(),
+            last_error: None,
+        }
+    }
+
+    /// Check whether an event is delivered to this subscription. Users only
+    /// receive events about their own resources.
+    pub fn accepts(&self, event: &Event) -> bool {
+        let visible = self.is_admin || event.user_id.as_deref() == Some(self.user_id.as_str());
+        visible && self.filter.matches(event)
+    }
+
+    /// The subscription as shown to clients, without the webhook secret
+    pub fn redacted(&self) -> Value {
+        let delivery = match &self.delivery {
+            Delivery::Mcp => json!({ "type": "mcp" }),
+            Delivery::Webhook { url, secret } => json!({
+                "type": "webhook",
+                "url": url,
+                "signed": secret.is_some(),
+            }),
+        };
+        json!({
+            "id": self.id,
+            "user_id": self.user_id,
+            "filter": self.filter,
+            "delivery": delivery,
+            "created_at": self.created_at,
+            "last_error": self.last_error,
+        })
+    }
+}
+
+/// SubscriptionManager keeps the subscriptions, optionally on disk
+#[derive(Debug, Default)]
+pub struct SubscriptionManager {
+    subscriptions: RwLock<HashMap<String, Subscription>>,
+    path: Option<PathBuf>,
+}
+
+impl SubscriptionManager {
+    /// Create an in-memory subscription manager
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Open the subscriptions stored at `path`, which is created on the
+    /// first change if it does not exist
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let subscriptions: Vec<Subscription> = match std::fs::read(&path) {
+            Ok(content) => serde_json::from_slice(&content)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)?;
+                }
+                Vec::new()
+            }
+            Err(e) => return Err(e),
+        };
+
+        Ok(Self {
+            subscriptions: RwLock::new(
+                subscriptions.into_iter().map(|sub| (sub.id.clone(), sub)).collect()
+            ),
+            path: Some(path),
+        })
+    }
+
+    /// Add a subscription
+    pub async fn add(&self, subscription: Subscription) {
+        let mut subscriptions = self.subscriptions.write().await;
+        subscriptions.insert(subscription.id.clone(), subscription);
+        self.persist(&subscriptions).await;
+    }
+
+    /// Remove a subscription of the user, or of anyone for admins
+    pub async fn remove(&self, id: &str, user_id: &str, is_admin: bool) -> Result<Subscription, String> {
+        let mut subscriptions = self.subscriptions.write().await;
+        match subscriptions.get(id) {
+            Some(sub) if is_admin || sub.user_id == user_id => {}
+            _ => return Err(format!("Subscription '{}' not found", id)),
+        }
+        let subscription = subscriptions.remove(id).ok_or_else(|| format!("Subscription '{}' not found", id))?;
+        self.persist(&subscriptions).await;
+        Ok(subscription)
+    }
+
+    /// List the subscriptions of the user, or every subscription for admins
+    pub async fn list(&self, user_id: &str, is_admin: bool) -> Vec<Subscription> {
+        let subscriptions = self.subscriptions.read().await;
+        let mut list: Vec<Subscription> = subscriptions.values()
+            .filter(|sub| is_admin || sub.user_id == user_id)
+            .cloned()
+            .collect();
+        list.sort_by_key(|sub| sub.created_at);
+        list
+    }
+
+    /// Subscriptions an event is delivered to
+    pub async fn matching(&self, event: &Event) -> Vec<Subscription> {
+        let subscriptions = self.subscriptions.read().await;
+        subscriptions.values()
+            .filter(|sub| sub.accepts(event))
+            .cloned()
+            .collect()
+    }
+
+    /// Record the outcome of a webhook delivery
+    pub async fn record_delivery(&self, id: &str, error: Option<String>) {
+        let mut subscriptions = self.subscriptions.write().await;
+        let Some(subscription) = subscriptions.get_mut(id) else {
+            return;
+        };
+        if subscription.last_error == error {
+            return;
+        }
+        subscription.last_error = error;
+        self.persist(&subscriptions).await;
+    }
+
+    /// Write the subscriptions to disk, through a temporary file so a crash
+    /// never leaves the file half written
+    async fn persist(&self, subscriptions: &HashMap<String, Subscription>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let list: Vec<&Subscription> = subscriptions.values().collect();
+        let result = match serde_json::to_vec_pretty(&list) {
+            Ok(content) => {
+                let tmp = path.with_extension("json.tmp");
+                match tokio::fs::write(&tmp, content).await {
+                    Ok(()) => tokio::fs::rename(&tmp, path).await,
+                    Err(e) => Err(e),
+                }
+            }
+            Err(e) => Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        };
+        if let Err(e) = result {
+            log::error!("Failed to persist subscriptions to {}: {}", path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,283 @@
+use std::sync::Arc;
+use std::time::Duration;
+use serde_json::{json, Value};
+
+use crate::events::watcher::{SseParser, StateCache};
+use crate::events::webhook::{check_destination, is_public, sign};
+use crate::events::{
+    operation_event, Delivery, Event, EventFilter, EventService, Subscription, SubscriptionManager,
+    WebhookDispatcher,
+};
+use crate::models::operations::Operation;
+
+fn instance(id: &str, owner: &str, status: &str) -> Value {
+    json!({
+        "instance_id": id,
+        "instance_owner": owner,
+        "build_id": "build-1",
+        "node_id": "node-1",
+        "status": status,
+    })
+}
+
+fn node(id: &str, heartbeat: i64, cores: u64) -> Value {
+    json!({
+        "node_id": id,
+        "node_owner": "operator",
+        "host_region": "us-east",
+        "last_heartbeat": heartbeat,
+        "capacity": { "cpu_total_cores": cores },
+        "metrics": { "load": heartbeat },
+    })
+}
+
+fn event_types(events: &[Event]) -> Vec<&str> {
+    events.iter().map(|event| event.event_type.as_str()).collect()
+}
+
+fn event(event_type: &str, owner: &str) -> Event {
+    Event {
+        id: "event-1".to_string(),
+        event_type: event_type.to_string(),
+        source: "test".to_string(),
+        user_id: Some(owner.to_string()),
+        resource_id: "instance-1".to_string(),
+        build_id: Some("build-1".to_string()),
+        data: Value::Null,
+        timestamp: chrono::Utc::now(),
+    }
+}
+
+#[test]
+fn test_sse_parser() {
+    let mut parser = SseParser::default();
+
+    // Messages can be split anywhere across chunks
+    assert!(parser.push(b": keep-alive\n\nid: a:1\nevent: instance_up").is_empty());
+    let messages = parser.push(b"dated\ndata: {\"x\":\ndata: 1}\n\n");
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].id.as_deref(), Some("a:1"));
+    assert_eq!(messages[0].event.as_deref(), Some("instance_updated"));
+    assert_eq!(messages[0].data, "{\"x\":\n1}");
+
+    let messages = parser.push(b"data: one\r\n\r\ndata: two\n\n");
+    let data: Vec<&str> = messages.iter().map(|message| message.data.as_str()).collect();
+    assert_eq!(data, vec!["one", "two"]);
+}
+
+#[test]
+fn test_instance_changes_raise_events() {
+    let mut cache = StateCache::default();
+    cache.seed(&[instance("i-1", "alice", "Started")], &[]);
+
+    let events = cache.apply_change(&json!({
+        "type": "InstanceUpdated",
+        "cursor": "feed:7",
+        "timestamp": 1_700_000_000,
+        "instance": instance("i-2", "bob", "Building"),
+    }));
+    assert_eq!(event_types(&events), vec!["instance.created", "pack.build_started"]);
+    assert_eq!(events[0].id, "feed:7-0");
+    assert_eq!(events[0].user_id.as_deref(), Some("bob"));
+    assert_eq!(events[0].build_id.as_deref(), Some("build-1"));
+    assert_eq!(events[0].timestamp.timestamp(), 1_700_000_000);
+
+    let events = cache.apply_change(&json!({ "type": "InstanceUpdated", "instance": instance("i-2", "bob", "Built") }));
+    assert_eq!(event_types(&events), vec!["instance.status_changed", "pack.build_completed"]);
+    assert_eq!(events[0].data["previous_status"], "Building");
+
+    // Updates that leave the status alone raise nothing
+    let events = cache.apply_change(&json!({ "type": "InstanceUpdated", "instance": instance("i-1", "alice", "Started") }));
+    assert!(events.is_empty());
+
+    // Deletions are attributed to the owner the cache remembers
+    let events = cache.apply_change(&json!({ "type": "InstanceDeleted", "instance_id": "i-1" }));
+    assert_eq!(event_types(&events), vec!["instance.deleted"]);
+    assert_eq!(events[0].user_id.as_deref(), Some("alice"));
+}
+
+#[test]
+fn test_node_heartbeats_are_ignored() {
+    let mut cache = StateCache::default();
+
+    let events = cache.apply_change(&json!({ "type": "NodeUpdated", "node": node("n-1", 1, 8) }));
+    assert_eq!(event_types(&events), vec!["node.joined"]);
+    assert_eq!(events[0].user_id.as_deref(), Some("operator"));
+
+    let events = cache.apply_change(&json!({ "type": "NodeUpdated", "node": node("n-1", 2, 8) }));
+    assert!(events.is_empty());
+
+    let events = cache.apply_change(&json!({ "type": "NodeUpdated", "node": node("n-1", 3, 16) }));
+    assert_eq!(event_types(&events), vec!["node.updated"]);
+
+    let events = cache.apply_change(&json!({ "type": "NodeDeleted", "node_id": "n-1" }));
+    assert_eq!(event_types(&events), vec!["node.left"]);
+}
+
+#[test]
+fn test_resync_raises_missed_changes() {
+    let mut cache = StateCache::default();
+    cache.seed(
+        &[instance("i-1", "alice", "Building"), instance("i-2", "alice", "Started")],
+        &[node("n-1", 1, 8)],
+    );
+
+    let events = cache.resync(&[instance("i-1", "alice", "CriticalError")], &[node("n-1", 5, 8)]);
+    assert_eq!(
+        event_types(&events),
+        vec!["instance.status_changed", "pack.build_failed", "instance.deleted"],
+    );
+}
+
+#[test]
+fn test_subscription_filters() {
+    let mine = Subscription::new("alice".to_string(), false, EventFilter::default(), Delivery::Mcp);
+    assert!(mine.accepts(&event("instance.deleted", "alice")));
+    // Users never see other users' events
+    assert!(!mine.accepts(&event("instance.deleted", "bob")));
+
+    let admin = Subscription::new("root".to_string(), true, EventFilter::default(), Delivery::Mcp);
+    assert!(admin.accepts(&event("instance.deleted", "bob")));
+
+    let filter = EventFilter {
+        event_types: vec!["pack.*".to_string(), "instance.deleted".to_string()],
+        resource_ids: vec!["build-1".to_string()],
+    };
+    assert!(filter.matches(&event("pack.build_completed", "alice")));
+    assert!(filter.matches(&event("instance.deleted", "alice")));
+    assert!(!filter.matches(&event("instance.created", "alice")));
+
+    let mut other_build = event("pack.build_completed", "alice");
+    other_build.build_id = Some("build-2".to_string());
+    assert!(!filter.matches(&other_build));
+}
+
+#[tokio::test]
+async fn test_subscriptions_are_persisted() {
+    let path = std::env::temp_dir()
+        .join(format!("form-mcp-events-{}", uuid::Uuid::new_v4()))
+        .join("subscriptions.json");
+
+    let manager = SubscriptionManager::open(&path).unwrap();
+    let webhook = Delivery::Webhook { url: "http://localhost/hook".to_string(), secret: Some("s3cret".to_string()) };
+    let subscription = Subscription::new("alice".to_string(), false, EventFilter::default(), webhook);
+    manager.add(subscription.clone()).await;
+    manager.add(Subscription::new("bob".to_string(), false, EventFilter::default(), Delivery::Mcp)).await;
+
+    let manager = SubscriptionManager::open(&path).unwrap();
+    assert_eq!(manager.list("alice", false).await, vec![subscription.clone()]);
+    assert_eq!(manager.list("root", true).await.len(), 2);
+
+    // The secret is never shown back
+    let redacted = subscription.redacted();
+    assert_eq!(redacted["delivery"]["signed"], true);
+    assert!(!redacted.to_string().contains("s3cret"));
+
+    // Users can only remove their own subscriptions
+    assert!(manager.remove(&subscription.id, "bob", false).await.is_err());
+    manager.remove(&subscription.id, "alice", false).await.unwrap();
+    let manager = SubscriptionManager::open(&path).unwrap();
+    assert!(manager.list("alice", false).await.is_empty());
+
+    std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+}
+
+#[test]
+fn test_sign() {
+    assert_eq!(
+        sign("key", "The quick brown fox jumps over the lazy dog"),
+        "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8",
+    );
+}
+
+#[tokio::test]
+async fn test_webhook_delivery_retries() {
+    let mut server = mockito::Server::new_async().await;
+    let dispatcher = WebhookDispatcher::new(3, Duration::from_secs(5))
+        .with_retry_delay(Duration::from_millis(1))
+        .allow_private_destinations(true);
+    let url = server.url();
+    let subscription = |path: &str| Subscription::new(
+        "alice".to_string(),
+        false,
+        EventFilter::default(),
+        Delivery::Webhook { url: format!("{}{}", url, path), secret: Some("key".to_string()) },
+    );
+    let event = event("instance.deleted", "alice");
+
+    let signed = server.mock("POST", "/ok")
+        .match_header("X-Form-Event", "instance.deleted")
+        .match_header("X-Form-Signature", mockito::Matcher::Regex("^sha256=[0-9a-f]{64}$".to_string()))
+        .with_status(204)
+        .expect(1)
+        .create_async().await;
+    assert!(dispatcher.deliver(&subscription("/ok"), &event).await.is_ok());
+    signed.assert_async().await;
+
+    // Server errors are retried until the attempts run out
+    let failing = server.mock("POST", "/down").with_status(503).expect(3).create_async().await;
+    assert!(dispatcher.deliver(&subscription("/down"), &event).await.is_err());
+    failing.assert_async().await;
+
+    // Client errors are not
+    let rejected = server.mock("POST", "/gone").with_status(404).expect(1).create_async().await;
+    assert!(dispatcher.deliver(&subscription("/gone"), &event).await.is_err());
+    rejected.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_webhook_private_destinations() {
+    assert!(is_public("93.184.216.34".parse().unwrap()));
+    assert!(is_public("2606:2800:220:1::1".parse().unwrap()));
+    for ip in [
+        "127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1",
+        "0.0.0.0", "203.0.113.1", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1",
+    ] {
+        assert!(!is_public(ip.parse().unwrap()), "{} is not public", ip);
+    }
+
+    assert!(check_destination("http://169.254.169.254/latest/meta-data").is_err());
+    assert!(check_destination("http://2130706433/hook").is_err());
+    assert!(check_destination("http://[::1]:8080/hook").is_err());
+    assert!(check_destination("https://hooks.example.com/form").is_ok());
+
+    // Names are checked once resolved, and no request is sent
+    let mut server = mockito::Server::new_async().await;
+    let hook = server.mock("POST", "/hook").expect(0).create_async().await;
+    let dispatcher = WebhookDispatcher::new(1, Duration::from_secs(5));
+    let subscription = Subscription::new(
+        "alice".to_string(),
+        false,
+        EventFilter::default(),
+        Delivery::Webhook {
+            url: format!("http://localhost:{}/hook", server.socket_address().port()),
+            secret: None,
+        },
+    );
+    assert!(dispatcher.deliver(&subscription, &event("instance.deleted", "alice")).await.is_err());
+    hook.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_operation_events_are_published_once() {
+    let service = Arc::new(EventService::new(
+        SubscriptionManager::new(),
+        WebhookDispatcher::new(1, Duration::from_secs(1)),
+    ));
+    let (sender, receiver) = tokio::sync::broadcast::channel(16);
+    let mut listener = service.listen();
+    service.watch_operations(receiver);
+
+    let mut operation = Operation::new("alice".to_string(), "vm.create".to_string());
+    sender.send(operation.clone()).unwrap();
+    operation.mark_completed(json!({}));
+    sender.send(operation.clone()).unwrap();
+    sender.send(operation.clone()).unwrap();
+
+    let published = tokio::time::timeout(Duration::from_secs(1), listener.recv()).await.unwrap().unwrap();
+    let expected = operation_event(&operation);
+    assert_eq!(published.event_type, "operation.completed");
+    assert_eq!(published.user_id, expected.user_id);
+    assert_eq!(published.resource_id, operation.id);
+    assert!(tokio::time::timeout(Duration::from_millis(50), listener.recv()).await.is_err());
+}
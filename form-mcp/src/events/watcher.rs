@@ -0,0 +1,412 @@
+// State watcher
+//
+// This module follows the form-state change feed (`/watch`) and turns the
+// records it streams into events. The feed carries each record as it stands
+// after a change, so the watcher keeps the last status of every instance and
+// node to tell what actually changed. When its cursor expires, the watcher
+// resyncs from the list endpoints and publishes the differences it finds.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{TimeZone, Utc};
+use reqwest::{Client, StatusCode};
+use serde_json::{json, Value};
+
+use super::{Event, EventService};
+use crate::tools::state::unwrap_response;
+
+/// Resources of the change feed the watcher follows
+const WATCHED_RESOURCES: &str = "instance,node";
+
+/// Longest the watcher waits before reconnecting
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Node fields that change on every heartbeat and are not worth an event
+const VOLATILE_NODE_FIELDS: [&str; 3] = ["metrics", "last_heartbeat", "updated_at"];
+
+/// A message of a server-sent event stream
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct SseMessage {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: String,
+}
+
+/// Incremental parser for server-sent event streams
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: String,
+    message: SseMessage,
+    has_data: bool,
+}
+
+impl SseParser {
+    /// Feed a chunk of the stream, returning the messages it completed
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseMessage> {
+        self.buffer.push_str(&String::from_utf8_lossy(chunk));
+
+        let mut messages = Vec::new();
+        while let Some(end) = self.buffer.find('\n') {
+            let line: String = self.buffer.drain(..=end).collect();
+            let line = line.trim_end_matches(['\n', '\r']);
+
+            if line.is_empty() {
+                if self.has_data {
+                    messages.push(std::mem::take(&mut self.message));
+                }
+                self.message = SseMessage::default();
+                self.has_data = false;
+                continue;
+            }
+            // Comments, such as keep-alives
+            if line.starts_with(':') {
+                continue;
+            }
+
+            let (field, value) = line.split_once(':').unwrap_or((line, ""));
+            let value = value.strip_prefix(' ').unwrap_or(value);
+            match field {
+                "id" => self.message.id = Some(value.to_string()),
+                "event" => self.message.event = Some(value.to_string()),
+                "data" => {
+                    if self.has_data {
+                        self.message.data.push('\n');
+                    }
+                    self.message.data.push_str(value);
+                    self.has_data = true;
+                }
+                _ => {}
+            }
+        }
+        messages
+    }
+}
+
+/// Last known state of an instance
+#[derive(Debug, Clone)]
+struct InstanceState {
+    owner: String,
+    build_id: String,
+    node_id: String,
+    status: String,
+}
+
+/// Last known state of a node
+#[derive(Debug, Clone)]
+struct NodeState {
+    owner: String,
+    digest: Value,
+}
+
+/// StateCache remembers the last state of instances and nodes, and turns
+/// changes to them into events
+#[derive(Debug, Default)]
+pub struct StateCache {
+    instances: HashMap<String, InstanceState>,
+    nodes: HashMap<String, NodeState>,
+}
+
+impl StateCache {
+    /// Apply a change event from the feed, returning the events it raises
+    pub fn apply_change(&mut self, change: &Value) -> Vec<Event> {
+        let timestamp = change.get("timestamp")
+            .and_then(Value::as_i64)
+            .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+            .unwrap_or_else(Utc::now);
+        let cursor = change.get("cursor").and_then(Value::as_str).unwrap_or_default();
+
+        let mut events = match change.get("type").and_then(Value::as_str) {
+            Some("InstanceUpdated") => self.instance_updated(&change["instance"]),
+            Some("InstanceDeleted") => self.instance_deleted(change["instance_id"].as_str().unwrap_or_default()),
+            Some("NodeUpdated") => self.node_updated(&change["node"]),
+            Some("NodeDeleted") => self.node_deleted(change["node_id"].as_str().unwrap_or_default()),
+            _ => Vec::new(),
+        };
+
+        // Events of a change share its cursor, so clients can correlate them
+        for (i, event) in events.iter_mut().enumerate() {
+            event.timestamp = timestamp;
+            if !cursor.is_empty() {
+                event.id = format!("{}-{}", cursor, i);
+            }
+        }
+        events
+    }
+
+    /// Replace the cache with the full state from the list endpoints,
+    /// returning the events for whatever changed since it was last seen
+    pub fn resync(&mut self, instances: &[Value], nodes: &[Value]) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        let mut gone: Vec<String> = self.instances.keys().cloned().collect();
+        for instance in instances {
+            let id = instance["instance_id"].as_str().unwrap_or_default();
+            gone.retain(|known| known != id);
+            events.extend(self.instance_updated(instance));
+        }
+        for id in gone {
+            events.extend(self.instance_deleted(&id));
+        }
+
+        let mut gone: Vec<String> = self.nodes.keys().cloned().collect();
+        for node in nodes {
+            let id = node["node_id"].as_str().unwrap_or_default();
+            gone.retain(|known| known != id);
+            events.extend(self.node_updated(node));
+        }
+        for id in gone {
+            events.extend(self.node_deleted(&id));
+        }
+
+        events
+    }
+
+    /// Fill an empty cache without raising events, as nothing is known to
+    /// have changed
+    pub fn seed(&mut self, instances: &[Value], nodes: &[Value]) {
+        self.resync(instances, nodes);
+    }
+
+    fn instance_updated(&mut self, instance: &Value) -> Vec<Event> {
+        let Some(instance_id) = instance["instance_id"].as_str() else {
+            return Vec::new();
+        };
+        let state = InstanceState {
+            owner: instance["instance_owner"].as_str().unwrap_or_default().to_string(),
+            build_id: instance["build_id"].as_str().unwrap_or_default().to_string(),
+            node_id: instance["node_id"].as_str().unwrap_or_default().to_string(),
+            status: instance["status"].as_str().unwrap_or_default().to_string(),
+        };
+        let previous = self.instances.insert(instance_id.to_string(), state.clone());
+
+        let mut events = Vec::new();
+        let previous_status = previous.as_ref().map(|previous| previous.status.as_str());
+        match previous_status {
+            None => events.push(instance_event("instance.created", instance_id, &state, None)),
+            Some(status) if status != state.status => {
+                events.push(instance_event("instance.status_changed", instance_id, &state, previous_status));
+            }
+            Some(_) => {
+                if previous.as_ref().is_some_and(|previous| previous.node_id != state.node_id) {
+                    events.push(instance_event("instance.moved", instance_id, &state, previous_status));
+                }
+                return events;
+            }
+        }
+
+        // form-pack moves instances through Building to Built, or to
+        // CriticalError when the build fails
+        let build_event = match (previous_status, state.status.as_str()) {
+            (_, "Building") => Some("pack.build_started"),
+            (Some("Building"), "Built") => Some("pack.build_completed"),
+            (Some("Building"), "CriticalError") => Some("pack.build_failed"),
+            _ => None,
+        };
+        if let Some(event_type) = build_event {
+            events.push(instance_event(event_type, instance_id, &state, previous_status));
+        }
+        events
+    }
+
+    fn instance_deleted(&mut self, instance_id: &str) -> Vec<Event> {
+        let Some(state) = self.instances.remove(instance_id) else {
+            return Vec::new();
+        };
+        vec![instance_event("instance.deleted", instance_id, &state, Some(state.status.as_str()))]
+    }
+
+    fn node_updated(&mut self, node: &Value) -> Vec<Event> {
+        let Some(node_id) = node["node_id"].as_str() else {
+            return Vec::new();
+        };
+        let mut digest = node.clone();
+        if let Some(fields) = digest.as_object_mut() {
+            for field in VOLATILE_NODE_FIELDS {
+                fields.remove(field);
+            }
+        }
+        let state = NodeState {
+            owner: node["node_owner"].as_str().unwrap_or_default().to_string(),
+            digest,
+        };
+
+        let event_type = match self.nodes.get(node_id) {
+            None => "node.joined",
+            Some(previous) if previous.digest != state.digest => "node.updated",
+            Some(_) => return Vec::new(),
+        };
+        let event = node_event(event_type, node_id, &state.owner, json!({
+            "node_id": node_id,
+            "host_region": node["host_region"],
+            "capacity": node["capacity"],
+        }));
+        self.nodes.insert(node_id.to_string(), state);
+        vec![event]
+    }
+
+    fn node_deleted(&mut self, node_id: &str) -> Vec<Event> {
+        let Some(state) = self.nodes.remove(node_id) else {
+            return Vec::new();
+        };
+        vec![node_event("node.left", node_id, &state.owner, json!({ "node_id": node_id }))]
+    }
+}
+
+fn instance_event(event_type: &str, instance_id: &str, state: &InstanceState, previous_status: Option<&str>) -> Event {
+    Event {
+        id: uuid::Uuid::new_v4().to_string(),
+        event_type: event_type.to_string(),
+        source: "form-state".to_string(),
+        user_id: Some(state.owner.clone()),
+        resource_id: instance_id.to_string(),
+        build_id: Some(state.build_id.clone()),
+        data: json!({
+            "instance_id": instance_id,
+            "build_id": state.build_id,
+            "node_id": state.node_id,
+            "status": state.status,
+            "previous_status": previous_status,
+        }),
+        timestamp: Utc::now(),
+    }
+}
+
+fn node_event(event_type: &str, node_id: &str, owner: &str, data: Value) -> Event {
+    Event {
+        id: uuid::Uuid::new_v4().to_string(),
+        event_type: event_type.to_string(),
+        source: "form-state".to_string(),
+        user_id: Some(owner.to_string()),
+        resource_id: node_id.to_string(),
+        build_id: None,
+        data,
+        timestamp: Utc::now(),
+    }
+}
+
+/// StateWatcher follows the form-state change feed and publishes events
+pub struct StateWatcher {
+    state_url: String,
+    http_client: Client,
+    events: Arc<EventService>,
+    cache: StateCache,
+    cursor: Option<String>,
+    seeded: bool,
+}
+
+impl StateWatcher {
+    /// Create a watcher for the form-state API at `state_url`
+    pub fn new(state_url: String, events: Arc<EventService>) -> Self {
+        Self {
+            state_url: state_url.trim_end_matches('/').to_string(),
+            http_client: Client::new(),
+            events,
+            cache: StateCache::default(),
+            cursor: None,
+            seeded: false,
+        }
+    }
+
+    /// Follow the feed forever, reconnecting with backoff when it drops
+    pub async fn run(mut self) {
+        let mut delay = Duration::from_secs(1);
+        loop {
+            match self.follow().await {
+                Ok(()) => {
+                    log::info!("form-state change feed ended, reconnecting");
+                    delay = Duration::from_secs(1);
+                }
+                Err(e) => {
+                    log::warn!("Watching form-state failed, retrying in {:?}: {}", delay, e);
+                    tokio::time::sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+                }
+            }
+        }
+    }
+
+    /// Connect to the feed and publish its events until it ends
+    async fn follow(&mut self) -> Result<(), String> {
+        let mut query = vec![("resources", WATCHED_RESOURCES.to_string())];
+        if let Some(cursor) = &self.cursor {
+            query.push(("cursor", cursor.clone()));
+        }
+        let mut response = self.http_client
+            .get(format!("{}/watch", self.state_url))
+            .query(&query)
+            .send()
+            .await
+            .map_err(|e| format!("Failed to connect to the change feed: {}", e))?;
+
+        match response.status() {
+            status if status.is_success() => {}
+            StatusCode::GONE | StatusCode::BAD_REQUEST => {
+                // The cursor is from a previous run of form-state or too old
+                self.cursor = None;
+                return Err("Change feed cursor expired, resyncing".to_string());
+            }
+            status => return Err(format!("Change feed returned status {}", status)),
+        }
+
+        // Without a cursor, events may have been missed; the stream is
+        // already open, so nothing changing from here on is lost
+        if self.cursor.is_none() {
+            self.resync().await?;
+        }
+
+        let mut parser = SseParser::default();
+        while let Some(chunk) = response.chunk().await.map_err(|e| format!("Change feed failed: {}", e))? {
+            for message in parser.push(&chunk) {
+                let change: Value = match serde_json::from_str(&message.data) {
+                    Ok(change) => change,
+                    Err(e) => {
+                        log::warn!("Skipping malformed change event: {}", e);
+                        continue;
+                    }
+                };
+                if let Some(cursor) = message.id.or_else(|| change["cursor"].as_str().map(str::to_string)) {
+                    self.cursor = Some(cursor);
+                }
+                for event in self.cache.apply_change(&change) {
+                    self.events.publish(event).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the full state from the list endpoints
+    async fn resync(&mut self) -> Result<(), String> {
+        let instances = self.list("/instance/list").await?;
+        let nodes = self.list("/node/list").await?;
+
+        if !self.seeded {
+            self.cache.seed(&instances, &nodes);
+            self.seeded = true;
+            log::info!("Watching {} instances and {} nodes in form-state", instances.len(), nodes.len());
+            return Ok(());
+        }
+
+        for event in self.cache.resync(&instances, &nodes) {
+            self.events.publish(event).await;
+        }
+        Ok(())
+    }
+
+    async fn list(&self, path: &str) -> Result<Vec<Value>, String> {
+        let body: Value = self.http_client
+            .get(format!("{}{}", self.state_url, path))
+            .send()
+            .await
+            .map_err(|e| format!("Failed to list {}: {}", path, e))?
+            .json()
+            .await
+            .map_err(|e| format!("Failed to parse {}: {}", path, e))?;
+
+        match unwrap_response(body).map_err(|e| e.to_string())? {
+            Value::Array(list) => Ok(list),
+            _ => Ok(Vec::new()),
+        }
+    }
+}
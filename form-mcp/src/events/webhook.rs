@@ -0,0 +1,221 @@
+// Webhook delivery
+//
+// This module POSTs events to the webhooks of subscriptions, retrying with
+// exponential backoff. Payloads of subscriptions with a secret are signed
+// with HMAC-SHA256 so receivers can check they came from form-mcp.
+//
+// Webhook URLs are chosen by users, so unless the operator allows it,
+// deliveries only go to public addresses: hosts are resolved by a resolver
+// that drops loopback, link-local and private addresses, IP literals are
+// checked the same way, and redirects are not followed.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use hmac::{Hmac, Mac};
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use reqwest::{redirect, Client, StatusCode, Url};
+use serde_json::json;
+use sha2::Sha256;
+use tokio::sync::Semaphore;
+
+use super::{Delivery, Event, Subscription};
+
+/// Delay before the first retry, doubled for every later one
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Default number of deliveries in flight at once
+const DEFAULT_MAX_CONCURRENCY: usize = 32;
+
+/// WebhookDispatcher delivers events to webhooks
+#[derive(Debug, Clone)]
+pub struct WebhookDispatcher {
+    http_client: Client,
+    max_attempts: u32,
+    retry_delay: Duration,
+    timeout: Duration,
+    allow_private: bool,
+    deliveries: Arc<Semaphore>,
+}
+
+impl WebhookDispatcher {
+    /// Create a dispatcher that tries every delivery up to `max_attempts`
+    /// times, giving each attempt `timeout` to complete
+    pub fn new(max_attempts: u32, timeout: Duration) -> Self {
+        Self {
+            http_client: http_client(timeout, false),
+            max_attempts: max_attempts.max(1),
+            retry_delay: BASE_RETRY_DELAY,
+            timeout,
+            allow_private: false,
+            deliveries: Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENCY)),
+        }
+    }
+
+    /// Use `delay` before the first retry instead of the default
+    pub fn with_retry_delay(mut self, delay: Duration) -> Self {
+        self.retry_delay = delay;
+        self
+    }
+
+    /// Deliver at most `max` events at once, queueing the rest
+    pub fn with_max_concurrency(mut self, max: usize) -> Self {
+        self.deliveries = Arc::new(Semaphore::new(max.max(1)));
+        self
+    }
+
+    /// Whether webhooks may be delivered to loopback, link-local and
+    /// private addresses, such as in development setups
+    pub fn allow_private_destinations(mut self, allow: bool) -> Self {
+        self.allow_private = allow;
+        self.http_client = http_client(self.timeout, allow);
+        self
+    }
+
+    /// Deliver an event to the webhook of a subscription, retrying failed
+    /// attempts. Client errors other than timeouts and rate limiting are
+    /// not retried, as retrying would not change the answer.
+    pub async fn deliver(&self, subscription: &Subscription, event: &Event) -> Result<(), String> {
+        let Delivery::Webhook { url, secret } = &subscription.delivery else {
+            return Ok(());
+        };
+        if !self.allow_private {
+            check_destination(url)?;
+        }
+        let _permit = self.deliveries.acquire().await
+            .map_err(|_| "Webhook dispatcher has shut down".to_string())?;
+        let body = json!({
+            "subscription_id": subscription.id,
+            "event": event,
+        }).to_string();
+
+        let mut delay = self.retry_delay;
+        let mut attempt = 1;
+        loop {
+            let mut request = self.http_client
+                .post(url)
+                .header("Content-Type", "application/json")
+                .header("X-Form-Event", &event.event_type)
+                .header("X-Form-Delivery", &event.id)
+                .body(body.clone());
+            if let Some(secret) = secret {
+                request = request.header("X-Form-Signature", format!("sha256={}", sign(secret, &body)));
+            }
+
+            let (error, retryable) = match request.send().await {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) => {
+                    let status = response.status();
+                    let retryable = status.is_server_error()
+                        || status == StatusCode::REQUEST_TIMEOUT
+                        || status == StatusCode::TOO_MANY_REQUESTS;
+                    (format!("Webhook returned status {}", status), retryable)
+                }
+                Err(e) => (format!("Webhook request failed: {}", e), true),
+            };
+
+            if !retryable || attempt >= self.max_attempts {
+                log::warn!(
+                    "Giving up delivering event {} to subscription {} after {} attempt(s): {}",
+                    event.id, subscription.id, attempt, error
+                );
+                return Err(error);
+            }
+
+            log::debug!("Delivering event {} to {} failed, retrying in {:?}: {}", event.id, url, delay, error);
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+}
+
+/// Sign a payload with HMAC-SHA256, returning the hex encoded signature
+pub fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Build the HTTP client for deliveries. Unless private destinations are
+/// allowed, names are resolved to public addresses only.
+fn http_client(timeout: Duration, allow_private: bool) -> Client {
+    let builder = Client::builder()
+        .timeout(timeout)
+        .redirect(redirect::Policy::none());
+    let builder = if allow_private {
+        builder
+    } else {
+        // A proxy would resolve the name itself
+        builder.no_proxy().dns_resolver(Arc::new(PublicResolver))
+    };
+    builder.build().unwrap_or_default()
+}
+
+/// Reject webhook URLs whose host is a non-public IP address. Names are
+/// checked when they are resolved, by `PublicResolver`.
+pub fn check_destination(url: &str) -> Result<(), String> {
+    let url = Url::parse(url).map_err(|e| format!("Invalid webhook URL {}: {}", url, e))?;
+    let host = url.host_str().ok_or_else(|| format!("Webhook URL {} has no host", url))?;
+    let Ok(ip) = host.trim_start_matches('[').trim_end_matches(']').parse::<IpAddr>() else {
+        return Ok(());
+    };
+    if is_public(ip) {
+        Ok(())
+    } else {
+        Err(format!("Webhook destination {} is not a public address", ip))
+    }
+}
+
+/// Whether an address is publicly routable
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_multicast()
+                || ip.is_documentation()
+                || a == 0
+                // Shared address space (RFC 6598)
+                || (a == 100 && (64..128).contains(&b))
+                // Reserved (RFC 1112)
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(ip) = ip.to_ipv4_mapped() {
+                return is_public(IpAddr::V4(ip));
+            }
+            let first = ip.segments()[0];
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_multicast()
+                // Unique local (RFC 4193)
+                || (first & 0xfe00) == 0xfc00
+                // Link-local
+                || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+/// Resolver that only returns public addresses
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let host = name.as_str().to_string();
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0)).await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} does not resolve to a public address", host).into());
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
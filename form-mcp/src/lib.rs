@@ -11,6 +11,7 @@ pub mod models;
 pub mod config;
 pub mod billing;
 pub mod errors;
+pub mod mcp;
 
 use std::sync::Arc;
 use tokio::sync::RwLock;
@@ -1,9 +1,10 @@
 use std::env;
-use form_mcp::{api, tools};
+use form_mcp::{api, billing, events, mcp, models, tools};
 use std::sync::Arc;
 use anyhow::Result;
 use log::{info, error};
 use std::process;
+use std::time::Duration;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -12,8 +13,11 @@ async fn main() -> Result<()> {
     
     info!("Starting form-mcp server version {}", form_mcp::MCP_VERSION);
     
-    // Get configuration path from command line arguments
-    let config_path = env::args().nth(1);
+    // Get configuration path from command line arguments; `--stdio` serves
+    // MCP over standard input and output instead of HTTP
+    let args: Vec<String> = env::args().skip(1).collect();
+    let stdio = args.iter().any(|arg| arg == "--stdio");
+    let config_path = args.iter().find(|arg| !arg.starts_with("--")).cloned();
     
     // Load configuration
     let settings = match form_mcp::config::load_config(config_path.as_deref()) {
@@ -31,8 +35,50 @@ async fn main() -> Result<()> {
     let registry = tools::init_registry();
     info!("Initialized tool registry with {} tools", registry.list_tools().len());
     
+    // Open the operations store
+    let operations = models::operations::open_repository(&settings);
+    
+    // Start the event service, which follows form-state and the operations
+    // and delivers events to subscribers
+    let events = events::EventService::open(&settings.events);
+    if settings.events.enabled {
+        events.watch_state(settings.events.state_url.clone());
+    }
+    events.watch_operations(operations.subscribe());
+    tools::events::register_tools(&registry, events.clone());
+    
+    // Meter usage into the billing ledger and check quotas before tools
+    // create resources
+    if settings.billing.enabled {
+        let billing = billing::BillingService::open(&settings.billing);
+        billing.start(Duration::from_secs(settings.billing.sample_interval_secs));
+        registry.set_billing(billing.clone());
+        tools::billing::register_tools(&registry, billing);
+    }
+    
+    // Settle the operations a previous run left unfinished; form-state is
+    // asked in the background
+    {
+        let operations = operations.clone();
+        let registry = registry.clone();
+        tokio::spawn(async move { operations.reconcile(&registry).await });
+    }
+    
+    if stdio {
+        return match mcp::stdio::serve(registry, operations, events).await {
+            Ok(_) => {
+                info!("form-mcp stdio transport stopped gracefully");
+                Ok(())
+            },
+            Err(e) => {
+                error!("Error serving MCP over stdio: {}", e);
+                process::exit(1);
+            }
+        };
+    }
+    
     // Start the API server
-    match api::init_server(settings, registry).await {
+    match api::init_server(settings, registry, operations, events).await {
         Ok(_) => {
             info!("form-mcp server stopped gracefully");
             Ok(())
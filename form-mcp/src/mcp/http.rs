@@ -0,0 +1,136 @@
+// MCP streamable HTTP transport
+//
+// This module serves MCP over HTTP at `/mcp`. Clients POST messages and get
+// the response back as JSON, or as a server-sent event stream when they ask
+// for progress and accept `text/event-stream`, so progress notifications
+// can be delivered before the response.
+
+use actix_web::{http::header, web, HttpMessage, HttpRequest, HttpResponse};
+use futures_util::stream;
+use serde_json::Value;
+use tokio::sync::mpsc;
+
+use crate::auth::AuthData;
+use crate::mcp::protocol::{JsonRpcError, JsonRpcResponse};
+use crate::mcp::server::{ClientContext, McpServer};
+
+/// Handler for MCP messages posted by a client
+pub async fn handle_post(
+    req: HttpRequest,
+    body: web::Bytes,
+    server: web::Data<McpServer>,
+) -> HttpResponse {
+    let message: Value = match serde_json::from_slice(&body) {
+        Ok(message) => message,
+        Err(e) => return HttpResponse::BadRequest().json(
+            JsonRpcResponse::failure(Value::Null, JsonRpcError::parse_error(e.to_string()))
+        ),
+    };
+
+    let server = server.get_ref().clone();
+    let client = client_context(&req);
+    let (notifier, receiver) = mpsc::unbounded_channel();
+
+    // Notifications and responses from the client are acknowledged without a body
+    if !needs_response(&message) {
+        server.handle(message, &client, &notifier).await;
+        return HttpResponse::Accepted().finish();
+    }
+
+    if !(wants_progress(&message) && accepts_event_stream(&req)) {
+        return match server.handle(message, &client, &notifier).await {
+            Some(response) => HttpResponse::Ok().json(response),
+            None => HttpResponse::Accepted().finish(),
+        };
+    }
+
+    // Stream notifications as they are raised, followed by the response;
+    // the stream ends once the sender is dropped with the task
+    tokio::spawn(async move {
+        if let Some(response) = server.handle(message, &client, &notifier).await {
+            let _ = notifier.send(response);
+        }
+    });
+
+    event_stream(receiver)
+}
+
+/// Handler for clients opening a stream for server-initiated messages,
+/// which carries the events of the client's MCP subscriptions
+pub async fn handle_get(req: HttpRequest, server: web::Data<McpServer>) -> HttpResponse {
+    if !server.has_events() || !accepts_event_stream(&req) {
+        return HttpResponse::MethodNotAllowed()
+            .insert_header((header::ALLOW, "POST"))
+            .finish();
+    }
+
+    // The forwarder stops once the client disconnects and the stream, with
+    // the receiver, is dropped
+    let server = server.get_ref().clone();
+    let client = client_context(&req);
+    let (notifier, receiver) = mpsc::unbounded_channel();
+    tokio::spawn(async move { server.forward_events(&client, &notifier).await });
+
+    event_stream(receiver)
+}
+
+/// Stream messages to the client as server-sent events
+fn event_stream(receiver: mpsc::UnboundedReceiver<Value>) -> HttpResponse {
+    let body = stream::unfold(receiver, |mut receiver| async move {
+        let message = receiver.recv().await?;
+        let event = web::Bytes::from(format!("event: message\ndata: {}\n\n", message));
+        Some((Ok::<_, actix_web::Error>(event), receiver))
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header((header::CACHE_CONTROL, "no-cache"))
+        .streaming(body)
+}
+
+/// Identify the client from the authentication data of the request
+fn client_context(req: &HttpRequest) -> ClientContext {
+    match req.extensions().get::<AuthData>() {
+        Some(auth) => ClientContext {
+            user_id: auth.user_id.clone(),
+            is_admin: auth.permissions.iter().any(|permission| permission == "admin"),
+        },
+        // Placeholder while authentication is not enforced
+        None => ClientContext {
+            user_id: "anonymous".to_string(),
+            is_admin: true,
+        },
+    }
+}
+
+/// Whether the message has to be answered, as opposed to holding only
+/// notifications or responses
+fn needs_response(message: &Value) -> bool {
+    let needs_response = |message: &Value| {
+        let is_notification = message.get("method").is_some() && message.get("id").is_none();
+        let is_response = message.get("result").is_some() || message.get("error").is_some();
+        !is_notification && !is_response
+    };
+    match message {
+        Value::Array(batch) => batch.is_empty() || batch.iter().any(needs_response),
+        message => needs_response(message),
+    }
+}
+
+/// Whether any request in the message asked for progress notifications
+fn wants_progress(message: &Value) -> bool {
+    let has_token = |message: &Value| message.pointer("/params/_meta/progressToken").is_some();
+    match message {
+        Value::Array(batch) => batch.iter().any(has_token),
+        message => has_token(message),
+    }
+}
+
+/// Whether the client accepts a server-sent event stream
+fn accepts_event_stream(req: &HttpRequest) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(|accept| accept.contains("text/event-stream"))
+        .unwrap_or(false)
+}
@@ -0,0 +1,16 @@
+// MCP module
+//
+// This module implements the Model Context Protocol over JSON-RPC, so that
+// standard MCP clients can discover and call the tools in the registry and
+// follow long-running operations as resources. It is served over stdio and
+// over streamable HTTP.
+
+pub mod protocol;
+pub mod server;
+pub mod stdio;
+pub mod http;
+#[cfg(test)]
+mod tests;
+
+pub use protocol::{JsonRpcError, JsonRpcRequest, JsonRpcResponse, PROTOCOL_VERSION};
+pub use server::{ClientContext, McpServer, Notifier};
@@ -0,0 +1,185 @@
+// MCP protocol types
+//
+// This module defines the JSON-RPC 2.0 messages the Model Context Protocol
+// is built on, and the mapping from tool definitions to MCP tools.
+
+use serde::{Serialize, Deserialize};
+use serde_json::{json, Map, Value};
+
+use crate::tools::ToolDefinition;
+
+/// Version of JSON-RPC used by MCP
+pub const JSONRPC_VERSION: &str = "2.0";
+
+/// Latest MCP revision implemented by this server
+pub const PROTOCOL_VERSION: &str = "2025-03-26";
+
+/// MCP revisions this server can speak, newest first
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[&str] = &["2025-03-26", "2024-11-05"];
+
+/// JSON-RPC and MCP error codes
+pub mod error_codes {
+    pub const PARSE_ERROR: i64 = -32700;
+    pub const INVALID_REQUEST: i64 = -32600;
+    pub const METHOD_NOT_FOUND: i64 = -32601;
+    pub const INVALID_PARAMS: i64 = -32602;
+    pub const INTERNAL_ERROR: i64 = -32603;
+    pub const RESOURCE_NOT_FOUND: i64 = -32002;
+}
+
+/// A JSON-RPC request, or a notification when it has no ID
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcRequest {
+    /// Always "2.0"
+    pub jsonrpc: String,
+    /// Request ID, absent for notifications
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Value>,
+    /// Method to invoke
+    pub method: String,
+    /// Method parameters
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub params: Option<Value>,
+}
+
+/// A JSON-RPC response, carrying either a result or an error
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JsonRpcResponse {
+    /// Always "2.0"
+    pub jsonrpc: String,
+    /// ID of the request this responds to, null if it could not be read
+    pub id: Value,
+    /// Result of a successful request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    /// Error of a failed request
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<JsonRpcError>,
+}
+
+impl JsonRpcResponse {
+    /// Create a successful response
+    pub fn success(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: Some(result),
+            error: None,
+        }
+    }
+
+    /// Create an error response
+    pub fn failure(id: Value, error: JsonRpcError) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(error),
+        }
+    }
+
+    /// Convert to a JSON value
+    pub fn to_value(&self) -> Value {
+        serde_json::to_value(self).unwrap_or_default()
+    }
+}
+
+/// A JSON-RPC error object
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct JsonRpcError {
+    /// Error code
+    pub code: i64,
+    /// Short description of the error
+    pub message: String,
+    /// Additional information about the error
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub data: Option<Value>,
+}
+
+impl JsonRpcError {
+    /// Create an error with the given code and message
+    pub fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+
+    /// The message was not valid JSON
+    pub fn parse_error(message: impl Into<String>) -> Self {
+        Self::new(error_codes::PARSE_ERROR, message)
+    }
+
+    /// The message was not a valid JSON-RPC request
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(error_codes::INVALID_REQUEST, message)
+    }
+
+    /// The method does not exist
+    pub fn method_not_found(method: &str) -> Self {
+        Self::new(error_codes::METHOD_NOT_FOUND, format!("Method not found: {}", method))
+    }
+
+    /// The parameters of the request were invalid
+    pub fn invalid_params(message: impl Into<String>) -> Self {
+        Self::new(error_codes::INVALID_PARAMS, message)
+    }
+
+    /// The server failed to handle the request
+    pub fn internal_error(message: impl Into<String>) -> Self {
+        Self::new(error_codes::INTERNAL_ERROR, message)
+    }
+
+    /// The requested resource does not exist
+    pub fn resource_not_found(uri: &str) -> Self {
+        Self {
+            code: error_codes::RESOURCE_NOT_FOUND,
+            message: "Resource not found".to_string(),
+            data: Some(json!({ "uri": uri })),
+        }
+    }
+}
+
+/// Build a JSON-RPC notification
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({
+        "jsonrpc": JSONRPC_VERSION,
+        "method": method,
+        "params": params,
+    })
+}
+
+/// Convert a tool definition to an MCP tool, describing its parameters as a
+/// JSON Schema object
+pub fn tool_to_mcp(definition: &ToolDefinition) -> Value {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+
+    for param in &definition.parameters {
+        let mut schema = Map::new();
+        schema.insert("type".to_string(), json!(param.parameter_type));
+        schema.insert("description".to_string(), json!(param.description));
+        if let Some(default) = &param.default {
+            schema.insert("default".to_string(), default.clone());
+        }
+        if let Some(values) = &param.enum_values {
+            schema.insert("enum".to_string(), json!(values));
+        }
+        properties.insert(param.name.clone(), Value::Object(schema));
+
+        if param.required {
+            required.push(json!(param.name));
+        }
+    }
+
+    json!({
+        "name": definition.name,
+        "description": definition.description,
+        "inputSchema": {
+            "type": "object",
+            "properties": properties,
+            "required": required,
+        },
+    })
+}
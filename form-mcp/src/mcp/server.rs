@@ -0,0 +1,462 @@
+// MCP server
+//
+// This module dispatches MCP JSON-RPC messages to the tool registry and the
+// operations repository. It is shared by the stdio and HTTP transports,
+// which only move messages in and out.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use futures_util::future::join_all;
+use serde_json::{json, Value};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+use crate::events::{Delivery, EventService};
+use crate::mcp::protocol::{
+    notification, tool_to_mcp, JsonRpcError, JsonRpcRequest, JsonRpcResponse,
+    JSONRPC_VERSION, PROTOCOL_VERSION, SUPPORTED_PROTOCOL_VERSIONS,
+};
+use crate::models::operations::{Operation, OperationStatus, OperationsRepository};
+use crate::tools::{ToolContext, ToolRegistry, ToolRequest, ToolResponse};
+
+/// URI scheme operations are exposed as resources under
+const OPERATION_SCHEME: &str = "operation://";
+
+/// Channel notifications are sent to the client through
+pub type Notifier = UnboundedSender<Value>;
+
+/// Identity of the client a message was received from
+#[derive(Debug, Clone)]
+pub struct ClientContext {
+    /// User ID of the client
+    pub user_id: String,
+    /// Whether the client has admin privileges
+    pub is_admin: bool,
+}
+
+impl ClientContext {
+    /// The client of the stdio transport, which is the local user that
+    /// started the server
+    pub fn local() -> Self {
+        Self {
+            user_id: "local".to_string(),
+            is_admin: true,
+        }
+    }
+}
+
+/// McpServer handles MCP messages on behalf of a transport
+#[derive(Clone)]
+pub struct McpServer {
+    registry: Arc<ToolRegistry>,
+    operations: Arc<OperationsRepository>,
+    /// Operations of the long-running tool calls in flight, by client and
+    /// request ID, so clients can cancel them
+    in_flight: Arc<Mutex<HashMap<(String, String), String>>>,
+    events: Option<Arc<EventService>>,
+}
+
+impl McpServer {
+    /// Create a new MCP server
+    pub fn new(registry: Arc<ToolRegistry>, operations: Arc<OperationsRepository>) -> Self {
+        Self {
+            registry,
+            operations,
+            in_flight: Arc::new(Mutex::new(HashMap::new())),
+            events: None,
+        }
+    }
+
+    /// Deliver the events of the clients' MCP subscriptions to them
+    pub fn with_events(mut self, events: Arc<EventService>) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Whether the server delivers events to its clients
+    pub fn has_events(&self) -> bool {
+        self.events.is_some()
+    }
+
+    /// Send the events matching the client's MCP subscriptions through
+    /// `notifier` as `notifications/message`, until the client is gone
+    pub async fn forward_events(&self, client: &ClientContext, notifier: &Notifier) {
+        let Some(events) = &self.events else {
+            return;
+        };
+        let mut listener = events.listen();
+        loop {
+            let event = tokio::select! {
+                event = listener.recv() => match event {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(skipped)) => {
+                        log::warn!("MCP client {} fell behind, {} events were dropped", client.user_id, skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                },
+                _ = notifier.closed() => return,
+            };
+
+            let subscriptions = events.subscriptions().matching(&event).await;
+            for subscription in subscriptions.iter().filter(|sub| {
+                sub.delivery == Delivery::Mcp && sub.user_id == client.user_id
+            }) {
+                let sent = notifier.send(notification("notifications/message", json!({
+                    "level": "info",
+                    "logger": "form-events",
+                    "data": {
+                        "subscription_id": subscription.id,
+                        "event": event,
+                    },
+                })));
+                if sent.is_err() {
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Handle a message or a batch of messages, returning the response to
+    /// send back, if any. Notifications raised while handling the message
+    /// are sent through `notifier`.
+    pub async fn handle(&self, message: Value, client: &ClientContext, notifier: &Notifier) -> Option<Value> {
+        match message {
+            Value::Array(batch) if batch.is_empty() => Some(
+                JsonRpcResponse::failure(Value::Null, JsonRpcError::invalid_request("Empty batch")).to_value()
+            ),
+            Value::Array(batch) => {
+                let responses: Vec<Value> = join_all(
+                    batch.into_iter().map(|message| self.handle_message(message, client, notifier))
+                ).await.into_iter().flatten().collect();
+
+                if responses.is_empty() {
+                    None
+                } else {
+                    Some(Value::Array(responses))
+                }
+            }
+            message => self.handle_message(message, client, notifier).await,
+        }
+    }
+
+    /// Handle a single message
+    async fn handle_message(&self, message: Value, client: &ClientContext, notifier: &Notifier) -> Option<Value> {
+        let id = message.get("id").cloned().unwrap_or(Value::Null);
+
+        let request: JsonRpcRequest = match serde_json::from_value(message.clone()) {
+            Ok(request) => request,
+            // Responses from the client need no answer; this server sends no requests
+            Err(_) if message.get("result").is_some() || message.get("error").is_some() => return None,
+            Err(e) => return Some(
+                JsonRpcResponse::failure(id, JsonRpcError::invalid_request(e.to_string())).to_value()
+            ),
+        };
+
+        if request.jsonrpc != JSONRPC_VERSION {
+            return Some(JsonRpcResponse::failure(
+                id,
+                JsonRpcError::invalid_request(format!("Unsupported JSON-RPC version: {}", request.jsonrpc)),
+            ).to_value());
+        }
+
+        let Some(id) = request.id.clone() else {
+            self.handle_notification(&request, client).await;
+            return None;
+        };
+
+        let params = request.params.clone().unwrap_or_else(|| json!({}));
+        let response = match self.dispatch(&request.method, params, &id, client, notifier).await {
+            Ok(result) => JsonRpcResponse::success(id, result),
+            Err(error) => JsonRpcResponse::failure(id, error),
+        };
+
+        Some(response.to_value())
+    }
+
+    /// Handle a notification from the client
+    async fn handle_notification(&self, request: &JsonRpcRequest, client: &ClientContext) {
+        match request.method.as_str() {
+            "notifications/initialized" => log::info!("MCP client initialized"),
+            "notifications/cancelled" => {
+                let request_id = request.params.as_ref()
+                    .and_then(|params| params.get("requestId"))
+                    .cloned()
+                    .unwrap_or(Value::Null);
+                log::info!("MCP client cancelled request {}", request_id);
+
+                // Cancelling a long-running tool call cancels its operation
+                let key = (client.user_id.clone(), request_id.to_string());
+                if let Some(operation_id) = self.in_flight.lock().await.remove(&key) {
+                    if let Err(e) = self.operations.cancel(&operation_id).await {
+                        log::debug!("Could not cancel operation {}: {}", operation_id, e);
+                    }
+                }
+            }
+            method => log::debug!("Ignoring MCP notification {}", method),
+        }
+    }
+
+    /// Dispatch a request to its method
+    async fn dispatch(
+        &self,
+        method: &str,
+        params: Value,
+        request_id: &Value,
+        client: &ClientContext,
+        notifier: &Notifier,
+    ) -> Result<Value, JsonRpcError> {
+        match method {
+            "initialize" => Ok(self.initialize(&params)),
+            "ping" => Ok(json!({})),
+            // Events are the only messages logged to clients, at info level
+            "logging/setLevel" => Ok(json!({})),
+            "tools/list" => Ok(self.list_tools()),
+            "tools/call" => self.call_tool(params, request_id, client, notifier).await,
+            "resources/list" => Ok(self.list_resources(client).await),
+            "resources/templates/list" => Ok(self.list_resource_templates()),
+            "resources/read" => self.read_resource(&params, client).await,
+            _ => Err(JsonRpcError::method_not_found(method)),
+        }
+    }
+
+    /// Negotiate the protocol version and advertise the server's capabilities
+    fn initialize(&self, params: &Value) -> Value {
+        let requested = params.get("protocolVersion").and_then(Value::as_str);
+        let version = requested
+            .filter(|version| SUPPORTED_PROTOCOL_VERSIONS.contains(version))
+            .unwrap_or(PROTOCOL_VERSION);
+
+        json!({
+            "protocolVersion": version,
+            "capabilities": {
+                "tools": { "listChanged": false },
+                "logging": {},
+                "resources": { "subscribe": false, "listChanged": false },
+            },
+            "serverInfo": {
+                "name": "form-mcp",
+                "version": crate::MCP_VERSION,
+            },
+        })
+    }
+
+    /// List the tools in the registry
+    fn list_tools(&self) -> Value {
+        let mut tools = self.registry.list_tools();
+        tools.sort_by(|a, b| a.name.cmp(&b.name));
+
+        json!({
+            "tools": tools.iter().map(tool_to_mcp).collect::<Vec<_>>(),
+        })
+    }
+
+    /// Execute a tool. Failures of the tool itself are reported in the
+    /// result, so the model can see them; only unknown tools and invalid
+    /// arguments are protocol errors.
+    async fn call_tool(
+        &self,
+        params: Value,
+        request_id: &Value,
+        client: &ClientContext,
+        notifier: &Notifier,
+    ) -> Result<Value, JsonRpcError> {
+        let name = params.get("name")
+            .and_then(Value::as_str)
+            .ok_or_else(|| JsonRpcError::invalid_params("Missing tool name"))?
+            .to_string();
+        let arguments = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+        let progress_token = params.get("_meta")
+            .and_then(|meta| meta.get("progressToken"))
+            .cloned();
+
+        let tool = self.registry.get_tool(&name)
+            .ok_or_else(|| JsonRpcError::invalid_params(format!("Unknown tool: {}", name)))?;
+        tool.validate_params(&arguments)
+            .map_err(|e| JsonRpcError::invalid_params(e.to_string()))?;
+
+        let request = ToolRequest {
+            name: name.clone(),
+            parameters: arguments,
+            context: None,
+        };
+        let context = ToolContext {
+            user_id: client.user_id.clone(),
+            request_id: Uuid::new_v4().to_string(),
+            context: Default::default(),
+            is_admin: client.is_admin,
+            operation: None,
+        };
+
+        if tool.definition().is_long_running.unwrap_or(false) {
+            let key = (client.user_id.clone(), request_id.to_string());
+            return self.call_long_running(request, context, key, progress_token, notifier).await;
+        }
+
+        crate::tools::execute_tool(self.registry.clone(), request, context).await
+            .map(|response| tool_result(response, None))
+            .map_err(|e| JsonRpcError::internal_error(e.to_string()))
+    }
+
+    /// Execute a long-running tool as an operation, forwarding the progress
+    /// it reports to the client if the client asked for it
+    async fn call_long_running(
+        &self,
+        request: ToolRequest,
+        context: ToolContext,
+        key: (String, String),
+        progress_token: Option<Value>,
+        notifier: &Notifier,
+    ) -> Result<Value, JsonRpcError> {
+        // Subscribe before spawning, so no change of the operation is missed
+        let mut events = self.operations.subscribe();
+        let operation = self.operations.spawn(self.registry.clone(), request, context).await;
+        let operation_id = operation.id.clone();
+        self.in_flight.lock().await.insert(key.clone(), operation_id.clone());
+
+        let mut reported: Option<(f32, Option<String>)> = None;
+        let mut current = Some(operation);
+        let operation = loop {
+            let Some(operation) = current.take() else {
+                break None;
+            };
+            if operation.is_finished() {
+                break Some(operation);
+            }
+
+            if let Some(token) = &progress_token {
+                let progress = operation.progress.unwrap_or(0.0);
+                let state = (progress, operation.message.clone());
+                // Progress must increase between notifications, so only
+                // report it when it moved or the tool has something new to say
+                let changed = match &reported {
+                    Some((last, message)) => progress > *last || state.1 != *message,
+                    None => true,
+                };
+                if changed {
+                    let message = operation.message.clone()
+                        .unwrap_or_else(|| format!("Operation {} is running", operation_id));
+                    let _ = notifier.send(notification("notifications/progress", json!({
+                        "progressToken": token,
+                        "progress": progress,
+                        "total": 1.0,
+                        "message": message,
+                    })));
+                    reported = Some(state);
+                }
+            }
+
+            current = loop {
+                match events.recv().await {
+                    Ok(update) if update.id == operation_id => break Some(update),
+                    Ok(_) => continue,
+                    // Changes were missed, so catch up with the current state
+                    Err(RecvError::Lagged(_)) => break self.operations.get_operation(&operation_id).await,
+                    Err(RecvError::Closed) => break None,
+                }
+            };
+        };
+        self.in_flight.lock().await.remove(&key);
+
+        let operation = operation
+            .ok_or_else(|| JsonRpcError::internal_error(format!("Operation {} was lost", operation_id)))?;
+        Ok(tool_result(operation_response(&operation), Some(&operation_id)))
+    }
+
+    /// List the operations visible to the client as resources
+    async fn list_resources(&self, client: &ClientContext) -> Value {
+        let operations = if client.is_admin {
+            self.operations.list_operations().await
+        } else {
+            self.operations.get_operations_by_user(&client.user_id).await
+        };
+
+        let resources: Vec<Value> = operations.iter().map(|operation| json!({
+            "uri": format!("{}{}", OPERATION_SCHEME, operation.id),
+            "name": format!("Operation {}", operation.id),
+            "description": format!("{} operation of tool {}", operation.status, operation.tool_name),
+            "mimeType": "application/json",
+        })).collect();
+
+        json!({ "resources": resources })
+    }
+
+    /// List the templates of the resources the server exposes
+    fn list_resource_templates(&self) -> Value {
+        json!({
+            "resourceTemplates": [{
+                "uriTemplate": format!("{}{{id}}", OPERATION_SCHEME),
+                "name": "Operation",
+                "description": "Status and result of a long-running tool execution",
+                "mimeType": "application/json",
+            }],
+        })
+    }
+
+    /// Read a resource
+    async fn read_resource(&self, params: &Value, client: &ClientContext) -> Result<Value, JsonRpcError> {
+        let uri = params.get("uri")
+            .and_then(Value::as_str)
+            .ok_or_else(|| JsonRpcError::invalid_params("Missing resource URI"))?;
+
+        let operation = match uri.strip_prefix(OPERATION_SCHEME) {
+            Some(id) => self.operations.get_operation(id).await,
+            None => None,
+        };
+        let operation = operation
+            .filter(|operation| client.is_admin || operation.user_id == client.user_id)
+            .ok_or_else(|| JsonRpcError::resource_not_found(uri))?;
+
+        let text = serde_json::to_string(&operation.to_api_response())
+            .map_err(|e| JsonRpcError::internal_error(e.to_string()))?;
+
+        Ok(json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": "application/json",
+                "text": text,
+            }],
+        }))
+    }
+}
+
+/// The tool response of a finished operation
+fn operation_response(operation: &Operation) -> ToolResponse {
+    let response = operation.result.clone()
+        .and_then(|result| serde_json::from_value::<ToolResponse>(result).ok());
+    match (&operation.status, response) {
+        (OperationStatus::Completed, Some(response)) => response,
+        (OperationStatus::Cancelled, _) => ToolResponse {
+            status: "error".to_string(),
+            result: None,
+            error: Some(format!("Operation {} was cancelled", operation.id)),
+        },
+        _ => ToolResponse {
+            status: "error".to_string(),
+            result: None,
+            error: Some(operation.error.clone().unwrap_or_else(|| "Tool execution failed".to_string())),
+        },
+    }
+}
+
+/// Convert a tool response to an MCP tool result
+fn tool_result(response: ToolResponse, operation_id: Option<&str>) -> Value {
+    let is_error = response.status != "success";
+    let text = if is_error {
+        response.error.unwrap_or_else(|| "Tool execution failed".to_string())
+    } else {
+        let result = response.result.unwrap_or(Value::Null);
+        serde_json::to_string_pretty(&result).unwrap_or_default()
+    };
+
+    let mut result = json!({
+        "content": [{ "type": "text", "text": text }],
+        "isError": is_error,
+    });
+    if let Some(operation_id) = operation_id {
+        result["_meta"] = json!({ "operationId": operation_id });
+    }
+    result
+}
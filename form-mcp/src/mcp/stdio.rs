@@ -0,0 +1,77 @@
+// MCP stdio transport
+//
+// This module serves MCP over standard input and output, for clients that
+// launch form-mcp as a subprocess. Messages are newline-delimited JSON;
+// logs go to standard error so they don't interleave with messages.
+
+use std::sync::Arc;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::sync::mpsc;
+
+use crate::events::EventService;
+use crate::mcp::protocol::{JsonRpcError, JsonRpcResponse};
+use crate::mcp::server::{ClientContext, McpServer};
+use crate::models::operations::OperationsRepository;
+use crate::tools::ToolRegistry;
+
+/// Serve MCP over stdio until standard input is closed
+pub async fn serve(
+    registry: Arc<ToolRegistry>,
+    operations: Arc<OperationsRepository>,
+    events: Arc<EventService>,
+) -> std::io::Result<()> {
+    let server = McpServer::new(registry, operations).with_events(events);
+    let (outgoing, mut messages) = mpsc::unbounded_channel::<Value>();
+
+    // A single writer keeps messages from interleaving on stdout
+    let writer = tokio::spawn(async move {
+        let mut stdout = tokio::io::stdout();
+        while let Some(message) = messages.recv().await {
+            let mut line = message.to_string();
+            line.push('\n');
+            stdout.write_all(line.as_bytes()).await?;
+            stdout.flush().await?;
+        }
+        Ok::<_, std::io::Error>(())
+    });
+
+    // Events of the local user's subscriptions are sent as they happen
+    let forwarder = {
+        let server = server.clone();
+        let outgoing = outgoing.clone();
+        tokio::spawn(async move { server.forward_events(&ClientContext::local(), &outgoing).await })
+    };
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let message: Value = match serde_json::from_str(&line) {
+            Ok(message) => message,
+            Err(e) => {
+                log::warn!("Received malformed MCP message: {}", e);
+                let response = JsonRpcResponse::failure(Value::Null, JsonRpcError::parse_error(e.to_string()));
+                let _ = outgoing.send(response.to_value());
+                continue;
+            }
+        };
+
+        // Handle each message on its own so long-running tools don't block
+        // the requests that follow them
+        let server = server.clone();
+        let outgoing = outgoing.clone();
+        tokio::spawn(async move {
+            if let Some(response) = server.handle(message, &ClientContext::local(), &outgoing).await {
+                let _ = outgoing.send(response);
+            }
+        });
+    }
+
+    log::info!("Standard input closed, stopping MCP stdio transport");
+    forwarder.abort();
+    drop(outgoing);
+    writer.await.map_err(std::io::Error::other)?
+}
@@ -0,0 +1,202 @@
+use std::sync::Arc;
+use std::time::Duration;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+use tokio::sync::mpsc;
+
+use crate::errors::ToolError;
+use crate::mcp::protocol::error_codes;
+use crate::mcp::server::{ClientContext, McpServer};
+use crate::models::operations::create_repository;
+use crate::tools::{Tool, ToolContext, ToolDefinition, ToolParameter, ToolRegistry, ToolResult};
+
+/// Tool that echoes its message back, optionally taking a while to do so
+struct EchoTool {
+    name: &'static str,
+    long_running: bool,
+}
+
+#[async_trait]
+impl Tool for EchoTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: self.name.to_string(),
+            description: "Echoes a message".to_string(),
+            version: "1.0.0".to_string(),
+            parameters: vec![ToolParameter {
+                name: "message".to_string(),
+                description: "Message to echo".to_string(),
+                required: true,
+                parameter_type: "string".to_string(),
+                default: None,
+                enum_values: None,
+            }],
+            return_type: "object".to_string(),
+            tags: vec!["test".to_string()],
+            is_long_running: Some(self.long_running),
+        }
+    }
+
+    async fn execute(&self, params: Value, _context: ToolContext) -> ToolResult {
+        if self.long_running {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        match params["message"].as_str() {
+            Some("fail") => Err(ToolError::ExecutionFailed("asked to fail".to_string())),
+            _ => Ok(json!({ "echo": params["message"] })),
+        }
+    }
+}
+
+fn create_server() -> McpServer {
+    let registry = ToolRegistry::new();
+    registry.register_tool(Arc::new(EchoTool { name: "echo", long_running: false })).unwrap();
+    registry.register_tool(Arc::new(EchoTool { name: "slow_echo", long_running: true })).unwrap();
+    McpServer::new(Arc::new(registry), create_repository())
+}
+
+async fn request(server: &McpServer, method: &str, params: Value) -> (Value, Vec<Value>) {
+    let (notifier, mut notifications) = mpsc::unbounded_channel();
+    let message = json!({ "jsonrpc": "2.0", "id": 1, "method": method, "params": params });
+    let response = server.handle(message, &ClientContext::local(), &notifier).await.unwrap();
+
+    drop(notifier);
+    let mut received = Vec::new();
+    while let Some(notification) = notifications.recv().await {
+        received.push(notification);
+    }
+    (response, received)
+}
+
+#[tokio::test]
+async fn test_initialize() {
+    let server = create_server();
+
+    let (response, _) = request(&server, "initialize", json!({
+        "protocolVersion": "2024-11-05",
+        "capabilities": {},
+        "clientInfo": { "name": "test", "version": "1.0" },
+    })).await;
+    assert_eq!(response["id"], 1);
+    assert_eq!(response["result"]["protocolVersion"], "2024-11-05");
+    assert_eq!(response["result"]["serverInfo"]["name"], "form-mcp");
+    assert!(response["result"]["capabilities"]["tools"].is_object());
+
+    // Unknown revisions are answered with the latest one
+    let (response, _) = request(&server, "initialize", json!({ "protocolVersion": "1999-01-01" })).await;
+    assert_eq!(response["result"]["protocolVersion"], crate::mcp::PROTOCOL_VERSION);
+}
+
+#[tokio::test]
+async fn test_tools_list() {
+    let server = create_server();
+
+    let (response, _) = request(&server, "tools/list", json!({})).await;
+    let tools = response["result"]["tools"].as_array().unwrap();
+    assert_eq!(tools.len(), 2);
+    assert_eq!(tools[0]["name"], "echo");
+    assert_eq!(tools[0]["inputSchema"]["type"], "object");
+    assert_eq!(tools[0]["inputSchema"]["properties"]["message"]["type"], "string");
+    assert_eq!(tools[0]["inputSchema"]["required"], json!(["message"]));
+}
+
+#[tokio::test]
+async fn test_tools_call() {
+    let server = create_server();
+
+    let (response, _) = request(&server, "tools/call", json!({
+        "name": "echo",
+        "arguments": { "message": "hello" },
+    })).await;
+    let result = &response["result"];
+    assert_eq!(result["isError"], false);
+    let text = result["content"][0]["text"].as_str().unwrap();
+    assert_eq!(serde_json::from_str::<Value>(text).unwrap(), json!({ "echo": "hello" }));
+
+    // Tool failures are reported in the result
+    let (response, _) = request(&server, "tools/call", json!({
+        "name": "echo",
+        "arguments": { "message": "fail" },
+    })).await;
+    assert_eq!(response["result"]["isError"], true);
+
+    // Unknown tools and invalid arguments are protocol errors
+    let (response, _) = request(&server, "tools/call", json!({ "name": "missing", "arguments": {} })).await;
+    assert_eq!(response["error"]["code"], error_codes::INVALID_PARAMS);
+    let (response, _) = request(&server, "tools/call", json!({ "name": "echo", "arguments": {} })).await;
+    assert_eq!(response["error"]["code"], error_codes::INVALID_PARAMS);
+}
+
+#[tokio::test]
+async fn test_long_running_tool_reports_progress() {
+    let server = create_server();
+
+    let (response, notifications) = request(&server, "tools/call", json!({
+        "name": "slow_echo",
+        "arguments": { "message": "hello" },
+        "_meta": { "progressToken": "token-1" },
+    })).await;
+    assert_eq!(response["result"]["isError"], false);
+    assert!(!notifications.is_empty());
+    assert_eq!(notifications[0]["method"], "notifications/progress");
+    assert_eq!(notifications[0]["params"]["progressToken"], "token-1");
+
+    // The execution is kept as an operation resource
+    let operation_id = response["result"]["_meta"]["operationId"].as_str().unwrap();
+    let uri = format!("operation://{}", operation_id);
+
+    let (response, _) = request(&server, "resources/list", json!({})).await;
+    assert_eq!(response["result"]["resources"][0]["uri"], uri);
+
+    let (response, _) = request(&server, "resources/read", json!({ "uri": uri })).await;
+    let text = response["result"]["contents"][0]["text"].as_str().unwrap();
+    let status: Value = serde_json::from_str(text).unwrap();
+    assert_eq!(status["status"], "completed");
+
+    let (response, _) = request(&server, "resources/read", json!({ "uri": "operation://missing" })).await;
+    assert_eq!(response["error"]["code"], error_codes::RESOURCE_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_progress_is_only_sent_when_requested() {
+    let server = create_server();
+
+    let (response, notifications) = request(&server, "tools/call", json!({
+        "name": "slow_echo",
+        "arguments": { "message": "hello" },
+    })).await;
+    assert_eq!(response["result"]["isError"], false);
+    assert!(notifications.is_empty());
+}
+
+#[tokio::test]
+async fn test_unknown_method() {
+    let server = create_server();
+
+    let (response, _) = request(&server, "prompts/list", json!({})).await;
+    assert_eq!(response["error"]["code"], error_codes::METHOD_NOT_FOUND);
+}
+
+#[tokio::test]
+async fn test_notifications_and_batches() {
+    let server = create_server();
+    let (notifier, _notifications) = mpsc::unbounded_channel();
+    let client = ClientContext::local();
+
+    let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+    assert!(server.handle(initialized.clone(), &client, &notifier).await.is_none());
+
+    let batch = json!([
+        initialized,
+        { "jsonrpc": "2.0", "id": 1, "method": "ping" },
+        { "jsonrpc": "1.0", "id": 2, "method": "ping" },
+    ]);
+    let responses = server.handle(batch, &client, &notifier).await.unwrap();
+    let responses = responses.as_array().unwrap();
+    assert_eq!(responses.len(), 2);
+    assert_eq!(responses[0]["result"], json!({}));
+    assert_eq!(responses[1]["error"]["code"], error_codes::INVALID_REQUEST);
+
+    let responses = server.handle(json!([]), &client, &notifier).await.unwrap();
+    assert_eq!(responses["error"]["code"], error_codes::INVALID_REQUEST);
+}
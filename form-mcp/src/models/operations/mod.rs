@@ -3,14 +3,19 @@
 // This module provides types and functions for managing long-running operations.
 
 mod repository;
+mod store;
 #[cfg(test)]
 mod tests;
 
-use std::time::{Duration, SystemTime};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 
-pub use repository::{OperationsRepository, create_repository};
+pub use repository::{OperationsRepository, OperationHandle, create_repository, open_repository};
+pub use store::OperationStore;
+
+/// Maximum number of log lines kept per operation
+const MAX_LOG_LINES: usize = 500;
 
 /// Status of an operation
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -39,6 +44,15 @@ impl std::fmt::Display for OperationStatus {
     }
 }
 
+/// A line logged by a long-running operation
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct OperationLog {
+    /// When the line was logged, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// The logged message
+    pub message: String,
+}
+
 /// Represents a long-running operation
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Operation {
@@ -64,6 +78,18 @@ pub struct Operation {
     pub completed_at: Option<SystemTime>,
     /// Time-to-live for the operation record
     pub ttl: Duration,
+    /// Parameters the tool was called with
+    #[serde(default)]
+    pub parameters: Option<serde_json::Value>,
+    /// Latest status message reported by the tool
+    #[serde(default)]
+    pub message: Option<String>,
+    /// Lines logged by the tool while it ran
+    #[serde(default)]
+    pub logs: Vec<OperationLog>,
+    /// State saved by the tool to find out how far it got after a restart
+    #[serde(default)]
+    pub checkpoint: Option<serde_json::Value>,
 }
 
 impl Operation {
@@ -82,6 +108,10 @@ impl Operation {
             updated_at: now,
             completed_at: None,
             ttl: Duration::from_secs(3600), // 1 hour by default
+            parameters: None,
+            message: None,
+            logs: Vec::new(),
+            checkpoint: None,
         }
     }
     
@@ -97,6 +127,25 @@ impl Operation {
         self.updated_at = SystemTime::now();
     }
     
+    /// Set the status message of the operation
+    pub fn set_message(&mut self, message: String) {
+        self.message = Some(message);
+        self.updated_at = SystemTime::now();
+    }
+    
+    /// Append a line to the log of the operation, dropping the oldest
+    /// lines once the log is full
+    pub fn append_log(&mut self, message: String) {
+        let now = SystemTime::now();
+        let timestamp = now.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+        self.logs.push(OperationLog { timestamp, message });
+        if self.logs.len() > MAX_LOG_LINES {
+            let excess = self.logs.len() - MAX_LOG_LINES;
+            self.logs.drain(..excess);
+        }
+        self.updated_at = now;
+    }
+    
     /// Mark the operation as completed
     pub fn mark_completed(&mut self, result: serde_json::Value) {
         let now = SystemTime::now();
@@ -124,6 +173,14 @@ impl Operation {
         self.completed_at = Some(now);
     }
     
+    /// Check if the operation has reached a terminal state
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self.status,
+            OperationStatus::Completed | OperationStatus::Failed | OperationStatus::Cancelled
+        )
+    }
+    
     /// Check if the operation record has expired
     pub fn is_expired(&self) -> bool {
         match self.status {
@@ -151,6 +208,8 @@ impl Operation {
             progress: self.progress,
             result: self.result.clone(),
             error: self.error.clone(),
+            message: self.message.clone(),
+            logs: self.logs.clone(),
         }
     }
 } 
\ No newline at end of file
@@ -1,49 +1,102 @@
 // Operations repository
 //
-// This module provides a repository for managing operation state.
+// This module provides a repository for managing operation state. Operations
+// are kept in memory and, when the repository has a store, written through
+// to disk so that they survive a restart. Every change is broadcast to
+// subscribers, which is how progress and logs are streamed to clients.
 
 use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
 use std::sync::Arc;
-use tokio::sync::RwLock;
-use std::time::{Duration, SystemTime};
+use futures_util::FutureExt;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, Mutex, RwLock};
+use tokio::task::AbortHandle;
+use std::time::Duration;
 
-use super::Operation;
+use super::{Operation, OperationStore};
+use crate::config::Settings;
+use crate::errors::ToolError;
+use crate::tools::{ToolContext, ToolRegistry, ToolRequest, ToolResponse};
+
+/// Number of updates a subscriber can fall behind before it misses some
+const EVENT_CAPACITY: usize = 256;
 
 /// Repository for managing operations
 #[derive(Debug, Clone)]
 pub struct OperationsRepository {
     operations: Arc<RwLock<HashMap<String, Operation>>>,
+    store: Option<OperationStore>,
+    events: broadcast::Sender<Operation>,
+    tasks: Arc<Mutex<HashMap<String, AbortHandle>>>,
+    interrupted: Arc<Mutex<Vec<String>>>,
+    ttl: Duration,
     cleanup_interval: Duration,
 }
 
 impl OperationsRepository {
-    /// Create a new operations repository
+    /// Create a new in-memory operations repository
     pub fn new() -> Self {
-        let repo = Self {
-            operations: Arc::new(RwLock::new(HashMap::new())),
-            cleanup_interval: Duration::from_secs(300), // 5 minutes
-        };
-        
+        let repo = Self::build(None, HashMap::new(), Duration::from_secs(3600), Duration::from_secs(300));
+
         // Start background cleanup task
         repo.start_cleanup_task();
-        
+
         repo
     }
-    
+
+    /// Create an operations repository backed by `store`, loading the
+    /// operations it holds. Operations that were still queued or running
+    /// were interrupted by a restart and are left for `reconcile`.
+    pub fn with_store(store: OperationStore, ttl: Duration, cleanup_interval: Duration) -> std::io::Result<Self> {
+        let operations: HashMap<String, Operation> = store.load_all()?
+            .into_iter()
+            .map(|operation| (operation.id.clone(), operation))
+            .collect();
+
+        let repo = Self::build(Some(store), operations, ttl, cleanup_interval);
+        repo.start_cleanup_task();
+        Ok(repo)
+    }
+
+    fn build(
+        store: Option<OperationStore>,
+        operations: HashMap<String, Operation>,
+        ttl: Duration,
+        cleanup_interval: Duration,
+    ) -> Self {
+        let interrupted = operations.values()
+            .filter(|op| !op.is_finished())
+            .map(|op| op.id.clone())
+            .collect();
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+
+        Self {
+            operations: Arc::new(RwLock::new(operations)),
+            store,
+            events,
+            tasks: Arc::new(Mutex::new(HashMap::new())),
+            interrupted: Arc::new(Mutex::new(interrupted)),
+            ttl,
+            cleanup_interval,
+        }
+    }
+
     /// Add a new operation to the repository
     pub async fn add_operation(&self, operation: Operation) -> String {
         let id = operation.id.clone();
         let mut operations = self.operations.write().await;
+        self.persist(&operation).await;
         operations.insert(id.clone(), operation);
         id
     }
-    
+
     /// Get an operation by ID
     pub async fn get_operation(&self, id: &str) -> Option<Operation> {
         let operations = self.operations.read().await;
         operations.get(id).cloned()
     }
-    
+
     /// Get operations by user ID
     pub async fn get_operations_by_user(&self, user_id: &str) -> Vec<Operation> {
         let operations = self.operations.read().await;
@@ -53,35 +106,224 @@ impl OperationsRepository {
             .cloned()
             .collect()
     }
-    
+
+    /// Get every operation in the repository
+    pub async fn list_operations(&self) -> Vec<Operation> {
+        let operations = self.operations.read().await;
+        operations.values().cloned().collect()
+    }
+
     /// Update an operation
     pub async fn update_operation(&self, operation: Operation) -> Result<(), String> {
         let mut operations = self.operations.write().await;
         if operations.contains_key(&operation.id) {
+            self.persist(&operation).await;
             operations.insert(operation.id.clone(), operation);
             Ok(())
         } else {
             Err(format!("Operation with ID '{}' not found", operation.id))
         }
     }
-    
+
     /// Remove an operation from the repository
     pub async fn remove_operation(&self, id: &str) -> Option<Operation> {
         let mut operations = self.operations.write().await;
-        operations.remove(id)
+        let operation = operations.remove(id);
+        if operation.is_some() {
+            self.unpersist(id).await;
+        }
+        operation
+    }
+
+    /// Subscribe to changes of the operations in the repository
+    pub fn subscribe(&self) -> broadcast::Receiver<Operation> {
+        self.events.subscribe()
+    }
+
+    /// Report the progress of a running operation
+    pub async fn report_progress(&self, id: &str, progress: f32, message: Option<String>) {
+        self.modify(id, |op| {
+            if !op.is_finished() {
+                op.update_progress(progress);
+                if let Some(message) = message {
+                    op.set_message(message);
+                }
+            }
+        }).await;
+    }
+
+    /// Append a line to the log of a running operation
+    pub async fn append_log(&self, id: &str, message: String) {
+        self.modify(id, |op| {
+            if !op.is_finished() {
+                op.append_log(message);
+            }
+        }).await;
+    }
+
+    /// Save the checkpoint of a running operation
+    pub async fn save_checkpoint(&self, id: &str, checkpoint: Value) {
+        self.modify(id, |op| {
+            if !op.is_finished() {
+                op.checkpoint = Some(checkpoint);
+            }
+        }).await;
+    }
+
+    /// Cancel an operation, stopping its tool if it is still executing
+    pub async fn cancel(&self, id: &str) -> Result<Operation, String> {
+        if let Some(task) = self.tasks.lock().await.remove(id) {
+            task.abort();
+        }
+
+        let operation = self.modify(id, |op| {
+            if !op.is_finished() {
+                op.append_log("Operation cancelled".to_string());
+                op.mark_cancelled();
+            }
+        }).await.ok_or_else(|| format!("Operation with ID '{}' not found", id))?;
+
+        match operation.status {
+            super::OperationStatus::Cancelled => Ok(operation),
+            status => Err(format!("Operation with ID '{}' has already {}", id, status)),
+        }
+    }
+
+    /// Execute a tool as an operation in the background, returning the
+    /// operation. The tool reports progress and logs through the handle in
+    /// its context.
+    pub async fn spawn(
+        &self,
+        registry: Arc<ToolRegistry>,
+        request: ToolRequest,
+        mut context: ToolContext,
+    ) -> Operation {
+        let mut operation = Operation::new(context.user_id.clone(), request.name.clone());
+        operation.ttl = self.ttl;
+        operation.parameters = Some(request.parameters.clone());
+        operation.mark_running();
+        let id = operation.id.clone();
+        self.add_operation(operation.clone()).await;
+
+        context.operation = Some(OperationHandle::new(id.clone(), self.clone()));
+
+        // The task lock is held until the task is registered, so a task that
+        // finishes straight away cannot deregister itself before that
+        let mut tasks = self.tasks.lock().await;
+        let repo = self.clone();
+        let operation_id = id.clone();
+        let task = tokio::spawn(async move {
+            let result = AssertUnwindSafe(crate::tools::execute_tool(registry, request, context))
+                .catch_unwind()
+                .await
+                .unwrap_or_else(|_| Err(ToolError::ExecutionFailed("Tool panicked".to_string())));
+            repo.finish(&operation_id, result).await;
+        });
+        tasks.insert(id, task.abort_handle());
+
+        operation
+    }
+
+    /// Settle the operations that were interrupted by a restart. Each tool
+    /// is asked how far its operation got; operations whose outcome cannot
+    /// be determined are marked as failed.
+    pub async fn reconcile(&self, registry: &ToolRegistry) {
+        let interrupted = std::mem::take(&mut *self.interrupted.lock().await);
+        for id in interrupted {
+            let Some(operation) = self.get_operation(&id).await else {
+                continue;
+            };
+
+            let outcome = match registry.get_tool(&operation.tool_name) {
+                Some(tool) => tool.reconcile(&operation).await,
+                None => None,
+            };
+
+            self.modify(&id, |op| {
+                if op.is_finished() {
+                    return;
+                }
+                match outcome {
+                    Some(Ok(result)) => {
+                        op.append_log("Operation found complete after a restart".to_string());
+                        op.mark_completed(json!(ToolResponse {
+                            status: "success".to_string(),
+                            result: Some(result),
+                            error: None,
+                        }));
+                    }
+                    Some(Err(error)) => op.mark_failed(format!("Tool execution failed: {}", error)),
+                    None => op.mark_failed("Operation was interrupted by a restart of form-mcp".to_string()),
+                }
+            }).await;
+            log::info!("Reconciled interrupted operation {}", id);
+        }
     }
-    
+
     /// Clean up expired operations
     pub async fn cleanup(&self) {
         let mut operations = self.operations.write().await;
-        operations.retain(|_, op| !op.is_expired());
+        let expired: Vec<String> = operations.values()
+            .filter(|op| op.is_expired())
+            .map(|op| op.id.clone())
+            .collect();
+        for id in expired {
+            operations.remove(&id);
+            self.unpersist(&id).await;
+        }
     }
-    
+
     /// Clean up expired operations (alias for cleanup)
     pub async fn cleanup_expired_operations(&self) {
         self.cleanup().await;
     }
-    
+
+    /// Record the outcome of a tool executed by `spawn`, unless the
+    /// operation was cancelled in the meantime
+    async fn finish(&self, id: &str, result: Result<ToolResponse, ToolError>) {
+        self.tasks.lock().await.remove(id);
+        self.modify(id, |op| {
+            if op.is_finished() {
+                return;
+            }
+            match result {
+                Ok(response) => op.mark_completed(json!(response)),
+                Err(error) => op.mark_failed(format!("Tool execution failed: {}", error)),
+            }
+        }).await;
+    }
+
+    /// Apply a change to an operation, returning the changed operation
+    async fn modify(&self, id: &str, change: impl FnOnce(&mut Operation)) -> Option<Operation> {
+        // The write lock is held while persisting, so records are written in
+        // the order their changes were made
+        let mut operations = self.operations.write().await;
+        let operation = operations.get_mut(id)?;
+        change(operation);
+        let operation = operation.clone();
+        self.persist(&operation).await;
+        Some(operation)
+    }
+
+    /// Write an operation to the store and notify subscribers of it
+    async fn persist(&self, operation: &Operation) {
+        if let Some(store) = &self.store {
+            if let Err(e) = store.save(operation).await {
+                log::error!("Failed to persist operation {}: {}", operation.id, e);
+            }
+        }
+        let _ = self.events.send(operation.clone());
+    }
+
+    /// Remove an operation from the store
+    async fn unpersist(&self, id: &str) {
+        if let Some(store) = &self.store {
+            if let Err(e) = store.remove(id).await {
+                log::error!("Failed to remove operation {}: {}", id, e);
+            }
+        }
+    }
+
     /// Start the background cleanup task
     fn start_cleanup_task(&self) {
         let repo = self.clone();
@@ -95,7 +337,73 @@ impl OperationsRepository {
     }
 }
 
+impl Default for OperationsRepository {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Handle a long-running tool reports its progress through
+#[derive(Debug, Clone)]
+pub struct OperationHandle {
+    id: String,
+    repository: OperationsRepository,
+}
+
+impl OperationHandle {
+    fn new(id: String, repository: OperationsRepository) -> Self {
+        Self { id, repository }
+    }
+
+    /// ID of the operation
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Report progress, from 0.0 to 1.0, along with a status message
+    pub async fn progress(&self, progress: f32, message: impl Into<String>) {
+        self.repository.report_progress(&self.id, progress, Some(message.into())).await;
+    }
+
+    /// Append a line to the log of the operation
+    pub async fn log(&self, message: impl Into<String>) {
+        self.repository.append_log(&self.id, message.into()).await;
+    }
+
+    /// Save state the tool needs to find out how far it got should the
+    /// server restart before the operation finishes
+    pub async fn checkpoint(&self, state: Value) {
+        self.repository.save_checkpoint(&self.id, state).await;
+    }
+}
+
 /// Create a new shared operations repository
 pub fn create_repository() -> Arc<OperationsRepository> {
     Arc::new(OperationsRepository::new())
-} 
\ No newline at end of file
+}
+
+/// Open the shared operations repository described by the settings,
+/// falling back to an in-memory repository if its store cannot be opened
+pub fn open_repository(settings: &Settings) -> Arc<OperationsRepository> {
+    let operations = &settings.operations;
+    let ttl = Duration::from_secs(operations.ttl_secs);
+    let cleanup_interval = Duration::from_secs(operations.cleanup_interval_secs);
+
+    let repository = OperationStore::open(&operations.store_path)
+        .and_then(|store| OperationsRepository::with_store(store, ttl, cleanup_interval));
+    match repository {
+        Ok(repository) => {
+            log::info!("Opened operations store at {}", operations.store_path);
+            Arc::new(repository)
+        }
+        Err(e) => {
+            log::error!(
+                "Failed to open operations store at {}, operations will not survive a restart: {}",
+                operations.store_path, e
+            );
+            let repository = OperationsRepository::build(None, HashMap::new(), ttl, cleanup_interval);
+            repository.start_cleanup_task();
+            Arc::new(repository)
+        }
+    }
+}
@@ -0,0 +1,78 @@
+// Operations store
+//
+// This module persists operations to disk, one JSON file per operation, so
+// that they survive a restart of the server.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::Operation;
+
+/// File-backed store for operations
+#[derive(Debug, Clone)]
+pub struct OperationStore {
+    dir: PathBuf,
+}
+
+impl OperationStore {
+    /// Open the store in `dir`, creating the directory if it does not exist
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Directory the store keeps its operations in
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Load every operation in the store. Files that cannot be read are
+    /// logged and skipped, so one corrupt record cannot keep the server down.
+    pub fn load_all(&self) -> io::Result<Vec<Operation>> {
+        let mut operations = Vec::new();
+        for entry in std::fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("json") {
+                continue;
+            }
+
+            let operation = std::fs::read(&path)
+                .map_err(|e| e.to_string())
+                .and_then(|content| serde_json::from_slice::<Operation>(&content).map_err(|e| e.to_string()));
+            match operation {
+                Ok(operation) => operations.push(operation),
+                Err(e) => log::warn!("Skipping unreadable operation record {}: {}", path.display(), e),
+            }
+        }
+        Ok(operations)
+    }
+
+    /// Write an operation to the store. The record is written to a temporary
+    /// file first and then renamed, so a crash never leaves it half written.
+    pub async fn save(&self, operation: &Operation) -> io::Result<()> {
+        let content = serde_json::to_vec_pretty(operation)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let path = self.path(&operation.id);
+        let tmp = path.with_extension("json.tmp");
+        tokio::fs::write(&tmp, content).await?;
+        tokio::fs::rename(&tmp, &path).await
+    }
+
+    /// Remove an operation from the store
+    pub async fn remove(&self, id: &str) -> io::Result<()> {
+        match tokio::fs::remove_file(self.path(id)).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Path of the record of an operation
+    fn path(&self, id: &str) -> PathBuf {
+        // IDs are UUIDs; anything else is flattened so it stays inside the store
+        let name: String = id.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        self.dir.join(format!("{}.json", name))
+    }
+}
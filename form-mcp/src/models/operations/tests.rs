@@ -4,7 +4,11 @@ mod tests {
     use serde_json::json;
     use tokio::time::sleep;
     use std::time::Duration;
-    use crate::models::operations::{Operation, OperationStatus, create_repository};
+    use std::sync::Arc;
+    use async_trait::async_trait;
+    use serde_json::Value;
+    use crate::models::operations::{Operation, OperationStatus, OperationStore, OperationsRepository, create_repository};
+    use crate::tools::{Tool, ToolContext, ToolDefinition, ToolRegistry, ToolRequest, ToolResult};
 
     #[tokio::test]
     async fn test_operation_repository_basic() {
@@ -92,4 +96,161 @@ mod tests {
         let retrieved = repo.get_operation(&op_id).await;
         assert!(retrieved.is_none(), "Operation should have been cleaned up after expiration");
     }
-} 
\ No newline at end of file
+
+    /// Tool that reports progress, checkpoints and then sleeps for as many
+    /// milliseconds as it is asked to
+    struct SleepTool;
+
+    #[async_trait]
+    impl Tool for SleepTool {
+        fn definition(&self) -> ToolDefinition {
+            ToolDefinition {
+                name: "sleep".to_string(),
+                description: "Sleeps".to_string(),
+                version: "1.0.0".to_string(),
+                parameters: Vec::new(),
+                return_type: "object".to_string(),
+                tags: vec!["test".to_string()],
+                is_long_running: Some(true),
+            }
+        }
+
+        async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+            let operation = context.operation.expect("long-running tools get an operation handle");
+            operation.checkpoint(json!({ "step": "sleeping" })).await;
+            operation.log("going to sleep").await;
+            operation.progress(0.5, "sleeping").await;
+            sleep(Duration::from_millis(params["ms"].as_u64().unwrap_or(0))).await;
+            Ok(json!({ "slept": true }))
+        }
+
+        async fn reconcile(&self, operation: &Operation) -> Option<ToolResult> {
+            let step = operation.checkpoint.as_ref()?.get("step")?;
+            Some(Ok(json!({ "reconciled": step })))
+        }
+    }
+
+    fn registry() -> Arc<ToolRegistry> {
+        let registry = ToolRegistry::new();
+        registry.register_tool(Arc::new(SleepTool)).unwrap();
+        Arc::new(registry)
+    }
+
+    fn sleep_request(ms: u64) -> (ToolRequest, ToolContext) {
+        let request = ToolRequest {
+            name: "sleep".to_string(),
+            parameters: json!({ "ms": ms }),
+            context: None,
+        };
+        let context = ToolContext {
+            user_id: "test-user".to_string(),
+            request_id: "test-request".to_string(),
+            context: Default::default(),
+            is_admin: false,
+            operation: None,
+        };
+        (request, context)
+    }
+
+    async fn wait_finished(repo: &OperationsRepository, id: &str) -> Operation {
+        for _ in 0..100 {
+            let op = repo.get_operation(id).await.unwrap();
+            if op.is_finished() {
+                return op;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Operation {} did not finish", id);
+    }
+
+    fn temp_store() -> OperationStore {
+        let dir = std::env::temp_dir().join(format!("form-mcp-operations-{}", uuid::Uuid::new_v4()));
+        OperationStore::open(dir).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_spawn_reports_progress_and_logs() {
+        let repo = create_repository();
+        let mut events = repo.subscribe();
+
+        let (request, context) = sleep_request(0);
+        let op = repo.spawn(registry(), request, context).await;
+        assert_eq!(op.status, OperationStatus::Running);
+
+        let op = wait_finished(&repo, &op.id).await;
+        assert_eq!(op.status, OperationStatus::Completed);
+        assert_eq!(op.message.as_deref(), Some("sleeping"));
+        assert_eq!(op.logs[0].message, "going to sleep");
+        assert_eq!(op.checkpoint, Some(json!({ "step": "sleeping" })));
+        assert_eq!(op.result.unwrap()["result"]["slept"], true);
+
+        // Every change was broadcast, ending with the completion
+        let mut last = None;
+        while let Ok(update) = events.try_recv() {
+            last = Some(update);
+        }
+        assert_eq!(last.unwrap().status, OperationStatus::Completed);
+    }
+
+    #[tokio::test]
+    async fn test_cancel_operation() {
+        let repo = create_repository();
+
+        let (request, context) = sleep_request(10_000);
+        let op = repo.spawn(registry(), request, context).await;
+        let cancelled = repo.cancel(&op.id).await.unwrap();
+        assert_eq!(cancelled.status, OperationStatus::Cancelled);
+
+        // The tool was stopped, so the operation stays cancelled
+        sleep(Duration::from_millis(20)).await;
+        let op = repo.get_operation(&op.id).await.unwrap();
+        assert_eq!(op.status, OperationStatus::Cancelled);
+
+        // Finished operations cannot be cancelled
+        let (request, context) = sleep_request(0);
+        let op = repo.spawn(registry(), request, context).await;
+        wait_finished(&repo, &op.id).await;
+        assert!(repo.cancel(&op.id).await.is_err());
+        assert!(repo.cancel("missing").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_operations_survive_restart() {
+        let store = temp_store();
+        let dir = store.dir().to_path_buf();
+
+        let repo = OperationsRepository::with_store(store, Duration::from_secs(60), Duration::from_secs(60)).unwrap();
+        let mut done = Operation::new("test-user".to_string(), "sleep".to_string());
+        done.append_log("finished before the restart".to_string());
+        done.mark_completed(json!({ "done": true }));
+        let mut checkpointed = Operation::new("test-user".to_string(), "sleep".to_string());
+        checkpointed.checkpoint = Some(json!({ "step": "sleeping" }));
+        checkpointed.mark_running();
+        let mut lost = Operation::new("test-user".to_string(), "sleep".to_string());
+        lost.mark_running();
+        for op in [&done, &checkpointed, &lost] {
+            repo.add_operation(op.clone()).await;
+        }
+
+        // Reopen the store as a restarted server would
+        let repo = OperationsRepository::with_store(OperationStore::open(&dir).unwrap(), Duration::from_secs(60), Duration::from_secs(60)).unwrap();
+        let reloaded = repo.get_operation(&done.id).await.unwrap();
+        assert_eq!(reloaded.status, OperationStatus::Completed);
+        assert_eq!(reloaded.logs, done.logs);
+
+        repo.reconcile(&registry()).await;
+        let reconciled = repo.get_operation(&checkpointed.id).await.unwrap();
+        assert_eq!(reconciled.status, OperationStatus::Completed);
+        assert_eq!(reconciled.result.unwrap()["result"]["reconciled"], "sleeping");
+        let failed = repo.get_operation(&lost.id).await.unwrap();
+        assert_eq!(failed.status, OperationStatus::Failed);
+
+        // Removed operations are removed from the store too
+        repo.remove_operation(&done.id).await;
+        let repo = OperationsRepository::with_store(OperationStore::open(&dir).unwrap(), Duration::from_secs(60), Duration::from_secs(60)).unwrap();
+        assert!(repo.get_operation(&done.id).await.is_none());
+        assert_eq!(repo.get_operation(&lost.id).await.unwrap().status, OperationStatus::Failed);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,145 @@
+// Billing Usage Tool
+//
+// This tool lets clients see their metered usage and invoices, and lets
+// admins reconcile token usage against form-state.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::billing::{period_of, BillingService};
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+
+/// Billing Usage Tool Implementation
+pub struct BillingUsageTool {
+    billing: Arc<BillingService>,
+}
+
+impl BillingUsageTool {
+    /// Create a new billing usage tool
+    pub fn new(billing: Arc<BillingService>) -> Self {
+        Self { billing }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry, billing: Arc<BillingService>) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new(billing)))
+    }
+}
+
+/// Register billing tools with the registry. They are registered apart from
+/// the other tools, as they need the running billing service.
+pub fn register_tools(registry: &ToolRegistry, billing: Arc<BillingService>) {
+    if let Err(err) = BillingUsageTool::register(registry, billing) {
+        log::error!("Failed to register billing usage tool: {}", err);
+    }
+}
+
+#[async_trait]
+impl Tool for BillingUsageTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "billing.usage".to_string(),
+            description: "Show metered usage or the invoice for a billing period, or reconcile token usage with form-state".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "operation".to_string(),
+                    description: "Operation to perform; 'reconcile' is restricted to admins".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: Some(vec![
+                        json!("usage"),
+                        json!("invoice"),
+                        json!("reconcile"),
+                    ]),
+                },
+                ToolParameter {
+                    name: "period".to_string(),
+                    description: "Billing period as YYYY-MM; the current month if omitted".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "user_id".to_string(),
+                    description: "User to show usage for; admins only, defaults to the requester".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["billing".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+
+        let operation = params.get("operation").and_then(|v| v.as_str()).unwrap_or_default();
+        match operation {
+            "usage" | "invoice" => {
+                require_permission(&context, "billing", "read")?;
+                let period = period(&params)?;
+                let user_id = match params.get("user_id").and_then(|v| v.as_str()) {
+                    Some(user_id) if user_id != context.user_id => {
+                        if !context.is_admin {
+                            return Err(ToolError::Forbidden(
+                                "You do not have permission to view other users' usage".to_string()
+                            ));
+                        }
+                        user_id.to_string()
+                    }
+                    _ => context.user_id.clone(),
+                };
+
+                if operation == "usage" {
+                    Ok(json!({
+                        "success": true,
+                        "user_id": user_id,
+                        "period": period,
+                        "usage": self.billing.usage(&user_id, &period).await,
+                    }))
+                } else {
+                    Ok(json!({
+                        "success": true,
+                        "invoice": self.billing.invoice(&user_id, &period).await,
+                    }))
+                }
+            }
+            "reconcile" => {
+                require_permission(&context, "billing", "reconcile")?;
+                let reconciliations = self.billing.reconcile_tokens().await?;
+                let adjusted = reconciliations.iter().filter(|r| r.adjustment() != 0.0).count();
+                Ok(json!({
+                    "success": true,
+                    "message": format!("Reconciled {} model usages, {} needed an adjustment", reconciliations.len(), adjusted),
+                    "reconciliations": reconciliations,
+                }))
+            }
+            _ => Err(ToolError::InvalidParameters(
+                format!("Invalid operation: {}. Must be 'usage', 'invoice' or 'reconcile'", operation)
+            )),
+        }
+    }
+}
+
+/// Parse the billing period, defaulting to the current month
+fn period(params: &Value) -> Result<String, ToolError> {
+    let Some(period) = params.get("period").and_then(|v| v.as_str()) else {
+        return Ok(period_of(chrono::Utc::now()));
+    };
+    chrono::NaiveDate::parse_from_str(&format!("{}-01", period), "%Y-%m-%d")
+        .map(|date| date.format("%Y-%m").to_string())
+        .map_err(|_| ToolError::InvalidParameters(
+            format!("Invalid period: {}. Must be formatted as YYYY-MM", period)
+        ))
+}
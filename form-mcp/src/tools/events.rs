@@ -0,0 +1,205 @@
+// Event Subscriptions Tool
+//
+// This tool lets clients subscribe to instance, node, pack build and
+// operation events, delivered as MCP notifications or webhooks.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::events::{Delivery, EventFilter, EventService, Subscription};
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+
+/// Event Subscriptions Tool Implementation
+pub struct EventSubscriptionsTool {
+    events: Arc<EventService>,
+}
+
+impl EventSubscriptionsTool {
+    /// Create a new event subscriptions tool
+    pub fn new(events: Arc<EventService>) -> Self {
+        Self { events }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry, events: Arc<EventService>) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new(events)))
+    }
+}
+
+/// Register event tools with the registry. They are registered apart from
+/// the other tools, as they need the running event service.
+pub fn register_tools(registry: &ToolRegistry, events: Arc<EventService>) {
+    if let Err(err) = EventSubscriptionsTool::register(registry, events) {
+        log::error!("Failed to register event subscriptions tool: {}", err);
+    }
+}
+
+#[async_trait]
+impl Tool for EventSubscriptionsTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "events.subscriptions".to_string(),
+            description: "List, create or delete subscriptions to instance, node, pack build and operation events".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "operation".to_string(),
+                    description: "Operation to perform".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: Some(vec![
+                        json!("list"),
+                        json!("create"),
+                        json!("delete"),
+                    ]),
+                },
+                ToolParameter {
+                    name: "id".to_string(),
+                    description: "ID of the subscription, required for 'delete'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "delivery".to_string(),
+                    description: "How events are delivered: as notifications on your MCP connections, or to a webhook".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: Some(json!("mcp")),
+                    enum_values: Some(vec![json!("mcp"), json!("webhook")]),
+                },
+                ToolParameter {
+                    name: "url".to_string(),
+                    description: "URL events are POSTed to, required for webhook delivery. It must resolve to a public address".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "secret".to_string(),
+                    description: "Secret webhook payloads are signed with, sent as an HMAC-SHA256 in 'X-Form-Signature'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "event_types".to_string(),
+                    description: "Event types to receive, such as 'instance.status_changed' or 'pack.*'; all types if omitted".to_string(),
+                    required: false,
+                    parameter_type: "array".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "resource_ids".to_string(),
+                    description: "Instance, node, build or operation IDs to receive events about; all resources if omitted".to_string(),
+                    required: false,
+                    parameter_type: "array".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["events".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+
+        let operation = params.get("operation").and_then(|v| v.as_str()).unwrap_or_default();
+        let subscriptions = self.events.subscriptions();
+        match operation {
+            "list" => {
+                require_permission(&context, "events", "read")?;
+                let list: Vec<Value> = subscriptions.list(&context.user_id, context.is_admin).await
+                    .iter()
+                    .map(Subscription::redacted)
+                    .collect();
+                Ok(json!({ "success": true, "subscriptions": list }))
+            }
+            "create" => {
+                require_permission(&context, "events", "subscribe")?;
+                let filter = EventFilter {
+                    event_types: string_list(&params, "event_types")?,
+                    resource_ids: string_list(&params, "resource_ids")?,
+                };
+                let subscription = Subscription::new(
+                    context.user_id.clone(),
+                    context.is_admin,
+                    filter,
+                    delivery(&params)?,
+                );
+                let redacted = subscription.redacted();
+                subscriptions.add(subscription).await;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("Subscription '{}' has been created", redacted["id"].as_str().unwrap_or_default()),
+                    "subscription": redacted,
+                }))
+            }
+            "delete" => {
+                require_permission(&context, "events", "subscribe")?;
+                let id = params.get("id").and_then(|v| v.as_str()).ok_or_else(|| {
+                    ToolError::InvalidParameters("'id' parameter is required for 'delete'".to_string())
+                })?;
+                subscriptions.remove(id, &context.user_id, context.is_admin).await
+                    .map_err(ToolError::ExecutionFailed)?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("Subscription '{}' has been deleted", id),
+                }))
+            }
+            _ => Err(ToolError::InvalidParameters(
+                format!("Invalid operation: {}. Must be 'list', 'create' or 'delete'", operation)
+            )),
+        }
+    }
+}
+
+/// Parse the delivery of a new subscription
+fn delivery(params: &Value) -> Result<Delivery, ToolError> {
+    match params.get("delivery").and_then(|v| v.as_str()).unwrap_or("mcp") {
+        "mcp" => Ok(Delivery::Mcp),
+        "webhook" => {
+            let url = params.get("url").and_then(|v| v.as_str()).ok_or_else(|| {
+                ToolError::InvalidParameters("'url' parameter is required for webhook delivery".to_string())
+            })?;
+            if !(url.starts_with("http://") || url.starts_with("https://")) {
+                return Err(ToolError::InvalidParameters(
+                    format!("Invalid webhook URL: {}. Must be an http or https URL", url)
+                ));
+            }
+            Ok(Delivery::Webhook {
+                url: url.to_string(),
+                secret: params.get("secret").and_then(|v| v.as_str()).map(str::to_string),
+            })
+        }
+        other => Err(ToolError::InvalidParameters(
+            format!("Invalid delivery: {}. Must be 'mcp' or 'webhook'", other)
+        )),
+    }
+}
+
+/// Parse an optional list of strings
+fn string_list(params: &Value, name: &str) -> Result<Vec<String>, ToolError> {
+    let Some(values) = params.get(name) else {
+        return Ok(Vec::new());
+    };
+    values.as_array()
+        .ok_or_else(|| ToolError::InvalidParameters(format!("'{}' must be an array", name)))?
+        .iter()
+        .map(|value| value.as_str().map(str::to_string).ok_or_else(|| {
+            ToolError::InvalidParameters(format!("'{}' must only contain strings", name))
+        }))
+        .collect()
+}
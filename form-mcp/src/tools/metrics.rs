@@ -1,12 +0,0 @@
-// Metrics tools module
-//
-// This module implements tools for metrics collection and analysis,
-// including resource usage, performance monitoring, and alerting.
-
-use std::sync::Arc;
-use crate::tools::registry::ToolRegistry;
-
-/// Register metrics tools with the registry
-pub fn register_tools(_registry: &ToolRegistry) {
-    // Tools will be implemented in future sub-tasks
-} 
\ No newline at end of file
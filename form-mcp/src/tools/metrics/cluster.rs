@@ -0,0 +1,80 @@
+// Cluster Metrics Tool
+//
+// This tool retrieves the current metrics of every instance of a build.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
+
+/// Cluster Metrics Tool Implementation
+#[derive(Default)]
+pub struct ClusterMetricsTool {
+    state: StateClient,
+}
+
+impl ClusterMetricsTool {
+    /// Create a new cluster metrics tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for ClusterMetricsTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "metrics.cluster".to_string(),
+            description: "Get the current metrics of every instance of a build".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "build_id".to_string(),
+                    description: "Build ID of the cluster".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["metrics".to_string(), "cluster".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+        require_permission(&context, "metrics", "read")?;
+
+        let build_id = params.get("build_id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                ToolError::InvalidParameters("'build_id' parameter is required".to_string())
+            })?;
+
+        let instances = self.state.authorize_build(build_id, &context).await?;
+
+        // Instances that don't respond are left out by form-state
+        let metrics = self.state.get(&format!("/cluster/{}/metrics", build_id), &[]).await?;
+        Ok(json!({
+            "success": true,
+            "build_id": build_id,
+            "instances": instances.len(),
+            "reporting": metrics.as_array().map(|m| m.len()).unwrap_or(0),
+            "metrics": metrics,
+        }))
+    }
+}
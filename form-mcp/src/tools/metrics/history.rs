@@ -0,0 +1,150 @@
+// Metrics History Tool
+//
+// This tool retrieves the recorded metrics history of a node or instance.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
+
+/// Metrics History Tool Implementation
+#[derive(Default)]
+pub struct MetricsHistoryTool {
+    state: StateClient,
+}
+
+impl MetricsHistoryTool {
+    /// Create a new metrics history tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for MetricsHistoryTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "metrics.history".to_string(),
+            description: "Get the metrics history of a node or instance over a time range".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "target".to_string(),
+                    description: "Kind of resource to get the history of".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: Some(vec![json!("node"), json!("instance")]),
+                },
+                ToolParameter {
+                    name: "id".to_string(),
+                    description: "ID of the node or instance".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "resolution".to_string(),
+                    description: "Resolution of the samples".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: Some(json!("raw")),
+                    enum_values: Some(vec![json!("raw"), json!("1m"), json!("1h")]),
+                },
+                ToolParameter {
+                    name: "from".to_string(),
+                    description: "Unix timestamp of the start of the range".to_string(),
+                    required: false,
+                    parameter_type: "number".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "to".to_string(),
+                    description: "Unix timestamp of the end of the range, defaults to now".to_string(),
+                    required: false,
+                    parameter_type: "number".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "fields".to_string(),
+                    description: "Comma separated metrics to return, defaults to all of them".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "limit".to_string(),
+                    description: "Maximum number of samples to return".to_string(),
+                    required: false,
+                    parameter_type: "number".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["metrics".to_string(), "history".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+        require_permission(&context, "metrics", "read")?;
+
+        let target = params.get("target").and_then(|v| v.as_str()).unwrap_or_default();
+        let id = params.get("id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                ToolError::InvalidParameters("'id' parameter is required".to_string())
+            })?;
+
+        let path = match target {
+            "node" => format!("/node/{}/metrics/history", id),
+            "instance" => {
+                self.state.authorize_instance(id, &context).await?;
+                format!("/instance/{}/metrics/history", id)
+            }
+            _ => return Err(ToolError::InvalidParameters(
+                format!("Invalid target: {}. Must be 'node' or 'instance'", target)
+            )),
+        };
+
+        let mut query = Vec::new();
+        if let Some(resolution) = params.get("resolution").and_then(|v| v.as_str()) {
+            query.push(("resolution", resolution.to_string()));
+        }
+        for field in ["from", "to", "limit"] {
+            if let Some(value) = params.get(field).and_then(|v| v.as_i64()) {
+                query.push((field, value.to_string()));
+            }
+        }
+        if let Some(fields) = params.get("fields").and_then(|v| v.as_str()) {
+            query.push(("fields", fields.to_string()));
+        }
+
+        let samples = self.state.get(&path, &query).await?;
+        Ok(json!({
+            "success": true,
+            "target": target,
+            "id": id,
+            "count": samples.as_array().map(|s| s.len()).unwrap_or(0),
+            "samples": samples,
+        }))
+    }
+}
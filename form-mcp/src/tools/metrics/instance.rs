@@ -0,0 +1,78 @@
+// Instance Metrics Tool
+//
+// This tool retrieves the current metrics of a single instance.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
+
+/// Instance Metrics Tool Implementation
+#[derive(Default)]
+pub struct InstanceMetricsTool {
+    state: StateClient,
+}
+
+impl InstanceMetricsTool {
+    /// Create a new instance metrics tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for InstanceMetricsTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "metrics.instance".to_string(),
+            description: "Get the current CPU, memory, disk and network metrics of an instance".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "instance_id".to_string(),
+                    description: "ID of the instance".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["metrics".to_string(), "vm".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+        require_permission(&context, "metrics", "read")?;
+
+        let instance_id = params.get("instance_id")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| {
+                ToolError::InvalidParameters("'instance_id' parameter is required".to_string())
+            })?;
+
+        // Only the owner of an instance can see its metrics
+        self.state.authorize_instance(instance_id, &context).await?;
+
+        let metrics = self.state.get(&format!("/instance/{}/metrics", instance_id), &[]).await?;
+        Ok(json!({
+            "success": true,
+            "instance_id": instance_id,
+            "metrics": metrics,
+        }))
+    }
+}
@@ -0,0 +1,39 @@
+// Metrics tools module
+//
+// This module implements tools for querying the resource usage of nodes,
+// instances and clusters recorded in the state datastore.
+
+mod node;
+mod instance;
+mod cluster;
+mod history;
+
+pub use node::NodeMetricsTool;
+pub use instance::InstanceMetricsTool;
+pub use cluster::ClusterMetricsTool;
+pub use history::MetricsHistoryTool;
+
+use crate::tools::registry::ToolRegistry;
+
+/// Register metrics tools with the registry
+pub fn register_tools(registry: &ToolRegistry) {
+    // Register node metrics tool
+    if let Err(err) = NodeMetricsTool::register(registry) {
+        log::error!("Failed to register node metrics tool: {}", err);
+    }
+
+    // Register instance metrics tool
+    if let Err(err) = InstanceMetricsTool::register(registry) {
+        log::error!("Failed to register instance metrics tool: {}", err);
+    }
+
+    // Register cluster metrics tool
+    if let Err(err) = ClusterMetricsTool::register(registry) {
+        log::error!("Failed to register cluster metrics tool: {}", err);
+    }
+
+    // Register metrics history tool
+    if let Err(err) = MetricsHistoryTool::register(registry) {
+        log::error!("Failed to register metrics history tool: {}", err);
+    }
+}
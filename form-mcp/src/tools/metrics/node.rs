@@ -0,0 +1,81 @@
+// Node Metrics Tool
+//
+// This tool retrieves the latest metrics reported by one or all nodes.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
+
+/// Node Metrics Tool Implementation
+#[derive(Default)]
+pub struct NodeMetricsTool {
+    state: StateClient,
+}
+
+impl NodeMetricsTool {
+    /// Create a new node metrics tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for NodeMetricsTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "metrics.node".to_string(),
+            description: "Get the latest CPU, memory, storage and network metrics of a node, or of every node".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "node_id".to_string(),
+                    description: "ID of the node, omit to get the metrics of every node".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["metrics".to_string(), "node".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+        require_permission(&context, "metrics", "read")?;
+
+        match params.get("node_id").and_then(|v| v.as_str()) {
+            Some(node_id) => {
+                let metrics = self.state.get(&format!("/node/{}/metrics", node_id), &[]).await?;
+                Ok(json!({
+                    "success": true,
+                    "node_id": node_id,
+                    "metrics": metrics,
+                }))
+            }
+            None => {
+                let metrics = self.state.get("/node/list/metrics", &[]).await?;
+                Ok(json!({
+                    "success": true,
+                    "count": metrics.as_array().map(|m| m.len()).unwrap_or(0),
+                    "nodes": metrics,
+                }))
+            }
+        }
+    }
+}
@@ -8,12 +8,18 @@ pub mod network;
 pub mod metrics;
 pub mod registry;
 pub mod pack;
+pub mod state;
+pub mod events;
+pub mod billing;
+#[cfg(test)]
+mod tests;
 
 pub use registry::{ToolRegistry, Tool, ToolDefinition, ToolParameter, ToolResult};
 
 use std::sync::Arc;
 use serde::{Serialize, Deserialize};
 use crate::errors::ToolError;
+use crate::models::operations::OperationHandle;
 
 /// ToolContext holds contextual information for tool execution
 #[derive(Clone)]
@@ -26,6 +32,8 @@ pub struct ToolContext {
     pub context: std::collections::HashMap<String, String>,
     /// Whether the user has admin privileges
     pub is_admin: bool,
+    /// Operation the tool is executing as, if it is long-running
+    pub operation: Option<OperationHandle>,
 }
 
 /// ToolRequest represents a request to execute a tool
@@ -40,7 +48,7 @@ pub struct ToolRequest {
 }
 
 /// ToolResponse represents the response from a tool execution
-#[derive(Serialize)]
+#[derive(Serialize, Deserialize)]
 pub struct ToolResponse {
     /// Status of the tool execution
     pub status: String,
@@ -50,6 +58,19 @@ pub struct ToolResponse {
     pub error: Option<String>,
 }
 
+/// Check that the requester may perform `action` on `resource`, using the
+/// admin role for admins and the user role for everyone else
+pub fn require_permission(context: &ToolContext, resource: &str, action: &str) -> Result<(), ToolError> {
+    let role = if context.is_admin { "admin" } else { "user" };
+    match crate::auth::permissions::has_permission(role, resource, action) {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(ToolError::Forbidden(
+            format!("Permission '{}:{}' is required", resource, action)
+        )),
+        Err(e) => Err(ToolError::ExecutionFailed(format!("Failed to check permissions: {}", e))),
+    }
+}
+
 /// Initialize the tool registry
 pub fn init_registry() -> Arc<registry::ToolRegistry> {
     let registry = registry::ToolRegistry::new();
@@ -78,12 +99,30 @@ pub async fn execute_tool(
     let tool = registry.get_tool(&request.name)
         .ok_or_else(|| ToolError::NotFound(request.name.clone()))?;
     
+    // Refuse to create resources the requester's quota has no room for
+    let billing = registry.billing();
+    if let (Some(billing), Some(resources)) = (&billing, tool.resource_request(&request.parameters)) {
+        if let Err(err) = billing.check_quota(&context.user_id, &resources).await {
+            return Ok(ToolResponse {
+                status: "error".to_string(),
+                result: None,
+                error: Some(err.to_string()),
+            });
+        }
+    }
+    
+    let user_id = context.user_id.clone();
     match tool.execute(request.parameters, context).await {
-        Ok(result) => Ok(ToolResponse {
-            status: "success".to_string(),
-            result: Some(result),
-            error: None,
-        }),
+        Ok(result) => {
+            if let Some(billing) = &billing {
+                billing.record_tool_execution(&user_id, &request.name).await;
+            }
+            Ok(ToolResponse {
+                status: "success".to_string(),
+                result: Some(result),
+                error: None,
+            })
+        }
         Err(err) => Ok(ToolResponse {
             status: "error".to_string(),
             result: None,
@@ -1,12 +0,0 @@
-// Network tools module
-//
-// This module implements tools for network management,
-// including connection, routing, and DNS.
-
-use std::sync::Arc;
-use crate::tools::registry::ToolRegistry;
-
-/// Register network management tools with the registry
-pub fn register_tools(_registry: &ToolRegistry) {
-    // Tools will be implemented in future sub-tasks
-} 
\ No newline at end of file
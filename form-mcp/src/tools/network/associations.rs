@@ -0,0 +1,118 @@
+// Network Associations Tool
+//
+// This tool lists and manages associations, which let peers in two CIDRs
+// reach each other.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
+use super::required_str;
+
+/// Network Associations Tool Implementation
+#[derive(Default)]
+pub struct AssociationsTool {
+    state: StateClient,
+}
+
+impl AssociationsTool {
+    /// Create a new network associations tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for AssociationsTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "network.associations".to_string(),
+            description: "List, create or delete associations between formnet CIDRs".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "operation".to_string(),
+                    description: "Operation to perform".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: Some(vec![
+                        json!("list"),
+                        json!("create"),
+                        json!("delete"),
+                    ]),
+                },
+                ToolParameter {
+                    name: "cidr_id_1".to_string(),
+                    description: "ID of the first CIDR, required for 'create' and 'delete'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "cidr_id_2".to_string(),
+                    description: "ID of the second CIDR, required for 'create' and 'delete'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["network".to_string(), "formnet".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+
+        let operation = params.get("operation").and_then(|v| v.as_str()).unwrap_or_default();
+        match operation {
+            "list" => {
+                require_permission(&context, "network", "read")?;
+                let associations = self.state.get("/assoc/list", &[]).await?;
+                Ok(json!({ "success": true, "associations": associations }))
+            }
+            "create" => {
+                require_permission(&context, "network", "write")?;
+                let cidr_id_1 = required_str(&params, "cidr_id_1", operation)?;
+                let cidr_id_2 = required_str(&params, "cidr_id_2", operation)?;
+                let association = self.state.post("/assoc/create", &json!({
+                    "Create": { "cidr_id_1": cidr_id_1, "cidr_id_2": cidr_id_2 }
+                })).await?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("CIDRs '{}' and '{}' have been associated", cidr_id_1, cidr_id_2),
+                    "association": association,
+                }))
+            }
+            "delete" => {
+                require_permission(&context, "network", "write")?;
+                let cidr_id_1 = required_str(&params, "cidr_id_1", operation)?;
+                let cidr_id_2 = required_str(&params, "cidr_id_2", operation)?;
+                self.state.post("/assoc/delete", &json!({ "Delete": [cidr_id_1, cidr_id_2] })).await?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("Association between '{}' and '{}' has been deleted", cidr_id_1, cidr_id_2),
+                }))
+            }
+            _ => Err(ToolError::InvalidParameters(
+                format!("Invalid operation: {}. Must be 'list', 'create' or 'delete'", operation)
+            )),
+        }
+    }
+}
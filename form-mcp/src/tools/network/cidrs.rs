@@ -0,0 +1,166 @@
+// Network CIDRs Tool
+//
+// This tool lists and manages the CIDRs formnet peers are allocated from.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
+use super::required_str;
+
+/// Network CIDRs Tool Implementation
+#[derive(Default)]
+pub struct CidrsTool {
+    state: StateClient,
+}
+
+impl CidrsTool {
+    /// Create a new network CIDRs tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for CidrsTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "network.cidrs".to_string(),
+            description: "List, inspect, create or delete formnet CIDRs and show which CIDRs can reach each other".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "operation".to_string(),
+                    description: "Operation to perform".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: Some(vec![
+                        json!("list"),
+                        json!("get"),
+                        json!("create"),
+                        json!("delete"),
+                        json!("relationships"),
+                    ]),
+                },
+                ToolParameter {
+                    name: "id".to_string(),
+                    description: "ID of the CIDR, required for 'get', 'delete' and 'relationships'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "name".to_string(),
+                    description: "Name of the CIDR to create".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "cidr".to_string(),
+                    description: "Network of the CIDR to create, e.g. 10.0.1.0/24".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "parent".to_string(),
+                    description: "ID of the CIDR the new CIDR is nested in".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["network".to_string(), "formnet".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+
+        let operation = params.get("operation").and_then(|v| v.as_str()).unwrap_or_default();
+        match operation {
+            "list" => {
+                require_permission(&context, "network", "read")?;
+                let cidrs = self.state.get("/cidr/list", &[]).await?;
+                Ok(json!({ "success": true, "cidrs": cidrs }))
+            }
+            "get" => {
+                require_permission(&context, "network", "read")?;
+                let id = required_str(&params, "id", operation)?;
+                let cidr = self.state.get(&format!("/cidr/{}/get", id), &[]).await?;
+                Ok(json!({ "success": true, "cidr": cidr }))
+            }
+            "relationships" => {
+                require_permission(&context, "network", "read")?;
+                let id = required_str(&params, "id", operation)?;
+                let relationships = self.state.get(&format!("/assoc/{}/relationships", id), &[]).await?;
+                Ok(json!({ "success": true, "relationships": relationships }))
+            }
+            "create" => {
+                require_permission(&context, "network", "write")?;
+                let name = required_str(&params, "name", operation)?;
+                let cidr = required_str(&params, "cidr", operation)?;
+                if !is_valid_cidr(cidr) {
+                    return Err(ToolError::InvalidParameters(format!("Invalid CIDR: {}", cidr)));
+                }
+                let created = self.state.post("/cidr/create", &json!({
+                    "Create": {
+                        "name": name,
+                        "cidr": cidr,
+                        "parent": params.get("parent").and_then(|v| v.as_str()),
+                    }
+                })).await?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("CIDR '{}' has been created", name),
+                    "cidr": created,
+                }))
+            }
+            "delete" => {
+                require_permission(&context, "network", "write")?;
+                let id = required_str(&params, "id", operation)?;
+                self.state.post("/cidr/delete", &json!({ "Delete": id })).await?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("CIDR '{}' has been deleted", id),
+                }))
+            }
+            _ => Err(ToolError::InvalidParameters(
+                format!("Invalid operation: {}. Must be 'list', 'get', 'create', 'delete' or 'relationships'", operation)
+            )),
+        }
+    }
+}
+
+/// Check that a CIDR is an IP address followed by a prefix length that fits it
+pub(super) fn is_valid_cidr(cidr: &str) -> bool {
+    let Some((ip, prefix)) = cidr.split_once('/') else {
+        return false;
+    };
+    match (ip.parse::<IpAddr>(), prefix.parse::<u8>()) {
+        (Ok(IpAddr::V4(_)), Ok(prefix)) => prefix <= 32,
+        (Ok(IpAddr::V6(_)), Ok(prefix)) => prefix <= 128,
+        _ => false,
+    }
+}
@@ -0,0 +1,346 @@
+// DNS Tools
+//
+// These tools manage the DNS records served by form-dns, and let users
+// point a domain of their own at one of their builds.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::{list_query, StateClient};
+use super::required_str;
+
+/// Port record addresses given without one are served on
+const DEFAULT_PORT: u16 = 80;
+
+/// TTL of records set without one
+const DEFAULT_TTL: u32 = 3600;
+
+/// DNS Records Tool Implementation
+#[derive(Default)]
+pub struct DnsRecordsTool {
+    state: StateClient,
+}
+
+impl DnsRecordsTool {
+    /// Create a new DNS records tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for DnsRecordsTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "dns.records".to_string(),
+            description: "List, inspect, create, update or delete DNS records".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "operation".to_string(),
+                    description: "Operation to perform; 'set' creates the record or replaces an existing one".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: Some(vec![
+                        json!("list"),
+                        json!("get"),
+                        json!("set"),
+                        json!("delete"),
+                    ]),
+                },
+                ToolParameter {
+                    name: "domain".to_string(),
+                    description: "Domain of the record, required for every operation but 'list'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "record_type".to_string(),
+                    description: "Type of the record to set".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: Some(json!("A")),
+                    enum_values: Some(vec![json!("A"), json!("AAAA"), json!("CNAME")]),
+                },
+                ToolParameter {
+                    name: "public_ip".to_string(),
+                    description: "Public addresses the record resolves to, as IP or IP:port".to_string(),
+                    required: false,
+                    parameter_type: "array".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "formnet_ip".to_string(),
+                    description: "Formnet addresses the record resolves to inside formnet, as IP or IP:port".to_string(),
+                    required: false,
+                    parameter_type: "array".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "cname_target".to_string(),
+                    description: "Target of a CNAME record".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "ssl_cert".to_string(),
+                    description: "Whether a TLS certificate is issued for the domain".to_string(),
+                    required: false,
+                    parameter_type: "boolean".to_string(),
+                    default: Some(json!(false)),
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "ttl".to_string(),
+                    description: "TTL of the record in seconds".to_string(),
+                    required: false,
+                    parameter_type: "number".to_string(),
+                    default: Some(json!(DEFAULT_TTL)),
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "limit".to_string(),
+                    description: "Maximum number of records 'list' returns to admins; users get every record of their own instances".to_string(),
+                    required: false,
+                    parameter_type: "number".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "cursor".to_string(),
+                    description: "Cursor of the next page returned by a previous 'list'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["network".to_string(), "dns".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+
+        let operation = params.get("operation").and_then(|v| v.as_str()).unwrap_or_default();
+        match operation {
+            "list" if context.is_admin => {
+                require_permission(&context, "dns", "read")?;
+                let records = self.state.get("/dns/list", &list_query(&params)).await?;
+                Ok(json!({ "success": true, "records": records }))
+            }
+            "list" => {
+                // Users only see the records of their own instances
+                require_permission(&context, "dns", "read")?;
+                let mut records = Vec::new();
+                for domain in self.state.owned_domains(&context).await? {
+                    match self.state.get(&format!("/dns/{}/get", domain), &[]).await? {
+                        Value::Null => {}
+                        record => records.push(record),
+                    }
+                }
+                Ok(json!({ "success": true, "records": records }))
+            }
+            "get" => {
+                require_permission(&context, "dns", "read")?;
+                let domain = required_str(&params, "domain", operation)?;
+                self.state.authorize_domain(domain, &context).await?;
+                let record = self.state.get(&format!("/dns/{}/get", domain), &[]).await?;
+                Ok(json!({ "success": true, "record": record }))
+            }
+            "set" => {
+                require_permission(&context, "dns", "write")?;
+                let record = build_record(&params)?;
+                let domain = record["domain"].as_str().unwrap_or_default().to_string();
+
+                let exists = self.state.get(&format!("/dns/{}/get", domain), &[]).await.is_ok();
+                let record = if exists {
+                    self.state.post("/dns/update", &json!({ "Update": record })).await?
+                } else {
+                    self.state.post("/dns/create", &json!({ "Create": record })).await?
+                };
+                Ok(json!({
+                    "success": true,
+                    "message": format!("DNS record for '{}' has been {}", domain, if exists { "updated" } else { "created" }),
+                    "record": record,
+                }))
+            }
+            "delete" => {
+                require_permission(&context, "dns", "write")?;
+                let domain = required_str(&params, "domain", operation)?;
+                self.state.post(&format!("/dns/{}/delete", domain), &json!({ "Delete": domain })).await?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("DNS record for '{}' has been deleted", domain),
+                }))
+            }
+            _ => Err(ToolError::InvalidParameters(
+                format!("Invalid operation: {}. Must be 'list', 'get', 'set' or 'delete'", operation)
+            )),
+        }
+    }
+}
+
+/// Build the form-dns record a 'set' operation describes
+pub(super) fn build_record(params: &Value) -> Result<Value, ToolError> {
+    let domain = required_str(params, "domain", "set")?.trim_end_matches('.').to_lowercase();
+    let record_type = params.get("record_type").and_then(|v| v.as_str()).unwrap_or("A");
+    let public_ip = socket_addrs(params, "public_ip")?;
+    let formnet_ip = socket_addrs(params, "formnet_ip")?;
+    let cname_target = params.get("cname_target").and_then(|v| v.as_str());
+
+    match record_type {
+        "A" | "AAAA" => {
+            if public_ip.is_empty() && formnet_ip.is_empty() {
+                return Err(ToolError::InvalidParameters(
+                    format!("An {} record needs at least one address", record_type)
+                ));
+            }
+            let ipv6 = record_type == "AAAA";
+            if public_ip.iter().chain(&formnet_ip).any(|addr| addr.is_ipv6() != ipv6) {
+                return Err(ToolError::InvalidParameters(
+                    format!("Every address of an {} record has to be IPv{}", record_type, if ipv6 { 6 } else { 4 })
+                ));
+            }
+        }
+        "CNAME" => {
+            if cname_target.is_none() {
+                return Err(ToolError::InvalidParameters("A CNAME record needs a 'cname_target'".to_string()));
+            }
+        }
+        _ => return Err(ToolError::InvalidParameters(
+            format!("Invalid record type: {}. Must be 'A', 'AAAA' or 'CNAME'", record_type)
+        )),
+    }
+
+    Ok(json!({
+        "domain": domain,
+        "record_type": record_type,
+        "public_ip": public_ip,
+        "formnet_ip": formnet_ip,
+        "cname_target": cname_target,
+        "ssl_cert": params.get("ssl_cert").and_then(|v| v.as_bool()).unwrap_or(false),
+        "ttl": params.get("ttl").and_then(|v| v.as_u64()).map(|ttl| ttl as u32).unwrap_or(DEFAULT_TTL),
+        "verification_status": null,
+        "verification_timestamp": null,
+    }))
+}
+
+/// Parse a list of addresses given as IP or IP:port
+fn socket_addrs(params: &Value, name: &str) -> Result<Vec<SocketAddr>, ToolError> {
+    let Some(values) = params.get(name) else {
+        return Ok(Vec::new());
+    };
+    let values = values.as_array()
+        .ok_or_else(|| ToolError::InvalidParameters(format!("'{}' must be an array", name)))?;
+
+    values.iter().map(|value| {
+        let addr = value.as_str().unwrap_or_default();
+        addr.parse::<SocketAddr>()
+            .or_else(|_| addr.parse::<IpAddr>().map(|ip| SocketAddr::new(ip, DEFAULT_PORT)))
+            .map_err(|_| ToolError::InvalidParameters(format!("Invalid address in '{}': {}", name, value)))
+    }).collect()
+}
+
+/// Domain Request Tool Implementation
+#[derive(Default)]
+pub struct DomainRequestTool {
+    state: StateClient,
+}
+
+impl DomainRequestTool {
+    /// Create a new domain request tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+}
+
+#[async_trait]
+impl Tool for DomainRequestTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "dns.request_domain".to_string(),
+            description: "Point a vanity domain at the instances of a build".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "domain".to_string(),
+                    description: "Domain to assign to the build".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "build_id".to_string(),
+                    description: "Build ID whose instances the domain resolves to".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "public".to_string(),
+                    description: "Whether the domain resolves publicly, through the hosting nodes, rather than only inside formnet".to_string(),
+                    required: false,
+                    parameter_type: "boolean".to_string(),
+                    default: Some(json!(false)),
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["network".to_string(), "dns".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+        require_permission(&context, "dns", "request")?;
+
+        let domain = required_str(&params, "domain", "request_domain")?.trim_end_matches('.').to_lowercase();
+        let build_id = required_str(&params, "build_id", "request_domain")?;
+        let public = params.get("public").and_then(|v| v.as_bool()).unwrap_or(false);
+
+        // Users can only point domains at their own builds
+        self.state.authorize_build(build_id, &context).await?;
+
+        let endpoint = if public { "request_public" } else { "request_vanity" };
+        let hosts = self.state.post(&format!("/dns/{}/{}/{}", domain, build_id, endpoint), &Value::Null).await?;
+        Ok(json!({
+            "success": true,
+            "message": format!("'{}' now resolves to the instances of build '{}'", domain, build_id),
+            "hosts": hosts,
+        }))
+    }
+}
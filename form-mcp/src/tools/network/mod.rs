@@ -0,0 +1,57 @@
+// Network tools module
+//
+// This module implements tools for network management, including formnet
+// peers, CIDRs and associations, DNS records and vanity domains.
+
+mod peers;
+mod cidrs;
+mod associations;
+mod dns;
+#[cfg(test)]
+mod tests;
+
+pub use peers::PeersTool;
+pub use cidrs::CidrsTool;
+pub use associations::AssociationsTool;
+pub use dns::{DnsRecordsTool, DomainRequestTool};
+
+use serde_json::Value;
+use crate::errors::ToolError;
+use crate::tools::registry::ToolRegistry;
+
+/// Register network management tools with the registry
+pub fn register_tools(registry: &ToolRegistry) {
+    // Register formnet peer tool
+    if let Err(err) = PeersTool::register(registry) {
+        log::error!("Failed to register network peers tool: {}", err);
+    }
+
+    // Register formnet CIDR tool
+    if let Err(err) = CidrsTool::register(registry) {
+        log::error!("Failed to register network CIDRs tool: {}", err);
+    }
+
+    // Register formnet association tool
+    if let Err(err) = AssociationsTool::register(registry) {
+        log::error!("Failed to register network associations tool: {}", err);
+    }
+
+    // Register DNS record tool
+    if let Err(err) = DnsRecordsTool::register(registry) {
+        log::error!("Failed to register DNS records tool: {}", err);
+    }
+
+    // Register domain request tool
+    if let Err(err) = DomainRequestTool::register(registry) {
+        log::error!("Failed to register domain request tool: {}", err);
+    }
+}
+
+/// Get a string parameter the operation being performed requires
+fn required_str<'a>(params: &'a Value, name: &str, operation: &str) -> Result<&'a str, ToolError> {
+    params.get(name)
+        .and_then(|v| v.as_str())
+        .ok_or_else(|| ToolError::InvalidParameters(
+            format!("'{}' parameter is required for '{}'", name, operation)
+        ))
+}
@@ -0,0 +1,143 @@
+// Network Peers Tool
+//
+// This tool lists and manages the peers of the formnet network.
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use serde_json::{json, Value};
+
+use crate::errors::ToolError;
+use crate::tools::{require_permission, Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
+use crate::tools::registry::ToolRegistry;
+use crate::tools::state::{list_query, StateClient};
+use super::required_str;
+
+/// Network Peers Tool Implementation
+#[derive(Default)]
+pub struct PeersTool {
+    state: StateClient,
+}
+
+impl PeersTool {
+    /// Create a new network peers tool
+    pub fn new() -> Self {
+        Self {
+            state: StateClient::new(),
+        }
+    }
+
+    /// Register this tool with the registry
+    pub fn register(registry: &ToolRegistry) -> Result<(), ToolError> {
+        registry.register_tool(Arc::new(Self::new()))
+    }
+
+    /// Enable or disable a peer, keeping the rest of its record as it is
+    async fn set_disabled(&self, id: &str, disabled: bool) -> Result<Value, ToolError> {
+        let mut contents = self.state.get(&format!("/user/{}/get", id), &[]).await?;
+        let record = contents.as_object_mut()
+            .ok_or_else(|| ToolError::ExecutionFailed(format!("Peer '{}' not found", id)))?;
+
+        // Peers are stored as their ID flattened into their contents
+        record.remove("id");
+        record.insert("is_disabled".to_string(), json!(disabled));
+
+        let path = if disabled { "/user/disable" } else { "/user/update" };
+        self.state.post(path, &json!({ "Update": contents })).await
+    }
+}
+
+#[async_trait]
+impl Tool for PeersTool {
+    fn definition(&self) -> ToolDefinition {
+        ToolDefinition {
+            name: "network.peers".to_string(),
+            description: "List, inspect, enable, disable or remove formnet peers".to_string(),
+            version: "1.0".to_string(),
+            parameters: vec![
+                ToolParameter {
+                    name: "operation".to_string(),
+                    description: "Operation to perform".to_string(),
+                    required: true,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: Some(vec![
+                        json!("list"),
+                        json!("get"),
+                        json!("enable"),
+                        json!("disable"),
+                        json!("delete"),
+                    ]),
+                },
+                ToolParameter {
+                    name: "id".to_string(),
+                    description: "ID of the peer, required for every operation but 'list'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "limit".to_string(),
+                    description: "Maximum number of peers 'list' returns".to_string(),
+                    required: false,
+                    parameter_type: "number".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+                ToolParameter {
+                    name: "cursor".to_string(),
+                    description: "Cursor of the next page returned by a previous 'list'".to_string(),
+                    required: false,
+                    parameter_type: "string".to_string(),
+                    default: None,
+                    enum_values: None,
+                },
+            ],
+            return_type: "object".to_string(),
+            tags: vec!["network".to_string(), "formnet".to_string()],
+            is_long_running: Some(false),
+        }
+    }
+
+    async fn execute(&self, params: Value, context: ToolContext) -> ToolResult {
+        // Validate parameters
+        self.validate_params(&params)?;
+
+        let operation = params.get("operation").and_then(|v| v.as_str()).unwrap_or_default();
+        match operation {
+            "list" => {
+                require_permission(&context, "network", "read")?;
+                let peers = self.state.get("/user/list", &list_query(&params)).await?;
+                Ok(json!({ "success": true, "peers": peers }))
+            }
+            "get" => {
+                require_permission(&context, "network", "read")?;
+                let id = required_str(&params, "id", operation)?;
+                let peer = self.state.get(&format!("/user/{}/get", id), &[]).await?;
+                Ok(json!({ "success": true, "peer": peer }))
+            }
+            "enable" | "disable" => {
+                require_permission(&context, "network", "write")?;
+                let id = required_str(&params, "id", operation)?;
+                let peer = self.set_disabled(id, operation == "disable").await?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("Peer '{}' has been {}d", id, operation),
+                    "peer": peer,
+                }))
+            }
+            "delete" => {
+                require_permission(&context, "network", "write")?;
+                let id = required_str(&params, "id", operation)?;
+                self.state.post("/user/delete", &json!({ "Delete": id })).await?;
+                Ok(json!({
+                    "success": true,
+                    "message": format!("Peer '{}' has been removed", id),
+                }))
+            }
+            _ => Err(ToolError::InvalidParameters(
+                format!("Invalid operation: {}. Must be 'list', 'get', 'enable', 'disable' or 'delete'", operation)
+            )),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use serde_json::json;
+
+use super::cidrs::is_valid_cidr;
+use super::dns::build_record;
+
+#[test]
+fn test_is_valid_cidr() {
+    assert!(is_valid_cidr("10.0.1.0/24"));
+    assert!(is_valid_cidr("fd00::/64"));
+    assert!(!is_valid_cidr("10.0.1.0"));
+    assert!(!is_valid_cidr("10.0.1.0/33"));
+    assert!(!is_valid_cidr("not-an-ip/8"));
+}
+
+#[test]
+fn test_build_a_record() {
+    let record = build_record(&json!({
+        "domain": "App.Example.com.",
+        "public_ip": ["203.0.113.10", "203.0.113.11:8080"],
+    })).unwrap();
+
+    assert_eq!(record["domain"], "app.example.com");
+    assert_eq!(record["record_type"], "A");
+    assert_eq!(record["public_ip"], json!(["203.0.113.10:80", "203.0.113.11:8080"]));
+    assert_eq!(record["formnet_ip"], json!([]));
+    assert_eq!(record["ttl"], 3600);
+}
+
+#[test]
+fn test_build_record_rejects_mismatched_records() {
+    // A records need IPv4 addresses
+    assert!(build_record(&json!({ "domain": "a.com", "public_ip": ["2001:db8::1"] })).is_err());
+    // Address records need an address
+    assert!(build_record(&json!({ "domain": "a.com" })).is_err());
+    // CNAME records need a target
+    assert!(build_record(&json!({ "domain": "a.com", "record_type": "CNAME" })).is_err());
+    assert!(build_record(&json!({ "domain": "a.com", "record_type": "CNAME", "cname_target": "b.com" })).is_ok());
+    assert!(build_record(&json!({ "domain": "a.com", "record_type": "MX" })).is_err());
+}
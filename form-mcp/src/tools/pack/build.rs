@@ -12,6 +12,7 @@ use reqwest::Client;
 use uuid::Uuid;
 
 use crate::errors::ToolError;
+use crate::models::operations::Operation;
 use crate::tools::{Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
 use crate::tools::registry::ToolRegistry;
 
@@ -119,6 +120,7 @@ impl PackBuildTool {
     /// Send a build request to the pack manager
     async fn submit_build_request(
         &self, 
+        build_id: &str,
         formfile_content: &str, 
         context_files: HashMap<String, String>,
         context: &ToolContext
@@ -171,16 +173,7 @@ impl PackBuildTool {
             .map_err(|e| ToolError::ExecutionFailed(format!("Failed to parse response: {}", e)))?;
         
         match queue_response {
-            QueueResponse::OpSuccess => {
-                // Return build ID for tracking
-                let build_id = Uuid::new_v4().to_string();
-                
-                Ok(json!({
-                    "status": "success",
-                    "build_id": build_id,
-                    "message": "Build request accepted successfully"
-                }))
-            },
+            QueueResponse::OpSuccess => Ok(accepted(build_id)),
             QueueResponse::Failure { reason } => {
                 Err(ToolError::ExecutionFailed(format!("Build request failed: {}", 
                     reason.unwrap_or_else(|| "Unknown reason".to_string()))))
@@ -242,7 +235,39 @@ impl Tool for PackBuildTool {
             }
         }
         
+        // Generate build ID for tracking, and remember it so the operation
+        // can be reconciled if the server restarts
+        let build_id = Uuid::new_v4().to_string();
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({ "build_id": build_id })).await;
+        }
+        
         // Submit build request
-        self.submit_build_request(formfile_content, context_files, &context).await
+        let result = self.submit_build_request(&build_id, formfile_content, context_files, &context).await?;
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({ "build_id": build_id, "submitted": true })).await;
+        }
+        Ok(result)
+    }
+    
+    async fn reconcile(&self, operation: &Operation) -> Option<ToolResult> {
+        // The tracking ID is not known to form-state, so all that can be
+        // recovered is whether the queue accepted the build
+        let checkpoint = operation.checkpoint.as_ref()?;
+        let build_id = checkpoint.get("build_id")?.as_str()?;
+        if checkpoint.get("submitted").and_then(Value::as_bool).unwrap_or(false) {
+            Some(Ok(accepted(build_id)))
+        } else {
+            None
+        }
     }
-} 
\ No newline at end of file
+} 
+
+/// Response for a build request the queue accepted
+fn accepted(build_id: &str) -> Value {
+    json!({
+        "status": "success",
+        "build_id": build_id,
+        "message": "Build request accepted successfully"
+    })
+}
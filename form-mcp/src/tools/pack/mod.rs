@@ -5,6 +5,8 @@
 
 pub mod build;
 pub mod ship;
+#[cfg(test)]
+mod tests;
 
 pub use build::PackBuildTool;
 pub use ship::PackShipTool;
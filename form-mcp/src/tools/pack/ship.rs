@@ -10,9 +10,12 @@ use serde_json::{json, Value};
 use reqwest::Client;
 use uuid::Uuid;
 
+use crate::billing::ResourceRequest;
 use crate::errors::ToolError;
+use crate::models::operations::Operation;
 use crate::tools::{Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
 use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
 
 // Constants for API endpoints
 const QUEUE_PORT: u16 = 53333;
@@ -122,6 +125,7 @@ impl PackShipTool {
     /// Send a deployment request
     async fn submit_ship_request(
         &self, 
+        deploy_id: &str,
         build_id: &str, 
         instance_name: &str,
         vm_config: Option<VMConfig>,
@@ -173,12 +177,9 @@ impl PackShipTool {
         
         match queue_response {
             QueueResponse::OpSuccess => {
-                // Generate deploy ID for tracking
-                let deploy_id = Uuid::new_v4().to_string();
-                
                 // Create status response
                 let status_response = ShipStatusResponse {
-                    deploy_id: deploy_id.clone(),
+                    deploy_id: deploy_id.to_string(),
                     instance_id: None, // Will be populated once VM is created
                     status: DeploymentStatus::Queued,
                     message: Some("Deployment request queued successfully".to_string()),
@@ -286,7 +287,83 @@ impl Tool for PackShipTool {
             None
         };
         
+        // Generate deploy ID for tracking, and remember it so the operation
+        // can be reconciled against form-state if the server restarts
+        let deploy_id = Uuid::new_v4().to_string();
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({ "deploy_id": deploy_id })).await;
+        }
+        
         // Submit ship request
-        self.submit_ship_request(build_id, instance_name, vm_config, &context).await
+        let result = self.submit_ship_request(&deploy_id, build_id, instance_name, vm_config, &context).await?;
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({ "deploy_id": deploy_id, "submitted": true })).await;
+        }
+        Ok(result)
     }
-} 
\ No newline at end of file
+    
+    async fn reconcile(&self, operation: &Operation) -> Option<ToolResult> {
+        let checkpoint = operation.checkpoint.as_ref()?;
+        let deploy_id = checkpoint.get("deploy_id")?.as_str()?;
+        let submitted = checkpoint.get("submitted").and_then(Value::as_bool).unwrap_or(false);
+        let build_id = operation.parameters.as_ref()?.get("build_id")?.as_str()?;
+        
+        let instances = StateClient::new()
+            .get(&format!("/instance/{}/get_by_build_id", build_id), &[])
+            .await
+            .ok()?;
+        let instances = instances.as_array().cloned().unwrap_or_default();
+        ship_outcome(deploy_id, &instances, submitted)
+    }
+    
+    fn resource_request(&self, params: &Value) -> Option<ResourceRequest> {
+        // A deploy without a VM config is charged the default VM size
+        let vm_config = &params["vm_config"];
+        Some(ResourceRequest {
+            vcpus: vm_config["vcpus"].as_u64().unwrap_or(1) as u32,
+            memory_mb: vm_config["memory_mb"].as_u64().unwrap_or(1024),
+        })
+    }
+} 
+
+/// Outcome of an interrupted deployment, from the instances form-state has
+/// for the shipped build. A deployment that was queued but has no instances
+/// yet is reported as still queued.
+pub(super) fn ship_outcome(deploy_id: &str, instances: &[Value], submitted: bool) -> Option<ToolResult> {
+    let status_of = |status: &str| {
+        instances.iter().find(|instance| instance["status"].as_str() == Some(status))
+    };
+    
+    if let Some(failed) = status_of("CriticalError").or_else(|| status_of("Killed")) {
+        return Some(Err(ToolError::ExecutionFailed(format!(
+            "Deployment '{}' failed: instance '{}' is {}",
+            deploy_id,
+            failed["instance_id"].as_str().unwrap_or_default(),
+            failed["status"].as_str().unwrap_or_default(),
+        ))));
+    }
+    
+    let (status, instance, message) = if let Some(started) = status_of("Started") {
+        (DeploymentStatus::Completed, Some(started), "Deployment completed successfully")
+    } else if let Some(instance) = instances.first() {
+        (DeploymentStatus::InProgress, Some(instance), "Deployment is in progress")
+    } else if submitted {
+        (DeploymentStatus::Queued, None, "Deployment request queued successfully")
+    } else {
+        return None;
+    };
+    
+    let status_response = ShipStatusResponse {
+        deploy_id: deploy_id.to_string(),
+        instance_id: instance.and_then(|instance| instance["instance_id"].as_str()).map(str::to_string),
+        status,
+        message: Some(message.to_string()),
+    };
+    
+    Some(Ok(json!({
+        "status": "success",
+        "deploy_id": deploy_id,
+        "message": message,
+        "details": status_response
+    })))
+}
@@ -0,0 +1,23 @@
+use serde_json::json;
+
+use super::ship::ship_outcome;
+
+#[test]
+fn test_ship_outcome() {
+    let started = json!({ "instance_id": "alice-app-1", "status": "Started" });
+    let building = json!({ "instance_id": "alice-app-2", "status": "Building" });
+
+    let result = ship_outcome("deploy-1", &[building.clone(), started], true).unwrap().unwrap();
+    assert_eq!(result["details"]["status"], "Completed");
+    assert_eq!(result["details"]["instance_id"], "alice-app-1");
+
+    let result = ship_outcome("deploy-1", &[building], true).unwrap().unwrap();
+    assert_eq!(result["details"]["status"], "InProgress");
+
+    let result = ship_outcome("deploy-1", &[], true).unwrap().unwrap();
+    assert_eq!(result["details"]["status"], "Queued");
+    assert!(ship_outcome("deploy-1", &[], false).is_none());
+
+    let killed = json!({ "instance_id": "alice-app-1", "status": "Killed" });
+    assert!(ship_outcome("deploy-1", &[killed], true).unwrap().is_err());
+}
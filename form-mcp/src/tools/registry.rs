@@ -9,7 +9,9 @@ use async_trait::async_trait;
 use serde::{Serialize, Deserialize};
 use serde_json::Value;
 
+use crate::billing::{BillingService, ResourceRequest};
 use crate::errors::ToolError;
+use crate::models::operations::Operation;
 use crate::tools::ToolContext;
 
 /// ToolParameter defines a parameter for a tool
@@ -81,11 +83,27 @@ pub trait Tool: Send + Sync {
         
         Ok(())
     }
+    
+    /// Find out the outcome of an operation of this tool that was
+    /// interrupted by a restart, using the checkpoint it saved. Returns
+    /// `None` when the outcome cannot be determined, in which case the
+    /// operation is marked as failed.
+    async fn reconcile(&self, _operation: &Operation) -> Option<ToolResult> {
+        None
+    }
+    
+    /// Compute the tool creates when executed with these parameters, which
+    /// is checked against the requester's quota before it executes. Returns
+    /// `None` for tools that create nothing.
+    fn resource_request(&self, _params: &Value) -> Option<ResourceRequest> {
+        None
+    }
 }
 
 /// ToolRegistry manages tool registration and discovery
 pub struct ToolRegistry {
     tools: RwLock<HashMap<String, Arc<dyn Tool>>>,
+    billing: RwLock<Option<Arc<BillingService>>>,
 }
 
 impl ToolRegistry {
@@ -93,9 +111,23 @@ impl ToolRegistry {
     pub fn new() -> Self {
         Self {
             tools: RwLock::new(HashMap::new()),
+            billing: RwLock::new(None),
         }
     }
     
+    /// Meter tool executions with the billing service, and check quotas
+    /// with it before tools create resources
+    pub fn set_billing(&self, billing: Arc<BillingService>) {
+        if let Ok(mut current) = self.billing.write() {
+            *current = Some(billing);
+        }
+    }
+    
+    /// Billing service tool executions are metered with, if any
+    pub fn billing(&self) -> Option<Arc<BillingService>> {
+        self.billing.read().ok()?.clone()
+    }
+    
     /// Register a tool with the registry
     pub fn register_tool(&self, tool: Arc<dyn Tool>) -> Result<(), ToolError> {
         let definition = tool.definition();
@@ -0,0 +1,171 @@
+// State API client
+//
+// This module provides a small client for the form-state datastore API,
+// shared by the metrics, network and DNS tools. form-state wraps every
+// payload in a `Response` enum, which the client unwraps into plain JSON.
+
+use std::collections::BTreeSet;
+
+use reqwest::Client;
+use serde_json::Value;
+
+use crate::errors::ToolError;
+use crate::tools::ToolContext;
+
+// Constants for the state API
+const STATE_PORT: u16 = 3004;
+
+/// Client for the form-state API on the local node
+#[derive(Clone)]
+pub struct StateClient {
+    http_client: Client,
+    base_url: String,
+}
+
+impl StateClient {
+    /// Create a client for the local state datastore
+    pub fn new() -> Self {
+        Self {
+            http_client: Client::new(),
+            base_url: format!("http://127.0.0.1:{}", STATE_PORT),
+        }
+    }
+
+    /// Create a client for the state datastore at `base_url`
+    pub fn with_base_url(base_url: impl Into<String>) -> Self {
+        Self {
+            http_client: Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Send a GET request and return the unwrapped payload
+    pub async fn get(&self, path: &str, query: &[(&str, String)]) -> Result<Value, ToolError> {
+        let request = self.http_client
+            .get(format!("{}{}", self.base_url, path))
+            .query(query);
+        self.send(request).await
+    }
+
+    /// Send a POST request with a JSON body and return the unwrapped payload
+    pub async fn post(&self, path: &str, body: &Value) -> Result<Value, ToolError> {
+        let request = self.http_client
+            .post(format!("{}{}", self.base_url, path))
+            .json(body);
+        self.send(request).await
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<Value, ToolError> {
+        let response = request
+            .send()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("State API request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(ToolError::ExecutionFailed(
+                format!("State API returned error status: {}", response.status())
+            ));
+        }
+
+        let body: Value = response
+            .json()
+            .await
+            .map_err(|e| ToolError::ExecutionFailed(format!("Failed to parse state API response: {}", e)))?;
+
+        unwrap_response(body)
+    }
+
+    /// Check that the requester owns the instance, unless they are an admin,
+    /// and return it
+    pub async fn authorize_instance(&self, instance_id: &str, context: &ToolContext) -> Result<Value, ToolError> {
+        let instance = self.get(&format!("/instance/{}/get", instance_id), &[]).await?;
+        if !context.is_admin && instance["instance_owner"] != context.user_id.as_str() {
+            return Err(ToolError::Forbidden(
+                format!("You do not have permission to access instance '{}'", instance_id)
+            ));
+        }
+        Ok(instance)
+    }
+
+    /// Check that the requester owns every instance of the build, unless they
+    /// are an admin, and return the instances
+    pub async fn authorize_build(&self, build_id: &str, context: &ToolContext) -> Result<Vec<Value>, ToolError> {
+        let instances = match self.get(&format!("/instance/{}/get_by_build_id", build_id), &[]).await? {
+            Value::Array(instances) => instances,
+            _ => Vec::new(),
+        };
+        if instances.is_empty() {
+            return Err(ToolError::ExecutionFailed(format!("Build '{}' has no instances", build_id)));
+        }
+        if !context.is_admin && instances.iter().any(|instance| instance["instance_owner"] != context.user_id.as_str()) {
+            return Err(ToolError::Forbidden(
+                format!("You do not have permission to access build '{}'", build_id)
+            ));
+        }
+        Ok(instances)
+    }
+
+    /// Domains the DNS records of the requester's instances are for
+    pub async fn owned_domains(&self, context: &ToolContext) -> Result<BTreeSet<String>, ToolError> {
+        let instances = match self.get("/instance/list", &[("owner", context.user_id.clone())]).await? {
+            Value::Array(instances) => instances,
+            _ => Vec::new(),
+        };
+        Ok(instances.iter()
+            .filter(|instance| instance["instance_owner"] == context.user_id.as_str())
+            .filter_map(|instance| instance["dns_record"]["domain"].as_str())
+            .map(|domain| domain.to_lowercase())
+            .collect())
+    }
+
+    /// Check that the DNS record of `domain` belongs to one of the
+    /// requester's instances, unless they are an admin
+    pub async fn authorize_domain(&self, domain: &str, context: &ToolContext) -> Result<(), ToolError> {
+        if context.is_admin || self.owned_domains(context).await?.contains(&domain.to_lowercase()) {
+            return Ok(());
+        }
+        Err(ToolError::Forbidden(
+            format!("You do not have permission to access the DNS record of '{}'", domain)
+        ))
+    }
+}
+
+impl Default for StateClient {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Unwrap a form-state `Response`, which serializes as
+/// `{"Success": {"Some": ..}}`, `{"Success": {"List": [..]}}`,
+/// `{"Success": "None"}` or `{"Failure": {"reason": ..}}`
+pub fn unwrap_response(body: Value) -> Result<Value, ToolError> {
+    if let Some(failure) = body.get("Failure") {
+        let reason = failure.get("reason")
+            .and_then(Value::as_str)
+            .unwrap_or("Unknown error from state API");
+        return Err(ToolError::ExecutionFailed(reason.to_string()));
+    }
+
+    match body.get("Success") {
+        Some(Value::String(none)) if none == "None" => Ok(Value::Null),
+        Some(Value::Object(success)) => success.get("Some")
+            .or_else(|| success.get("List"))
+            .or_else(|| success.get("Relationships"))
+            .cloned()
+            .ok_or_else(|| ToolError::ExecutionFailed("Unexpected state API response".to_string())),
+        _ => Err(ToolError::ExecutionFailed("Unexpected state API response".to_string())),
+    }
+}
+
+/// Collect the pagination parameters form-state list endpoints accept
+pub fn list_query(params: &Value) -> Vec<(&'static str, String)> {
+    let mut query = Vec::new();
+    if let Some(limit) = params.get("limit").and_then(Value::as_u64) {
+        query.push(("limit", limit.to_string()));
+    }
+    if let Some(cursor) = params.get("cursor").and_then(Value::as_str) {
+        query.push(("cursor", cursor.to_string()));
+    }
+    query
+}
@@ -0,0 +1,51 @@
+use serde_json::json;
+
+use crate::errors::ToolError;
+use crate::tools::require_permission;
+use crate::tools::state::unwrap_response;
+use crate::tools::ToolContext;
+
+fn context(is_admin: bool) -> ToolContext {
+    ToolContext {
+        user_id: "test-user".to_string(),
+        request_id: "test-request".to_string(),
+        context: Default::default(),
+        is_admin,
+        operation: None,
+    }
+}
+
+#[test]
+fn test_require_permission() {
+    // Users can read their metrics but not the formnet network
+    assert!(require_permission(&context(false), "metrics", "read").is_ok());
+    assert!(matches!(
+        require_permission(&context(false), "network", "read"),
+        Err(ToolError::Forbidden(_))
+    ));
+    assert!(matches!(
+        require_permission(&context(false), "network", "write"),
+        Err(ToolError::Forbidden(_))
+    ));
+
+    // Admins are granted everything through wildcards
+    assert!(require_permission(&context(true), "network", "write").is_ok());
+    assert!(require_permission(&context(true), "dns", "write").is_ok());
+}
+
+#[test]
+fn test_unwrap_response() {
+    let some = unwrap_response(json!({ "Success": { "Some": { "id": "a" } } })).unwrap();
+    assert_eq!(some, json!({ "id": "a" }));
+
+    let list = unwrap_response(json!({ "Success": { "List": [1, 2] } })).unwrap();
+    assert_eq!(list, json!([1, 2]));
+
+    let none = unwrap_response(json!({ "Success": "None" })).unwrap();
+    assert!(none.is_null());
+
+    match unwrap_response(json!({ "Failure": { "reason": "not found" } })) {
+        Err(ToolError::ExecutionFailed(reason)) => assert_eq!(reason, "not found"),
+        other => panic!("expected a failure, got {:?}", other),
+    }
+}
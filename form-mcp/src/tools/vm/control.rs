@@ -10,8 +10,10 @@ use reqwest::Client;
 use tiny_keccak::{Hasher, Sha3};
 
 use crate::errors::ToolError;
+use crate::models::operations::Operation;
 use crate::tools::{Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
 use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
 
 // Constants for API endpoints
 const QUEUE_PORT: u16 = 53333;
@@ -379,6 +381,15 @@ impl Tool for VMControlTool {
         // Convert name to instance_id if needed
         let instance_id = self.get_instance_id_by_name(vm_id, &context).await?;
         
+        // Remember the instance and operation, so the operation can be
+        // reconciled against form-state if the server restarts
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({
+                "instance_id": instance_id,
+                "operation": operation_str,
+            })).await;
+        }
+        
         // Try direct API endpoint first
         match self.control_vm_api(&instance_id, &operation, &context).await {
             Ok(result) => Ok(result),
@@ -389,4 +400,37 @@ impl Tool for VMControlTool {
             }
         }
     }
-} 
\ No newline at end of file
+    
+    async fn reconcile(&self, operation: &Operation) -> Option<ToolResult> {
+        let checkpoint = operation.checkpoint.as_ref()?;
+        let instance_id = checkpoint.get("instance_id")?.as_str()?;
+        let operation_str = checkpoint.get("operation")?.as_str()?;
+        
+        let instance = StateClient::new()
+            .get(&format!("/instance/{}/get", instance_id), &[])
+            .await
+            .ok()?;
+        control_outcome(instance_id, operation_str, &instance)
+    }
+}
+
+/// Outcome of an interrupted control operation, from the instance's status
+/// in form-state. The operation only counts as done once the instance has
+/// reached the state it asked for; otherwise it stays interrupted.
+pub(super) fn control_outcome(instance_id: &str, operation: &str, instance: &Value) -> Option<ToolResult> {
+    let (expected, done) = match operation {
+        "start" => ("Started", "started"),
+        "restart" => ("Started", "restarted"),
+        "stop" => ("Stopped", "stopped"),
+        _ => return None,
+    };
+    
+    if instance["status"].as_str() == Some(expected) {
+        Some(Ok(json!({
+            "success": true,
+            "message": format!("VM '{}' has been {}", instance_id, done)
+        })))
+    } else {
+        None
+    }
+}
@@ -11,9 +11,12 @@ use reqwest::Client;
 use tiny_keccak::{Hasher, Sha3};
 use rand::Rng;
 
+use crate::billing::ResourceRequest;
 use crate::errors::ToolError;
+use crate::models::operations::Operation;
 use crate::tools::{Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
 use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
 
 // Constants for API endpoints
 const QUEUE_PORT: u16 = 53333;
@@ -399,7 +402,73 @@ impl Tool for VMCreateTool {
         let random_id = rand::random::<u32>();
         let build_id = format!("{}-{}", name, random_id);
         
+        // Remember the build ID, so the operation can be reconciled against
+        // form-state if the server restarts before it finishes
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({ "build_id": build_id })).await;
+            operation.progress(0.5, format!("Submitting VM '{}'", build_id)).await;
+        }
+        
         // Submit create request with the generated build_id
-        self.submit_create_request(&vm_config, &context, build_id).await
+        let result = self.submit_create_request(&vm_config, &context, build_id.clone()).await?;
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({ "build_id": build_id, "submitted": true })).await;
+        }
+        Ok(result)
+    }
+    
+    async fn reconcile(&self, operation: &Operation) -> Option<ToolResult> {
+        let checkpoint = operation.checkpoint.as_ref()?;
+        let build_id = checkpoint.get("build_id")?.as_str()?;
+        let submitted = checkpoint.get("submitted").and_then(Value::as_bool).unwrap_or(false);
+        
+        let instances = StateClient::new()
+            .get(&format!("/instance/{}/get_by_build_id", build_id), &[])
+            .await
+            .ok()?;
+        let instances = instances.as_array().cloned().unwrap_or_default();
+        create_outcome(build_id, &instances, submitted)
+    }
+    
+    fn resource_request(&self, params: &Value) -> Option<ResourceRequest> {
+        // Sizes left out get the same defaults as the create request
+        Some(ResourceRequest {
+            vcpus: params.get("vcpus").and_then(|v| v.as_u64()).unwrap_or(1) as u32,
+            memory_mb: params.get("memory_mb").and_then(|v| v.as_u64()).unwrap_or(1024),
+        })
     }
-} 
\ No newline at end of file
+} 
+
+/// Outcome of an interrupted create, from the instances form-state has for
+/// its build. A create that was submitted but whose instances form-state
+/// does not know yet is still pending: queued writes take a while to land.
+pub(super) fn create_outcome(build_id: &str, instances: &[Value], submitted: bool) -> Option<ToolResult> {
+    if let Some(failed) = instances.iter().find(|instance| {
+        matches!(instance["status"].as_str(), Some("Killed") | Some("CriticalError"))
+    }) {
+        return Some(Err(ToolError::ExecutionFailed(format!(
+            "VM '{}' failed: instance '{}' is {}",
+            build_id,
+            failed["instance_id"].as_str().unwrap_or_default(),
+            failed["status"].as_str().unwrap_or_default(),
+        ))));
+    }
+    
+    if !instances.is_empty() {
+        Some(Ok(json!({
+            "success": true,
+            "vm_id": build_id,
+            "status": "creating",
+            "message": format!("VM '{}' creation has been initiated", build_id)
+        })))
+    } else if submitted {
+        Some(Ok(json!({
+            "success": true,
+            "vm_id": build_id,
+            "status": "pending",
+            "message": format!("VM '{}' creation was submitted and is still pending", build_id)
+        })))
+    } else {
+        None
+    }
+}
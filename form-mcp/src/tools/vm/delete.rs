@@ -10,8 +10,10 @@ use reqwest::Client;
 use tiny_keccak::{Hasher, Sha3};
 
 use crate::errors::ToolError;
+use crate::models::operations::Operation;
 use crate::tools::{Tool, ToolContext, ToolDefinition, ToolParameter, ToolResult};
 use crate::tools::registry::ToolRegistry;
+use crate::tools::state::StateClient;
 
 // Constants for API endpoints
 const QUEUE_PORT: u16 = 53333;
@@ -307,6 +309,12 @@ impl Tool for VMDeleteTool {
         // Convert name to instance_id if needed
         let instance_id = self.get_instance_id_by_name(vm_id, &context).await?;
         
+        // Remember the instance, so the operation can be reconciled against
+        // form-state if the server restarts
+        if let Some(operation) = &context.operation {
+            operation.checkpoint(json!({ "instance_id": instance_id })).await;
+        }
+        
         // Try direct API endpoint first
         match self.delete_vm_api(&instance_id, force, &context).await {
             Ok(result) => Ok(result),
@@ -317,4 +325,28 @@ impl Tool for VMDeleteTool {
             }
         }
     }
-} 
\ No newline at end of file
+    
+    async fn reconcile(&self, operation: &Operation) -> Option<ToolResult> {
+        let instance_id = operation.checkpoint.as_ref()?.get("instance_id")?.as_str()?;
+        
+        let instances = StateClient::new()
+            .get("/instance/list", &[])
+            .await
+            .ok()?;
+        let instances = instances.as_array().cloned().unwrap_or_default();
+        delete_outcome(instance_id, &instances)
+    }
+}
+
+/// Outcome of an interrupted delete, from the instances form-state lists.
+/// The delete is done once the instance is gone from the list.
+pub(super) fn delete_outcome(instance_id: &str, instances: &[Value]) -> Option<ToolResult> {
+    if instances.iter().any(|instance| instance["instance_id"].as_str() == Some(instance_id)) {
+        return None;
+    }
+    
+    Some(Ok(json!({
+        "success": true,
+        "message": format!("VM '{}' has been deleted", instance_id)
+    })))
+}
@@ -7,6 +7,8 @@ mod control;
 mod create;
 mod list;
 mod delete;
+#[cfg(test)]
+mod tests;
 
 pub use status::VMStatusTool;
 pub use control::VMControlTool;
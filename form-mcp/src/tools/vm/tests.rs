@@ -0,0 +1,43 @@
+use serde_json::json;
+
+use super::control::control_outcome;
+use super::create::create_outcome;
+use super::delete::delete_outcome;
+
+#[test]
+fn test_create_outcome() {
+    let building = json!({ "instance_id": "alice-web-1", "status": "Building" });
+    let result = create_outcome("web-1", &[building], true).unwrap().unwrap();
+    assert_eq!(result["status"], "creating");
+
+    // Submitted, but form-state has not seen the instance yet
+    let result = create_outcome("web-1", &[], true).unwrap().unwrap();
+    assert_eq!(result["status"], "pending");
+
+    // Never submitted
+    assert!(create_outcome("web-1", &[], false).is_none());
+
+    let failed = json!({ "instance_id": "alice-web-1", "status": "CriticalError" });
+    assert!(create_outcome("web-1", &[failed], true).unwrap().is_err());
+}
+
+#[test]
+fn test_control_outcome() {
+    let started = json!({ "instance_id": "alice-web-1", "status": "Started" });
+    let stopped = json!({ "instance_id": "alice-web-1", "status": "Stopped" });
+
+    assert!(control_outcome("alice-web-1", "start", &started).unwrap().is_ok());
+    assert!(control_outcome("alice-web-1", "restart", &started).unwrap().is_ok());
+    assert!(control_outcome("alice-web-1", "stop", &stopped).unwrap().is_ok());
+    assert!(control_outcome("alice-web-1", "start", &stopped).is_none());
+    assert!(control_outcome("alice-web-1", "stop", &started).is_none());
+}
+
+#[test]
+fn test_delete_outcome() {
+    let instance = json!({ "instance_id": "alice-web-1", "status": "Stopped" });
+
+    assert!(delete_outcome("alice-web-1", &[instance.clone()]).is_none());
+    assert!(delete_outcome("alice-web-2", &[instance]).unwrap().is_ok());
+    assert!(delete_outcome("alice-web-1", &[]).unwrap().is_ok());
+}
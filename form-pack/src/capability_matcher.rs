@@ -1,8 +1,9 @@
 use crate::formfile::Formfile;
+use crate::scheduler::{PlacementContext, ScoredNode, ScoringPipeline};
 use form_state::nodes::Node;
 use form_types::state::{Response as StateResponse, Success};
 use reqwest::Client;
-use std::collections::{HashMap, BTreeMap};
+use std::collections::HashMap;
 use std::error::Error;
 use log::{info, warn, error, debug};
 
@@ -11,6 +12,7 @@ use log::{info, warn, error, debug};
 pub struct CapabilityMatcher {
     form_state_url: String,
     http_client: Client,
+    pipeline: ScoringPipeline,
 }
 
 impl CapabilityMatcher {
@@ -23,9 +25,16 @@ impl CapabilityMatcher {
         Self {
             form_state_url,
             http_client: Client::new(),
+            pipeline: ScoringPipeline::default(),
         }
     }
 
+    /// Replace the default scoring pipeline used to rank capable nodes
+    pub fn with_pipeline(mut self, pipeline: ScoringPipeline) -> Self {
+        self.pipeline = pipeline;
+        self
+    }
+
     /// Check if the local node has the capability and capacity to handle the workload
     pub async fn is_local_node_capable(&self, formfile: &Formfile, node_id: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
         let node = self.get_node(node_id).await?;
@@ -47,7 +56,9 @@ impl CapabilityMatcher {
 
     /// Check if the local node is responsible for handling this workload
     /// - First filters nodes based on capability/capacity
-    /// - Then determines the responsible node using XOR of build_id and node_id
+    /// - Then ranks the capable nodes with the scoring pipeline
+    /// - The top `REPLICAS` nodes (one if the Formfile doesn't set it) are
+    ///   responsible, each running one replica
     pub async fn is_local_node_responsible(
         &self, 
         formfile: &Formfile, 
@@ -67,111 +78,43 @@ impl CapabilityMatcher {
             return Ok(false);
         }
         
-        let responsible_nodes = self.select_responsible_nodes(capable_nodes, build_id, 1);
+        let ctx = PlacementContext::new(build_id, formfile);
+        let replicas = formfile.get_placement().replica_count();
+        let responsible_nodes = self.select_responsible_nodes(capable_nodes, &ctx, replicas);
         if responsible_nodes.is_empty() {
             warn!("No responsible nodes determined for build {}", build_id);
             return Ok(false);
         }
         
-        let is_responsible = responsible_nodes[0].node_id == local_node_id;
+        let is_responsible = responsible_nodes.iter().any(|node| node.node_id == local_node_id);
         if is_responsible {
             info!("Local node {} is responsible for build {}", local_node_id, build_id);
         } else {
+            let responsible: Vec<&str> = responsible_nodes.iter().map(|n| n.node_id.as_str()).collect();
             info!("Local node {} is NOT responsible for build {}", local_node_id, build_id);
-            info!("Responsible node is: {}", responsible_nodes[0].node_id);
+            info!("Responsible nodes are: {:?}", responsible);
         }
         
         Ok(is_responsible)
     }
     
-    /// Determine if the local node is part of a cluster for this workload
-    /// For clustering (future implementation), returns true if the node is one of the N lowest XOR values
-    pub async fn is_local_node_in_cluster(
-        &self, 
-        formfile: &Formfile, 
-        local_node_id: &str, 
-        build_id: &str,
-        cluster_size: usize
-    ) -> Result<bool, Box<dyn Error + Send + Sync>> {
-        // First check if the local node is capable
-        if !self.is_local_node_capable(formfile, local_node_id).await? {
-            return Ok(false);
-        }
-        
-        // Get all capable nodes
-        let capable_nodes = self.get_capable_nodes(formfile).await?;
-        
-        if capable_nodes.is_empty() {
-            warn!("No nodes capable of handling this workload");
-            return Ok(false);
-        }
-        
-        // If we have fewer nodes than the requested cluster size, all capable nodes form the cluster
-        let effective_cluster_size = if capable_nodes.len() < cluster_size {
-            capable_nodes.len()
-        } else {
-            cluster_size
-        };
-        
-        let responsible_nodes = self.select_responsible_nodes(capable_nodes, build_id, effective_cluster_size);
-        if responsible_nodes.is_empty() {
-            warn!("No responsible nodes determined for build {}", build_id);
-            return Ok(false);
-        }
-        
-        // Check if the local node is in the cluster
-        let is_in_cluster = responsible_nodes.iter().any(|node| node.node_id == local_node_id);
-        
-        if is_in_cluster {
-            info!("Local node {} is part of the cluster for build {}", local_node_id, build_id);
-        } else {
-            info!("Local node {} is NOT part of the cluster for build {}", local_node_id, build_id);
-            let cluster_nodes: Vec<String> = responsible_nodes.iter().map(|n| n.node_id.clone()).collect();
-            info!("Cluster nodes are: {:?}", cluster_nodes);
-        }
-        
-        Ok(is_in_cluster)
+    /// Select the responsible nodes for a workload with the scoring pipeline.
+    /// Every node ranks the same node list the same way, so they all agree
+    /// on the result without coordinating.
+    pub fn select_responsible_nodes(&self, nodes: Vec<Node>, ctx: &PlacementContext, count: usize) -> Vec<Node> {
+        self.rank_nodes(nodes, ctx, count)
+            .into_iter()
+            .map(|scored| scored.node)
+            .collect()
     }
-    
-    /// Select the responsible nodes for a workload using XOR of build_id and node_id
-    /// Returns the top N nodes with the lowest XOR values
-    fn select_responsible_nodes(&self, nodes: Vec<Node>, build_id: &str, count: usize) -> Vec<Node> {
+
+    /// Like `select_responsible_nodes`, but keeps each node's scores
+    pub fn rank_nodes(&self, nodes: Vec<Node>, ctx: &PlacementContext, count: usize) -> Vec<ScoredNode> {
         if nodes.is_empty() || count == 0 {
             return Vec::new();
         }
-        
-        // Calculate XOR values for each node
-        let mut node_xor_values: BTreeMap<u64, Node> = BTreeMap::new();
-        
-        for node in nodes {
-            let xor_value = self.calculate_xor_value(&node.node_id, build_id);
-            node_xor_values.insert(xor_value, node);
-        }
-        
-        // Return the N nodes with the lowest XOR values
-        node_xor_values.into_iter()
-            .take(count)
-            .map(|(_, node)| node)
-            .collect()
-    }
-    
-    /// Calculate the XOR value between a node_id and build_id
-    /// This is used to deterministically select the responsible node
-    fn calculate_xor_value(&self, node_id: &str, build_id: &str) -> u64 {
-        // Convert the node_id and build_id to u64 values that can be XORed
-        let node_hash = self.hash_string(node_id);
-        let build_hash = self.hash_string(build_id);
-        
-        // XOR the hash values
-        node_hash ^ build_hash
-    }
-    
-    /// Simple hash function to convert a string to a u64
-    fn hash_string(&self, s: &str) -> u64 {
-        use std::hash::{Hash, Hasher};
-        let mut hasher = std::collections::hash_map::DefaultHasher::new();
-        s.hash(&mut hasher);
-        hasher.finish()
+
+        self.pipeline.select(nodes, ctx, count)
     }
 
     /// Get a list of all nodes from form-state that are capable of handling the workload
@@ -195,6 +138,11 @@ impl CapabilityMatcher {
     /// Check if a node can handle the workload defined in the formfile
     /// Returns (is_capable, reason) where reason is a string explaining why the node is not capable (if applicable)
     fn check_node_capability(&self, node: &Node, formfile: &Formfile) -> (bool, String) {
+        // Check the Formfile's region, label and TEE constraints
+        if let Err(reason) = formfile.get_placement().admits(node) {
+            return (false, reason);
+        }
+
         // Check CPU requirements
         let vcpus = formfile.get_vcpus() as usize;
         if node.capabilities.cpu_cores < vcpus {
@@ -280,7 +228,7 @@ impl CapabilityMatcher {
 
     /// Get a specific node from form-state
     async fn get_node(&self, node_id: &str) -> Result<Option<Node>, Box<dyn Error + Send + Sync>> {
-        let url = format!("{}/nodes/{}", self.form_state_url, node_id);
+        let url = format!("{}/node/{}/get", self.form_state_url, node_id);
         
         match self.http_client.get(&url).send().await {
             Ok(response) => {
@@ -307,7 +255,7 @@ impl CapabilityMatcher {
 
     /// Get all nodes from form-state
     async fn get_all_nodes(&self) -> Result<Vec<Node>, Box<dyn Error + Send + Sync>> {
-        let url = format!("{}/nodes/list", self.form_state_url);
+        let url = format!("{}/node/list", self.form_state_url);
         
         match self.http_client.get(&url).send().await {
             Ok(response) => {
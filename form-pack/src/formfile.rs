@@ -3,6 +3,7 @@ use serde_json::Value;
 use sha_crypt::{sha512_crypt_b64, Sha512Params};
 use serde::{Serialize, Deserialize};
 use std::{collections::{HashMap, HashSet}, path::{Component, PathBuf}};
+use form_state::placement::{AntiAffinityScope, Placement, TeeRequirement};
 
 pub struct FormfileParser {
     current_line: usize,
@@ -14,6 +15,7 @@ pub struct FormfileParser {
     system_config: Vec<SystemConfigOpt>,
     users: Vec<User>,
     workdir: Option<PathBuf>,
+    placement: Placement,
 }
 
 impl FormfileParser {
@@ -28,6 +30,7 @@ impl FormfileParser {
             system_config: Vec::new(),
             users: Vec::new(),
             workdir: None,
+            placement: Placement::default(),
         }
     }
 
@@ -85,6 +88,11 @@ impl FormfileParser {
             "GPU" => self.parse_gpu(args)?,
             "WORKDIR" => self.parse_workdir(args)?,
             "ENTRYPOINT" => self.parse_entrypoint(args)?,
+            "REGION" => self.parse_region(args)?,
+            "NODE_LABEL" => self.parse_node_label(args)?,
+            "TEE" => self.parse_tee(args)?,
+            "ANTI_AFFINITY" => self.parse_anti_affinity(args)?,
+            "REPLICAS" => self.parse_replicas(args)?,
             _ => {}
         }
 
@@ -1003,6 +1011,99 @@ impl FormfileParser {
         Ok(())
     }
 
+    /// Splits `required:a,b` or `preferred:a,b` into the kind and its values
+    fn parse_constraint(&self, directive: &str, args: &str) -> Result<(bool, Vec<String>), Box<dyn std::error::Error>> {
+        let (kind, values) = args.split_once(':').ok_or_else(|| {
+            Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Invalid {directive} format on line {}. Use 'required:value' or 'preferred:value'", self.current_line)
+            ))
+        })?;
+
+        let required = match kind.trim() {
+            "required" => true,
+            "preferred" => false,
+            _ => return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Invalid {directive} type on line {}. Use 'required' or 'preferred'", self.current_line)
+            ))),
+        };
+
+        let values: Vec<String> = values.split(',')
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect();
+        if values.is_empty() {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{directive} on line {} has no values", self.current_line)
+            )));
+        }
+
+        Ok((required, values))
+    }
+
+    /// Parse `REGION required:eu-west,eu-central` or `REGION preferred:us-east`
+    pub fn parse_region(&mut self, args: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (required, regions) = self.parse_constraint("REGION", args)?;
+        if required {
+            self.placement.required_regions.extend(regions);
+        } else {
+            self.placement.preferred_regions.extend(regions);
+        }
+        Ok(())
+    }
+
+    /// Parse `NODE_LABEL required:tier=gold` or `NODE_LABEL preferred:ssd`
+    pub fn parse_node_label(&mut self, args: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let (required, labels) = self.parse_constraint("NODE_LABEL", args)?;
+        if required {
+            self.placement.required_labels.extend(labels);
+        } else {
+            self.placement.preferred_labels.extend(labels);
+        }
+        Ok(())
+    }
+
+    /// Parse `TEE sev`, `TEE sgx`, `TEE tpm` or `TEE any`
+    pub fn parse_tee(&mut self, args: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let tee: TeeRequirement = args.parse().map_err(|e: String| {
+            Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{e}: line {}", self.current_line)
+            ))
+        })?;
+        self.placement.tee = Some(tee);
+        Ok(())
+    }
+
+    /// Parse `ANTI_AFFINITY node` or `ANTI_AFFINITY region`
+    pub fn parse_anti_affinity(&mut self, args: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let scope: AntiAffinityScope = args.parse().map_err(|e: String| {
+            Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("{e}: line {}", self.current_line)
+            ))
+        })?;
+        self.placement.anti_affinity = scope;
+        Ok(())
+    }
+
+    pub fn parse_replicas(&mut self, args: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let replicas: u32 = args.trim().parse()?;
+        if replicas == 0 || replicas > 64 {
+            return Err(Box::new(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("Invalid value provided for REPLICAS, must be at least 1 and no greater than 64: line {}: {}",
+                    self.current_line,
+                    replicas
+                )
+            )));
+        }
+        self.placement.replicas = Some(replicas);
+        Ok(())
+    }
+
     pub fn build_formfile(&self) -> Result<Formfile, Box<dyn std::error::Error>> {
         let name = self.name.clone().ok_or(
             Box::new(
@@ -1024,6 +1125,7 @@ impl FormfileParser {
             system_config: self.system_config.clone(),
             users: self.users.clone(),
             workdir,
+            placement: self.placement.clone(),
         })
     }
 }
@@ -1044,7 +1146,10 @@ pub struct Formfile {
     /// User configurations
     pub users: Vec<User>,
     /// Working directory for the application
-    pub workdir: PathBuf
+    pub workdir: PathBuf,
+    /// Where the workload may run
+    #[serde(default)]
+    pub placement: Placement,
 }
 
 impl Formfile {
@@ -1056,6 +1161,7 @@ impl Formfile {
                 "system_config": self.system_config.iter().map(|opt| opt.to_json()).collect::<Vec<String>>(),
                 "users": self.users.iter().map(|user| user.to_json()).collect::<Vec<String>>(),
                 "workdir": self.workdir.to_string_lossy(),
+                "placement": self.placement,
             }
         }).to_string()
     }
@@ -1117,6 +1223,10 @@ impl Formfile {
     pub fn is_model_required(&self) -> bool {
         self.model_required
     }
+
+    pub fn get_placement(&self) -> &Placement {
+        &self.placement
+    }
 }
 
 /// Instructions that are executed during teh image build phase
@@ -1923,6 +2033,35 @@ MODEL invalid_format
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_placement_parsing() -> Result<(), Box<dyn std::error::Error>> {
+        let content = r#"
+NAME compliant-app
+REGION required:eu-west,eu-central
+REGION preferred:eu-west
+NODE_LABEL required:tier=gold
+TEE sev
+ANTI_AFFINITY region
+REPLICAS 3
+"#;
+        let mut parser = FormfileParser::new();
+        let formfile = parser.parse(content)?;
+        let placement = formfile.get_placement();
+
+        assert_eq!(placement.required_regions, vec!["eu-west", "eu-central"]);
+        assert_eq!(placement.preferred_regions, vec!["eu-west"]);
+        assert_eq!(placement.required_labels, vec!["tier=gold"]);
+        assert_eq!(placement.tee, Some(TeeRequirement::Sev));
+        assert_eq!(placement.anti_affinity, AntiAffinityScope::Region);
+        assert_eq!(placement.replicas, Some(3));
+
+        assert!(FormfileParser::new().parse("NAME app\nREGION eu-west").is_err());
+        assert!(FormfileParser::new().parse("NAME app\nTEE enclave").is_err());
+        assert!(FormfileParser::new().parse("NAME app\nREPLICAS 0").is_err());
+
+        Ok(())
+    }
+
     #[test]
     fn test_complete_formfile_with_new_directives() -> Result<(), Box<dyn std::error::Error>> {
         let content = r#"
@@ -3,3 +3,4 @@ pub mod image_builder;
 pub mod pack;
 pub mod formfile;
 pub mod capability_matcher;
+pub mod scheduler;
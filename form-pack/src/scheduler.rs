@@ -0,0 +1,477 @@
+//! Scoring pipeline used to place workloads on capable nodes.
+//!
+//! Each `Scorer` can rule a node out with `filter` and rates the remaining
+//! nodes with an integer `score` between 0 and `MAX_SCORE`. The pipeline sums
+//! the weighted scores and picks nodes greedily, one at a time, so scorers
+//! that depend on earlier picks (region spread, anti-affinity) see them in
+//! the `PlacementContext`.
+//!
+//! Placement has to be deterministic: every node runs the same pipeline over
+//! the same node list from form-state and must arrive at the same answer
+//! without talking to the others. Scores are therefore integers, and scorers
+//! only look at the context and at node fields that change when an operator
+//! changes the node (totals, region, tags, GPUs), never at available
+//! capacity or load, which every node reports on its own schedule and peers
+//! see at different times. Ties are broken by the XOR of the hashed build id
+//! and node id, then by node id.
+
+use std::collections::{BTreeMap, BTreeSet};
+use form_state::nodes::Node;
+use form_state::placement::{AntiAffinityScope, Placement};
+use log::debug;
+use crate::formfile::Formfile;
+
+/// Highest score a single scorer can give a node
+pub const MAX_SCORE: i64 = 1000;
+
+/// Node tag prefix marking a taint; workloads are only placed on a tainted
+/// node if they tolerate every one of its taints, e.g. `taint:gpu-only`
+pub const TAINT_TAG_PREFIX: &str = "taint:";
+
+/// Node tag prefix an operator uses to make a node more or less attractive,
+/// from `priority:-100` to `priority:100`
+pub const PRIORITY_TAG_PREFIX: &str = "priority:";
+
+/// What is being placed, and what has already been placed
+#[derive(Clone, Debug, Default)]
+pub struct PlacementContext {
+    pub build_id: String,
+    pub vcpus: usize,
+    pub memory_mb: usize,
+    pub storage_gb: Option<u16>,
+    /// Requested GPU count per model
+    pub gpus: BTreeMap<String, u32>,
+    /// Placement constraints from the Formfile
+    pub placement: Placement,
+    /// Taints the workload tolerates
+    pub tolerations: BTreeSet<String>,
+    /// Nodes already hosting a replica of the workload
+    pub replicas: BTreeSet<String>,
+    /// Number of replicas per region, including the nodes picked so far
+    pub region_replicas: BTreeMap<String, usize>,
+}
+
+impl PlacementContext {
+    pub fn new(build_id: &str, formfile: &Formfile) -> Self {
+        let mut gpus = BTreeMap::new();
+        for request in formfile.get_gpu_devices().unwrap_or_default() {
+            let (model, count) = match request.split_once(':') {
+                Some((model, count)) => (model.to_string(), count.parse().unwrap_or(1)),
+                None => (request.clone(), 1),
+            };
+            *gpus.entry(model).or_insert(0) += count;
+        }
+
+        Self {
+            build_id: build_id.to_string(),
+            vcpus: formfile.get_vcpus() as usize,
+            memory_mb: formfile.get_memory(),
+            storage_gb: formfile.get_storage(),
+            gpus,
+            placement: formfile.get_placement().clone(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_tolerations(mut self, tolerations: impl IntoIterator<Item = String>) -> Self {
+        self.tolerations.extend(tolerations);
+        self
+    }
+
+    /// Marks nodes that already host a replica, so new replicas avoid them
+    /// and are spread away from their regions
+    pub fn with_replicas(mut self, node_ids: impl IntoIterator<Item = String>) -> Self {
+        self.replicas.extend(node_ids);
+        self
+    }
+
+    fn place(&mut self, node: &Node) {
+        self.replicas.insert(node.node_id.clone());
+        *self.region_replicas.entry(node.host_region.clone()).or_insert(0) += 1;
+    }
+}
+
+/// One dimension of the placement decision
+pub trait Scorer: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Rules a node out, with the reason why
+    fn filter(&self, _node: &Node, _ctx: &PlacementContext) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Rates a node from 0 to `MAX_SCORE`, higher is better
+    fn score(&self, _node: &Node, _ctx: &PlacementContext) -> i64 {
+        0
+    }
+}
+
+/// Prefers the smallest nodes the workload fits on, i.e. those it takes the
+/// largest share of, keeping large nodes free for large workloads. Scored on
+/// total capacity; whether the node has room left is up to the capacity
+/// check before scoring.
+pub struct BinPacking;
+
+impl Scorer for BinPacking {
+    fn name(&self) -> &'static str {
+        "bin_packing"
+    }
+
+    fn score(&self, node: &Node, ctx: &PlacementContext) -> i64 {
+        let cpu_total = node.capacity.cpu_total_cores as i64;
+        let memory_total = node.capacity.memory_total_bytes as i64;
+
+        (share(cpu_total, ctx.vcpus as i64) + share(memory_total, ctx.memory_mb as i64 * 1024 * 1024)) / 2
+    }
+}
+
+fn share(total: i64, requested: i64) -> i64 {
+    if total <= 0 {
+        return 0;
+    }
+    (requested.clamp(0, total) * MAX_SCORE) / total
+}
+
+/// Prefers regions holding the fewest replicas of the workload
+pub struct RegionSpread;
+
+impl Scorer for RegionSpread {
+    fn name(&self) -> &'static str {
+        "region_spread"
+    }
+
+    fn score(&self, node: &Node, ctx: &PlacementContext) -> i64 {
+        let replicas = ctx.region_replicas.get(&node.host_region).copied().unwrap_or(0) as i64;
+        MAX_SCORE / (1 + replicas)
+    }
+}
+
+/// Keeps GPU nodes free for GPU workloads, and places GPU workloads where
+/// they leave the fewest matching GPUs stranded
+pub struct GpuAware;
+
+impl Scorer for GpuAware {
+    fn name(&self) -> &'static str {
+        "gpu_aware"
+    }
+
+    fn score(&self, node: &Node, ctx: &PlacementContext) -> i64 {
+        let mut available: BTreeMap<&str, u32> = BTreeMap::new();
+        for gpu in &node.capabilities.gpu_models {
+            if let Some(model) = &gpu.model {
+                *available.entry(model.as_str()).or_insert(0) += gpu.count;
+            }
+        }
+
+        if ctx.gpus.is_empty() {
+            return if available.is_empty() { MAX_SCORE } else { 0 };
+        }
+
+        let leftover: u32 = ctx.gpus.iter()
+            .map(|(model, count)| available.get(model.as_str()).copied().unwrap_or(0).saturating_sub(*count))
+            .sum();
+        MAX_SCORE / (1 + leftover as i64)
+    }
+}
+
+/// Applies operator taints and priorities set through node tags
+pub struct OperatorPreference;
+
+impl Scorer for OperatorPreference {
+    fn name(&self) -> &'static str {
+        "operator_preference"
+    }
+
+    fn filter(&self, node: &Node, ctx: &PlacementContext) -> Result<(), String> {
+        for tag in node.metadata.tags() {
+            if let Some(taint) = tag.strip_prefix(TAINT_TAG_PREFIX) {
+                if !ctx.tolerations.contains(taint) {
+                    return Err(format!("Node is tainted with {taint}"));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn score(&self, node: &Node, _ctx: &PlacementContext) -> i64 {
+        let priority = node.metadata.tags().iter()
+            .find_map(|tag| tag.strip_prefix(PRIORITY_TAG_PREFIX)?.parse::<i64>().ok())
+            .unwrap_or(0)
+            .clamp(-100, 100);
+        MAX_SCORE / 2 + priority * 5
+    }
+}
+
+/// Enforces the Formfile's placement constraints and prefers the regions
+/// and node labels it asks for
+pub struct PlacementConstraints;
+
+impl Scorer for PlacementConstraints {
+    fn name(&self) -> &'static str {
+        "placement"
+    }
+
+    fn filter(&self, node: &Node, ctx: &PlacementContext) -> Result<(), String> {
+        ctx.placement.admits(node)?;
+        if ctx.placement.anti_affinity == AntiAffinityScope::Region
+            && ctx.region_replicas.get(&node.host_region).copied().unwrap_or(0) > 0
+        {
+            return Err(format!("A replica already runs in region {}", node.host_region));
+        }
+        Ok(())
+    }
+
+    fn score(&self, node: &Node, ctx: &PlacementContext) -> i64 {
+        let placement = &ctx.placement;
+        if placement.preferred_regions.is_empty() && placement.preferred_labels.is_empty() {
+            return MAX_SCORE;
+        }
+
+        let tags = node.metadata.tags();
+        let matched = placement.preferred_regions.contains(&node.host_region) as i64
+            + placement.preferred_labels.iter().filter(|label| tags.contains(label)).count() as i64;
+        let wanted = (!placement.preferred_regions.is_empty()) as i64 + placement.preferred_labels.len() as i64;
+        matched * MAX_SCORE / wanted
+    }
+}
+
+/// Never places two replicas of the same workload on one node
+pub struct AntiAffinity;
+
+impl Scorer for AntiAffinity {
+    fn name(&self) -> &'static str {
+        "anti_affinity"
+    }
+
+    fn filter(&self, node: &Node, ctx: &PlacementContext) -> Result<(), String> {
+        if ctx.replicas.contains(&node.node_id) {
+            return Err("Node already hosts a replica of this workload".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// A node's total and per scorer scores
+#[derive(Clone, Debug)]
+pub struct ScoredNode {
+    pub node: Node,
+    pub total: i64,
+    pub scores: Vec<(&'static str, i64)>,
+}
+
+/// Weighted scorers applied in order
+pub struct ScoringPipeline {
+    scorers: Vec<(Box<dyn Scorer>, i64)>,
+}
+
+impl Default for ScoringPipeline {
+    fn default() -> Self {
+        Self::empty()
+            .with_scorer(AntiAffinity, 1)
+            .with_scorer(PlacementConstraints, 4)
+            .with_scorer(OperatorPreference, 1)
+            .with_scorer(RegionSpread, 3)
+            .with_scorer(BinPacking, 2)
+            .with_scorer(GpuAware, 2)
+    }
+}
+
+impl ScoringPipeline {
+    pub fn empty() -> Self {
+        Self { scorers: Vec::new() }
+    }
+
+    pub fn with_scorer(mut self, scorer: impl Scorer + 'static, weight: i64) -> Self {
+        self.scorers.push((Box::new(scorer), weight));
+        self
+    }
+
+    /// Scores a node, or returns why it was filtered out
+    pub fn score(&self, node: &Node, ctx: &PlacementContext) -> Result<ScoredNode, String> {
+        let mut scores = Vec::with_capacity(self.scorers.len());
+        let mut total = 0;
+        for (scorer, weight) in &self.scorers {
+            scorer.filter(node, ctx).map_err(|reason| format!("{}: {reason}", scorer.name()))?;
+            let score = scorer.score(node, ctx).clamp(0, MAX_SCORE);
+            total += score * weight;
+            scores.push((scorer.name(), score));
+        }
+
+        Ok(ScoredNode { node: node.clone(), total, scores })
+    }
+
+    /// Picks up to `count` nodes, one at a time, each time taking the
+    /// highest scoring node given the picks made so far
+    pub fn select(&self, nodes: Vec<Node>, ctx: &PlacementContext, count: usize) -> Vec<ScoredNode> {
+        let mut ctx = ctx.clone();
+        for node in &nodes {
+            if ctx.replicas.contains(&node.node_id) {
+                *ctx.region_replicas.entry(node.host_region.clone()).or_insert(0) += 1;
+            }
+        }
+
+        let mut candidates = nodes;
+        let mut selected = Vec::new();
+        while selected.len() < count {
+            let best = candidates.iter()
+                .enumerate()
+                .filter_map(|(i, node)| match self.score(node, &ctx) {
+                    Ok(scored) => Some((i, scored)),
+                    Err(reason) => {
+                        debug!("Node {} filtered out: {reason}", node.node_id);
+                        None
+                    }
+                })
+                .max_by(|(_, a), (_, b)| {
+                    a.total.cmp(&b.total)
+                        .then_with(|| {
+                            placement_hash(&b.node.node_id, &ctx.build_id)
+                                .cmp(&placement_hash(&a.node.node_id, &ctx.build_id))
+                        })
+                        .then_with(|| b.node.node_id.cmp(&a.node.node_id))
+                });
+
+            let Some((i, scored)) = best else {
+                break;
+            };
+            debug!("Placing {} on {} with score {} {:?}", ctx.build_id, scored.node.node_id, scored.total, scored.scores);
+            ctx.place(&scored.node);
+            candidates.swap_remove(i);
+            selected.push(scored);
+        }
+
+        selected
+    }
+}
+
+/// XOR of the hashed node id and build id, used to break ties between
+/// equally scored nodes the same way on every node. Hashed with SHA3 rather
+/// than the std hasher, whose output may differ between Rust releases and
+/// so between nodes running different builds.
+pub fn placement_hash(node_id: &str, build_id: &str) -> u64 {
+    hash_string(node_id) ^ hash_string(build_id)
+}
+
+fn hash_string(s: &str) -> u64 {
+    use tiny_keccak::{Hasher, Sha3};
+    let mut hasher = Sha3::v256();
+    let mut digest = [0u8; 32];
+    hasher.update(s.as_bytes());
+    hasher.finalize(&mut digest);
+    u64::from_be_bytes(digest[..8].try_into().expect("digest is 32 bytes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(id: &str, region: &str, free_cores: i64, tags: &[&str]) -> Node {
+        let mut value = serde_json::to_value(Node::default()).unwrap();
+        value["metadata"]["tags"] = serde_json::json!(tags);
+        let mut node: Node = serde_json::from_value(value).unwrap();
+        node.node_id = id.to_string();
+        node.host_region = region.to_string();
+        node.capabilities.cpu_cores = 8;
+        node.capacity.cpu_total_cores = 8;
+        node.capacity.cpu_available_cores = free_cores * 1000;
+        node.capacity.memory_total_bytes = 16 << 30;
+        node.capacity.memory_available_bytes = 8 << 30;
+        node
+    }
+
+    fn ctx() -> PlacementContext {
+        PlacementContext {
+            build_id: "build".to_string(),
+            vcpus: 1,
+            memory_mb: 512,
+            ..Default::default()
+        }
+    }
+
+    fn ids(selected: &[ScoredNode]) -> Vec<&str> {
+        selected.iter().map(|s| s.node.node_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_selection_spreads_across_regions() {
+        let nodes = vec![
+            node("a", "us-east", 2, &[]),
+            node("b", "us-east", 2, &[]),
+            node("c", "eu-west", 6, &[]),
+        ];
+        let selected = ScoringPipeline::default().select(nodes, &ctx(), 2);
+        let picked = ids(&selected);
+        assert_eq!(picked.len(), 2);
+        assert!(picked.contains(&"c"));
+    }
+
+    #[test]
+    fn test_taints_and_replicas_are_filtered() {
+        let nodes = vec![
+            node("a", "us-east", 4, &["taint:gpu-only"]),
+            node("b", "us-east", 4, &[]),
+            node("c", "us-east", 4, &[]),
+        ];
+        let pipeline = ScoringPipeline::default();
+
+        let selected = pipeline.select(nodes.clone(), &ctx().with_replicas(["b".to_string()]), 3);
+        assert_eq!(ids(&selected), vec!["c"]);
+
+        let tolerant = ctx().with_tolerations(["gpu-only".to_string()]);
+        assert_eq!(pipeline.select(nodes, &tolerant, 3).len(), 3);
+    }
+
+    #[test]
+    fn test_placement_constraints_are_applied() {
+        let nodes = vec![
+            node("a", "us-east", 4, &[]),
+            node("b", "eu-west", 4, &[]),
+            node("c", "eu-west", 4, &[]),
+            node("d", "eu-central", 4, &[]),
+        ];
+        let mut ctx = ctx();
+        ctx.placement = Placement {
+            required_regions: vec!["eu-west".to_string(), "eu-central".to_string()],
+            anti_affinity: AntiAffinityScope::Region,
+            ..Default::default()
+        };
+
+        let selected = ScoringPipeline::default().select(nodes, &ctx, 3);
+        let picked = ids(&selected);
+        assert_eq!(picked.len(), 2);
+        assert!(picked.contains(&"d"));
+        assert!(!picked.contains(&"a"));
+    }
+
+    #[test]
+    fn test_scores_ignore_available_capacity_and_load() {
+        let pipeline = ScoringPipeline::default();
+        let quiet = node("a", "us-east", 6, &[]);
+        let mut busy = quiet.clone();
+        busy.capacity.cpu_available_cores = 1000;
+        busy.capacity.memory_available_bytes = 1 << 30;
+        busy.metrics.load_avg_1 = 8000;
+
+        assert_eq!(
+            pipeline.score(&quiet, &ctx()).unwrap().total,
+            pipeline.score(&busy, &ctx()).unwrap().total
+        );
+
+        // Smaller nodes are preferred for bin packing
+        let mut small = node("b", "us-east", 4, &[]);
+        small.capacity.cpu_total_cores = 4;
+        assert!(BinPacking.score(&small, &ctx()) > BinPacking.score(&quiet, &ctx()));
+    }
+
+    #[test]
+    fn test_selection_is_deterministic() {
+        let nodes: Vec<Node> = (0..8).map(|i| node(&format!("node-{i}"), "us-east", 4, &[])).collect();
+        let mut reversed = nodes.clone();
+        reversed.reverse();
+
+        let pipeline = ScoringPipeline::default();
+        let first = pipeline.select(nodes, &ctx(), 3);
+        let second = pipeline.select(reversed, &ctx(), 3);
+        assert_eq!(ids(&first), ids(&second));
+    }
+}
@@ -23,7 +23,7 @@ pub struct ResolverManager {
 impl ResolvesServerCert for ResolverManager {
     fn resolve(&self, client_hello: ClientHello) -> Option<Arc<CertifiedKey>> {
         if let Some(domain) = client_hello.server_name() {
-            if domain.starts_with(".fog") {
+            if domain.ends_with(".fog") {
                 return self.vanity_resolvers.resolve(client_hello);
             } else if let Ok(guard) = self.acme_resolvers.lock() {
                 if let Some(resolver) = guard.get(domain) {
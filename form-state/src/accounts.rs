@@ -600,6 +600,7 @@ impl AccountState {
     pub fn account_op(&mut self, op: AccountOp) -> Option<(String, String)> {
         log::info!("Applying peer op");
         self.map.apply(op.clone());
+        crate::watch::account_changed(&op, |key| self.get_account(key));
         match op {
             Op::Up { dot, key, op: _ } => Some((dot.actor, key)),
             Op::Rm { .. } => None
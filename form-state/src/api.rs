@@ -1,4 +1,8 @@
-use crate::datastore::{DataStore, pong, complete_bootstrap, process_message, full_state};
+use crate::datastore::{
+    DataStore, AntiEntropyStatus, DB_HANDLE, ANTI_ENTROPY_STATUS, pong, complete_bootstrap,
+    process_message, full_state, sync_pull, anti_entropy_round,
+};
+use crate::db::compact_datastore;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::Mutex;
@@ -7,7 +11,7 @@ use axum::{
     routing::{post, get}, 
     middleware, 
     Json,
-    extract::{Path, State},
+    extract::{Path, State, DefaultBodyLimit},
     response::{Response, IntoResponse},
     http::{Request, StatusCode},
     body::Body,
@@ -15,6 +19,7 @@ use axum::{
 use serde::{Serialize, Deserialize};
 use crate::helpers::{
     network::*, 
+    dns::*,
     nodes::*, 
     instances::*, 
     account::*, 
@@ -22,6 +27,15 @@ use crate::helpers::{
     model::*,
     api_key_handlers::*,
 };
+use crate::snapshot::{
+    create_snapshot, list_snapshots, download_snapshot,
+    restore_stored_snapshot, restore_uploaded_snapshot, MAX_SNAPSHOT_UPLOAD_BYTES
+};
+use crate::autoscaler::{autoscale_round, evaluate_now, get_cluster_decisions, list_decisions, set_autoscaling};
+use crate::watch::watch;
+use crate::metrics_history::{get_instance_metrics_history, get_node_metrics_history, sample_round};
+use crate::migration::migrate_instance;
+use crate::resize::resize_instance;
 use crate::auth::{
     JWKSManager, JwtClaims, jwt_auth_middleware, AuthError,
     verify_project_path_access, has_resource_access, extract_user_info
@@ -33,6 +47,11 @@ use tokio::net::TcpListener;
 use serde_json::json;
 use crate::billing::middleware::EligibilityError;
 
+const COMPACTION_INTERVAL_SECS: u64 = 300;
+const ANTI_ENTROPY_INTERVAL_SECS: u64 = 30;
+const AUTOSCALER_INTERVAL_SECS: u64 = 60;
+const METRICS_SAMPLE_INTERVAL_SECS: u64 = 60;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum HealthStatus {
@@ -47,18 +66,30 @@ pub struct HealthResponse {
     #[serde(skip_serializing_if = "Option::is_none")]
     version: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    uptime: Option<u64>
+    uptime: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    anti_entropy: Option<AntiEntropyStatus>,
 }
 
 async fn health_check() -> Json<HealthResponse> {
     // Get the version from Cargo.toml if available
     let version = option_env!("CARGO_PKG_VERSION").map(String::from);
     
-    // Return a healthy status
+    let anti_entropy = ANTI_ENTROPY_STATUS.read()
+        .map(|status| status.clone())
+        .unwrap_or_else(|e| e.into_inner().clone());
+
+    // A failed sync round means this node may be serving stale state
+    let status = match &anti_entropy.last_error {
+        Some(reason) => HealthStatus::Degraded { reason: format!("Anti-entropy sync failed: {reason}") },
+        None => HealthStatus::Healthy,
+    };
+
     Json(HealthResponse {
-        status: HealthStatus::Healthy,
+        status,
         version,
-        uptime: None // Could add uptime calculation if needed
+        uptime: None, // Could add uptime calculation if needed
+        anti_entropy: Some(anti_entropy).filter(|status| status.rounds > 0),
     })
 }
 
@@ -160,7 +191,8 @@ pub fn app(state: Arc<Mutex<DataStore>>) -> Router {
         .route("/bootstrap/network_state", get(network_state))
         .route("/bootstrap/peer_state", get(peer_state))
         .route("/bootstrap/cidr_state", get(cidr_state))
-        .route("/bootstrap/assoc_state", get(assoc_state));
+        .route("/bootstrap/assoc_state", get(assoc_state))
+        .route("/sync/pull", post(sync_pull));
 
     let network_writers_api = Router::new()
         .route("/user/create", post(create_user))
@@ -177,11 +209,34 @@ pub fn app(state: Arc<Mutex<DataStore>>) -> Router {
         .route("/dns/create", post(create_dns))
         .route("/dns/update", post(update_dns))
         .route("/dns/:domain/delete", post(delete_dns))
+        // DNSSEC keys, readable by Formation nodes only
+        .route("/dnssec/sign", post(sign_zone))
+        .route("/dnssec/update", post(update_dnssec))
+        .route("/dnssec/:zone/delete", post(delete_dnssec))
+        .route("/dnssec/list", get(list_dnssec_zones))
         .route("/node/create", post(create_node))
         .route("/node/update", post(update_node))
         .route("/node/:id/get", get(get_node))
         .route("/node/:id/delete", post(delete_node))
         .route("/user/redeem", post(redeem_invite))
+        // Snapshot and restore of the full datastore
+        .route("/admin/snapshot/create", post(create_snapshot))
+        .route("/admin/snapshot/list", get(list_snapshots))
+        .route("/admin/snapshot/:name/download", get(download_snapshot))
+        .route("/admin/snapshot/:name/restore", post(restore_stored_snapshot))
+        .route(
+            "/admin/snapshot/restore",
+            post(restore_uploaded_snapshot).layer(DefaultBodyLimit::max(MAX_SNAPSHOT_UPLOAD_BYTES))
+        )
+        // Autoscaler decision history and manual evaluation
+        .route("/admin/autoscaler/decisions", get(list_decisions))
+        .route("/admin/autoscaler/evaluate", post(evaluate_now))
+        // Server-sent change feed for instances, nodes, DNS and accounts
+        .route("/watch", get(watch))
+        // Recording a completed live migration
+        .route("/instance/migrate", post(migrate_instance))
+        // Recording an online resize, checked against the owner's quota
+        .route("/instance/resize", post(resize_instance))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             node_auth_middleware,
@@ -210,10 +265,13 @@ pub fn app(state: Arc<Mutex<DataStore>>) -> Router {
         .route("/dns/:domain/get", get(get_dns_record))
         .route("/dns/:node_ip/list", get(get_dns_records_by_node_ip))
         .route("/dns/list", get(list_dns_records))
+        .route("/dnssec/:zone/ds", get(get_ds))
         
         // Node management
         .route("/node/list", get(list_nodes))
+        .route("/node/local", get(get_local_node_id))
         .route("/node/:id/metrics", get(get_node_metrics))
+        .route("/node/:id/metrics/history", get(get_node_metrics_history))
         .route("/node/list/metrics", get(list_node_metrics))
         
         // Node authentication key management
@@ -234,8 +292,11 @@ pub fn app(state: Arc<Mutex<DataStore>>) -> Router {
         .route("/instance/:build_id/get_instance_ips", get(get_instance_ips))
         .route("/instance/:instance_id/delete", post(delete_instance))
         .route("/instance/:instance_id/metrics", get(get_instance_metrics))
+        .route("/instance/:instance_id/metrics/history", get(get_instance_metrics_history))
         .route("/instance/list/metrics", get(list_instance_metrics))
         .route("/cluster/:build_id/metrics", get(get_cluster_metrics))
+        .route("/cluster/:build_id/autoscaler/decisions", get(get_cluster_decisions))
+        .route("/cluster/:build_id/autoscaling", post(set_autoscaling))
         .route("/instance/list", get(list_instances))
         
         // Account management
@@ -245,6 +306,7 @@ pub fn app(state: Arc<Mutex<DataStore>>) -> Router {
         .route("/account/update", post(update_account))
         .route("/account/delete", post(delete_account))
         .route("/account/transfer-ownership", post(transfer_instance_ownership))
+        .route("/account/:address/quota", get(crate::billing::handlers::get_account_quota))
         
         // API key management
         .route("/api-keys", get(list_api_keys_handler))
@@ -391,6 +453,80 @@ pub async fn run_queue_reader(datastore: Arc<Mutex<DataStore>>, mut shutdown: to
     Ok(())
 }
 
+/// Periodically fold the write-ahead op log of every map into the entries
+/// table, so startup replay stays short even on quiet maps that never reach
+/// the per-map compaction threshold.
+pub async fn run_compaction(datastore: Arc<Mutex<DataStore>>, mut shutdown: tokio::sync::broadcast::Receiver<()>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(COMPACTION_INTERVAL_SECS));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                let guard = datastore.lock().await;
+                if let Err(e) = compact_datastore(&DB_HANDLE, &guard) {
+                    log::error!("Error compacting datastore: {e}");
+                }
+                drop(guard);
+            }
+            _ = shutdown.recv() => {
+                break;
+            }
+        }
+    }
+}
+
+/// Periodically pull missing state from a random peer so nodes that missed
+/// queue messages converge without a full bootstrap.
+pub async fn run_anti_entropy(datastore: Arc<Mutex<DataStore>>, mut shutdown: tokio::sync::broadcast::Receiver<()>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(ANTI_ENTROPY_INTERVAL_SECS));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = anti_entropy_round(datastore.clone()).await {
+                    log::warn!("Anti-entropy round failed: {e}");
+                }
+            }
+            _ = shutdown.recv() => {
+                break;
+            }
+        }
+    }
+}
+
+/// Periodically evaluate the clusters this node is responsible for against
+/// their scaling policies.
+pub async fn run_autoscaler(datastore: Arc<Mutex<DataStore>>, mut shutdown: tokio::sync::broadcast::Receiver<()>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(AUTOSCALER_INTERVAL_SECS));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = autoscale_round(datastore.clone()).await {
+                    log::warn!("Autoscaling round failed: {e}");
+                }
+            }
+            _ = shutdown.recv() => {
+                break;
+            }
+        }
+    }
+}
+
+/// Periodically record node and instance metrics into the metrics history.
+pub async fn run_metrics_sampler(datastore: Arc<Mutex<DataStore>>, mut shutdown: tokio::sync::broadcast::Receiver<()>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(METRICS_SAMPLE_INTERVAL_SECS));
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {
+                if let Err(e) = sample_round(datastore.clone()).await {
+                    log::warn!("Metrics sampling round failed: {e}");
+                }
+            }
+            _ = shutdown.recv() => {
+                break;
+            }
+        }
+    }
+}
+
 /// Run both the API server and queue reader
 pub async fn run(datastore: Arc<Mutex<DataStore>>, mut shutdown: tokio::sync::broadcast::Receiver<()>) -> Result<(), Box<dyn std::error::Error>> {
     let router = app(datastore.clone());
@@ -5,6 +5,7 @@ pub mod jwks;
 pub mod claims;
 pub mod middleware;
 pub mod permissions;
+pub mod node;
 
 pub use config::AuthConfig;
 pub use jwks::JWKSManager;
@@ -0,0 +1,139 @@
+//! Authentication of requests one Formation node makes to another on its
+//! own authority, rather than with a signature of the owner of the
+//! resource, e.g. the autoscaler asking vmm-service to scale a cluster.
+//!
+//! The sending node signs the request path, an expiry and a digest of the
+//! body with its node key. The receiving node recovers the signer from the
+//! signature, recomputes the digest over the body it actually received and
+//! checks that the signer is a registered node. A captured signature can't
+//! be replayed against another path or with another body, and stops being
+//! accepted after `NODE_REQUEST_TTL_SECS`.
+use std::time::{SystemTime, UNIX_EPOCH};
+use alloy_primitives::Address;
+use axum::http::HeaderMap;
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use tiny_keccak::{Hasher, Sha3};
+
+pub const NODE_SIGNATURE_HEADER: &str = "x-formation-node-signature";
+pub const NODE_RECOVERY_ID_HEADER: &str = "x-formation-node-recovery-id";
+pub const NODE_EXPIRES_HEADER: &str = "x-formation-node-expires";
+
+/// How long a node signature is accepted for after it was made
+pub const NODE_REQUEST_TTL_SECS: u64 = 60;
+
+/// Digest of a request body, as signed by the sending node
+pub fn body_digest(body: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3::v256();
+    let mut digest = [0u8; 32];
+    hasher.update(body);
+    hasher.finalize(&mut digest);
+    digest
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NodeSignature {
+    pub signature: String,
+    pub recovery_id: u32,
+    /// Unix timestamp after which the signature is no longer accepted
+    pub expires_at: u64,
+}
+
+impl NodeSignature {
+    /// Signs a request to `path` whose body has the given digest with a node
+    /// key, given as hex
+    pub fn sign(signing_key: &str, path: &str, digest: &[u8; 32]) -> Result<Self, String> {
+        let key = hex::decode(signing_key).map_err(|e| format!("Invalid node key: {e}"))?;
+        let key = SigningKey::from_slice(&key).map_err(|e| format!("Invalid node key: {e}"))?;
+        let expires_at = now() + NODE_REQUEST_TTL_SECS;
+        let hash = message_hash(path, expires_at, digest);
+        let (signature, recovery_id) = key.sign_recoverable(&hash)
+            .map_err(|e| format!("Unable to sign node request: {e}"))?;
+
+        Ok(Self {
+            signature: hex::encode(signature.to_bytes()),
+            recovery_id: recovery_id.to_byte() as u32,
+            expires_at,
+        })
+    }
+
+    /// Headers carrying the signature
+    pub fn headers(&self) -> [(&'static str, String); 3] {
+        [
+            (NODE_SIGNATURE_HEADER, self.signature.clone()),
+            (NODE_RECOVERY_ID_HEADER, self.recovery_id.to_string()),
+            (NODE_EXPIRES_HEADER, self.expires_at.to_string()),
+        ]
+    }
+
+    /// Reads the signature from request headers, if the request has one
+    pub fn from_headers(headers: &HeaderMap) -> Option<Self> {
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+        Some(Self {
+            signature: header(NODE_SIGNATURE_HEADER)?.to_string(),
+            recovery_id: header(NODE_RECOVERY_ID_HEADER)?.parse().ok()?,
+            expires_at: header(NODE_EXPIRES_HEADER)?.parse().ok()?,
+        })
+    }
+
+    /// Recovers the address of the node that signed a request to `path`
+    /// whose body has the given digest. Whether that address belongs to a
+    /// registered node is up to the caller.
+    pub fn verify(&self, path: &str, digest: &[u8; 32]) -> Result<String, String> {
+        let now = now();
+        if self.expires_at < now {
+            return Err("Node signature has expired".to_string());
+        }
+        if self.expires_at > now + NODE_REQUEST_TTL_SECS {
+            return Err("Node signature expires too far in the future".to_string());
+        }
+
+        let signature = hex::decode(&self.signature)
+            .map_err(|e| format!("Invalid node signature: {e}"))?;
+        let signature = Signature::from_slice(&signature)
+            .map_err(|e| format!("Invalid node signature: {e}"))?;
+        let recovery_id = RecoveryId::from_byte(self.recovery_id as u8)
+            .ok_or("Invalid node signature recovery id".to_string())?;
+        let hash = message_hash(path, self.expires_at, digest);
+        let key = VerifyingKey::recover_from_msg(&hash, &signature, recovery_id)
+            .map_err(|e| format!("Unable to recover node key: {e}"))?;
+
+        Ok(hex::encode(Address::from_public_key(&key)))
+    }
+}
+
+fn message_hash(path: &str, expires_at: u64, digest: &[u8; 32]) -> [u8; 32] {
+    let message = format!("NodeRequest:{path}:{expires_at}:{}", hex::encode(digest));
+    body_digest(message.as_bytes())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_node_signature_binds_path_and_body() {
+        let key = SigningKey::random(&mut thread_rng());
+        let node_id = hex::encode(Address::from_private_key(&key));
+        let key = hex::encode(key.to_bytes());
+        let digest = body_digest(b"{\"build_id\":\"build1\"}");
+
+        let signature = NodeSignature::sign(&key, "/cluster/build1/autoscale", &digest).unwrap();
+        assert_eq!(signature.verify("/cluster/build1/autoscale", &digest).unwrap(), node_id);
+
+        // Another path or body recovers some other key, never the node's
+        let other = body_digest(b"{\"build_id\":\"build2\"}");
+        assert_ne!(signature.verify("/cluster/build2/autoscale", &digest).ok(), Some(node_id.clone()));
+        assert_ne!(signature.verify("/cluster/build1/autoscale", &other).ok(), Some(node_id));
+
+        let expired = NodeSignature { expires_at: now() - 1, ..signature };
+        assert!(expired.verify("/cluster/build1/autoscale", &digest).is_err());
+    }
+}
@@ -0,0 +1,523 @@
+//! Metric-driven autoscaling.
+//!
+//! Every `AUTOSCALER_INTERVAL_SECS` the node evaluates the clusters it is
+//! responsible for (those whose template instance it hosts) against their
+//! `ScalingPolicy`, using the average CPU utilisation reported by the
+//! cluster's instances. For clusters whose owner enabled autoscaling through
+//! `/cluster/:build_id/autoscaling`, scale out and scale in decisions are
+//! turned into a `ScalingOperation` and handed to the local vmm-service in a
+//! request signed with the node key (see `crate::auth::node`); vmm-service
+//! runs it on the node's own authority. Every decision, including the ones
+//! that leave a cluster alone, is kept in the local database so policies can
+//! be tuned against what the autoscaler actually saw.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::{extract::{Path, Query, State}, Json};
+use bincode::{serialize, deserialize};
+use form_types::state::{Response, Success};
+use redb::{Database, ReadableTable, TableDefinition};
+use reqwest::Client;
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+use crate::auth::{get_wallet_address, JwtClaims};
+use crate::auth::node::{body_digest, NodeSignature};
+use crate::datastore::{DataStore, DB_HANDLE};
+use crate::helpers::instances::fetch_instance_metrics;
+use crate::instances::{Instance, InstanceCluster, ScalingPolicy};
+use crate::scaling::ScalingOperation;
+
+// Decision history, keyed by `{build_id}/{evaluated_at:020}/{uuid}`
+const DECISIONS_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("autoscaler_decisions");
+
+/// Decisions kept per cluster; older ones are pruned as new ones are written.
+pub const MAX_DECISIONS_PER_CLUSTER: usize = 500;
+
+/// Decisions returned by the history endpoints when no limit is given.
+const DEFAULT_DECISION_LIMIT: usize = 50;
+
+/// vmm-service on the local node, which executes scaling operations
+const VMM_URL: &str = "http://127.0.0.1:3002";
+
+/// A scaling operation the autoscaler asks the local vmm-service to run.
+/// Sent to `/cluster/:build_id/autoscale` with the node signature headers.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AutoscaleRequest {
+    pub build_id: String,
+    pub operation: ScalingOperation,
+}
+
+/// Enables or disables autoscaling of a build's cluster
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SetAutoscalingRequest {
+    pub enabled: bool,
+    /// Replaces the cluster's scaling policy when given
+    pub scaling_policy: Option<ScalingPolicy>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub enum AutoscaleAction {
+    ScaleOut,
+    ScaleIn,
+    /// Utilisation is within the policy's band
+    NoChange,
+    /// Utilisation called for scaling, but the matching cooldown has not passed
+    Cooldown,
+    /// The cluster could not be evaluated, see `reason`
+    Skipped,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct AutoscaleDecision {
+    pub build_id: String,
+    pub evaluated_at: i64,
+    pub current_instances: u32,
+    /// Average CPU utilisation across the instances that reported metrics
+    pub cpu_utilization: Option<u32>,
+    pub target_cpu_utilization: u32,
+    pub action: AutoscaleAction,
+    pub operation: Option<ScalingOperation>,
+    /// Whether the operation was accepted by vmm-service
+    pub emitted: bool,
+    pub reason: String,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct DecisionQuery {
+    pub limit: Option<usize>,
+}
+
+/// Evaluates a cluster against its scaling policy.
+///
+/// # Arguments
+///
+/// * `build_id` - The build the cluster belongs to
+/// * `cluster` - The cluster to evaluate
+/// * `cpu_utilization` - Average CPU utilisation of the cluster, if any instance reported metrics
+/// * `now` - The current timestamp (Unix timestamp in seconds)
+///
+/// # Returns
+///
+/// The decision, with the `ScalingOperation` to run if the cluster should be scaled.
+/// The decision is not yet emitted.
+pub fn evaluate_cluster(
+    build_id: &str,
+    cluster: &InstanceCluster,
+    cpu_utilization: Option<u32>,
+    now: i64,
+) -> AutoscaleDecision {
+    let current_instances = cluster.size() as u32;
+    let mut decision = AutoscaleDecision {
+        build_id: build_id.to_string(),
+        evaluated_at: now,
+        current_instances,
+        cpu_utilization,
+        target_cpu_utilization: 0,
+        action: AutoscaleAction::Skipped,
+        operation: None,
+        emitted: false,
+        reason: String::new(),
+    };
+
+    let Some(policy) = cluster.scaling_policy() else {
+        decision.reason = "Cluster has no scaling policy".to_string();
+        return decision;
+    };
+    decision.target_cpu_utilization = policy.target_cpu_utilization();
+
+    if let Err(e) = policy.validate() {
+        decision.reason = format!("Invalid scaling policy: {e}");
+        return decision;
+    }
+
+    let in_progress = cluster.scaling_manager()
+        .and_then(|manager| manager.current_phase())
+        .map_or(false, |phase| !phase.is_terminal());
+    if in_progress {
+        decision.reason = "A scaling operation is already in progress".to_string();
+        return decision;
+    }
+
+    let Some(cpu) = cpu_utilization else {
+        decision.reason = "No instance reported metrics".to_string();
+        return decision;
+    };
+
+    if let Some(target_instances) = cluster.should_scale_out(cpu, now) {
+        decision.action = AutoscaleAction::ScaleOut;
+        decision.operation = Some(ScalingOperation::ScaleOut { target_instances });
+        decision.reason = format!(
+            "CPU utilisation {cpu}% is above the target of {}%",
+            policy.target_cpu_utilization()
+        );
+    } else if let Some(target_instances) = cluster.should_scale_in(cpu, now) {
+        decision.action = AutoscaleAction::ScaleIn;
+        decision.operation = Some(ScalingOperation::ScaleIn { target_instances, instance_ids: None });
+        decision.reason = format!(
+            "CPU utilisation {cpu}% is below the target of {}%",
+            policy.target_cpu_utilization()
+        );
+    } else if policy.should_scale_out(current_instances, cpu) && policy.is_in_scale_out_cooldown(now) {
+        decision.action = AutoscaleAction::Cooldown;
+        decision.reason = format!(
+            "Scale out needed but last scale out was {}s ago (cooldown {}s)",
+            now - policy.last_scale_out_time(),
+            policy.scale_out_cooldown_seconds()
+        );
+    } else if policy.should_scale_in(current_instances, cpu) && policy.is_in_scale_in_cooldown(now) {
+        decision.action = AutoscaleAction::Cooldown;
+        decision.reason = format!(
+            "Scale in needed but last scale in was {}s ago (cooldown {}s)",
+            now - policy.last_scale_in_time(),
+            policy.scale_in_cooldown_seconds()
+        );
+    } else {
+        decision.action = AutoscaleAction::NoChange;
+        decision.reason = format!(
+            "CPU utilisation {cpu}% is within range of the target of {}%",
+            policy.target_cpu_utilization()
+        );
+    }
+
+    decision
+}
+
+/// Runs one autoscaling pass over every cluster with a scaling policy that
+/// this node is responsible for.
+pub async fn autoscale_round(state: Arc<Mutex<DataStore>>) -> Result<Vec<AutoscaleDecision>, Box<dyn std::error::Error + Send + Sync>> {
+    let (clusters, signing_key) = {
+        let datastore = state.lock().await;
+        (responsible_clusters(&datastore), datastore.instance_state.signing_key().to_string())
+    };
+
+    let mut decisions = Vec::new();
+    for (build_id, instances) in clusters {
+        let Some(template) = template_of(&instances) else {
+            continue;
+        };
+
+        let endpoints = instances.iter()
+            .filter_map(|instance| instance.formnet_ip.map(|ip| format!("http://{ip}:63210/get")))
+            .collect();
+        let metrics = fetch_instance_metrics(endpoints).await;
+        let cpu_utilization = if metrics.is_empty() {
+            None
+        } else {
+            let total: i64 = metrics.iter().map(|m| m.cpu.usage_pct().clamp(0, 100)).sum();
+            Some((total / metrics.len() as i64) as u32)
+        };
+
+        let mut decision = evaluate_cluster(&build_id, &template.cluster, cpu_utilization, now());
+        if let Some(operation) = decision.operation.clone() {
+            if template.cluster.autoscaling_enabled() {
+                match emit_operation(&build_id, &signing_key, operation).await {
+                    Ok(()) => decision.emitted = true,
+                    Err(e) => decision.reason = format!("{}; vmm-service rejected the operation: {e}", decision.reason),
+                }
+            } else {
+                decision.reason = format!("{}; autoscaling is not enabled for this cluster", decision.reason);
+            }
+        }
+
+        log::info!(
+            "Autoscaler decision for {build_id}: {:?} ({})",
+            decision.action, decision.reason
+        );
+        if let Err(e) = record_decision(&DB_HANDLE, &decision) {
+            log::error!("Unable to record autoscaler decision for {build_id}: {e}");
+        }
+        decisions.push(decision);
+    }
+
+    Ok(decisions)
+}
+
+/// Groups instances by build and keeps the clusters that have a scaling
+/// policy and whose template instance runs on this node, so each cluster is
+/// evaluated by exactly one node.
+fn responsible_clusters(datastore: &DataStore) -> BTreeMap<String, Vec<Instance>> {
+    let node_id = datastore.instance_state.node_id().to_lowercase();
+    let mut builds: BTreeMap<String, Vec<Instance>> = BTreeMap::new();
+    for ctx in datastore.instance_state.map.iter() {
+        let (_, reg) = ctx.val;
+        if let Some(val) = reg.val() {
+            let instance = val.value();
+            builds.entry(instance.build_id.clone()).or_default().push(instance);
+        }
+    }
+
+    builds.retain(|_, instances| {
+        let Some(template) = template_of(instances) else {
+            return false;
+        };
+        if template.cluster.scaling_policy().is_none() {
+            return false;
+        }
+        template.cluster.responsible_node()
+            .map_or(false, |responsible| responsible.to_lowercase() == node_id)
+    });
+
+    builds
+}
+
+fn template_of(instances: &[Instance]) -> Option<&Instance> {
+    let template_id = instances.first()?.cluster.template_instance_id().cloned();
+    template_id
+        .and_then(|id| instances.iter().find(|instance| instance.instance_id == id))
+        .or(instances.first())
+}
+
+/// Hands the operation to the local vmm-service, signed with the node key
+async fn emit_operation(
+    build_id: &str,
+    signing_key: &str,
+    operation: ScalingOperation,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let path = format!("/cluster/{build_id}/autoscale");
+    let body = serde_json::to_vec(&AutoscaleRequest {
+        build_id: build_id.to_string(),
+        operation,
+    })?;
+    let signature = NodeSignature::sign(signing_key, &path, &body_digest(&body))?;
+
+    let mut request = Client::new()
+        .post(format!("{VMM_URL}{path}"))
+        .header("content-type", "application/json")
+        .body(body);
+    for (name, value) in signature.headers() {
+        request = request.header(name, value);
+    }
+    let resp = request.send().await?
+        .json::<serde_json::Value>().await?;
+
+    match resp.get("Failure") {
+        Some(reason) => Err(reason.as_str().unwrap_or("unknown error").to_string().into()),
+        None => Ok(()),
+    }
+}
+
+/// Decisions sort by time within a cluster; the random suffix keeps
+/// decisions made in the same second from overwriting each other
+fn decision_key(build_id: &str, evaluated_at: i64) -> Vec<u8> {
+    format!("{}/{:020}/{}", build_id, evaluated_at, uuid::Uuid::new_v4()).into_bytes()
+}
+
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    if let Some(last) = end.last_mut() {
+        *last += 1;
+    }
+    end
+}
+
+/// Appends a decision to the history and prunes the cluster's oldest
+/// decisions beyond `MAX_DECISIONS_PER_CLUSTER`.
+pub fn record_decision(db: &Database, decision: &AutoscaleDecision) -> Result<(), Box<dyn std::error::Error>> {
+    let write_txn = db.begin_write()?;
+    {
+        let mut table = write_txn.open_table(DECISIONS_TABLE)?;
+        let bytes = serialize(decision)?;
+        table.insert(&decision_key(&decision.build_id, decision.evaluated_at)[..], &bytes[..])?;
+
+        let prefix = format!("{}/", decision.build_id).into_bytes();
+        let end = prefix_end(&prefix);
+        let keys: Vec<Vec<u8>> = table.range::<&[u8]>(&prefix[..]..&end[..])?
+            .map(|record| record.map(|(key, _)| key.value().to_vec()))
+            .collect::<Result<_, _>>()?;
+        let excess = keys.len().saturating_sub(MAX_DECISIONS_PER_CLUSTER);
+        for key in &keys[..excess] {
+            table.remove(&key[..])?;
+        }
+    }
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Reads the most recent decisions, newest first, for one cluster or for
+/// every cluster this node has evaluated.
+pub fn read_decisions(db: &Database, build_id: Option<&str>, limit: usize) -> Result<Vec<AutoscaleDecision>, Box<dyn std::error::Error>> {
+    let read_txn = db.begin_read()?;
+    let table = match read_txn.open_table(DECISIONS_TABLE) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let mut decisions = Vec::new();
+    match build_id {
+        Some(build_id) => {
+            let prefix = format!("{}/", build_id).into_bytes();
+            let end = prefix_end(&prefix);
+            for record in table.range::<&[u8]>(&prefix[..]..&end[..])?.rev().take(limit) {
+                let (_, value) = record?;
+                decisions.push(deserialize(value.value())?);
+            }
+        }
+        None => {
+            for record in table.iter()? {
+                let (_, value) = record?;
+                decisions.push(deserialize::<AutoscaleDecision>(value.value())?);
+            }
+            decisions.sort_by(|a, b| b.evaluated_at.cmp(&a.evaluated_at));
+            decisions.truncate(limit);
+        }
+    }
+
+    Ok(decisions)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+pub async fn get_cluster_decisions(
+    Path(build_id): Path<String>,
+    Query(query): Query<DecisionQuery>,
+) -> Json<Response<AutoscaleDecision>> {
+    let limit = query.limit.unwrap_or(DEFAULT_DECISION_LIMIT);
+    match read_decisions(&DB_HANDLE, Some(&build_id), limit) {
+        Ok(decisions) => Json(Response::Success(Success::List(decisions))),
+        Err(e) => Json(Response::Failure { reason: Some(format!("Unable to read autoscaler decisions: {e}")) }),
+    }
+}
+
+pub async fn list_decisions(
+    Query(query): Query<DecisionQuery>,
+) -> Json<Response<AutoscaleDecision>> {
+    let limit = query.limit.unwrap_or(DEFAULT_DECISION_LIMIT);
+    match read_decisions(&DB_HANDLE, None, limit) {
+        Ok(decisions) => Json(Response::Success(Success::List(decisions))),
+        Err(e) => Json(Response::Failure { reason: Some(format!("Unable to read autoscaler decisions: {e}")) }),
+    }
+}
+
+/// Lets the owner of a build turn autoscaling of its cluster on or off,
+/// optionally replacing the scaling policy at the same time. Every instance
+/// of the build carries a copy of the cluster, so all of them are updated.
+pub async fn set_autoscaling(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    JwtClaims(claims): JwtClaims,
+    Path(build_id): Path<String>,
+    Json(request): Json<SetAutoscalingRequest>,
+) -> Json<Response<Instance>> {
+    let caller = get_wallet_address(&claims).unwrap_or(&claims.sub).to_lowercase();
+    if let Some(policy) = &request.scaling_policy {
+        if let Err(e) = policy.validate() {
+            return Json(Response::Failure { reason: Some(format!("Invalid scaling policy: {e}")) });
+        }
+    }
+
+    let mut datastore = state.lock().await;
+    let instances = datastore.instance_state.get_instances_by_build_id(build_id.clone());
+    if instances.is_empty() {
+        return Json(Response::Failure { reason: Some(format!("No instances found for build {build_id}")) });
+    }
+    if instances.iter().any(|instance| instance.instance_owner.to_lowercase() != caller) {
+        return Json(Response::Failure { reason: Some(format!("Unauthorized: {caller} is not the owner of build {build_id}")) });
+    }
+
+    let mut updated = Vec::new();
+    for mut instance in instances {
+        if let Some(policy) = &request.scaling_policy {
+            instance.cluster.set_scaling_policy(Some(policy.clone()));
+        }
+        if request.enabled && instance.cluster.scaling_policy().is_none() {
+            return Json(Response::Failure { reason: Some(format!("Cluster of build {build_id} has no scaling policy")) });
+        }
+        instance.cluster.set_autoscaling_enabled(request.enabled);
+        instance.updated_at = now();
+
+        let op = datastore.instance_state.update_instance_local(instance.clone());
+        if let Err(e) = datastore.handle_instance_op(op).await {
+            return Json(Response::Failure { reason: Some(format!("Unable to update instance {}: {e}", instance.instance_id)) });
+        }
+        updated.push(instance);
+    }
+
+    Json(Response::Success(Success::List(updated)))
+}
+
+/// Runs an autoscaling pass immediately instead of waiting for the next tick
+pub async fn evaluate_now(
+    State(state): State<Arc<Mutex<DataStore>>>,
+) -> Json<Response<AutoscaleDecision>> {
+    match autoscale_round(state).await {
+        Ok(decisions) => Json(Response::Success(Success::List(decisions))),
+        Err(e) => Json(Response::Failure { reason: Some(format!("Autoscaling round failed: {e}")) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::instances::{ClusterMember, ScalingPolicy};
+
+    fn cluster(size: usize, policy: ScalingPolicy) -> InstanceCluster {
+        let mut cluster = InstanceCluster::new_with_policy(policy);
+        for i in 0..size {
+            cluster.insert(ClusterMember {
+                node_id: format!("node-{i}"),
+                node_public_ip: "10.0.0.1".parse().unwrap(),
+                node_formnet_ip: "10.0.0.1".parse().unwrap(),
+                instance_id: format!("instance-{i}"),
+                instance_formnet_ip: "10.0.0.2".parse().unwrap(),
+                status: "Started".to_string(),
+                last_heartbeat: 0,
+                heartbeats_skipped: 0,
+            });
+        }
+        cluster
+    }
+
+    #[test]
+    fn test_evaluate_cluster() {
+        let now = 1_000_000;
+        let policy = ScalingPolicy::new(1, 10, 70, 300, 120);
+
+        let decision = evaluate_cluster("build", &cluster(2, policy.clone()), Some(95), now);
+        assert_eq!(decision.action, AutoscaleAction::ScaleOut);
+        assert_eq!(decision.operation, Some(ScalingOperation::ScaleOut { target_instances: 3 }));
+
+        let decision = evaluate_cluster("build", &cluster(4, policy.clone()), Some(20), now);
+        assert_eq!(decision.action, AutoscaleAction::ScaleIn);
+        assert!(matches!(decision.operation, Some(ScalingOperation::ScaleIn { target_instances: 1, .. })));
+
+        let decision = evaluate_cluster("build", &cluster(2, policy.clone()), Some(65), now);
+        assert_eq!(decision.action, AutoscaleAction::NoChange);
+        assert!(decision.operation.is_none());
+
+        let mut cooling = cluster(2, policy.clone());
+        cooling.record_scale_out(now - 60);
+        let decision = evaluate_cluster("build", &cooling, Some(95), now);
+        assert_eq!(decision.action, AutoscaleAction::Cooldown);
+
+        let decision = evaluate_cluster("build", &cluster(2, policy), None, now);
+        assert_eq!(decision.action, AutoscaleAction::Skipped);
+    }
+
+    #[test]
+    fn test_decision_history_is_pruned() {
+        let path = std::env::temp_dir().join(format!("form-state-autoscaler-{}.redb", uuid::Uuid::new_v4()));
+        let db = Database::create(&path).unwrap();
+
+        for i in 0..(MAX_DECISIONS_PER_CLUSTER + 5) {
+            let decision = evaluate_cluster("build", &InstanceCluster::default(), None, i as i64);
+            record_decision(&db, &decision).unwrap();
+        }
+        record_decision(&db, &evaluate_cluster("other", &InstanceCluster::default(), None, 0)).unwrap();
+        record_decision(&db, &evaluate_cluster("other", &InstanceCluster::default(), None, 0)).unwrap();
+        assert_eq!(read_decisions(&db, Some("other"), usize::MAX).unwrap().len(), 2);
+
+        let history = read_decisions(&db, Some("build"), usize::MAX).unwrap();
+        assert_eq!(history.len(), MAX_DECISIONS_PER_CLUSTER);
+        assert_eq!(history[0].evaluated_at, (MAX_DECISIONS_PER_CLUSTER + 4) as i64);
+        assert_eq!(read_decisions(&db, None, 10).unwrap().len(), 10);
+
+        let _ = std::fs::remove_file(path);
+    }
+}
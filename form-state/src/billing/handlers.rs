@@ -17,9 +17,12 @@ use serde_json::json;
 use uuid::Uuid;
 use chrono::{Utc, DateTime};
 
+use form_types::state::{Response as StateResponse, Success};
+
 use crate::datastore::DataStore;
 use crate::auth::{JwtClaims, DynamicClaims};
-use crate::billing::{SubscriptionInfo, SubscriptionStatus, SubscriptionTier, UsageTracker, PeriodUsage};
+use crate::billing::{SubscriptionInfo, SubscriptionQuota, SubscriptionStatus, SubscriptionTier, UsageTracker, PeriodUsage};
+use crate::resize::owner_usage;
 use crate::billing::stripe::{BillingStore, BillingError, BillingTransaction};
 
 /// Response for usage statistics
@@ -140,6 +143,60 @@ pub struct ApiVerifySubscription {
     pub account_id: String,
 }
 
+/// Quota of an account, with the compute its instances currently hold
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AccountQuota {
+    /// Account address
+    pub address: String,
+    
+    /// Subscription tier the quota comes from
+    pub tier: SubscriptionTier,
+    
+    /// Quota of the tier
+    pub quota: SubscriptionQuota,
+    
+    /// vCPUs held by the account's live instances
+    pub vcpus: u32,
+    
+    /// Memory in MiB held by the account's live instances
+    pub memory_mb: u64,
+    
+    /// Available credits
+    pub available_credits: u64,
+    
+    /// Tokens consumed in the current period
+    pub current_period_tokens: u64,
+}
+
+/// Handler for getting an account's quota and current compute usage, so
+/// services can check the quota before they create resources. Accounts
+/// without a subscription, or without an account yet, get the free tier.
+pub async fn get_account_quota(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Path(address): Path<String>,
+) -> Json<StateResponse<AccountQuota>> {
+    let datastore = state.lock().await;
+    let account = datastore.account_state.get_account(&address);
+    let tier = account.as_ref()
+        .and_then(|account| account.subscription.as_ref())
+        .map(|subscription| subscription.tier)
+        .unwrap_or_default();
+    let usage = owner_usage(&datastore, &address, "");
+    
+    Json(StateResponse::Success(Success::Some(AccountQuota {
+        address,
+        tier,
+        quota: tier.quota(),
+        vcpus: usage.vcpus,
+        memory_mb: usage.memory_mb,
+        available_credits: account.as_ref().map(|account| account.available_credits()).unwrap_or_default(),
+        current_period_tokens: account.as_ref()
+            .and_then(|account| account.usage.as_ref())
+            .map(|usage| usage.current_period_tokens())
+            .unwrap_or_default(),
+    })))
+}
+
 /// Handler for getting subscription status
 pub async fn get_subscription_status(
     State(state): State<Arc<Mutex<DataStore>>>,
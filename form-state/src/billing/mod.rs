@@ -42,6 +42,9 @@ impl SubscriptionTier {
                 additional_agent_discount: 0, // No discount on additional agents
                 max_premium_models: 0,        // No premium models allowed
                 premium_agent_access: false,  // No premium agents
+                max_vcpus: 2,
+                max_memory_mb: 4096,
+                max_storage_gb: 20,
             },
             Self::Pro => SubscriptionQuota {
                 max_agents: 3,
@@ -52,6 +55,9 @@ impl SubscriptionTier {
                 additional_agent_discount: 10, // 10% discount on additional agents
                 max_premium_models: 1,        // 1 premium model allowed
                 premium_agent_access: true,   // Premium agents allowed
+                max_vcpus: 8,
+                max_memory_mb: 16384,
+                max_storage_gb: 100,
             },
             Self::ProPlus => SubscriptionQuota {
                 max_agents: 5,
@@ -62,6 +68,9 @@ impl SubscriptionTier {
                 additional_agent_discount: 15, // 15% discount on additional agents
                 max_premium_models: 3,        // 3 premium models allowed
                 premium_agent_access: true,   // Premium agents allowed
+                max_vcpus: 16,
+                max_memory_mb: 32768,
+                max_storage_gb: 250,
             },
             Self::Power => SubscriptionQuota {
                 max_agents: 10,
@@ -72,6 +81,9 @@ impl SubscriptionTier {
                 additional_agent_discount: 20, // 20% discount on additional agents
                 max_premium_models: 10,       // 10 premium models allowed
                 premium_agent_access: true,   // Premium agents allowed
+                max_vcpus: 32,
+                max_memory_mb: 65536,
+                max_storage_gb: 500,
             },
             Self::PowerPlus => SubscriptionQuota {
                 max_agents: 25,
@@ -82,6 +94,9 @@ impl SubscriptionTier {
                 additional_agent_discount: 25, // 25% discount on additional agents
                 max_premium_models: 25,       // 25 premium models allowed (unlimited)
                 premium_agent_access: true,   // Premium agents allowed
+                max_vcpus: 64,
+                max_memory_mb: 131072,
+                max_storage_gb: 1000,
             },
         }
     }
@@ -113,6 +128,15 @@ pub struct SubscriptionQuota {
     
     /// Whether this tier has access to premium agents
     pub premium_agent_access: bool,
+
+    /// Maximum vCPUs across all of the account's instances
+    pub max_vcpus: u32,
+
+    /// Maximum memory in MiB across all of the account's instances
+    pub max_memory_mb: u64,
+
+    /// Maximum size in GB of the disks hot-plugged into the account's instances
+    pub max_storage_gb: u64,
 }
 
 impl Default for SubscriptionTier {
@@ -1,6 +1,6 @@
-use std::{collections::{HashMap, HashSet}, path::PathBuf, sync::Arc};
+use std::{collections::{BTreeMap, BTreeSet, HashMap, HashSet}, path::PathBuf, sync::{Arc, RwLock}};
 use axum::{extract::State, Json};
-use form_dns::{api::{DomainRequest, DomainResponse}, store::FormDnsRecord};
+use form_dns::{api::{DomainRequest, DomainResponse}, dnssec::DnssecZone, store::FormDnsRecord};
 use form_p2p::queue::{QueueRequest, QueueResponse, QUEUE_PORT};
 use rand::{seq::SliceRandom, thread_rng};
 use reqwest::Client;
@@ -10,19 +10,24 @@ use shared::{AssociationContents, Cidr, CidrContents, PeerContents};
 use tiny_keccak::{Hasher, Sha3};
 use tokio::sync::Mutex;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use crdts::{map::Op, BFTReg, CvRDT, Map, CmRDT};
-use crate::{accounts::{Account, AccountOp, AccountState, AuthorizationLevel}, agent::{AIAgent, AgentMap, AgentOp, AgentState}, db::{open_db, write_datastore, DbHandle}, instances::{ClusterMember, Instance, InstanceOp, InstanceState}, model::{AIModel, ModelMap, ModelOp, ModelState}, network::{AssocOp, CidrOp, CrdtAssociation, CrdtCidr, CrdtDnsRecord, CrdtPeer, DnsOp, NetworkState, PeerOp}, nodes::{Node, NodeOp, NodeState}};
+use crdts::{map::{Entry, Op}, BFTReg, CvRDT, Map, CmRDT, ResetRemove, VClock};
+use crate::{accounts::{Account, AccountOp, AccountState, AuthorizationLevel}, agent::{AIAgent, AgentMap, AgentOp, AgentState}, db::{open_db, persist_op, read_datastore, store_entries, DbHandle, ACCOUNT_MAP, AGENT_MAP, ASSOC_MAP, CIDR_MAP, DNSSEC_MAP, DNS_MAP, INSTANCE_MAP, MODEL_MAP, NODE_MAP, PEER_MAP}, instances::{ClusterMember, Instance, InstanceOp, InstanceState}, model::{AIModel, ModelMap, ModelOp, ModelState}, network::{AssocOp, CidrOp, CrdtAssociation, CrdtCidr, CrdtDnsRecord, CrdtDnssecZone, CrdtPeer, DnsOp, DnssecOp, NetworkState, PeerOp}, nodes::{Node, NodeOp, NodeState}};
+use crate::placement::check_new_instance;
+use crate::migration::{plan_migration, InstanceMigration};
+use crate::resize::{plan_resize, InstanceResize};
 use lazy_static::lazy_static;
 use url::Host;
 
 lazy_static! {
     pub static ref DB_HANDLE: DbHandle = open_db(PathBuf::from("/var/lib/formation/db/form.db"));
+    pub static ref ANTI_ENTROPY_STATUS: RwLock<AntiEntropyStatus> = RwLock::new(AntiEntropyStatus::default());
 }
 
 pub type PeerMap = Map<String, BFTReg<CrdtPeer<String>, String>, String>;
 pub type CidrMap = Map<String, BFTReg<CrdtCidr<String>, String>, String>;
 pub type AssocMap = Map<String, BFTReg<CrdtAssociation<String>, String>, String>;
 pub type DnsMap = Map<String, BFTReg<CrdtDnsRecord, String>, String>;
+pub type DnssecMap = Map<String, BFTReg<CrdtDnssecZone, String>, String>;
 pub type InstanceMap = Map<String, BFTReg<Instance, String>, String>;
 pub type NodeMap = Map<String, BFTReg<Node, String>, String>;
 pub type AccountMap = Map<String, BFTReg<Account, String>, String>;
@@ -52,6 +57,8 @@ pub struct MergeableState {
     cidrs: CidrMap,
     assocs: AssocMap,
     dns: DnsMap,
+    #[serde(default)]
+    dnssec: DnssecMap,
     instances: InstanceMap,
     nodes: NodeMap,
     accounts: AccountMap,
@@ -66,6 +73,7 @@ impl From<DataStore> for MergeableState {
             cidrs: value.network_state.cidrs.clone(),
             assocs: value.network_state.associations.clone(),
             dns: value.network_state.dns_state.zones.clone(),
+            dnssec: value.network_state.dns_state.dnssec.clone(),
             instances: value.instance_state.map.clone(),
             nodes: value.node_state.map.clone(),
             accounts: value.account_state.map.clone(),
@@ -75,6 +83,95 @@ impl From<DataStore> for MergeableState {
     }
 }
 
+impl MergeableState {
+    /// The state as served over the unauthenticated bootstrap routes.
+    /// DNSSEC zones hold their private keys, so they are left out and only
+    /// replicated through the node queue.
+    pub fn without_secrets(mut self) -> Self {
+        self.dnssec = Map::new();
+        self
+    }
+}
+
+/// The clock of every map held by a node. Exchanged during anti-entropy so a
+/// peer can work out which entries the sender has not seen yet. DNSSEC zones
+/// are left out, as they hold private keys and `/sync/pull` is public.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq)]
+pub struct StateSummary {
+    pub peers: VClock<String>,
+    pub cidrs: VClock<String>,
+    pub assocs: VClock<String>,
+    pub dns: VClock<String>,
+    pub instances: VClock<String>,
+    pub nodes: VClock<String>,
+    pub accounts: VClock<String>,
+    pub agents: VClock<String>,
+    pub models: VClock<String>,
+}
+
+/// The part of a map that a peer is missing.
+///
+/// `entries` only holds entries whose clock is not covered by the peer's
+/// summary. `keys` lists every live key, which lets the receiver tell
+/// entries that were removed here apart from entries it simply already has.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MapDelta<V> {
+    clock: VClock<String>,
+    entries: BTreeMap<String, Entry<V, String>>,
+    keys: BTreeSet<String>,
+    deferred: HashMap<VClock<String>, BTreeSet<String>>,
+}
+
+/// Response to an anti-entropy pull. Maps the requester is already up to
+/// date on are `None`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateDelta {
+    summary: StateSummary,
+    peers: Option<MapDelta<BFTReg<CrdtPeer<String>, String>>>,
+    cidrs: Option<MapDelta<BFTReg<CrdtCidr<String>, String>>>,
+    assocs: Option<MapDelta<BFTReg<CrdtAssociation<String>, String>>>,
+    dns: Option<MapDelta<BFTReg<CrdtDnsRecord, String>>>,
+    instances: Option<MapDelta<BFTReg<Instance, String>>>,
+    nodes: Option<MapDelta<BFTReg<Node, String>>>,
+    accounts: Option<MapDelta<BFTReg<Account, String>>>,
+    agents: Option<MapDelta<BFTReg<AIAgent, String>>>,
+    models: Option<MapDelta<BFTReg<AIModel, String>>>,
+}
+
+/// How far a single map was from a peer's copy during the last anti-entropy
+/// round, measured in ops (dots) seen by one side and not the other.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct MapDivergence {
+    /// Ops the peer had that this node was missing
+    pub behind: u64,
+    /// Ops this node had that the peer was missing
+    pub ahead: u64,
+    /// Entries added, updated or removed locally to repair the difference
+    pub repaired: usize,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct AntiEntropyStatus {
+    pub rounds: u64,
+    pub last_round_at: Option<i64>,
+    pub last_peer: Option<String>,
+    pub last_error: Option<String>,
+    pub total_repaired: u64,
+    pub maps: BTreeMap<String, MapDivergence>,
+}
+
+impl AntiEntropyStatus {
+    /// Total ops this node was missing from its peer in the last round.
+    pub fn behind(&self) -> u64 {
+        self.maps.values().map(|m| m.behind).sum()
+    }
+
+    /// Total ops the peer was missing from this node in the last round.
+    pub fn ahead(&self) -> u64 {
+        self.maps.values().map(|m| m.ahead).sum()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DataStore {
     pub network_state: NetworkState,
@@ -117,6 +214,13 @@ pub enum DnsRequest {
     Delete(String)
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DnssecRequest {
+    Op(DnssecOp),
+    Update(DnssecZone),
+    Delete(String)
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum InstanceRequest {
     Op(InstanceOp),
@@ -130,7 +234,9 @@ pub enum InstanceRequest {
     RemoveClusterMember {
         build_id: String,
         cluster_member_id: String, 
-    }
+    },
+    Migrate(InstanceMigration),
+    Resize(InstanceResize),
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -224,26 +330,133 @@ impl DataStore {
         } 
     }
 
+    /// Rebuilds the datastore from the local database, replaying any ops
+    /// logged since the last compaction. Falls back to an empty datastore
+    /// if the database can't be read.
+    pub fn from_db(node_id: String, pk: String) -> Self {
+        match read_datastore(&DB_HANDLE, node_id.clone(), pk.clone()) {
+            Ok(datastore) => datastore,
+            Err(e) => {
+                log::warn!("Unable to load datastore from db, starting empty: {e}");
+                Self::new(node_id, pk)
+            }
+        }
+    }
+
     pub fn new_from_state(
         node_id: String,
         pk: String,
         other: MergeableState,
     ) -> Self {
         log::info!("Building new datastore from state...");
-        let mut local = Self::new(node_id, pk); 
-        local.network_state.peers.merge(other.peers);
-        local.network_state.cidrs.merge(other.cidrs);
-        local.network_state.associations.merge(other.assocs);
-        local.network_state.dns_state.zones.merge(other.dns);
-        local.instance_state.map.merge(other.instances);
-        local.node_state.map.merge(other.nodes);
-        local.account_state.map.merge(other.accounts);
-        local.agent_state.map.merge(other.agents);
-        local.model_state.map.merge(other.models);
+        let mut local = Self::from_db(node_id, pk); 
+        local.merge_state(other);
         log::info!("Built new datastore from state... Returning...");
         local
     }
 
+    /// Merges every map of `other` into the local maps.
+    pub fn merge_state(&mut self, other: MergeableState) {
+        let instances = self.instance_state.map.entries.clone();
+        let nodes = self.node_state.map.entries.clone();
+        let dns = self.network_state.dns_state.zones.entries.clone();
+        let accounts = self.account_state.map.entries.clone();
+
+        self.network_state.peers.merge(other.peers);
+        self.network_state.cidrs.merge(other.cidrs);
+        self.network_state.associations.merge(other.assocs);
+        self.network_state.dns_state.zones.merge(other.dns);
+        self.network_state.dns_state.dnssec.merge(other.dnssec);
+        self.instance_state.map.merge(other.instances);
+        self.node_state.map.merge(other.nodes);
+        self.account_state.map.merge(other.accounts);
+        self.agent_state.map.merge(other.agents);
+        self.model_state.map.merge(other.models);
+
+        self.publish_changes(
+            changed_keys(&instances, &self.instance_state.map),
+            changed_keys(&nodes, &self.node_state.map),
+            changed_keys(&dns, &self.network_state.dns_state.zones),
+            changed_keys(&accounts, &self.account_state.map),
+        );
+    }
+
+    /// Publishes watch events for records changed by a merge rather than an
+    /// op, which doesn't go through the `*_op` methods that normally do so
+    fn publish_changes(
+        &self,
+        instances: BTreeSet<String>,
+        nodes: BTreeSet<String>,
+        dns: BTreeSet<String>,
+        accounts: BTreeSet<String>,
+    ) {
+        crate::watch::instances_changed(instances, |key| self.instance_state.get_instance(key.clone()));
+        crate::watch::nodes_changed(nodes, |key| self.node_state.get_node(key.clone()));
+        crate::watch::dns_records_changed(dns, |key| self.network_state.get_dns_record(key));
+        crate::watch::accounts_changed(accounts, |key| self.account_state.get_account(key));
+    }
+
+    pub fn summary(&self) -> StateSummary {
+        StateSummary {
+            peers: self.network_state.peers.clock.clone(),
+            cidrs: self.network_state.cidrs.clock.clone(),
+            assocs: self.network_state.associations.clock.clone(),
+            dns: self.network_state.dns_state.zones.clock.clone(),
+            instances: self.instance_state.map.clock.clone(),
+            nodes: self.node_state.map.clock.clone(),
+            accounts: self.account_state.map.clock.clone(),
+            agents: self.agent_state.map.clock.clone(),
+            models: self.model_state.map.clock.clone(),
+        }
+    }
+
+    /// Builds the delta a peer with the given summary needs to catch up.
+    pub fn delta_since(&self, summary: &StateSummary) -> StateDelta {
+        StateDelta {
+            summary: self.summary(),
+            peers: map_delta(&self.network_state.peers, &summary.peers),
+            cidrs: map_delta(&self.network_state.cidrs, &summary.cidrs),
+            assocs: map_delta(&self.network_state.associations, &summary.assocs),
+            dns: map_delta(&self.network_state.dns_state.zones, &summary.dns),
+            instances: map_delta(&self.instance_state.map, &summary.instances),
+            nodes: map_delta(&self.node_state.map, &summary.nodes),
+            accounts: map_delta(&self.account_state.map, &summary.accounts),
+            agents: map_delta(&self.agent_state.map, &summary.agents),
+            models: map_delta(&self.model_state.map, &summary.models),
+        }
+    }
+
+    /// Merges a delta received from a peer, persists the entries it changed
+    /// and reports how far each map had diverged.
+    pub fn apply_delta(&mut self, delta: StateDelta) -> Result<BTreeMap<String, MapDivergence>, Box<dyn std::error::Error>> {
+        let local = self.summary();
+        let remote = delta.summary;
+        let mut divergence = BTreeMap::new();
+
+        let (peers, _) = sync_map(&mut self.network_state.peers, PEER_MAP, delta.peers, &local.peers, &remote.peers)?;
+        let (cidrs, _) = sync_map(&mut self.network_state.cidrs, CIDR_MAP, delta.cidrs, &local.cidrs, &remote.cidrs)?;
+        let (assocs, _) = sync_map(&mut self.network_state.associations, ASSOC_MAP, delta.assocs, &local.assocs, &remote.assocs)?;
+        let (dns, dns_changed) = sync_map(&mut self.network_state.dns_state.zones, DNS_MAP, delta.dns, &local.dns, &remote.dns)?;
+        let (instances, instances_changed) = sync_map(&mut self.instance_state.map, INSTANCE_MAP, delta.instances, &local.instances, &remote.instances)?;
+        let (nodes, nodes_changed) = sync_map(&mut self.node_state.map, NODE_MAP, delta.nodes, &local.nodes, &remote.nodes)?;
+        let (accounts, accounts_changed) = sync_map(&mut self.account_state.map, ACCOUNT_MAP, delta.accounts, &local.accounts, &remote.accounts)?;
+        let (agents, _) = sync_map(&mut self.agent_state.map, AGENT_MAP, delta.agents, &local.agents, &remote.agents)?;
+        let (models, _) = sync_map(&mut self.model_state.map, MODEL_MAP, delta.models, &local.models, &remote.models)?;
+        self.publish_changes(instances_changed, nodes_changed, dns_changed, accounts_changed);
+
+        divergence.insert(PEER_MAP.to_string(), peers);
+        divergence.insert(CIDR_MAP.to_string(), cidrs);
+        divergence.insert(ASSOC_MAP.to_string(), assocs);
+        divergence.insert(DNS_MAP.to_string(), dns);
+        divergence.insert(INSTANCE_MAP.to_string(), instances);
+        divergence.insert(NODE_MAP.to_string(), nodes);
+        divergence.insert(ACCOUNT_MAP.to_string(), accounts);
+        divergence.insert(AGENT_MAP.to_string(), agents);
+        divergence.insert(MODEL_MAP.to_string(), models);
+
+        Ok(divergence)
+    }
+
     pub fn get_all_users(&self) -> HashMap<String, CrdtPeer<String>> {
         log::info!("Getting all peers from datastore network state...");
         self.network_state.peers.iter().filter_map(|item| {
@@ -338,7 +551,7 @@ impl DataStore {
                 if let (true, _) = self.network_state.peer_op_success(key.clone(), op.clone()) {
                     log::info!("Peer Op succesffully applied...");
                     DataStore::write_to_queue(PeerRequest::Op(peer_op.clone()), 0).await?;
-                    write_datastore(&DB_HANDLE, &self.clone())?;
+                    persist_op(&DB_HANDLE, PEER_MAP, &self.network_state.peers, &peer_op)?;
                 } else {
                     log::info!("Peer Op rejected...");
                     return Err(
@@ -353,6 +566,7 @@ impl DataStore {
             }
             Op::Rm { .. } => {
                 self.network_state.peer_op(peer_op.clone());
+                persist_op(&DB_HANDLE, PEER_MAP, &self.network_state.peers, &peer_op)?;
                 return Ok(());
             }
         }
@@ -399,7 +613,7 @@ impl DataStore {
                 if let (true, _) = self.network_state.cidr_op_success(key.clone(), op.clone()) {
                     log::info!("CIDR Op succesffully applied...");
                     DataStore::write_to_queue(CidrRequest::Op(cidr_op.clone()), 1).await?;
-                    write_datastore(&DB_HANDLE, &self.clone())?;
+                    persist_op(&DB_HANDLE, CIDR_MAP, &self.network_state.cidrs, &cidr_op)?;
                 } else {
                     log::info!("CIDR Op rejected...");
                     return Err(
@@ -414,6 +628,7 @@ impl DataStore {
             }
             Op::Rm { .. } => {
                 self.network_state.cidr_op(cidr_op.clone());
+                persist_op(&DB_HANDLE, CIDR_MAP, &self.network_state.cidrs, &cidr_op)?;
                 return Ok(());
             }
         }
@@ -457,7 +672,7 @@ impl DataStore {
                 if let (true, _) = self.network_state.associations_op_success(key.clone(), op.clone()) {
                     log::info!("Assoc Op succesffully applied...");
                     DataStore::write_to_queue(AssocRequest::Op(assoc_op.clone()), 2).await?;
-                    write_datastore(&DB_HANDLE, &self.clone())?;
+                    persist_op(&DB_HANDLE, ASSOC_MAP, &self.network_state.associations, &assoc_op)?;
                 } else {
                     log::info!("Assoc Op rejected...");
                     return Err(
@@ -472,6 +687,7 @@ impl DataStore {
             }
             Op::Rm { .. } => {
                 self.network_state.associations_op(assoc_op.clone());
+                persist_op(&DB_HANDLE, ASSOC_MAP, &self.network_state.associations, &assoc_op)?;
                 return Ok(());
             }
         }
@@ -510,7 +726,7 @@ impl DataStore {
                 if let (true, _) = self.network_state.dns_op_success(key.clone(), op.clone()) {
                     log::info!("DNS Op succesffully applied...");
                     DataStore::write_to_queue(DnsRequest::Op(dns_op.clone()), 3).await?;
-                    write_datastore(&DB_HANDLE, &self.clone())?;
+                    persist_op(&DB_HANDLE, DNS_MAP, &self.network_state.dns_state.zones, &dns_op)?;
                 } else {
                     log::info!("DNS Op rejected...");
                     return Err(
@@ -525,6 +741,7 @@ impl DataStore {
             }
             Op::Rm { .. } => {
                 self.network_state.dns_op(dns_op.clone());
+                persist_op(&DB_HANDLE, DNS_MAP, &self.network_state.dns_state.zones, &dns_op)?;
                 return Ok(());
             }
         }
@@ -551,6 +768,11 @@ impl DataStore {
             ip_addr, 
             cname_target: create.cname_target.clone(), 
             ssl_cert: create.ssl_cert, 
+            txt: create.txt.clone(),
+            mx: create.mx.clone(),
+            srv: create.srv.clone(),
+            caa: create.caa.clone(),
+            routing: Some(create.routing.clone()),
         };
 
         Client::new()
@@ -600,6 +822,11 @@ impl DataStore {
             ip_addr, 
             cname_target: update.cname_target.clone(), 
             ssl_cert: update.ssl_cert, 
+            txt: update.txt.clone(),
+            mx: update.mx.clone(),
+            srv: update.srv.clone(),
+            caa: update.caa.clone(),
+            routing: Some(update.routing.clone()),
         };
 
         Client::new()
@@ -623,6 +850,58 @@ impl DataStore {
         Ok(())
     }
 
+    pub async fn handle_dnssec_request(&mut self, dnssec_request: DnssecRequest) -> Result<(), Box<dyn std::error::Error>> {
+        match dnssec_request {
+            DnssecRequest::Op(op) => self.handle_dnssec_op(op).await?,
+            DnssecRequest::Update(update) => self.handle_dnssec_update(update).await?,
+            DnssecRequest::Delete(zone) => self.handle_dnssec_delete(zone).await?
+        }
+
+        Ok(())
+    }
+
+    pub async fn handle_dnssec_op(&mut self, dnssec_op: DnssecOp) -> Result<(), Box<dyn std::error::Error>> {
+        match &dnssec_op {
+            Op::Up { dot: _, key, op } => {
+                self.network_state.dnssec_op(dnssec_op.clone());
+                if let (true, _) = self.network_state.dnssec_op_success(key.clone(), op.clone()) {
+                    log::info!("DNSSEC Op succesffully applied...");
+                    persist_op(&DB_HANDLE, DNSSEC_MAP, &self.network_state.dns_state.dnssec, &dnssec_op)?;
+                } else {
+                    log::info!("DNSSEC Op rejected...");
+                    return Err(
+                        Box::new(
+                            std::io::Error::new(
+                                std::io::ErrorKind::Other,
+                                "update was rejected".to_string()
+                            )
+                        )
+                    )
+                }
+            }
+            Op::Rm { .. } => {
+                self.network_state.dnssec_op(dnssec_op.clone());
+                persist_op(&DB_HANDLE, DNSSEC_MAP, &self.network_state.dns_state.dnssec, &dnssec_op)?;
+                return Ok(());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub async fn handle_dnssec_update(&mut self, update: DnssecZone) -> Result<(), Box<dyn std::error::Error>> {
+        let op = self.network_state.update_dnssec_local(update);
+        self.handle_dnssec_op(op.clone()).await?;
+        // Ops made on this node are gossiped, ops from peers are not
+        DataStore::write_to_queue(DnssecRequest::Op(op), 10).await
+    }
+
+    pub async fn handle_dnssec_delete(&mut self, zone: String) -> Result<(), Box<dyn std::error::Error>> {
+        let op = self.network_state.remove_dnssec_local(zone);
+        self.handle_dnssec_op(op.clone()).await?;
+        DataStore::write_to_queue(DnssecRequest::Op(op), 10).await
+    }
+
     pub async fn handle_instance_request(&mut self, instance_request: InstanceRequest) -> Result<(), Box<dyn std::error::Error>> {
         match instance_request {
             InstanceRequest::Op(op) => self.handle_instance_op(op).await?,
@@ -631,6 +910,8 @@ impl DataStore {
             InstanceRequest::Delete(id) => self.handle_instance_delete(id).await?,
             InstanceRequest::AddClusterMember { build_id, cluster_member }  => self.handle_add_cluster_member(build_id, cluster_member).await?,
             InstanceRequest::RemoveClusterMember { build_id, cluster_member_id }  => self.handle_remove_cluster_member(build_id, cluster_member_id).await?,
+            InstanceRequest::Migrate(migration) => { self.handle_instance_migrate(migration).await?; }
+            InstanceRequest::Resize(resize) => { self.handle_instance_resize(resize).await?; }
         }
 
         Ok(())
@@ -652,7 +933,7 @@ impl DataStore {
                     if let (true, _) = self.instance_state.instance_op_success(key.to_string(), op.clone()) {
                         log::info!("Instance Op succesfully applied...");
                         DataStore::write_to_queue(InstanceRequest::Op(instance_op.clone()), 4).await?;
-                        write_datastore(&DB_HANDLE, &self.clone())?;
+                        persist_op(&DB_HANDLE, INSTANCE_MAP, &self.instance_state.map, &instance_op)?;
                     } else {
                         return Err(
                             Box::new(
@@ -666,6 +947,7 @@ impl DataStore {
                 }
                 Op::Rm { .. } => {
                     self.instance_state.instance_op(instance_op.clone());
+                    persist_op(&DB_HANDLE, INSTANCE_MAP, &self.instance_state.map, &instance_op)?;
                     return Ok(());
                 }
             }
@@ -689,7 +971,7 @@ impl DataStore {
                     if let (true, _) = self.instance_state.instance_op_success(key.to_string(), op.clone()) {
                         log::info!("Instance Op succesfully applied...");
                         DataStore::write_to_queue(InstanceRequest::Op(instance_op.clone()), 4).await?;
-                        write_datastore(&DB_HANDLE, &self.clone())?;
+                        persist_op(&DB_HANDLE, INSTANCE_MAP, &self.instance_state.map, &instance_op)?;
                     } else {
                         return Err(
                             Box::new(
@@ -703,6 +985,7 @@ impl DataStore {
                 }
                 Op::Rm { .. } => {
                     self.instance_state.instance_op(instance_op.clone());
+                    persist_op(&DB_HANDLE, INSTANCE_MAP, &self.instance_state.map, &instance_op)?;
                     return Ok(());
                 }
             }
@@ -710,6 +993,58 @@ impl DataStore {
         Ok(())
     }
 
+    /// Records a completed live migration. The whole migration is planned
+    /// before any op is applied, so a migration that cannot be recorded
+    /// leaves the instance, its cluster and its domain as they were.
+    pub async fn handle_instance_migrate(&mut self, migration: InstanceMigration) -> Result<Instance, Box<dyn std::error::Error>> {
+        let plan = plan_migration(self, &migration)
+            .map_err(|reason| format!("Unable to record migration: {reason}"))?;
+
+        let op = self.instance_state.update_instance_local(plan.migrated.clone());
+        self.handle_instance_op(op).await?;
+        for replica in plan.replicas {
+            let op = self.instance_state.update_instance_local(replica);
+            self.handle_instance_op(op).await?;
+        }
+        let op = self.instance_state.remove_instance_local(plan.removed_instance_id);
+        self.handle_instance_op(op).await?;
+
+        if let Some(record) = plan.dns_record {
+            let op = self.network_state.update_dns_local(record.clone());
+            self.handle_dns_op(op).await?;
+
+            let request = DomainRequest::Update {
+                replace: true,
+                record_type: record.record_type,
+                ip_addr: record.formnet_ip.iter().chain(record.public_ip.iter()).cloned().collect(),
+                cname_target: record.cname_target.clone(),
+                ssl_cert: record.ssl_cert,
+                txt: record.txt.clone(),
+                mx: record.mx.clone(),
+                srv: record.srv.clone(),
+                caa: record.caa.clone(),
+                routing: Some(record.routing.clone()),
+            };
+            if let Err(e) = Client::new()
+                .post(format!("http://127.0.0.1:3005/record/{}/update", record.domain))
+                .json(&request)
+                .send().await {
+                log::error!("Unable to update form-dns record for {}: {e}", record.domain);
+            }
+        }
+
+        Ok(plan.migrated)
+    }
+
+    pub async fn handle_instance_resize(&mut self, resize: InstanceResize) -> Result<Instance, Box<dyn std::error::Error>> {
+        let resized = plan_resize(self, &resize)
+            .map_err(|reason| format!("Unable to resize instance: {reason}"))?;
+        let op = self.instance_state.update_instance_local(resized.clone());
+        self.handle_instance_op(op).await?;
+
+        Ok(resized)
+    }
+
     pub async fn handle_instance_op(&mut self, instance_op: InstanceOp) -> Result<(), Box<dyn std::error::Error>> {
         match &instance_op {
             Op::Up { dot: _, key, op } => {
@@ -717,7 +1052,7 @@ impl DataStore {
                 if let (true, _) = self.instance_state.instance_op_success(key.clone(), op.clone()) {
                     log::info!("Instance Op succesffully applied...");
                     DataStore::write_to_queue(InstanceRequest::Op(instance_op.clone()), 4).await?;
-                    write_datastore(&DB_HANDLE, &self.clone())?;
+                    persist_op(&DB_HANDLE, INSTANCE_MAP, &self.instance_state.map, &instance_op)?;
                 } else {
                     log::info!("Instance Op rejected...");
                     return Err(
@@ -732,6 +1067,7 @@ impl DataStore {
             }
             Op::Rm { .. } => {
                 self.instance_state.instance_op(instance_op.clone());
+                persist_op(&DB_HANDLE, INSTANCE_MAP, &self.instance_state.map, &instance_op)?;
                 return Ok(());
             }
         }
@@ -739,6 +1075,8 @@ impl DataStore {
     }
 
     pub async fn handle_instance_create(&mut self, create: Instance) -> Result<(), Box<dyn std::error::Error>> {
+        check_new_instance(self, &create)
+            .map_err(|reason| format!("Placement constraint violated: {reason}"))?;
         let op = self.instance_state.update_instance_local(create);
         self.handle_instance_op(op).await?;
 
@@ -746,6 +1084,8 @@ impl DataStore {
     }
 
     pub async fn handle_instance_update(&mut self, update: Instance) -> Result<(), Box<dyn std::error::Error>> {
+        check_new_instance(self, &update)
+            .map_err(|reason| format!("Placement constraint violated: {reason}"))?;
         let op = self.instance_state.update_instance_local(update);
         self.handle_instance_op(op).await?;
 
@@ -806,7 +1146,7 @@ impl DataStore {
                 if let (true, _) = self.node_state.node_op_success(key.clone(), op.clone()) {
                     log::info!("Peer Op succesffully applied...");
                     DataStore::write_to_queue(NodeRequest::Op(node_op.clone()), 5).await?;
-                    write_datastore(&DB_HANDLE, &self.clone())?;
+                    persist_op(&DB_HANDLE, NODE_MAP, &self.node_state.map, &node_op)?;
                 } else {
                     log::info!("Peer Op rejected...");
                     return Err(
@@ -821,6 +1161,7 @@ impl DataStore {
             }
             Op::Rm { .. } => {
                 self.node_state.node_op(node_op.clone());
+                persist_op(&DB_HANDLE, NODE_MAP, &self.node_state.map, &node_op)?;
                 return Ok(());
             }
         }
@@ -902,7 +1243,7 @@ impl DataStore {
                 if let (true, _) = self.account_state.account_op_success(key.clone(), op.clone()) {
                     log::info!("Account Op succesffully applied...");
                     DataStore::write_to_queue(AccountRequest::Op(account_op.clone()), 7).await?;
-                    write_datastore(&DB_HANDLE, &self.clone())?;
+                    persist_op(&DB_HANDLE, ACCOUNT_MAP, &self.account_state.map, &account_op)?;
                 } else {
                     log::info!("Account Op rejected...");
                     return Err(
@@ -917,6 +1258,7 @@ impl DataStore {
             }
             Op::Rm { .. } => {
                 self.account_state.account_op(account_op.clone());
+                persist_op(&DB_HANDLE, ACCOUNT_MAP, &self.account_state.map, &account_op)?;
                 return Ok(());
             }
         }
@@ -945,8 +1287,9 @@ impl DataStore {
         
         // Delete the account (remove it from the map)
         let op = self.account_state.remove_account_local(delete);
-        // Apply the operation directly
-        self.account_state.map.apply(op.clone());
+        // Apply through account_op so watchers see the deletion
+        self.account_state.account_op(op.clone());
+        persist_op(&DB_HANDLE, ACCOUNT_MAP, &self.account_state.map, &op)?;
         
         // Write to queue
         if let Err(e) = DataStore::write_to_queue(AccountRequest::Op(op), 7).await {
@@ -1094,6 +1437,7 @@ impl DataStore {
         let op = self.agent_state.remove_agent_local(delete);
         // Apply the operation directly
         self.agent_state.map.apply(op.clone());
+        persist_op(&DB_HANDLE, AGENT_MAP, &self.agent_state.map, &op)?;
         
         // Write to queue
         if let Err(e) = DataStore::write_to_queue(AgentRequest::Op(op), 8).await {
@@ -1104,7 +1448,8 @@ impl DataStore {
     }
 
     pub async fn handle_agent_op(&mut self, agent_op: AgentOp) -> Result<(), Box<dyn std::error::Error>> {
-        self.agent_state.map.apply(agent_op);
+        self.agent_state.map.apply(agent_op.clone());
+        persist_op(&DB_HANDLE, AGENT_MAP, &self.agent_state.map, &agent_op)?;
         Ok(())
     }
 
@@ -1151,6 +1496,7 @@ impl DataStore {
         let op = self.model_state.remove_model_local(delete);
         // Apply the operation directly
         self.model_state.map.apply(op.clone());
+        persist_op(&DB_HANDLE, MODEL_MAP, &self.model_state.map, &op)?;
         
         // Write to queue
         if let Err(e) = DataStore::write_to_queue(ModelRequest::Op(op), 9).await {
@@ -1161,7 +1507,8 @@ impl DataStore {
     }
 
     pub async fn handle_model_op(&mut self, agent_op: ModelOp) -> Result<(), Box<dyn std::error::Error>> {
-        self.model_state.map.apply(agent_op);
+        self.model_state.map.apply(agent_op.clone());
+        persist_op(&DB_HANDLE, MODEL_MAP, &self.model_state.map, &agent_op)?;
         Ok(())
     }
 
@@ -1341,6 +1688,7 @@ pub async fn complete_bootstrap(State(state): State<Arc<Mutex<DataStore>>>) {
                         guard.network_state.cidrs.merge(mergeable_state.cidrs);
                         guard.network_state.associations.merge(mergeable_state.assocs);
                         guard.network_state.dns_state.zones.merge(mergeable_state.dns);
+                        guard.network_state.dns_state.dnssec.merge(mergeable_state.dnssec);
                         guard.instance_state.map.merge(mergeable_state.instances);
                         guard.node_state.map.merge(mergeable_state.nodes);
                         drop(guard);
@@ -1419,6 +1767,11 @@ pub async fn process_message(message: Vec<u8>, state: Arc<Mutex<DataStore>>) ->
             let model_request: ModelRequest = serde_json::from_slice(payload)?;
             guard.handle_model_request(model_request).await?;
         }
+        10 => {
+            log::info!("Pulled dnssec request from queue, processing...");
+            let dnssec_request: DnssecRequest = serde_json::from_slice(payload)?;
+            guard.handle_dnssec_request(dnssec_request).await?;
+        }
         _ => unreachable!()
     }
 
@@ -1427,6 +1780,168 @@ pub async fn process_message(message: Vec<u8>, state: Arc<Mutex<DataStore>>) ->
     Ok(())
 }
 
+/// Number of ops (dots) in `other` that `clock` has not seen.
+fn missing_ops(clock: &VClock<String>, other: &VClock<String>) -> u64 {
+    other.iter()
+        .map(|dot| dot.counter.saturating_sub(clock.get(dot.actor)))
+        .sum()
+}
+
+fn map_delta<V>(map: &Map<String, V, String>, since: &VClock<String>) -> Option<MapDelta<V>>
+where
+    V: CmRDT + ResetRemove<String> + Clone + Default,
+{
+    if map.clock <= *since {
+        return None;
+    }
+
+    let entries = map.entries.iter()
+        .filter(|(_, entry)| !(entry.clock <= *since))
+        .map(|(k, entry)| (k.clone(), entry.clone()))
+        .collect();
+
+    Some(MapDelta {
+        clock: map.clock.clone(),
+        entries,
+        keys: map.entries.keys().cloned().collect(),
+        deferred: map.deferred.clone(),
+    })
+}
+
+/// Merges a `MapDelta` into `map` and returns the keys whose entries changed.
+///
+/// The delta is expanded back into a full map before merging: every key the
+/// peer holds but did not send is one whose entry we have already seen, so
+/// our own entry stands in for it. A plain CvRDT merge then handles both new
+/// entries and removals the same way a full state merge would.
+fn apply_map_delta<V>(map: &mut Map<String, V, String>, delta: MapDelta<V>) -> BTreeSet<String>
+where
+    V: CmRDT + ResetRemove<String> + Clone + Default,
+    Map<String, V, String>: CvRDT,
+{
+    let before = map.entries.clone();
+    let mut entries = delta.entries;
+    for key in delta.keys {
+        if entries.contains_key(&key) {
+            continue;
+        }
+        if let Some(entry) = map.entries.get(&key) {
+            entries.insert(key, entry.clone());
+        }
+    }
+
+    map.merge(Map {
+        clock: delta.clock,
+        entries,
+        deferred: delta.deferred,
+    });
+
+    changed_keys(&before, map)
+}
+
+/// Keys whose entries differ between `before` and the current `map`
+fn changed_keys<V>(before: &BTreeMap<String, Entry<V, String>>, map: &Map<String, V, String>) -> BTreeSet<String>
+where
+    V: CmRDT + ResetRemove<String> + Clone + Default,
+{
+    let mut changed: BTreeSet<String> = before.iter()
+        .filter(|(k, entry)| match map.entries.get(*k) {
+            Some(current) => current.clock != entry.clock,
+            None => true,
+        })
+        .map(|(k, _)| k.clone())
+        .collect();
+    changed.extend(map.entries.keys().filter(|k| !before.contains_key(*k)).cloned());
+    changed
+}
+
+fn sync_map<V>(
+    map: &mut Map<String, V, String>,
+    map_name: &str,
+    delta: Option<MapDelta<V>>,
+    local: &VClock<String>,
+    remote: &VClock<String>,
+) -> Result<(MapDivergence, BTreeSet<String>), Box<dyn std::error::Error>>
+where
+    V: Serialize + CmRDT + ResetRemove<String> + Clone + Default,
+    Map<String, V, String>: CvRDT,
+{
+    let mut divergence = MapDivergence {
+        behind: missing_ops(local, remote),
+        ahead: missing_ops(remote, local),
+        repaired: 0,
+    };
+
+    let mut changed = BTreeSet::new();
+    if let Some(delta) = delta {
+        changed = apply_map_delta(map, delta);
+        if !changed.is_empty() {
+            log::info!("Anti-entropy repaired {} entries in {map_name}", changed.len());
+            store_entries(&DB_HANDLE, map_name, map, &changed)?;
+        }
+        divergence.repaired = changed.len();
+    }
+
+    Ok((divergence, changed))
+}
+
+/// Serves an anti-entropy pull: returns everything the caller is missing
+/// according to the summary it sent.
+pub async fn sync_pull(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Json(summary): Json<StateSummary>,
+) -> Json<StateDelta> {
+    let datastore = state.lock().await;
+    Json(datastore.delta_since(&summary))
+}
+
+/// Runs one anti-entropy round against a random active admin peer: sends our
+/// summary, merges the entries we are missing and records the divergence.
+pub async fn anti_entropy_round(state: Arc<Mutex<DataStore>>) -> Result<(), Box<dyn std::error::Error>> {
+    let (summary, target) = {
+        let mut guard = state.lock().await;
+        let node_id = guard.node_state.node_id.clone();
+        let peers: Vec<(String, CrdtPeer<String>)> = guard.get_all_active_admin()
+            .into_iter()
+            .filter(|(id, _)| *id != node_id)
+            .collect();
+        let target = peers.choose(&mut thread_rng()).cloned();
+        (guard.summary(), target)
+    };
+
+    let Some((id, peer)) = target else {
+        log::debug!("No peers available for anti-entropy");
+        return Ok(());
+    };
+
+    let result: Result<BTreeMap<String, MapDivergence>, Box<dyn std::error::Error>> = async {
+        let delta = Client::new()
+            .post(format!("http://{}:3004/sync/pull", peer.ip()))
+            .json(&summary)
+            .send().await?
+            .json::<StateDelta>().await?;
+        let mut guard = state.lock().await;
+        guard.apply_delta(delta)
+    }.await;
+
+    let mut status = ANTI_ENTROPY_STATUS.write().unwrap_or_else(|e| e.into_inner());
+    status.rounds += 1;
+    status.last_round_at = Some(chrono::Utc::now().timestamp());
+    status.last_peer = Some(id.clone());
+    match result {
+        Ok(divergence) => {
+            status.total_repaired += divergence.values().map(|d| d.repaired as u64).sum::<u64>();
+            status.maps = divergence;
+            status.last_error = None;
+            Ok(())
+        }
+        Err(e) => {
+            status.last_error = Some(e.to_string());
+            Err(e)
+        }
+    }
+}
+
 pub async fn pong() -> Json<Value> {
     log::info!("Received Ping Request, sending Pong...");
     Json(serde_json::json!({"ping":"pong"}))
@@ -1437,7 +1952,7 @@ pub async fn full_state(
 ) -> Json<MergeableState> {
     log::info!("Received full state request, returning...");
     let datastore = state.lock().await.clone();
-    Json(datastore.into())
+    Json(MergeableState::from(datastore).without_secrets())
 }
 
 pub async fn request_full_state(to_dial: &str) -> Result<MergeableState, Box<dyn std::error::Error>> {
@@ -1462,6 +1977,7 @@ mod tests {
     use std::collections::BTreeMap;
     use std::str::FromStr;
     use trust_dns_proto::rr::RecordType;
+    use form_dns::routing::RoutingPolicy;
 
     // This test builds a MergableState (your datastore state without private keys or node ids)
     // with one entry in each of the maps and then serializes and deserializes it.
@@ -1540,6 +2056,11 @@ mod tests {
             cname_target: None,
             ttl: 300,
             ssl_cert: false,
+            txt: vec![],
+            mx: vec![],
+            srv: vec![],
+            caa: vec![],
+            routing: RoutingPolicy::default(),
         };
         let dns_ctx = dns.read_ctx().derive_add_ctx(actor.clone());
         let dns_op = dns.update("example.com".to_string(), dns_ctx, |reg, _| {
@@ -1573,6 +2094,8 @@ mod tests {
                 template_instance_id: None,
                 session_affinity_enabled: false,
                 scaling_manager: None,
+                autoscaling_enabled: false,
+                deploy: None,
             },
             formfile: "".to_string(),
             snapshots: None,
@@ -1597,6 +2120,7 @@ mod tests {
                     metrics_endpoint: "http://localhost".to_string(),
                 },
             },
+            disks: vec![],
         };
         let inst_ctx = instances.read_ctx().derive_add_ctx(actor.clone());
         let inst_op = instances.update("instance1".to_string(), inst_ctx, |reg, _| {
@@ -1644,6 +2168,7 @@ mod tests {
             cidrs,
             assocs,
             dns,
+            dnssec: Map::new(),
             instances,
             nodes,
             accounts: Map::new(),
@@ -1671,4 +2196,81 @@ mod tests {
 
         Ok(())
     }
+
+    fn add_cidr(map: &mut CidrMap, actor: &str, sk: &SigningKey, id: &str, net: &str) {
+        let cidr = CrdtCidr {
+            id: id.to_string(),
+            name: id.to_string(),
+            cidr: IpNet::from_str(net).unwrap(),
+            parent: None,
+        };
+        let ctx = map.read_ctx().derive_add_ctx(actor.to_string());
+        let op = map.update(id.to_string(), ctx, |reg, _| {
+            reg.update(cidr, actor.to_string(), sk.clone()).expect("Unable to sign update, Panicking")
+        });
+        map.apply(op);
+    }
+
+    // DNSSEC zones hold their private keys, which the unauthenticated
+    // bootstrap and anti-entropy routes must never serve
+    #[tokio::test]
+    async fn test_public_state_has_no_dnssec_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let mut datastore = DataStore::new("node1".to_string(), hex::encode([1u8; 32]));
+        let zone = DnssecZone::new("example.com", form_dns::dnssec::Denial::Nsec, vec![], 1_000)?;
+        let keys: Vec<String> = zone.keys.iter().map(|key| key.pkcs8.clone()).collect();
+        let op = datastore.network_state.update_dnssec_local(zone);
+        datastore.network_state.dnssec_op(op);
+        let state = Arc::new(Mutex::new(datastore));
+
+        let Json(full) = full_state(State(state.clone())).await;
+        let Json(delta) = sync_pull(State(state.clone()), Json(StateSummary::default())).await;
+        for served in [serde_json::to_string(&full)?, serde_json::to_string(&delta)?] {
+            assert!(keys.iter().all(|key| !served.contains(key.as_str())));
+        }
+
+        // Snapshots, only served to nodes, still carry the keys
+        let snapshot = serde_json::to_string(&MergeableState::from(state.lock().await.clone()))?;
+        assert!(keys.iter().all(|key| snapshot.contains(key.as_str())));
+        Ok(())
+    }
+
+    // Two replicas start in sync, then diverge on both sides. Pulling from
+    // the other replica must bring in its additions and removals while
+    // keeping local-only changes, and only ship entries the puller lacks.
+    #[test]
+    fn test_anti_entropy_delta_converges() {
+        let sk = SigningKey::random(&mut thread_rng());
+        let mut a: CidrMap = Map::new();
+        add_cidr(&mut a, "a", &sk, "cidr1", "10.0.0.0/24");
+        add_cidr(&mut a, "a", &sk, "cidr2", "10.0.1.0/24");
+        let mut b = a.clone();
+
+        assert!(map_delta(&a, &b.clock).is_none());
+
+        let rm_ctx = a.read_ctx().derive_rm_ctx();
+        let op = a.rm("cidr1".to_string(), rm_ctx);
+        a.apply(op);
+        add_cidr(&mut a, "a", &sk, "cidr3", "10.0.2.0/24");
+        add_cidr(&mut b, "b", &sk, "cidr4", "10.0.3.0/24");
+
+        assert_eq!(missing_ops(&b.clock, &a.clock), 2);
+        assert_eq!(missing_ops(&a.clock, &b.clock), 1);
+
+        let delta = map_delta(&a, &b.clock).expect("a is ahead of b");
+        assert_eq!(delta.entries.keys().collect::<Vec<_>>(), vec!["cidr3"]);
+
+        let changed = apply_map_delta(&mut b, delta);
+        assert_eq!(changed, BTreeSet::from(["cidr1".to_string(), "cidr3".to_string()]));
+        assert_eq!(
+            b.entries.keys().cloned().collect::<Vec<_>>(),
+            vec!["cidr2".to_string(), "cidr3".to_string(), "cidr4".to_string()]
+        );
+
+        // Pulling the other way leaves both replicas identical
+        let delta = map_delta(&b, &a.clock).expect("b is ahead of a");
+        apply_map_delta(&mut a, delta);
+        assert_eq!(a.clock, b.clock);
+        assert_eq!(a.entries.keys().collect::<Vec<_>>(), b.entries.keys().collect::<Vec<_>>());
+        assert!(map_delta(&a, &b.clock).is_none());
+    }
 }
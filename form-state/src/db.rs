@@ -6,11 +6,15 @@ use serde::{Serialize, de::DeserializeOwned};
 use std::collections::{BTreeMap, HashMap, BTreeSet};
 use std::hash::Hash;
 use std::str::FromStr;
-use crdts::map::{Map, Entry};
+use crdts::map::{Map, Entry, Op};
 use crdts::VClock;
-use crdts::{CmRDT, ResetRemove};
+use crdts::{BFTReg, CmRDT, ResetRemove};
 
 use crate::datastore::DataStore;
+use crate::instances::Instance;
+use crate::legacy::{LegacyDnsRecord, LegacyInstance};
+use crate::network::CrdtDnsRecord;
+use crate::Actor;
 
 /// Database handle wrapped in Arc for sharing across threads.
 pub type DbHandle = Arc<Database>;
@@ -18,6 +22,42 @@ pub type DbHandle = Arc<Database>;
 // Define our table for storing entries
 const ENTRIES_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("entries");
 
+// Write-ahead log of map ops that have not yet been folded into `entries`.
+// Keys are `{map_name}/{seq:020}` so a prefix range scan returns ops in order.
+const OPLOG_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("oplog");
+
+/// Number of logged ops for a single map after which the log is folded
+/// back into the entries table.
+pub const COMPACTION_THRESHOLD: u64 = 256;
+
+pub const PEER_MAP: &str = "network_state/peers";
+pub const CIDR_MAP: &str = "network_state/cidrs";
+pub const ASSOC_MAP: &str = "network_state/assocs";
+pub const DNS_MAP: &str = "network_state/dns";
+pub const DNSSEC_MAP: &str = "network_state/dnssec";
+pub const INSTANCE_MAP: &str = "instance_state/instances";
+pub const NODE_MAP: &str = "node_state/nodes";
+pub const ACCOUNT_MAP: &str = "account_state/accounts";
+pub const AGENT_MAP: &str = "agent_state/agents";
+pub const MODEL_MAP: &str = "model_state/models";
+
+/// Layout the entries and logged ops of a map are written in. It is bumped
+/// whenever fields are added to the map's value type, with the previous
+/// layout kept in `crate::legacy` so `read_datastore` can migrate maps
+/// written before the change.
+pub fn layout_version(map_name: &str) -> u32 {
+    match map_name {
+        INSTANCE_MAP | DNS_MAP => 1,
+        _ => 0,
+    }
+}
+
+/// Maps stored by a fresh database, which start out in the current layout
+const MAP_NAMES: [&str; 10] = [
+    PEER_MAP, CIDR_MAP, ASSOC_MAP, DNS_MAP, DNSSEC_MAP,
+    INSTANCE_MAP, NODE_MAP, ACCOUNT_MAP, AGENT_MAP, MODEL_MAP,
+];
+
 /// Opens a redb database at the specified path.
 /// Creates the database if it doesn't exist.
 pub fn open_db(path: PathBuf) -> DbHandle {
@@ -27,12 +67,20 @@ pub fn open_db(path: PathBuf) -> DbHandle {
         }
     }
     
+    let fresh = !path.exists();
     let db = Database::create(&path).expect("Failed to open redb database");
     
     // Create the tables if they don't exist
     let write_txn = db.begin_write().expect("Failed to begin write transaction");
     {
-        let _ = write_txn.open_table(ENTRIES_TABLE).expect("Failed to open entries table");
+        let mut table = write_txn.open_table(ENTRIES_TABLE).expect("Failed to open entries table");
+        let _ = write_txn.open_table(OPLOG_TABLE).expect("Failed to open oplog table");
+        if fresh {
+            for map_name in MAP_NAMES {
+                write_u64(&mut table, &layout_key(map_name), layout_version(map_name) as u64)
+                    .expect("Failed to record map layout");
+            }
+        }
     }
     write_txn.commit().expect("Failed to commit transaction");
     
@@ -40,6 +88,10 @@ pub fn open_db(path: PathBuf) -> DbHandle {
 }
 
 /// Stores a Map<K, V, A> to redb.
+///
+/// This is a full rewrite of the map: entries and deferred ops that are no
+/// longer present are pruned and the map's op log is truncated, since
+/// everything it held is now reflected in the entries table.
 pub fn store_map<K, V, A>(db: &Database, map_name: &str, map: &Map<K, V, A>) -> Result<(), Box<dyn std::error::Error>>
 where
     K: Serialize + Ord + ToString,
@@ -49,6 +101,8 @@ where
     let write_txn = db.begin_write()?;
     {
         let mut table = write_txn.open_table(ENTRIES_TABLE)?;
+        remove_prefix(&mut table, format!("{}/entries/", map_name).as_bytes())?;
+        remove_prefix(&mut table, format!("{}/deferred/", map_name).as_bytes())?;
 
         // Store the clock
         let clock_key = format!("{}/clock", map_name).into_bytes();
@@ -69,6 +123,12 @@ where
             let deferred_bytes = serialize(&(cloned_clock, keys))?;
             table.insert(&deferred_key[..], &deferred_bytes[..])?;
         }
+
+        let mut oplog = write_txn.open_table(OPLOG_TABLE)?;
+        remove_prefix(&mut oplog, format!("{}/", map_name).as_bytes())?;
+        let next_seq = read_u64(&table, &oplog_seq_key(map_name))?;
+        write_u64(&mut table, &checkpoint_key(map_name), next_seq)?;
+        write_u64(&mut table, &layout_key(map_name), layout_version(map_name) as u64)?;
     }
     
     // Write all changes atomically
@@ -76,6 +136,219 @@ where
     Ok(())
 }
 
+/// Persists a single op applied to `map`.
+///
+/// The op is appended to the write-ahead log, which is a small constant-size
+/// write regardless of how large the map is. Once `COMPACTION_THRESHOLD` ops
+/// have accumulated for the map they are folded into the entries table with
+/// `compact_map`.
+///
+/// `map` must already have `op` applied to it.
+pub fn persist_op<K, V, A>(db: &Database, map_name: &str, map: &Map<K, V, A>, op: &Op<K, V, A>) -> Result<(), Box<dyn std::error::Error>>
+where
+    K: Serialize + DeserializeOwned + Ord + ToString + Clone,
+    V: Serialize + CmRDT + ResetRemove<A> + Clone + Default,
+    A: Serialize + Ord + Hash + Clone,
+    Op<K, V, A>: Serialize + DeserializeOwned,
+{
+    let pending = append_op(db, map_name, op)?;
+    if pending >= COMPACTION_THRESHOLD {
+        log::info!("Compacting {map_name} after {pending} logged ops...");
+        compact_map(db, map_name, map)?;
+    }
+
+    Ok(())
+}
+
+/// Appends an op to the write-ahead log for `map_name` and returns the number
+/// of ops logged since the last compaction.
+pub fn append_op<K, V, A>(db: &Database, map_name: &str, op: &Op<K, V, A>) -> Result<u64, Box<dyn std::error::Error>>
+where
+    K: Ord,
+    V: CmRDT + ResetRemove<A> + Clone + Default,
+    A: Ord + Hash,
+    Op<K, V, A>: Serialize,
+{
+    let write_txn = db.begin_write()?;
+    let pending = {
+        let mut table = write_txn.open_table(ENTRIES_TABLE)?;
+        let mut oplog = write_txn.open_table(OPLOG_TABLE)?;
+
+        let seq = read_u64(&table, &oplog_seq_key(map_name))?;
+        let checkpoint = read_u64(&table, &checkpoint_key(map_name))?;
+
+        let op_bytes = serialize(op)?;
+        oplog.insert(&oplog_key(map_name, seq)[..], &op_bytes[..])?;
+        write_u64(&mut table, &oplog_seq_key(map_name), seq + 1)?;
+
+        (seq + 1).saturating_sub(checkpoint)
+    };
+    write_txn.commit()?;
+
+    Ok(pending)
+}
+
+/// Folds the op log for `map_name` into the entries table.
+///
+/// Only the entries touched by logged ops are rewritten, together with the
+/// clock and the deferred ops, so the cost is proportional to the number of
+/// changes rather than the size of the map.
+pub fn compact_map<K, V, A>(db: &Database, map_name: &str, map: &Map<K, V, A>) -> Result<(), Box<dyn std::error::Error>>
+where
+    K: Serialize + DeserializeOwned + Ord + ToString + Clone,
+    V: Serialize + CmRDT + ResetRemove<A> + Clone + Default,
+    A: Serialize + Ord + Hash + Clone,
+    Op<K, V, A>: DeserializeOwned,
+{
+    let write_txn = db.begin_write()?;
+    {
+        let mut table = write_txn.open_table(ENTRIES_TABLE)?;
+        let mut oplog = write_txn.open_table(OPLOG_TABLE)?;
+
+        let prefix = format!("{}/", map_name).into_bytes();
+        let end = prefix_end(&prefix);
+        let mut touched = BTreeSet::new();
+        let mut logged = Vec::new();
+        for record in oplog.range::<&[u8]>(&prefix[..]..&end[..])? {
+            let (log_key, value) = record?;
+            let op: Op<K, V, A> = deserialize(value.value())?;
+            match op {
+                Op::Up { key, .. } => {
+                    touched.insert(key);
+                }
+                Op::Rm { keyset, .. } => {
+                    touched.extend(keyset);
+                }
+            }
+            logged.push(log_key.value().to_vec());
+        }
+
+        if logged.is_empty() {
+            return Ok(());
+        }
+
+        write_entries(&mut table, map_name, map, &touched)?;
+
+        for key in logged {
+            oplog.remove(&key[..])?;
+        }
+
+        let next_seq = read_u64(&table, &oplog_seq_key(map_name))?;
+        write_u64(&mut table, &checkpoint_key(map_name), next_seq)?;
+    }
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+/// Writes the clock, the deferred ops and only the entries named in `keys`.
+/// Keys that are no longer in the map are removed from the entries table.
+///
+/// Used for state that arrives without an op, e.g. entries merged in by
+/// anti-entropy, where the set of changed keys is known up front.
+pub fn store_entries<K, V, A>(db: &Database, map_name: &str, map: &Map<K, V, A>, keys: &BTreeSet<K>) -> Result<(), Box<dyn std::error::Error>>
+where
+    K: Serialize + Ord + ToString,
+    V: Serialize + CmRDT + ResetRemove<A> + Clone + Default,
+    A: Serialize + Ord + Hash + Clone,
+{
+    let write_txn = db.begin_write()?;
+    {
+        let mut table = write_txn.open_table(ENTRIES_TABLE)?;
+        write_entries(&mut table, map_name, map, keys)?;
+    }
+    write_txn.commit()?;
+
+    Ok(())
+}
+
+fn write_entries<K, V, A>(
+    table: &mut redb::Table<'_, '_, &'static [u8], &'static [u8]>,
+    map_name: &str,
+    map: &Map<K, V, A>,
+    keys: &BTreeSet<K>,
+) -> Result<(), Box<dyn std::error::Error>>
+where
+    K: Serialize + Ord + ToString,
+    V: Serialize + CmRDT + ResetRemove<A> + Clone + Default,
+    A: Serialize + Ord + Hash + Clone,
+{
+    let clock_key = format!("{}/clock", map_name).into_bytes();
+    let clock_bytes = serialize(&map.clock)?;
+    table.insert(&clock_key[..], &clock_bytes[..])?;
+
+    for k in keys.iter() {
+        let entry_key = format!("{}/entries/{}", map_name, k.to_string()).into_bytes();
+        match map.entries.get(k) {
+            Some(entry) => {
+                let entry_bytes = serialize(entry)?;
+                table.insert(&entry_key[..], &entry_bytes[..])?;
+            }
+            None => {
+                table.remove(&entry_key[..])?;
+            }
+        }
+    }
+
+    remove_prefix(table, format!("{}/deferred/", map_name).as_bytes())?;
+    for (idx, (vclock, keys)) in map.deferred.iter().enumerate() {
+        let deferred_key = format!("{}/deferred/{}", map_name, idx).into_bytes();
+        let cloned_clock = (*vclock).clone();
+        let deferred_bytes = serialize(&(cloned_clock, keys))?;
+        table.insert(&deferred_key[..], &deferred_bytes[..])?;
+    }
+
+    Ok(())
+}
+
+/// Returns the ops logged for `map_name` since the last compaction, oldest first.
+pub fn read_oplog<K, V, A>(db: &Database, map_name: &str) -> Result<Vec<Op<K, V, A>>, Box<dyn std::error::Error>>
+where
+    K: Ord,
+    V: CmRDT + ResetRemove<A> + Clone + Default,
+    A: Ord + Hash,
+    Op<K, V, A>: DeserializeOwned,
+{
+    let read_txn = db.begin_read()?;
+    let oplog = read_txn.open_table(OPLOG_TABLE)?;
+
+    let prefix = format!("{}/", map_name).into_bytes();
+    let end = prefix_end(&prefix);
+    let mut ops = Vec::new();
+    for record in oplog.range::<&[u8]>(&prefix[..]..&end[..])? {
+        let (_, value) = record?;
+        ops.push(deserialize(value.value())?);
+    }
+
+    Ok(ops)
+}
+
+/// Loads a Map<K, V, A> from redb and replays any ops in its write-ahead log
+/// that were not yet compacted into the entries table.
+///
+/// Replaying is idempotent: ops whose dots are already covered by the loaded
+/// clock are ignored by the map.
+pub fn load_map_with_oplog<K, V, A>(db: &Database, map_name: &str) -> Result<Map<K, V, A>, Box<dyn std::error::Error>>
+where
+    K: DeserializeOwned + Ord + FromStr,
+    <K as FromStr>::Err: std::fmt::Debug + std::error::Error + 'static,
+    V: DeserializeOwned + CmRDT + ResetRemove<A> + Clone + Default,
+    A: DeserializeOwned + Ord + Hash,
+    Map<K, V, A>: CmRDT<Op = Op<K, V, A>>,
+    Op<K, V, A>: DeserializeOwned,
+{
+    let mut map = load_map(db, map_name)?;
+    let ops = read_oplog::<K, V, A>(db, map_name)?;
+    if !ops.is_empty() {
+        log::info!("Replaying {} logged ops for {map_name}...", ops.len());
+    }
+    for op in ops {
+        map.apply(op);
+    }
+
+    Ok(map)
+}
+
 /// Loads a Map<K, V, A> from redb.
 pub fn load_map<K, V, A>(db: &Database, map_name: &str) -> Result<Map<K, V, A>, Box<dyn std::error::Error>>
 where
@@ -158,13 +431,431 @@ fn starts_with(bytes: &[u8], prefix: &[u8]) -> bool {
     bytes.len() >= prefix.len() && &bytes[..prefix.len()] == prefix
 }
 
+/// Returns the smallest key that sorts after every key starting with `prefix`.
+/// All prefixes used here end in `/`, so incrementing the last byte is enough.
+fn prefix_end(prefix: &[u8]) -> Vec<u8> {
+    let mut end = prefix.to_vec();
+    if let Some(last) = end.last_mut() {
+        *last += 1;
+    }
+    end
+}
+
+fn remove_prefix(table: &mut redb::Table<'_, '_, &'static [u8], &'static [u8]>, prefix: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+    let end = prefix_end(prefix);
+    let mut keys = Vec::new();
+    for record in table.range::<&[u8]>(prefix..&end[..])? {
+        let (key, _) = record?;
+        keys.push(key.value().to_vec());
+    }
+    for key in keys {
+        table.remove(&key[..])?;
+    }
+
+    Ok(())
+}
+
+fn oplog_key(map_name: &str, seq: u64) -> Vec<u8> {
+    format!("{}/{:020}", map_name, seq).into_bytes()
+}
+
+fn oplog_seq_key(map_name: &str) -> Vec<u8> {
+    format!("{}/oplog_seq", map_name).into_bytes()
+}
+
+fn checkpoint_key(map_name: &str) -> Vec<u8> {
+    format!("{}/checkpoint", map_name).into_bytes()
+}
+
+fn layout_key(map_name: &str) -> Vec<u8> {
+    format!("{}/layout", map_name).into_bytes()
+}
+
+/// Layout the map was last written in. Maps of databases created before
+/// layouts were recorded are in layout 0.
+pub fn read_layout(db: &Database, map_name: &str) -> Result<u32, Box<dyn std::error::Error>> {
+    let read_txn = db.begin_read()?;
+    let table = read_txn.open_table(ENTRIES_TABLE)?;
+    Ok(read_u64(&table, &layout_key(map_name))? as u32)
+}
+
+/// Rewrites a map written in an older layout in the current one.
+///
+/// `L` is the value type of the map in the layout it was written in. Its
+/// entries and logged ops are decoded as `L` and every entry is carried over
+/// to `V` through JSON, which is self-describing, so fields added since get
+/// their serde defaults. The clock and deferred ops don't depend on the value
+/// type and are kept as they are. The migrated map is stored in full, which
+/// also truncates the op log and records the current layout.
+pub fn migrate_map<K, L, V, A>(db: &Database, map_name: &str) -> Result<(), Box<dyn std::error::Error>>
+where
+    K: Serialize + DeserializeOwned + Ord + FromStr + ToString + Clone,
+    <K as FromStr>::Err: std::fmt::Debug + std::error::Error + 'static,
+    L: Serialize + DeserializeOwned + CmRDT + ResetRemove<A> + Clone + Default,
+    V: Serialize + DeserializeOwned + CmRDT + ResetRemove<A> + Clone + Default,
+    A: Serialize + DeserializeOwned + Ord + Hash + Clone,
+    Map<K, L, A>: CmRDT<Op = Op<K, L, A>>,
+    Op<K, L, A>: DeserializeOwned,
+{
+    let from = read_layout(db, map_name)?;
+    let to = layout_version(map_name);
+    if from >= to {
+        return Ok(());
+    }
+
+    log::info!("Migrating {map_name} from layout {from} to layout {to}...");
+    let legacy: Map<K, L, A> = load_map_with_oplog(db, map_name)?;
+    let mut entries = BTreeMap::new();
+    for (key, entry) in legacy.entries {
+        let entry: Entry<V, A> = serde_json::from_value(serde_json::to_value(&entry)?)?;
+        entries.insert(key, entry);
+    }
+    let map = Map {
+        clock: legacy.clock,
+        entries,
+        deferred: legacy.deferred,
+    };
+    store_map(db, map_name, &map)?;
+    log::info!("Migrated {} entries of {map_name}", map.entries.len());
+
+    Ok(())
+}
+
+fn read_u64(table: &impl ReadableTable<&'static [u8], &'static [u8]>, key: &[u8]) -> Result<u64, Box<dyn std::error::Error>> {
+    match table.get(key)? {
+        Some(bytes) => Ok(deserialize(bytes.value())?),
+        None => Ok(0),
+    }
+}
+
+fn write_u64(table: &mut redb::Table<'_, '_, &'static [u8], &'static [u8]>, key: &[u8], value: u64) -> Result<(), Box<dyn std::error::Error>> {
+    let bytes = serialize(&value)?;
+    table.insert(key, &bytes[..])?;
+    Ok(())
+}
+
+/// Rewrites every map of the datastore in full. Prefer `persist_op` for
+/// individual changes; this is intended for seeding a fresh database.
 pub fn write_datastore(db: &Database, datastore: &DataStore) -> Result<(), Box<dyn std::error::Error>> {
-    store_map(db, "network_state/peers", &datastore.network_state.peers)?;
-    store_map(db, "network_state/cidrs", &datastore.network_state.cidrs)?;
-    store_map(db, "network_state/assocs", &datastore.network_state.associations)?;
-    store_map(db, "network_state/dns", &datastore.network_state.dns_state.zones)?;
-    store_map(db, "instance_state/instances", &datastore.instance_state.map)?;
-    store_map(db, "node_state/nodes", &datastore.node_state.map)?;
+    store_map(db, PEER_MAP, &datastore.network_state.peers)?;
+    store_map(db, CIDR_MAP, &datastore.network_state.cidrs)?;
+    store_map(db, ASSOC_MAP, &datastore.network_state.associations)?;
+    store_map(db, DNS_MAP, &datastore.network_state.dns_state.zones)?;
+    store_map(db, DNSSEC_MAP, &datastore.network_state.dns_state.dnssec)?;
+    store_map(db, INSTANCE_MAP, &datastore.instance_state.map)?;
+    store_map(db, NODE_MAP, &datastore.node_state.map)?;
+    store_map(db, ACCOUNT_MAP, &datastore.account_state.map)?;
+    store_map(db, AGENT_MAP, &datastore.agent_state.map)?;
+    store_map(db, MODEL_MAP, &datastore.model_state.map)?;
+
+    Ok(())
+}
+
+/// Folds the pending op log of every map into the entries table.
+pub fn compact_datastore(db: &Database, datastore: &DataStore) -> Result<(), Box<dyn std::error::Error>> {
+    compact_map(db, PEER_MAP, &datastore.network_state.peers)?;
+    compact_map(db, CIDR_MAP, &datastore.network_state.cidrs)?;
+    compact_map(db, ASSOC_MAP, &datastore.network_state.associations)?;
+    compact_map(db, DNS_MAP, &datastore.network_state.dns_state.zones)?;
+    compact_map(db, DNSSEC_MAP, &datastore.network_state.dns_state.dnssec)?;
+    compact_map(db, INSTANCE_MAP, &datastore.instance_state.map)?;
+    compact_map(db, NODE_MAP, &datastore.node_state.map)?;
+    compact_map(db, ACCOUNT_MAP, &datastore.account_state.map)?;
+    compact_map(db, AGENT_MAP, &datastore.agent_state.map)?;
+    compact_map(db, MODEL_MAP, &datastore.model_state.map)?;
 
     Ok(())
 }
+
+/// Rebuilds a datastore from the materialized maps and their op logs.
+///
+/// Maps that were never persisted (e.g. on a database written before
+/// accounts, agents and models were stored) load as empty maps.
+pub fn read_datastore(db: &Database, node_id: String, pk: String) -> Result<DataStore, Box<dyn std::error::Error>> {
+    migrate_map::<String, BFTReg<LegacyInstance, Actor>, BFTReg<Instance, Actor>, Actor>(db, INSTANCE_MAP)?;
+    migrate_map::<String, BFTReg<LegacyDnsRecord, Actor>, BFTReg<CrdtDnsRecord, Actor>, Actor>(db, DNS_MAP)?;
+
+    let mut datastore = DataStore::new(node_id, pk);
+    datastore.network_state.peers = load_map_with_oplog(db, PEER_MAP)?;
+    datastore.network_state.cidrs = load_map_with_oplog(db, CIDR_MAP)?;
+    datastore.network_state.associations = load_map_with_oplog(db, ASSOC_MAP)?;
+    datastore.network_state.dns_state.zones = load_map_with_oplog(db, DNS_MAP)?;
+    datastore.network_state.dns_state.dnssec = load_map_with_oplog(db, DNSSEC_MAP)?;
+    datastore.instance_state.map = load_map_with_oplog(db, INSTANCE_MAP)?;
+    datastore.node_state.map = load_map_with_oplog(db, NODE_MAP)?;
+    datastore.account_state.map = load_map_with_oplog(db, ACCOUNT_MAP)?;
+    datastore.agent_state.map = load_map_with_oplog(db, AGENT_MAP)?;
+    datastore.model_state.map = load_map_with_oplog(db, MODEL_MAP)?;
+
+    Ok(datastore)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datastore::CidrMap;
+    use crate::network::CrdtCidr;
+    use ipnet::IpNet;
+    use k256::ecdsa::SigningKey;
+    use rand::thread_rng;
+
+    fn temp_db() -> (PathBuf, DbHandle) {
+        let path = std::env::temp_dir().join(format!("form-state-db-{}.redb", uuid::Uuid::new_v4()));
+        let db = open_db(path.clone());
+        (path, db)
+    }
+
+    fn cidr(id: &str, net: &str) -> CrdtCidr<String> {
+        CrdtCidr {
+            id: id.to_string(),
+            name: id.to_string(),
+            cidr: net.parse::<IpNet>().unwrap(),
+            parent: None,
+        }
+    }
+
+    fn add(map: &mut CidrMap, actor: &str, sk: &SigningKey, value: CrdtCidr<String>) -> Op<String, crdts::BFTReg<CrdtCidr<String>, String>, String> {
+        let ctx = map.read_ctx().derive_add_ctx(actor.to_string());
+        let op = map.update(value.id.clone(), ctx, |reg, _| {
+            reg.update(value.clone(), actor.to_string(), sk.clone()).expect("Unable to sign update")
+        });
+        map.apply(op.clone());
+        op
+    }
+
+    #[test]
+    fn test_oplog_replay_and_compaction() -> Result<(), Box<dyn std::error::Error>> {
+        let (path, db) = temp_db();
+        let actor = "test_actor";
+        let sk = SigningKey::random(&mut thread_rng());
+        let mut map: CidrMap = Map::new();
+
+        let op = add(&mut map, actor, &sk, cidr("cidr1", "10.0.0.0/24"));
+        persist_op(&db, CIDR_MAP, &map, &op)?;
+        let op = add(&mut map, actor, &sk, cidr("cidr2", "10.0.1.0/24"));
+        persist_op(&db, CIDR_MAP, &map, &op)?;
+        let rm_ctx = map.read_ctx().derive_rm_ctx();
+        let op = map.rm("cidr1".to_string(), rm_ctx);
+        map.apply(op.clone());
+        persist_op(&db, CIDR_MAP, &map, &op)?;
+
+        // Nothing has been compacted yet, so the plain layout is still empty
+        // and the state only exists in the op log.
+        let materialized: CidrMap = load_map(&db, CIDR_MAP)?;
+        assert!(materialized.entries.is_empty());
+        assert_eq!(read_oplog::<String, crdts::BFTReg<CrdtCidr<String>, String>, String>(&db, CIDR_MAP)?.len(), 3);
+
+        let replayed: CidrMap = load_map_with_oplog(&db, CIDR_MAP)?;
+        assert_eq!(replayed.entries.len(), 1);
+        assert!(replayed.entries.contains_key("cidr2"));
+        assert_eq!(replayed.clock, map.clock);
+
+        compact_map(&db, CIDR_MAP, &map)?;
+        assert!(read_oplog::<String, crdts::BFTReg<CrdtCidr<String>, String>, String>(&db, CIDR_MAP)?.is_empty());
+
+        // After compaction the existing `load_map` layout holds the full state.
+        let materialized: CidrMap = load_map(&db, CIDR_MAP)?;
+        assert_eq!(materialized.entries.len(), 1);
+        assert!(materialized.entries.contains_key("cidr2"));
+        assert_eq!(materialized.clock, map.clock);
+
+        drop(db);
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    /// Marks a map as written before layouts were recorded
+    fn reset_layout(db: &Database, map_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let write_txn = db.begin_write()?;
+        {
+            let mut table = write_txn.open_table(ENTRIES_TABLE)?;
+            table.remove(&layout_key(map_name)[..])?;
+        }
+        write_txn.commit()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrates_instances_from_layout_0() -> Result<(), Box<dyn std::error::Error>> {
+        let (path, db) = temp_db();
+        let actor = "test_actor";
+        let sk = SigningKey::random(&mut thread_rng());
+        let mut map: Map<String, BFTReg<LegacyInstance, String>, String> = Map::new();
+
+        // One instance compacted into the entries table, one only in the op log
+        for instance_id in ["instance1", "instance2"] {
+            let instance = LegacyInstance {
+                instance_id: instance_id.to_string(),
+                build_id: "build1".to_string(),
+                ..Default::default()
+            };
+            let ctx = map.read_ctx().derive_add_ctx(actor.to_string());
+            let op = map.update(instance_id.to_string(), ctx, |reg, _| {
+                reg.update(instance.clone(), actor.to_string(), sk.clone()).expect("Unable to sign update")
+            });
+            map.apply(op.clone());
+            if instance_id == "instance1" {
+                store_map(&db, INSTANCE_MAP, &map)?;
+            } else {
+                persist_op(&db, INSTANCE_MAP, &map, &op)?;
+            }
+        }
+        reset_layout(&db, INSTANCE_MAP)?;
+        assert_eq!(read_layout(&db, INSTANCE_MAP)?, 0);
+
+        let datastore = read_datastore(&db, "node1".to_string(), hex::encode([1u8; 32]))?;
+        assert_eq!(read_layout(&db, INSTANCE_MAP)?, layout_version(INSTANCE_MAP));
+        for instance_id in ["instance1", "instance2"] {
+            let instance = datastore.instance_state.get_instance(instance_id.to_string())
+                .expect("Instance lost in migration");
+            assert_eq!(instance.build_id, "build1");
+            assert!(instance.cluster.scaling_manager.is_none());
+
+            // Unchanged instances hash the way they were signed
+            let legacy = instance.legacy().expect("No fields added since layout 0 are used");
+            assert_eq!(legacy.instance_id, instance_id);
+        }
+        assert_eq!(datastore.instance_state.map.clock, map.clock);
+
+        // Once migrated, the map loads in the current layout
+        let reloaded = read_datastore(&db, "node1".to_string(), hex::encode([1u8; 32]))?;
+        assert_eq!(reloaded.instance_state.map.entries.len(), 2);
+
+        drop(db);
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    fn sha3(value: &impl crdts::merkle_reg::Sha3Hash) -> [u8; 32] {
+        use tiny_keccak::Hasher;
+        let mut hasher = tiny_keccak::Sha3::v256();
+        value.hash(&mut hasher);
+        let mut hash = [0u8; 32];
+        hasher.finalize(&mut hash);
+        hash
+    }
+
+    #[test]
+    fn test_migrates_dns_records_from_layout_0() -> Result<(), Box<dyn std::error::Error>> {
+        let (path, db) = temp_db();
+        let actor = "test_actor";
+        let sk = SigningKey::random(&mut thread_rng());
+        let mut map: Map<String, BFTReg<LegacyDnsRecord, String>, String> = Map::new();
+
+        // One record compacted into the entries table, one only in the op log
+        let mut records = Vec::new();
+        for domain in ["app.example.com", "www.example.com"] {
+            let record = LegacyDnsRecord {
+                domain: domain.to_string(),
+                record_type: trust_dns_proto::rr::RecordType::A,
+                formnet_ip: vec![],
+                public_ip: vec!["203.0.113.10:80".parse()?],
+                cname_target: None,
+                ttl: 300,
+                ssl_cert: true,
+            };
+            let ctx = map.read_ctx().derive_add_ctx(actor.to_string());
+            let op = map.update(domain.to_string(), ctx, |reg, _| {
+                reg.update(record.clone(), actor.to_string(), sk.clone()).expect("Unable to sign update")
+            });
+            map.apply(op.clone());
+            if domain == "app.example.com" {
+                store_map(&db, DNS_MAP, &map)?;
+            } else {
+                persist_op(&db, DNS_MAP, &map, &op)?;
+            }
+            records.push(record);
+        }
+        reset_layout(&db, DNS_MAP)?;
+        assert_eq!(read_layout(&db, DNS_MAP)?, 0);
+
+        let datastore = read_datastore(&db, "node1".to_string(), hex::encode([1u8; 32]))?;
+        assert_eq!(read_layout(&db, DNS_MAP)?, layout_version(DNS_MAP));
+        for record in records {
+            let migrated = datastore.network_state.dns_state.zones.get(&record.domain).val
+                .and_then(|reg| reg.val().map(|v| v.value()))
+                .expect("Record lost in migration");
+            assert_eq!(migrated.public_ip(), record.public_ip);
+            assert_eq!(migrated.ttl(), 300);
+            assert!(migrated.txt().is_empty());
+
+            // Unchanged records hash the way they were signed
+            assert_eq!(migrated.legacy(), Some(record.clone()));
+            assert_eq!(sha3(&migrated), sha3(&record));
+        }
+        assert_eq!(datastore.network_state.dns_state.zones.clock, map.clock);
+
+        // Once migrated, the map loads in the current layout
+        let reloaded = read_datastore(&db, "node1".to_string(), hex::encode([1u8; 32]))?;
+        assert_eq!(reloaded.network_state.dns_state.zones.entries.len(), 2);
+
+        drop(db);
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_migrated_dns_records_take_default_routing() -> Result<(), Box<dyn std::error::Error>> {
+        let (path, db) = temp_db();
+        let actor = "test_actor";
+        let sk = SigningKey::random(&mut thread_rng());
+        let mut map: Map<String, BFTReg<LegacyDnsRecord, String>, String> = Map::new();
+        let record = LegacyDnsRecord {
+            domain: "app.example.com".to_string(),
+            record_type: trust_dns_proto::rr::RecordType::A,
+            formnet_ip: vec![],
+            public_ip: vec!["203.0.113.10:80".parse()?, "203.0.113.11:80".parse()?],
+            cname_target: None,
+            ttl: 300,
+            ssl_cert: false,
+        };
+        let ctx = map.read_ctx().derive_add_ctx(actor.to_string());
+        let op = map.update(record.domain.clone(), ctx, |reg, _| {
+            reg.update(record.clone(), actor.to_string(), sk.clone()).expect("Unable to sign update")
+        });
+        map.apply(op.clone());
+        persist_op(&db, DNS_MAP, &map, &op)?;
+        reset_layout(&db, DNS_MAP)?;
+
+        let datastore = read_datastore(&db, "node1".to_string(), hex::encode([1u8; 32]))?;
+        let mut migrated = datastore.network_state.dns_state.zones.get(&record.domain).val
+            .and_then(|reg| reg.val().map(|v| v.value()))
+            .expect("Record lost in migration");
+        assert!(migrated.routing().is_default());
+        assert_eq!(sha3(&migrated), sha3(&record));
+
+        // A routing policy takes the record out of the legacy layout
+        migrated.routing = form_dns::routing::RoutingPolicy::Latency;
+        assert!(migrated.legacy().is_none());
+        assert_ne!(sha3(&migrated), sha3(&record));
+
+        drop(db);
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+
+    #[test]
+    fn test_store_map_prunes_removed_entries() -> Result<(), Box<dyn std::error::Error>> {
+        let (path, db) = temp_db();
+        let actor = "test_actor";
+        let sk = SigningKey::random(&mut thread_rng());
+        let mut map: CidrMap = Map::new();
+
+        add(&mut map, actor, &sk, cidr("cidr1", "10.0.0.0/24"));
+        add(&mut map, actor, &sk, cidr("cidr2", "10.0.1.0/24"));
+        store_map(&db, CIDR_MAP, &map)?;
+
+        let rm_ctx = map.read_ctx().derive_rm_ctx();
+        let op = map.rm("cidr1".to_string(), rm_ctx);
+        map.apply(op);
+        store_map(&db, CIDR_MAP, &map)?;
+
+        let loaded: CidrMap = load_map(&db, CIDR_MAP)?;
+        assert_eq!(loaded.entries.len(), 1);
+        assert!(loaded.entries.contains_key("cidr2"));
+
+        drop(db);
+        let _ = std::fs::remove_file(path);
+        Ok(())
+    }
+}
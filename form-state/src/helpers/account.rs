@@ -1,14 +1,15 @@
-use crate::datastore::{DataStore, DB_HANDLE, AccountRequest};
-use crate::db::write_datastore;
+use crate::datastore::{DataStore, AccountRequest};
 use crate::accounts::*;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use axum::{extract::{State, Path}, Json};
+use axum::{extract::{State, Path, Query}, response::IntoResponse, Json};
+use crate::pagination::ListQuery;
 use form_types::state::{Response, Success};
 
 pub async fn list_accounts(
     State(state): State<Arc<Mutex<DataStore>>>,
-) -> Json<Response<Account>> {
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
     log::info!("Requesting a list of all accounts...");
     let mut accounts = Vec::new();
     
@@ -22,7 +23,7 @@ pub async fn list_accounts(
     }
     
     log::info!("Retrieved a list of all accounts... Returning...");
-    Json(Response::Success(Success::List(accounts)))
+    query.respond(accounts)
 }
 
 pub async fn get_account(
@@ -68,9 +69,6 @@ pub async fn create_account(
             match &op {
                 crdts::map::Op::Up { key, .. } => {
                     if let Some(account) = datastore.account_state.get_account(key) {
-                        // Write to persistent storage
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
-                        
                         // Add to message queue
                         if let Err(e) = DataStore::write_to_queue(AccountRequest::Op(op), 7).await {
                             log::error!("Error writing to queue: {}", e);
@@ -129,9 +127,6 @@ pub async fn update_account(
             match &op {
                 crdts::map::Op::Up { key, .. } => {
                     if let Some(account) = datastore.account_state.get_account(key) {
-                        // Write to persistent storage
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
-                        
                         // Add to message queue
                         if let Err(e) = DataStore::write_to_queue(AccountRequest::Op(op), 7).await {
                             log::error!("Error writing to queue: {}", e);
@@ -189,9 +184,6 @@ pub async fn delete_account(
                 });
             }
             
-            // Write to persistent storage
-            let _ = write_datastore(&DB_HANDLE, &datastore.clone());
-            
             return Json(Response::Success(Success::Some(account_copy)));
         },
         _ => {
@@ -254,9 +246,6 @@ pub async fn transfer_instance_ownership(
                 
                 // Get the updated account
                 if let Some(account) = datastore.account_state.get_account(&to_address) {
-                    // Write to persistent storage
-                    let _ = write_datastore(&DB_HANDLE, &datastore.clone());
-                    
                     // Add transfer operation to message queue
                     let transfer_request = AccountRequest::TransferOwnership {
                         from_address: from_address.clone(),
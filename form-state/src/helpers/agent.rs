@@ -1,11 +1,11 @@
-use crate::datastore::{AccountRequest, AgentRequest, DataStore, DB_HANDLE};
-use crate::db::write_datastore;
+use crate::datastore::{AccountRequest, AgentRequest, DataStore};
 use crate::agent::*;
 use crate::auth::JwtClaims;
 use crate::api_keys::ApiKeyAuth;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use axum::{extract::{State, Path}, Json};
+use axum::{extract::{State, Path, Query}, Json};
+use crate::pagination::ListQuery;
 use form_types::state::{Response, Success};
 use axum::http::StatusCode;
 use serde_json::json;
@@ -42,9 +42,6 @@ pub async fn create_agent(
             match &op {
                 crdts::map::Op::Up { key, .. } => {
                     if let Some(agent) = datastore.agent_state.get_agent(key) {
-                        // Write to persistent storage
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
-                        
                         // Add to message queue
                         if let Err(e) = DataStore::write_to_queue(AgentRequest::Op(op), 8).await {
                             log::error!("Error writing to queue: {}", e);
@@ -85,8 +82,43 @@ pub async fn get_agent(
 ) {}
 
 pub async fn list_agent(
-    State(datatore): State<Arc<Mutex<DataStore>>>
-) {}
+    State(state): State<Arc<Mutex<DataStore>>>,
+    auth: ApiKeyAuth,
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
+    log::info!("Account {} is requesting list of all agents", auth.account.address);
+
+    // Check operation permission
+    if !auth.api_key.can_perform_operation("agents.list") {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(json!({
+                "success": false,
+                "error": "API key does not have permission to list agents"
+            }))
+        );
+    }
+
+    let all_agents: Vec<AIAgent> = state.lock().await.agent_state.list_agents().into_values().collect();
+    match query.apply(all_agents) {
+        Ok(page) => (
+            StatusCode::OK,
+            Json(json!({
+                "success": true,
+                "agents": page.items,
+                "next_cursor": page.next_cursor,
+                "total": page.total
+            }))
+        ),
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "success": false,
+                "error": e
+            }))
+        ),
+    }
+}
 
 /// Handler for hiring an agent
 pub async fn agent_hire(
@@ -0,0 +1,152 @@
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::{extract::{Path, State}, Json};
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+use form_dns::dnssec::{Denial, DnssecZone, DsExport};
+use form_types::state::{Response, Success};
+use crate::datastore::{DataStore, DnssecRequest, DB_HANDLE};
+use crate::db::{persist_op, DNSSEC_MAP};
+use crate::network::DnssecOp;
+
+/// Request to sign a zone Formation is authoritative for
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SignZoneRequest {
+    pub zone: String,
+    #[serde(default)]
+    pub denial: Denial,
+    /// Name servers to publish in the zone's NS set
+    #[serde(default)]
+    pub nameservers: Vec<String>,
+}
+
+/// Generates the keys of a zone and starts signing it. The response holds
+/// the DS records to hand to the registrar.
+pub async fn sign_zone(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Json(request): Json<SignZoneRequest>,
+) -> Json<Response<DsExport>> {
+    let mut datastore = state.lock().await;
+    let zone = request.zone.trim_end_matches('.').to_lowercase();
+    if datastore.network_state.dns_state.dnssec.get(&zone).val.map_or(false, |reg| reg.val().is_some()) {
+        return Json(Response::Failure { reason: Some(format!("{zone} is already signed")) });
+    }
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let dnssec_zone = match DnssecZone::new(&zone, request.denial, request.nameservers, now) {
+        Ok(dnssec_zone) => dnssec_zone,
+        Err(e) => return Json(Response::Failure { reason: Some(e) }),
+    };
+    let served = datastore.network_state.dns_state.zones.iter()
+        .any(|ctx| dnssec_zone.contains(ctx.val.0));
+    if !served {
+        return Json(Response::Failure { reason: Some(format!("No records of {zone} are served by Formation")) });
+    }
+
+    let export = match dnssec_zone.export() {
+        Ok(export) => export,
+        Err(e) => return Json(Response::Failure { reason: Some(e) }),
+    };
+    let map_op = datastore.network_state.update_dnssec_local(dnssec_zone);
+    match apply_dnssec_op(&mut datastore, map_op.clone()) {
+        Response::Success(_) => {
+            broadcast_dnssec_op(map_op).await;
+            Json(Response::Success(Success::Some(export)))
+        }
+        Response::Failure { reason } => Json(Response::Failure { reason }),
+    }
+}
+
+pub async fn update_dnssec(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Json(request): Json<DnssecRequest>,
+) -> Json<Response<DnssecZone>> {
+    let mut datastore = state.lock().await;
+    let map_op = match request {
+        DnssecRequest::Op(map_op @ crdts::map::Op::Up { .. }) => {
+            log::info!("Update DNSSEC request is an Op from another peer");
+            map_op
+        }
+        DnssecRequest::Update(zone) => {
+            log::info!("Update DNSSEC request was a direct request...");
+            let map_op = datastore.network_state.update_dnssec_local(zone);
+            let response = apply_dnssec_op(&mut datastore, map_op.clone());
+            if let Response::Success(_) = response {
+                broadcast_dnssec_op(map_op).await;
+            }
+            return Json(response);
+        }
+        _ => return Json(Response::Failure { reason: Some("Invalid request for update dnssec".into()) }),
+    };
+    Json(apply_dnssec_op(&mut datastore, map_op))
+}
+
+pub async fn delete_dnssec(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Path(zone): Path<String>,
+    Json(request): Json<DnssecRequest>,
+) -> Json<Response<DnssecZone>> {
+    let mut datastore = state.lock().await;
+    let map_op = match request {
+        DnssecRequest::Op(map_op @ crdts::map::Op::Rm { .. }) => map_op,
+        DnssecRequest::Delete(_) => {
+            let map_op = datastore.network_state.remove_dnssec_local(zone.trim_end_matches('.').to_lowercase());
+            let response = apply_dnssec_op(&mut datastore, map_op.clone());
+            broadcast_dnssec_op(map_op).await;
+            return Json(response);
+        }
+        _ => return Json(Response::Failure { reason: Some("Invalid request for delete dnssec".into()) }),
+    };
+    Json(apply_dnssec_op(&mut datastore, map_op))
+}
+
+/// Lists signed zones with their keys, for form-dns to sign with
+pub async fn list_dnssec_zones(
+    State(state): State<Arc<Mutex<DataStore>>>,
+) -> Json<Response<DnssecZone>> {
+    let datastore = state.lock().await;
+    let zones = datastore.network_state.dns_state.dnssec.iter().filter_map(|ctx| {
+        let (_zone, reg) = ctx.val;
+        reg.val().map(|v| v.value().into())
+    }).collect::<Vec<DnssecZone>>();
+
+    Json(Response::Success(Success::List(zones)))
+}
+
+/// The DS records of a signed zone, for the registrar
+pub async fn get_ds(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Path(zone): Path<String>,
+) -> Json<Response<DsExport>> {
+    let datastore = state.lock().await;
+    let zone = zone.trim_end_matches('.').to_lowercase();
+    let Some(dnssec_zone) = datastore.network_state.dns_state.dnssec.get(&zone).val
+        .and_then(|reg| reg.val().map(|v| DnssecZone::from(v.value()))) else {
+        return Json(Response::Failure { reason: Some(format!("{zone} is not signed")) });
+    };
+
+    match dnssec_zone.export() {
+        Ok(export) => Json(Response::Success(Success::Some(export))),
+        Err(e) => Json(Response::Failure { reason: Some(e) }),
+    }
+}
+
+/// Gossips an op made on this node to the other nodes. Ops received from
+/// peers are not gossiped again.
+async fn broadcast_dnssec_op(map_op: DnssecOp) {
+    if let Err(e) = DataStore::write_to_queue(DnssecRequest::Op(map_op), 10).await {
+        log::error!("Error writing DNSSEC op to queue: {}", e);
+    }
+}
+
+fn apply_dnssec_op(datastore: &mut DataStore, map_op: DnssecOp) -> Response<DnssecZone> {
+    datastore.network_state.dnssec_op(map_op.clone());
+    let _ = persist_op(&DB_HANDLE, DNSSEC_MAP, &datastore.network_state.dns_state.dnssec, &map_op);
+    match map_op {
+        crdts::map::Op::Up { key, op, .. } => match datastore.network_state.dnssec_op_success(key, op) {
+            (true, v) => Response::Success(Success::Some(v.into())),
+            (false, _) => Response::Failure { reason: Some("update was rejected".to_string()) },
+        },
+        crdts::map::Op::Rm { .. } => Response::Success(Success::None),
+    }
+}
@@ -1,11 +1,13 @@
 use crate::datastore::{DataStore, DB_HANDLE, InstanceRequest};
-use crate::db::write_datastore;
+use crate::db::{persist_op, INSTANCE_MAP};
 use crate::instances::*;
 use reqwest::Client;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 use form_types::state::{Response, Success};
-use axum::{extract::{State, Path}, Json};
+use axum::{extract::{State, Path, Query}, response::IntoResponse, Json};
+use crate::pagination::ListQuery;
+use crate::placement::check_new_instance;
 use form_vm_metrics::system::SystemMetrics;
 use std::net::IpAddr;
 
@@ -20,8 +22,8 @@ pub async fn create_instance(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.instance_state.instance_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, INSTANCE_MAP, &datastore.instance_state.map, &map_op);
                     if let (true, v) = datastore.instance_state.instance_op_success(key.clone(), op.clone()) {
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         log::info!("Instance Op succesffully applied...");
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
@@ -36,10 +38,14 @@ pub async fn create_instance(
         }
         InstanceRequest::Create(contents) => {
             log::info!("Create Instance request was a direct request...");
+            if let Err(reason) = check_new_instance(&datastore, &contents) {
+                return Json(Response::Failure { reason: Some(format!("Placement constraint violated: {reason}")) });
+            }
             log::info!("Building Map Op...");
             let map_op = datastore.instance_state.update_instance_local(contents);
             log::info!("Map op created... Applying...");
             datastore.instance_state.instance_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, INSTANCE_MAP, &datastore.instance_state.map, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Create request".to_string()) });
@@ -50,7 +56,6 @@ pub async fn create_instance(
                         let request = InstanceRequest::Op(map_op);
                         match datastore.broadcast::<Response<Instance>>(request, "/instance/create").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting Instance Create Request: {e}")
@@ -79,9 +84,9 @@ pub async fn update_instance(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.instance_state.instance_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, INSTANCE_MAP, &datastore.instance_state.map, &map_op);
                     if let (true, v) = datastore.instance_state.instance_op_success(key.clone(), op.clone()) {
                         log::info!("Instance Op succesffully applied...");
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         log::info!("Instance Op rejected...");
@@ -95,10 +100,14 @@ pub async fn update_instance(
         }
         InstanceRequest::Update(contents) => {
             log::info!("Update Instance request was a direct request...");
+            if let Err(reason) = check_new_instance(&datastore, &contents) {
+                return Json(Response::Failure { reason: Some(format!("Placement constraint violated: {reason}")) });
+            }
             log::info!("Building Map Op...");
             let map_op = datastore.instance_state.update_instance_local(contents);
             log::info!("Map op created... Applying...");
             datastore.instance_state.instance_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, INSTANCE_MAP, &datastore.instance_state.map, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Update request".to_string()) });
@@ -109,7 +118,6 @@ pub async fn update_instance(
                         let request = InstanceRequest::Op(map_op);
                         match datastore.broadcast::<Response<Instance>>(request, "/instance/update").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting Instance Update Request: {e}")
@@ -301,7 +309,20 @@ pub async fn get_cluster_metrics(
         }
     }).collect();
 
-    let mut results = vec![]; 
+    drop(datastore);
+
+    let results = fetch_instance_metrics(endpoints).await;
+    if !results.is_empty() {
+        Json(Response::Success(Success::List(results)))
+    } else {
+        Json(Response::Failure { reason: Some(format!("Unable to acquire any metrics foor instances with build_id: {id}")) }) 
+    }
+}
+
+/// Collects metrics from each instance's metrics endpoint, skipping
+/// instances that do not respond
+pub async fn fetch_instance_metrics(endpoints: Vec<String>) -> Vec<SystemMetrics> {
+    let mut results = vec![];
     for endpoint in endpoints {
         if let Ok(resp) = Client::new()
             .get(endpoint)
@@ -312,14 +333,9 @@ pub async fn get_cluster_metrics(
             }
     };
 
-    if !results.is_empty() {
-        Json(Response::Success(Success::List(results)))
-    } else {
-        Json(Response::Failure { reason: Some(format!("Unable to acquire any metrics foor instances with build_id: {id}")) }) 
-    }
+    results
 }
 
-
 pub async fn delete_instance(
     State(state): State<Arc<Mutex<DataStore>>>,
     Path(_id): Path<(String, String)>,
@@ -334,7 +350,8 @@ pub async fn delete_instance(
                     return Json(Response::Failure { reason: Some("Invalid Op type for delete dns".into()) });
                 }
                 crdts::map::Op::Rm { .. } => {
-                    datastore.instance_state.instance_op(map_op);
+                    datastore.instance_state.instance_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, INSTANCE_MAP, &datastore.instance_state.map, &map_op);
                     return Json(Response::Success(Success::None))
                 }
             }
@@ -345,6 +362,7 @@ pub async fn delete_instance(
             let map_op = datastore.instance_state.remove_instance_local(id.clone());
             log::info!("Map op created... Applying...");
             datastore.instance_state.instance_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, INSTANCE_MAP, &datastore.instance_state.map, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     let request = InstanceRequest::Op(map_op);
@@ -367,7 +385,8 @@ pub async fn delete_instance(
 
 pub async fn list_instances(
     State(state): State<Arc<Mutex<DataStore>>>,
-) -> Json<Response<Instance>> {
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
     let datastore = state.lock().await;
     let list: Vec<Instance> = datastore.instance_state.map().iter().filter_map(|ctx| {
         let (_, value) = ctx.val;
@@ -379,5 +398,5 @@ pub async fn list_instances(
         }
     }).collect(); 
 
-    return Json(Response::Success(Success::List(list)))
+    query.respond(list)
 }
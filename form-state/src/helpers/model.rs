@@ -3,7 +3,8 @@ use crate::api_keys::ApiKeyAuth;
 use crate::billing::UsageTracker;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use axum::{extract::{State, Path}, Json};
+use axum::{extract::{State, Path, Query}, Json};
+use crate::pagination::ListQuery;
 use serde::{Serialize, Deserialize};
 use axum::http::StatusCode;
 use axum::response::IntoResponse;
@@ -339,6 +340,7 @@ pub async fn get_model(
 pub async fn list_model(
     State(state): State<Arc<Mutex<DataStore>>>,
     auth: ApiKeyAuth,
+    Query(query): Query<ListQuery>,
 ) -> impl IntoResponse {
     log::info!("Account {} is requesting list of all models", auth.account.address);
     
@@ -356,16 +358,41 @@ pub async fn list_model(
     // Get all models from datastore
     let datastore = state.lock().await;
     let all_models = datastore.model_state.list_models();
-    
-    // Return the models with 200 OK
-    (
-        StatusCode::OK, 
-        Json(json!({
-            "success": true,
-            "models": all_models,
-            "total": all_models.len()
-        }))
-    )
+    drop(datastore);
+
+    // Without a limit or cursor the models keep being returned keyed by id
+    let paginated = query.is_paginated();
+    match query.apply(all_models.into_values().collect::<Vec<_>>()) {
+        Ok(page) if paginated => (
+            StatusCode::OK,
+            Json(json!({
+                "success": true,
+                "models": page.items,
+                "next_cursor": page.next_cursor,
+                "total": page.total
+            }))
+        ),
+        Ok(page) => {
+            let models: std::collections::HashMap<String, _> = page.items.into_iter()
+                .map(|model| (model.model_id.clone(), model))
+                .collect();
+            (
+                StatusCode::OK,
+                Json(json!({
+                    "success": true,
+                    "total": models.len(),
+                    "models": models
+                }))
+            )
+        }
+        Err(e) => (
+            StatusCode::BAD_REQUEST,
+            Json(json!({
+                "success": false,
+                "error": e
+            }))
+        ),
+    }
 }
 
 /// Handler for model inference
@@ -1,14 +1,15 @@
 use crdts::{bft_reg::Update, BFTReg, Map};
-use crate::db::{store_map, write_datastore};
+use crate::db::{persist_op, ASSOC_MAP, CIDR_MAP, DNS_MAP, PEER_MAP};
 use reqwest::Client;
 use crate::datastore::{DataStore, PeerRequest, CidrRequest, DnsRequest, AssocRequest, DB_HANDLE, InstanceRequest}; 
 use crate::network::{NetworkState, CrdtPeer, CrdtCidr, CrdtAssociation, CrdtDnsRecord};
 use form_types::state::{Response, Success};
 use serde::{Serialize, Deserialize};
-use axum::{extract::{State, Path}, Json};
+use axum::{extract::{State, Path, Query}, response::IntoResponse, Json};
+use crate::pagination::ListQuery;
 use std::sync::Arc;
 use tokio::sync::Mutex;
-use form_dns::{store::FormDnsRecord, api::{DomainResponse, DomainRequest}};
+use form_dns::{store::FormDnsRecord, api::{DomainResponse, DomainRequest}, routing::RoutingPolicy};
 use trust_dns_proto::rr::RecordType;
 use std::net::SocketAddr;
 use std::net::IpAddr;
@@ -115,9 +116,9 @@ pub async fn create_user(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.peer_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
                     if let (true, v) = datastore.network_state.peer_op_success(key.clone(), op.clone()) {
                         log::info!("Peer Op succesffully applied...");
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         log::info!("Peer Op rejected...");
@@ -135,6 +136,7 @@ pub async fn create_user(
             let map_op = datastore.network_state.update_peer_local(contents);
             log::info!("Map op created... Applying...");
             datastore.network_state.peer_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Join request".to_string()) });
@@ -145,7 +147,6 @@ pub async fn create_user(
                         let request = PeerRequest::Op(map_op);
                         match datastore.broadcast::<Response<Peer<String>>>(request, "/user/create").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting DeletePeerRequest: {e}")
@@ -175,8 +176,8 @@ pub async fn update_user(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.peer_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
                     if let (true, v) = datastore.network_state.peer_op_success(key.clone(), op.clone()) {
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         return Json(Response::Failure { reason: Some("update was rejected".to_string()) })
@@ -203,6 +204,7 @@ pub async fn update_user(
             };
             let map_op = datastore.network_state.update_peer_local(contents.clone());
             datastore.network_state.peer_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Join request".to_string()) });
@@ -213,7 +215,6 @@ pub async fn update_user(
                         let request = PeerRequest::Op(map_op);
                         match datastore.broadcast::<Response<Peer<String>>>(request, "/user/update").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())));
                             }
                             Err(e) => eprintln!("Error broadcasting DeletePeerRequest: {e}")
@@ -259,9 +260,9 @@ pub async fn disable_user(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.peer_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
                     if let (true, v) = datastore.network_state.peer_op_success(key.clone(), op.clone()) {
                         log::info!("Map Op was successful, broadcasting...");
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         return Json(Response::Failure { reason: Some("update was rejected".to_string()) })
@@ -277,6 +278,7 @@ pub async fn disable_user(
             log::info!("Building Map Op...");
             let map_op = datastore.network_state.update_peer_local(contents);
             datastore.network_state.peer_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Join request".to_string()) });
@@ -287,7 +289,6 @@ pub async fn disable_user(
                         let request = PeerRequest::Op(map_op);
                         match datastore.broadcast::<Response<Peer<String>>>(request, "/user/disable").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting DeletePeerRequest: {e}")
@@ -317,8 +318,8 @@ pub async fn redeem_invite(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.peer_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
                     if let (true, v) = datastore.network_state.peer_op_success(key.clone(), op.clone()) {
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         return Json(Response::Failure { reason: Some("update was rejected".to_string()) })
@@ -334,6 +335,7 @@ pub async fn redeem_invite(
             log::info!("Building Map Op...");
             let map_op = datastore.network_state.update_peer_local(contents);
             datastore.network_state.peer_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Join request".to_string()) });
@@ -344,7 +346,6 @@ pub async fn redeem_invite(
                         let request = PeerRequest::Op(map_op);
                         match datastore.broadcast::<Response<Peer<String>>>(request, "/user/redeem").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting DeletePeerRequest: {e}")
@@ -375,7 +376,8 @@ pub async fn delete_user(
                     return Json(Response::Failure { reason: Some("Invalid Op type for delete User".into()) });
                 }
                 crdts::map::Op::Rm { .. } => {
-                    datastore.network_state.peer_op(map_op);
+                    datastore.network_state.peer_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
                     return Json(Response::Success(Success::None));
                 }
             }
@@ -385,6 +387,7 @@ pub async fn delete_user(
             log::info!("Building Map Op...");
             let map_op = datastore.network_state.remove_peer_local(contents);
             datastore.network_state.peer_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, PEER_MAP, &datastore.network_state.peers, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     let request = PeerRequest::Op(map_op);
@@ -459,11 +462,12 @@ pub async fn get_all_allowed(
 
 pub async fn list_users(
     State(state): State<Arc<Mutex<DataStore>>>,
-) -> Json<Response<Peer<String>>> {
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
     log::info!("Requesting a list of all users in the network...");
-    let peers = state.lock().await.get_all_users().iter().map(|(_, v)| v.clone().into()).collect();
+    let peers: Vec<Peer<String>> = state.lock().await.get_all_users().iter().map(|(_, v)| v.clone().into()).collect();
     log::info!("Retrieved a list of all users in the network... Returning...");
-    Json(Response::Success(Success::List(peers)))
+    query.respond(peers)
 }
 
 pub async fn list_admin(
@@ -532,8 +536,8 @@ pub async fn create_cidr(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.cidr_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, CIDR_MAP, &datastore.network_state.cidrs, &map_op);
                     if let (true, v) = datastore.network_state.cidr_op_success(key.clone(), op.clone()) {
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         return Json(Response::Failure { reason: Some("update was rejected".to_string()) })
@@ -547,6 +551,7 @@ pub async fn create_cidr(
         CidrRequest::Create(contents) => {
             let map_op = datastore.network_state.update_cidr_local(contents);
             datastore.network_state.cidr_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, CIDR_MAP, &datastore.network_state.cidrs, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Create request".to_string()) });
@@ -556,7 +561,6 @@ pub async fn create_cidr(
                         let request = CidrRequest::Op(map_op);
                         match datastore.broadcast::<Response<Cidr<String>>>(request, "/cidr/create").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting CreateCidrRequest: {e}")
@@ -584,8 +588,8 @@ pub async fn update_cidr(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.cidr_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, CIDR_MAP, &datastore.network_state.cidrs, &map_op);
                     if let (true, v) = datastore.network_state.cidr_op_success(key.clone(), op.clone()) {
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         return Json(Response::Failure { reason: Some("update was rejected".to_string()) })
@@ -599,6 +603,7 @@ pub async fn update_cidr(
         CidrRequest::Update(contents) => {
             let map_op = datastore.network_state.update_cidr_local(contents);
             datastore.network_state.cidr_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, CIDR_MAP, &datastore.network_state.cidrs, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Update request".to_string()) });
@@ -608,7 +613,6 @@ pub async fn update_cidr(
                         let request = CidrRequest::Op(map_op);
                         match datastore.broadcast::<Response<Cidr<String>>>(request, "/cidr/update").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting UpdateCidrRequest: {e}")
@@ -638,7 +642,8 @@ pub async fn delete_cidr(
                     return Json(Response::Failure { reason: Some("Invalid Op type for delete cidr".into()) });
                 }
                 crdts::map::Op::Rm { .. } => {
-                    datastore.network_state.cidr_op(map_op);
+                    datastore.network_state.cidr_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, CIDR_MAP, &datastore.network_state.cidrs, &map_op);
                     return Json(Response::Success(Success::None));
                 }
             }
@@ -646,6 +651,7 @@ pub async fn delete_cidr(
         CidrRequest::Delete(contents) => {
             let map_op = datastore.network_state.remove_cidr_local(contents);
             datastore.network_state.cidr_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, CIDR_MAP, &datastore.network_state.cidrs, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     let request = CidrRequest::Op(map_op);
@@ -702,8 +708,8 @@ pub async fn create_assoc(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.associations_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, ASSOC_MAP, &datastore.network_state.associations, &map_op);
                     if let (true, v) = datastore.network_state.associations_op_success(key.clone(), op.clone()) {
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         return Json(Response::Failure { reason: Some("update was rejected".to_string()) })
@@ -717,6 +723,7 @@ pub async fn create_assoc(
         AssocRequest::Create(contents) => {
             let map_op = datastore.network_state.update_association_local(contents);
             datastore.network_state.associations_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, ASSOC_MAP, &datastore.network_state.associations, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Create Association request".to_string()) });
@@ -726,7 +733,6 @@ pub async fn create_assoc(
                         let request = AssocRequest::Op(map_op);
                         match datastore.broadcast::<Response<Association<String, (String, String)>>>(request, "/assoc/create").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting CreateAssoc Request: {e}")
@@ -756,7 +762,8 @@ pub async fn delete_assoc(
                     return Json(Response::Failure { reason: Some("Invalid Op type for delete association".into()) });
                 }
                 crdts::map::Op::Rm { .. } => {
-                    datastore.network_state.associations_op(map_op);
+                    datastore.network_state.associations_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, ASSOC_MAP, &datastore.network_state.associations, &map_op);
                     return Json(Response::Success(Success::None));
                 }
             }
@@ -764,6 +771,7 @@ pub async fn delete_assoc(
         AssocRequest::Delete(contents) => {
             let map_op = datastore.network_state.remove_association_local(contents);
             datastore.network_state.associations_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, ASSOC_MAP, &datastore.network_state.associations, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     let request = AssocRequest::Op(map_op);
@@ -867,6 +875,11 @@ pub async fn request_vanity(
         ttl: 3600,
         verification_status: None,
         verification_timestamp: None,
+        txt: vec![],
+        mx: vec![],
+        srv: vec![],
+        caa: vec![],
+        routing: RoutingPolicy::default(),
     };
 
     let request = DnsRequest::Create(dns_a_record.clone());
@@ -1013,7 +1026,12 @@ pub async fn request_public(
         ssl_cert: false,
         ttl: 3600,
         verification_status: None,
-        verification_timestamp: None
+        verification_timestamp: None,
+        txt: vec![],
+        mx: vec![],
+        srv: vec![],
+        caa: vec![],
+        routing: RoutingPolicy::default(),
     };
 
     let request = DnsRequest::Create(dns_a_record.clone());
@@ -1089,6 +1107,7 @@ pub async fn create_dns(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.dns_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, DNS_MAP, &datastore.network_state.dns_state.zones, &map_op);
                     return Json(handle_create_dns_op(&datastore.network_state, key, op.clone()).await)
                 }
                 crdts::map::Op::Rm { .. } => {
@@ -1098,10 +1117,14 @@ pub async fn create_dns(
         }
         DnsRequest::Create(contents) => {
             log::info!("Create user request was a direct request...");
+            if let Err(e) = contents.routing.validate() {
+                return Json(Response::Failure { reason: Some(e) });
+            }
             log::info!("Building Map Op...");
             let map_op = datastore.network_state.update_dns_local(contents);
             log::info!("Map op created... Applying...");
             datastore.network_state.dns_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, DNS_MAP, &datastore.network_state.dns_state.zones, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Create DNS Record request".to_string()) });
@@ -1129,6 +1152,7 @@ pub async fn update_dns(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.network_state.dns_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, DNS_MAP, &datastore.network_state.dns_state.zones, &map_op);
                     return Json(handle_update_dns_op(&datastore.network_state, key, op.clone()).await);
                 }
                 crdts::map::Op::Rm { .. } => {
@@ -1138,10 +1162,14 @@ pub async fn update_dns(
         }
         DnsRequest::Update(contents) => {
             log::info!("Create user request was a direct request...");
+            if let Err(e) = contents.routing.validate() {
+                return Json(Response::Failure { reason: Some(e) });
+            }
             log::info!("Building Map Op...");
             let map_op = datastore.network_state.update_dns_local(contents);
             log::info!("Map op created... Applying...");
             datastore.network_state.dns_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, DNS_MAP, &datastore.network_state.dns_state.zones, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Update request".to_string()) });
@@ -1171,7 +1199,8 @@ pub async fn delete_dns(
                     return Json(Response::Failure { reason: Some("Invalid Op type for delete dns".into()) });
                 }
                 crdts::map::Op::Rm { .. } => {
-                    datastore.network_state.dns_op(map_op);
+                    datastore.network_state.dns_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, DNS_MAP, &datastore.network_state.dns_state.zones, &map_op);
                     return Json(send_dns_delete_request(&domain).await)
                 }
             }
@@ -1182,6 +1211,7 @@ pub async fn delete_dns(
             let map_op = datastore.network_state.remove_dns_local(domain.clone());
             log::info!("Map op created... Applying...");
             datastore.network_state.dns_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, DNS_MAP, &datastore.network_state.dns_state.zones, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     let request = DnsRequest::Op(map_op);
@@ -1273,7 +1303,8 @@ pub async fn get_dns_records_by_node_ip(
 
 pub async fn list_dns_records(
     State(state): State<Arc<Mutex<DataStore>>>,
-) -> Json<Response<FormDnsRecord>> {
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
     let datastore = state.lock().await;
     let dns_record_list = datastore.network_state.dns_state.zones.iter().filter_map(|ctx|{ 
         let (_domain, reg) = ctx.val;
@@ -1283,12 +1314,12 @@ pub async fn list_dns_records(
         }
     }).collect::<Vec<FormDnsRecord>>();
 
-    if !dns_record_list.is_empty() {
-        return Json(Response::Success(Success::List(dns_record_list)))
-    } else {
-        return Json(Response::Failure { reason: Some("Unable to find any valid DNS records".to_string()) }) 
+    if dns_record_list.is_empty() && !query.is_paginated() {
+        return Json(Response::<FormDnsRecord>::Failure { reason: Some("Unable to find any valid DNS records".to_string()) }).into_response()
     }
 
+    query.respond(dns_record_list)
+
 }
 
 pub async fn build_dns_request(v: Option<CrdtDnsRecord>, op_type: &str) -> (DomainRequest, Option<Response<FormDnsRecord>>) {
@@ -1299,7 +1330,12 @@ pub async fn build_dns_request(v: Option<CrdtDnsRecord>, op_type: &str) -> (Doma
                 record_type: RecordType::NULL,
                 ip_addr: vec![],
                 cname_target: None,
-                ssl_cert: false
+                ssl_cert: false,
+                txt: vec![],
+                mx: vec![],
+                srv: vec![],
+                caa: vec![],
+                routing: None,
             };
             return (request, Some(Response::Failure { reason: Some("Create request requires a record".into()) }))
         }
@@ -1314,6 +1350,11 @@ pub async fn build_dns_request(v: Option<CrdtDnsRecord>, op_type: &str) -> (Doma
                 ip_addr: vec![],
                 cname_target: None,
                 ssl_cert: false,
+                txt: vec![],
+                mx: vec![],
+                srv: vec![],
+                caa: vec![],
+                routing: None,
             };
             return (request, Some(Response::Failure { reason: Some("Update request requires a record".into()) }))
         }
@@ -1325,6 +1366,11 @@ pub async fn build_dns_request(v: Option<CrdtDnsRecord>, op_type: &str) -> (Doma
         ip_addr: vec![],
         cname_target: None,
         ssl_cert: false,
+        txt: vec![],
+        mx: vec![],
+        srv: vec![],
+        caa: vec![],
+        routing: None,
     };
     return (request, Some(Response::Failure { reason: Some("Update request requires a record".into()) }))
 }
@@ -1334,17 +1380,24 @@ pub async fn build_create_request(v: CrdtDnsRecord) -> (DomainRequest, Option<Re
         return build_create_a_record_request(v).await
     } else if let RecordType::AAAA = v.record_type() {
         return build_create_aaaa_record_request(v).await
-    } else if let RecordType::CNAME = v.record_type() {
+    } else if let RecordType::CNAME | RecordType::ANAME = v.record_type() {
         return build_create_cname_record_request(v).await
+    } else if let RecordType::TXT | RecordType::MX | RecordType::SRV | RecordType::CAA = v.record_type() {
+        return build_create_rdata_record_request(v).await
     } else {
         let request = DomainRequest::Create {
             domain: v.domain(), 
             record_type: RecordType::NULL,
             ip_addr: vec![],
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         };
-        return (request, Some(Response::Failure { reason: Some("Only A, AAAA and CNAME records are supported".to_string()) }));
+        return (request, Some(Response::Failure { reason: Some("Only A, AAAA, CNAME, ANAME, TXT, MX, SRV and CAA records are supported".to_string()) }));
     };
 }
 
@@ -1353,17 +1406,24 @@ pub async fn build_update_request(v: CrdtDnsRecord) -> (DomainRequest, Option<Re
         return build_update_a_record_request(v).await
     } else if let RecordType::AAAA = v.record_type() {
         return build_update_aaaa_record_request(v).await
-    } else if let RecordType::CNAME = v.record_type() {
+    } else if let RecordType::CNAME | RecordType::ANAME = v.record_type() {
         return build_update_cname_record_request(v).await
+    } else if let RecordType::TXT | RecordType::MX | RecordType::SRV | RecordType::CAA = v.record_type() {
+        return build_update_rdata_record_request(v).await
     } else {
         let request = DomainRequest::Update {
             replace: false, 
             record_type: RecordType::NULL,
             ip_addr: vec![],
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         };
-        return (request, Some(Response::Failure { reason: Some("Only A, AAAA and CNAME records are supported".to_string()) }));
+        return (request, Some(Response::Failure { reason: Some("Only A, AAAA, CNAME, ANAME, TXT, MX, SRV and CAA records are supported".to_string()) }));
     }
 }
 
@@ -1378,7 +1438,12 @@ pub async fn build_create_a_record_request(v: CrdtDnsRecord) -> (DomainRequest,
             record_type: v.record_type(), 
             ip_addr: ips,
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         };
         return (request, None)
     } else {
@@ -1387,7 +1452,12 @@ pub async fn build_create_a_record_request(v: CrdtDnsRecord) -> (DomainRequest,
             record_type: v.record_type(),
             ip_addr: v.public_ip(), 
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         }; 
         return (request, None)
     }
@@ -1404,16 +1474,26 @@ pub async fn build_update_a_record_request(v: CrdtDnsRecord) -> (DomainRequest,
             record_type: v.record_type(), 
             ip_addr: ips, 
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         };
         return(request, None);
     } else {
-        let request = DomainRequest::Create { 
-            domain: v.domain().clone(), 
+        let request = DomainRequest::Update { 
+            replace: true,
             record_type: v.record_type(),
             ip_addr: v.public_ip(), 
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         }; 
         (request, None)
     }
@@ -1426,7 +1506,12 @@ pub async fn build_create_aaaa_record_request(v: CrdtDnsRecord) -> (DomainReques
             record_type: v.record_type(),
             ip_addr: v.public_ip(), 
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         }; 
         return (request, None)
     } else {
@@ -1435,7 +1520,12 @@ pub async fn build_create_aaaa_record_request(v: CrdtDnsRecord) -> (DomainReques
             record_type: v.record_type(),
             ip_addr: v.public_ip(), 
             cname_target: None,
-            ssl_cert: v.ssl_cert()
+            ssl_cert: v.ssl_cert(),
+            txt: v.txt(),
+            mx: v.mx(),
+            srv: v.srv(),
+            caa: v.caa(),
+            routing: Some(v.routing()),
         };
         return (request, Some(Response::Failure { reason: Some("AAAA Record Updates require a public IP V6 address".to_string()) }))
     }
@@ -1447,7 +1537,12 @@ pub async fn build_update_aaaa_record_request(v: CrdtDnsRecord) -> (DomainReques
         record_type: v.record_type(),
         ip_addr: v.public_ip(), 
         cname_target: None,
-        ssl_cert: v.ssl_cert()
+        ssl_cert: v.ssl_cert(),
+        txt: v.txt(),
+        mx: v.mx(),
+        srv: v.srv(),
+        caa: v.caa(),
+        routing: Some(v.routing()),
     }; 
     (request, None)
 }
@@ -1462,7 +1557,12 @@ pub async fn build_create_cname_record_request(v: CrdtDnsRecord) -> (DomainReque
             ips
         },
         cname_target: v.cname_target().clone(),
-        ssl_cert: v.ssl_cert()
+        ssl_cert: v.ssl_cert(),
+        txt: v.txt(),
+        mx: v.mx(),
+        srv: v.srv(),
+        caa: v.caa(),
+        routing: Some(v.routing()),
     };
     (request, None)
 }
@@ -1477,7 +1577,44 @@ pub async fn build_update_cname_record_request(v: CrdtDnsRecord) -> (DomainReque
             ips
         },
         cname_target: v.cname_target().clone(),
-        ssl_cert: v.ssl_cert()
+        ssl_cert: v.ssl_cert(),
+        txt: v.txt(),
+        mx: v.mx(),
+        srv: v.srv(),
+        caa: v.caa(),
+        routing: Some(v.routing()),
+    };
+    (request, None)
+}
+
+pub async fn build_create_rdata_record_request(v: CrdtDnsRecord) -> (DomainRequest, Option<Response<FormDnsRecord>>) {
+    let request = DomainRequest::Create {
+        domain: v.domain().clone(),
+        record_type: v.record_type(),
+        ip_addr: vec![],
+        cname_target: None,
+        ssl_cert: v.ssl_cert(),
+        txt: v.txt(),
+        mx: v.mx(),
+        srv: v.srv(),
+        caa: v.caa(),
+        routing: Some(v.routing()),
+    };
+    (request, None)
+}
+
+pub async fn build_update_rdata_record_request(v: CrdtDnsRecord) -> (DomainRequest, Option<Response<FormDnsRecord>>) {
+    let request = DomainRequest::Update {
+        replace: true,
+        record_type: v.record_type(),
+        ip_addr: vec![],
+        cname_target: None,
+        ssl_cert: v.ssl_cert(),
+        txt: v.txt(),
+        mx: v.mx(),
+        srv: v.srv(),
+        caa: v.caa(),
+        routing: Some(v.routing()),
     };
     (request, None)
 }
@@ -1561,7 +1698,6 @@ pub async fn handle_create_dns_op(network_state: &NetworkState, key: &str, op: U
         if let Some(failure) = failure {
             return failure;
         }
-        let _ = store_map(&DB_HANDLE, "network_state/dns", &network_state.dns_state.zones.clone());
         return Response::Success(Success::Some(v.into()))
     } else {
         log::info!("DNS Op rejected...");
@@ -1572,7 +1708,7 @@ pub async fn handle_create_dns_op(network_state: &NetworkState, key: &str, op: U
 pub async fn handle_update_dns_op(network_state: &NetworkState, key: &str, op: Update<CrdtDnsRecord, String>) -> Response<FormDnsRecord> {
     if let (true, v) = network_state.dns_op_success(key.to_string(), op.clone()) {
         log::info!("Peer Op succesffully applied...");
-        let (request, failure) = build_dns_request(Some(v.clone()), "create").await;
+        let (request, failure) = build_dns_request(Some(v.clone()), "update").await;
         if let Some(failure) = failure {
             return failure
         }
@@ -1580,7 +1716,6 @@ pub async fn handle_update_dns_op(network_state: &NetworkState, key: &str, op: U
         if let Some(failure) = failure {
             return failure;
         }
-        let _ = store_map(&DB_HANDLE, "network_state/dns", &network_state.dns_state.zones.clone());
         return Response::Success(Success::Some(v.into()))
     } else {
         log::info!("Peer Op rejected...");
@@ -1,10 +1,11 @@
 use crate::datastore::{DataStore, NodeRequest, DB_HANDLE};
-use crate::db::write_datastore;
+use crate::db::{persist_op, NODE_MAP};
 use crate::nodes::Node;
 use std::sync::Arc;
 use form_node_metrics::metrics::NodeMetrics;
 use tokio::sync::Mutex;
-use axum::{extract::{State, Path}, Json};
+use axum::{extract::{State, Path, Query}, response::IntoResponse, Json};
+use crate::pagination::ListQuery;
 use form_types::state::{Response, Success};
 
 pub async fn create_node(
@@ -28,9 +29,9 @@ pub async fn create_node(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.node_state.node_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, NODE_MAP, &datastore.node_state.map, &map_op);
                     if let (true, v) = datastore.node_state.node_op_success(key.clone(), op.clone()) {
                         log::info!("Node Op succesffully applied...");
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         log::info!("Node Op rejected...");
@@ -58,6 +59,7 @@ pub async fn create_node(
             let map_op = datastore.node_state.update_node_local(contents);
             log::info!("Map op created... Applying...");
             datastore.node_state.node_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, NODE_MAP, &datastore.node_state.map, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Create request".to_string()) });
@@ -68,7 +70,6 @@ pub async fn create_node(
                         let request = NodeRequest::Op(map_op);
                         match datastore.broadcast::<Response<Node>>(request, "/node/create").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting Node Create Request: {e}")
@@ -97,9 +98,9 @@ pub async fn update_node(
             match &map_op {
                 crdts::map::Op::Up { ref key, ref op, .. } => {
                     datastore.node_state.node_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, NODE_MAP, &datastore.node_state.map, &map_op);
                     if let (true, v) = datastore.node_state.node_op_success(key.clone(), op.clone()) {
                         log::info!("Node Op succesffully applied...");
-                        let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                         return Json(Response::Success(Success::Some(v.into())))
                     } else {
                         log::info!("Node Op rejected...");
@@ -117,6 +118,7 @@ pub async fn update_node(
             let map_op = datastore.node_state.update_node_local(contents);
             log::info!("Map op created... Applying...");
             datastore.node_state.node_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, NODE_MAP, &datastore.node_state.map, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     return Json(Response::Failure { reason: Some("Map generated RM context instead of Add context on Create request".to_string()) });
@@ -127,7 +129,6 @@ pub async fn update_node(
                         let request = NodeRequest::Op(map_op);
                         match datastore.broadcast::<Response<Node>>(request, "/node/update").await {
                             Ok(()) => {
-                                let _ = write_datastore(&DB_HANDLE, &datastore.clone());
                                 return Json(Response::Success(Success::Some(v.into())))
                             }
                             Err(e) => eprintln!("Error broadcasting Node Update Request: {e}")
@@ -159,7 +160,8 @@ pub async fn delete_node(
                     return Json(Response::Failure { reason: Some("Invalid Op type for delete dns".into()) });
                 }
                 crdts::map::Op::Rm { .. } => {
-                    datastore.node_state.node_op(map_op);
+                    datastore.node_state.node_op(map_op.clone());
+                    let _ = persist_op(&DB_HANDLE, NODE_MAP, &datastore.node_state.map, &map_op);
                     return Json(Response::Success(Success::None))
                 }
             }
@@ -170,6 +172,7 @@ pub async fn delete_node(
             let map_op = datastore.node_state.remove_node_local(node_id.clone());
             log::info!("Map op created... Applying...");
             datastore.node_state.node_op(map_op.clone());
+            let _ = persist_op(&DB_HANDLE, NODE_MAP, &datastore.node_state.map, &map_op);
             match &map_op {
                 crdts::map::Op::Rm { .. } => {
                     let request = NodeRequest::Op(map_op);
@@ -202,6 +205,14 @@ pub async fn get_node(
     return Json(Response::Failure { reason: Some(format!("Unable to find node with id: {node_id}"))})
 }
 
+/// Id of the node this datastore runs on, for services on the same node
+pub async fn get_local_node_id(
+    State(state): State<Arc<Mutex<DataStore>>>,
+) -> Json<Response<String>> {
+    let datastore = state.lock().await;
+    Json(Response::Success(Success::Some(datastore.network_state.node_id.clone())))
+}
+
 pub async fn get_node_metrics(
     State(state): State<Arc<Mutex<DataStore>>>,
     Path(node_id): Path<String>,
@@ -233,7 +244,8 @@ pub async fn list_node_metrics(
 
 pub async fn list_nodes(
     State(state): State<Arc<Mutex<DataStore>>>,
-) -> Json<Response<Node>> {
+    Query(query): Query<ListQuery>,
+) -> impl IntoResponse {
     let datastore = state.lock().await;
     let list: Vec<Node> = datastore.node_state.map().iter().filter_map(|ctx| {
         let (_, value) = ctx.val;
@@ -245,5 +257,5 @@ pub async fn list_nodes(
         }
     }).collect(); 
 
-    return Json(Response::Success(Success::List(list)))
+    query.respond(list)
 }
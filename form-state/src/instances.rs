@@ -2,6 +2,7 @@ use std::{collections::{btree_map::{Iter, IterMut}, BTreeMap, HashSet}, fmt::Dis
 use crdts::{map::Op, merkle_reg::Sha3Hash, BFTReg, CmRDT, Map, bft_reg::Update};
 use form_dns::store::FormDnsRecord;
 use form_types::state::{Response, Success};
+use form_types::DeployStatus;
 use k256::ecdsa::SigningKey;
 use reqwest::Client;
 use serde::{Serialize, Deserialize};
@@ -56,6 +57,9 @@ pub struct Instance {
     pub formfile: String, 
     pub snapshots: Option<Snapshots>,
     pub metadata: InstanceMetadata,
+    /// Disks hot-plugged into the instance since it was created
+    #[serde(default)]
+    pub disks: Vec<InstanceDisk>,
 }
 
 impl Default for Instance {
@@ -78,15 +82,20 @@ impl Default for Instance {
             cluster: Default::default(),
             formfile: String::new(),
             snapshots: None,
-            metadata: Default::default()
-
+            metadata: Default::default(),
+            disks: Vec::new(),
         }
     }
 }
 
 impl Sha3Hash for Instance {
     fn hash(&self, hasher: &mut tiny_keccak::Sha3) {
-        hasher.update(&bincode::serialize(self).unwrap());
+        // Instances that don't use fields added since layout 0 hash the way
+        // peers still running it do
+        match self.legacy() {
+            Some(legacy) => legacy.hash(hasher),
+            None => hasher.update(&bincode::serialize(self).unwrap()),
+        }
     }
 }
 
@@ -230,6 +239,54 @@ impl Instance {
         return (current, n);
     }
 
+    /// Pushes a new snapshot onto the head of the snapshot history and drops
+    /// the oldest entries beyond `MAX_SNAPSHOTS`. Returns the ids of the
+    /// dropped snapshots so their files can be removed.
+    pub fn record_snapshot(&mut self, snapshot_id: String, timestamp: i64, description: Option<String>) -> Vec<String> {
+        let previous = self.snapshots.take();
+        self.last_snapshot = timestamp;
+        let mut history = vec![(snapshot_id, timestamp, description)];
+        let mut current = previous;
+        while let Some(snapshot) = current {
+            history.push((snapshot.snapshot_id, snapshot.timestamp, snapshot.description));
+            current = *snapshot.previous_snapshot;
+        }
+
+        let pruned = history.split_off(history.len().min(MAX_SNAPSHOTS))
+            .into_iter()
+            .map(|(snapshot_id, _, _)| snapshot_id)
+            .collect();
+        self.snapshots = history.into_iter().rev().fold(None, |previous, (snapshot_id, timestamp, description)| {
+            Some(Snapshots {
+                snapshot_id,
+                timestamp,
+                description,
+                previous_snapshot: Box::new(previous),
+            })
+        });
+        pruned
+    }
+
+    pub fn find_snapshot(&self, snapshot_id: &str) -> Option<Snapshots> {
+        let mut current = self.snapshots().clone();
+        while let Some(snapshot) = current {
+            if snapshot.snapshot_id == snapshot_id {
+                return Some(snapshot);
+            }
+            current = *snapshot.previous_snapshot;
+        }
+        None
+    }
+
+    pub fn disks(&self) -> &[InstanceDisk] {
+        &self.disks
+    }
+
+    /// Size of every disk hot-plugged into the instance
+    pub fn disk_gb(&self) -> u64 {
+        self.disks.iter().map(|disk| disk.size_gb).sum()
+    }
+
     pub fn tags(&self) -> Vec<String> {
         self.metadata().tags()
     }
@@ -283,6 +340,18 @@ impl Instance {
     }
 }
 
+/// A disk hot-plugged into a running instance. Its size counts against the
+/// owner's storage quota for as long as it is attached.
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InstanceDisk {
+    /// Device id the disk is attached under
+    pub disk_id: String,
+    /// Name of the disk image in the instance's volume directory
+    pub path: String,
+    pub size_gb: u64,
+    pub readonly: bool,
+}
+
 #[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct InstanceResources {
     pub vcpus: u8,
@@ -700,9 +769,38 @@ pub struct InstanceCluster {
     pub session_affinity_enabled: bool,
     
     /// State machine for managing scaling operations
-    /// This field is not serialized as part of the CRDT
-    #[serde(skip)]
+    /// Serialized with the cluster so the phase history of every operation is
+    /// replicated and survives restarts. Its history is capped at
+    /// `crate::scaling::MAX_OPERATION_HISTORY` operations. Not part of
+    /// layout 0, see `crate::legacy`
+    #[serde(default)]
     pub scaling_manager: Option<crate::scaling::ScalingManager>,
+
+    /// Whether the owner lets the autoscaler act on this cluster's scaling
+    /// policy. Set through `/cluster/:build_id/autoscaling`; the responsible
+    /// node then scales the cluster on its own authority.
+    #[serde(default)]
+    pub autoscaling_enabled: bool,
+
+    /// The deploy moving this cluster's domain over to a newer build, if
+    /// one has run. Not part of layout 0, see `crate::legacy`
+    #[serde(default)]
+    pub deploy: Option<ClusterDeploy>,
+}
+
+/// A deploy as recorded in the cluster of the build it deploys over, by the
+/// node running it. The record is what lets that node finish or roll back
+/// the deploy after a restart, and what the nodes it deletes instances on
+/// check its authority against.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ClusterDeploy {
+    /// Node running the deploy
+    pub node_id: String,
+    pub status: DeployStatus,
+    /// The domain's record before the deploy changed it
+    pub original_record: Option<FormDnsRecord>,
+    /// Hosts and ids of the new build's instances requested so far
+    pub created: Vec<(String, String)>,
 }
 
 impl Sha3Hash for InstanceCluster {
@@ -815,6 +913,26 @@ impl InstanceCluster {
         self.session_affinity_enabled = enabled;
     }
 
+    /// Returns whether the autoscaler may act on this cluster's scaling policy
+    pub fn autoscaling_enabled(&self) -> bool {
+        self.autoscaling_enabled
+    }
+
+    /// Sets whether the autoscaler may act on this cluster's scaling policy
+    pub fn set_autoscaling_enabled(&mut self, enabled: bool) {
+        self.autoscaling_enabled = enabled;
+    }
+
+    /// Returns the node responsible for autoscaling this cluster: the node
+    /// hosting the template instance, or the first member's node when the
+    /// template is not a member
+    pub fn responsible_node(&self) -> Option<&str> {
+        self.template_instance_id()
+            .and_then(|id| self.get(id))
+            .or_else(|| self.members.values().next())
+            .map(|member| member.node_id.as_str())
+    }
+
     /// Returns the number of members in this cluster
     pub fn size(&self) -> usize {
         self.members.len()
@@ -833,6 +951,8 @@ impl InstanceCluster {
             template_instance_id: Some(template_id),
             session_affinity_enabled: false,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         }
     }
 
@@ -844,6 +964,8 @@ impl InstanceCluster {
             template_instance_id: None,
             session_affinity_enabled: false,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         }
     }
 
@@ -1001,6 +1123,11 @@ impl InstanceCluster {
     pub fn scaling_manager_mut(&mut self) -> Option<&mut ScalingManager> {
         self.scaling_manager.as_mut()
     }
+
+    /// Returns the deploy recorded in this cluster, if any
+    pub fn deploy(&self) -> Option<&ClusterDeploy> {
+        self.deploy.as_ref()
+    }
     
     /// Processes a single step of the scaling state machine
     ///
@@ -2931,6 +3058,8 @@ impl InstanceCluster {
                                     template_instance_id: template_id_clone,
                                     session_affinity_enabled: session_affinity,
                                     scaling_manager: None,
+                                    autoscaling_enabled: false,
+                                    deploy: None,
                                 };
                                 
                                 // Use the verification framework with the temporary cluster
@@ -3926,6 +4055,10 @@ impl ClusterMember {
     }
 }
 
+/// Number of snapshots kept in an instance's history, older ones are
+/// dropped as new ones are recorded
+pub const MAX_SNAPSHOTS: usize = 8;
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Snapshots {
     pub snapshot_id: String,
@@ -4112,6 +4245,16 @@ impl InstanceState {
         }
     }
 
+    pub fn node_id(&self) -> &str {
+        &self.node_id
+    }
+
+    /// The node key, hex encoded, used to sign requests made on the node's
+    /// own authority
+    pub(crate) fn signing_key(&self) -> &str {
+        &self.pk
+    }
+
     pub fn map(&self) -> Map<String, BFTReg<Instance, Actor>, Actor> {
         self.map.clone()
     }
@@ -4143,6 +4286,7 @@ impl InstanceState {
     pub fn instance_op(&mut self, op: InstanceOp) -> Option<(String, String)> {
         log::info!("Applying peer op");
         self.map.apply(op.clone());
+        crate::watch::instance_changed(&op, |key| self.get_instance(key.clone()));
         match op {
             Op::Up { dot, key, op: _ } => Some((dot.actor, key)),
             Op::Rm { .. } => None
@@ -4518,6 +4662,8 @@ mod tests {
             template_instance_id: Some("instance1".to_string()),
             session_affinity_enabled: true,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         };
         
         // Verify the values
@@ -4566,6 +4712,8 @@ mod tests {
                 template_instance_id: Some("instance1".to_string()),
                 session_affinity_enabled: true,
                 scaling_manager: None,
+                autoscaling_enabled: false,
+                deploy: None,
             }
         };
         
@@ -4612,6 +4760,8 @@ mod tests {
             template_instance_id: Some("instance1".to_string()),
             session_affinity_enabled: true,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         };
         
         // Test accessors
@@ -4947,6 +5097,8 @@ mod tests {
                 template_instance_id: Some("template1".to_string()),
                 session_affinity_enabled: true,
                 scaling_manager: None,
+                autoscaling_enabled: false,
+                deploy: None,
             },
             formfile: "".to_string(),
             snapshots: None,
@@ -4971,6 +5123,7 @@ mod tests {
                     metrics_endpoint: "".to_string(),
                 },
             },
+            disks: vec![],
         };
 
         // Serialize and deserialize the instance to verify it works with our new fields
@@ -4983,6 +5136,60 @@ mod tests {
         assert_eq!(instance.cluster.session_affinity_enabled, deserialized.cluster.session_affinity_enabled);
     }
 
+    #[test]
+    fn test_snapshot_history() {
+        let mut instance = Instance {
+            instance_id: "test1".to_string(),
+            node_id: "node1".to_string(),
+            build_id: "build1".to_string(),
+            instance_owner: "owner1".to_string(),
+            formnet_ip: None,
+            dns_record: None,
+            created_at: 0,
+            updated_at: 0,
+            last_snapshot: 0,
+            status: InstanceStatus::Started,
+            host_region: "us-east".to_string(),
+            resources: InstanceResources {
+                vcpus: 2,
+                memory_mb: 1024,
+                bandwidth_mbps: 100,
+                gpu: None,
+            },
+            cluster: InstanceCluster::default(),
+            formfile: "".to_string(),
+            snapshots: None,
+            metadata: InstanceMetadata::default(),
+            disks: vec![],
+        };
+
+        assert_eq!(instance.n_snapshots_ago(0), (None, 0));
+
+        instance.record_snapshot("snap-1".to_string(), 100, None);
+        instance.record_snapshot("snap-2".to_string(), 200, Some("before upgrade".to_string()));
+        instance.record_snapshot("snap-3".to_string(), 300, None);
+
+        assert_eq!(instance.last_snapshot, 300);
+        assert_eq!(instance.n_snapshots_ago(0).0.unwrap().id(), "snap-3");
+        assert_eq!(instance.n_snapshots_ago(2).0.unwrap().id(), "snap-1");
+        let (oldest, remaining) = instance.n_snapshots_ago(5);
+        assert_eq!(oldest.unwrap().id(), "snap-1");
+        assert_eq!(remaining, 3);
+
+        let found = instance.find_snapshot("snap-2").unwrap();
+        assert_eq!(found.description(), Some("before upgrade".to_string()));
+        assert!(instance.find_snapshot("snap-4").is_none());
+
+        for i in 4..=MAX_SNAPSHOTS {
+            assert!(instance.record_snapshot(format!("snap-{i}"), i as i64 * 100, None).is_empty());
+        }
+        let pruned = instance.record_snapshot("snap-next".to_string(), 10_000, None);
+        assert_eq!(pruned, vec!["snap-1".to_string()]);
+        assert!(instance.find_snapshot("snap-1").is_none());
+        assert_eq!(instance.n_snapshots_ago(0).0.unwrap().id(), "snap-next");
+        assert_eq!(instance.n_snapshots_ago(MAX_SNAPSHOTS as u32 - 1).0.unwrap().id(), "snap-2");
+    }
+
     #[test]
     fn test_instance_cluster_crdt_merge() {
         use k256::ecdsa::SigningKey;
@@ -5025,6 +5232,8 @@ mod tests {
                 template_instance_id: Some("template1".to_string()),
                 session_affinity_enabled: true,
                 scaling_manager: None,
+                autoscaling_enabled: false,
+                deploy: None,
             },
             formfile: "".to_string(),
             snapshots: None,
@@ -5049,6 +5258,7 @@ mod tests {
                     metrics_endpoint: "".to_string(),
                 },
             },
+            disks: vec![],
         };
 
         // Create the first operation with no members
@@ -5250,6 +5460,8 @@ mod tests {
             template_instance_id: Some("template-instance-123".to_string()),
             session_affinity_enabled: false,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         };
         
         // Initialize scaling manager
@@ -5338,6 +5550,8 @@ mod tests {
             template_instance_id: Some("template-instance-123".to_string()),
             session_affinity_enabled: false,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         };
         
         // Add a template instance to the cluster
@@ -5447,6 +5661,8 @@ mod tests {
             template_instance_id: Some("template-instance-123".to_string()),
             session_affinity_enabled: false,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         };
         
         // Add a template instance to the cluster
@@ -5549,6 +5765,8 @@ mod tests {
             template_instance_id: Some("template-instance-123".to_string()),
             session_affinity_enabled: false,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         };
         
         // Add a template instance to the cluster
@@ -5670,6 +5888,8 @@ mod tests {
             template_instance_id: Some("template-instance-123".to_string()),
             session_affinity_enabled: false,
             scaling_manager: None,
+            autoscaling_enabled: false,
+            deploy: None,
         };
         
         // Add a template instance to the cluster
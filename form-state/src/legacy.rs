@@ -0,0 +1,184 @@
+//! Layouts of persisted records as they were written before fields were
+//! added to them.
+//!
+//! Map entries and logged ops are encoded with bincode, which is positional
+//! and ignores `#[serde(default)]`, so a record written before a field was
+//! added cannot be decoded as the current type. `db::read_datastore` decodes
+//! maps whose stored layout is older than `db::layout_version` with the
+//! types below and rewrites them in the current layout.
+//!
+//! Records that don't use any of the added fields also hash in the layout
+//! they were signed in, so peers that haven't been upgraded yet accept them.
+use std::{collections::BTreeMap, net::{IpAddr, SocketAddr}};
+use crdts::merkle_reg::Sha3Hash;
+use form_dns::store::{FormDnsRecord, VerificationStatus};
+use serde::{Serialize, Deserialize};
+use tiny_keccak::Hasher;
+use trust_dns_proto::rr::RecordType;
+
+use crate::instances::{
+    ClusterMember, Instance, InstanceMetadata, InstanceResources,
+    InstanceStatus, ScalingPolicy, Snapshots,
+};
+use crate::network::CrdtDnsRecord;
+
+/// `Instance` in layout 0
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LegacyInstance {
+    pub instance_id: String,
+    pub node_id: String,
+    pub build_id: String,
+    pub instance_owner: String,
+    pub formnet_ip: Option<IpAddr>,
+    pub dns_record: Option<LegacyFormDnsRecord>,
+    pub created_at: i64,
+    pub updated_at: i64,
+    pub last_snapshot: i64,
+    pub status: InstanceStatus,
+    pub host_region: String,
+    pub resources: InstanceResources,
+    pub cluster: LegacyInstanceCluster,
+    pub formfile: String,
+    pub snapshots: Option<Snapshots>,
+    pub metadata: InstanceMetadata,
+}
+
+impl Default for LegacyInstance {
+    fn default() -> Self {
+        Instance::default().legacy().expect("A default instance has no added fields")
+    }
+}
+
+impl Sha3Hash for LegacyInstance {
+    fn hash(&self, hasher: &mut tiny_keccak::Sha3) {
+        hasher.update(&bincode::serialize(self).unwrap());
+    }
+}
+
+/// `InstanceCluster` in layout 0, before the scaling manager was persisted
+#[derive(Clone, Default, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LegacyInstanceCluster {
+    pub members: BTreeMap<String, ClusterMember>,
+    pub scaling_policy: Option<ScalingPolicy>,
+    pub template_instance_id: Option<String>,
+    pub session_affinity_enabled: bool,
+}
+
+/// `FormDnsRecord` as embedded in an `Instance` in layout 0, before TXT,
+/// MX, SRV and CAA data and routing policies were added
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LegacyFormDnsRecord {
+    pub domain: String,
+    pub record_type: RecordType,
+    pub public_ip: Vec<SocketAddr>,
+    pub formnet_ip: Vec<SocketAddr>,
+    pub cname_target: Option<String>,
+    pub ssl_cert: bool,
+    pub ttl: u32,
+    pub verification_status: Option<VerificationStatus>,
+    pub verification_timestamp: Option<u64>,
+}
+
+/// `CrdtDnsRecord` in layout 0, before TXT, MX, SRV and CAA data and
+/// routing policies were added
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct LegacyDnsRecord {
+    pub domain: String,
+    pub record_type: RecordType,
+    pub formnet_ip: Vec<SocketAddr>,
+    pub public_ip: Vec<SocketAddr>,
+    pub cname_target: Option<String>,
+    pub ttl: u32,
+    pub ssl_cert: bool,
+}
+
+impl Sha3Hash for LegacyDnsRecord {
+    fn hash(&self, hasher: &mut tiny_keccak::Sha3) {
+        hasher.update(&serde_json::to_vec(self).unwrap());
+    }
+}
+
+impl CrdtDnsRecord {
+    /// The record in layout 0, if it doesn't use any field added since
+    pub fn legacy(&self) -> Option<LegacyDnsRecord> {
+        let extended = !self.txt.is_empty()
+            || !self.mx.is_empty()
+            || !self.srv.is_empty()
+            || !self.caa.is_empty()
+            || !self.routing.is_default();
+        if extended {
+            return None;
+        }
+
+        Some(LegacyDnsRecord {
+            domain: self.domain.clone(),
+            record_type: self.record_type,
+            formnet_ip: self.formnet_ip.clone(),
+            public_ip: self.public_ip.clone(),
+            cname_target: self.cname_target.clone(),
+            ttl: self.ttl,
+            ssl_cert: self.ssl_cert,
+        })
+    }
+}
+
+impl Instance {
+    /// The instance in layout 0, if it doesn't use any field added since
+    pub fn legacy(&self) -> Option<LegacyInstance> {
+        let cluster = &self.cluster;
+        if cluster.scaling_manager.is_some() || cluster.autoscaling_enabled || cluster.deploy.is_some() || !self.disks.is_empty() {
+            return None;
+        }
+        let dns_record = match &self.dns_record {
+            Some(record) => Some(legacy_form_dns_record(record)?),
+            None => None,
+        };
+
+        Some(LegacyInstance {
+            instance_id: self.instance_id.clone(),
+            node_id: self.node_id.clone(),
+            build_id: self.build_id.clone(),
+            instance_owner: self.instance_owner.clone(),
+            formnet_ip: self.formnet_ip,
+            dns_record,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            last_snapshot: self.last_snapshot,
+            status: self.status.clone(),
+            host_region: self.host_region.clone(),
+            resources: self.resources.clone(),
+            cluster: LegacyInstanceCluster {
+                members: cluster.members.clone(),
+                scaling_policy: cluster.scaling_policy.clone(),
+                template_instance_id: cluster.template_instance_id.clone(),
+                session_affinity_enabled: cluster.session_affinity_enabled,
+            },
+            formfile: self.formfile.clone(),
+            snapshots: self.snapshots.clone(),
+            metadata: self.metadata.clone(),
+        })
+    }
+}
+
+fn legacy_form_dns_record(record: &FormDnsRecord) -> Option<LegacyFormDnsRecord> {
+    let extended = !record.txt.is_empty()
+        || !record.mx.is_empty()
+        || !record.srv.is_empty()
+        || !record.caa.is_empty()
+        || !record.routing.is_default();
+    if extended {
+        return None;
+    }
+
+    Some(LegacyFormDnsRecord {
+        domain: record.domain.clone(),
+        record_type: record.record_type,
+        public_ip: record.public_ip.clone(),
+        formnet_ip: record.formnet_ip.clone(),
+        cname_target: record.cname_target.clone(),
+        ssl_cert: record.ssl_cert,
+        ttl: record.ttl,
+        verification_status: record.verification_status.clone(),
+        verification_timestamp: record.verification_timestamp,
+    })
+}
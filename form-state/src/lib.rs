@@ -3,11 +3,20 @@ pub mod datastore;
 pub mod instances;
 pub mod nodes;
 pub mod db;
+pub mod legacy;
+pub mod snapshot;
 pub mod accounts;
 pub mod scaling;
+pub mod autoscaler;
+pub mod placement;
+pub mod migration;
+pub mod resize;
+pub mod metrics_history;
 pub mod verification;
 pub mod model;
 pub mod agent;
+pub mod pagination;
+pub mod watch;
 pub mod helpers;
 pub mod api;
 pub mod auth;
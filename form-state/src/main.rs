@@ -2,6 +2,7 @@ use std::path::PathBuf;
 
 use alloy_primitives::Address;
 use form_state::datastore::{request_full_state, DataStore};
+use form_state::snapshot::{restore_snapshot, Snapshot};
 use form_config::OperatorConfig;
 use clap::Parser;
 use k256::ecdsa::SigningKey;
@@ -32,6 +33,10 @@ pub struct Cli {
     jwt_leeway: Option<String>,
     #[clap(long)]
     env_file: Option<PathBuf>,
+    /// Seed this node from a snapshot file instead of from the local
+    /// database or a bootstrap peer
+    #[clap(long)]
+    restore_from: Option<PathBuf>,
 }
 
 #[tokio::main]
@@ -103,12 +108,18 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Acquired private key...");
 
     let address = hex::encode(Address::from_private_key(&SigningKey::from_slice(&hex::decode(&private_key)?)?)); 
-    let mut datastore = if parser.to_dial.is_empty() {
+    let mut datastore = if let Some(path) = &parser.restore_from {
+        log::info!("Restoring datastore from snapshot {}...", path.display());
+        let snapshot = Snapshot::read_from(path)?;
+        let mut datastore = DataStore::from_db(address.clone(), private_key.clone());
+        restore_snapshot(&mut datastore, &snapshot)?;
+        Some(datastore)
+    } else if parser.to_dial.is_empty() {
         if config.is_none() {
-            let datastore = DataStore::new(address.clone(), private_key.clone());
+            let datastore = DataStore::from_db(address.clone(), private_key.clone());
             Some(datastore)
         } else if config.clone().unwrap().bootstrap_nodes.is_empty() {
-            let datastore = DataStore::new(address.clone(), private_key.clone());
+            let datastore = DataStore::from_db(address.clone(), private_key.clone());
             Some(datastore)
         } else { 
             None
@@ -163,10 +174,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     log::info!("Built data store, running...");
     
     let (tx, _rx) = tokio::sync::broadcast::channel(1024);
+    let datastore = Arc::new(Mutex::new(datastore.unwrap()));
+
+    let compaction_datastore = datastore.clone();
+    let compaction_shutdown = tx.subscribe();
+    tokio::spawn(async move {
+        form_state::api::run_compaction(compaction_datastore, compaction_shutdown).await;
+    });
+
+    let sync_datastore = datastore.clone();
+    let sync_shutdown = tx.subscribe();
+    tokio::spawn(async move {
+        form_state::api::run_anti_entropy(sync_datastore, sync_shutdown).await;
+    });
+
+    let autoscaler_datastore = datastore.clone();
+    let autoscaler_shutdown = tx.subscribe();
+    tokio::spawn(async move {
+        form_state::api::run_autoscaler(autoscaler_datastore, autoscaler_shutdown).await;
+    });
+
+    let sampler_datastore = datastore.clone();
+    let sampler_shutdown = tx.subscribe();
+    tokio::spawn(async move {
+        form_state::api::run_metrics_sampler(sampler_datastore, sampler_shutdown).await;
+    });
     
     // Always run in full mode, devnet feature controls queue behavior
     let handle = tokio::spawn(async move {
-        if let Err(e) = form_state::api::run_api(Arc::new(Mutex::new(datastore.unwrap()))).await {
+        if let Err(e) = form_state::api::run_api(datastore).await {
             eprintln!("Error running datastore: {e}");
         }
     });
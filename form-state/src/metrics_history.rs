@@ -0,0 +1,558 @@
+//! Downsampled metric history for nodes and instances.
+//!
+//! Every `METRICS_SAMPLE_INTERVAL_SECS` the node records a raw sample of the
+//! latest `NodeMetrics` of every node in the datastore, and of the
+//! `SystemMetrics` of every instance it hosts. Samples are timestamped with
+//! when the metrics were reported, and a report that is no newer than the
+//! series' latest raw point is skipped, so a node that stopped reporting
+//! leaves a gap instead of repeating its last reading. Each sample is flattened into
+//! named gauges and folded into one-minute and one-hour buckets as it is
+//! written, so each resolution is ready to query without a separate rollup
+//! pass. Older points are pruned per resolution according to
+//! `Resolution::retention_secs`, both when a series is written and by a sweep
+//! over every series after each sampling round.
+//!
+//! Node metrics are replicated, so every node keeps the same node history.
+//! Instance history is only kept by the node hosting the instance, since
+//! that is the node that polls the instance's metrics endpoint.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::{extract::{Path, Query, State}, Json};
+use bincode::{serialize, deserialize};
+use form_node_metrics::metrics::NodeMetrics;
+use form_types::state::{Response, Success};
+use form_vm_metrics::system::SystemMetrics;
+use redb::{Database, ReadableTable, TableDefinition};
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+use crate::datastore::{DataStore, DB_HANDLE};
+use crate::helpers::instances::fetch_instance_metrics;
+
+// Metric points, keyed by `{resolution}/{kind}/{id}/{bucket:020}`
+const METRICS_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("metrics_history");
+
+/// Points returned by a range query when no limit is given
+const DEFAULT_POINT_LIMIT: usize = 1000;
+
+/// Upper bound on points returned by a single range query
+const MAX_POINT_LIMIT: usize = 10_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MetricsKind {
+    Node,
+    Instance,
+}
+
+impl MetricsKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MetricsKind::Node => "node",
+            MetricsKind::Instance => "instance",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Resolution {
+    #[default]
+    #[serde(rename = "raw")]
+    Raw,
+    #[serde(rename = "1m")]
+    Minute,
+    #[serde(rename = "1h")]
+    Hour,
+}
+
+impl Resolution {
+    pub const ALL: [Resolution; 3] = [Resolution::Raw, Resolution::Minute, Resolution::Hour];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Resolution::Raw => "raw",
+            Resolution::Minute => "1m",
+            Resolution::Hour => "1h",
+        }
+    }
+
+    /// Width of a bucket in seconds; raw points are keyed by their own timestamp
+    pub fn bucket_secs(&self) -> i64 {
+        match self {
+            Resolution::Raw => 1,
+            Resolution::Minute => 60,
+            Resolution::Hour => 3600,
+        }
+    }
+
+    /// How long points at this resolution are kept
+    pub fn retention_secs(&self) -> i64 {
+        match self {
+            Resolution::Raw => 6 * 3600,
+            Resolution::Minute => 7 * 24 * 3600,
+            Resolution::Hour => 90 * 24 * 3600,
+        }
+    }
+
+    fn bucket(&self, timestamp: i64) -> i64 {
+        timestamp - timestamp.rem_euclid(self.bucket_secs())
+    }
+}
+
+/// Aggregate of one gauge over the samples that fell into a bucket
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricStat {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+}
+
+impl MetricStat {
+    fn new(value: f64) -> Self {
+        Self { min: value, max: value, avg: value }
+    }
+
+    /// Folds a new value into an aggregate of `samples` values
+    fn merge(&mut self, value: f64, samples: u32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.avg += (value - self.avg) / (samples as f64 + 1.0);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MetricPoint {
+    /// Start of the bucket, or the sample time for raw points
+    pub timestamp: i64,
+    /// Number of raw samples folded into this point
+    pub samples: u32,
+    pub values: BTreeMap<String, MetricStat>,
+}
+
+impl MetricPoint {
+    fn from_sample(timestamp: i64, gauges: &BTreeMap<String, f64>) -> Self {
+        Self {
+            timestamp,
+            samples: 1,
+            values: gauges.iter().map(|(name, value)| (name.clone(), MetricStat::new(*value))).collect(),
+        }
+    }
+
+    fn merge(&mut self, gauges: &BTreeMap<String, f64>) {
+        for (name, value) in gauges {
+            match self.values.get_mut(name) {
+                Some(stat) => stat.merge(*value, self.samples),
+                None => {
+                    self.values.insert(name.clone(), MetricStat::new(*value));
+                }
+            }
+        }
+        self.samples += 1;
+    }
+}
+
+/// Flattens a node's metrics into named gauges. Optional readings the node
+/// did not report are left out rather than recorded as zero.
+pub fn node_gauges(metrics: &NodeMetrics) -> BTreeMap<String, f64> {
+    let mut gauges = BTreeMap::from([
+        ("load_avg_1".to_string(), metrics.load_avg_1 as f64),
+        ("load_avg_5".to_string(), metrics.load_avg_5 as f64),
+        ("load_avg_15".to_string(), metrics.load_avg_15 as f64),
+        ("process_count".to_string(), metrics.process_count as f64),
+        ("disk_read_bytes_per_sec".to_string(), metrics.disk_read_bytes_per_sec as f64),
+        ("disk_write_bytes_per_sec".to_string(), metrics.disk_write_bytes_per_sec as f64),
+        ("network_in_bytes_per_sec".to_string(), metrics.network_in_bytes_per_sec as f64),
+        ("network_out_bytes_per_sec".to_string(), metrics.network_out_bytes_per_sec as f64),
+    ]);
+    if let Some(temperature) = metrics.cpu_temperature {
+        gauges.insert("cpu_temperature".to_string(), temperature as f64);
+    }
+    if let Some(temperature) = metrics.gpu_temperature {
+        gauges.insert("gpu_temperature".to_string(), temperature as f64);
+    }
+    if let Some(watts) = metrics.power_usage_watts {
+        gauges.insert("power_usage_watts".to_string(), watts as f64);
+    }
+    gauges
+}
+
+/// Flattens an instance's metrics into named gauges. Disk and network
+/// counters are summed over devices, GPU readings are averaged over GPUs.
+pub fn instance_gauges(metrics: &SystemMetrics) -> BTreeMap<String, f64> {
+    let mut gauges = BTreeMap::from([
+        ("cpu_usage_pct".to_string(), metrics.cpu.usage_pct() as f64),
+        ("process_count".to_string(), metrics.cpu.process_count() as f64),
+        ("memory_total".to_string(), metrics.memory.total() as f64),
+        ("memory_used".to_string(), metrics.memory.used() as f64),
+        ("memory_available".to_string(), metrics.memory.available() as f64),
+        ("load1".to_string(), metrics.load.load1 as f64),
+        ("load5".to_string(), metrics.load.load5 as f64),
+        ("load15".to_string(), metrics.load.load15 as f64),
+        ("disk_sectors_read".to_string(), metrics.disks.iter().map(|d| d.sectors_read as f64).sum()),
+        ("disk_sectors_written".to_string(), metrics.disks.iter().map(|d| d.sectors_written as f64).sum()),
+        ("network_bytes_received".to_string(), metrics.network.interfaces.iter().map(|i| i.bytes_received as f64).sum()),
+        ("network_bytes_sent".to_string(), metrics.network.interfaces.iter().map(|i| i.bytes_sent as f64).sum()),
+    ]);
+    if !metrics.gpus.is_empty() {
+        let count = metrics.gpus.len() as f64;
+        gauges.insert(
+            "gpu_utilization_bps".to_string(),
+            metrics.gpus.iter().map(|g| g.utilization_bps as f64).sum::<f64>() / count,
+        );
+        gauges.insert(
+            "gpu_memory_usage_bps".to_string(),
+            metrics.gpus.iter().map(|g| g.memory_usage_bps as f64).sum::<f64>() / count,
+        );
+    }
+    gauges
+}
+
+fn series_prefix(resolution: Resolution, kind: MetricsKind, id: &str) -> String {
+    format!("{}/{}/{}/", resolution.as_str(), kind.as_str(), id)
+}
+
+fn point_key(resolution: Resolution, kind: MetricsKind, id: &str, bucket: i64) -> Vec<u8> {
+    format!("{}{:020}", series_prefix(resolution, kind, id), bucket.max(0)).into_bytes()
+}
+
+/// Records a sample at every resolution and prunes the series' points that
+/// have fallen out of retention. Returns `false` without recording anything
+/// if the sample is no newer than the series' latest raw point.
+pub fn record_sample(
+    db: &Database,
+    kind: MetricsKind,
+    id: &str,
+    timestamp: i64,
+    gauges: &BTreeMap<String, f64>,
+) -> Result<bool, Box<dyn std::error::Error>> {
+    let write_txn = db.begin_write()?;
+    {
+        let mut table = write_txn.open_table(METRICS_TABLE)?;
+        let start = series_prefix(Resolution::Raw, kind, id).into_bytes();
+        let end = point_key(Resolution::Raw, kind, id, i64::MAX);
+        let latest = table.range::<&[u8]>(&start[..]..=&end[..])?
+            .next_back()
+            .transpose()?
+            .map(|(_, value)| deserialize::<MetricPoint>(value.value()))
+            .transpose()?;
+        if latest.map_or(false, |point| point.timestamp >= timestamp) {
+            return Ok(false);
+        }
+
+        for resolution in Resolution::ALL {
+            let bucket = resolution.bucket(timestamp);
+            let key = point_key(resolution, kind, id, bucket);
+            let existing = table.get(&key[..])?
+                .map(|value| deserialize::<MetricPoint>(value.value()))
+                .transpose()?;
+            let point = match existing {
+                Some(mut point) => {
+                    point.merge(gauges);
+                    point
+                }
+                None => MetricPoint::from_sample(bucket, gauges),
+            };
+            let bytes = serialize(&point)?;
+            table.insert(&key[..], &bytes[..])?;
+
+            let start = series_prefix(resolution, kind, id).into_bytes();
+            let cutoff = point_key(resolution, kind, id, timestamp - resolution.retention_secs());
+            let expired: Vec<Vec<u8>> = table.range::<&[u8]>(&start[..]..&cutoff[..])?
+                .map(|record| record.map(|(key, _)| key.value().to_vec()))
+                .collect::<Result<_, _>>()?;
+            for key in expired {
+                table.remove(&key[..])?;
+            }
+        }
+    }
+    write_txn.commit()?;
+
+    Ok(true)
+}
+
+/// Removes every point, across all series, that has fallen out of its
+/// resolution's retention as of `now`. `record_sample` only prunes the
+/// series it writes to, so this keeps the history of nodes and instances
+/// that stopped reporting, or were deleted, from growing without bound.
+/// Returns the number of points removed.
+pub fn prune_expired(db: &Database, now: i64) -> Result<usize, Box<dyn std::error::Error>> {
+    let write_txn = db.begin_write()?;
+    let mut removed = 0;
+    {
+        let mut table = write_txn.open_table(METRICS_TABLE)?;
+        for resolution in Resolution::ALL {
+            // Keys of a resolution share the `{resolution}/` prefix, and `0`
+            // is the byte after `/`, so this range covers exactly them
+            let start = format!("{}/", resolution.as_str()).into_bytes();
+            let end = format!("{}0", resolution.as_str()).into_bytes();
+            let cutoff = format!("{:020}", (now - resolution.retention_secs()).max(0));
+            let expired: Vec<Vec<u8>> = table.range::<&[u8]>(&start[..]..&end[..])?
+                .filter_map(|record| match record {
+                    Ok((key, _)) => {
+                        let key = key.value();
+                        let bucket = &key[key.len().saturating_sub(20)..];
+                        (bucket < cutoff.as_bytes()).then(|| Ok(key.to_vec()))
+                    }
+                    Err(e) => Some(Err(e)),
+                })
+                .collect::<Result<_, _>>()?;
+            removed += expired.len();
+            for key in expired {
+                table.remove(&key[..])?;
+            }
+        }
+    }
+    write_txn.commit()?;
+
+    Ok(removed)
+}
+
+/// Reads the points of a series between `from` and `to` inclusive, oldest
+/// first, optionally keeping only the named gauges.
+pub fn read_range(
+    db: &Database,
+    kind: MetricsKind,
+    id: &str,
+    query: &HistoryQuery,
+) -> Result<Vec<MetricPoint>, Box<dyn std::error::Error>> {
+    let read_txn = db.begin_read()?;
+    let table = match read_txn.open_table(METRICS_TABLE) {
+        Ok(table) => table,
+        Err(redb::TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+
+    let resolution = query.resolution.unwrap_or_default();
+    let to = query.to.unwrap_or_else(now);
+    let from = query.from.unwrap_or(to - resolution.retention_secs());
+    let limit = query.limit.unwrap_or(DEFAULT_POINT_LIMIT).min(MAX_POINT_LIMIT);
+    let fields: Option<Vec<&str>> = query.fields.as_deref()
+        .map(|fields| fields.split(',').map(str::trim).filter(|f| !f.is_empty()).collect());
+
+    let start = point_key(resolution, kind, id, resolution.bucket(from));
+    let end = point_key(resolution, kind, id, to);
+    let mut points = Vec::new();
+    for record in table.range::<&[u8]>(&start[..]..=&end[..])?.take(limit) {
+        let (_, value) = record?;
+        let mut point: MetricPoint = deserialize(value.value())?;
+        if let Some(fields) = &fields {
+            point.values.retain(|name, _| fields.contains(&name.as_str()));
+        }
+        points.push(point);
+    }
+
+    Ok(points)
+}
+
+/// Takes one raw sample of every node's metrics and of the metrics of each
+/// instance hosted on this node, then prunes expired points of every series.
+pub async fn sample_round(state: Arc<Mutex<DataStore>>) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (nodes, instances) = {
+        let datastore = state.lock().await;
+        let node_id = datastore.instance_state.node_id().to_lowercase();
+        let nodes: Vec<(String, i64, NodeMetrics)> = datastore.node_state.map.iter().filter_map(|ctx| {
+            let (id, reg) = ctx.val;
+            let node = reg.val()?.value();
+            Some((id.clone(), node.updated_at, node.metrics))
+        }).collect();
+        let instances: Vec<(String, String)> = datastore.instance_state.map.iter().filter_map(|ctx| {
+            let (id, reg) = ctx.val;
+            let instance = reg.val()?.value();
+            if instance.node_id.to_lowercase() != node_id {
+                return None;
+            }
+            instance.formnet_ip.map(|ip| (id.clone(), format!("http://{ip}:63210/get")))
+        }).collect();
+        (nodes, instances)
+    };
+
+    for (id, reported_at, metrics) in nodes {
+        // Nodes that never reported metrics have nothing to record
+        if reported_at == 0 {
+            continue;
+        }
+        if let Err(e) = record_sample(&DB_HANDLE, MetricsKind::Node, &id, reported_at, &node_gauges(&metrics)) {
+            log::error!("Unable to record metrics for node {id}: {e}");
+        }
+    }
+
+    for (id, endpoint) in instances {
+        // Instances that don't respond simply have a gap in their history
+        let Some(metrics) = fetch_instance_metrics(vec![endpoint]).await.pop() else {
+            continue;
+        };
+        if let Err(e) = record_sample(&DB_HANDLE, MetricsKind::Instance, &id, metrics.timestamp, &instance_gauges(&metrics)) {
+            log::error!("Unable to record metrics for instance {id}: {e}");
+        }
+    }
+
+    if let Err(e) = prune_expired(&DB_HANDLE, now()) {
+        log::error!("Unable to prune expired metrics history: {e}");
+    }
+
+    Ok(())
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HistoryQuery {
+    /// `raw`, `1m` or `1h`, defaults to `raw`
+    pub resolution: Option<Resolution>,
+    /// Unix timestamp of the start of the range, defaults to the start of
+    /// the resolution's retention window
+    pub from: Option<i64>,
+    /// Unix timestamp of the end of the range, defaults to now
+    pub to: Option<i64>,
+    /// Comma separated gauges to return, defaults to all of them
+    pub fields: Option<String>,
+    pub limit: Option<usize>,
+}
+
+pub async fn get_node_metrics_history(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Path(node_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Response<MetricPoint>> {
+    if state.lock().await.node_state.get_node(node_id.clone()).is_none() {
+        return Json(Response::Failure { reason: Some(format!("Unable to find node with id: {node_id}")) });
+    }
+
+    match read_range(&DB_HANDLE, MetricsKind::Node, &node_id, &query) {
+        Ok(points) => Json(Response::Success(Success::List(points))),
+        Err(e) => Json(Response::Failure { reason: Some(format!("Unable to read metrics history: {e}")) }),
+    }
+}
+
+pub async fn get_instance_metrics_history(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Path(instance_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Json<Response<MetricPoint>> {
+    if state.lock().await.instance_state.get_instance(instance_id.clone()).is_none() {
+        return Json(Response::Failure { reason: Some("No record of instance in datastore".to_string()) });
+    }
+
+    match read_range(&DB_HANDLE, MetricsKind::Instance, &instance_id, &query) {
+        Ok(points) => Json(Response::Success(Success::List(points))),
+        Err(e) => Json(Response::Failure { reason: Some(format!("Unable to read metrics history: {e}")) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_db() -> Database {
+        let path = std::env::temp_dir().join(format!("metrics-history-{}.redb", uuid::Uuid::new_v4()));
+        Database::create(path).unwrap()
+    }
+
+    fn cpu(value: f64) -> BTreeMap<String, f64> {
+        BTreeMap::from([("cpu_usage_pct".to_string(), value)])
+    }
+
+    #[test]
+    fn test_samples_are_downsampled() {
+        let db = temp_db();
+        let base = 1_700_000_000 - 1_700_000_000 % 3600;
+        for (offset, value) in [(0, 10.0), (30, 30.0), (90, 50.0)] {
+            record_sample(&db, MetricsKind::Instance, "vm", base + offset, &cpu(value)).unwrap();
+        }
+
+        let query = |resolution| HistoryQuery {
+            resolution: Some(resolution),
+            from: Some(base),
+            to: Some(base + 3599),
+            ..Default::default()
+        };
+
+        let raw = read_range(&db, MetricsKind::Instance, "vm", &query(Resolution::Raw)).unwrap();
+        assert_eq!(raw.len(), 3);
+
+        let minutes = read_range(&db, MetricsKind::Instance, "vm", &query(Resolution::Minute)).unwrap();
+        assert_eq!(minutes.len(), 2);
+        assert_eq!(minutes[0].samples, 2);
+        assert_eq!(minutes[0].values["cpu_usage_pct"], MetricStat { min: 10.0, max: 30.0, avg: 20.0 });
+
+        let hours = read_range(&db, MetricsKind::Instance, "vm", &query(Resolution::Hour)).unwrap();
+        assert_eq!(hours.len(), 1);
+        assert_eq!(hours[0].samples, 3);
+        assert_eq!(hours[0].values["cpu_usage_pct"].avg, 30.0);
+    }
+
+    #[test]
+    fn test_stale_samples_are_skipped() {
+        let db = temp_db();
+        let reported_at = 1_700_000_000;
+        assert!(record_sample(&db, MetricsKind::Node, "node", reported_at, &cpu(1.0)).unwrap());
+        assert!(!record_sample(&db, MetricsKind::Node, "node", reported_at, &cpu(1.0)).unwrap());
+        assert!(!record_sample(&db, MetricsKind::Node, "node", reported_at - 60, &cpu(1.0)).unwrap());
+        assert!(record_sample(&db, MetricsKind::Node, "node", reported_at + 60, &cpu(2.0)).unwrap());
+
+        let hours = read_range(&db, MetricsKind::Node, "node", &HistoryQuery {
+            resolution: Some(Resolution::Hour),
+            from: Some(reported_at - 3600),
+            to: Some(reported_at + 3600),
+            ..Default::default()
+        }).unwrap();
+        assert_eq!(hours.iter().map(|point| point.samples).sum::<u32>(), 2);
+    }
+
+    #[test]
+    fn test_expired_points_are_pruned() {
+        let db = temp_db();
+        let start = 1_700_000_000;
+        record_sample(&db, MetricsKind::Node, "node", start, &cpu(1.0)).unwrap();
+        let later = start + Resolution::Raw.retention_secs() + 60;
+        record_sample(&db, MetricsKind::Node, "node", later, &cpu(2.0)).unwrap();
+
+        let query = HistoryQuery { from: Some(start), to: Some(later), ..Default::default() };
+        let raw = read_range(&db, MetricsKind::Node, "node", &query).unwrap();
+        assert_eq!(raw.len(), 1);
+        assert_eq!(raw[0].timestamp, later);
+
+        let minutes = read_range(&db, MetricsKind::Node, "node", &HistoryQuery {
+            resolution: Some(Resolution::Minute),
+            ..query
+        }).unwrap();
+        assert_eq!(minutes.len(), 2);
+    }
+
+    #[test]
+    fn test_prune_expired_sweeps_every_series() {
+        let db = temp_db();
+        let start = 1_700_000_000;
+        record_sample(&db, MetricsKind::Node, "gone", start, &cpu(1.0)).unwrap();
+        record_sample(&db, MetricsKind::Instance, "vm", start + 3600, &cpu(1.0)).unwrap();
+
+        // Nothing is old enough to expire yet
+        assert_eq!(prune_expired(&db, start + 60).unwrap(), 0);
+
+        // The node's raw point expires first, while the instance's is kept
+        let now = start + Resolution::Raw.retention_secs() + 60;
+        assert_eq!(prune_expired(&db, now).unwrap(), 1);
+        let query = |resolution| HistoryQuery {
+            resolution: Some(resolution),
+            from: Some(start - 3600),
+            to: Some(now),
+            ..Default::default()
+        };
+        assert!(read_range(&db, MetricsKind::Node, "gone", &query(Resolution::Raw)).unwrap().is_empty());
+        assert_eq!(read_range(&db, MetricsKind::Node, "gone", &query(Resolution::Minute)).unwrap().len(), 1);
+        assert_eq!(read_range(&db, MetricsKind::Instance, "vm", &query(Resolution::Raw)).unwrap().len(), 1);
+
+        // Past the longest retention the node's history is gone entirely
+        let now = start + Resolution::Hour.retention_secs() + 3600;
+        prune_expired(&db, now).unwrap();
+        for resolution in Resolution::ALL {
+            let query = HistoryQuery { from: Some(start - 3600), to: Some(now), ..query(resolution) };
+            assert!(read_range(&db, MetricsKind::Node, "gone", &query).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,170 @@
+//! Moving an instance's state to the node it was live migrated to.
+//!
+//! Instance ids are derived from the hosting node, so a migrated instance
+//! gets a new id and record. `plan_migration` works out every change a
+//! migration makes: the new record, the cluster membership held by every
+//! replica, and the A record of the build's domain. Nothing is applied until
+//! the whole plan has been built, so a migration that cannot be recorded
+//! leaves the old state untouched.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::{extract::State, Json};
+use form_dns::store::FormDnsRecord;
+use form_types::state::{Response, Success};
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+use crate::datastore::DataStore;
+use crate::instances::{Instance, InstanceStatus};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct InstanceMigration {
+    /// Id of the instance on the source node
+    pub instance_id: String,
+    pub destination_node_id: String,
+    /// Id of the instance on the destination node
+    pub destination_instance_id: String,
+    pub node_public_ip: IpAddr,
+    pub node_formnet_ip: IpAddr,
+}
+
+/// Every change a migration makes, built before any of them is applied
+#[derive(Clone, Debug)]
+pub struct MigrationPlan {
+    /// The migrated instance, under its new id
+    pub migrated: Instance,
+    /// The other replicas of the build, with their cluster updated
+    pub replicas: Vec<Instance>,
+    /// Record of the instance on the source node, to be removed
+    pub removed_instance_id: String,
+    /// The build's domain record, pointing at the destination node
+    pub dns_record: Option<FormDnsRecord>,
+}
+
+pub fn plan_migration(datastore: &DataStore, migration: &InstanceMigration) -> Result<MigrationPlan, String> {
+    let source = datastore.instance_state.get_instance(migration.instance_id.clone())
+        .ok_or_else(|| format!("Instance {} does not exist", migration.instance_id))?;
+    if source.node_id == migration.destination_node_id {
+        return Err(format!("Instance {} already runs on {}", source.instance_id, source.node_id));
+    }
+    let destination = datastore.node_state.get_node(migration.destination_node_id.clone())
+        .ok_or_else(|| format!("Destination node {} does not exist", migration.destination_node_id))?;
+
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+
+    let move_member = |instance: &mut Instance| {
+        if let Some(mut member) = instance.cluster.remove(&migration.instance_id) {
+            member.instance_id = migration.destination_instance_id.clone();
+            member.node_id = migration.destination_node_id.clone();
+            member.node_public_ip = migration.node_public_ip;
+            member.node_formnet_ip = migration.node_formnet_ip;
+            member.last_heartbeat = now;
+            instance.cluster.insert(member);
+        }
+        instance.updated_at = now;
+    };
+
+    let mut migrated = source.clone();
+    migrated.instance_id = migration.destination_instance_id.clone();
+    migrated.node_id = migration.destination_node_id.clone();
+    migrated.host_region = destination.host_region.clone();
+    migrated.status = InstanceStatus::Started;
+    move_member(&mut migrated);
+
+    let mut replicas: Vec<Instance> = datastore.instance_state
+        .get_instances_by_build_id(source.build_id.clone())
+        .into_iter()
+        .filter(|replica| replica.instance_id != source.instance_id && replica.instance_id != migrated.instance_id)
+        .collect();
+    replicas.iter_mut().for_each(|replica| move_member(replica));
+
+    // The source node only leaves the A record if no other replica still
+    // runs there
+    let source_ips: Vec<IpAddr> = source.cluster.members.get(&source.instance_id)
+        .map(|member| member.node_public_ip)
+        .into_iter()
+        .chain(datastore.node_state.get_node(source.node_id.clone())
+            .and_then(|node| node.host.to_string().parse().ok()))
+        .collect();
+    let source_still_hosts = replicas.iter().any(|replica| replica.node_id == source.node_id);
+
+    let dns_record = source.dns_record.as_ref().map(|record| {
+        let mut record = datastore.network_state.dns_state.zones.get(&record.domain).val
+            .and_then(|reg| reg.val().map(|current| FormDnsRecord::from(current.value())))
+            .unwrap_or_else(|| record.clone());
+        record.public_ip = move_public_ip(&record.public_ip, &source_ips, migration.node_public_ip, source_still_hosts);
+        record
+    });
+
+    if let Some(record) = &dns_record {
+        migrated.dns_record = Some(record.clone());
+        replicas.iter_mut().for_each(|replica| replica.dns_record = Some(record.clone()));
+    }
+
+    Ok(MigrationPlan {
+        migrated,
+        replicas,
+        removed_instance_id: source.instance_id,
+        dns_record,
+    })
+}
+
+/// Points the addresses of a record that belong to the source node at the
+/// destination node, keeping their ports
+fn move_public_ip(public_ip: &[SocketAddr], source_ips: &[IpAddr], destination: IpAddr, keep_source: bool) -> Vec<SocketAddr> {
+    let port = public_ip.iter()
+        .find(|addr| source_ips.contains(&addr.ip()))
+        .or(public_ip.first())
+        .map(|addr| addr.port())
+        .unwrap_or(80);
+
+    let mut moved: Vec<SocketAddr> = public_ip.iter()
+        .filter(|addr| keep_source || !source_ips.contains(&addr.ip()))
+        .cloned()
+        .collect();
+    let destination = SocketAddr::new(destination, port);
+    if !moved.contains(&destination) {
+        moved.push(destination);
+    }
+    moved
+}
+
+pub async fn migrate_instance(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Json(migration): Json<InstanceMigration>,
+) -> Json<Response<Instance>> {
+    let mut datastore = state.lock().await;
+    match datastore.handle_instance_migrate(migration).await {
+        Ok(instance) => Json(Response::Success(Success::Some(instance))),
+        Err(e) => Json(Response::Failure { reason: Some(e.to_string()) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_move_public_ip() {
+        let source: IpAddr = "203.0.113.1".parse().unwrap();
+        let destination: IpAddr = "203.0.113.9".parse().unwrap();
+        let record = vec![addr("203.0.113.1:443"), addr("198.51.100.4:443")];
+
+        assert_eq!(
+            move_public_ip(&record, &[source], destination, false),
+            vec![addr("198.51.100.4:443"), addr("203.0.113.9:443")]
+        );
+        assert_eq!(
+            move_public_ip(&record, &[source], destination, true),
+            vec![addr("203.0.113.1:443"), addr("198.51.100.4:443"), addr("203.0.113.9:443")]
+        );
+        assert_eq!(move_public_ip(&[], &[source], destination, false), vec![addr("203.0.113.9:80")]);
+    }
+}
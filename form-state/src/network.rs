@@ -6,13 +6,16 @@ use shared::{Association, AssociationContents, Cidr, CidrContents, Endpoint, Pee
 use serde::{Serialize, Deserialize};
 use tiny_keccak::{Hasher, Sha3};
 use trust_dns_proto::rr::RecordType;
-use form_dns::store::FormDnsRecord;
+use form_dns::dnssec::{Denial, DnssecZone, ZoneKey};
+use form_dns::routing::RoutingPolicy;
+use form_dns::store::{CaaRecord, FormDnsRecord, MxRecord, SrvRecord};
 use crate::Actor;
 
 pub type PeerOp<T> = Op<String, BFTReg<CrdtPeer<T>, Actor>, Actor>; 
 pub type CidrOp<T> = Op<String, BFTReg<CrdtCidr<T>, Actor>, Actor>;
 pub type AssocOp<T> = Op<String, BFTReg<CrdtAssociation<T>, Actor>, Actor>;
 pub type DnsOp = Op<String, BFTReg<CrdtDnsRecord, Actor>, Actor>;
+pub type DnssecOp = Op<String, BFTReg<CrdtDnssecZone, Actor>, Actor>;
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub struct CrdtPeer<T: Clone> {
@@ -227,6 +230,16 @@ pub struct CrdtDnsRecord {
     pub(crate) cname_target: Option<String>,
     pub(crate) ttl: u32,
     pub(crate) ssl_cert: bool,
+    #[serde(default)]
+    pub(crate) txt: Vec<String>,
+    #[serde(default)]
+    pub(crate) mx: Vec<MxRecord>,
+    #[serde(default)]
+    pub(crate) srv: Vec<SrvRecord>,
+    #[serde(default)]
+    pub(crate) caa: Vec<CaaRecord>,
+    #[serde(default)]
+    pub(crate) routing: RoutingPolicy,
 }
 
 impl CrdtDnsRecord {
@@ -258,6 +271,26 @@ impl CrdtDnsRecord {
         self.ssl_cert
     }
 
+    pub fn txt(&self) -> Vec<String> {
+        self.txt.clone()
+    }
+
+    pub fn mx(&self) -> Vec<MxRecord> {
+        self.mx.clone()
+    }
+
+    pub fn srv(&self) -> Vec<SrvRecord> {
+        self.srv.clone()
+    }
+
+    pub fn caa(&self) -> Vec<CaaRecord> {
+        self.caa.clone()
+    }
+
+    pub fn routing(&self) -> RoutingPolicy {
+        self.routing.clone()
+    }
+
 }
 
 impl From<FormDnsRecord> for CrdtDnsRecord {
@@ -269,7 +302,12 @@ impl From<FormDnsRecord> for CrdtDnsRecord {
             public_ip: value.public_ip, 
             cname_target: value.cname_target, 
             ttl: value.ttl,
-            ssl_cert: value.ssl_cert
+            ssl_cert: value.ssl_cert,
+            txt: value.txt,
+            mx: value.mx,
+            srv: value.srv,
+            caa: value.caa,
+            routing: value.routing,
         }
     }
 }
@@ -285,7 +323,12 @@ impl From<CrdtDnsRecord> for FormDnsRecord {
             ttl: value.ttl,
             ssl_cert: value.ssl_cert,
             verification_status: None,
-            verification_timestamp: None
+            verification_timestamp: None,
+            txt: value.txt,
+            mx: value.mx,
+            srv: value.srv,
+            caa: value.caa,
+            routing: value.routing,
         }
     }
 }
@@ -301,32 +344,112 @@ impl From<&CrdtDnsRecord> for FormDnsRecord {
             ttl: value.ttl,
             ssl_cert: value.ssl_cert,
             verification_status: None,
-            verification_timestamp: None
+            verification_timestamp: None,
+            txt: value.txt.clone(),
+            mx: value.mx.clone(),
+            srv: value.srv.clone(),
+            caa: value.caa.clone(),
+            routing: value.routing.clone(),
         }
     }
 }
 
 impl Sha3Hash for CrdtDnsRecord {
     fn hash(&self, hasher: &mut Sha3) {
-        hasher.update(&serde_json::to_vec(self).unwrap())
+        // Records that don't use fields added since layout 0 hash the way
+        // peers still running it do
+        match self.legacy() {
+            Some(legacy) => legacy.hash(hasher),
+            None => hasher.update(&serde_json::to_vec(self).unwrap()),
+        }
+    }
+}
+
+/// DNSSEC keys of a zone signed by form-dns
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CrdtDnssecZone {
+    pub(crate) zone: String,
+    pub(crate) keys: Vec<ZoneKey>,
+    pub(crate) denial: Denial,
+    pub(crate) nameservers: Vec<String>,
+    pub(crate) updated: u64,
+}
+
+impl CrdtDnssecZone {
+    pub fn zone(&self) -> String {
+        self.zone.clone()
+    }
+
+    pub fn keys(&self) -> Vec<ZoneKey> {
+        self.keys.clone()
+    }
+
+    pub fn denial(&self) -> Denial {
+        self.denial.clone()
+    }
+
+    pub fn nameservers(&self) -> Vec<String> {
+        self.nameservers.clone()
+    }
+
+    pub fn updated(&self) -> u64 {
+        self.updated
+    }
+}
+
+impl From<DnssecZone> for CrdtDnssecZone {
+    fn from(value: DnssecZone) -> Self {
+        CrdtDnssecZone {
+            zone: value.zone,
+            keys: value.keys,
+            denial: value.denial,
+            nameservers: value.nameservers,
+            updated: value.updated,
+        }
+    }
+}
+
+impl From<CrdtDnssecZone> for DnssecZone {
+    fn from(value: CrdtDnssecZone) -> Self {
+        DnssecZone {
+            zone: value.zone,
+            keys: value.keys,
+            denial: value.denial,
+            nameservers: value.nameservers,
+            updated: value.updated,
+        }
+    }
+}
+
+impl Sha3Hash for CrdtDnssecZone {
+    fn hash(&self, hasher: &mut Sha3) {
+        hasher.update(&bincode::serialize(self).unwrap())
     }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct DnsState {
-    pub zones: Map<String, BFTReg<CrdtDnsRecord, Actor>, Actor>
+    pub zones: Map<String, BFTReg<CrdtDnsRecord, Actor>, Actor>,
+    /// DNSSEC keys of signed zones, keyed by zone apex
+    #[serde(default)]
+    pub dnssec: Map<String, BFTReg<CrdtDnssecZone, Actor>, Actor>,
 }
 
 impl DnsState {
     pub fn new() -> Self {
         Self {
-            zones: Map::new()
+            zones: Map::new(),
+            dnssec: Map::new(),
         }
     }
 
     pub fn apply(&mut self, op: DnsOp) {
         self.zones.apply(op);
     }
+
+    pub fn apply_dnssec(&mut self, op: DnssecOp) {
+        self.dnssec.apply(op);
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -570,7 +693,14 @@ impl NetworkState {
     }
 
     pub fn dns_op(&mut self, op: DnsOp) {
-        self.dns_state.apply(op);
+        self.dns_state.apply(op.clone());
+        crate::watch::dns_changed(&op, |domain| self.get_dns_record(domain));
+    }
+
+    pub fn get_dns_record(&self, domain: &str) -> Option<FormDnsRecord> {
+        self.dns_state.zones.get(&domain.to_string()).val
+            .and_then(|reg| reg.val().map(|v| v.value()))
+            .map(FormDnsRecord::from)
     }
 
     pub fn dns_op_success(&self, domain: String, update: Update<CrdtDnsRecord, String>) -> (bool, CrdtDnsRecord) {
@@ -601,6 +731,50 @@ impl NetworkState {
         }
     }
 
+    pub fn update_dnssec_local(&mut self, zone: DnssecZone) -> DnssecOp {
+        log::info!("Acquiring add ctx...");
+        let add_ctx = self.dns_state.dnssec.read_ctx().derive_add_ctx(self.node_id.clone());
+        log::info!("Decoding our private key...");
+        let signing_key = SigningKey::from_slice(
+            &hex::decode(self.pk.clone())
+                .expect("PANIC: Invalid SigningKey Cannot Decode from Hex"))
+                .expect("PANIC: Invalid SigningKey cannot recover ffrom Bytes");
+        log::info!("Creating op...");
+        let op = self.dns_state.dnssec.update(zone.zone.clone(), add_ctx, |reg, _ctx| {
+            let op = reg.update(zone.into(), self.node_id.clone(), signing_key).expect("PANIC: Unable to sign updates");
+            op
+        });
+        log::info!("Op created, returning...");
+        op
+    }
+
+    pub fn remove_dnssec_local(&mut self, zone: String) -> DnssecOp {
+        log::info!("Acquiring remove context...");
+        let rm_ctx = self.dns_state.dnssec.read_ctx().derive_rm_ctx();
+        log::info!("Building Rm Op...");
+        self.dns_state.dnssec.rm(zone, rm_ctx)
+    }
+
+    pub fn dnssec_op(&mut self, op: DnssecOp) {
+        self.dns_state.apply_dnssec(op);
+    }
+
+    pub fn dnssec_op_success(&self, zone: String, update: Update<CrdtDnssecZone, String>) -> (bool, CrdtDnssecZone) {
+        if let Some(reg) = self.dns_state.dnssec.get(&zone).val {
+            if let Some(v) = reg.val() {
+                // The update either won, is a concurrent head or is waiting
+                // on a missing child, as for DNS records
+                if v.value() == update.op().value
+                    || (reg.dag_contains(&update.hash()) && reg.is_head(&update.hash()))
+                    || reg.is_orphaned(&update.hash()) {
+                    return (true, v.value())
+                }
+                return (false, v.value())
+            }
+        }
+        (false, update.op().value)
+    }
+
     fn handle_cached_peer_ops(&mut self, ops: Vec<PeerOp<String>>) {
         for op in ops {
             self.peer_op(op);
@@ -715,4 +889,16 @@ mod tests {
         assert!(peer.is_admin());
         Ok(())
     }
+
+    #[test]
+    fn test_dnssec_zone_bincode_roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        // DNSSEC zones are persisted and hashed with bincode, which can't
+        // skip fields or decode self-describing formats
+        for denial in [Denial::Nsec, Denial::Nsec3 { iterations: 0, salt: "ab".to_string() }] {
+            let zone: CrdtDnssecZone = DnssecZone::new("example.com", denial, vec!["ns1.example.com".to_string()], 1_000)?.into();
+            let decoded: CrdtDnssecZone = bincode::deserialize(&bincode::serialize(&zone)?)?;
+            assert_eq!(decoded, zone);
+        }
+        Ok(())
+    }
 }
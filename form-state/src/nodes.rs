@@ -229,6 +229,12 @@ impl NodeState {
                 let mut node = node_val.value();
                 node.capacity = node_capacity;
                 node.metrics = node_metrics;
+                // Marks when the metrics were reported, so samplers can tell
+                // a fresh report from one they have already recorded
+                node.updated_at = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.as_secs() as i64)
+                    .unwrap_or(0);
                 return Some(self.update_node_local(node))
             }
         }
@@ -260,6 +266,7 @@ impl NodeState {
     pub fn node_op(&mut self, op: NodeOp) -> Option<(String, String)> {
         log::info!("Applying peer node op");
         self.map.apply(op.clone());
+        crate::watch::node_changed(&op, |key| self.get_node(key.clone()));
         match op {
             Op::Up { dot, key, op: _ } => Some((dot.actor, key)),
             Op::Rm { .. } => None,
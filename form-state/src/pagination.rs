@@ -0,0 +1,555 @@
+//! Cursor pagination, filtering and sorting for list endpoints.
+//!
+//! Every list endpoint accepts the same `ListQuery` parameters. Without
+//! `limit` or `cursor` the endpoint keeps returning `Success::List` with every
+//! matching record, so existing callers are unaffected. With either of them
+//! it returns `Success::Some(Page)`, whose `next_cursor` is passed back as
+//! `cursor` to fetch the following page.
+//!
+//! Cursors encode the sort key and id of the last record returned rather than
+//! an offset, so records created or deleted between requests do not cause a
+//! page to skip or repeat records.
+
+use std::cmp::Ordering;
+use axum::{response::{IntoResponse, Response as AxumResponse}, Json};
+use form_types::state::{Response, Success};
+use form_dns::store::{FormDnsRecord, VerificationStatus};
+use serde::{Serialize, Deserialize};
+use shared::Peer;
+use crate::accounts::Account;
+use crate::agent::AIAgent;
+use crate::instances::Instance;
+use crate::model::AIModel;
+use crate::nodes::Node;
+
+/// Page size used when a cursor is given without a limit
+pub const DEFAULT_PAGE_SIZE: usize = 100;
+
+/// Largest page a single request can ask for
+pub const MAX_PAGE_SIZE: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// Query parameters shared by every list endpoint
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ListQuery {
+    /// Maximum number of records to return
+    pub limit: Option<usize>,
+    /// `next_cursor` of the previous page
+    pub cursor: Option<String>,
+    /// Field to sort by, defaults to the record id
+    pub sort: Option<String>,
+    pub order: Option<SortOrder>,
+    pub owner: Option<String>,
+    pub status: Option<String>,
+    pub region: Option<String>,
+    /// Comma separated; a record must have every tag to match
+    pub tags: Option<String>,
+    pub build_id: Option<String>,
+}
+
+/// One page of a list endpoint
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Cursor for the next page, `None` on the last page
+    pub next_cursor: Option<String>,
+    /// Number of records matching the filters, across all pages
+    pub total: usize,
+}
+
+/// A value records can be sorted by
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum SortKey {
+    Int(i64),
+    Text(String),
+}
+
+impl From<i64> for SortKey {
+    fn from(value: i64) -> Self {
+        SortKey::Int(value)
+    }
+}
+
+impl From<String> for SortKey {
+    fn from(value: String) -> Self {
+        SortKey::Text(value)
+    }
+}
+
+impl From<&str> for SortKey {
+    fn from(value: &str) -> Self {
+        SortKey::Text(value.to_string())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CursorPosition {
+    sort: Option<String>,
+    key: SortKey,
+    id: String,
+}
+
+/// A record that can be returned from a paginated list endpoint.
+///
+/// Filter accessors return `None` when the record type has no such field;
+/// filtering on an unsupported field is rejected rather than matching nothing.
+pub trait Listable {
+    /// Unique, stable id, used as the default sort key and as a tie breaker
+    fn list_id(&self) -> String;
+
+    /// Fields accepted by `sort`, in addition to `id`
+    fn sort_fields() -> &'static [&'static str];
+
+    /// The value of a field listed in `sort_fields`
+    fn sort_key(&self, field: &str) -> Option<SortKey>;
+
+    fn owner(&self) -> Option<String> { None }
+    fn status(&self) -> Option<String> { None }
+    fn region(&self) -> Option<String> { None }
+    fn tags(&self) -> Option<Vec<String>> { None }
+    fn build_id(&self) -> Option<String> { None }
+}
+
+impl ListQuery {
+    /// Whether the caller asked for a page rather than the full list
+    pub fn is_paginated(&self) -> bool {
+        self.limit.is_some() || self.cursor.is_some()
+    }
+
+    fn tag_list(&self) -> Vec<String> {
+        self.tags.iter()
+            .flat_map(|tags| tags.split(','))
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect()
+    }
+
+    /// Filters, sorts and pages `items`.
+    ///
+    /// # Returns
+    ///
+    /// The requested page, or an error describing an unsupported filter,
+    /// sort field or an invalid cursor.
+    pub fn apply<T: Listable>(&self, items: Vec<T>) -> Result<Page<T>, String> {
+        let sort = self.sort.clone().filter(|field| field != "id");
+        if let Some(field) = &sort {
+            if !T::sort_fields().contains(&field.as_str()) {
+                return Err(format!(
+                    "Cannot sort by {field}, supported fields are: id, {}",
+                    T::sort_fields().join(", ")
+                ));
+            }
+        }
+
+        let tags = self.tag_list();
+        let mut filtered = Vec::new();
+        for item in items {
+            if self.matches(&item, &tags)? {
+                filtered.push(item);
+            }
+        }
+
+        let order = self.order.unwrap_or_default();
+        let mut keyed: Vec<(SortKey, String, T)> = filtered.into_iter().map(|item| {
+            let id = item.list_id();
+            let key = match &sort {
+                Some(field) => item.sort_key(field).unwrap_or(SortKey::Text(String::new())),
+                None => SortKey::Text(id.clone()),
+            };
+            (key, id, item)
+        }).collect();
+        keyed.sort_by(|a, b| compare(order, (&a.0, &a.1), (&b.0, &b.1)));
+
+        let total = keyed.len();
+        if let Some(cursor) = &self.cursor {
+            let position = decode_cursor(cursor)?;
+            if position.sort != sort {
+                return Err("Cursor was issued for a different sort field".to_string());
+            }
+            keyed.retain(|(key, id, _)| {
+                compare(order, (key, id), (&position.key, &position.id)) == Ordering::Greater
+            });
+        }
+
+        let limit = match (self.limit, self.cursor.is_some()) {
+            (Some(limit), _) => limit.clamp(1, MAX_PAGE_SIZE),
+            (None, true) => DEFAULT_PAGE_SIZE,
+            (None, false) => usize::MAX,
+        };
+
+        let next_cursor = if keyed.len() > limit {
+            let (key, id, _) = &keyed[limit - 1];
+            Some(encode_cursor(&CursorPosition { sort: sort.clone(), key: key.clone(), id: id.clone() }))
+        } else {
+            None
+        };
+
+        let items = keyed.into_iter().take(limit).map(|(_, _, item)| item).collect();
+        Ok(Page { items, next_cursor, total })
+    }
+
+    fn matches<T: Listable>(&self, item: &T, tags: &[String]) -> Result<bool, String> {
+        fn check(filter: &Option<String>, name: &str, value: Option<String>) -> Result<bool, String> {
+            match filter {
+                None => Ok(true),
+                Some(wanted) => match value {
+                    Some(value) => Ok(value.eq_ignore_ascii_case(wanted)),
+                    None => Err(format!("Filtering by {name} is not supported for this resource")),
+                },
+            }
+        }
+
+        // Every filter is checked so an unsupported one is reported even if
+        // an earlier one already rejected the record
+        let checks = [
+            check(&self.owner, "owner", item.owner())?,
+            check(&self.status, "status", item.status())?,
+            check(&self.region, "region", item.region())?,
+            check(&self.build_id, "build_id", item.build_id())?,
+        ];
+        let matched = checks.iter().all(|matched| *matched);
+
+        if !matched || tags.is_empty() {
+            return Ok(matched);
+        }
+
+        match item.tags() {
+            Some(item_tags) => Ok(tags.iter().all(|tag| item_tags.iter().any(|t| t.eq_ignore_ascii_case(tag)))),
+            None => Err("Filtering by tags is not supported for this resource".to_string()),
+        }
+    }
+
+    /// Applies the query and builds the endpoint's response: the full list
+    /// when the caller did not ask for a page, otherwise the page.
+    pub fn respond<T: Listable + Serialize>(&self, items: Vec<T>) -> AxumResponse {
+        match self.apply(items) {
+            Ok(page) if self.is_paginated() => Json(Response::Success(Success::Some(page))).into_response(),
+            Ok(page) => Json(Response::Success(Success::List(page.items))).into_response(),
+            Err(reason) => Json(Response::<T>::Failure { reason: Some(reason) }).into_response(),
+        }
+    }
+}
+
+fn compare(order: SortOrder, a: (&SortKey, &String), b: (&SortKey, &String)) -> Ordering {
+    let ordering = a.cmp(&b);
+    match order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    }
+}
+
+fn encode_cursor(position: &CursorPosition) -> String {
+    hex::encode(serde_json::to_vec(position).unwrap_or_default())
+}
+
+fn decode_cursor(cursor: &str) -> Result<CursorPosition, String> {
+    hex::decode(cursor).ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .ok_or_else(|| "Invalid cursor".to_string())
+}
+
+impl Listable for Instance {
+    fn list_id(&self) -> String {
+        self.instance_id.clone()
+    }
+
+    fn sort_fields() -> &'static [&'static str] {
+        &["created_at", "updated_at", "status", "build_id", "node_id"]
+    }
+
+    fn sort_key(&self, field: &str) -> Option<SortKey> {
+        match field {
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            "status" => Some(format!("{:?}", self.status).into()),
+            "build_id" => Some(self.build_id.as_str().into()),
+            "node_id" => Some(self.node_id.as_str().into()),
+            _ => None,
+        }
+    }
+
+    fn owner(&self) -> Option<String> {
+        Some(self.instance_owner.clone())
+    }
+
+    fn status(&self) -> Option<String> {
+        Some(format!("{:?}", self.status))
+    }
+
+    fn region(&self) -> Option<String> {
+        Some(self.host_region.clone())
+    }
+
+    fn tags(&self) -> Option<Vec<String>> {
+        Some(self.metadata.tags())
+    }
+
+    fn build_id(&self) -> Option<String> {
+        Some(self.build_id.clone())
+    }
+}
+
+impl Listable for Node {
+    fn list_id(&self) -> String {
+        self.node_id.clone()
+    }
+
+    fn sort_fields() -> &'static [&'static str] {
+        &["created_at", "updated_at", "last_heartbeat", "region"]
+    }
+
+    fn sort_key(&self, field: &str) -> Option<SortKey> {
+        match field {
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            "last_heartbeat" => Some(self.last_heartbeat.into()),
+            "region" => Some(self.host_region.as_str().into()),
+            _ => None,
+        }
+    }
+
+    fn owner(&self) -> Option<String> {
+        Some(self.node_owner.clone())
+    }
+
+    fn region(&self) -> Option<String> {
+        Some(self.host_region.clone())
+    }
+
+    fn tags(&self) -> Option<Vec<String>> {
+        Some(self.metadata.tags())
+    }
+}
+
+impl Listable for Account {
+    fn list_id(&self) -> String {
+        self.address.clone()
+    }
+
+    fn sort_fields() -> &'static [&'static str] {
+        &["created_at", "updated_at", "credits"]
+    }
+
+    fn sort_key(&self, field: &str) -> Option<SortKey> {
+        match field {
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            "credits" => Some((self.credits as i64).into()),
+            _ => None,
+        }
+    }
+
+    fn owner(&self) -> Option<String> {
+        Some(self.address.clone())
+    }
+}
+
+impl Listable for AIAgent {
+    fn list_id(&self) -> String {
+        self.agent_id.clone()
+    }
+
+    fn sort_fields() -> &'static [&'static str] {
+        &["name", "created_at", "updated_at"]
+    }
+
+    fn sort_key(&self, field: &str) -> Option<SortKey> {
+        match field {
+            "name" => Some(self.name.as_str().into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+
+    fn owner(&self) -> Option<String> {
+        Some(self.owner_id.clone())
+    }
+
+    fn tags(&self) -> Option<Vec<String>> {
+        Some(self.tags.clone())
+    }
+}
+
+impl Listable for AIModel {
+    fn list_id(&self) -> String {
+        self.model_id.clone()
+    }
+
+    fn sort_fields() -> &'static [&'static str] {
+        &["name", "created_at", "updated_at"]
+    }
+
+    fn sort_key(&self, field: &str) -> Option<SortKey> {
+        match field {
+            "name" => Some(self.name.as_str().into()),
+            "created_at" => Some(self.created_at.into()),
+            "updated_at" => Some(self.updated_at.into()),
+            _ => None,
+        }
+    }
+
+    fn owner(&self) -> Option<String> {
+        Some(self.owner_id.clone())
+    }
+
+    fn tags(&self) -> Option<Vec<String>> {
+        Some(self.tags.clone())
+    }
+}
+
+impl Listable for FormDnsRecord {
+    fn list_id(&self) -> String {
+        self.domain.clone()
+    }
+
+    fn sort_fields() -> &'static [&'static str] {
+        &["record_type", "ttl"]
+    }
+
+    fn sort_key(&self, field: &str) -> Option<SortKey> {
+        match field {
+            "record_type" => Some(self.record_type.to_string().into()),
+            "ttl" => Some((self.ttl as i64).into()),
+            _ => None,
+        }
+    }
+
+    /// The domain's verification status: `verified`, `pending`, `failed`
+    /// or `unverified`
+    fn status(&self) -> Option<String> {
+        let status = match &self.verification_status {
+            Some(VerificationStatus::Verified) => "verified",
+            Some(VerificationStatus::Pending) => "pending",
+            Some(VerificationStatus::Failed(_)) => "failed",
+            Some(VerificationStatus::NotVerified) | None => "unverified",
+        };
+        Some(status.to_string())
+    }
+}
+
+impl Listable for Peer<String> {
+    fn list_id(&self) -> String {
+        self.id.clone()
+    }
+
+    fn sort_fields() -> &'static [&'static str] {
+        &["name", "ip", "cidr"]
+    }
+
+    fn sort_key(&self, field: &str) -> Option<SortKey> {
+        match field {
+            "name" => Some(self.name.to_string().into()),
+            "ip" => Some(self.ip.to_string().into()),
+            "cidr" => Some(self.cidr_id.as_str().into()),
+            _ => None,
+        }
+    }
+
+    /// `disabled`, `active` once the invite has been redeemed, otherwise `invited`
+    fn status(&self) -> Option<String> {
+        let status = if self.is_disabled {
+            "disabled"
+        } else if self.is_redeemed {
+            "active"
+        } else {
+            "invited"
+        };
+        Some(status.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Record {
+        id: String,
+        owner: String,
+        created_at: i64,
+        tags: Vec<String>,
+    }
+
+    impl Listable for Record {
+        fn list_id(&self) -> String {
+            self.id.clone()
+        }
+
+        fn sort_fields() -> &'static [&'static str] {
+            &["created_at"]
+        }
+
+        fn sort_key(&self, field: &str) -> Option<SortKey> {
+            match field {
+                "created_at" => Some(self.created_at.into()),
+                _ => None,
+            }
+        }
+
+        fn owner(&self) -> Option<String> {
+            Some(self.owner.clone())
+        }
+
+        fn tags(&self) -> Option<Vec<String>> {
+            Some(self.tags.clone())
+        }
+    }
+
+    fn records() -> Vec<Record> {
+        (0..10).map(|i| Record {
+            id: format!("record-{i}"),
+            owner: if i % 2 == 0 { "alice".to_string() } else { "bob".to_string() },
+            created_at: 100 - i,
+            tags: if i < 3 { vec!["gpu".to_string(), "eu".to_string()] } else { vec!["eu".to_string()] },
+        }).collect()
+    }
+
+    #[test]
+    fn test_cursor_pagination_walks_every_record_once() {
+        let mut query = ListQuery {
+            limit: Some(3),
+            sort: Some("created_at".to_string()),
+            order: Some(SortOrder::Desc),
+            ..Default::default()
+        };
+
+        let mut seen = Vec::new();
+        loop {
+            let page = query.apply(records()).unwrap();
+            assert_eq!(page.total, 10);
+            seen.extend(page.items.into_iter().map(|r| r.created_at));
+            match page.next_cursor {
+                Some(cursor) => query.cursor = Some(cursor),
+                None => break,
+            }
+        }
+
+        assert_eq!(seen, (91..=100).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_filters() {
+        let query = ListQuery {
+            owner: Some("Alice".to_string()),
+            tags: Some("gpu, eu".to_string()),
+            ..Default::default()
+        };
+        let page = query.apply(records()).unwrap();
+        let ids: Vec<String> = page.items.into_iter().map(|r| r.id).collect();
+        assert_eq!(ids, vec!["record-0".to_string(), "record-2".to_string()]);
+
+        let unsupported = ListQuery { region: Some("us-east".to_string()), ..Default::default() };
+        assert!(unsupported.apply(records()).is_err());
+
+        let bad_sort = ListQuery { sort: Some("name".to_string()), ..Default::default() };
+        assert!(bad_sort.apply(records()).is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! Placement constraints declared in a Formfile.
+//!
+//! The Formfile `REGION`, `NODE_LABEL`, `TEE`, `ANTI_AFFINITY` and
+//! `REPLICAS` directives are parsed into a `Placement`, which travels with
+//! the Formfile. The `CapabilityMatcher` uses it to pick nodes, and
+//! form-state checks it again before accepting a new instance, so a
+//! workload bound to a jurisdiction cannot end up outside it even if the
+//! instance is created by hand.
+
+use serde::{Serialize, Deserialize};
+use crate::datastore::DataStore;
+use crate::instances::Instance;
+use crate::nodes::Node;
+
+/// Trusted execution environment a workload requires
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TeeRequirement {
+    /// Any of SEV, SGX or a TPM
+    Any,
+    Sev,
+    Sgx,
+    Tpm,
+}
+
+impl std::str::FromStr for TeeRequirement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "any" => Ok(TeeRequirement::Any),
+            "sev" => Ok(TeeRequirement::Sev),
+            "sgx" => Ok(TeeRequirement::Sgx),
+            "tpm" => Ok(TeeRequirement::Tpm),
+            other => Err(format!("Unknown TEE {other}, expected one of any, sev, sgx, tpm")),
+        }
+    }
+}
+
+/// How far apart replicas of a workload must be
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AntiAffinityScope {
+    /// At most one replica per node
+    #[default]
+    Node,
+    /// At most one replica per region
+    Region,
+}
+
+impl std::str::FromStr for AntiAffinityScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "node" => Ok(AntiAffinityScope::Node),
+            "region" => Ok(AntiAffinityScope::Region),
+            other => Err(format!("Unknown anti-affinity scope {other}, expected node or region")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Placement {
+    /// Replicas may only run in these regions
+    pub required_regions: Vec<String>,
+    /// Replicas run in these regions when a capable node is available
+    pub preferred_regions: Vec<String>,
+    /// Node tags a node must carry, either `key` or `key=value`
+    pub required_labels: Vec<String>,
+    /// Node tags that make a node more attractive
+    pub preferred_labels: Vec<String>,
+    pub tee: Option<TeeRequirement>,
+    pub anti_affinity: AntiAffinityScope,
+    /// Number of replicas to run
+    pub replicas: Option<u32>,
+}
+
+impl Placement {
+    /// Reads the placement from a Formfile serialized as JSON. Formfiles
+    /// written before placement directives existed have no constraints.
+    pub fn from_formfile(formfile: &str) -> Self {
+        #[derive(Deserialize)]
+        struct WithPlacement {
+            #[serde(default)]
+            placement: Placement,
+        }
+
+        serde_json::from_str::<WithPlacement>(formfile)
+            .map(|f| f.placement)
+            .unwrap_or_default()
+    }
+
+    /// Number of nodes the workload is placed on, one unless the Formfile
+    /// sets `REPLICAS`
+    pub fn replica_count(&self) -> usize {
+        self.replicas.unwrap_or(1) as usize
+    }
+
+    /// Checks the hard constraints that depend only on the node itself
+    pub fn admits(&self, node: &Node) -> Result<(), String> {
+        if !self.required_regions.is_empty() && !self.required_regions.contains(&node.host_region) {
+            return Err(format!(
+                "Node is in region {}, but workload is restricted to {}",
+                node.host_region, self.required_regions.join(", ")
+            ));
+        }
+
+        let tags = node.metadata.tags();
+        for label in &self.required_labels {
+            if !tags.contains(label) {
+                return Err(format!("Node does not have required label {label}"));
+            }
+        }
+
+        if let Some(tee) = self.tee {
+            let capabilities = &node.capabilities;
+            let sev = capabilities.sev.as_ref().map_or(false, |sev| sev.supported);
+            let sgx = capabilities.sgx.as_ref().map_or(false, |sgx| sgx.supported && sgx.driver_loaded);
+            let tpm = capabilities.tpm.as_ref().map_or(false, |tpm| tpm.present);
+            let satisfied = match tee {
+                TeeRequirement::Any => sev || sgx || tpm,
+                TeeRequirement::Sev => sev,
+                TeeRequirement::Sgx => sgx,
+                TeeRequirement::Tpm => tpm,
+            };
+            if !satisfied {
+                return Err(format!("Node does not provide required TEE {tee:?}"));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks a node against the replicas already running, given as the
+    /// regions of the nodes hosting them
+    pub fn admits_replica(&self, node: &Node, replica_regions: &[String]) -> Result<(), String> {
+        if let Some(replicas) = self.replicas {
+            if replica_regions.len() as u32 >= replicas {
+                return Err(format!("Workload already has its {replicas} replicas"));
+            }
+        }
+
+        if self.anti_affinity == AntiAffinityScope::Region && replica_regions.contains(&node.host_region) {
+            return Err(format!("A replica already runs in region {}", node.host_region));
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks a new instance against the placement in its Formfile. Updates to
+/// instances that already exist are not checked again, so tightening a
+/// Formfile does not strand running replicas.
+pub fn check_new_instance(datastore: &DataStore, instance: &Instance) -> Result<(), String> {
+    if datastore.instance_state.get_instance(instance.instance_id.clone()).is_some() {
+        return Ok(());
+    }
+
+    let placement = Placement::from_formfile(&instance.formfile);
+    if placement == Placement::default() {
+        return Ok(());
+    }
+
+    let node = datastore.node_state.get_node(instance.node_id.clone())
+        .ok_or_else(|| format!("Instance is assigned to unknown node {}", instance.node_id))?;
+    placement.admits(&node)?;
+
+    let replica_regions: Vec<String> = datastore.instance_state
+        .get_instances_by_build_id(instance.build_id.clone())
+        .iter()
+        .filter(|replica| replica.instance_id != instance.instance_id)
+        .map(|replica| {
+            datastore.node_state.get_node(replica.node_id.clone())
+                .map(|node| node.host_region)
+                .unwrap_or_default()
+        })
+        .collect();
+    placement.admits_replica(&node, &replica_regions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(region: &str) -> Node {
+        Node { host_region: region.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_placement_constraints() {
+        let placement = Placement {
+            required_regions: vec!["eu-west".to_string()],
+            anti_affinity: AntiAffinityScope::Region,
+            replicas: Some(2),
+            ..Default::default()
+        };
+
+        assert!(placement.admits(&node("eu-west")).is_ok());
+        assert!(placement.admits(&node("us-east")).is_err());
+
+        assert!(placement.admits_replica(&node("eu-west"), &[]).is_ok());
+        assert!(placement.admits_replica(&node("eu-west"), &["eu-west".to_string()]).is_err());
+        assert!(placement.admits_replica(&node("eu-north"), &["eu-west".to_string(), "eu-south".to_string()]).is_err());
+
+        let tee = Placement { tee: Some(TeeRequirement::Sev), ..Default::default() };
+        assert!(tee.admits(&node("eu-west")).is_err());
+    }
+
+    #[test]
+    fn test_placement_from_formfile() {
+        assert_eq!(Placement::from_formfile(r#"{"name":"app"}"#), Placement::default());
+        let placement = Placement::from_formfile(r#"{"name":"app","placement":{"required_regions":["eu-west"],"preferred_regions":[],"required_labels":[],"preferred_labels":[],"tee":"Sgx","anti_affinity":"Node","replicas":3}}"#);
+        assert_eq!(placement.required_regions, vec!["eu-west".to_string()]);
+        assert_eq!(placement.tee, Some(TeeRequirement::Sgx));
+        assert_eq!(placement.replicas, Some(3));
+        assert_eq!(placement.replica_count(), 3);
+        assert_eq!(Placement::default().replica_count(), 1);
+    }
+}
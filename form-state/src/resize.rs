@@ -0,0 +1,211 @@
+//! Recording online resizes of running instances.
+//!
+//! vmm-service records a resize here before it hot-plugs vCPUs or memory
+//! into the VM, and records the old size again if the hot-plug fails. The
+//! resize is charged to the instance owner's compute quota: growing an
+//! instance is refused if the owner's instances would hold more vCPUs or
+//! memory than their subscription tier allows. Shrinking is always allowed,
+//! so an owner who is already over quota can still get back under it.
+//!
+//! Disks hot-plugged into an instance are recorded the same way and count
+//! against the owner's storage quota until they are unplugged again.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use axum::{extract::State, Json};
+use form_types::state::{Response, Success};
+use serde::{Serialize, Deserialize};
+use tokio::sync::Mutex;
+use crate::billing::{SubscriptionQuota, SubscriptionTier};
+use crate::datastore::DataStore;
+use crate::instances::{Instance, InstanceDisk, InstanceResources, InstanceStatus};
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct InstanceResize {
+    pub instance_id: String,
+    /// New vCPU count, `None` keeps the current one
+    pub vcpus: Option<u8>,
+    /// New memory size in MiB, `None` keeps the current one
+    pub memory_mb: Option<u32>,
+    /// Disk hot-plugged into the instance
+    #[serde(default)]
+    pub attach_disk: Option<InstanceDisk>,
+    /// Device id of a disk unplugged from the instance
+    #[serde(default)]
+    pub detach_disk: Option<String>,
+}
+
+/// vCPUs, memory and hot-plugged disk held by an owner's instances
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ComputeUsage {
+    pub vcpus: u32,
+    pub memory_mb: u64,
+    pub disk_gb: u64,
+}
+
+impl ComputeUsage {
+    pub fn of(instance: &Instance) -> Self {
+        let mut usage = Self::default();
+        usage.add(instance);
+        usage
+    }
+
+    fn add(&mut self, instance: &Instance) {
+        let resources: &InstanceResources = &instance.resources;
+        self.vcpus += resources.vcpus as u32;
+        self.memory_mb += resources.memory_mb as u64;
+        self.disk_gb += instance.disk_gb();
+    }
+}
+
+/// Sums the resources of every live instance owned by `owner`, leaving out
+/// `excluding`
+pub fn owner_usage(datastore: &DataStore, owner: &str, excluding: &str) -> ComputeUsage {
+    let mut usage = ComputeUsage::default();
+    for ctx in datastore.instance_state.map().iter() {
+        let (_, reg) = ctx.val;
+        if let Some(val) = reg.val() {
+            let instance = val.value();
+            if instance.instance_owner == owner
+                && instance.instance_id != excluding
+                && !matches!(instance.status, InstanceStatus::Killed) {
+                usage.add(&instance);
+            }
+        }
+    }
+    usage
+}
+
+/// Checks that resizing an instance from `current` to `resized` keeps its
+/// owner within `quota`, given what the owner's other instances hold
+pub fn check_quota(
+    quota: &SubscriptionQuota,
+    others: ComputeUsage,
+    current: &ComputeUsage,
+    resized: &ComputeUsage,
+) -> Result<(), String> {
+    let total = ComputeUsage {
+        vcpus: others.vcpus + resized.vcpus,
+        memory_mb: others.memory_mb + resized.memory_mb,
+        disk_gb: others.disk_gb + resized.disk_gb,
+    };
+
+    if resized.vcpus > current.vcpus && total.vcpus > quota.max_vcpus {
+        return Err(format!(
+            "Resize needs {} vCPUs across the owner's instances, quota allows {}",
+            total.vcpus, quota.max_vcpus
+        ));
+    }
+
+    if resized.memory_mb > current.memory_mb && total.memory_mb > quota.max_memory_mb {
+        return Err(format!(
+            "Resize needs {} MiB of memory across the owner's instances, quota allows {}",
+            total.memory_mb, quota.max_memory_mb
+        ));
+    }
+
+    if resized.disk_gb > current.disk_gb && total.disk_gb > quota.max_storage_gb {
+        return Err(format!(
+            "Resize needs {} GB of disk across the owner's instances, quota allows {}",
+            total.disk_gb, quota.max_storage_gb
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn plan_resize(datastore: &DataStore, resize: &InstanceResize) -> Result<Instance, String> {
+    let mut instance = datastore.instance_state.get_instance(resize.instance_id.clone())
+        .ok_or_else(|| format!("Instance {} does not exist", resize.instance_id))?;
+
+    let current = ComputeUsage::of(&instance);
+    if let Some(vcpus) = resize.vcpus {
+        if vcpus == 0 {
+            return Err("An instance needs at least one vCPU".to_string());
+        }
+        instance.resources.vcpus = vcpus;
+    }
+    if let Some(memory_mb) = resize.memory_mb {
+        if memory_mb == 0 {
+            return Err("An instance needs some memory".to_string());
+        }
+        instance.resources.memory_mb = memory_mb;
+    }
+    if let Some(disk_id) = &resize.detach_disk {
+        instance.disks.retain(|disk| &disk.disk_id != disk_id);
+    }
+    if let Some(disk) = &resize.attach_disk {
+        if instance.disks.iter().any(|attached| attached.disk_id == disk.disk_id || attached.path == disk.path) {
+            return Err(format!("Disk {} is already attached", disk.disk_id));
+        }
+        instance.disks.push(disk.clone());
+    }
+
+    // Owners without a subscription get the free tier
+    let quota = datastore.account_state.get_account(&instance.instance_owner)
+        .and_then(|account| account.subscription)
+        .map(|subscription| subscription.quota())
+        .unwrap_or_else(|| SubscriptionTier::Free.quota());
+    let others = owner_usage(datastore, &instance.instance_owner, &instance.instance_id);
+    check_quota(&quota, others, &current, &ComputeUsage::of(&instance))?;
+
+    instance.updated_at = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default();
+    Ok(instance)
+}
+
+pub async fn resize_instance(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    Json(resize): Json<InstanceResize>,
+) -> Json<Response<Instance>> {
+    let mut datastore = state.lock().await;
+    match datastore.handle_instance_resize(resize).await {
+        Ok(instance) => Json(Response::Success(Success::Some(instance))),
+        Err(e) => Json(Response::Failure { reason: Some(e.to_string()) }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(vcpus: u32, memory_mb: u64, disk_gb: u64) -> ComputeUsage {
+        ComputeUsage { vcpus, memory_mb, disk_gb }
+    }
+
+    #[test]
+    fn test_check_quota() {
+        let quota = SubscriptionTier::Free.quota();
+        let others = usage(1, 1024, 10);
+        let current = usage(1, 1024, 0);
+
+        assert!(check_quota(&quota, others, &current, &usage(1, 3072, 0)).is_ok());
+        assert!(check_quota(&quota, others, &current, &usage(2, 1024, 0)).is_err());
+        assert!(check_quota(&quota, others, &current, &usage(1, 4096, 0)).is_err());
+        assert!(check_quota(&quota, others, &current, &usage(1, 1024, 10)).is_ok());
+        assert!(check_quota(&quota, others, &current, &usage(1, 1024, 11)).is_err());
+    }
+
+    #[test]
+    fn test_check_quota_allows_shrinking_over_quota() {
+        let quota = SubscriptionTier::Free.quota();
+        let others = usage(4, 8192, 40);
+        let current = usage(4, 8192, 40);
+
+        assert!(check_quota(&quota, others, &current, &usage(2, 4096, 20)).is_ok());
+        assert!(check_quota(&quota, others, &current, &usage(2, 16384, 20)).is_err());
+    }
+
+    #[test]
+    fn test_usage_counts_attached_disks() {
+        let mut instance = Instance::default();
+        instance.resources = InstanceResources { vcpus: 2, memory_mb: 2048, ..Default::default() };
+        instance.disks = vec![
+            InstanceDisk { disk_id: "data".to_string(), path: "data.raw".to_string(), size_gb: 10, readonly: false },
+            InstanceDisk { disk_id: "logs".to_string(), path: "logs.raw".to_string(), size_gb: 5, readonly: false },
+        ];
+        assert_eq!(ComputeUsage::of(&instance), usage(2, 2048, 15));
+        assert!(instance.legacy().is_none());
+    }
+}
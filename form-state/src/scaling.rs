@@ -2,6 +2,11 @@ use serde::{Deserialize, Serialize};
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use std::collections::BTreeMap;
 
+/// Number of operations kept in a manager's history, older ones are dropped
+/// as new ones start. The manager is stored with every instance of a
+/// cluster and gossiped with it, so its history has to stay bounded.
+pub const MAX_OPERATION_HISTORY: usize = 16;
+
 /// Types of scaling operations that can be performed
 #[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum ScalingOperation {
@@ -206,11 +211,9 @@ pub struct ScalingOperationRecord {
     /// Serialized backup of cluster state before operation start
     pub initial_cluster_state: Option<String>,
     /// Maps phase names to serialized cluster state backups for that phase
-    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(default)]
     pub phase_cluster_states: BTreeMap<String, String>,
     /// Additional metadata about the operation (for analysis and debugging)
-    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(default)]
     pub metadata: BTreeMap<String, String>,
 }
@@ -316,7 +319,6 @@ pub struct ScalingManager {
     /// History of past scaling operations
     pub operation_history: Vec<ScalingOperationRecord>,
     /// Maximum allowed duration for each phase in seconds
-    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
     #[serde(default)]
     phase_timeouts: BTreeMap<String, u64>,
     /// Last check timestamp (used for timeout detection)
@@ -391,6 +393,63 @@ impl ScalingManager {
         &self.operation_history
     }
     
+    /// Returns the record of the most recent operation, if any
+    pub fn latest_operation(&self) -> Option<&ScalingOperationRecord> {
+        self.operation_history.last()
+    }
+    
+    /// Records the outcome of the current phase in the active operation's history
+    ///
+    /// The record is named after the current phase and stores the phase data
+    /// needed to roll back work done in it, so it should be written before the
+    /// operation is failed or moved on to the next phase.
+    ///
+    /// # Arguments
+    ///
+    /// * `phase_data` - Data produced by the phase (for rollback and auditing)
+    /// * `error` - The error the phase ended with, if it failed
+    ///
+    /// # Returns
+    ///
+    /// * `Ok(())` if the record was added
+    /// * `Err(ScalingError)` if there is no active operation
+    pub fn record_phase(&mut self, phase_data: PhaseData, error: Option<ScalingError>) -> Result<(), ScalingError> {
+        let current_phase = match &self.current_phase {
+            Some(phase) if !phase.is_terminal() => phase,
+            _ => return Err(ScalingError {
+                error_type: "NoActiveOperation".to_string(),
+                message: "No active operation to record a phase for".to_string(),
+                phase: "None".to_string(),
+            }),
+        };
+        
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or(Duration::from_secs(0))
+            .as_secs() as i64;
+        
+        let record = PhaseRecord {
+            phase_name: current_phase.phase_name().to_string(),
+            started_at: current_phase.start_time().unwrap_or(now),
+            ended_at: Some(now),
+            successful: Some(error.is_none()),
+            error,
+            phase_data,
+        };
+        
+        match self.operation_history.last_mut() {
+            Some(operation) => {
+                operation.phase_history.push(record);
+                Ok(())
+            }
+            None => Err(ScalingError {
+                error_type: "NoActiveOperation".to_string(),
+                message: "Active operation has no history record".to_string(),
+                phase: current_phase.phase_name().to_string(),
+            }),
+        }
+    }
+    
     /// Sets the timeout for a specific phase
     pub fn set_phase_timeout(&mut self, phase: &str, timeout_seconds: u64) {
         self.phase_timeouts.insert(phase.to_string(), timeout_seconds);
@@ -447,6 +506,8 @@ impl ScalingManager {
             phase_cluster_states: BTreeMap::new(),
             metadata: BTreeMap::new(),
         });
+        let excess = self.operation_history.len().saturating_sub(MAX_OPERATION_HISTORY);
+        self.operation_history.drain(..excess);
         
         // Set the current phase
         self.current_phase = Some(requested_phase);
@@ -1490,6 +1551,26 @@ mod tests {
         assert!(manager.operation_history()[0].error.is_none());
     }
     
+    #[test]
+    fn test_operation_history_is_capped() {
+        let mut manager = ScalingManager::new();
+        for target_instances in 1..=(MAX_OPERATION_HISTORY as u32 + 3) {
+            manager.start_operation(ScalingOperation::ScaleOut { target_instances }).unwrap();
+            manager.cancel_operation("Testing history").unwrap();
+        }
+
+        // The oldest operations were dropped
+        assert_eq!(manager.operation_history().len(), MAX_OPERATION_HISTORY);
+        assert_eq!(
+            manager.operation_history()[0].operation,
+            ScalingOperation::ScaleOut { target_instances: 4 },
+        );
+        assert_eq!(
+            manager.latest_operation().unwrap().operation,
+            ScalingOperation::ScaleOut { target_instances: MAX_OPERATION_HISTORY as u32 + 3 },
+        );
+    }
+    
     #[test]
     fn test_timeout_detection() {
         // Create a manager with very short timeouts for testing
@@ -1525,6 +1606,45 @@ mod tests {
         assert_eq!(manager.operation_history()[0].error.as_ref().unwrap().error_type, "Timeout");
     }
 
+    #[test]
+    fn test_record_phase() {
+        let mut manager = ScalingManager::new();
+        
+        // Nothing to record against before an operation starts
+        assert!(manager.record_phase(PhaseData::Validating {}, None).is_err());
+        
+        manager.start_operation(ScalingOperation::ScaleOut { target_instances: 3 }).unwrap();
+        manager.transition_to_validating().unwrap();
+        assert!(manager.record_phase(PhaseData::Validating {}, None).is_ok());
+        manager.transition_to_planning(None).unwrap();
+        manager.transition_to_resource_allocating(None).unwrap();
+        
+        let error = ScalingError {
+            error_type: "InsufficientNodes".to_string(),
+            message: "No capable nodes".to_string(),
+            phase: "ResourceAllocating".to_string(),
+        };
+        let phase_data = PhaseData::ResourceAllocating {
+            resources: None,
+            allocated_resource_ids: vec!["instance-1".to_string()],
+        };
+        assert!(manager.record_phase(phase_data, Some(error.clone())).is_ok());
+        manager.fail_operation(&error.error_type, &error.message, None).unwrap();
+        
+        let record = manager.latest_operation().unwrap();
+        assert_eq!(record.phase_history.len(), 2);
+        assert_eq!(record.phase_history[0].phase_name, "Validating");
+        assert_eq!(record.phase_history[0].successful, Some(true));
+        assert_eq!(record.phase_history[1].phase_name, "ResourceAllocating");
+        assert_eq!(record.phase_history[1].successful, Some(false));
+        assert_eq!(record.phase_history[1].error, Some(error));
+        
+        // The recorded phase data drives rollback
+        manager.rollback_operation().unwrap();
+        let record = manager.latest_operation().unwrap();
+        assert_eq!(record.metadata.get("resources_to_release").unwrap(), "instance-1");
+    }
+
     #[test]
     fn test_rollback_operation() {
         // Create a manager
@@ -0,0 +1,390 @@
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use axum::{body::Bytes, extract::{Path as AxumPath, State}, http::{header, StatusCode}, response::{IntoResponse, Response as AxumResponse}, Json};
+use form_types::state::{Response, Success};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tokio::sync::Mutex;
+
+use crate::datastore::{DataStore, MergeableState, DB_HANDLE};
+use crate::db::write_datastore;
+
+/// Identifies a file as a form-state snapshot.
+pub const SNAPSHOT_MAGIC: &str = "formation-state-snapshot";
+
+/// Bumped whenever the layout of the snapshot payload changes.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+/// Directory where snapshots created through the admin API are written.
+pub const SNAPSHOT_DIR: &str = "/var/lib/formation/snapshots";
+
+/// Largest snapshot accepted by the upload restore endpoint.
+pub const MAX_SNAPSHOT_UPLOAD_BYTES: usize = 1 << 30;
+
+const SNAPSHOT_EXTENSION: &str = "snap";
+
+#[derive(Debug, thiserror::Error)]
+pub enum SnapshotError {
+    #[error("Snapshot is missing its header")]
+    MissingHeader,
+    #[error("Not a form-state snapshot")]
+    InvalidMagic,
+    #[error("Unsupported snapshot version {0}")]
+    UnsupportedVersion(u32),
+    #[error("Snapshot checksum mismatch: header says {expected}, payload hashes to {actual}")]
+    ChecksumMismatch { expected: String, actual: String },
+    #[error("Snapshot payload is {actual} bytes, header says {expected}")]
+    SizeMismatch { expected: u64, actual: u64 },
+    #[error("Invalid snapshot name: {0}")]
+    InvalidName(String),
+    #[error("Serialization error: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// Per-map entry counts recorded in the snapshot header, so a snapshot can be
+/// inspected without parsing its payload.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotCounts {
+    pub peers: usize,
+    pub cidrs: usize,
+    pub assocs: usize,
+    pub dns: usize,
+    #[serde(default)]
+    pub dnssec: usize,
+    pub instances: usize,
+    pub nodes: usize,
+    pub accounts: usize,
+    pub agents: usize,
+    pub models: usize,
+}
+
+impl From<&DataStore> for SnapshotCounts {
+    fn from(value: &DataStore) -> Self {
+        Self {
+            peers: value.network_state.peers.len().val,
+            cidrs: value.network_state.cidrs.len().val,
+            assocs: value.network_state.associations.len().val,
+            dns: value.network_state.dns_state.zones.len().val,
+            dnssec: value.network_state.dns_state.dnssec.len().val,
+            instances: value.instance_state.map.len().val,
+            nodes: value.node_state.map.len().val,
+            accounts: value.account_state.map.len().val,
+            agents: value.agent_state.map.len().val,
+            models: value.model_state.map.len().val,
+        }
+    }
+}
+
+/// The first line of a snapshot file.
+///
+/// A snapshot is this header serialized as a single line of JSON, a newline,
+/// and then the JSON encoded `MergeableState`. The checksum covers exactly the
+/// payload bytes, so it can be verified before anything is deserialized.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SnapshotHeader {
+    pub magic: String,
+    pub version: u32,
+    pub created_at: i64,
+    pub node_id: String,
+    pub checksum: String,
+    pub size: u64,
+    pub counts: SnapshotCounts,
+}
+
+/// Header of a snapshot stored on disk, together with its file name.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SnapshotMetadata {
+    pub name: String,
+    pub header: SnapshotHeader,
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub header: SnapshotHeader,
+    payload: Vec<u8>,
+}
+
+impl Snapshot {
+    /// Captures the network, instance, node, account, agent, model and DNS
+    /// maps of the datastore.
+    pub fn capture(datastore: &DataStore) -> Result<Self, SnapshotError> {
+        let counts = SnapshotCounts::from(datastore);
+        let node_id = datastore.node_state.node_id.clone();
+        let state: MergeableState = datastore.clone().into();
+        let payload = serde_json::to_vec(&state)?;
+
+        Ok(Self {
+            header: SnapshotHeader {
+                magic: SNAPSHOT_MAGIC.to_string(),
+                version: SNAPSHOT_VERSION,
+                created_at: chrono::Utc::now().timestamp(),
+                node_id,
+                checksum: checksum(&payload),
+                size: payload.len() as u64,
+                counts,
+            },
+            payload,
+        })
+    }
+
+    /// Parses a snapshot and verifies its header and checksum. The payload is
+    /// not deserialized until `state` is called.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, SnapshotError> {
+        let split = bytes.iter().position(|b| *b == b'\n').ok_or(SnapshotError::MissingHeader)?;
+        let header: SnapshotHeader = serde_json::from_slice(&bytes[..split])?;
+        let payload = bytes[split + 1..].to_vec();
+
+        if header.magic != SNAPSHOT_MAGIC {
+            return Err(SnapshotError::InvalidMagic);
+        }
+
+        if header.version > SNAPSHOT_VERSION {
+            return Err(SnapshotError::UnsupportedVersion(header.version));
+        }
+
+        if header.size != payload.len() as u64 {
+            return Err(SnapshotError::SizeMismatch { expected: header.size, actual: payload.len() as u64 });
+        }
+
+        let actual = checksum(&payload);
+        if actual != header.checksum {
+            return Err(SnapshotError::ChecksumMismatch { expected: header.checksum.clone(), actual });
+        }
+
+        Ok(Self { header, payload })
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, SnapshotError> {
+        let mut bytes = serde_json::to_vec(&self.header)?;
+        bytes.push(b'\n');
+        bytes.extend_from_slice(&self.payload);
+        Ok(bytes)
+    }
+
+    pub fn state(&self) -> Result<MergeableState, SnapshotError> {
+        Ok(serde_json::from_slice(&self.payload)?)
+    }
+
+    pub fn read_from(path: impl AsRef<Path>) -> Result<Self, SnapshotError> {
+        let bytes = std::fs::read(path)?;
+        Self::from_bytes(&bytes)
+    }
+
+    /// Writes the snapshot to `path` through a temporary file, so a crash
+    /// mid-write never leaves a truncated snapshot behind.
+    pub fn write_to(&self, path: impl AsRef<Path>) -> Result<(), SnapshotError> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, self.to_bytes()?)?;
+        std::fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("state-{}-{}.{}", self.header.created_at, &self.header.checksum[..12], SNAPSHOT_EXTENSION)
+    }
+}
+
+fn checksum(payload: &[u8]) -> String {
+    hex::encode(Sha256::digest(payload))
+}
+
+/// Resolves a snapshot name to a path inside `SNAPSHOT_DIR`, rejecting
+/// anything that could escape the directory.
+fn snapshot_path(name: &str) -> Result<PathBuf, SnapshotError> {
+    if name.is_empty() || name.contains('/') || name.contains('\\') || name.starts_with('.') {
+        return Err(SnapshotError::InvalidName(name.to_string()));
+    }
+    Ok(PathBuf::from(SNAPSHOT_DIR).join(name))
+}
+
+/// Merges the snapshot into the datastore and rewrites the local database.
+///
+/// Restoring is a CRDT merge, so restoring into a node that already holds
+/// newer state never rolls that state back.
+pub fn restore_snapshot(datastore: &mut DataStore, snapshot: &Snapshot) -> Result<(), Box<dyn std::error::Error>> {
+    let state = snapshot.state()?;
+    datastore.merge_state(state);
+    write_datastore(&DB_HANDLE, datastore)?;
+    log::info!(
+        "Restored snapshot taken by {} at {} ({})",
+        snapshot.header.node_id,
+        snapshot.header.created_at,
+        snapshot.header.checksum
+    );
+    Ok(())
+}
+
+pub fn list_snapshot_files() -> Result<Vec<SnapshotMetadata>, SnapshotError> {
+    let dir = PathBuf::from(SNAPSHOT_DIR);
+    if !dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut list = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(SNAPSHOT_EXTENSION) {
+            continue;
+        }
+        match read_header(&path) {
+            Ok(header) => list.push(SnapshotMetadata {
+                name: path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default(),
+                header,
+            }),
+            Err(e) => log::warn!("Skipping unreadable snapshot {}: {e}", path.display()),
+        }
+    }
+    list.sort_by_key(|m| std::cmp::Reverse(m.header.created_at));
+
+    Ok(list)
+}
+
+/// Reads only the header line of a snapshot file.
+fn read_header(path: &Path) -> Result<SnapshotHeader, SnapshotError> {
+    use std::io::BufRead;
+    let file = std::fs::File::open(path)?;
+    let mut line = String::new();
+    std::io::BufReader::new(file).read_line(&mut line)?;
+    if line.is_empty() {
+        return Err(SnapshotError::MissingHeader);
+    }
+    Ok(serde_json::from_str(line.trim_end())?)
+}
+
+pub async fn create_snapshot(
+    State(state): State<Arc<Mutex<DataStore>>>,
+) -> Json<Response<SnapshotMetadata>> {
+    log::info!("Received snapshot create request...");
+    let snapshot = {
+        let datastore = state.lock().await;
+        Snapshot::capture(&datastore)
+    };
+
+    let snapshot = match snapshot {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Json(Response::Failure { reason: Some(format!("Unable to capture snapshot: {e}")) }),
+    };
+
+    let name = snapshot.file_name();
+    let path = PathBuf::from(SNAPSHOT_DIR).join(&name);
+    if let Err(e) = snapshot.write_to(&path) {
+        return Json(Response::Failure { reason: Some(format!("Unable to write snapshot: {e}")) });
+    }
+
+    log::info!("Wrote snapshot {}", path.display());
+    Json(Response::Success(Success::Some(SnapshotMetadata { name, header: snapshot.header })))
+}
+
+pub async fn list_snapshots() -> Json<Response<SnapshotMetadata>> {
+    match list_snapshot_files() {
+        Ok(list) => Json(Response::Success(Success::List(list))),
+        Err(e) => Json(Response::Failure { reason: Some(format!("Unable to list snapshots: {e}")) }),
+    }
+}
+
+pub async fn download_snapshot(
+    AxumPath(name): AxumPath<String>,
+) -> AxumResponse {
+    let path = match snapshot_path(&name) {
+        Ok(path) => path,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => (
+            [
+                (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+                (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{name}\"")),
+            ],
+            bytes,
+        ).into_response(),
+        Err(e) => (StatusCode::NOT_FOUND, format!("Unable to read snapshot {name}: {e}")).into_response(),
+    }
+}
+
+/// Restores from a snapshot uploaded as the raw request body.
+pub async fn restore_uploaded_snapshot(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    body: Bytes,
+) -> Json<Response<SnapshotMetadata>> {
+    let snapshot = match Snapshot::from_bytes(&body) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Json(Response::Failure { reason: Some(format!("Invalid snapshot: {e}")) }),
+    };
+
+    restore(state, "upload".to_string(), snapshot).await
+}
+
+/// Restores from a snapshot previously written to `SNAPSHOT_DIR`.
+pub async fn restore_stored_snapshot(
+    State(state): State<Arc<Mutex<DataStore>>>,
+    AxumPath(name): AxumPath<String>,
+) -> Json<Response<SnapshotMetadata>> {
+    let snapshot = match snapshot_path(&name).and_then(Snapshot::read_from) {
+        Ok(snapshot) => snapshot,
+        Err(e) => return Json(Response::Failure { reason: Some(format!("Invalid snapshot {name}: {e}")) }),
+    };
+
+    restore(state, name, snapshot).await
+}
+
+async fn restore(state: Arc<Mutex<DataStore>>, name: String, snapshot: Snapshot) -> Json<Response<SnapshotMetadata>> {
+    let mut datastore = state.lock().await;
+    if let Err(e) = restore_snapshot(&mut datastore, &snapshot) {
+        return Json(Response::Failure { reason: Some(format!("Unable to restore snapshot: {e}")) });
+    }
+
+    Json(Response::Success(Success::Some(SnapshotMetadata { name, header: snapshot.header })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot() -> Snapshot {
+        let datastore = DataStore::new("node1".to_string(), hex::encode([1u8; 32]));
+        Snapshot::capture(&datastore).expect("Unable to capture snapshot")
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let snapshot = snapshot();
+        let bytes = snapshot.to_bytes()?;
+        let parsed = Snapshot::from_bytes(&bytes)?;
+        assert_eq!(parsed.header, snapshot.header);
+        assert_eq!(parsed.header.node_id, "node1");
+        let _state = parsed.state()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_rejects_corrupt_payload() -> Result<(), Box<dyn std::error::Error>> {
+        let mut bytes = snapshot().to_bytes()?;
+        let last = bytes.len() - 2;
+        bytes[last] ^= 0x01;
+        assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::ChecksumMismatch { .. })));
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_rejects_newer_version() -> Result<(), Box<dyn std::error::Error>> {
+        let mut snapshot = snapshot();
+        snapshot.header.version = SNAPSHOT_VERSION + 1;
+        let bytes = snapshot.to_bytes()?;
+        assert!(matches!(Snapshot::from_bytes(&bytes), Err(SnapshotError::UnsupportedVersion(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_snapshot_path_rejects_traversal() {
+        assert!(snapshot_path("../form.db").is_err());
+        assert!(snapshot_path("a/b.snap").is_err());
+        assert!(snapshot_path("state-1-abc.snap").is_ok());
+    }
+}
@@ -0,0 +1,407 @@
+//! Change feed for instances, nodes, DNS records and accounts.
+//!
+//! Every op applied to one of these maps, whether it was created locally or
+//! received from a peer, publishes a `ChangeEvent` carrying the record as it
+//! stands after the op. Events are numbered with a version that increases by
+//! one per event and kept in a bounded in-memory buffer, so a client that
+//! reconnects with the cursor of the last event it saw receives everything it
+//! missed before switching to live events.
+//!
+//! Cursors are `{feed_id}:{version}`. The feed id changes whenever form-state
+//! restarts; a cursor from a previous run, or one older than the buffer, is
+//! rejected and the client has to resync through the list endpoints.
+
+use std::collections::{BTreeSet, VecDeque};
+use std::convert::Infallible;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use axum::extract::Query;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{sse::{Event, KeepAlive, Sse}, IntoResponse, Response};
+use axum::Json;
+use crdts::map::Op;
+use form_dns::store::FormDnsRecord;
+use futures::stream::{self, StreamExt};
+use lazy_static::lazy_static;
+use serde::{Serialize, Deserialize};
+use tokio::sync::broadcast;
+use crate::accounts::Account;
+use crate::instances::Instance;
+use crate::nodes::Node;
+
+/// Events kept for clients resuming from a cursor
+pub const CHANGE_BUFFER_SIZE: usize = 4096;
+
+/// How often an idle stream sends a keep-alive comment
+const KEEP_ALIVE_SECS: u64 = 15;
+
+lazy_static! {
+    pub static ref CHANGE_FEED: Mutex<ChangeFeed> = Mutex::new(ChangeFeed::new(CHANGE_BUFFER_SIZE));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ResourceKind {
+    Instance,
+    Node,
+    Dns,
+    Account,
+}
+
+impl std::str::FromStr for ResourceKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "instance" | "instances" => Ok(ResourceKind::Instance),
+            "node" | "nodes" => Ok(ResourceKind::Node),
+            "dns" => Ok(ResourceKind::Dns),
+            "account" | "accounts" => Ok(ResourceKind::Account),
+            other => Err(format!("Unknown resource {other}, expected one of instance, node, dns, account")),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum WatchEvent {
+    InstanceUpdated { instance: Instance },
+    InstanceDeleted { instance_id: String },
+    NodeUpdated { node: Node },
+    NodeDeleted { node_id: String },
+    DnsRecordUpdated { record: FormDnsRecord },
+    DnsRecordDeleted { domain: String },
+    AccountUpdated { account: Account },
+    AccountDeleted { address: String },
+}
+
+impl WatchEvent {
+    pub fn resource(&self) -> ResourceKind {
+        match self {
+            WatchEvent::InstanceUpdated { .. } | WatchEvent::InstanceDeleted { .. } => ResourceKind::Instance,
+            WatchEvent::NodeUpdated { .. } | WatchEvent::NodeDeleted { .. } => ResourceKind::Node,
+            WatchEvent::DnsRecordUpdated { .. } | WatchEvent::DnsRecordDeleted { .. } => ResourceKind::Dns,
+            WatchEvent::AccountUpdated { .. } | WatchEvent::AccountDeleted { .. } => ResourceKind::Account,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            WatchEvent::InstanceUpdated { .. } => "instance_updated",
+            WatchEvent::InstanceDeleted { .. } => "instance_deleted",
+            WatchEvent::NodeUpdated { .. } => "node_updated",
+            WatchEvent::NodeDeleted { .. } => "node_deleted",
+            WatchEvent::DnsRecordUpdated { .. } => "dns_record_updated",
+            WatchEvent::DnsRecordDeleted { .. } => "dns_record_deleted",
+            WatchEvent::AccountUpdated { .. } => "account_updated",
+            WatchEvent::AccountDeleted { .. } => "account_deleted",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// Cursor to resume from after this event
+    pub cursor: String,
+    pub version: u64,
+    pub timestamp: i64,
+    #[serde(flatten)]
+    pub event: WatchEvent,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum WatchError {
+    /// The cursor is malformed
+    InvalidCursor,
+    /// The cursor is from a previous run or older than the buffer
+    CursorExpired,
+}
+
+impl std::fmt::Display for WatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatchError::InvalidCursor => write!(f, "Invalid cursor"),
+            WatchError::CursorExpired => write!(f, "Cursor has expired, resync with the list endpoints and watch without a cursor"),
+        }
+    }
+}
+
+pub struct ChangeFeed {
+    feed_id: String,
+    /// Version of the most recently published event, 0 if none
+    version: u64,
+    capacity: usize,
+    buffer: VecDeque<ChangeEvent>,
+    sender: broadcast::Sender<ChangeEvent>,
+}
+
+impl ChangeFeed {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self {
+            feed_id: uuid::Uuid::new_v4().simple().to_string(),
+            version: 0,
+            capacity,
+            buffer: VecDeque::with_capacity(capacity),
+            sender,
+        }
+    }
+
+    /// Cursor pointing at the latest event, for clients that only want new events
+    pub fn cursor(&self) -> String {
+        format!("{}:{}", self.feed_id, self.version)
+    }
+
+    pub fn publish(&mut self, event: WatchEvent) {
+        self.version += 1;
+        let change = ChangeEvent {
+            cursor: self.cursor(),
+            version: self.version,
+            timestamp: now(),
+            event,
+        };
+
+        if self.buffer.len() == self.capacity {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(change.clone());
+
+        // No receivers is not an error, nobody is watching
+        let _ = self.sender.send(change);
+    }
+
+    /// Returns the buffered events after `cursor` together with a receiver
+    /// for every later event. Both are taken under the same lock so no event
+    /// is missed or delivered twice.
+    pub fn subscribe(&self, cursor: Option<&str>) -> Result<(Vec<ChangeEvent>, broadcast::Receiver<ChangeEvent>), WatchError> {
+        let receiver = self.sender.subscribe();
+        let Some(cursor) = cursor else {
+            return Ok((Vec::new(), receiver));
+        };
+
+        let (feed_id, version) = cursor.split_once(':').ok_or(WatchError::InvalidCursor)?;
+        let version: u64 = version.parse().map_err(|_| WatchError::InvalidCursor)?;
+        if feed_id != self.feed_id || version > self.version {
+            return Err(WatchError::CursorExpired);
+        }
+
+        let oldest = self.buffer.front().map_or(self.version + 1, |event| event.version);
+        if version + 1 < oldest {
+            return Err(WatchError::CursorExpired);
+        }
+
+        let backlog = self.buffer.iter()
+            .filter(|event| event.version > version)
+            .cloned()
+            .collect();
+        Ok((backlog, receiver))
+    }
+}
+
+fn publish(event: WatchEvent) {
+    match CHANGE_FEED.lock() {
+        Ok(mut feed) => feed.publish(event),
+        Err(e) => log::error!("Change feed lock poisoned, dropping event: {e}"),
+    }
+}
+
+/// Keys an op touched
+fn touched<K: Clone, V, A>(op: &Op<K, V, A>) -> Vec<K> {
+    match op {
+        Op::Up { key, .. } => vec![key.clone()],
+        Op::Rm { keyset, .. } => keyset.iter().cloned().collect(),
+    }
+}
+
+pub fn instance_changed<V, A>(op: &Op<String, V, A>, lookup: impl Fn(&String) -> Option<Instance>) {
+    instances_changed(touched(op), lookup)
+}
+
+pub fn node_changed<V, A>(op: &Op<String, V, A>, lookup: impl Fn(&String) -> Option<Node>) {
+    nodes_changed(touched(op), lookup)
+}
+
+pub fn dns_changed<V, A>(op: &Op<String, V, A>, lookup: impl Fn(&String) -> Option<FormDnsRecord>) {
+    dns_records_changed(touched(op), lookup)
+}
+
+pub fn account_changed<V, A>(op: &Op<String, V, A>, lookup: impl Fn(&String) -> Option<Account>) {
+    accounts_changed(touched(op), lookup)
+}
+
+// The functions below publish the value of each key as it stands now. Maps
+// changed by a merge rather than an op (anti-entropy deltas, bootstrap and
+// snapshot restores) call them with the keys the merge changed.
+
+pub fn instances_changed(keys: impl IntoIterator<Item = String>, lookup: impl Fn(&String) -> Option<Instance>) {
+    for instance_id in keys {
+        publish(match lookup(&instance_id) {
+            Some(instance) => WatchEvent::InstanceUpdated { instance },
+            None => WatchEvent::InstanceDeleted { instance_id },
+        });
+    }
+}
+
+pub fn nodes_changed(keys: impl IntoIterator<Item = String>, lookup: impl Fn(&String) -> Option<Node>) {
+    for node_id in keys {
+        publish(match lookup(&node_id) {
+            Some(node) => WatchEvent::NodeUpdated { node },
+            None => WatchEvent::NodeDeleted { node_id },
+        });
+    }
+}
+
+pub fn dns_records_changed(keys: impl IntoIterator<Item = String>, lookup: impl Fn(&String) -> Option<FormDnsRecord>) {
+    for domain in keys {
+        publish(match lookup(&domain) {
+            Some(record) => WatchEvent::DnsRecordUpdated { record },
+            None => WatchEvent::DnsRecordDeleted { domain },
+        });
+    }
+}
+
+pub fn accounts_changed(keys: impl IntoIterator<Item = String>, lookup: impl Fn(&String) -> Option<Account>) {
+    for address in keys {
+        publish(match lookup(&address) {
+            Some(account) => WatchEvent::AccountUpdated { account },
+            None => WatchEvent::AccountDeleted { address },
+        });
+    }
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct WatchQuery {
+    /// Comma separated resources to watch, defaults to all of them
+    pub resources: Option<String>,
+    /// Resume after this cursor; the `Last-Event-ID` header is used if absent
+    pub cursor: Option<String>,
+}
+
+/// Streams change events as server-sent events. Each event's `id` is its
+/// cursor, so a reconnecting `EventSource` resumes automatically.
+pub async fn watch(
+    headers: HeaderMap,
+    Query(query): Query<WatchQuery>,
+) -> Response {
+    let resources = match &query.resources {
+        Some(list) => match list.split(',').map(str::parse).collect::<Result<BTreeSet<ResourceKind>, _>>() {
+            Ok(resources) => resources,
+            Err(e) => return (StatusCode::BAD_REQUEST, Json(serde_json::json!({ "error": e }))).into_response(),
+        },
+        None => BTreeSet::new(),
+    };
+
+    let cursor = query.cursor.clone().or_else(|| {
+        headers.get("Last-Event-ID")
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    });
+
+    let subscription = match CHANGE_FEED.lock() {
+        Ok(feed) => feed.subscribe(cursor.as_deref()),
+        Err(e) => return (StatusCode::INTERNAL_SERVER_ERROR, Json(serde_json::json!({ "error": e.to_string() }))).into_response(),
+    };
+    let (backlog, receiver) = match subscription {
+        Ok(subscription) => subscription,
+        Err(e) => {
+            let status = match e {
+                WatchError::InvalidCursor => StatusCode::BAD_REQUEST,
+                WatchError::CursorExpired => StatusCode::GONE,
+            };
+            return (status, Json(serde_json::json!({ "error": e.to_string() }))).into_response();
+        }
+    };
+
+    let wanted = move |event: &ChangeEvent| resources.is_empty() || resources.contains(&event.event.resource());
+    let live = stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(event) => return Some((event, receiver)),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    log::warn!("Watch client fell behind, {skipped} events were dropped");
+                    // The stream ends and the client resumes from its last
+                    // cursor, picking the dropped events up from the buffer
+                    return None;
+                }
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let events = stream::iter(backlog)
+        .chain(live)
+        .filter(move |event| futures::future::ready(wanted(event)))
+        .map(sse_event);
+
+    Sse::new(events)
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(KEEP_ALIVE_SECS)))
+        .into_response()
+}
+
+fn sse_event(change: ChangeEvent) -> Result<Event, Infallible> {
+    let event = Event::default()
+        .id(change.cursor.clone())
+        .event(change.event.name());
+    Ok(match serde_json::to_string(&change) {
+        Ok(data) => event.data(data),
+        Err(e) => event.data(serde_json::json!({ "error": e.to_string() }).to_string()),
+    })
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deleted(id: &str) -> WatchEvent {
+        WatchEvent::InstanceDeleted { instance_id: id.to_string() }
+    }
+
+    #[test]
+    fn test_subscribe_resumes_from_cursor() {
+        let mut feed = ChangeFeed::new(4);
+        feed.publish(deleted("a"));
+        let cursor = feed.cursor();
+        feed.publish(deleted("b"));
+        feed.publish(deleted("c"));
+
+        let (backlog, mut receiver) = feed.subscribe(Some(&cursor)).unwrap();
+        let versions: Vec<u64> = backlog.iter().map(|event| event.version).collect();
+        assert_eq!(versions, vec![2, 3]);
+
+        feed.publish(deleted("d"));
+        assert_eq!(receiver.try_recv().unwrap().version, 4);
+    }
+
+    #[test]
+    fn test_stale_cursors_are_rejected() {
+        let mut feed = ChangeFeed::new(2);
+        let start = feed.cursor();
+        for id in ["a", "b", "c"] {
+            feed.publish(deleted(id));
+        }
+
+        assert_eq!(feed.subscribe(Some(&start)).unwrap_err(), WatchError::CursorExpired);
+        assert_eq!(feed.subscribe(Some("other:1")).unwrap_err(), WatchError::CursorExpired);
+        assert_eq!(feed.subscribe(Some("garbage")).unwrap_err(), WatchError::InvalidCursor);
+        assert!(feed.subscribe(Some(&feed.cursor())).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn test_merged_keys_publish_current_value() {
+        let cursor = CHANGE_FEED.lock().unwrap().cursor();
+        accounts_changed(vec!["0xremoved".to_string()], |_| None);
+
+        let (backlog, _) = CHANGE_FEED.lock().unwrap().subscribe(Some(&cursor)).unwrap();
+        assert!(backlog.iter().any(|change| matches!(
+            &change.event,
+            WatchEvent::AccountDeleted { address } if address == "0xremoved"
+        )));
+    }
+}
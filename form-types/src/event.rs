@@ -3,6 +3,7 @@ use alloy_core::primitives::Address;
 use serde::{Serialize, Deserialize};
 use uuid::Uuid;
 use form_traits::{Event as EventTrait, IntoEvent};
+use crate::request::{AddDeviceRequest, AddDiskRequest, AddFsRequest, CommitVmRequest, MigrateVmRequest, RemoveDeviceRequest};
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub enum Event {
@@ -245,9 +246,45 @@ pub enum VmmEvent {
         #[cfg(any(feature = "testnet", feature = "mainnet"))]
         recovery_id: u32,
     },
-    Migrate,
+    Migrate {
+        request: MigrateVmRequest,
+    },
+    ReceiveMigration {
+        request: MigrateVmRequest,
+    },
     Copy,
-    Snapshot,
+    Snapshot {
+        id: String,
+        snapshot_id: String,
+        description: Option<String>,
+    },
+    Restore {
+        id: String,
+        snapshot_id: Option<String>,
+        n_snapshots_ago: u32,
+    },
+    Resize {
+        id: String,
+        name: String,
+        vcpus: Option<u8>,
+        memory_mb: Option<u32>,
+    },
+    AddDisk {
+        request: AddDiskRequest,
+    },
+    AddFs {
+        request: AddFsRequest,
+    },
+    AddDevice {
+        request: AddDeviceRequest,
+    },
+    RemoveDevice {
+        request: RemoveDeviceRequest,
+    },
+    Commit {
+        request: CommitVmRequest,
+        commit_id: String,
+    },
 }
 
 impl IntoEvent for VmmEvent {
@@ -55,6 +55,187 @@ pub struct GetVmRequest {
     pub recovery_id: u32,
 }
 
+/// Request to capture a snapshot of a running VM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotVmRequest {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to restore a VM from one of its snapshots. `snapshot_id` takes
+/// precedence; otherwise the snapshot `n_snapshots_ago` steps back from the
+/// latest one is used, defaulting to the latest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RestoreVmRequest {
+    pub id: String,
+    pub name: String,
+    pub snapshot_id: Option<String>,
+    pub n_snapshots_ago: Option<u32>,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to live migrate a VM to another node. The source node forwards
+/// the same signed request to the destination. The signature covers
+/// `MigrateVmRequest:{id}:{destination_node_id}`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrateVmRequest {
+    pub id: String,
+    pub name: String,
+    pub destination_node_id: String,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to hot-plug vCPUs into, or unplug them from, a running VM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResizeVcpuRequest {
+    pub id: String,
+    pub name: String,
+    pub vcpus: u8,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to hot-plug memory into, or unplug it from, a running VM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResizeMemoryRequest {
+    pub id: String,
+    pub name: String,
+    pub memory_mb: u32,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to hot-plug a disk image into a running VM. `path` names a file
+/// in the VM's volume directory on the node; if it does not exist yet, an
+/// empty disk of `size_gb` is created.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddDiskRequest {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub size_gb: Option<u64>,
+    pub readonly: bool,
+    pub direct: bool,
+    pub iommu: bool,
+    pub disk_id: Option<String>,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to hot-plug a virtio-fs share into a running VM. `socket` names
+/// the socket of a virtiofsd in the VM's volume directory on the node.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddFsRequest {
+    pub id: String,
+    pub name: String,
+    pub tag: String,
+    pub socket: String,
+    pub fs_id: Option<String>,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to pass a host VFIO device, given by its sysfs path, through to
+/// a running VM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddDeviceRequest {
+    pub id: String,
+    pub name: String,
+    pub path: String,
+    pub iommu: bool,
+    pub device_id: Option<String>,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to unplug a disk, filesystem or device from a running VM
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RemoveDeviceRequest {
+    pub id: String,
+    pub name: String,
+    pub device_id: String,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to propagate the disk of a modified VM to every other member of
+/// its cluster. Sent to the node running the modified instance, which
+/// forwards the same signed request to each member while rolling it out,
+/// under its own node signature.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommitVmRequest {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// Request to move the traffic of a domain from the instances of one build
+/// to instances of a newer build of the same Formpack.
+///
+/// The owner signs a create request for the new build up front. Deleting
+/// instances, whether to roll a failed deploy back or to retire the old
+/// build once it passes, is done on the authority of the node running the
+/// deploy, which the other nodes check against the deploy recorded in the
+/// old build's cluster.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployRequest {
+    pub from_build_id: String,
+    pub to_build_id: String,
+    /// Domain whose DNS record is moved over to the new build
+    pub domain: String,
+    pub strategy: DeployStrategy,
+    /// Number of instances of the new build, defaults to the size of the
+    /// old build's cluster
+    pub instances: Option<u32>,
+    pub health_check: Option<HealthCheck>,
+    /// How long a batch has to become healthy before the deploy rolls back
+    pub gate_timeout_secs: u64,
+    /// How long a batch has to stay healthy while serving traffic before
+    /// the deploy moves on
+    pub observe_secs: u64,
+    pub create_request: CreateVmRequest,
+    /// Owner signature over `DeployRequest::signing_message`
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+impl DeployRequest {
+    /// The message the owner signs: `DeployRequest:{request}`, with the
+    /// request serialized as JSON without its signature and recovery id, so
+    /// no field can be changed without invalidating the signature
+    pub fn signing_message(&self) -> String {
+        let unsigned = DeployRequest { signature: None, recovery_id: 0, ..self.clone() };
+        format!("DeployRequest:{}", serde_json::to_string(&unsigned).unwrap_or_default())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DeployStrategy {
+    /// Brings up `batch_size` new instances at a time, moving as many of
+    /// the domain's targets over to them once they pass the health gate
+    Rolling {
+        batch_size: u32,
+    },
+    /// Brings up every new instance, then swaps all of the domain's targets
+    /// over at once
+    BlueGreen,
+}
+
+/// An HTTP check a new instance has to answer with a 2xx status, on its
+/// formnet IP, before it is given traffic and for as long as it is observed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HealthCheck {
+    pub port: u16,
+    pub path: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ListRequest {
     pub requestor: String,
@@ -74,3 +255,146 @@ pub enum VmmResponse {
     Success(VmResponse),
     Failure(String),
 }
+
+/// Response to the commit endpoints of vmm-service, on the coordinating
+/// node and on the members it updates
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum CommitResponse {
+    /// The coordinator has started the commit
+    Accepted {
+        id: String,
+        commit_id: String,
+    },
+    Status(CommitStatus),
+    /// A member has shut its VM down and digested its disk
+    Prepared(DiskDigests),
+    /// A member has merged an overlay of `bytes` into its disk and booted
+    Applied {
+        bytes: u64,
+    },
+    /// A member has booted its VM again on its old disk
+    Aborted,
+    Failure(String),
+}
+
+/// Progress of a commit, kept by the node coordinating it
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommitStatus {
+    pub commit_id: String,
+    pub id: String,
+    pub description: Option<String>,
+    pub phase: CommitPhase,
+    pub members: Vec<MemberCommit>,
+    pub started_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CommitPhase {
+    Snapshotting,
+    RollingOut,
+    Completed,
+    Failed(String),
+}
+
+impl CommitPhase {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, CommitPhase::Completed | CommitPhase::Failed(_))
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemberCommit {
+    pub instance_id: String,
+    pub node_id: String,
+    pub state: MemberState,
+    /// Bytes of the member's disk the commit changed
+    pub changed_bytes: Option<u64>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MemberState {
+    Pending,
+    /// Shutting the VM down and digesting its disk
+    Stopping,
+    /// Sending the overlay, merging it and booting the VM
+    Updating,
+    Committed,
+    Failed(String),
+    /// Not updated because an earlier member failed
+    Skipped,
+}
+
+/// Digest of every `chunk_size` chunk of a disk, the last one possibly short
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct DiskDigests {
+    pub size: u64,
+    pub chunk_size: u64,
+    pub digests: Vec<[u8; 32]>,
+}
+
+/// Response to the deploy endpoints of vmm-service
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DeployResponse {
+    Accepted {
+        deploy_id: String,
+    },
+    Status(DeployStatus),
+    Failure(String),
+}
+
+/// Progress of a deploy, kept by the node running it
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DeployStatus {
+    pub deploy_id: String,
+    pub from_build_id: String,
+    pub to_build_id: String,
+    pub domain: String,
+    pub strategy: DeployStrategy,
+    pub phase: DeployPhase,
+    /// Batches of new instances, in the order they are rolled out
+    pub batches: Vec<DeployBatch>,
+    /// Instances the domain currently points at
+    pub serving: Vec<String>,
+    pub started_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum DeployPhase {
+    RollingOut,
+    /// Every batch passed, deleting the old build's instances
+    Retiring,
+    Completed,
+    /// A batch failed, restoring the domain and deleting the new instances
+    RollingBack(String),
+    RolledBack(String),
+    /// The deploy could not be started or rolled back cleanly
+    Failed(String),
+}
+
+impl DeployPhase {
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, DeployPhase::Completed | DeployPhase::RolledBack(_) | DeployPhase::Failed(_))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct DeployBatch {
+    /// Instances of the new build, known once their nodes are picked
+    pub instance_ids: Vec<String>,
+    pub size: u32,
+    pub state: BatchState,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum BatchState {
+    Pending,
+    Creating,
+    /// Waiting for the batch to boot, join the cluster and pass its checks
+    Gating,
+    /// Serving traffic, watched for failing checks
+    Observing,
+    Healthy,
+    Failed(String),
+}
@@ -1,7 +1,12 @@
+use std::sync::OnceLock;
 use alloy_primitives::Address;
-use k256::ecdsa::{RecoveryId, Signature, VerifyingKey};
+use axum::http::HeaderMap;
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
 use tiny_keccak::{Hasher, Sha3};
+use form_state::auth::node::{body_digest, NodeSignature};
 use form_state::instances::Instance;
+use form_state::nodes::Node;
+use form_state::scaling::ScalingOperation;
 use form_types::state::{Response, Success};
 
 use crate::error::VmmError;
@@ -51,6 +56,20 @@ impl SignatureVerifier {
         format!("{}:{}", op_type, instance_id)
     }
     
+    /// Creates the message signed for a scaling operation, which binds the
+    /// operation and its target so a signature can't be replayed to scale
+    /// the cluster differently
+    pub fn create_scaling_message(build_id: &str, operation: &ScalingOperation) -> String {
+        let operation = serde_json::to_string(operation).unwrap_or_default();
+        format!("ScaleClusterRequest:{}:{}", build_id, operation)
+    }
+    
+    /// Creates the message signed for a migration, which binds the node the
+    /// VM is moved to so a signature can't be replayed to move it elsewhere
+    pub fn create_migration_message(instance_id: &str, destination_node_id: &str) -> String {
+        format!("MigrateVmRequest:{}:{}", instance_id, destination_node_id)
+    }
+    
     /// Generates the message hash for a VM operation
     pub fn hash_operation_message(op_type: &str, instance_id: &str) -> [u8; 32] {
         let message = Self::create_operation_message(op_type, instance_id);
@@ -62,6 +81,73 @@ impl SignatureVerifier {
     }
 }
 
+/// Key of the node this vmm-service runs on, hex encoded. Set once when the
+/// `VmManager` starts, and used to sign requests made on the node's own
+/// authority.
+static NODE_KEY: OnceLock<String> = OnceLock::new();
+
+pub fn set_node_key(signing_key: String) {
+    if NODE_KEY.set(signing_key).is_err() {
+        log::warn!("Node key was already set, keeping the existing key");
+    }
+}
+
+/// Id of the node this vmm-service runs on, derived from the node key
+pub fn node_id() -> Result<String, VmmError> {
+    let signing_key = NODE_KEY.get()
+        .ok_or_else(|| VmmError::Config("Node key is not set".to_string()))?;
+    let key = hex::decode(signing_key)
+        .map_err(|e| VmmError::Config(format!("Invalid node key: {e}")))?;
+    let key = SigningKey::from_slice(&key)
+        .map_err(|e| VmmError::Config(format!("Invalid node key: {e}")))?;
+    Ok(hex::encode(Address::from_private_key(&key)))
+}
+
+/// Signs a request to `path` on another node's vmm-service with the node key
+pub fn sign_node_request(path: &str, body: &[u8]) -> Result<NodeSignature, VmmError> {
+    sign_node_digest(path, &body_digest(body))
+}
+
+/// Signs a request to `path` whose body has the given digest
+pub fn sign_node_digest(path: &str, digest: &[u8; 32]) -> Result<NodeSignature, VmmError> {
+    let signing_key = NODE_KEY.get()
+        .ok_or_else(|| VmmError::Config("Node key is not set".to_string()))?;
+    NodeSignature::sign(signing_key, path, digest)
+        .map_err(VmmError::Config)
+}
+
+/// Verifies requests that another Formation node makes on its own authority
+pub struct NodeVerifier;
+
+impl NodeVerifier {
+    /// Checks the node signature on a request to `path` with the given body
+    /// and returns the id of the registered node that signed it
+    pub async fn verify(headers: &HeaderMap, path: &str, body: &[u8]) -> Result<String, VmmError> {
+        Self::verify_digest(headers, path, &body_digest(body)).await
+    }
+
+    /// Like `verify`, for a body that is streamed and checked against
+    /// `digest` once it has arrived
+    pub async fn verify_digest(headers: &HeaderMap, path: &str, digest: &[u8; 32]) -> Result<String, VmmError> {
+        let signature = NodeSignature::from_headers(headers)
+            .ok_or_else(|| VmmError::Config("Node signature is required".to_string()))?;
+        let node_id = signature.verify(path, digest)
+            .map_err(VmmError::Config)?;
+
+        let response = reqwest::Client::new()
+            .get(format!("http://127.0.0.1:3004/node/{node_id}/get"))
+            .send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?
+            .json::<Response<Node>>().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        match response {
+            Response::Success(Success::Some(_)) => Ok(node_id),
+            _ => Err(VmmError::Config(format!("Unauthorized: {node_id} is not a registered node"))),
+        }
+    }
+}
+
 /// Permission levels for VM operations
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Permission {
@@ -1,6 +1,6 @@
 use alloy_primitives::Address;
 use axum::{
-    extract::State, routing::{get, post}, Json, Router
+    body::{Body, Bytes}, extract::{DefaultBodyLimit, Path, State}, http::HeaderMap, routing::{get, post}, Json, Router
 };
 use form_p2p::queue::{QueueRequest, QueueResponse, QUEUE_PORT};
 use reqwest::Client;
@@ -13,7 +13,11 @@ use std::{sync::Arc, time::Duration};
 use std::net::SocketAddr;
 
 use crate::VmmError;
-use form_types::{BootCompleteRequest, CreateVmRequest, DeleteVmRequest, GetVmRequest, PingVmmRequest, StartVmRequest, StopVmRequest, VmResponse, VmmEvent, VmmResponse};
+use crate::service::scaling::{autoscale_cluster, create_member, delete_member, scale_cluster, scaling_status};
+use crate::service::deploy::{delete_deploy_member, deploy_build, deployment_status};
+use crate::service::migration::MigrationResponse;
+use crate::service::commit::{abort_member, apply_member, authorize_coordinator, commit_status, prepare_member, OVERLAY_DIGEST_HEADER};
+use form_types::{AddDeviceRequest, AddDiskRequest, AddFsRequest, BootCompleteRequest, CommitResponse, CommitVmRequest, CreateVmRequest, DeleteVmRequest, GetVmRequest, MigrateVmRequest, PingVmmRequest, RemoveDeviceRequest, ResizeMemoryRequest, ResizeVcpuRequest, RestoreVmRequest, SnapshotVmRequest, StartVmRequest, StopVmRequest, VmResponse, VmmEvent, VmmResponse};
 
 pub mod auth;
 
@@ -432,6 +436,10 @@ impl VmmApi {
             .route("/vm/:id/power_button", post(power_button))
             .route("/vm/:id/commit", post(commit))
             .route("/vm/:id/update", post(commit))
+            .route("/vm/:id/commit/:commit_id", get(get_commit))
+            .route("/vm/:id/commit/:commit_id/prepare", post(prepare_commit))
+            .route("/vm/:id/commit/:commit_id/apply", post(apply_commit).layer(DefaultBodyLimit::disable()))
+            .route("/vm/:id/commit/:commit_id/abort", post(abort_commit))
             .route("/vm/:id/snapshot", post(snapshot))
             .route("/vm/:id/coredump", post(coredump))
             .route("/vm/:id/restore", post(restore))
@@ -447,6 +455,14 @@ impl VmmApi {
             .route("/vm/:id/info", get(get_vm))
             .route("/vm/:id", get(get_vm))
             .route("/vms/list", get(list))
+            .route("/cluster/:build_id/scale", post(scale_cluster))
+            .route("/cluster/:build_id/scaling", get(scaling_status))
+            .route("/cluster/:build_id/autoscale", post(autoscale_cluster))
+            .route("/cluster/:build_id/member/create", post(create_member))
+            .route("/cluster/:build_id/member/delete", post(delete_member))
+            .route("/cluster/:build_id/deploy", post(deploy_build))
+            .route("/deploy/:deploy_id", get(deployment_status))
+            .route("/deploy/:deploy_id/member/delete", post(delete_deploy_member))
             .with_state(app_state);
 
         log::info!("Established route, binding to {}", &self.addr);
@@ -818,22 +834,412 @@ async fn list(
     request_receive(channel, event).await
 }
 
+/// Verifies that `signature` over `operation` for VM `id` comes from an
+/// address holding `permission` on that VM
+async fn authorize_operation(
+    operation: &str,
+    id: &str,
+    signature: Option<&String>,
+    recovery_id: u32,
+    permission: auth::Permission,
+) -> Result<(), String> {
+    let message = auth::SignatureVerifier::create_operation_message(operation, id);
+    authorize_message(message, id, signature, recovery_id, permission).await
+}
+
+/// Checks that `message` was signed by an address holding `permission` on
+/// the instance
+async fn authorize_message(
+    message: String,
+    id: &str,
+    signature: Option<&String>,
+    recovery_id: u32,
+    permission: auth::Permission,
+) -> Result<(), String> {
+    let signature = signature.ok_or("Signature is required".to_string())?;
+    let signer_address = auth::SignatureVerifier::verify_signature(message, signature, recovery_id)
+        .map_err(|e| format!("Signature verification failed: {}", e))?;
+    match auth::OwnershipVerifier::verify_authorization(id, &signer_address, permission).await {
+        Ok(true) => Ok(()),
+        Ok(false) => Err(format!(
+            "Unauthorized: Address {} is not the owner or authorized user for instance {}",
+            signer_address, id
+        )),
+        Err(e) => Err(format!("Error checking authorization: {}", e)),
+    }
+}
+
+/// Snapshots can take far longer than `request_receive` waits for, so the
+/// event is queued and the snapshot id returned straight away. The snapshot
+/// shows up in the instance's snapshot history once it has been written.
+async fn snapshot(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<SnapshotVmRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "SnapshotVmRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    let snapshot_id = uuid::Uuid::new_v4().to_string();
+    let event = VmmEvent::Snapshot {
+        id: request.id.clone(),
+        snapshot_id: snapshot_id.clone(),
+        description: request.description.clone(),
+    };
+
+    if let Err(e) = channel.lock().await.send(event.clone()).await {
+        log::info!("Error sending {event:?}: {e}");
+        return Json(VmmResponse::Failure(
+            format!("Error sending event {event:?} across VmmApiChannel to request snapshot of vm {}", request.id)
+        ))
+    }
+
+    Json(VmmResponse::Success(
+        VmResponse {
+            id: request.id,
+            name: request.name,
+            state: format!("snapshot {snapshot_id} pending")
+    }))
+}
+
+async fn restore(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<RestoreVmRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "RestoreVmRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    let event = VmmEvent::Restore {
+        id: request.id.clone(),
+        snapshot_id: request.snapshot_id.clone(),
+        n_snapshots_ago: request.n_snapshots_ago.unwrap_or(0),
+    };
+
+    if let Err(e) = channel.lock().await.send(event.clone()).await {
+        log::info!("Error sending {event:?}: {e}");
+        return Json(VmmResponse::Failure(
+            format!("Error sending event {event:?} across VmmApiChannel to request restore of vm {}", request.id)
+        ))
+    }
+
+    Json(VmmResponse::Success(
+        VmResponse {
+            id: request.id,
+            name: request.name,
+            state: "restoring".to_string()
+    }))
+}
+
+/// Starts a live migration of a VM running on this node. The migration runs
+/// in the background; the instance shows up under the destination node in
+/// form-state once it has completed.
+async fn migrate_to(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<MigrateVmRequest>,
+) -> Json<MigrationResponse> {
+    if let Err(e) = authorize_message(
+        auth::SignatureVerifier::create_migration_message(&request.id, &request.destination_node_id),
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(MigrationResponse::Failure(e))
+    }
+
+    let event = VmmEvent::Migrate { request: request.clone() };
+    if let Err(e) = channel.lock().await.send(event.clone()).await {
+        log::info!("Error sending {event:?}: {e}");
+        return Json(MigrationResponse::Failure(
+            format!("Error sending event {event:?} across VmmApiChannel to request migration of vm {}", request.id)
+        ))
+    }
+
+    Json(MigrationResponse::Accepted {
+        id: request.id,
+        destination_node_id: request.destination_node_id,
+    })
+}
+
+/// Called by the source node of a migration, with the signed request it
+/// received, to prepare this node to receive the VM
+async fn migrate_from(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<MigrateVmRequest>,
+) -> Json<MigrationResponse> {
+    if let Err(e) = authorize_message(
+        auth::SignatureVerifier::create_migration_message(&request.id, &request.destination_node_id),
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(MigrationResponse::Failure(e))
+    }
+
+    let event = VmmEvent::ReceiveMigration { request };
+    match request_receive::<MigrationResponse>(channel, event).await {
+        Ok(resp) => resp,
+        Err(e) => Json(MigrationResponse::Failure(e)),
+    }
+}
+
 async fn power_button() {}
 async fn reboot() {}
-async fn commit() {}
-async fn snapshot() {}
+/// Starts a commit on the node running the modified VM. The rollout can
+/// take far longer than `request_receive` waits for, so only its start is
+/// awaited; poll `/vm/:id/commit/:commit_id` for per-member progress.
+async fn commit(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<CommitVmRequest>,
+) -> Json<CommitResponse> {
+    if let Err(e) = authorize_operation(
+        "CommitVmRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(CommitResponse::Failure(e))
+    }
+
+    let commit_id = uuid::Uuid::new_v4().to_string();
+    match request_receive::<CommitResponse>(channel, VmmEvent::Commit { request, commit_id }).await {
+        Ok(resp) => resp,
+        Err(e) => Json(CommitResponse::Failure(e)),
+    }
+}
+
+async fn get_commit(Path((id, commit_id)): Path<(String, String)>) -> Json<CommitResponse> {
+    match commit_status(&commit_id).await {
+        Some(status) if status.id == id => Json(CommitResponse::Status(status)),
+        _ => Json(CommitResponse::Failure(format!("Commit {commit_id} of {id} not found on this node"))),
+    }
+}
+
+/// The steps below are sent by the coordinating node to each member,
+/// signed with its node key, along with the owner's original request
+async fn prepare_commit(
+    Path((id, commit_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<CommitResponse> {
+    let path = format!("/vm/{id}/commit/{commit_id}/prepare");
+    if let Err(e) = authorize_commit(&id, &path, &headers, &body).await {
+        return Json(CommitResponse::Failure(e))
+    }
+    match prepare_member(&id, &commit_id).await {
+        Ok(digests) => Json(CommitResponse::Prepared(digests)),
+        Err(e) => Json(CommitResponse::Failure(e.to_string())),
+    }
+}
+
+/// The body is the overlay itself, so the node signature covers the
+/// overlay digest sent in a header, which the overlay is checked against
+/// once it has arrived
+async fn apply_commit(
+    Path((id, commit_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Body,
+) -> Json<CommitResponse> {
+    let path = format!("/vm/{id}/commit/{commit_id}/apply");
+    let digest = headers.get(OVERLAY_DIGEST_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| hex::decode(value).ok())
+        .and_then(|value| <[u8; 32]>::try_from(value).ok());
+    let digest = match digest {
+        Some(digest) => digest,
+        None => return Json(CommitResponse::Failure("Overlay digest is required".to_string())),
+    };
+    let authorized = match auth::NodeVerifier::verify_digest(&headers, &path, &digest).await {
+        Ok(node_id) => authorize_coordinator(&id, &node_id).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = authorized {
+        return Json(CommitResponse::Failure(format!("Node authentication failed: {e}")))
+    }
+    match apply_member(&id, &commit_id, digest, body.into_data_stream()).await {
+        Ok(bytes) => Json(CommitResponse::Applied { bytes }),
+        Err(e) => Json(CommitResponse::Failure(e.to_string())),
+    }
+}
+
+async fn abort_commit(
+    Path((id, commit_id)): Path<(String, String)>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<CommitResponse> {
+    let path = format!("/vm/{id}/commit/{commit_id}/abort");
+    if let Err(e) = authorize_commit(&id, &path, &headers, &body).await {
+        return Json(CommitResponse::Failure(e))
+    }
+    match abort_member(&id, &commit_id).await {
+        Ok(_) => Json(CommitResponse::Aborted),
+        Err(e) => Json(CommitResponse::Failure(e.to_string())),
+    }
+}
+
+/// Checks that a commit step comes from a node running a replica of the
+/// instance, and carries the owner's signed request for it
+async fn authorize_commit(id: &str, path: &str, headers: &HeaderMap, body: &[u8]) -> Result<(), String> {
+    let authorized = match auth::NodeVerifier::verify(headers, path, body).await {
+        Ok(node_id) => authorize_coordinator(id, &node_id).await,
+        Err(e) => Err(e),
+    };
+    authorized.map_err(|e| format!("Node authentication failed: {e}"))?;
+
+    let request: CommitVmRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid commit request: {e}"))?;
+    if request.id != id {
+        return Err("Instance id in path and request do not match".to_string());
+    }
+    authorize_operation(
+        "CommitVmRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await
+}
+
 async fn coredump() {}
-async fn restore() {}
-async fn resize_vcpu() {}
-async fn resize_memory() {}
-async fn add_device() {}
-async fn add_disk() {}
-async fn add_fs() {}
-async fn remove_device() {}
-async fn migrate_to() {}
-async fn migrate_from() {}
-
-async fn request_receive<T: DeserializeOwned>(
+async fn resize_vcpu(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<ResizeVcpuRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "ResizeVcpuRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    let event = VmmEvent::Resize {
+        id: request.id,
+        name: request.name,
+        vcpus: Some(request.vcpus),
+        memory_mb: None,
+    };
+    hotplug(channel, event).await
+}
+
+async fn resize_memory(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<ResizeMemoryRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "ResizeMemoryRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    let event = VmmEvent::Resize {
+        id: request.id,
+        name: request.name,
+        vcpus: None,
+        memory_mb: Some(request.memory_mb),
+    };
+    hotplug(channel, event).await
+}
+
+async fn add_device(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<AddDeviceRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "AddDeviceRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    hotplug(channel, VmmEvent::AddDevice { request }).await
+}
+
+async fn add_disk(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<AddDiskRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "AddDiskRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    hotplug(channel, VmmEvent::AddDisk { request }).await
+}
+
+async fn add_fs(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<AddFsRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "AddFsRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    hotplug(channel, VmmEvent::AddFs { request }).await
+}
+
+async fn remove_device(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Json(request): Json<RemoveDeviceRequest>,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_operation(
+        "RemoveDeviceRequest",
+        &request.id,
+        request.signature.as_ref(),
+        request.recovery_id,
+        auth::Permission::Operator
+    ).await {
+        return Json(VmmResponse::Failure(e))
+    }
+
+    hotplug(channel, VmmEvent::RemoveDevice { request }).await
+}
+
+/// Hands a resize or hotplug to the VmManager, which answers with a
+/// `VmmResponse` once the VM has been changed
+async fn hotplug(channel: Arc<Mutex<VmmApiChannel>>, event: VmmEvent) -> Json<VmmResponse> {
+    match request_receive::<VmmResponse>(channel, event).await {
+        Ok(resp) => resp,
+        Err(e) => Json(VmmResponse::Failure(e)),
+    }
+}
+
+pub(crate) async fn request_receive<T: DeserializeOwned>(
     channel: Arc<Mutex<VmmApiChannel>>,
     event: VmmEvent,
 ) -> Result<Json<T>, String> {
@@ -138,14 +138,20 @@ pub fn create_vm_config(config: &VmInstanceConfig) -> VmConfig {
     // Enable IOMMU at the VM level if we have GPU devices
     let enable_iommu = devices.is_some();
     
+    // Leave room to hot-plug vCPUs and memory up to the per VM limits
+    let limits = ResourceLimits::default();
+    let max_memory = limits.max_memory_per_vm << 20;
+    let memory = config.memory_mb << 20; // Convert MB to bytes
+
     VmConfig {
         cpus: CpusConfig {
             boot_vcpus: config.vcpu_count,
-            max_vcpus: config.vcpu_count,
+            max_vcpus: config.vcpu_count.max(limits.max_vcpus_per_vm),
             ..CpusConfig::default()
         },
         memory: MemoryConfig {
-            size: config.memory_mb << 20, // Convert MB to bytes
+            size: memory,
+            hotplug_size: (max_memory > memory).then(|| max_memory - memory),
             ..MemoryConfig::default()
         },
         payload: Some(PayloadConfig {
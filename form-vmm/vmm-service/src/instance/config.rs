@@ -10,6 +10,30 @@ use rand::{thread_rng, Rng};
 use gabble::Gab;
 
 pub const IMAGE_DIR: &str = "/var/lib/formation/vm-images";
+pub const SNAPSHOT_DIR: &str = "/var/lib/formation/snapshots";
+pub const VOLUME_DIR: &str = "/var/lib/formation/volumes";
+pub const COMMIT_DIR: &str = "/var/lib/formation/commits";
+
+/// Directory holding the files cloud-hypervisor writes for one snapshot of a VM
+pub fn snapshot_dir(name: &str, snapshot_id: &str) -> PathBuf {
+    PathBuf::from(SNAPSHOT_DIR).join(name).join(snapshot_id)
+}
+
+/// Directory holding the disk snapshot or overlay of one commit of a VM
+pub fn commit_dir(name: &str, commit_id: &str) -> PathBuf {
+    PathBuf::from(COMMIT_DIR).join(name).join(commit_id)
+}
+
+/// Path of a disk image or virtio-fs socket in a VM's volume directory.
+/// Hot-plugged files have to live there, so a caller can only attach what
+/// belongs to their own VM.
+pub fn volume_path(name: &str, file: &str) -> Option<PathBuf> {
+    let valid = !file.is_empty()
+        && file != "."
+        && file != ".."
+        && !file.contains('/');
+    valid.then(|| PathBuf::from(VOLUME_DIR).join(name).join(file))
+}
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
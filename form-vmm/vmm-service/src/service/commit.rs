@@ -0,0 +1,644 @@
+//! Cluster-wide commits: propagating the disk of one modified instance to
+//! every other member of its cluster.
+//!
+//! The operator sends a signed `CommitVmRequest` to the node running the
+//! modified instance, which coordinates the commit. It pauses the VM while
+//! its disk is copied into a qcow2 snapshot and resumes it. The other
+//! members are then updated one at a time, so the rest of the cluster keeps
+//! serving while each of them restarts:
+//!
+//! 1. The member shuts its VM down and returns a digest of every chunk of
+//!    its disk.
+//! 2. The coordinator writes the chunks of the snapshot that differ into a
+//!    qcow2 overlay and uploads it to the member.
+//! 3. The member merges the overlay into its disk and boots the VM again.
+//!
+//! Member disks are raw images, which the in-tree qcow2 support can't use
+//! as a backing file, so overlays carry no backing file: an unallocated
+//! cluster means the member's disk is left as it is.
+//!
+//! If a member fails, the rollout stops there and the members after it are
+//! left untouched. A member that fails before its disk is written to is
+//! booted again on its old disk; one whose merge fails is left shut down,
+//! as its disk is only partly updated. Progress is kept in memory on the
+//! coordinating node and served from `/vm/:id/commit/:commit_id`.
+//!
+//! The coordinator signs every step it sends a member with its node key.
+//! A member only acts for a registered node that runs a replica of the
+//! same instance. An overlay upload is signed over the overlay's digest,
+//! and the member refuses an overlay that doesn't match it before merging
+//! anything.
+
+use std::collections::BTreeMap;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use block::qcow::{self, ImageType, QcowFile, QcowHeader, RawFile};
+use bytes::Bytes;
+use form_state::datastore::InstanceRequest;
+use form_state::instances::{ClusterMember, Instance};
+use form_types::{CommitPhase, CommitResponse, CommitStatus, CommitVmRequest, DiskDigests, MemberCommit, MemberState};
+use futures::{Stream, StreamExt};
+use reqwest::Client;
+use tiny_keccak::{Hasher, Sha3};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+use vmm_sys_util::seek_hole::SeekHole;
+use crate::commit_dir;
+use crate::error::VmmError;
+use crate::service::migration::rootfs_path;
+use crate::api::auth::{sign_node_digest, sign_node_request};
+use crate::service::scaling::ScalingExecutor;
+use crate::service::{now, STATE_URL, VMM_PORT};
+use crate::service::vmm::{api_socket_path, expect_success, FormVmApi};
+
+/// Size of the chunks disks are compared in
+pub const CHUNK_SIZE: u64 = 1 << 20;
+/// Finished commits kept around for their status to be read
+const MAX_FINISHED_COMMITS: usize = 64;
+/// Header carrying the hex digest of an overlay upload, which the node
+/// signature covers in place of the streamed body
+pub const OVERLAY_DIGEST_HEADER: &str = "x-formation-overlay-digest";
+
+static COMMITS: Mutex<BTreeMap<String, CommitStatus>> = Mutex::const_new(BTreeMap::new());
+
+fn digest(chunk: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha3::v256();
+    let mut hash = [0u8; 32];
+    hasher.update(chunk);
+    hasher.finalize(&mut hash);
+    hash
+}
+
+fn io_error(context: String) -> impl FnOnce(std::io::Error) -> VmmError {
+    move |e| VmmError::OperationFailed(format!("{context}: {e}"))
+}
+
+fn qcow_error(context: String) -> impl FnOnce(qcow::Error) -> VmmError {
+    move |e| VmmError::OperationFailed(format!("{context}: {e}"))
+}
+
+/// Digests every chunk of a raw disk image
+pub fn disk_digests(path: &Path) -> Result<DiskDigests, VmmError> {
+    let mut file = File::open(path).map_err(io_error(format!("Unable to open {}", path.display())))?;
+    let size = file.metadata().map_err(io_error(format!("Unable to stat {}", path.display())))?.len();
+    let mut digests = Vec::with_capacity(size.div_ceil(CHUNK_SIZE) as usize);
+    let mut buf = vec![0u8; CHUNK_SIZE as usize];
+    let mut offset = 0;
+    while offset < size {
+        let len = CHUNK_SIZE.min(size - offset) as usize;
+        file.read_exact(&mut buf[..len]).map_err(io_error(format!("Unable to read {}", path.display())))?;
+        digests.push(digest(&buf[..len]));
+        offset += len as u64;
+    }
+    Ok(DiskDigests { size, chunk_size: CHUNK_SIZE, digests })
+}
+
+/// Digests a whole file, as an overlay is digested when it is uploaded
+pub fn file_digest(path: &Path) -> Result<[u8; 32], VmmError> {
+    let mut file = File::open(path).map_err(io_error(format!("Unable to open {}", path.display())))?;
+    let mut hasher = Sha3::v256();
+    let mut buf = vec![0u8; CHUNK_SIZE as usize];
+    loop {
+        let len = file.read(&mut buf).map_err(io_error(format!("Unable to read {}", path.display())))?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[..len]);
+    }
+    let mut hash = [0u8; 32];
+    hasher.finalize(&mut hash);
+    Ok(hash)
+}
+
+/// Copies a raw disk image into a new qcow2 image at `dest`
+pub fn snapshot_disk(disk: &Path, dest: &Path) -> Result<(), VmmError> {
+    let src = File::open(disk).map_err(io_error(format!("Unable to open {}", disk.display())))?;
+    let dst = OpenOptions::new().read(true).write(true).create_new(true).open(dest)
+        .map_err(io_error(format!("Unable to create {}", dest.display())))?;
+    qcow::convert(RawFile::new(src, false), RawFile::new(dst, false), ImageType::Qcow2, 0)
+        .map_err(qcow_error(format!("Unable to snapshot {}", disk.display())))
+}
+
+/// Writes the chunks of `snapshot` that differ from a member's disk into a
+/// new qcow2 overlay, returning how many bytes it holds
+pub fn build_overlay(snapshot: &Path, overlay: &Path, member: &DiskDigests) -> Result<u64, VmmError> {
+    let file = OpenOptions::new().read(true).write(true).open(snapshot)
+        .map_err(io_error(format!("Unable to open {}", snapshot.display())))?;
+    let mut source = QcowFile::from(RawFile::new(file, false))
+        .map_err(qcow_error(format!("Unable to open {}", snapshot.display())))?;
+    let size = source.seek(SeekFrom::End(0)).map_err(io_error("Unable to size snapshot".to_string()))?;
+    if member.size != size {
+        return Err(VmmError::OperationFailed(format!(
+            "Member disk is {} bytes, the committed disk is {size}; disks can't be resized by a commit",
+            member.size
+        )));
+    }
+    if member.chunk_size != CHUNK_SIZE || member.digests.len() as u64 != size.div_ceil(CHUNK_SIZE) {
+        return Err(VmmError::OperationFailed("Member sent digests in an unexpected layout".to_string()));
+    }
+
+    let file = OpenOptions::new().read(true).write(true).create_new(true).open(overlay)
+        .map_err(io_error(format!("Unable to create {}", overlay.display())))?;
+    let mut out = QcowFile::new(RawFile::new(file, false), 3, size)
+        .map_err(qcow_error(format!("Unable to create {}", overlay.display())))?;
+
+    source.rewind().map_err(io_error("Unable to read snapshot".to_string()))?;
+    let mut buf = vec![0u8; CHUNK_SIZE as usize];
+    let mut changed = 0;
+    for (index, member_digest) in member.digests.iter().enumerate() {
+        let offset = index as u64 * CHUNK_SIZE;
+        let len = CHUNK_SIZE.min(size - offset) as usize;
+        source.read_exact(&mut buf[..len]).map_err(io_error("Unable to read snapshot".to_string()))?;
+        if digest(&buf[..len]) != *member_digest {
+            out.seek(SeekFrom::Start(offset))
+                .and_then(|_| out.write_all(&buf[..len]))
+                .map_err(io_error(format!("Unable to write {}", overlay.display())))?;
+            changed += len as u64;
+        }
+    }
+    out.flush().map_err(io_error(format!("Unable to write {}", overlay.display())))?;
+    Ok(changed)
+}
+
+/// Copies every allocated cluster of an overlay into a raw disk image of
+/// the same size, returning how many bytes were written
+pub fn merge_overlay(overlay: &Path, disk: &Path) -> Result<u64, VmmError> {
+    let file = OpenOptions::new().read(true).write(true).open(overlay)
+        .map_err(io_error(format!("Unable to open {}", overlay.display())))?;
+    let mut raw = RawFile::new(file, false);
+    // Refuse overlays pointing at a backing file before it gets opened
+    let header = QcowHeader::new(&mut raw).map_err(qcow_error(format!("Unable to read {}", overlay.display())))?;
+    if header.backing_file_path.is_some() {
+        return Err(VmmError::OperationFailed("Commit overlays can't have a backing file".to_string()));
+    }
+    let mut overlay = QcowFile::from(raw).map_err(qcow_error("Unable to open overlay".to_string()))?;
+    let size = overlay.seek(SeekFrom::End(0)).map_err(io_error("Unable to size overlay".to_string()))?;
+
+    let target = OpenOptions::new().write(true).open(disk)
+        .map_err(io_error(format!("Unable to open {}", disk.display())))?;
+    let disk_size = target.metadata().map_err(io_error(format!("Unable to stat {}", disk.display())))?.len();
+    if disk_size != size {
+        return Err(VmmError::OperationFailed(format!(
+            "Overlay is {size} bytes, {} is {disk_size}", disk.display()
+        )));
+    }
+
+    let mut buf = vec![0u8; CHUNK_SIZE as usize];
+    let mut offset = 0;
+    let mut merged = 0;
+    let read_error = || io_error("Unable to read overlay".to_string());
+    while let Some(data) = overlay.seek_data(offset).map_err(read_error())? {
+        let hole = overlay.seek_hole(data).map_err(read_error())?.unwrap_or(size);
+        overlay.seek(SeekFrom::Start(data)).map_err(read_error())?;
+        let mut pos = data;
+        while pos < hole {
+            let len = CHUNK_SIZE.min(hole - pos) as usize;
+            overlay.read_exact(&mut buf[..len]).map_err(read_error())?;
+            target.write_all_at(&buf[..len], pos).map_err(io_error(format!("Unable to write {}", disk.display())))?;
+            pos += len as u64;
+        }
+        merged += hole - data;
+        offset = hole;
+    }
+    target.sync_all().map_err(io_error(format!("Unable to sync {}", disk.display())))?;
+    Ok(merged)
+}
+
+/// Largest overlay a member accepts for a disk of `disk_size`: every
+/// cluster allocated, plus room for the qcow2 tables
+pub fn max_overlay_size(disk_size: u64) -> u64 {
+    disk_size + (disk_size >> 6) + CHUNK_SIZE
+}
+
+/// Directory a member keeps a commit's overlay in, once it is prepared for it
+fn member_dir(id: &str, commit_id: &str) -> Result<PathBuf, VmmError> {
+    uuid::Uuid::parse_str(commit_id)
+        .map_err(|_| VmmError::OperationFailed(format!("{commit_id} is not a valid commit id")))?;
+    Ok(commit_dir(id, commit_id))
+}
+
+fn vm_api(id: &str) -> Result<FormVmApi, VmmError> {
+    let socket_path = api_socket_path(id);
+    if !socket_path.exists() {
+        return Err(VmmError::VmNotFound(id.to_string()));
+    }
+    Ok(FormVmApi::new(&socket_path.display().to_string()))
+}
+
+async fn boot(api: &FormVmApi) -> Result<(), VmmError> {
+    api.boot().await
+        .and_then(|resp| expect_success("vm.boot", resp))
+        .map(|_| ())
+        .map_err(|e| VmmError::OperationFailed(e.to_string()))
+}
+
+/// Checks that the node coordinating a commit of `id` runs a replica of it
+pub async fn authorize_coordinator(id: &str, node_id: &str) -> Result<(), VmmError> {
+    let instances = ScalingExecutor::new(None).get_instances(id).await?;
+    if !instances.iter().any(|instance| instance.node_id.eq_ignore_ascii_case(node_id)) {
+        return Err(VmmError::Config(format!("Unauthorized: {node_id} does not run a replica of {id}")));
+    }
+    Ok(())
+}
+
+/// Shuts down this node's member of a commit and digests its disk
+pub async fn prepare_member(id: &str, commit_id: &str) -> Result<DiskDigests, VmmError> {
+    let dir = member_dir(id, commit_id)?;
+    let api = vm_api(id)?;
+    tokio::fs::create_dir_all(&dir).await.map_err(io_error(format!("Unable to create {}", dir.display())))?;
+    if let Err(e) = api.shutdown_vm().await.and_then(|resp| expect_success("vm.shutdown", resp)) {
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        return Err(VmmError::OperationFailed(e.to_string()));
+    }
+
+    let disk = rootfs_path(id);
+    let digests = tokio::task::spawn_blocking(move || disk_digests(&disk)).await
+        .map_err(|e| VmmError::SystemError(e.to_string()))
+        .and_then(|digests| digests);
+    if digests.is_err() {
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        if let Err(e) = boot(&api).await {
+            log::error!("Unable to boot {id} after failed commit {commit_id}: {e}");
+        }
+    }
+    digests
+}
+
+/// Receives an overlay for a prepared member, merges it into the VM's disk
+/// and boots the VM. The overlay has to match `expected`, the digest the
+/// coordinator signed.
+pub async fn apply_member<S, E>(id: &str, commit_id: &str, expected: [u8; 32], mut body: S) -> Result<u64, VmmError>
+where
+    S: Stream<Item = Result<Bytes, E>> + Unpin,
+    E: std::fmt::Display,
+{
+    let dir = member_dir(id, commit_id)?;
+    if !dir.exists() {
+        return Err(VmmError::OperationFailed(format!("{id} was not prepared for commit {commit_id}")));
+    }
+    let api = vm_api(id)?;
+    let disk = rootfs_path(id);
+    let overlay = dir.join("overlay.qcow2");
+
+    let received = async {
+        let limit = max_overlay_size(tokio::fs::metadata(&disk).await
+            .map_err(io_error(format!("Unable to stat {}", disk.display())))?.len());
+        let mut file = tokio::fs::File::create(&overlay).await
+            .map_err(io_error(format!("Unable to create {}", overlay.display())))?;
+        let mut hasher = Sha3::v256();
+        let mut written = 0;
+        while let Some(chunk) = body.next().await {
+            let chunk = chunk.map_err(|e| VmmError::NetworkError(e.to_string()))?;
+            written += chunk.len() as u64;
+            if written > limit {
+                return Err(VmmError::OperationFailed(format!("Overlay is larger than a disk of {} could need", disk.display())));
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await.map_err(io_error(format!("Unable to write {}", overlay.display())))?;
+        }
+        let mut received = [0u8; 32];
+        hasher.finalize(&mut received);
+        if received != expected {
+            return Err(VmmError::OperationFailed("Overlay does not match the digest it was signed with".to_string()));
+        }
+        file.sync_all().await.map_err(io_error(format!("Unable to write {}", overlay.display())))
+    }.await;
+    if let Err(e) = received {
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+        if let Err(e) = boot(&api).await {
+            log::error!("Unable to boot {id} after failed commit {commit_id}: {e}");
+        }
+        return Err(e);
+    }
+
+    let task_overlay = overlay.clone();
+    let merged = tokio::task::spawn_blocking(move || merge_overlay(&task_overlay, &disk)).await
+        .map_err(|e| VmmError::SystemError(e.to_string()))
+        .and_then(|merged| merged);
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    let merged = merged.map_err(|e| VmmError::OperationFailed(
+        format!("{e}; {id} is left shut down as its disk may be partly updated")
+    ))?;
+
+    boot(&api).await?;
+    Ok(merged)
+}
+
+/// Boots a prepared member again on its old disk
+pub async fn abort_member(id: &str, commit_id: &str) -> Result<(), VmmError> {
+    let dir = member_dir(id, commit_id)?;
+    if !dir.exists() {
+        return Err(VmmError::OperationFailed(format!("{id} was not prepared for commit {commit_id}")));
+    }
+    let _ = tokio::fs::remove_dir_all(&dir).await;
+    boot(&vm_api(id)?).await
+}
+
+/// Registers a commit of `request.id`, refusing it while another commit of
+/// the same instance is still running
+pub async fn begin_commit(request: &CommitVmRequest, commit_id: &str) -> Result<(), VmmError> {
+    let mut commits = COMMITS.lock().await;
+    if commits.values().any(|status| status.id == request.id && !status.phase.is_terminal()) {
+        return Err(VmmError::OperationFailed(format!("A commit of {} is already in progress", request.id)));
+    }
+
+    let finished = commits.values().filter(|status| status.phase.is_terminal()).count();
+    if finished >= MAX_FINISHED_COMMITS {
+        let oldest = commits.values()
+            .filter(|status| status.phase.is_terminal())
+            .min_by_key(|status| status.started_at)
+            .map(|status| status.commit_id.clone());
+        if let Some(oldest) = oldest {
+            commits.remove(&oldest);
+        }
+    }
+
+    let timestamp = now();
+    commits.insert(commit_id.to_string(), CommitStatus {
+        commit_id: commit_id.to_string(),
+        id: request.id.clone(),
+        description: request.description.clone(),
+        phase: CommitPhase::Snapshotting,
+        members: vec![],
+        started_at: timestamp,
+        updated_at: timestamp,
+    });
+    Ok(())
+}
+
+pub async fn commit_status(commit_id: &str) -> Option<CommitStatus> {
+    COMMITS.lock().await.get(commit_id).cloned()
+}
+
+async fn update_status<F: FnOnce(&mut CommitStatus)>(commit_id: &str, update: F) {
+    if let Some(status) = COMMITS.lock().await.get_mut(commit_id) {
+        update(status);
+        status.updated_at = now();
+    }
+}
+
+async fn set_member(commit_id: &str, instance_id: &str, state: MemberState, changed_bytes: Option<u64>) {
+    update_status(commit_id, |status| {
+        if let Some(member) = status.members.iter_mut().find(|m| m.instance_id == instance_id) {
+            member.state = state;
+            member.changed_bytes = changed_bytes.or(member.changed_bytes);
+        }
+    }).await
+}
+
+/// Runs a commit from the node holding the modified instance: snapshots its
+/// disk, then updates every other member of its cluster in turn.
+pub struct CommitExecutor {
+    client: Client,
+    state_url: String,
+}
+
+impl CommitExecutor {
+    pub fn new(state_url: Option<String>) -> Self {
+        Self {
+            client: Client::new(),
+            state_url: state_url.unwrap_or_else(|| STATE_URL.to_string()),
+        }
+    }
+
+    /// Runs a commit registered with `begin_commit` to completion. `node_id`
+    /// is this node, which runs the modified instance.
+    pub async fn execute(&self, request: CommitVmRequest, commit_id: String, node_id: String) -> Result<(), VmmError> {
+        let dir = commit_dir(&request.id, &commit_id);
+        let result = self.run(&request, &commit_id, &node_id, &dir).await;
+        let _ = tokio::fs::remove_dir_all(&dir).await;
+
+        let phase = match &result {
+            Ok(_) => CommitPhase::Completed,
+            Err(e) => CommitPhase::Failed(e.to_string()),
+        };
+        update_status(&commit_id, |status| status.phase = phase).await;
+        result
+    }
+
+    async fn run(&self, request: &CommitVmRequest, commit_id: &str, node_id: &str, dir: &Path) -> Result<(), VmmError> {
+        let scaling = ScalingExecutor::new(Some(self.state_url.clone()));
+        let instances = scaling.get_instances(&request.id).await?;
+        let source = instances.iter()
+            .find(|instance| instance.node_id == node_id)
+            .ok_or(VmmError::VmNotFound(request.id.clone()))?;
+        let members: Vec<ClusterMember> = source.cluster.members.values()
+            .filter(|member| member.node_id != node_id)
+            .cloned()
+            .collect();
+        update_status(commit_id, |status| {
+            status.members = members.iter().map(|member| MemberCommit {
+                instance_id: member.instance_id.clone(),
+                node_id: member.node_id.clone(),
+                state: MemberState::Pending,
+                changed_bytes: None,
+            }).collect();
+        }).await;
+
+        tokio::fs::create_dir_all(dir).await.map_err(io_error(format!("Unable to create {}", dir.display())))?;
+        let snapshot = dir.join("disk.qcow2");
+        self.snapshot(&request.id, &snapshot).await?;
+        self.record_commit(&scaling, source, commit_id).await;
+        update_status(commit_id, |status| status.phase = CommitPhase::RollingOut).await;
+
+        let mut failed = None;
+        for member in &members {
+            if failed.is_some() {
+                set_member(commit_id, &member.instance_id, MemberState::Skipped, None).await;
+                continue;
+            }
+            match self.commit_member(request, commit_id, member, &snapshot, dir).await {
+                Ok(changed) => {
+                    log::info!("Commit {commit_id} changed {changed} bytes of {}", member.instance_id);
+                    set_member(commit_id, &member.instance_id, MemberState::Committed, Some(changed)).await;
+                    if let Some(instance) = instances.iter().find(|i| i.instance_id == member.instance_id) {
+                        self.record_commit(&scaling, instance, commit_id).await;
+                    }
+                }
+                Err(e) => {
+                    log::error!("Commit {commit_id} failed on {}: {e}", member.instance_id);
+                    set_member(commit_id, &member.instance_id, MemberState::Failed(e.to_string()), None).await;
+                    failed = Some(member.instance_id.clone());
+                }
+            }
+        }
+
+        match failed {
+            Some(instance_id) => Err(VmmError::OperationFailed(format!("Rollout stopped at {instance_id}"))),
+            None => Ok(()),
+        }
+    }
+
+    /// Pauses the VM while its disk is copied, so the copy is consistent,
+    /// and resumes it whether or not the copy succeeded
+    async fn snapshot(&self, id: &str, dest: &Path) -> Result<(), VmmError> {
+        let api = vm_api(id)?;
+        api.pause().await
+            .and_then(|resp| expect_success("vm.pause", resp))
+            .map_err(|e| VmmError::OperationFailed(e.to_string()))?;
+
+        let disk = rootfs_path(id);
+        let task_dest = dest.to_path_buf();
+        let copied = tokio::task::spawn_blocking(move || snapshot_disk(&disk, &task_dest)).await
+            .map_err(|e| VmmError::SystemError(e.to_string()))
+            .and_then(|copied| copied);
+
+        if let Err(e) = api.resume().await.and_then(|resp| expect_success("vm.resume", resp)) {
+            log::error!("Unable to resume {id} after copying its disk: {e}");
+        }
+        copied
+    }
+
+    async fn commit_member(
+        &self,
+        request: &CommitVmRequest,
+        commit_id: &str,
+        member: &ClusterMember,
+        snapshot: &Path,
+        dir: &Path,
+    ) -> Result<u64, VmmError> {
+        let host = format!("http://{}:{VMM_PORT}", member.node_formnet_ip);
+        let base = format!("/vm/{}/commit/{commit_id}", request.id);
+
+        set_member(commit_id, &member.instance_id, MemberState::Stopping, None).await;
+        let digests = match self.post(&host, &format!("{base}/prepare"), request).await? {
+            CommitResponse::Prepared(digests) => digests,
+            CommitResponse::Failure(reason) => return Err(VmmError::OperationFailed(reason)),
+            resp => return Err(VmmError::OperationFailed(format!("Unexpected response to prepare: {resp:?}"))),
+        };
+
+        // The member's VM is down from here on
+        set_member(commit_id, &member.instance_id, MemberState::Updating, None).await;
+        let overlay = dir.join(format!("{}.qcow2", member.instance_id));
+        let task_snapshot = snapshot.to_path_buf();
+        let task_overlay = overlay.clone();
+        let built = tokio::task::spawn_blocking(move || build_overlay(&task_snapshot, &task_overlay, &digests)).await
+            .map_err(|e| VmmError::SystemError(e.to_string()))
+            .and_then(|built| built);
+        if let Err(e) = built {
+            let _ = tokio::fs::remove_file(&overlay).await;
+            self.abort(&host, &base, request, &member.instance_id).await;
+            return Err(e);
+        }
+
+        let applied = self.upload(&host, &format!("{base}/apply"), &overlay).await;
+        let _ = tokio::fs::remove_file(&overlay).await;
+        match applied {
+            Ok(CommitResponse::Applied { bytes }) => Ok(bytes),
+            Ok(CommitResponse::Failure(reason)) => Err(VmmError::OperationFailed(reason)),
+            Ok(resp) => Err(VmmError::OperationFailed(format!("Unexpected response to apply: {resp:?}"))),
+            Err(e) => {
+                // The upload may not have arrived; make sure the VM comes back
+                self.abort(&host, &base, request, &member.instance_id).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn abort(&self, host: &str, base: &str, request: &CommitVmRequest, instance_id: &str) {
+        match self.post(host, &format!("{base}/abort"), request).await {
+            Ok(CommitResponse::Aborted) => {}
+            Ok(resp) => log::error!("Unable to boot {instance_id} after failed commit: {resp:?}"),
+            Err(e) => log::error!("Unable to boot {instance_id} after failed commit: {e}"),
+        }
+    }
+
+    /// Sends a step to the member at `host`, signed with the node key
+    async fn post(&self, host: &str, path: &str, request: &CommitVmRequest) -> Result<CommitResponse, VmmError> {
+        let body = serde_json::to_vec(request).map_err(|e| VmmError::Config(e.to_string()))?;
+        let signature = sign_node_request(path, &body)?;
+        let mut req = self.client.post(format!("{host}{path}"))
+            .header("content-type", "application/json")
+            .body(body);
+        for (name, value) in signature.headers() {
+            req = req.header(name, value);
+        }
+        req.send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?
+            .json::<CommitResponse>().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))
+    }
+
+    /// Streams an overlay to the member at `host`, signed over its digest
+    async fn upload(&self, host: &str, path: &str, overlay: &Path) -> Result<CommitResponse, VmmError> {
+        let task_overlay = overlay.to_path_buf();
+        let digest = tokio::task::spawn_blocking(move || file_digest(&task_overlay)).await
+            .map_err(|e| VmmError::SystemError(e.to_string()))??;
+        let signature = sign_node_digest(path, &digest)?;
+        let file = tokio::fs::File::open(overlay).await
+            .map_err(io_error(format!("Unable to open {}", overlay.display())))?;
+        let mut req = self.client.post(format!("{host}{path}"))
+            .header(OVERLAY_DIGEST_HEADER, hex::encode(digest))
+            .body(reqwest::Body::from(file));
+        for (name, value) in signature.headers() {
+            req = req.header(name, value);
+        }
+        req.send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?
+            .json::<CommitResponse>().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))
+    }
+
+    /// Records the commit an instance's disk now matches. A failed write is
+    /// logged; the disk has been updated either way.
+    async fn record_commit(&self, scaling: &ScalingExecutor, instance: &Instance, commit_id: &str) {
+        let mut instance = instance.clone();
+        instance.metadata.annotations.build_commit = Some(commit_id.to_string());
+        instance.updated_at = now();
+        if let Err(e) = scaling.write_state(InstanceRequest::Update(instance)).await {
+            log::error!("Unable to record commit {commit_id}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disk(dir: &Path, name: &str, chunks: &[u8]) -> PathBuf {
+        let path = dir.join(name);
+        let mut file = File::create(&path).unwrap();
+        for fill in chunks {
+            file.write_all(&vec![*fill; CHUNK_SIZE as usize]).unwrap();
+        }
+        path
+    }
+
+    #[test]
+    fn test_overlay_round_trip() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = disk(dir.path(), "source.raw", &[1, 2, 0, 4]);
+        let member = disk(dir.path(), "member.raw", &[1, 9, 9, 4]);
+
+        let snapshot = dir.path().join("snapshot.qcow2");
+        snapshot_disk(&source, &snapshot).unwrap();
+        let overlay = dir.path().join("overlay.qcow2");
+        let changed = build_overlay(&snapshot, &overlay, &disk_digests(&member).unwrap()).unwrap();
+        assert_eq!(changed, 2 * CHUNK_SIZE);
+
+        assert_eq!(merge_overlay(&overlay, &member).unwrap(), 2 * CHUNK_SIZE);
+        assert_eq!(std::fs::read(&member).unwrap(), std::fs::read(&source).unwrap());
+    }
+
+    #[test]
+    fn test_file_digest() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = disk(dir.path(), "disk.raw", &[1, 2, 3]);
+        let digest = file_digest(&path).unwrap();
+        assert_eq!(digest, form_state::auth::node::body_digest(&std::fs::read(&path).unwrap()));
+    }
+
+    #[test]
+    fn test_build_overlay_refuses_other_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = disk(dir.path(), "source.raw", &[1, 2]);
+        let member = disk(dir.path(), "member.raw", &[1, 2, 3]);
+
+        let snapshot = dir.path().join("snapshot.qcow2");
+        snapshot_disk(&source, &snapshot).unwrap();
+        let overlay = dir.path().join("overlay.qcow2");
+        assert!(build_overlay(&snapshot, &overlay, &disk_digests(&member).unwrap()).is_err());
+    }
+}
@@ -0,0 +1,1141 @@
+//! Deploys: moving a domain from the instances of one build of a Formpack to
+//! instances of a newer build.
+//!
+//! The owner packs the new version under a new build id and sends a signed
+//! `DeployRequest` to any node. That node brings the new build up in batches
+//! on nodes picked by the `CapabilityMatcher`, one batch for a blue/green
+//! deploy. A batch has to pass a health gate before it is given traffic: its
+//! instances must boot, join the cluster and answer the optional HTTP check
+//! within the gate timeout. The domain's DNS record in form-state is then
+//! pointed at it, and the batch is watched for the observe period before the
+//! deploy moves on.
+//!
+//! A rolling deploy takes one old instance out of the record for every new
+//! one added; a blue/green deploy swaps every target at once. Old instances
+//! only leave the record while the deploy runs, and are deleted once every
+//! batch has passed, so rolling back never has to recreate them: the record
+//! is restored and the new build's instances are deleted again.
+//!
+//! Progress is recorded as a `ClusterDeploy` in the old build's cluster in
+//! form-state, and cached on the node running the deploy to serve
+//! `/deploy/:deploy_id`. When that node restarts it picks its unfinished
+//! deploys back up from the record. Instances are deleted on the node's own
+//! authority: the node running the deploy signs the request with its node
+//! key, and the node holding the instance checks it against the record.
+
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use axum::{body::Bytes, extract::{Path, State}, http::HeaderMap, Json};
+use form_dns::store::FormDnsRecord;
+use form_pack::capability_matcher::CapabilityMatcher;
+use form_pack::formfile::Formfile;
+use form_pack::manager::build_instance_id;
+use form_pack::scheduler::PlacementContext;
+use form_state::datastore::{DnsRequest, InstanceRequest};
+use form_state::instances::{ClusterDeploy, ClusterMember, Instance, InstanceStatus};
+use form_state::nodes::Node;
+use form_types::state::{Response, Success};
+use form_types::{
+    BatchState, CreateVmRequest, DeployBatch, DeployPhase, DeployRequest, DeployResponse,
+    DeployStatus, DeployStrategy, HealthCheck, VmResponse, VmmEvent, VmmResponse,
+};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use tokio::sync::Mutex;
+use crate::api::auth::{self, sign_node_request, NodeVerifier, SignatureVerifier};
+use crate::api::{request_receive, VmmApi, VmmApiChannel};
+use crate::error::VmmError;
+use crate::service::scaling::{cluster_of, select_nodes, template_of, ScalingExecutor};
+use crate::service::{now, STATE_URL, VMM_PORT};
+
+/// How often a batch is checked while it is gated or observed
+const CHECK_INTERVAL: Duration = Duration::from_secs(10);
+/// How long an instance has to answer its HTTP check
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+/// Failed checks in a row after which an observed instance is unhealthy
+const FAILURE_THRESHOLD: u32 = 3;
+/// Port given to DNS targets when the record has none to copy
+const DEFAULT_TARGET_PORT: u16 = 80;
+/// Finished deploys kept around for their status to be read
+const MAX_FINISHED_DEPLOYS: usize = 64;
+/// Attempts at deleting an instance. The deploy record the other node
+/// checks the request against is gossiped, and may not have reached it yet.
+const DELETE_ATTEMPTS: u32 = 3;
+/// Attempts at reading form-state for unfinished deploys on startup
+const RESUME_ATTEMPTS: u32 = 30;
+
+/// Deploys run by this node, by deploy id
+static DEPLOYS: Mutex<BTreeMap<String, ClusterDeploy>> = Mutex::const_new(BTreeMap::new());
+
+/// Sizes of the batches `count` new instances are rolled out in
+pub fn plan_batches(strategy: &DeployStrategy, count: u32) -> Vec<u32> {
+    let batch_size = match strategy {
+        DeployStrategy::Rolling { batch_size } => (*batch_size).max(1),
+        DeployStrategy::BlueGreen => count.max(1),
+    };
+    (0..count)
+        .step_by(batch_size as usize)
+        .map(|start| batch_size.min(count - start))
+        .collect()
+}
+
+/// The members a domain points at once `new` members have taken over in a
+/// rolling deploy: one old member leaves for every new one, in order
+pub fn serving_members(old: &[ClusterMember], new: &[ClusterMember]) -> Vec<ClusterMember> {
+    old.iter().skip(new.len()).chain(new).cloned().collect()
+}
+
+/// Points a record at `members`, keeping the ports it already uses
+pub fn route(record: &FormDnsRecord, members: &[ClusterMember]) -> FormDnsRecord {
+    let public_port = record.public_ip.first().map_or(DEFAULT_TARGET_PORT, |addr| addr.port());
+    let formnet_port = record.formnet_ip.first().map_or(DEFAULT_TARGET_PORT, |addr| addr.port());
+
+    let mut public_ip = Vec::new();
+    let mut formnet_ip = Vec::new();
+    for member in members {
+        let public = SocketAddr::new(member.node_public_ip, public_port);
+        if !public_ip.contains(&public) {
+            public_ip.push(public);
+        }
+        let formnet = SocketAddr::new(member.instance_formnet_ip, formnet_port);
+        if !formnet_ip.contains(&formnet) {
+            formnet_ip.push(formnet);
+        }
+    }
+
+    FormDnsRecord {
+        public_ip,
+        formnet_ip,
+        ..record.clone()
+    }
+}
+
+/// Whether a record points at any of `members`
+pub fn routes_to(record: &FormDnsRecord, members: &[ClusterMember]) -> bool {
+    members.iter().any(|member| {
+        record.formnet_ip.iter().any(|addr| addr.ip() == member.instance_formnet_ip)
+    })
+}
+
+/// Where a new instance is on its way to taking traffic
+#[derive(Clone, Debug, PartialEq)]
+pub enum Readiness {
+    Waiting(String),
+    Ready(ClusterMember),
+    Failed(String),
+}
+
+/// Whether an instance of the new build has booted and joined its cluster
+pub fn readiness(instances: &[Instance], instance_id: &str) -> Readiness {
+    let Some(instance) = instances.iter().find(|instance| instance.instance_id == instance_id) else {
+        return Readiness::Waiting(format!("{instance_id} has not been created yet"));
+    };
+
+    match instance.status {
+        InstanceStatus::Started => {}
+        InstanceStatus::Stopped | InstanceStatus::Killed | InstanceStatus::CriticalError => {
+            return Readiness::Failed(format!("{instance_id} is {}", instance.status));
+        }
+        _ => return Readiness::Waiting(format!("{instance_id} is {}", instance.status)),
+    }
+
+    match cluster_of(instances).and_then(|cluster| cluster.members.get(instance_id)) {
+        Some(member) if member.status == "Started" && member.heartbeats_skipped == 0 => {
+            Readiness::Ready(member.clone())
+        }
+        Some(member) => Readiness::Waiting(format!(
+            "{instance_id} is {} and has skipped {} heartbeats", member.status, member.heartbeats_skipped
+        )),
+        None => Readiness::Waiting(format!("{instance_id} has not joined the cluster")),
+    }
+}
+
+/// Checks a request against the cluster it deploys over, before anything
+/// is touched
+pub fn validate_request(request: &DeployRequest, current_members: usize) -> Result<(), String> {
+    if request.from_build_id == request.to_build_id {
+        return Err("A build cannot be deployed over itself".to_string());
+    }
+    if request.create_request.name != request.to_build_id {
+        return Err("Create request is for a different build".to_string());
+    }
+    if request.instances.unwrap_or(current_members as u32) == 0 {
+        return Err("A deploy needs at least one instance of the new build".to_string());
+    }
+    if request.strategy == (DeployStrategy::Rolling { batch_size: 0 }) {
+        return Err("Batch size must be at least 1".to_string());
+    }
+    if request.gate_timeout_secs == 0 {
+        return Err("Gate timeout must be at least one second".to_string());
+    }
+    if let Some(check) = &request.health_check {
+        if !check.path.starts_with('/') {
+            return Err(format!("Health check path {} must start with /", check.path));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the create request for the new build was signed by the owner
+/// who signed the deploy, so a deploy can't bring a build up under someone
+/// else's name
+pub fn check_create_owner(request: &DeployRequest, signer: &str) -> Result<(), String> {
+    // The create request signature covers the first 32 bytes of the formfile
+    if request.create_request.formfile.len() < 32 {
+        return Err("Create request has an invalid formfile".to_string());
+    }
+    let owner = VmmApi::extract_owner_from_create_request(request.create_request.clone())
+        .map_err(|e| format!("Create request signature verification failed: {e}"))?;
+    if !owner.eq_ignore_ascii_case(signer) {
+        return Err(format!("Unauthorized: Create request is signed by {owner}, not {signer}"));
+    }
+    Ok(())
+}
+
+/// A node-authenticated request from the node running a deploy to delete
+/// the local instance of one of the deploy's builds
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DeployMemberRequest {
+    pub deploy_id: String,
+    /// Build the deploy moves away from, whose cluster holds its record
+    pub from_build_id: String,
+    /// Build whose instance is deleted
+    pub build_id: String,
+}
+
+/// Checks a delete request from `node_id` against the deploy recorded in
+/// the old build's cluster. The new build's instances may be deleted while
+/// the deploy runs, the old build's only once the deploy is retiring them.
+pub fn check_deploy_delete(deploy: &ClusterDeploy, request: &DeployMemberRequest, node_id: &str) -> Result<(), String> {
+    let status = &deploy.status;
+    if status.deploy_id != request.deploy_id || status.from_build_id != request.from_build_id {
+        return Err(format!("Deploy {} is not recorded for {}", request.deploy_id, request.from_build_id));
+    }
+    if !deploy.node_id.eq_ignore_ascii_case(node_id) {
+        return Err(format!("Unauthorized: node {node_id} is not running deploy {}", request.deploy_id));
+    }
+
+    if request.build_id == status.to_build_id && !status.phase.is_terminal() {
+        Ok(())
+    } else if request.build_id == status.from_build_id && status.phase == DeployPhase::Retiring {
+        Ok(())
+    } else {
+        Err(format!("Deploy {} in phase {:?} cannot delete {}", request.deploy_id, status.phase, request.build_id))
+    }
+}
+
+/// Registers a deploy, refusing it while another deploy of either build is
+/// still running
+pub async fn begin_deploy(request: &DeployRequest, deploy_id: &str, node_id: &str) -> Result<(), VmmError> {
+    let mut deploys = DEPLOYS.lock().await;
+    let builds = [&request.from_build_id, &request.to_build_id];
+    let busy = deploys.values().map(|deploy| &deploy.status).any(|status| {
+        !status.phase.is_terminal()
+            && (builds.contains(&&status.from_build_id) || builds.contains(&&status.to_build_id))
+    });
+    if busy {
+        return Err(VmmError::OperationFailed(format!(
+            "A deploy of {} or {} is already in progress", request.from_build_id, request.to_build_id
+        )));
+    }
+
+    let finished = deploys.values().filter(|deploy| deploy.status.phase.is_terminal()).count();
+    if finished >= MAX_FINISHED_DEPLOYS {
+        let oldest = deploys.values()
+            .map(|deploy| &deploy.status)
+            .filter(|status| status.phase.is_terminal())
+            .min_by_key(|status| status.started_at)
+            .map(|status| status.deploy_id.clone());
+        if let Some(oldest) = oldest {
+            deploys.remove(&oldest);
+        }
+    }
+
+    let timestamp = now();
+    deploys.insert(deploy_id.to_string(), ClusterDeploy {
+        node_id: node_id.to_string(),
+        status: DeployStatus {
+            deploy_id: deploy_id.to_string(),
+            from_build_id: request.from_build_id.clone(),
+            to_build_id: request.to_build_id.clone(),
+            domain: request.domain.clone(),
+            strategy: request.strategy.clone(),
+            phase: DeployPhase::RollingOut,
+            batches: vec![],
+            serving: vec![],
+            started_at: timestamp,
+            updated_at: timestamp,
+        },
+        original_record: None,
+        created: vec![],
+    });
+    Ok(())
+}
+
+pub async fn find_deploy(deploy_id: &str) -> Option<DeployStatus> {
+    DEPLOYS.lock().await.get(deploy_id).map(|deploy| deploy.status.clone())
+}
+
+async fn deploy_record(deploy_id: &str) -> Option<ClusterDeploy> {
+    DEPLOYS.lock().await.get(deploy_id).cloned()
+}
+
+/// Runs a deploy from whichever node received it
+pub struct DeployExecutor {
+    client: Client,
+    matcher: CapabilityMatcher,
+    scaling: ScalingExecutor,
+    state_url: String,
+}
+
+impl DeployExecutor {
+    pub fn new(state_url: Option<String>) -> Self {
+        let state_url = state_url.unwrap_or_else(|| STATE_URL.to_string());
+        Self {
+            client: Client::new(),
+            matcher: CapabilityMatcher::new(Some(state_url.clone())),
+            scaling: ScalingExecutor::new(Some(state_url.clone())),
+            state_url,
+        }
+    }
+
+    /// Runs a deploy registered with `begin_deploy` to completion, rolling
+    /// it back if any batch fails
+    pub async fn execute(&self, request: DeployRequest, deploy_id: String) -> DeployPhase {
+        let phase = match self.run(&request, &deploy_id).await {
+            Ok(()) => self.retire(&deploy_id).await,
+            Err(e) => self.roll_back(&deploy_id, e.to_string()).await,
+        };
+        self.finish(&deploy_id, phase).await
+    }
+
+    /// Finishes a deploy this node was running when it stopped. A deploy
+    /// that was retiring the old build goes on doing so; any other is rolled
+    /// back, as nothing has watched its batches since.
+    pub async fn recover(&self, deploy: ClusterDeploy) -> DeployPhase {
+        let deploy_id = deploy.status.deploy_id.clone();
+        let retiring = deploy.status.phase == DeployPhase::Retiring;
+        DEPLOYS.lock().await.insert(deploy_id.clone(), deploy);
+
+        let phase = if retiring {
+            self.retire(&deploy_id).await
+        } else {
+            self.roll_back(&deploy_id, "The node running the deploy restarted".to_string()).await
+        };
+        self.finish(&deploy_id, phase).await
+    }
+
+    async fn finish(&self, deploy_id: &str, phase: DeployPhase) -> DeployPhase {
+        self.update(deploy_id, |deploy| deploy.status.phase = phase.clone()).await;
+        phase
+    }
+
+    /// Applies `update` to a deploy and records the result in the old
+    /// build's cluster. A failed write is logged; the next update carries
+    /// the full record again.
+    async fn update<F: FnOnce(&mut ClusterDeploy)>(&self, deploy_id: &str, update: F) {
+        let deploy = {
+            let mut deploys = DEPLOYS.lock().await;
+            let Some(deploy) = deploys.get_mut(deploy_id) else {
+                return;
+            };
+            update(deploy);
+            deploy.status.updated_at = now();
+            deploy.clone()
+        };
+
+        let build_id = deploy.status.from_build_id.clone();
+        if let Err(e) = self.scaling.update_cluster(&build_id, move |cluster| {
+            cluster.deploy = Some(deploy.clone());
+        }).await {
+            log::error!("Unable to record progress of deploy {deploy_id} in {build_id}: {e}");
+        }
+    }
+
+    async fn set_batch(&self, deploy_id: &str, index: usize, state: BatchState) {
+        self.update(deploy_id, |deploy| {
+            if let Some(batch) = deploy.status.batches.get_mut(index) {
+                batch.state = state;
+            }
+        }).await
+    }
+
+    async fn set_serving(&self, deploy_id: &str, members: &[ClusterMember]) {
+        let serving = members.iter().map(|member| member.instance_id.clone()).collect();
+        self.update(deploy_id, |deploy| deploy.status.serving = serving).await
+    }
+
+    /// Records the domain's record before its first change, so a rollback
+    /// can restore it
+    async fn keep_original(&self, deploy_id: &str, record: &FormDnsRecord) {
+        self.update(deploy_id, |deploy| {
+            deploy.original_record.get_or_insert_with(|| record.clone());
+        }).await
+    }
+
+    async fn run(&self, request: &DeployRequest, deploy_id: &str) -> Result<(), VmmError> {
+        let instances = self.scaling.get_instances(&request.from_build_id).await?;
+        let old: Vec<ClusterMember> = cluster_of(&instances)
+            .map(|cluster| cluster.members.values().cloned().collect())
+            .unwrap_or_default();
+        let count = request.instances.unwrap_or(old.len() as u32);
+
+        let formfile: Formfile = serde_json::from_str(&request.create_request.formfile)
+            .map_err(|e| VmmError::Config(format!("Unable to parse formfile of {}: {e}", request.to_build_id)))?;
+        let record = self.get_record(&request.domain).await?;
+        if !routes_to(&record, &old) {
+            return Err(VmmError::OperationFailed(format!(
+                "{} does not point at any instance of {}", request.domain, request.from_build_id
+            )));
+        }
+
+        let batches = plan_batches(&request.strategy, count);
+        self.update(deploy_id, |deploy| {
+            deploy.status.batches = batches.iter().map(|size| DeployBatch {
+                instance_ids: vec![],
+                size: *size,
+                state: BatchState::Pending,
+            }).collect();
+            deploy.status.serving = old.iter().map(|member| member.instance_id.clone()).collect();
+        }).await;
+
+        let mut new = Vec::new();
+        for (index, size) in batches.into_iter().enumerate() {
+            if let Err(e) = self.roll_out_batch(request, deploy_id, &formfile, &record, &old, &mut new, index, size).await {
+                log::error!("Deploy {deploy_id} failed in batch {index}: {e}");
+                self.set_batch(deploy_id, index, BatchState::Failed(e.to_string())).await;
+                return Err(e);
+            }
+        }
+
+        // Old members still serving when the new build has fewer instances
+        if matches!(request.strategy, DeployStrategy::Rolling { .. }) && old.len() > new.len() {
+            self.keep_original(deploy_id, &record).await;
+            self.write_record(route(&record, &new)).await?;
+            self.set_serving(deploy_id, &new).await;
+        }
+        Ok(())
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn roll_out_batch(
+        &self,
+        request: &DeployRequest,
+        deploy_id: &str,
+        formfile: &Formfile,
+        record: &FormDnsRecord,
+        old: &[ClusterMember],
+        new: &mut Vec<ClusterMember>,
+        index: usize,
+        size: u32,
+    ) -> Result<(), VmmError> {
+        self.set_batch(deploy_id, index, BatchState::Creating).await;
+        let allocation = self.allocate_nodes(&request.to_build_id, formfile, size as usize).await?;
+        let ids: Vec<String> = allocation.iter().map(|(_, id)| id.clone()).collect();
+        self.update(deploy_id, |deploy| {
+            if let Some(batch) = deploy.status.batches.get_mut(index) {
+                batch.instance_ids = ids.clone();
+            }
+        }).await;
+
+        for (node, instance_id) in allocation {
+            let host = node.host.to_string();
+            // Recorded before the request, so that a restart while it is in
+            // flight still rolls the instance back
+            let created = (host.clone(), instance_id.clone());
+            self.update(deploy_id, |deploy| deploy.created.push(created.clone())).await;
+            if let Err(e) = self.create_vm(&host, &request.create_request).await {
+                self.update(deploy_id, |deploy| deploy.created.retain(|entry| *entry != created)).await;
+                return Err(e);
+            }
+            log::info!("Deploy {deploy_id} requested instance {instance_id} on node {}", node.node_id);
+        }
+
+        self.set_batch(deploy_id, index, BatchState::Gating).await;
+        let members = self.gate(request, &ids).await?;
+        new.extend(members);
+
+        let serving = match request.strategy {
+            DeployStrategy::Rolling { .. } => serving_members(old, new),
+            DeployStrategy::BlueGreen => new.clone(),
+        };
+        self.keep_original(deploy_id, record).await;
+        self.write_record(route(record, &serving)).await?;
+        self.set_serving(deploy_id, &serving).await;
+
+        self.set_batch(deploy_id, index, BatchState::Observing).await;
+        self.observe(request, new).await?;
+        self.set_batch(deploy_id, index, BatchState::Healthy).await;
+        Ok(())
+    }
+
+    /// Picks `count` capable nodes that do not already host an instance of
+    /// the new build
+    async fn allocate_nodes(&self, build_id: &str, formfile: &Formfile, count: usize) -> Result<Vec<(Node, String)>, VmmError> {
+        let nodes = self.matcher.get_capable_nodes(formfile).await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+        let cluster = self.scaling.get_instances(build_id).await.ok()
+            .and_then(|instances| cluster_of(&instances).cloned())
+            .unwrap_or_default();
+
+        let ctx = PlacementContext::new(build_id, formfile);
+        select_nodes(&self.matcher, nodes, &cluster, &ctx, count)
+            .map_err(|e| VmmError::OperationFailed(e.message))?
+            .into_iter()
+            .map(|node| {
+                let instance_id = build_instance_id(node.node_id.clone(), build_id.to_string())
+                    .map_err(|e| VmmError::OperationFailed(e.to_string()))?;
+                Ok((node, instance_id))
+            })
+            .collect()
+    }
+
+    async fn create_vm(&self, host: &str, create_request: &CreateVmRequest) -> Result<(), VmmError> {
+        let endpoint = format!("http://{host}:{VMM_PORT}/vm/create");
+        let resp = self.client.post(&endpoint)
+            .json(create_request)
+            .send().await
+            .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))?
+            .json::<VmmResponse>().await
+            .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))?;
+
+        match resp {
+            VmmResponse::Success(_) => Ok(()),
+            VmmResponse::Failure(reason) => Err(VmmError::OperationFailed(format!("{host}: {reason}"))),
+        }
+    }
+
+    /// Asks the vmm-service on `host` to delete its instance of `build_id`,
+    /// signed with the node key. The request is retried while the other
+    /// node refuses it, as the deploy record it is checked against may not
+    /// have reached that node yet.
+    async fn delete_member(&self, host: &str, deploy: &ClusterDeploy, build_id: &str) -> Result<(), VmmError> {
+        let path = format!("/deploy/{}/member/delete", deploy.status.deploy_id);
+        let body = serde_json::to_vec(&DeployMemberRequest {
+            deploy_id: deploy.status.deploy_id.clone(),
+            from_build_id: deploy.status.from_build_id.clone(),
+            build_id: build_id.to_string(),
+        }).map_err(|e| VmmError::Config(e.to_string()))?;
+        let endpoint = format!("http://{host}:{VMM_PORT}{path}");
+
+        let mut attempt = 1;
+        loop {
+            let signature = sign_node_request(&path, &body)?;
+            let mut req = self.client.post(&endpoint)
+                .header("content-type", "application/json")
+                .body(body.clone());
+            for (name, value) in signature.headers() {
+                req = req.header(name, value);
+            }
+
+            let result = match req.send().await {
+                Ok(resp) => match resp.json::<VmmResponse>().await {
+                    Ok(VmmResponse::Success(_)) => Ok(()),
+                    Ok(VmmResponse::Failure(reason)) => Err(VmmError::OperationFailed(format!("{host}: {reason}"))),
+                    Err(e) => Err(VmmError::NetworkError(format!("{endpoint}: {e}"))),
+                },
+                Err(e) => Err(VmmError::NetworkError(format!("{endpoint}: {e}"))),
+            };
+            match result {
+                Ok(()) => return Ok(()),
+                Err(e) if attempt >= DELETE_ATTEMPTS => return Err(e),
+                Err(e) => log::warn!("Attempt {attempt} at deleting {build_id} on {host} failed: {e}"),
+            }
+            attempt += 1;
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Waits for every instance of a batch to become ready and pass its
+    /// check, failing as soon as one of them fails outright
+    async fn gate(&self, request: &DeployRequest, ids: &[String]) -> Result<Vec<ClusterMember>, VmmError> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(request.gate_timeout_secs);
+        loop {
+            let instances = self.scaling.get_instances(&request.to_build_id).await.unwrap_or_default();
+            let mut ready = Vec::new();
+            let mut waiting = None;
+            for id in ids {
+                match readiness(&instances, id) {
+                    Readiness::Ready(member) => match self.check(&member, request.health_check.as_ref()).await {
+                        Ok(()) => ready.push(member),
+                        Err(reason) => waiting = Some(reason),
+                    },
+                    Readiness::Waiting(reason) => waiting = Some(reason),
+                    Readiness::Failed(reason) => return Err(VmmError::OperationFailed(reason)),
+                }
+            }
+
+            if ready.len() == ids.len() {
+                return Ok(ready);
+            }
+            if tokio::time::Instant::now() >= deadline {
+                return Err(VmmError::OperationFailed(format!(
+                    "Batch did not pass its health gate within {} seconds: {}",
+                    request.gate_timeout_secs,
+                    waiting.unwrap_or_default(),
+                )));
+            }
+            tokio::time::sleep(CHECK_INTERVAL).await;
+        }
+    }
+
+    /// Watches the new build's serving instances for the observe period.
+    /// An instance fails once it has failed `FAILURE_THRESHOLD` checks in a
+    /// row, or at once if it stops or errors.
+    async fn observe(&self, request: &DeployRequest, members: &[ClusterMember]) -> Result<(), VmmError> {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(request.observe_secs);
+        let mut failures: BTreeMap<&str, u32> = BTreeMap::new();
+        while tokio::time::Instant::now() < deadline {
+            tokio::time::sleep(CHECK_INTERVAL.min(deadline.saturating_duration_since(tokio::time::Instant::now()))).await;
+            let instances = match self.scaling.get_instances(&request.to_build_id).await {
+                Ok(instances) => instances,
+                Err(e) => {
+                    log::warn!("Unable to look up {} while observing it: {e}", request.to_build_id);
+                    continue;
+                }
+            };
+
+            for member in members {
+                let id = member.instance_id.as_str();
+                let result = match readiness(&instances, id) {
+                    Readiness::Ready(member) => self.check(&member, request.health_check.as_ref()).await,
+                    Readiness::Waiting(reason) => Err(reason),
+                    Readiness::Failed(reason) => return Err(VmmError::OperationFailed(reason)),
+                };
+                match result {
+                    Ok(()) => {
+                        failures.remove(id);
+                    }
+                    Err(reason) => {
+                        let failed = failures.entry(id).or_default();
+                        *failed += 1;
+                        if *failed >= FAILURE_THRESHOLD {
+                            return Err(VmmError::OperationFailed(format!(
+                                "{id} failed {failed} checks in a row: {reason}"
+                            )));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn check(&self, member: &ClusterMember, check: Option<&HealthCheck>) -> Result<(), String> {
+        let Some(check) = check else {
+            return Ok(());
+        };
+        let url = format!("http://{}{}", SocketAddr::new(member.instance_formnet_ip, check.port), check.path);
+        let resp = self.client.get(&url)
+            .timeout(CHECK_TIMEOUT)
+            .send().await
+            .map_err(|e| format!("{url}: {e}"))?;
+        if resp.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("{url} returned {}", resp.status()))
+        }
+    }
+
+    /// Deletes the old build's instances once the new build serves the
+    /// domain. The deploy is not rolled back if this fails; the old
+    /// instances just keep running outside of the record.
+    async fn retire(&self, deploy_id: &str) -> DeployPhase {
+        self.update(deploy_id, |deploy| deploy.status.phase = DeployPhase::Retiring).await;
+        let Some(deploy) = deploy_record(deploy_id).await else {
+            return DeployPhase::Failed(format!("Deploy {deploy_id} is not known to this node"));
+        };
+        let status = &deploy.status;
+        let members = match self.scaling.get_instances(&status.from_build_id).await {
+            Ok(instances) => cluster_of(&instances)
+                .map(|cluster| cluster.members.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default(),
+            Err(e) => return DeployPhase::Failed(format!("Deployed, but unable to look up {}: {e}", status.from_build_id)),
+        };
+
+        let mut errors = Vec::new();
+        for member in members {
+            let host = member.node_public_ip.to_string();
+            if let Err(e) = self.delete_member(&host, &deploy, &status.from_build_id).await {
+                errors.push(format!("{}: {e}", member.instance_id));
+                continue;
+            }
+            if let Err(e) = self.scaling.write_state(InstanceRequest::RemoveClusterMember {
+                build_id: status.from_build_id.clone(),
+                cluster_member_id: member.instance_id.clone(),
+            }).await {
+                log::error!("Error removing {} from its cluster: {e}", member.instance_id);
+            }
+        }
+
+        if errors.is_empty() {
+            DeployPhase::Completed
+        } else {
+            DeployPhase::Failed(format!(
+                "{} serves {}, but not every instance of {} could be deleted: {}",
+                status.to_build_id, status.domain, status.from_build_id, errors.join("; ")
+            ))
+        }
+    }
+
+    /// Points the domain back at the old build and deletes every instance
+    /// the deploy created
+    async fn roll_back(&self, deploy_id: &str, reason: String) -> DeployPhase {
+        let Some(deploy) = deploy_record(deploy_id).await else {
+            return DeployPhase::Failed(reason);
+        };
+        if deploy.original_record.is_none() && deploy.created.is_empty() {
+            return DeployPhase::Failed(reason);
+        }
+
+        self.update(deploy_id, |deploy| deploy.status.phase = DeployPhase::RollingBack(reason.clone())).await;
+        let status = &deploy.status;
+        let mut errors = Vec::new();
+
+        if let Some(original) = &deploy.original_record {
+            match self.write_record(original.clone()).await {
+                Ok(()) => {
+                    let old = self.scaling.get_instances(&status.from_build_id).await.ok()
+                        .and_then(|instances| cluster_of(&instances).map(|cluster| cluster.members.values().cloned().collect::<Vec<_>>()))
+                        .unwrap_or_default();
+                    self.set_serving(deploy_id, &old).await;
+                }
+                Err(e) => errors.push(format!("restoring {}: {e}", status.domain)),
+            }
+        }
+
+        for (host, instance_id) in &deploy.created {
+            log::info!("Rolling back instance {instance_id} of deploy {deploy_id}");
+            if let Err(e) = self.delete_member(host, &deploy, &status.to_build_id).await {
+                errors.push(format!("deleting {instance_id}: {e}"));
+            }
+            if let Err(e) = self.scaling.write_state(InstanceRequest::RemoveClusterMember {
+                build_id: status.to_build_id.clone(),
+                cluster_member_id: instance_id.clone(),
+            }).await {
+                log::error!("Error removing {instance_id} from its cluster during rollback: {e}");
+            }
+        }
+
+        if errors.is_empty() {
+            DeployPhase::RolledBack(reason)
+        } else {
+            DeployPhase::Failed(format!("{reason}; rollback incomplete: {}", errors.join("; ")))
+        }
+    }
+
+    async fn get_record(&self, domain: &str) -> Result<FormDnsRecord, VmmError> {
+        let resp = self.client.get(format!("{}/dns/{domain}/get", self.state_url))
+            .send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?
+            .json::<Response<FormDnsRecord>>().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        match resp {
+            Response::Success(Success::Some(record)) => Ok(record),
+            Response::Success(_) => Err(VmmError::OperationFailed(format!("No DNS record for {domain}"))),
+            Response::Failure { reason } => Err(VmmError::OperationFailed(reason.unwrap_or_default())),
+        }
+    }
+
+    async fn write_record(&self, record: FormDnsRecord) -> Result<(), VmmError> {
+        let request = DnsRequest::Update(record);
+
+        #[cfg(not(feature = "devnet"))]
+        VmmApi::write_to_queue(request, 3, "state").await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        #[cfg(feature = "devnet")]
+        self.client.post(format!("{}/dns/update", self.state_url))
+            .json(&request)
+            .send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    async fn list_instances(&self) -> Result<Vec<Instance>, VmmError> {
+        let resp = self.client.get(format!("{}/instance/list", self.state_url))
+            .send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?
+            .json::<Response<Instance>>().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        match resp {
+            Response::Success(Success::List(instances)) => Ok(instances),
+            Response::Success(_) => Ok(vec![]),
+            Response::Failure { reason } => Err(VmmError::OperationFailed(reason.unwrap_or_default())),
+        }
+    }
+}
+
+/// Picks up the deploys `node_id` was running when it stopped, from the
+/// records in form-state, and finishes them
+pub async fn resume_deploys(node_id: String) {
+    let executor = DeployExecutor::new(None);
+    let mut attempt = 1;
+    let instances = loop {
+        match executor.list_instances().await {
+            Ok(instances) => break instances,
+            Err(e) if attempt >= RESUME_ATTEMPTS => {
+                log::error!("Unable to look up unfinished deploys: {e}");
+                return;
+            }
+            Err(_) => {
+                attempt += 1;
+                tokio::time::sleep(CHECK_INTERVAL).await;
+            }
+        }
+    };
+
+    // Every instance of a build carries the same record
+    let deploys: BTreeMap<String, ClusterDeploy> = instances.into_iter()
+        .filter_map(|instance| instance.cluster.deploy)
+        .filter(|deploy| deploy.node_id.eq_ignore_ascii_case(&node_id) && !deploy.status.phase.is_terminal())
+        .map(|deploy| (deploy.status.deploy_id.clone(), deploy))
+        .collect();
+
+    for (deploy_id, deploy) in deploys {
+        log::info!("Resuming deploy {deploy_id} of {} in phase {:?}", deploy.status.to_build_id, deploy.status.phase);
+        let phase = executor.recover(deploy).await;
+        log::info!("Deploy {deploy_id} finished: {phase:?}");
+    }
+}
+
+/// Starts deploying a new build over `build_id`. The deploy runs in the
+/// background; poll `/deploy/:deploy_id` for its progress.
+pub async fn deploy_build(
+    Path(build_id): Path<String>,
+    Json(request): Json<DeployRequest>,
+) -> Json<DeployResponse> {
+    if request.from_build_id != build_id {
+        return Json(DeployResponse::Failure("Build id in path and request do not match".to_string()));
+    }
+
+    let Some(signature) = &request.signature else {
+        return Json(DeployResponse::Failure("Signature is required".to_string()));
+    };
+
+    let signer = match SignatureVerifier::verify_signature(request.signing_message(), signature, request.recovery_id) {
+        Ok(signer) => signer,
+        Err(e) => return Json(DeployResponse::Failure(format!("Signature verification failed: {e}"))),
+    };
+    if let Err(reason) = check_create_owner(&request, &signer) {
+        return Json(DeployResponse::Failure(reason));
+    }
+
+    let executor = DeployExecutor::new(None);
+    let instances = match executor.scaling.get_instances(&build_id).await {
+        Ok(instances) => instances,
+        Err(e) => return Json(DeployResponse::Failure(e.to_string())),
+    };
+
+    let owner = template_of(&instances).map(|instance| instance.instance_owner.to_lowercase());
+    if owner.as_deref() != Some(signer.to_lowercase().as_str()) {
+        return Json(DeployResponse::Failure(format!("Unauthorized: Address {signer} is not the owner of {build_id}")));
+    }
+
+    // A deploy run by another node is only known from its record
+    let running = cluster_of(&instances)
+        .and_then(|cluster| cluster.deploy())
+        .filter(|deploy| !deploy.status.phase.is_terminal());
+    if let Some(deploy) = running {
+        return Json(DeployResponse::Failure(format!(
+            "Deploy {} of {build_id} is already in progress on node {}", deploy.status.deploy_id, deploy.node_id
+        )));
+    }
+
+    let members = cluster_of(&instances).map_or(0, |cluster| cluster.members.len());
+    if let Err(reason) = validate_request(&request, members) {
+        return Json(DeployResponse::Failure(reason));
+    }
+
+    let node_id = match auth::node_id() {
+        Ok(node_id) => node_id,
+        Err(e) => return Json(DeployResponse::Failure(e.to_string())),
+    };
+    let deploy_id = uuid::Uuid::new_v4().to_string();
+    if let Err(e) = begin_deploy(&request, &deploy_id, &node_id).await {
+        return Json(DeployResponse::Failure(e.to_string()));
+    }
+
+    let task_deploy_id = deploy_id.clone();
+    tokio::spawn(async move {
+        let phase = executor.execute(request, task_deploy_id.clone()).await;
+        log::info!("Deploy {task_deploy_id} finished: {phase:?}");
+    });
+
+    Json(DeployResponse::Accepted { deploy_id })
+}
+
+/// Returns the progress of a deploy run by this node
+pub async fn deployment_status(Path(deploy_id): Path<String>) -> Json<DeployResponse> {
+    match find_deploy(&deploy_id).await {
+        Some(status) => Json(DeployResponse::Status(status)),
+        None => Json(DeployResponse::Failure(format!("Deploy {deploy_id} not found on this node"))),
+    }
+}
+
+/// Verifies a delete request from the node running a deploy
+async fn authorize_deploy_delete(
+    deploy_id: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<DeployMemberRequest, String> {
+    let path = format!("/deploy/{deploy_id}/member/delete");
+    let node_id = NodeVerifier::verify(headers, &path, body).await
+        .map_err(|e| format!("Node authentication failed: {e}"))?;
+    let request: DeployMemberRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid deploy member request: {e}"))?;
+    if request.deploy_id != deploy_id {
+        return Err("Deploy id in path and request do not match".to_string());
+    }
+
+    let scaling = ScalingExecutor::new(None);
+    let old = scaling.get_instances(&request.from_build_id).await
+        .map_err(|e| e.to_string())?;
+    let deploy = cluster_of(&old)
+        .and_then(|cluster| cluster.deploy())
+        .ok_or_else(|| format!("No deploy is recorded for {}", request.from_build_id))?;
+    check_deploy_delete(deploy, &request, &node_id)?;
+
+    // Nodes write the record, so it only lets a deploy delete builds owned
+    // by the owner of the build it deploys over
+    if request.build_id != request.from_build_id {
+        let new = scaling.get_instances(&request.build_id).await
+            .map_err(|e| e.to_string())?;
+        let owner = |instances: &[Instance]| template_of(instances).map(|instance| instance.instance_owner.to_lowercase());
+        if owner(&old) != owner(&new) {
+            return Err(format!("Unauthorized: {} and {} have different owners", request.from_build_id, request.build_id));
+        }
+    }
+    Ok(request)
+}
+
+/// Deletes this node's instance of a build for the node running a deploy
+pub async fn delete_deploy_member(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Path(deploy_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<VmmResponse> {
+    let request = match authorize_deploy_delete(&deploy_id, &headers, &body).await {
+        Ok(request) => request,
+        Err(e) => return Json(VmmResponse::Failure(e)),
+    };
+
+    let event = VmmEvent::Delete { id: request.build_id.clone() };
+    if let Err(e) = request_receive::<()>(channel, event).await {
+        return Json(VmmResponse::Failure(e));
+    }
+
+    Json(VmmResponse::Success(VmResponse {
+        id: request.build_id.clone(),
+        name: request.build_id,
+        state: "pending".to_string(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use form_state::instances::InstanceCluster;
+
+    fn member(instance_id: &str, public_ip: &str, formnet_ip: &str) -> ClusterMember {
+        ClusterMember {
+            node_id: format!("node-{instance_id}"),
+            node_public_ip: public_ip.parse().unwrap(),
+            node_formnet_ip: public_ip.parse().unwrap(),
+            instance_id: instance_id.to_string(),
+            instance_formnet_ip: formnet_ip.parse().unwrap(),
+            status: "Started".to_string(),
+            last_heartbeat: 0,
+            heartbeats_skipped: 0,
+        }
+    }
+
+    fn record(members: &[ClusterMember]) -> FormDnsRecord {
+        let record: FormDnsRecord = serde_json::from_value(serde_json::json!({
+            "domain": "app.example.com",
+            "record_type": "A",
+            "public_ip": [],
+            "formnet_ip": [],
+            "cname_target": null,
+            "ssl_cert": false,
+            "ttl": 3600,
+            "verification_status": null,
+            "verification_timestamp": null,
+        })).unwrap();
+        route(&record, members)
+    }
+
+    #[test]
+    fn test_plan_batches() {
+        assert_eq!(plan_batches(&DeployStrategy::Rolling { batch_size: 2 }, 5), vec![2, 2, 1]);
+        assert_eq!(plan_batches(&DeployStrategy::Rolling { batch_size: 4 }, 3), vec![3]);
+        assert_eq!(plan_batches(&DeployStrategy::BlueGreen, 5), vec![5]);
+        assert!(plan_batches(&DeployStrategy::BlueGreen, 0).is_empty());
+    }
+
+    #[test]
+    fn test_rolling_shifts_one_target_per_new_member() {
+        let old = vec![
+            member("a", "1.1.1.1", "10.0.0.1"),
+            member("b", "2.2.2.2", "10.0.0.2"),
+            member("c", "3.3.3.3", "10.0.0.3"),
+        ];
+        let new = vec![member("x", "4.4.4.4", "10.0.1.1")];
+
+        let serving: Vec<String> = serving_members(&old, &new).into_iter().map(|m| m.instance_id).collect();
+        assert_eq!(serving, vec!["b", "c", "x"]);
+
+        let more: Vec<ClusterMember> = ["x", "y", "z", "w"].iter()
+            .map(|id| member(id, "4.4.4.4", "10.0.1.1"))
+            .collect();
+        assert_eq!(serving_members(&old, &more).len(), 4);
+    }
+
+    #[test]
+    fn test_route_keeps_ports_and_dedups_hosts() {
+        let old = vec![member("a", "1.1.1.1", "10.0.0.1")];
+        let mut original = record(&old);
+        original.public_ip = vec!["1.1.1.1:443".parse().unwrap()];
+        assert!(routes_to(&original, &old));
+
+        // Two new instances on the same node share a public target
+        let new = vec![
+            member("x", "4.4.4.4", "10.0.1.1"),
+            member("y", "4.4.4.4", "10.0.1.2"),
+        ];
+        let routed = route(&original, &new);
+        assert_eq!(routed.domain, original.domain);
+        assert_eq!(routed.public_ip, vec!["4.4.4.4:443".parse().unwrap()]);
+        assert_eq!(routed.formnet_ip, vec!["10.0.1.1:80".parse().unwrap(), "10.0.1.2:80".parse().unwrap()]);
+        assert!(!routes_to(&routed, &old));
+    }
+
+    #[test]
+    fn test_readiness() {
+        let mut started = Instance::default();
+        started.instance_id = "x".to_string();
+        started.status = InstanceStatus::Started;
+        started.cluster = InstanceCluster {
+            members: [("x".to_string(), member("x", "4.4.4.4", "10.0.1.1"))].into_iter().collect(),
+            ..Default::default()
+        };
+        let mut failed = started.clone();
+        failed.instance_id = "y".to_string();
+        failed.status = InstanceStatus::CriticalError;
+        let mut booting = started.clone();
+        booting.instance_id = "z".to_string();
+        booting.status = InstanceStatus::Created;
+        let instances = vec![started, failed, booting];
+
+        assert!(matches!(readiness(&instances, "x"), Readiness::Ready(_)));
+        assert!(matches!(readiness(&instances, "y"), Readiness::Failed(_)));
+        assert!(matches!(readiness(&instances, "z"), Readiness::Waiting(_)));
+        assert!(matches!(readiness(&instances, "missing"), Readiness::Waiting(_)));
+    }
+
+    #[test]
+    fn test_check_deploy_delete() {
+        let mut deploy = ClusterDeploy {
+            node_id: "node-a".to_string(),
+            status: DeployStatus {
+                deploy_id: "deploy".to_string(),
+                from_build_id: "old".to_string(),
+                to_build_id: "new".to_string(),
+                domain: "app.example.com".to_string(),
+                strategy: DeployStrategy::BlueGreen,
+                phase: DeployPhase::RollingBack("failed".to_string()),
+                batches: vec![],
+                serving: vec![],
+                started_at: 0,
+                updated_at: 0,
+            },
+            original_record: None,
+            created: vec![],
+        };
+        let request = |build_id: &str| DeployMemberRequest {
+            deploy_id: "deploy".to_string(),
+            from_build_id: "old".to_string(),
+            build_id: build_id.to_string(),
+        };
+
+        assert!(check_deploy_delete(&deploy, &request("new"), "NODE-A").is_ok());
+        assert!(check_deploy_delete(&deploy, &request("new"), "node-b").is_err());
+        assert!(check_deploy_delete(&deploy, &request("old"), "node-a").is_err());
+        assert!(check_deploy_delete(&deploy, &request("other"), "node-a").is_err());
+
+        let mut other = request("new");
+        other.deploy_id = "other".to_string();
+        assert!(check_deploy_delete(&deploy, &other, "node-a").is_err());
+
+        deploy.status.phase = DeployPhase::Retiring;
+        assert!(check_deploy_delete(&deploy, &request("old"), "node-a").is_ok());
+
+        deploy.status.phase = DeployPhase::Completed;
+        assert!(check_deploy_delete(&deploy, &request("old"), "node-a").is_err());
+        assert!(check_deploy_delete(&deploy, &request("new"), "node-a").is_err());
+    }
+
+    #[test]
+    fn test_deploy_signature_covers_request() {
+        use crate::service::scaling::tests::sign;
+
+        let key = k256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let owner = format!("{:x}", alloy_primitives::Address::from_private_key(&key));
+        let formfile = serde_json::json!({ "name": "app", "padding": "x".repeat(32) }).to_string();
+        let (create_signature, create_recovery_id) = sign(
+            &key,
+            &format!("CreateVmRequest:new:{}", hex::encode(&formfile.as_bytes()[0..32])),
+        );
+        let mut request = DeployRequest {
+            from_build_id: "old".to_string(),
+            to_build_id: "new".to_string(),
+            domain: "app.example.com".to_string(),
+            strategy: DeployStrategy::Rolling { batch_size: 1 },
+            instances: None,
+            health_check: None,
+            gate_timeout_secs: 60,
+            observe_secs: 60,
+            create_request: CreateVmRequest {
+                name: "new".to_string(),
+                formfile,
+                signature: Some(create_signature),
+                recovery_id: create_recovery_id,
+            },
+            signature: None,
+            recovery_id: 0,
+        };
+        let (signature, recovery_id) = sign(&key, &request.signing_message());
+        request.signature = Some(signature.clone());
+        request.recovery_id = recovery_id;
+
+        let signer = |request: &DeployRequest| SignatureVerifier::verify_signature(
+            request.signing_message(),
+            &signature,
+            recovery_id,
+        ).ok();
+        assert_eq!(signer(&request), Some(owner.clone()));
+        assert!(check_create_owner(&request, &owner).is_ok());
+
+        // Changing any field after signing recovers someone else
+        let mut retargeted = request.clone();
+        retargeted.domain = "other.example.com".to_string();
+        assert_ne!(signer(&retargeted), Some(owner.clone()));
+        let mut restrategized = request.clone();
+        restrategized.strategy = DeployStrategy::BlueGreen;
+        assert_ne!(signer(&restrategized), Some(owner.clone()));
+
+        // A create request signed by someone else is rejected
+        let other = k256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let other = format!("{:x}", alloy_primitives::Address::from_private_key(&other));
+        assert!(check_create_owner(&request, &other).is_err());
+        let mut short = request.clone();
+        short.create_request.formfile = "{}".to_string();
+        assert!(check_create_owner(&short, &owner).is_err());
+    }
+}
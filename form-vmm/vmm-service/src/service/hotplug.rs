@@ -0,0 +1,174 @@
+//! Online resize and device hotplug of running VMs.
+//!
+//! A vCPU or memory resize is recorded in form-state before it is applied,
+//! which checks it against the owner's compute quota, and whatever the VM
+//! grows by has to fit in what this node has free. If cloud-hypervisor then
+//! refuses the resize, the previous size is recorded again.
+//!
+//! Hot-plugged disks are recorded on the instance too, against the owner's
+//! storage quota, and dropped from it when they are unplugged.
+//! Filesystems and passthrough devices are checked against this node's
+//! capacity but are not recorded; they don't hold any of the owner's
+//! storage and go away with the VMM.
+
+use std::path::{Path, PathBuf};
+use form_state::instances::{Instance, InstanceResources};
+use form_state::nodes::Node;
+use form_state::resize::InstanceResize;
+use form_types::state::{Response, Success};
+use reqwest::Client;
+use crate::config::ResourceLimits;
+use crate::error::VmmError;
+use crate::service::STATE_URL;
+
+/// Where the host lists the PCI devices that can be passed through
+const PCI_DEVICES: &str = "/sys/bus/pci/devices";
+
+/// How much a resize grows an instance by; dimensions it shrinks are zero
+pub fn growth(current: &InstanceResources, resized: &InstanceResources) -> InstanceResources {
+    InstanceResources {
+        vcpus: resized.vcpus.saturating_sub(current.vcpus),
+        memory_mb: resized.memory_mb.saturating_sub(current.memory_mb),
+        ..Default::default()
+    }
+}
+
+/// Checks a resize against the room the VM was created with
+pub fn within_limits(resized: &InstanceResources) -> Result<(), String> {
+    let limits = ResourceLimits::default();
+    if resized.vcpus > limits.max_vcpus_per_vm {
+        return Err(format!("VMs can have at most {} vCPUs", limits.max_vcpus_per_vm));
+    }
+    if resized.memory_mb as u64 > limits.max_memory_per_vm {
+        return Err(format!("VMs can have at most {} MiB of memory", limits.max_memory_per_vm));
+    }
+    Ok(())
+}
+
+/// Checks that a node has room for a disk image of `bytes`
+pub fn has_storage(node: &Node, bytes: u64) -> Result<(), String> {
+    let available = node.capacity.storage_available_bytes;
+    if available < bytes {
+        return Err(format!(
+            "Node has {} MiB of storage available, disk needs {}",
+            available / (1024 * 1024), bytes / (1024 * 1024)
+        ));
+    }
+    Ok(())
+}
+
+/// Creates an empty, sparse disk image for a VM, if the node has room for it
+pub fn create_disk(node: &Node, path: &Path, size_gb: u64) -> Result<(), VmmError> {
+    let limit = ResourceLimits::default().max_disk_size_per_vm;
+    if size_gb == 0 || size_gb > limit {
+        return Err(VmmError::OperationFailed(format!("Disks have to be between 1 and {limit} GB")));
+    }
+    let bytes = size_gb << 30;
+    has_storage(node, bytes).map_err(VmmError::OperationFailed)?;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| VmmError::OperationFailed(format!("Unable to create {}: {e}", dir.display())))?;
+    }
+    let file = std::fs::OpenOptions::new().write(true).create_new(true).open(path)
+        .map_err(|e| VmmError::OperationFailed(format!("Unable to create disk {}: {e}", path.display())))?;
+    file.set_len(bytes)
+        .map_err(|e| VmmError::OperationFailed(format!("Unable to size disk {}: {e}", path.display())))
+}
+
+/// Size of an existing disk image, rounded up to whole GB
+pub fn disk_size_gb(path: &Path) -> Result<u64, VmmError> {
+    let bytes = std::fs::metadata(path)
+        .map_err(|e| VmmError::OperationFailed(format!("Unable to read disk {}: {e}", path.display())))?
+        .len();
+    Ok(bytes.div_ceil(1 << 30))
+}
+
+/// Checks that `path` is a PCI device bound to vfio-pci, which is what
+/// cloud-hypervisor can pass through to a VM
+pub fn passthrough_device(path: &str) -> Result<PathBuf, String> {
+    let path = PathBuf::from(path);
+    let address = path.strip_prefix(PCI_DEVICES).ok()
+        .filter(|address| address.components().count() == 1)
+        .ok_or_else(|| format!("{} is not a PCI device under {PCI_DEVICES}", path.display()))?;
+
+    let driver = std::fs::read_link(path.join("driver")).ok();
+    if driver.as_ref().and_then(|driver| driver.file_name()).map_or(true, |name| name != "vfio-pci") {
+        return Err(format!("PCI device {} is not bound to vfio-pci", address.display()));
+    }
+    Ok(path)
+}
+
+/// Records a resize in form-state, which refuses it if it would take the
+/// owner over their quota
+pub async fn record_resize(resize: &InstanceResize) -> Result<Instance, VmmError> {
+    let resp = Client::new().post(format!("{STATE_URL}/instance/resize"))
+        .json(resize)
+        .send().await
+        .map_err(|e| VmmError::NetworkError(e.to_string()))?
+        .json::<Response<Instance>>().await
+        .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+    match resp {
+        Response::Success(Success::Some(instance)) => Ok(instance),
+        Response::Failure { reason } => Err(VmmError::OperationFailed(reason.unwrap_or_default())),
+        _ => Err(VmmError::OperationFailed(format!("Unable to record resize of {}", resize.instance_id))),
+    }
+}
+
+/// Drops a disk from the instance record once it has been unplugged, or
+/// when it could not be attached after all
+pub async fn record_detach(instance_id: &str, disk_id: &str) -> Result<Instance, VmmError> {
+    record_resize(&InstanceResize {
+        instance_id: instance_id.to_string(),
+        detach_disk: Some(disk_id.to_string()),
+        ..Default::default()
+    }).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resources(vcpus: u8, memory_mb: u32) -> InstanceResources {
+        InstanceResources { vcpus, memory_mb, ..Default::default() }
+    }
+
+    #[test]
+    fn test_growth() {
+        assert_eq!(growth(&resources(2, 2048), &resources(4, 1024)), resources(2, 0));
+        assert_eq!(growth(&resources(2, 2048), &resources(1, 4096)), resources(0, 2048));
+    }
+
+    #[test]
+    fn test_within_limits() {
+        assert!(within_limits(&resources(4, 4096)).is_ok());
+        assert!(within_limits(&resources(64, 4096)).is_err());
+        assert!(within_limits(&resources(4, 1 << 20)).is_err());
+    }
+
+    #[test]
+    fn test_passthrough_device_outside_pci_devices() {
+        assert!(passthrough_device("/dev/sda").is_err());
+        assert!(passthrough_device("/sys/bus/pci/devices/../../../../dev/sda").is_err());
+    }
+
+    #[test]
+    fn test_volume_path() {
+        assert_eq!(
+            crate::volume_path("vm", "data.raw"),
+            Some(PathBuf::from("/var/lib/formation/volumes/vm/data.raw"))
+        );
+        assert_eq!(crate::volume_path("vm", "../other/data.raw"), None);
+        assert_eq!(crate::volume_path("vm", ".."), None);
+        assert_eq!(crate::volume_path("vm", ""), None);
+    }
+
+    #[test]
+    fn test_has_storage() {
+        let mut node = Node::default();
+        node.capacity.storage_available_bytes = 10 << 30;
+        assert!(has_storage(&node, 1 << 30).is_ok());
+        assert!(has_storage(&node, 20 << 30).is_err());
+    }
+}
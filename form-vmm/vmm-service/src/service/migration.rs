@@ -0,0 +1,171 @@
+//! Live migration of VMs between Formation nodes.
+//!
+//! The operator sends a signed `MigrateVmRequest` to the node currently
+//! running the VM. That node forwards it to the destination's
+//! `/vm/:id/migrate_from`, where the destination checks that it can host
+//! the VM, starts an empty VMM and listens for the migration stream on its
+//! formnet address. The source then streams the VM across with
+//! cloud-hypervisor's `vm.send-migration`. Once the VM runs on the
+//! destination, the source records the move in form-state; if anything
+//! fails before that, the source VM is resumed and nothing is recorded.
+//!
+//! Only memory and device state are migrated. The VM's disk image has to
+//! be present on the destination under the same path, as it is for
+//! Formpacks built on every node the build is shipped to.
+
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::path::PathBuf;
+use std::time::Duration;
+use form_state::instances::InstanceResources;
+use form_state::nodes::Node;
+use form_types::state::{Response, Success};
+use form_types::MigrateVmRequest;
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use crate::error::VmmError;
+use crate::service::{STATE_URL, VMM_PORT};
+use crate::IMAGE_DIR;
+
+/// How long a destination waits for the source to start streaming the VM
+pub const MIGRATION_TIMEOUT: Duration = Duration::from_secs(1800);
+/// How long the source waits for the destination to get ready to receive
+const TARGET_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum MigrationResponse {
+    /// The source has started migrating the VM
+    Accepted {
+        id: String,
+        destination_node_id: String,
+    },
+    /// The destination is listening for the migration stream
+    Ready(MigrationTarget),
+    Failure(String),
+}
+
+/// Result of a `vm.send-migration` run in the background, reported back to
+/// the `VmManager` event loop so it can release or keep the local VMM
+#[derive(Debug)]
+pub struct MigrationOutcome {
+    pub request: MigrateVmRequest,
+    pub target: MigrationTarget,
+    pub result: Result<(), String>,
+}
+
+/// Where, and to which node, the source streams the VM
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MigrationTarget {
+    /// cloud-hypervisor receiver URL, `tcp:<formnet ip>:<port>`
+    pub receiver_url: String,
+    pub node_public_ip: IpAddr,
+    pub node_formnet_ip: IpAddr,
+}
+
+/// Checks that a node has room for an instance with the given resources
+pub fn has_capacity(node: &Node, resources: &InstanceResources) -> Result<(), String> {
+    let capacity = &node.capacity;
+    // Available cores are reported in thousandths of a core
+    if capacity.cpu_available_cores / 1000 < resources.vcpus as i64 {
+        return Err(format!(
+            "Node has {} cores available, instance needs {}",
+            capacity.cpu_available_cores / 1000, resources.vcpus
+        ));
+    }
+
+    let memory_bytes = resources.memory_mb as u64 * 1024 * 1024;
+    if capacity.memory_available_bytes < memory_bytes {
+        return Err(format!(
+            "Node has {} MiB of memory available, instance needs {}",
+            capacity.memory_available_bytes / (1024 * 1024), resources.memory_mb
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn receiver_url(ip: IpAddr, port: u16) -> String {
+    format!("tcp:{}", SocketAddr::new(ip, port))
+}
+
+/// Asks the OS for a free port on `ip` for the migration listener
+pub fn free_port(ip: IpAddr) -> std::io::Result<u16> {
+    Ok(TcpListener::bind(SocketAddr::new(ip, 0))?.local_addr()?.port())
+}
+
+/// Disk image the VM was created with, which a destination must already hold
+pub fn rootfs_path(name: &str) -> PathBuf {
+    PathBuf::from(IMAGE_DIR).join(name).with_extension("raw")
+}
+
+pub async fn get_node(node_id: &str) -> Result<Node, VmmError> {
+    let resp = Client::new().get(format!("{STATE_URL}/node/{node_id}/get"))
+        .send().await
+        .map_err(|e| VmmError::NetworkError(e.to_string()))?
+        .json::<Response<Node>>().await
+        .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+    match resp {
+        Response::Success(Success::Some(node)) => Ok(node),
+        Response::Failure { reason } => Err(VmmError::OperationFailed(reason.unwrap_or_default())),
+        _ => Err(VmmError::OperationFailed(format!("Node {node_id} not found"))),
+    }
+}
+
+/// Forwards the signed request to the destination and waits until it is
+/// ready to receive the VM
+pub async fn request_target(destination_formnet_ip: IpAddr, request: &MigrateVmRequest) -> Result<MigrationTarget, VmmError> {
+    let endpoint = format!("http://{}/vm/{}/migrate_from", SocketAddr::new(destination_formnet_ip, VMM_PORT), request.id);
+    let client = Client::builder()
+        .timeout(TARGET_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+    let resp = client.post(&endpoint)
+        .json(request)
+        .send().await
+        .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))?
+        .json::<MigrationResponse>().await
+        .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))?;
+
+    match resp {
+        MigrationResponse::Ready(target) => Ok(target),
+        MigrationResponse::Failure(reason) => Err(VmmError::OperationFailed(
+            format!("Destination {} refused migration: {reason}", request.destination_node_id)
+        )),
+        MigrationResponse::Accepted { .. } => Err(VmmError::OperationFailed(
+            format!("Destination {} sent an invalid response", request.destination_node_id)
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_capacity() {
+        let mut node = Node::default();
+        node.capacity.cpu_available_cores = 4000;
+        node.capacity.memory_available_bytes = 4096 * 1024 * 1024;
+
+        let fits = InstanceResources { vcpus: 2, memory_mb: 2048, ..Default::default() };
+        assert!(has_capacity(&node, &fits).is_ok());
+
+        let too_many_cores = InstanceResources { vcpus: 8, memory_mb: 2048, ..Default::default() };
+        assert!(has_capacity(&node, &too_many_cores).is_err());
+
+        let too_much_memory = InstanceResources { vcpus: 2, memory_mb: 8192, ..Default::default() };
+        assert!(has_capacity(&node, &too_much_memory).is_err());
+
+        // Cores are reported in millicores, so 3.999 free cores can't take 4 vCPUs
+        let all_cores = InstanceResources { vcpus: 4, memory_mb: 1024, ..Default::default() };
+        assert!(has_capacity(&node, &all_cores).is_ok());
+        node.capacity.cpu_available_cores = 3999;
+        assert!(has_capacity(&node, &all_cores).is_err());
+    }
+
+    #[test]
+    fn test_receiver_url() {
+        assert_eq!(receiver_url("10.0.0.5".parse().unwrap(), 6000), "tcp:10.0.0.5:6000");
+        assert_eq!(receiver_url("fd00::5".parse().unwrap(), 6000), "tcp:[fd00::5]:6000");
+    }
+}
@@ -1,2 +1,27 @@
 pub mod vmm;
+pub mod scaling;
+pub mod migration;
+pub mod hotplug;
+pub mod commit;
+pub mod deploy;
 pub use vmm::*;
+pub use scaling::*;
+pub use migration::*;
+pub use hotplug::*;
+pub use commit::*;
+pub use deploy::*;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Local form-state API
+pub(crate) const STATE_URL: &str = "http://127.0.0.1:3004";
+/// Port every node's vmm-service API listens on
+pub(crate) const VMM_PORT: u16 = 3002;
+
+/// Current unix time in seconds
+pub(crate) fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,1009 @@
+use std::collections::BTreeSet;
+use std::time::Duration;
+use axum::{body::Bytes, extract::{Path, State}, http::HeaderMap, Json};
+use form_pack::capability_matcher::CapabilityMatcher;
+use form_pack::formfile::Formfile;
+use form_pack::manager::build_instance_id;
+use form_pack::scheduler::PlacementContext;
+use form_state::autoscaler::AutoscaleRequest;
+use form_state::datastore::InstanceRequest;
+use form_state::instances::{ClusterMember, Instance, InstanceCluster};
+use form_state::nodes::Node;
+use form_state::scaling::{
+    PhaseData, ScalingError, ScalingManager, ScalingOperation, ScalingOperationRecord,
+    ScalingPhase, ScalingResources, VerificationResult,
+};
+use form_types::state::{Response, Success};
+use form_types::{CreateVmRequest, DeleteVmRequest, VmResponse, VmmEvent, VmmResponse};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use crate::api::auth::{sign_node_request, NodeVerifier, SignatureVerifier};
+use crate::api::{request_receive, VmmApi, VmmApiChannel};
+use crate::error::VmmError;
+use crate::service::{now, STATE_URL, VMM_PORT};
+
+/// How often to check whether new members have joined the cluster
+const MEMBERSHIP_POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// How long new members have to boot and join before the operation fails
+const MEMBERSHIP_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// A request to run a scaling operation on the cluster of a build.
+///
+/// When the owner scales a cluster, they sign a create and/or delete request
+/// for the build up front. Every member of a cluster runs the same build
+/// under the same name, so the same signed requests are valid on whichever
+/// nodes the executor picks, and the delete request is also what lets a
+/// failed scale out be rolled back.
+///
+/// Operations started by the autoscaler carry no owner requests. Members
+/// are then created and deleted through `/cluster/:build_id/member/*` with
+/// requests signed by the node key, which the other nodes only accept from
+/// the cluster's responsible node while autoscaling is enabled.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScaleClusterRequest {
+    pub build_id: String,
+    pub operation: ScalingOperation,
+    /// Signed create request for the build, required for owners to add members
+    pub create_request: Option<CreateVmRequest>,
+    /// Signed delete request for the build, required for owners to remove
+    /// members and to roll back members added by a failed operation
+    pub delete_request: Option<DeleteVmRequest>,
+    /// Owner signature over `ScaleClusterRequest:{build_id}:{operation}`,
+    /// with the operation serialized as JSON
+    pub signature: Option<String>,
+    pub recovery_id: u32,
+}
+
+/// A node-authenticated request to create or delete the local member of a
+/// build's cluster
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MemberRequest {
+    pub build_id: String,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ScalingStatus {
+    pub build_id: String,
+    pub current_phase: Option<ScalingPhase>,
+    pub operation: Option<ScalingOperationRecord>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ScalingResponse {
+    Accepted {
+        build_id: String,
+        operation: ScalingOperation,
+    },
+    Status(ScalingStatus),
+    Failure(String),
+}
+
+/// What an operation will change, worked out before anything is touched
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ScalingPlan {
+    /// Number of members to add
+    pub add: usize,
+    /// Members to remove
+    pub remove: Vec<ClusterMember>,
+}
+
+/// Drives a `ScalingManager` through every phase of a `ScalingOperation`:
+/// picks nodes with the `CapabilityMatcher`, creates and deletes VMs through
+/// the vmm-service API of the chosen nodes, and writes the manager, with a
+/// `PhaseRecord` per phase, back into form-state as it goes. If a phase
+/// fails, every VM created so far is deleted again before the operation is
+/// marked as failed and rolled back.
+pub struct ScalingExecutor {
+    client: Client,
+    matcher: CapabilityMatcher,
+    state_url: String,
+}
+
+impl ScalingExecutor {
+    pub fn new(state_url: Option<String>) -> Self {
+        let state_url = state_url.unwrap_or_else(|| STATE_URL.to_string());
+        Self {
+            client: Client::new(),
+            matcher: CapabilityMatcher::new(Some(state_url.clone())),
+            state_url,
+        }
+    }
+
+    /// Runs the operation to completion and returns the final state of the
+    /// manager. Errors from the operation itself are recorded in the manager;
+    /// `Err` is only returned if the operation could not be started.
+    pub async fn execute(&self, request: ScaleClusterRequest) -> Result<ScalingManager, VmmError> {
+        let build_id = request.build_id.clone();
+        let instances = self.get_instances(&build_id).await?;
+        let cluster = cluster_of(&instances)
+            .ok_or(VmmError::VmNotFound(build_id.clone()))?
+            .clone();
+
+        let mut manager = cluster.scaling_manager.clone().unwrap_or_else(ScalingManager::new);
+        manager.start_operation(request.operation.clone())
+            .map_err(|e| VmmError::OperationFailed(e.message))?;
+        self.checkpoint(&build_id, &manager).await;
+
+        let mut created = Vec::new();
+        if let Err(err) = self.run_phases(&request, &instances, &mut manager, &mut created).await {
+            log::error!("Scaling operation on {build_id} failed in {}: {}", err.phase, err.message);
+            if manager.current_phase().map_or(false, |phase| !phase.is_terminal()) {
+                let partial_results = serde_json::to_string(&created).ok();
+                if let Err(e) = manager.fail_operation(&err.error_type, &err.message, partial_results) {
+                    log::error!("Unable to mark scaling operation as failed: {}", e.message);
+                }
+            }
+            self.undo(&request, &created).await;
+            if let Err(e) = manager.rollback_operation() {
+                log::error!("Unable to roll back scaling operation on {build_id}: {}", e.message);
+            }
+        }
+
+        self.checkpoint(&build_id, &manager).await;
+        Ok(manager)
+    }
+
+    async fn run_phases(
+        &self,
+        request: &ScaleClusterRequest,
+        instances: &[Instance],
+        manager: &mut ScalingManager,
+        created: &mut Vec<(Node, String)>,
+    ) -> Result<(), ScalingError> {
+        let build_id = &request.build_id;
+        let template = template_of(instances).cloned().ok_or_else(|| {
+            phase_error("Validating", "ClusterNotFound", format!("No instances found for {build_id}"))
+        })?;
+        let cluster = template.cluster.clone();
+
+        // Validating
+        manager.transition_to_validating()?;
+        let validation = validate_request(request, &cluster);
+        manager.record_phase(PhaseData::Validating {}, validation.clone().err())?;
+        validation?;
+        self.checkpoint(build_id, manager).await;
+
+        // Planning
+        manager.transition_to_planning(None)?;
+        let plan = plan_operation(&request.operation, &cluster);
+        manager.record_phase(PhaseData::Planning { pre_metrics: None }, plan.clone().err())?;
+        let plan = plan?;
+        self.checkpoint(build_id, manager).await;
+
+        // ResourceAllocating
+        let formfile: Formfile = serde_json::from_str(&template.formfile).map_err(|e| {
+            phase_error("ResourceAllocating", "InvalidFormfile", format!("Unable to parse template formfile: {e}"))
+        })?;
+        let resources = ScalingResources {
+            cpu_cores: formfile.get_vcpus() as u32 * plan.add as u32,
+            memory_mb: formfile.get_memory() as u32 * plan.add as u32,
+            storage_gb: formfile.get_storage().unwrap_or(0) as u32 * plan.add as u32,
+            network_bandwidth_mbps: template.resources.bandwidth_mbps * plan.add as u32,
+        };
+        manager.transition_to_resource_allocating(Some(resources.clone()))?;
+        let allocation = self.allocate_nodes(build_id, &formfile, &cluster, plan.add).await;
+        let allocated_resource_ids = allocation.as_ref()
+            .map(|nodes| nodes.iter().map(|(_, id)| id.clone()).collect())
+            .unwrap_or_default();
+        manager.record_phase(
+            PhaseData::ResourceAllocating { resources: Some(resources), allocated_resource_ids },
+            allocation.as_ref().err().cloned(),
+        )?;
+        let allocation = allocation?;
+        self.checkpoint(build_id, manager).await;
+
+        // InstancePreparing
+        let new_ids: Vec<String> = allocation.iter().map(|(_, id)| id.clone()).collect();
+        let previous_members = serde_json::to_string(&cluster.members).ok();
+        manager.transition_to_instance_preparing(new_ids.clone())?;
+        let preparation = self.create_members(request, allocation, created).await;
+        manager.record_phase(
+            PhaseData::InstancePreparing { instance_ids: new_ids.clone(), instance_configs: previous_members.clone() },
+            preparation.as_ref().err().cloned(),
+        )?;
+        preparation?;
+        self.checkpoint(build_id, manager).await;
+
+        // Configuring
+        manager.transition_to_configuring(previous_members.clone())?;
+        let removed_ids: Vec<String> = plan.remove.iter().map(|m| m.instance_id.clone()).collect();
+        let configuration = self.remove_members(request, &plan.remove).await;
+        let applied_changes = serde_json::to_string(&serde_json::json!({
+            "added": new_ids,
+            "removed": removed_ids,
+        })).ok();
+        manager.record_phase(
+            PhaseData::Configuring { previous_config: previous_members, applied_changes },
+            configuration.as_ref().err().cloned(),
+        )?;
+        configuration?;
+        self.checkpoint(build_id, manager).await;
+
+        // Verifying
+        manager.transition_to_verifying()?;
+        let members = self.get_instances(build_id).await
+            .ok()
+            .and_then(|instances| cluster_of(&instances).map(|cluster| cluster.members.clone()))
+            .unwrap_or_default();
+        let now = now();
+        let test_results = vec![
+            VerificationResult {
+                test_name: "members_added".to_string(),
+                passed: new_ids.iter().all(|id| members.contains_key(id)),
+                details: format!("expected {} new members", new_ids.len()),
+                timestamp: now,
+            },
+            VerificationResult {
+                test_name: "members_removed".to_string(),
+                passed: removed_ids.iter().all(|id| !members.contains_key(id)),
+                details: format!("expected {} members to be removed", removed_ids.len()),
+                timestamp: now,
+            },
+        ];
+        for result in &test_results {
+            manager.add_verification_result(result.clone())?;
+        }
+        let verification = match test_results.iter().find(|result| !result.passed) {
+            Some(result) => Err(phase_error("Verifying", "VerificationFailed", format!("{} check failed", result.test_name))),
+            None => Ok(()),
+        };
+        manager.record_phase(PhaseData::Verifying { test_results }, verification.clone().err())?;
+        verification?;
+        self.checkpoint(build_id, manager).await;
+
+        // Finalizing
+        let cleanup_tasks = vec!["record_scaling_time".to_string()];
+        manager.transition_to_finalizing(cleanup_tasks.clone())?;
+        manager.record_phase(PhaseData::Finalizing { cleanup_tasks }, None)?;
+        manager.complete_operation(None)?;
+
+        let operation = request.operation.clone();
+        let completed = manager.clone();
+        self.update_cluster(build_id, move |cluster| {
+            match operation {
+                ScalingOperation::ScaleOut { .. } => cluster.record_scale_out(now),
+                ScalingOperation::ScaleIn { .. } => cluster.record_scale_in(now),
+                ScalingOperation::ReplaceInstances { .. } => {}
+            }
+            cluster.scaling_manager = Some(completed.clone());
+        }).await.map_err(|e| phase_error("Finalizing", "StateUpdateFailed", e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Picks `count` capable nodes that do not already host a member
+    async fn allocate_nodes(
+        &self,
+        build_id: &str,
+        formfile: &Formfile,
+        cluster: &InstanceCluster,
+        count: usize,
+    ) -> Result<Vec<(Node, String)>, ScalingError> {
+        if count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let nodes = self.matcher.get_capable_nodes(formfile).await.map_err(|e| {
+            phase_error("ResourceAllocating", "NodeLookupFailed", e.to_string())
+        })?;
+
+        let ctx = PlacementContext::new(build_id, formfile);
+        select_nodes(&self.matcher, nodes, cluster, &ctx, count)?
+            .into_iter()
+            .map(|node| {
+                let instance_id = build_instance_id(node.node_id.clone(), build_id.to_string())
+                    .map_err(|e| phase_error("ResourceAllocating", "InvalidInstanceId", e.to_string()))?;
+                Ok((node, instance_id))
+            })
+            .collect()
+    }
+
+    /// Asks each allocated node to create the VM, then waits for all of them
+    /// to boot and join the cluster
+    async fn create_members(
+        &self,
+        request: &ScaleClusterRequest,
+        allocation: Vec<(Node, String)>,
+        created: &mut Vec<(Node, String)>,
+    ) -> Result<(), ScalingError> {
+        if allocation.is_empty() {
+            return Ok(());
+        }
+
+        let pending: BTreeSet<String> = allocation.iter().map(|(_, id)| id.clone()).collect();
+        for (node, instance_id) in allocation {
+            let host = node.host.to_string();
+            let resp = match &request.create_request {
+                Some(create_request) => {
+                    let endpoint = format!("http://{host}:{VMM_PORT}/vm/create");
+                    self.client.post(&endpoint)
+                        .json(create_request)
+                        .send().await
+                        .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))?
+                        .json::<VmmResponse>().await
+                        .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))
+                }
+                None => self.member_request(&host, &request.build_id, "create").await,
+            }.map_err(|e| phase_error("InstancePreparing", "CreateFailed", e.to_string()))?;
+
+            match resp {
+                VmmResponse::Success(_) => {
+                    log::info!("Requested instance {instance_id} on node {}", node.node_id);
+                    created.push((node, instance_id));
+                }
+                VmmResponse::Failure(reason) => {
+                    return Err(phase_error("InstancePreparing", "CreateFailed", format!("node {}: {reason}", node.node_id)));
+                }
+            }
+        }
+
+        let deadline = tokio::time::Instant::now() + MEMBERSHIP_TIMEOUT;
+        loop {
+            if let Ok(instances) = self.get_instances(&request.build_id).await {
+                if let Some(cluster) = cluster_of(&instances) {
+                    if pending.iter().all(|id| cluster.members.contains_key(id)) {
+                        return Ok(());
+                    }
+                }
+            }
+
+            if tokio::time::Instant::now() >= deadline {
+                return Err(phase_error(
+                    "InstancePreparing",
+                    "MembershipTimeout",
+                    format!("New members did not join within {} seconds", MEMBERSHIP_TIMEOUT.as_secs()),
+                ));
+            }
+            tokio::time::sleep(MEMBERSHIP_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Deletes the VM behind each member and drops it from the cluster
+    async fn remove_members(&self, request: &ScaleClusterRequest, members: &[ClusterMember]) -> Result<(), ScalingError> {
+        if members.is_empty() {
+            return Ok(());
+        }
+
+        for member in members {
+            self.delete_member(&member.node_public_ip.to_string(), request).await
+                .map_err(|e| phase_error("Configuring", "DeleteFailed", e.to_string()))?;
+            self.write_state(InstanceRequest::RemoveClusterMember {
+                build_id: request.build_id.clone(),
+                cluster_member_id: member.instance_id.clone(),
+            }).await.map_err(|e| phase_error("Configuring", "StateUpdateFailed", e.to_string()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes every VM the operation created. Best effort: failures are
+    /// logged, since the operation has already failed.
+    async fn undo(&self, request: &ScaleClusterRequest, created: &[(Node, String)]) {
+        if created.is_empty() {
+            return;
+        }
+
+        for (node, instance_id) in created {
+            log::info!("Rolling back instance {instance_id} on node {}", node.node_id);
+            if let Err(e) = self.delete_member(&node.host.to_string(), request).await {
+                log::error!("Error deleting instance {instance_id} during rollback: {e}");
+            }
+            if let Err(e) = self.write_state(InstanceRequest::RemoveClusterMember {
+                build_id: request.build_id.clone(),
+                cluster_member_id: instance_id.clone(),
+            }).await {
+                log::error!("Error removing {instance_id} from cluster during rollback: {e}");
+            }
+        }
+    }
+
+    /// Deletes the member on `host` with the owner's delete request, or on
+    /// the node's own authority if the operation has none
+    async fn delete_member(&self, host: &str, request: &ScaleClusterRequest) -> Result<(), VmmError> {
+        let resp = match &request.delete_request {
+            Some(delete_request) => return self.delete_vm(host, &request.build_id, delete_request).await,
+            None => self.member_request(host, &request.build_id, "delete").await?,
+        };
+
+        match resp {
+            VmmResponse::Success(_) => Ok(()),
+            VmmResponse::Failure(reason) => Err(VmmError::OperationFailed(reason)),
+        }
+    }
+
+    /// Asks the vmm-service on `host` to create or delete its member of the
+    /// cluster, signed with the node key
+    async fn member_request(&self, host: &str, build_id: &str, action: &str) -> Result<VmmResponse, VmmError> {
+        let path = format!("/cluster/{build_id}/member/{action}");
+        let body = serde_json::to_vec(&MemberRequest { build_id: build_id.to_string() })
+            .map_err(|e| VmmError::Config(e.to_string()))?;
+        let signature = sign_node_request(&path, &body)?;
+
+        let endpoint = format!("http://{host}:{VMM_PORT}{path}");
+        let mut req = self.client.post(&endpoint)
+            .header("content-type", "application/json")
+            .body(body);
+        for (name, value) in signature.headers() {
+            req = req.header(name, value);
+        }
+        req.send().await
+            .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))?
+            .json::<VmmResponse>().await
+            .map_err(|e| VmmError::NetworkError(format!("{endpoint}: {e}")))
+    }
+
+    pub(crate) async fn delete_vm(&self, host: &str, build_id: &str, delete_request: &DeleteVmRequest) -> Result<(), VmmError> {
+        let endpoint = format!("http://{host}:{VMM_PORT}/vm/{build_id}/delete");
+        let resp = self.client.post(&endpoint)
+            .json(delete_request)
+            .send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?
+            .json::<VmmResponse>().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        match resp {
+            VmmResponse::Success(_) => Ok(()),
+            VmmResponse::Failure(reason) => Err(VmmError::OperationFailed(reason)),
+        }
+    }
+
+    /// Writes the manager into the cluster of every instance of the build.
+    /// A failed write is logged rather than aborting the operation; the next
+    /// checkpoint carries the full history again.
+    async fn checkpoint(&self, build_id: &str, manager: &ScalingManager) {
+        let manager = manager.clone();
+        if let Err(e) = self.update_cluster(build_id, move |cluster| {
+            cluster.scaling_manager = Some(manager.clone());
+        }).await {
+            log::error!("Unable to record scaling progress for {build_id}: {e}");
+        }
+    }
+
+    pub(crate) async fn update_cluster<F>(&self, build_id: &str, update: F) -> Result<(), VmmError>
+    where
+        F: Fn(&mut InstanceCluster),
+    {
+        for mut instance in self.get_instances(build_id).await? {
+            update(&mut instance.cluster);
+            instance.updated_at = now();
+            self.write_state(InstanceRequest::Update(instance)).await?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn write_state(&self, request: InstanceRequest) -> Result<(), VmmError> {
+        #[cfg(not(feature = "devnet"))]
+        VmmApi::write_to_queue(request, 4, "state").await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        #[cfg(feature = "devnet")]
+        self.client.post(format!("{}/instance/update", self.state_url))
+            .json(&request)
+            .send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    pub async fn get_instances(&self, build_id: &str) -> Result<Vec<Instance>, VmmError> {
+        let resp = self.client.get(format!("{}/instance/{build_id}/get_by_build_id", self.state_url))
+            .send().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?
+            .json::<Response<Instance>>().await
+            .map_err(|e| VmmError::NetworkError(e.to_string()))?;
+
+        match resp {
+            Response::Success(Success::List(instances)) if !instances.is_empty() => Ok(instances),
+            Response::Success(_) => Err(VmmError::VmNotFound(build_id.to_string())),
+            Response::Failure { reason } => Err(VmmError::OperationFailed(reason.unwrap_or_default())),
+        }
+    }
+
+    pub async fn status(&self, build_id: &str) -> Result<ScalingStatus, VmmError> {
+        let instances = self.get_instances(build_id).await?;
+        let manager = cluster_of(&instances).and_then(|cluster| cluster.scaling_manager.clone());
+        Ok(ScalingStatus {
+            build_id: build_id.to_string(),
+            current_phase: manager.as_ref().and_then(|m| m.current_phase().cloned()),
+            operation: manager.as_ref().and_then(|m| m.latest_operation().cloned()),
+        })
+    }
+}
+
+/// The instance scaling is based on: the cluster's template instance, or
+/// the first instance of the build if there is none
+pub(crate) fn template_of(instances: &[Instance]) -> Option<&Instance> {
+    let template_id = instances.first()?.cluster.template_instance_id.clone();
+    template_id
+        .and_then(|id| instances.iter().find(|instance| instance.instance_id == id))
+        .or(instances.first())
+}
+
+/// Every instance of a build carries a copy of the cluster
+pub(crate) fn cluster_of(instances: &[Instance]) -> Option<&InstanceCluster> {
+    template_of(instances).map(|instance| &instance.cluster)
+}
+
+fn validate_request(request: &ScaleClusterRequest, cluster: &InstanceCluster) -> Result<(), ScalingError> {
+    let current = cluster.members.len() as u32;
+    let invalid = |message: String| phase_error("Validating", "InvalidOperation", message);
+
+    let target = match &request.operation {
+        ScalingOperation::ScaleOut { target_instances } => {
+            if *target_instances <= current {
+                return Err(invalid(format!("Cluster already has {current} members, cannot scale out to {target_instances}")));
+            }
+            Some(*target_instances)
+        }
+        ScalingOperation::ScaleIn { target_instances, instance_ids } => {
+            if *target_instances >= current {
+                return Err(invalid(format!("Cluster has {current} members, cannot scale in to {target_instances}")));
+            }
+            if let Some(ids) = instance_ids {
+                if ids.len() as u32 != current - target_instances {
+                    return Err(invalid(format!("Removing {} instances would not leave {target_instances} members", ids.len())));
+                }
+            }
+            Some(*target_instances)
+        }
+        ScalingOperation::ReplaceInstances { instance_ids } => {
+            if instance_ids.is_empty() {
+                return Err(invalid("No instances to replace".to_string()));
+            }
+            None
+        }
+    };
+
+    if let (Some(target), Some(policy)) = (target, cluster.scaling_policy()) {
+        if target < policy.min_instances() || target > policy.max_instances() {
+            return Err(invalid(format!(
+                "Target of {target} instances is outside the policy range {}..={}",
+                policy.min_instances(), policy.max_instances()
+            )));
+        }
+    }
+
+    let ids = match &request.operation {
+        ScalingOperation::ScaleIn { instance_ids: Some(ids), .. } => ids.as_slice(),
+        ScalingOperation::ReplaceInstances { instance_ids } => instance_ids.as_slice(),
+        _ => &[],
+    };
+    for id in ids {
+        if !cluster.members.contains_key(id) {
+            return Err(invalid(format!("Instance {id} is not a member of the cluster")));
+        }
+        if cluster.template_instance_id.as_ref() == Some(id) {
+            return Err(invalid(format!("Cannot remove template instance {id}")));
+        }
+    }
+
+    // Owner requests are checked by `scale_cluster`; without them the
+    // operation runs on the node's authority
+    if let Some(create) = &request.create_request {
+        if create.name != request.build_id {
+            return Err(invalid("Create request is for a different build".to_string()));
+        }
+    }
+    if let Some(delete) = &request.delete_request {
+        if delete.id != request.build_id {
+            return Err(invalid("Delete request is for a different build".to_string()));
+        }
+    }
+
+    Ok(())
+}
+
+/// An owner scaling a cluster has to hand over signed delete and, to add
+/// members, create requests
+fn check_owner_requests(request: &ScaleClusterRequest) -> Result<(), String> {
+    let adds = matches!(request.operation, ScalingOperation::ScaleOut { .. } | ScalingOperation::ReplaceInstances { .. });
+    if adds && request.create_request.is_none() {
+        return Err("A signed create request is required to add members".to_string());
+    }
+    // Adding members also needs the delete request, so a failure can be rolled back
+    if request.delete_request.is_none() {
+        return Err("A signed delete request is required to scale a cluster".to_string());
+    }
+    Ok(())
+}
+
+fn in_progress(cluster: Option<&InstanceCluster>) -> bool {
+    cluster
+        .and_then(|cluster| cluster.scaling_manager())
+        .and_then(|manager| manager.current_phase())
+        .map_or(false, |phase| !phase.is_terminal())
+}
+
+/// Checks that a node-authenticated request comes from the node responsible
+/// for the cluster and that the owner enabled autoscaling
+fn check_responsible_node(cluster: &InstanceCluster, node_id: &str, build_id: &str) -> Result<(), String> {
+    if !cluster.autoscaling_enabled() {
+        return Err(format!("Autoscaling is not enabled for {build_id}"));
+    }
+    match cluster.responsible_node() {
+        Some(responsible) if responsible.eq_ignore_ascii_case(node_id) => Ok(()),
+        _ => Err(format!("Unauthorized: node {node_id} is not responsible for {build_id}")),
+    }
+}
+
+/// Works out how many members to add and which to remove
+pub fn plan_operation(operation: &ScalingOperation, cluster: &InstanceCluster) -> Result<ScalingPlan, ScalingError> {
+    let current = cluster.members.len();
+    let members = |ids: &[String]| -> Result<Vec<ClusterMember>, ScalingError> {
+        ids.iter().map(|id| {
+            cluster.members.get(id).cloned().ok_or_else(|| {
+                phase_error("Planning", "InstanceNotFound", format!("Instance {id} is not a member of the cluster"))
+            })
+        }).collect()
+    };
+
+    match operation {
+        ScalingOperation::ScaleOut { target_instances } => Ok(ScalingPlan {
+            add: (*target_instances as usize).saturating_sub(current),
+            remove: Vec::new(),
+        }),
+        ScalingOperation::ScaleIn { target_instances, instance_ids } => {
+            let count = current.saturating_sub(*target_instances as usize);
+            let ids = match instance_ids {
+                Some(ids) => ids.clone(),
+                None => cluster.select_instances_to_remove(count),
+            };
+            if ids.len() < count {
+                return Err(phase_error(
+                    "Planning",
+                    "NotEnoughInstances",
+                    format!("Need to remove {count} instances but only found {}", ids.len()),
+                ));
+            }
+            Ok(ScalingPlan { add: 0, remove: members(&ids)? })
+        }
+        ScalingOperation::ReplaceInstances { instance_ids } => Ok(ScalingPlan {
+            add: instance_ids.len(),
+            remove: members(instance_ids)?,
+        }),
+    }
+}
+
+/// Picks `count` of the capable nodes with the matcher's scoring pipeline.
+/// Nodes that already host a member are excluded (a node can only run one
+/// instance of a build) and count towards their region when spreading.
+pub fn select_nodes(
+    matcher: &CapabilityMatcher,
+    nodes: Vec<Node>,
+    cluster: &InstanceCluster,
+    ctx: &PlacementContext,
+    count: usize,
+) -> Result<Vec<Node>, ScalingError> {
+    let occupied: BTreeSet<String> = cluster.members.values().map(|m| m.node_id.clone()).collect();
+    let ctx = ctx.clone().with_replicas(occupied);
+    let selected = matcher.select_responsible_nodes(nodes, &ctx, count);
+
+    if selected.len() < count {
+        return Err(phase_error(
+            "ResourceAllocating",
+            "InsufficientNodes",
+            format!("Need {count} capable nodes without a member, found {}", selected.len()),
+        ));
+    }
+
+    Ok(selected)
+}
+
+fn phase_error(phase: &str, error_type: &str, message: impl Into<String>) -> ScalingError {
+    ScalingError {
+        error_type: error_type.to_string(),
+        message: message.into(),
+        phase: phase.to_string(),
+    }
+}
+
+/// Starts a scaling operation on a build's cluster. The operation runs in
+/// the background; poll `/cluster/:build_id/scaling` for its progress.
+pub async fn scale_cluster(
+    Path(build_id): Path<String>,
+    Json(request): Json<ScaleClusterRequest>,
+) -> Json<ScalingResponse> {
+    if request.build_id != build_id {
+        return Json(ScalingResponse::Failure("Build id in path and request do not match".to_string()));
+    }
+
+    let Some(signature) = &request.signature else {
+        return Json(ScalingResponse::Failure("Signature is required".to_string()));
+    };
+    if let Err(e) = check_owner_requests(&request) {
+        return Json(ScalingResponse::Failure(e));
+    }
+
+    let message = SignatureVerifier::create_scaling_message(&build_id, &request.operation);
+    let signer = match SignatureVerifier::verify_signature(message, signature, request.recovery_id) {
+        Ok(signer) => signer,
+        Err(e) => return Json(ScalingResponse::Failure(format!("Signature verification failed: {e}"))),
+    };
+
+    let executor = ScalingExecutor::new(None);
+    let instances = match executor.get_instances(&build_id).await {
+        Ok(instances) => instances,
+        Err(e) => return Json(ScalingResponse::Failure(e.to_string())),
+    };
+
+    let owner = template_of(&instances).map(|instance| instance.instance_owner.to_lowercase());
+    if owner.as_deref() != Some(signer.to_lowercase().as_str()) {
+        return Json(ScalingResponse::Failure(format!("Unauthorized: Address {signer} is not the owner of {build_id}")));
+    }
+
+    if in_progress(cluster_of(&instances)) {
+        return Json(ScalingResponse::Failure(format!("A scaling operation is already in progress for {build_id}")));
+    }
+
+    spawn_operation(executor, request)
+}
+
+/// Starts an operation the autoscaler decided on. The request is signed with
+/// the key of the node responsible for the cluster, and the operation runs
+/// on that node's authority.
+pub async fn autoscale_cluster(
+    Path(build_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<ScalingResponse> {
+    let path = format!("/cluster/{build_id}/autoscale");
+    let node_id = match NodeVerifier::verify(&headers, &path, &body).await {
+        Ok(node_id) => node_id,
+        Err(e) => return Json(ScalingResponse::Failure(format!("Node authentication failed: {e}"))),
+    };
+    let request: AutoscaleRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => return Json(ScalingResponse::Failure(format!("Invalid autoscale request: {e}"))),
+    };
+    if request.build_id != build_id {
+        return Json(ScalingResponse::Failure("Build id in path and request do not match".to_string()));
+    }
+
+    let executor = ScalingExecutor::new(None);
+    let instances = match executor.get_instances(&build_id).await {
+        Ok(instances) => instances,
+        Err(e) => return Json(ScalingResponse::Failure(e.to_string())),
+    };
+    let Some(cluster) = cluster_of(&instances) else {
+        return Json(ScalingResponse::Failure(format!("No cluster found for {build_id}")));
+    };
+    if let Err(e) = check_responsible_node(cluster, &node_id, &build_id) {
+        return Json(ScalingResponse::Failure(e));
+    }
+    if in_progress(Some(cluster)) {
+        return Json(ScalingResponse::Failure(format!("A scaling operation is already in progress for {build_id}")));
+    }
+
+    spawn_operation(executor, ScaleClusterRequest {
+        build_id: request.build_id,
+        operation: request.operation,
+        create_request: None,
+        delete_request: None,
+        signature: None,
+        recovery_id: 0,
+    })
+}
+
+fn spawn_operation(executor: ScalingExecutor, request: ScaleClusterRequest) -> Json<ScalingResponse> {
+    let build_id = request.build_id.clone();
+    let operation = request.operation.clone();
+    let task_build_id = build_id.clone();
+    tokio::spawn(async move {
+        match executor.execute(request).await {
+            Ok(manager) => {
+                let outcome = manager.current_phase().map(|phase| phase.phase_name()).unwrap_or("Unknown");
+                log::info!("Scaling operation on {task_build_id} finished: {outcome}");
+            }
+            Err(e) => log::error!("Unable to start scaling operation on {task_build_id}: {e}"),
+        }
+    });
+
+    Json(ScalingResponse::Accepted { build_id, operation })
+}
+
+/// Verifies a member request from another node's scaling executor and
+/// returns the template instance the member is created from
+async fn authorize_member_request(
+    build_id: &str,
+    action: &str,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> Result<Instance, String> {
+    let path = format!("/cluster/{build_id}/member/{action}");
+    let node_id = NodeVerifier::verify(headers, &path, body).await
+        .map_err(|e| format!("Node authentication failed: {e}"))?;
+    let request: MemberRequest = serde_json::from_slice(body)
+        .map_err(|e| format!("Invalid member request: {e}"))?;
+    if request.build_id != build_id {
+        return Err("Build id in path and request do not match".to_string());
+    }
+
+    let instances = ScalingExecutor::new(None).get_instances(build_id).await
+        .map_err(|e| e.to_string())?;
+    let template = template_of(&instances).cloned()
+        .ok_or_else(|| format!("No instances found for {build_id}"))?;
+    check_responsible_node(&template.cluster, &node_id, build_id)?;
+    Ok(template)
+}
+
+/// Creates this node's member of a cluster for the node scaling it
+pub async fn create_member(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Path(build_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<VmmResponse> {
+    let template = match authorize_member_request(&build_id, "create", &headers, &body).await {
+        Ok(template) => template,
+        Err(e) => return Json(VmmResponse::Failure(e)),
+    };
+
+    let event = VmmEvent::Create {
+        formfile: template.formfile,
+        name: build_id.clone(),
+        owner: template.instance_owner,
+    };
+    if let Err(e) = channel.lock().await.send(event).await {
+        return Json(VmmResponse::Failure(format!("Error requesting creation of {build_id}: {e}")));
+    }
+
+    Json(VmmResponse::Success(VmResponse {
+        id: "pending".to_string(),
+        name: build_id,
+        state: "PENDING".to_string(),
+    }))
+}
+
+/// Deletes this node's member of a cluster for the node scaling it
+pub async fn delete_member(
+    State(channel): State<Arc<Mutex<VmmApiChannel>>>,
+    Path(build_id): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Json<VmmResponse> {
+    if let Err(e) = authorize_member_request(&build_id, "delete", &headers, &body).await {
+        return Json(VmmResponse::Failure(e));
+    }
+
+    let event = VmmEvent::Delete { id: build_id.clone() };
+    if let Err(e) = request_receive::<()>(channel, event).await {
+        return Json(VmmResponse::Failure(e));
+    }
+
+    Json(VmmResponse::Success(VmResponse {
+        id: build_id.clone(),
+        name: build_id,
+        state: "pending".to_string(),
+    }))
+}
+
+/// Returns the current phase and latest operation record of a cluster
+pub async fn scaling_status(Path(build_id): Path<String>) -> Json<ScalingResponse> {
+    match ScalingExecutor::new(None).status(&build_id).await {
+        Ok(status) => Json(ScalingResponse::Status(status)),
+        Err(e) => Json(ScalingResponse::Failure(e.to_string())),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    fn member(instance_id: &str, node_id: &str) -> ClusterMember {
+        ClusterMember {
+            node_id: node_id.to_string(),
+            node_public_ip: "10.0.0.1".parse().unwrap(),
+            node_formnet_ip: "10.0.0.1".parse().unwrap(),
+            instance_id: instance_id.to_string(),
+            instance_formnet_ip: "10.0.0.2".parse().unwrap(),
+            status: "Started".to_string(),
+            last_heartbeat: 0,
+            heartbeats_skipped: 0,
+        }
+    }
+
+    fn cluster(members: &[(&str, &str)]) -> InstanceCluster {
+        InstanceCluster {
+            members: members.iter()
+                .map(|(id, node)| (id.to_string(), member(id, node)))
+                .collect::<BTreeMap<_, _>>(),
+            template_instance_id: Some(members[0].0.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn node(node_id: &str, cpu_total_cores: usize) -> Node {
+        let mut node = Node::default();
+        node.node_id = node_id.to_string();
+        node.capacity.cpu_total_cores = cpu_total_cores;
+        node.capacity.cpu_available_cores = cpu_total_cores as i64 * 1000;
+        node
+    }
+
+    /// Signs a message the way owners sign requests, returning the
+    /// signature and recovery id
+    /// Signs `message` the way the CLI signs owner requests
+    pub(crate) fn sign(key: &k256::ecdsa::SigningKey, message: &str) -> (String, u32) {
+        use tiny_keccak::{Hasher, Sha3};
+        let mut hasher = Sha3::v256();
+        let mut hash = [0u8; 32];
+        hasher.update(message.as_bytes());
+        hasher.finalize(&mut hash);
+        let (signature, recovery_id) = key.sign_recoverable(&hash).unwrap();
+        (hex::encode(signature.to_vec()), recovery_id.to_byte() as u32)
+    }
+
+    #[test]
+    fn test_scaling_signature_binds_operation() {
+        let key = k256::ecdsa::SigningKey::random(&mut rand::thread_rng());
+        let owner = format!("{:x}", alloy_primitives::Address::from_private_key(&key));
+        let scale_out = ScalingOperation::ScaleOut { target_instances: 3 };
+        let (signature, recovery_id) = sign(&key, &SignatureVerifier::create_scaling_message("build1", &scale_out));
+
+        let signer = |operation: &ScalingOperation| SignatureVerifier::verify_signature(
+            SignatureVerifier::create_scaling_message("build1", operation),
+            &signature,
+            recovery_id,
+        ).ok();
+        assert_eq!(signer(&scale_out), Some(owner.clone()));
+        // Replaying the signature with another target recovers someone else
+        assert_ne!(signer(&ScalingOperation::ScaleOut { target_instances: 10 }), Some(owner.clone()));
+        assert_ne!(signer(&ScalingOperation::ScaleIn { target_instances: 1, instance_ids: None }), Some(owner));
+    }
+
+    #[test]
+    fn test_plan_operation() {
+        let cluster = cluster(&[("a", "node-a"), ("b", "node-b"), ("c", "node-c")]);
+
+        let plan = plan_operation(&ScalingOperation::ScaleOut { target_instances: 5 }, &cluster).unwrap();
+        assert_eq!(plan.add, 2);
+        assert!(plan.remove.is_empty());
+
+        let plan = plan_operation(&ScalingOperation::ScaleIn {
+            target_instances: 2,
+            instance_ids: Some(vec!["c".to_string()]),
+        }, &cluster).unwrap();
+        assert_eq!(plan.add, 0);
+        assert_eq!(plan.remove, vec![member("c", "node-c")]);
+
+        let plan = plan_operation(&ScalingOperation::ReplaceInstances {
+            instance_ids: vec!["b".to_string()],
+        }, &cluster).unwrap();
+        assert_eq!(plan.add, 1);
+        assert_eq!(plan.remove.len(), 1);
+
+        assert!(plan_operation(&ScalingOperation::ReplaceInstances {
+            instance_ids: vec!["missing".to_string()],
+        }, &cluster).is_err());
+    }
+
+    #[test]
+    fn test_check_responsible_node() {
+        let mut cluster = cluster(&[("a", "node-a"), ("b", "node-b")]);
+        assert!(check_responsible_node(&cluster, "node-a", "build").is_err());
+
+        cluster.set_autoscaling_enabled(true);
+        assert!(check_responsible_node(&cluster, "node-a", "build").is_ok());
+        assert!(check_responsible_node(&cluster, "NODE-A", "build").is_ok());
+        assert!(check_responsible_node(&cluster, "node-b", "build").is_err());
+    }
+
+    #[test]
+    fn test_select_nodes_skips_occupied_nodes() {
+        let cluster = cluster(&[("a", "node-a")]);
+        let nodes = vec![node("node-a", 8), node("node-b", 2), node("node-c", 4)];
+        let matcher = CapabilityMatcher::new(None);
+        let ctx = PlacementContext { build_id: "build".to_string(), vcpus: 1, ..Default::default() };
+
+        // Bin packing prefers the smaller node
+        let selected = select_nodes(&matcher, nodes.clone(), &cluster, &ctx, 2).unwrap();
+        let ids: Vec<&str> = selected.iter().map(|n| n.node_id.as_str()).collect();
+        assert_eq!(ids, vec!["node-b", "node-c"]);
+
+        let err = select_nodes(&matcher, nodes, &cluster, &ctx, 3).unwrap_err();
+        assert_eq!(err.error_type, "InsufficientNodes");
+    }
+}
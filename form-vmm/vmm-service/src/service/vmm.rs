@@ -3,12 +3,14 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use std::{collections::HashMap, path::PathBuf};
+use std::{collections::{HashMap, HashSet}, path::{Path, PathBuf}};
 use std::net::{IpAddr, SocketAddr};
 use alloy_primitives::Address;
 use form_pack::formfile::Formfile;
 use form_state::datastore::InstanceRequest;
-use form_state::instances::{ClusterMember, Instance, InstanceAnnotations, InstanceCluster, InstanceEncryption, InstanceMetadata, InstanceMonitoring, InstanceResources, InstanceSecurity, InstanceStatus};
+use form_state::migration::InstanceMigration;
+use form_state::resize::InstanceResize;
+use form_state::instances::{ClusterMember, Instance, InstanceAnnotations, InstanceDisk, InstanceCluster, InstanceEncryption, InstanceMetadata, InstanceMonitoring, InstanceResources, InstanceSecurity, InstanceStatus};
 use formnet::{JoinRequest, JoinResponse, VmJoinRequest};
 use formnet_server::db::CrdtMap;
 use formnet_server::DatabasePeer;
@@ -29,16 +31,20 @@ use tokio::sync::{mpsc, Mutex};
 use tokio::sync::broadcast;
 use tokio::time::interval;
 use vmm_sys_util::signal::block_signal;
-use vmm::{api::{VmAddDevice, VmAddUserDevice, VmCoredumpData, VmCounters, VmInfo, VmReceiveMigrationData, VmRemoveDevice, VmResize, VmResizeZone, VmSendMigrationData, VmSnapshotConfig, VmmPingResponse}, config::RestoreConfig, vm_config::{DiskConfig, FsConfig, NetConfig, PmemConfig, VdpaConfig, VsockConfig}, PciDeviceInfo, VmmThreadHandle};
+use vmm::{api::{VmAddUserDevice, VmCoredumpData, VmCounters, VmInfo, VmInfoResponse, VmReceiveMigrationData, VmRemoveDeviceData, VmResizeData, VmResizeZoneData, VmSendMigrationData, VmSnapshotConfig, VmmPingResponse}, config::RestoreConfig, vm_config::{DeviceConfig, DiskConfig, FsConfig, NetConfig, PmemConfig, VdpaConfig, VsockConfig}, PciDeviceInfo, VmmThreadHandle};
 use vmm_sys_util::eventfd::EventFd;
 use seccompiler::SeccompAction;
 use tokio::task::JoinHandle;
-use form_types::{FormnetMessage, FormnetTopic, GenericPublisher, PeerType, VmmEvent, VmmSubscriber};
+use form_types::{AddDeviceRequest, AddDiskRequest, AddFsRequest, CommitResponse, CommitVmRequest, FormnetMessage, FormnetTopic, GenericPublisher, MigrateVmRequest, PeerType, RemoveDeviceRequest, VmResponse, VmmEvent, VmmResponse, VmmSubscriber};
 use form_broker::{subscriber::SubStream, publisher::PubStream};
 use futures::future::join_all;
 use crate::api::VmmApiChannel;
 use crate::{api::VmmApi, util::ensure_directory};
 use crate::util::add_tap_to_bridge;
+use crate::service::migration::{free_port, get_node, has_capacity, receiver_url, request_target, rootfs_path, MigrationOutcome, MigrationResponse, MigrationTarget, MIGRATION_TIMEOUT};
+use crate::service::hotplug::{create_disk, disk_size_gb, growth, passthrough_device, record_detach, record_resize, within_limits};
+use crate::service::scaling::ScalingExecutor;
+use crate::service::commit::{begin_commit, CommitExecutor};
 use crate::{
     error::VmmError,
     config::create_vm_config,
@@ -48,7 +54,15 @@ use std::io::{Cursor, Write};
 use std::convert::TryFrom;
 use std::error::Error;
 use crate::ChError;
-use crate::IMAGE_DIR;
+use crate::{IMAGE_DIR, snapshot_dir, volume_path};
+
+/// Path of the API socket of the VMM running `name`
+pub fn api_socket_path(name: &str) -> PathBuf {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(path) => PathBuf::from(path).join("form-vmm").join(format!("{name}.sock")),
+        Err(_) => PathBuf::from("/run/form-vmm").join(format!("{name}.sock")),
+    }
+}
 
 type VmmResult<T> = Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
 type ApiResult<T> = Result<ApiResponse<T>, Box<dyn std::error::Error + Send + Sync + 'static>>; 
@@ -68,6 +82,46 @@ pub enum ApiResponse<T> {
     }
 }
 
+/// Snapshot of the running VM taken while a restore is in progress, so the
+/// VM can be put back if the restore fails. Snapshot ids are uuids, so this
+/// never names a recorded snapshot.
+const ROLLBACK_SNAPSHOT: &str = "pre-restore";
+
+fn restore_config(dir: &Path) -> RestoreConfig {
+    RestoreConfig {
+        source_url: PathBuf::from(format!("file://{}", dir.display())),
+        prefault: false,
+        net_fds: None,
+    }
+}
+
+/// Turns an error reported by the VMM API into an `Err`
+pub(crate) fn expect_success<T>(op: &str, resp: ApiResponse<T>) -> ApiResult<T> {
+    match resp {
+        ApiResponse::Error { code, reason } => Err(Box::new(VmmError::OperationFailed(
+            format!("{op} failed with {code}: {reason}")
+        ))),
+        resp => Ok(resp),
+    }
+}
+
+/// Reports which PCI slot a hot-plugged device ended up in
+fn hotplug_response(id: &str, name: &str, result: ApiResult<PciDeviceInfo>) -> VmmResponse {
+    match result {
+        Ok(ApiResponse::Success { content: Some(info), .. }) => VmmResponse::Success(VmResponse {
+            id: id.to_string(),
+            name: name.to_string(),
+            state: format!("attached {} at {}", info.id, info.bdf),
+        }),
+        Ok(_) => VmmResponse::Success(VmResponse {
+            id: id.to_string(),
+            name: name.to_string(),
+            state: "attached".to_string(),
+        }),
+        Err(e) => VmmResponse::Failure(e.to_string()),
+    }
+}
+
 pub struct FormVmm {
     socket_path: String,
     thread: Option<VmmThreadHandle>,
@@ -142,6 +196,11 @@ impl FormVmApi {
         self.empty_body_request("vm.boot").await
     }
 
+    /// Shuts the VM down, keeping its config so it can be booted again
+    pub async fn shutdown_vm(&self) -> ApiResult<()> {
+        self.empty_body_request("vm.shutdown").await
+    }
+
     pub async fn delete(&self) -> ApiResult<()> {
         self.empty_body_request("vm.delete").await
     }
@@ -177,12 +236,12 @@ impl FormVmApi {
         self.body_request("vm.restore", body).await
     }
 
-    pub async fn resize(&self, data: &VmResize) -> ApiResult<()> {
+    pub async fn resize(&self, data: &VmResizeData) -> ApiResult<()> {
         let body = serde_json::to_string(data)?;
         self.body_request("vm.resize", body).await
     }
 
-    pub async fn resize_zone(&self, data: &VmResizeZone) -> ApiResult<()> {
+    pub async fn resize_zone(&self, data: &VmResizeZoneData) -> ApiResult<()> {
         let body = serde_json::to_string(data)?;
         self.body_request("vm.resize-zone", body).await
     }
@@ -191,8 +250,12 @@ impl FormVmApi {
         self.get::<VmInfo>("vm.info").await
     }
 
-    pub async fn add_device(&self, data: &VmAddDevice) -> ApiResult<PciDeviceInfo> {
-        let body = serde_json::to_string(data)?;
+    pub async fn vm_info(&self) -> ApiResult<VmInfoResponse> {
+        self.get::<VmInfoResponse>("vm.info").await
+    }
+
+    pub async fn add_device(&self, config: &DeviceConfig) -> ApiResult<PciDeviceInfo> {
+        let body = serde_json::to_string(config)?;
         self.body_request("vm.add-device", body).await
     }
 
@@ -231,7 +294,7 @@ impl FormVmApi {
         self.body_request("vm.add-vsock", body).await
     }
 
-    pub async fn remove_device(&self, data: &VmRemoveDevice) -> ApiResult<()> {
+    pub async fn remove_device(&self, data: &VmRemoveDeviceData) -> ApiResult<()> {
         let body = serde_json::to_string(data)?;
         self.body_request("vm.remove-device", body).await
     }
@@ -392,6 +455,10 @@ pub struct VmManager {
     subscriber: Option<VmmSubscriber>,
     signing_key: String,
     publisher_addr: Option<String>,
+    /// VMs with a migration to another node in flight
+    migrating: HashSet<String>,
+    migration_tx: mpsc::Sender<MigrationOutcome>,
+    migration_rx: Option<mpsc::Receiver<MigrationOutcome>>,
     create_futures: Arc<Mutex<FuturesUnordered<Pin<Box<dyn Future<Output = Result<VmmEvent, Box<dyn std::error::Error + Send + Sync>>> + Send + 'static>>>>>
 }
 
@@ -409,7 +476,9 @@ impl VmManager {
             &hex::decode(&signing_key)?
         )?;
 
-        let _node_id = hex::encode(Address::from_private_key(&pk));
+        let node_id = hex::encode(Address::from_private_key(&pk));
+        crate::api::auth::set_node_key(signing_key.clone());
+        tokio::spawn(crate::service::deploy::resume_deploys(node_id));
         let (resp_tx, resp_rx) = tokio::sync::mpsc::channel(1024);
         let api_channel = Arc::new(Mutex::new(VmmApiChannel::new(
             event_sender,
@@ -440,6 +509,8 @@ impl VmManager {
             }
         });
 
+        let (migration_tx, migration_rx) = mpsc::channel(16);
+
         Ok(Self {
             vm_monitors: HashMap::new(),
             server, 
@@ -449,6 +520,9 @@ impl VmManager {
             api_response_sender: resp_tx,
             subscriber,
             publisher_addr,
+            migrating: HashSet::new(),
+            migration_tx,
+            migration_rx: Some(migration_rx),
             #[cfg(not(feature = "devnet"))]
             queue_reader: queue_handle,
             create_futures: Arc::new(Mutex::new(FuturesUnordered::new())),
@@ -463,39 +537,25 @@ impl VmManager {
         Ok(hex::encode(Address::from_private_key(&pk)))
     }
 
-    pub async fn create(
-        &mut self,
-        config: &VmInstanceConfig
-    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
-        log::info!("Received create request to create vm instance {}...", config.name);
-        let (api_socket_path, api_socket_fd) = if let Ok(path) = std::env::var("XDG_RUNTIME_DIR") {
-            let sock_path = format!("{path}/form-vmm/{}.sock", config.name);
-            ensure_directory(
-                PathBuf::from(&sock_path).parent().ok_or(
-                    Box::new(
-                        std::io::Error::new(
-                            std::io::ErrorKind::NotFound,
-                            format!("Parent directory for {sock_path} not found")
-                        )
-                    )
-                )?
-            )?;
-            (Some(format!("{path}/form-vmm/{}.sock", config.name)), None)
-        } else {
-            let sock_path = format!("/run/form-vmm/{}.sock", config.name);
-            ensure_directory(
-                PathBuf::from(&sock_path).parent().ok_or(
-                    Box::new(
-                        std::io::Error::new(
-                            std::io::ErrorKind::NotFound,
-                            format!("Parent directory for {sock_path} not found")
-                        )
+    /// Starts a VMM thread with its API socket for `name`. The VMM does not
+    /// run a VM until one is created, restored or migrated into it.
+    fn start_vmm(
+        &self,
+        name: &str
+    ) -> Result<FormVmm, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let sock_path = api_socket_path(name);
+        ensure_directory(
+            sock_path.parent().ok_or(
+                Box::new(
+                    std::io::Error::new(
+                        std::io::ErrorKind::NotFound,
+                        format!("Parent directory for {} not found", sock_path.display())
                     )
-                )?
-            )?;
-            (Some(format!("/run/form-vmm/{}.sock", config.name)), None) 
-        };
-        log::info!("Established API Socket for vm instance {}: {:?}...", config.name, api_socket_path);
+                )
+            )?
+        )?;
+        let (api_socket_path, api_socket_fd) = (Some(sock_path.display().to_string()), None);
+        log::info!("Established API Socket for vm instance {}: {:?}...", name, api_socket_path);
 
         // Create channels and EventFDs
         let (api_request_sender, api_request_receiver) = std::sync::mpsc::channel();
@@ -565,6 +625,15 @@ impl VmManager {
         );
 
         log::info!("Created new FormVmm");
+        Ok(vmm)
+    }
+
+    pub async fn create(
+        &mut self,
+        config: &VmInstanceConfig
+    ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        log::info!("Received create request to create vm instance {}...", config.name);
+        let vmm = self.start_vmm(&config.name)?;
         log::info!("Calling `create` on FormVmm");
         vmm.api.create(config).await.map_err(|e| {
             Box::new(
@@ -599,6 +668,8 @@ impl VmManager {
                 template_instance_id: None,
                 session_affinity_enabled: false,
                 scaling_manager: None,
+                autoscaling_enabled: false,
+                deploy: None,
             },
             snapshots: None,
             metadata: InstanceMetadata {
@@ -628,6 +699,7 @@ impl VmManager {
                 bandwidth_mbps: 1024,
                 gpu: None
             },
+            disks: vec![],
         };
 
         #[cfg(not(feature = "devnet"))]
@@ -692,6 +764,7 @@ impl VmManager {
     }
 
     pub async fn delete(&mut self, name: &String) -> ApiResult<()> {
+        self.ensure_not_migrating(name)?;
         let api = &self.get_vmm(name)?.api;
         let resp = api.delete().await?;
         match &resp {
@@ -723,11 +796,480 @@ impl VmManager {
         self.get_vmm(name)?.api.power_button().await
     }
 
+    /// Live migrates a VM to the destination named in the request. The VM
+    /// keeps running while its memory is copied and is only paused for the
+    /// final round. The copy runs in the background and reports back to the
+    /// event loop through `migration_tx`, where `finish_migration` picks it
+    /// up.
+    pub async fn migrate(&mut self, request: &MigrateVmRequest) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let name = &request.id;
+        self.ensure_not_migrating(name)?;
+        let socket_path = self.get_vmm(name)?.socket_path().to_string();
+        let destination_ip = DatabasePeer::<String, CrdtMap>::get(request.destination_node_id.clone()).await?.inner.ip;
+
+        let target = request_target(destination_ip, request).await?;
+        log::info!("Migrating {name} to {} at {}", request.destination_node_id, target.receiver_url);
+        self.migrating.insert(name.clone());
+
+        let request = request.clone();
+        let migration_tx = self.migration_tx.clone();
+        tokio::spawn(async move {
+            let api = FormVmApi::new(&socket_path);
+            let sent = api.send_migration(VmSendMigrationData {
+                destination_url: target.receiver_url.clone(),
+                local: false,
+            }).await.and_then(|resp| expect_success("vm.send-migration", resp));
+            if sent.is_err() {
+                if let Err(e) = api.resume().await.and_then(|resp| expect_success("vm.resume", resp)) {
+                    log::error!("Unable to resume {} after failed migration: {e}", request.id);
+                }
+            }
+            let outcome = MigrationOutcome {
+                request,
+                target,
+                result: sent.map(|_| ()).map_err(|e| e.to_string()),
+            };
+            if let Err(e) = migration_tx.send(outcome).await {
+                log::error!("Unable to report migration outcome: {e}");
+            }
+        });
+
+        Ok(())
+    }
+
+    /// Completes a migration once the VM has been streamed to the
+    /// destination: the VMM left here is shut down and the move is recorded
+    /// in form-state. If the migration failed the VM has already been
+    /// resumed and form-state is left untouched.
+    async fn finish_migration(&mut self, outcome: MigrationOutcome) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let MigrationOutcome { request, target, result } = outcome;
+        let name = &request.id;
+        self.migrating.remove(name);
+        if let Err(e) = result {
+            log::error!("Migration of {name} to {} failed: {e}", request.destination_node_id);
+            return Ok(());
+        }
+
+        // The VM now runs on the destination, only its VMM is left here
+        let socket_path = self.get_vmm(name)?.socket_path().to_string();
+        if let Err(e) = self.shutdown(name).await {
+            log::error!("Unable to shut down VMM of migrated {name}: {e}");
+        }
+        let _ = std::fs::remove_file(&socket_path);
+        self.remove_vmm(name)?;
+
+        let node_id = self.derive_address().await?;
+        let migration = InstanceMigration {
+            instance_id: form_pack::manager::build_instance_id(node_id, name.clone())?,
+            destination_node_id: request.destination_node_id.clone(),
+            destination_instance_id: form_pack::manager::build_instance_id(request.destination_node_id.clone(), name.clone())?,
+            node_public_ip: target.node_public_ip,
+            node_formnet_ip: target.node_formnet_ip,
+        };
+
+        #[cfg(not(feature = "devnet"))]
+        VmmApi::write_to_queue(InstanceRequest::Migrate(migration), 4, "state").await?;
+
+        #[cfg(feature = "devnet")]
+        reqwest::Client::new().post("http://127.0.0.1:3004/instance/migrate")
+            .json(&migration)
+            .send()
+            .await?
+            .json::<form_types::state::Response<Instance>>()
+            .await?;
+
+        log::info!("Migrated {name} to {}", request.destination_node_id);
+        Ok(())
+    }
+
+    /// Refuses operations that would race a migration of the VM
+    fn ensure_not_migrating(&self, name: &str) -> VmmResult<()> {
+        if self.migrating.contains(name) {
+            return Err(Box::new(VmmError::OperationFailed(
+                format!("{name} is being migrated to another node")
+            )));
+        }
+        Ok(())
+    }
+
+    /// Starts propagating the disk of a VM running on this node to the rest
+    /// of its cluster. The commit runs in the background; its progress is
+    /// served from `/vm/:id/commit/:commit_id`.
+    pub async fn commit(&self, request: &CommitVmRequest, commit_id: &str) -> VmmResult<()> {
+        self.get_vmm(&request.id)?;
+        let node_id = self.derive_address().await?;
+        begin_commit(request, commit_id).await?;
+
+        let request = request.clone();
+        let commit_id = commit_id.to_string();
+        tokio::spawn(async move {
+            match CommitExecutor::new(None).execute(request, commit_id.clone(), node_id).await {
+                Ok(_) => log::info!("Commit {commit_id} completed"),
+                Err(e) => log::error!("Commit {commit_id} failed: {e}"),
+            }
+        });
+        Ok(())
+    }
+
+    /// Reserves this node for an incoming migration: checks that the VM
+    /// fits, starts an empty VMM for it and listens for the migration
+    /// stream on this node's formnet address in the background.
+    pub async fn prepare_migration(&mut self, request: &MigrateVmRequest) -> Result<MigrationTarget, Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let name = &request.id;
+        let node_id = self.derive_address().await?;
+        if request.destination_node_id != node_id {
+            return Err(Box::new(VmmError::OperationFailed(
+                format!("Migration is addressed to {}, not this node", request.destination_node_id)
+            )));
+        }
+
+        if let Some(vmm) = self.vm_monitors.get(name) {
+            if vmm.api.ping().await.is_ok() {
+                return Err(Box::new(VmmError::OperationFailed(format!("{name} already runs on this node"))));
+            }
+            // Left behind by a migration that never arrived
+            self.remove_vmm(name)?;
+        }
+
+        if !rootfs_path(name).exists() {
+            return Err(Box::new(VmmError::OperationFailed(
+                format!("Disk image for {name} is not present on this node")
+            )));
+        }
+
+        let instance = ScalingExecutor::new(None).get_instances(name).await?
+            .into_iter()
+            .next()
+            .ok_or(VmmError::VmNotFound(name.clone()))?;
+        let node = get_node(&node_id).await?;
+        form_state::placement::Placement::from_formfile(&instance.formfile).admits(&node)
+            .and_then(|_| has_capacity(&node, &instance.resources))
+            .map_err(|reason| VmmError::OperationFailed(format!("Unable to host {name}: {reason}")))?;
+
+        let node_formnet_ip = DatabasePeer::<String, CrdtMap>::get(node_id).await?.inner.ip;
+        let node_public_ip = publicip::get_any(Preference::Ipv4).ok_or(
+            Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Unable to get node public ip"))
+        )?;
+        let receiver_url = receiver_url(node_formnet_ip, free_port(node_formnet_ip)?);
+
+        let vmm = self.start_vmm(name)?;
+        let socket_path = vmm.socket_path().to_string();
+        self.vm_monitors.insert(name.clone(), vmm);
+
+        let task_name = name.clone();
+        let task_url = receiver_url.clone();
+        tokio::spawn(async move {
+            let api = FormVmApi::new(&socket_path);
+            let received = tokio::time::timeout(
+                MIGRATION_TIMEOUT,
+                api.receive_migration(VmReceiveMigrationData { receiver_url: task_url })
+            ).await
+                .map_err(|e| -> Box<dyn std::error::Error + Send + Sync> { Box::new(e) })
+                .and_then(|resp| resp)
+                .and_then(|resp| expect_success("vm.receive-migration", resp));
+            match received {
+                Ok(_) => {
+                    log::info!("Received migration of {task_name}");
+                    if let Ok(ApiResponse::Success { content: Some(info), .. }) = api.vm_info().await {
+                        for tap in info.config.net.iter().flatten().filter_map(|net| net.tap.clone()) {
+                            if let Err(e) = add_tap_to_bridge("br0", &tap).await {
+                                log::error!("Error attempting to add tap device {tap} to bridge: {e}");
+                            }
+                        }
+                    }
+                }
+                Err(e) => {
+                    log::error!("Migration of {task_name} to this node did not complete, releasing reservation: {e}");
+                    let _ = api.shutdown().await;
+                    let _ = std::fs::remove_file(&socket_path);
+                }
+            }
+        });
+
+        Ok(MigrationTarget {
+            receiver_url,
+            node_public_ip,
+            node_formnet_ip,
+        })
+    }
+
+    /// Pauses the VM, writes its memory and device state to the snapshot
+    /// directory and resumes it, whether or not the snapshot succeeded.
+    pub async fn snapshot(&self, name: &String, snapshot_id: &str) -> ApiResult<()> {
+        self.ensure_not_migrating(name)?;
+        let dir = snapshot_dir(name, snapshot_id);
+        ensure_directory(&dir)?;
+        let api = &self.get_vmm(name)?.api;
+        expect_success("vm.pause", api.pause().await?)?;
+        let resp = api.snapshot(&VmSnapshotConfig {
+            destination_url: format!("file://{}", dir.display())
+        }).await;
+        if let Err(e) = api.resume().await.and_then(|resp| expect_success("vm.resume", resp)) {
+            log::error!("Unable to resume {name} after snapshot {snapshot_id}: {e}");
+        }
+
+        let resp = resp.and_then(|resp| expect_success("vm.snapshot", resp));
+        if resp.is_err() {
+            let _ = std::fs::remove_dir_all(&dir);
+        }
+        resp
+    }
+
+    /// Replaces the VM with the state captured in a snapshot. Only the VM
+    /// is torn down, the VMM process and its API socket are kept. The
+    /// running VM is snapshotted first, so if the restore fails it is put
+    /// back the way it was.
+    pub async fn restore(&self, name: &String, snapshot_id: &str) -> ApiResult<()> {
+        self.ensure_not_migrating(name)?;
+        let dir = snapshot_dir(name, snapshot_id);
+        if !dir.exists() {
+            return Err(Box::new(VmmError::OperationFailed(
+                format!("Snapshot {snapshot_id} of {name} was not found in {}", dir.display())
+            )));
+        }
+        let rollback_dir = snapshot_dir(name, ROLLBACK_SNAPSHOT);
+        let _ = std::fs::remove_dir_all(&rollback_dir);
+        self.snapshot(name, ROLLBACK_SNAPSHOT).await.map_err(|e| {
+            Box::new(VmmError::OperationFailed(
+                format!("Unable to save the state of {name} before restoring {snapshot_id}: {e}")
+            ))
+        })?;
+
+        let api = &self.get_vmm(name)?.api;
+        expect_success("vm.pause", api.pause().await?)?;
+        if let Err(e) = api.delete().await.and_then(|resp| expect_success("vm.delete", resp)) {
+            if let Err(e) = api.resume().await.and_then(|resp| expect_success("vm.resume", resp)) {
+                log::error!("Unable to resume {name} after a failed restore: {e}");
+            }
+            let _ = std::fs::remove_dir_all(&rollback_dir);
+            return Err(e);
+        }
+
+        let restored = api.restore(&restore_config(&dir)).await
+            .and_then(|resp| expect_success("vm.restore", resp));
+        let e = match restored {
+            Ok(_) => {
+                let _ = std::fs::remove_dir_all(&rollback_dir);
+                // cloud-hypervisor leaves a restored VM paused
+                return api.resume().await
+            }
+            Err(e) => e,
+        };
+
+        log::error!("Restore of {name} from {snapshot_id} failed, putting the previous VM back: {e}");
+        // A failed restore can leave a half-built VM behind
+        let _ = api.delete().await;
+        if let Err(e) = api.restore(&restore_config(&rollback_dir)).await
+            .and_then(|resp| expect_success("vm.restore", resp))
+        {
+            log::error!("Unable to put {name} back after a failed restore, its state is kept in {}: {e}", rollback_dir.display());
+            return Err(e);
+        }
+        if let Err(e) = api.resume().await.and_then(|resp| expect_success("vm.resume", resp)) {
+            log::error!("Unable to resume {name} after a failed restore: {e}");
+        }
+        let _ = std::fs::remove_dir_all(&rollback_dir);
+        Err(e)
+    }
+
+    /// Removes the files of snapshots dropped from an instance's history
+    fn prune_snapshots(&self, name: &str, snapshot_ids: &[String]) {
+        for snapshot_id in snapshot_ids {
+            if let Err(e) = std::fs::remove_dir_all(snapshot_dir(name, snapshot_id)) {
+                log::warn!("Unable to remove snapshot {snapshot_id} of {name}: {e}");
+            }
+        }
+    }
+
+    /// Hot-plugs vCPUs or memory into a running VM, or unplugs them. The
+    /// new size is recorded in form-state, against the owner's quota, before
+    /// cloud-hypervisor is asked for it, and rolled back if it refuses.
+    pub async fn resize(&self, name: &String, vcpus: Option<u8>, memory_mb: Option<u32>) -> VmmResult<Instance> {
+        let api = &self.get_vmm(name)?.api;
+        let node_id = self.derive_address().await?;
+        let instance_id = form_pack::manager::build_instance_id(node_id.clone(), name.clone())?;
+        let current = Instance::get(&instance_id).await.ok_or(VmmError::VmNotFound(name.clone()))?.resources;
+
+        let mut resized = current.clone();
+        resized.vcpus = vcpus.unwrap_or(current.vcpus);
+        resized.memory_mb = memory_mb.unwrap_or(current.memory_mb);
+        let node = get_node(&node_id).await?;
+        within_limits(&resized)
+            .and_then(|_| has_capacity(&node, &growth(&current, &resized)))
+            .map_err(|reason| VmmError::OperationFailed(format!("Unable to resize {name}: {reason}")))?;
+
+        let instance = record_resize(&InstanceResize {
+            instance_id: instance_id.clone(),
+            vcpus,
+            memory_mb,
+            ..Default::default()
+        }).await?;
+
+        let resp = api.resize(&VmResizeData {
+            desired_vcpus: vcpus,
+            desired_ram: memory_mb.map(|memory_mb| (memory_mb as u64) << 20),
+            desired_balloon: None,
+        }).await.and_then(|resp| expect_success("vm.resize", resp));
+        if let Err(e) = resp {
+            if let Err(e) = record_resize(&InstanceResize {
+                instance_id,
+                vcpus: Some(current.vcpus),
+                memory_mb: Some(current.memory_mb),
+                ..Default::default()
+            }).await {
+                log::error!("Unable to roll back recorded resize of {name}: {e}");
+            }
+            return Err(e);
+        }
+
+        Ok(instance)
+    }
+
+    /// Hot-plugs a disk from the VM's volume directory, creating it first
+    /// if it doesn't exist yet. The disk is recorded on the instance, against
+    /// the owner's storage quota, before it is attached, and dropped from the
+    /// record again if it can't be.
+    pub async fn add_disk(&self, request: &AddDiskRequest) -> ApiResult<PciDeviceInfo> {
+        let name = &request.id;
+        let api = &self.get_vmm(name)?.api;
+        let path = volume_path(name, &request.path).ok_or(VmmError::OperationFailed(
+            format!("Invalid disk name {}", request.path)
+        ))?;
+        let node_id = self.derive_address().await?;
+        let instance_id = form_pack::manager::build_instance_id(node_id.clone(), name.clone())?;
+
+        let create = !path.exists();
+        let size_gb = if create {
+            request.size_gb.ok_or(VmmError::OperationFailed(
+                format!("Disk {} does not exist, a size is needed to create it", request.path)
+            ))?
+        } else {
+            disk_size_gb(&path)?
+        };
+        let node = get_node(&node_id).await?;
+
+        let disk = InstanceDisk {
+            disk_id: request.disk_id.clone().unwrap_or_else(|| request.path.clone()),
+            path: request.path.clone(),
+            size_gb,
+            readonly: request.readonly,
+        };
+        record_resize(&InstanceResize {
+            instance_id: instance_id.clone(),
+            attach_disk: Some(disk.clone()),
+            ..Default::default()
+        }).await?;
+
+        let created = if create { create_disk(&node, &path, size_gb).map(|_| true) } else { Ok(false) };
+        let resp = match created {
+            Ok(created) => {
+                let resp = api.add_disk(&DiskConfig {
+                    path: Some(path.clone()),
+                    readonly: request.readonly,
+                    direct: request.direct,
+                    iommu: request.iommu,
+                    num_queues: 1,
+                    queue_size: 256,
+                    vhost_user: false,
+                    vhost_socket: None,
+                    rate_limit_group: None,
+                    rate_limiter_config: None,
+                    id: Some(disk.disk_id.clone()),
+                    disable_io_uring: false,
+                    disable_aio: false,
+                    pci_segment: 0,
+                    serial: None,
+                    queue_affinity: None,
+                }).await.and_then(|resp| expect_success("vm.add-disk", resp));
+                if resp.is_err() && created {
+                    let _ = std::fs::remove_file(&path);
+                }
+                resp
+            }
+            Err(e) => Err(e.into()),
+        };
+
+        if resp.is_err() {
+            if let Err(e) = record_detach(&instance_id, &disk.disk_id).await {
+                log::error!("Unable to roll back recorded disk {} of {name}: {e}", disk.disk_id);
+            }
+        }
+        resp
+    }
+
+    /// Hot-plugs a virtio-fs share served by a virtiofsd in the VM's volume
+    /// directory
+    pub async fn add_fs(&self, request: &AddFsRequest) -> ApiResult<PciDeviceInfo> {
+        let name = &request.id;
+        let api = &self.get_vmm(name)?.api;
+        let socket = volume_path(name, &request.socket).filter(|socket| socket.exists()).ok_or(
+            VmmError::OperationFailed(format!("No virtiofsd socket {} for {name}", request.socket))
+        )?;
+
+        api.add_fs(&FsConfig {
+            tag: request.tag.clone(),
+            socket,
+            num_queues: 1,
+            queue_size: 1024,
+            id: request.fs_id.clone(),
+            pci_segment: 0,
+        }).await.and_then(|resp| expect_success("vm.add-fs", resp))
+    }
+
+    /// Passes a host PCI device through to the VM, if no other VM on this
+    /// node holds it
+    pub async fn add_device(&self, request: &AddDeviceRequest) -> ApiResult<PciDeviceInfo> {
+        let name = &request.id;
+        let api = &self.get_vmm(name)?.api;
+        let path = passthrough_device(&request.path)
+            .map_err(|reason| VmmError::OperationFailed(format!("Unable to pass device through to {name}: {reason}")))?;
+
+        for (other, vmm) in &self.vm_monitors {
+            if let Ok(ApiResponse::Success { content: Some(info), .. }) = vmm.api.vm_info().await {
+                if info.config.devices.iter().flatten().any(|device| device.path == path) {
+                    return Err(Box::new(VmmError::OperationFailed(
+                        format!("{} is already passed through to {other}", path.display())
+                    )));
+                }
+            }
+        }
+
+        api.add_device(&DeviceConfig {
+            path,
+            iommu: request.iommu,
+            id: request.device_id.clone(),
+            pci_segment: 0,
+            x_nv_gpudirect_clique: None,
+        }).await.and_then(|resp| expect_success("vm.add-device", resp))
+    }
+
+    /// Unplugs a device from the VM. A disk that was recorded on the
+    /// instance when it was plugged in is dropped from the record.
+    pub async fn remove_device(&self, request: &RemoveDeviceRequest) -> ApiResult<()> {
+        let name = &request.id;
+        let resp = self.get_vmm(name)?.api.remove_device(&VmRemoveDeviceData {
+            id: request.device_id.clone(),
+        }).await.and_then(|resp| expect_success("vm.remove-device", resp))?;
+
+        let instance_id = form_pack::manager::build_instance_id(self.derive_address().await?, name.clone())?;
+        let recorded = Instance::get(&instance_id).await
+            .map_or(false, |instance| instance.disks.iter().any(|disk| disk.disk_id == request.device_id));
+        if recorded {
+            if let Err(e) = record_detach(&instance_id, &request.device_id).await {
+                log::error!("Unable to drop unplugged disk {} of {name} from its record: {e}", request.device_id);
+            }
+        }
+        Ok(resp)
+    }
+
     pub async fn run(
         mut self,
         mut shutdown_rx: broadcast::Receiver<()>,
         mut api_rx: mpsc::Receiver<VmmEvent>
     ) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let mut migration_rx = self.migration_rx.take().ok_or(
+            VmmError::SystemError("VmManager is already running".to_string())
+        )?;
         if let Some(mut subscriber) = self.subscriber.take() {
             let futures_clone = self.create_futures.clone();
             let mut interval = interval(Duration::from_secs(20));
@@ -749,6 +1291,11 @@ impl VmManager {
                             log::error!("Error while handling event: {event:?}: {e}"); 
                         }
                     }
+                    Some(outcome) = migration_rx.recv() => {
+                        if let Err(e) = self.finish_migration(outcome).await {
+                            log::error!("Error while finishing migration: {e}");
+                        }
+                    }
                     Ok(events) = subscriber.receive() => {
                         for event in events {
                             if let Err(e) = self.handle_vmm_event(&event).await {
@@ -788,6 +1335,11 @@ impl VmManager {
                             log::error!("Error while handling event: {event:?}: {e}"); 
                         }
                     }
+                    Some(outcome) = migration_rx.recv() => {
+                        if let Err(e) = self.finish_migration(outcome).await {
+                            log::error!("Error while finishing migration: {e}");
+                        }
+                    }
                     _ = interval.tick() => {
                         let mut guard = futures_clone.lock().await;
                         while let Some(Ok(event)) = guard.next().await {
@@ -1035,6 +1587,99 @@ Formpack for {name} doesn't exist:
             VmmEvent::Delete { id, .. } => {
                 self.delete(id).await?;
             }
+            VmmEvent::Migrate { request } => {
+                self.migrate(request).await?;
+            }
+            VmmEvent::ReceiveMigration { request } => {
+                let resp = match self.prepare_migration(request).await {
+                    Ok(target) => MigrationResponse::Ready(target),
+                    Err(e) => MigrationResponse::Failure(e.to_string()),
+                };
+                self.api_response_sender.send(
+                    serde_json::to_string(&resp)?
+                ).await?;
+            }
+            VmmEvent::Snapshot { id, snapshot_id, description } => {
+                self.snapshot(id, snapshot_id).await?;
+                let instance_id = form_pack::manager::build_instance_id(self.derive_address().await?, id.to_string())?;
+                let mut instance = Instance::get(&instance_id).await.ok_or(
+                    Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Instance doesn't exist"))
+                )?;
+                let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                let pruned = instance.record_snapshot(snapshot_id.clone(), timestamp, description.clone());
+                instance.updated_at = timestamp;
+                self.publish_instance_update(instance).await?;
+                self.prune_snapshots(id, &pruned);
+            }
+            VmmEvent::Restore { id, snapshot_id, n_snapshots_ago } => {
+                let instance_id = form_pack::manager::build_instance_id(self.derive_address().await?, id.to_string())?;
+                let mut instance = Instance::get(&instance_id).await.ok_or(
+                    Box::new(std::io::Error::new(std::io::ErrorKind::Other, "Instance doesn't exist"))
+                )?;
+                // Only snapshots recorded in the instance history can be
+                // restored, so a caller can't point us at an arbitrary path
+                let snapshot = match snapshot_id {
+                    Some(snapshot_id) => instance.find_snapshot(snapshot_id),
+                    None => match instance.n_snapshots_ago(*n_snapshots_ago) {
+                        (snapshot, 0) => snapshot,
+                        _ => None,
+                    }
+                }.ok_or(Box::new(VmmError::OperationFailed(
+                    format!("No matching snapshot recorded for instance {instance_id}")
+                )))?;
+                log::info!("Restoring {id} from snapshot {}", snapshot.id());
+                self.restore(id, snapshot.id()).await?;
+                instance.status = InstanceStatus::Started;
+                instance.updated_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+                self.publish_instance_update(instance).await?;
+            }
+            VmmEvent::Resize { id, name, vcpus, memory_mb } => {
+                let resp = match self.resize(id, *vcpus, *memory_mb).await {
+                    Ok(instance) => VmmResponse::Success(VmResponse {
+                        id: id.clone(),
+                        name: name.clone(),
+                        state: format!(
+                            "resized to {} vCPUs and {} MiB",
+                            instance.resources.vcpus, instance.resources.memory_mb
+                        ),
+                    }),
+                    Err(e) => VmmResponse::Failure(e.to_string()),
+                };
+                self.api_response_sender.send(serde_json::to_string(&resp)?).await?;
+            }
+            VmmEvent::AddDisk { request } => {
+                let resp = hotplug_response(&request.id, &request.name, self.add_disk(request).await);
+                self.api_response_sender.send(serde_json::to_string(&resp)?).await?;
+            }
+            VmmEvent::AddFs { request } => {
+                let resp = hotplug_response(&request.id, &request.name, self.add_fs(request).await);
+                self.api_response_sender.send(serde_json::to_string(&resp)?).await?;
+            }
+            VmmEvent::AddDevice { request } => {
+                let resp = hotplug_response(&request.id, &request.name, self.add_device(request).await);
+                self.api_response_sender.send(serde_json::to_string(&resp)?).await?;
+            }
+            VmmEvent::RemoveDevice { request } => {
+                let resp = match self.remove_device(request).await {
+                    Ok(_) => VmmResponse::Success(VmResponse {
+                        id: request.id.clone(),
+                        name: request.name.clone(),
+                        state: format!("removed {}", request.device_id),
+                    }),
+                    Err(e) => VmmResponse::Failure(e.to_string()),
+                };
+                self.api_response_sender.send(serde_json::to_string(&resp)?).await?;
+            }
+            VmmEvent::Commit { request, commit_id } => {
+                let resp = match self.commit(request, commit_id).await {
+                    Ok(_) => CommitResponse::Accepted {
+                        id: request.id.clone(),
+                        commit_id: commit_id.clone(),
+                    },
+                    Err(e) => CommitResponse::Failure(e.to_string()),
+                };
+                self.api_response_sender.send(serde_json::to_string(&resp)?).await?;
+            }
             VmmEvent::Get { id, .. } => {
                 let resp = serde_json::to_string(&self.info(id).await?)?;
                 self.api_response_sender.send(
@@ -1148,6 +1793,22 @@ Formpack for {name} doesn't exist:
         }
     }
 
+    async fn publish_instance_update(&self, instance: Instance) -> Result<(), Box<dyn std::error::Error + Send + Sync + 'static>> {
+        let request = InstanceRequest::Update(instance);
+        #[cfg(not(feature = "devnet"))]
+        VmmApi::write_to_queue(request.clone(), 4, "state").await?;
+
+        #[cfg(feature = "devnet")]
+        reqwest::Client::new().post("http://127.0.0.1:3004/instance/update")
+            .json(&request)
+            .send()
+            .await?
+            .json::<form_types::state::Response<Instance>>()
+            .await?;
+
+        Ok(())
+    }
+
     fn get_vmm(&self, name: &str) -> VmmResult<&FormVmm> {
         Ok(self.vm_monitors.get(name).ok_or(
             VmmError::VmNotFound(